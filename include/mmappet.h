@@ -0,0 +1,59 @@
+/* C API of mmappet, built with `cargo build --release --features capi`
+ * (libmmappet.so / libmmappet.dylib / mmappet.dll in target/release).
+ *
+ * Functions returning a pointer return NULL on error; mmappet_last_error()
+ * then describes it. Column pointers stay valid until the dataset is closed.
+ */
+#ifndef MMAPPET_H
+#define MMAPPET_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct MmappetDataset MmappetDataset;
+
+/* Dtype codes stored by mmappet_get_column_ptr. */
+enum {
+    MMAPPET_UINT8 = 0,
+    MMAPPET_INT8 = 1,
+    MMAPPET_UINT16 = 2,
+    MMAPPET_INT16 = 3,
+    MMAPPET_UINT32 = 4,
+    MMAPPET_INT32 = 5,
+    MMAPPET_UINT64 = 6,
+    MMAPPET_INT64 = 7,
+    MMAPPET_FLOAT16 = 8,
+    MMAPPET_FLOAT32 = 9,
+    MMAPPET_FLOAT64 = 10,
+    MMAPPET_BOOL = 11,          /* one byte, 0 or 1 */
+    MMAPPET_CATEGORICAL = 12,   /* uint32_t codes */
+    MMAPPET_TIMESTAMP_MS = 13,  /* int64_t milliseconds since the Unix epoch, UTC */
+    MMAPPET_TIMESTAMP_US = 14,  /* int64_t microseconds since the Unix epoch, UTC */
+};
+
+/* Open a dataset directory; free it with mmappet_close. */
+MmappetDataset *mmappet_open(const char *path);
+
+/* Close a dataset, invalidating pointers to its columns. NULL is ignored. */
+void mmappet_close(MmappetDataset *dataset);
+
+size_t mmappet_num_rows(const MmappetDataset *dataset);
+
+/* Values of a column, in native byte order. Stores the number of values (rows
+ * times the width of array columns) in len and the dtype code in dtype, unless
+ * they're NULL. Returns NULL for missing columns and text or custom dtypes. */
+const void *mmappet_get_column_ptr(const MmappetDataset *dataset, const char *name,
+                                   size_t *len, int32_t *dtype);
+
+/* Last error on this thread, valid until the next failing call, or NULL. */
+const char *mmappet_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
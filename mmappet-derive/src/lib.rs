@@ -0,0 +1,103 @@
+//! Derive macro for `mmappet::MmappetRow`.
+//!
+//! Use it through the `derive` feature of the `mmappet` crate rather than
+//! depending on this crate directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr};
+
+/// Derive `mmappet::MmappetRow` for a struct with named fields.
+///
+/// Every field becomes a column named after it, with the dtype of the field's
+/// `MmappetType`. Use `#[mmappet(rename = "name")]` on a field to map it to a
+/// column with a different name.
+#[proc_macro_derive(MmappetRow, attributes(mmappet))]
+pub fn derive_mmappet_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "MmappetRow can't be derived for generic structs",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "MmappetRow requires a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "MmappetRow can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut columns = Vec::new();
+    let mut reads = Vec::new();
+    let mut writes = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let column = column_name(field)?.unwrap_or_else(|| ident.to_string());
+
+        columns.push(quote! {
+            (#column, <#ty as ::mmappet::MmappetType>::DTYPE)
+        });
+        reads.push(quote! {
+            #ident: columns[#i].as_slice::<#ty>().unwrap()[row]
+        });
+        writes.push(quote! {
+            writer.write_at(indices[#i], ::std::slice::from_ref(&self.#ident))?;
+        });
+    }
+
+    Ok(quote! {
+        impl ::mmappet::MmappetRow for #name {
+            const COLUMNS: &'static [(&'static str, ::mmappet::DType)] = &[#(#columns),*];
+
+            fn read(columns: &[&::mmappet::Column], row: usize) -> Self {
+                #name { #(#reads),* }
+            }
+
+            fn write(
+                &self,
+                writer: &mut ::mmappet::DatasetWriter,
+                indices: &[usize],
+            ) -> ::mmappet::Result<()> {
+                #(#writes)*
+                Ok(())
+            }
+        }
+    })
+}
+
+/// Column name given by a `#[mmappet(rename = "...")]` attribute, if any.
+fn column_name(field: &syn::Field) -> syn::Result<Option<String>> {
+    let mut rename = None;
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("mmappet")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                rename = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("expected `rename = \"...\"`"))
+            }
+        })?;
+    }
+    Ok(rename)
+}
@@ -0,0 +1,425 @@
+//! Group-by with aggregations over hashed keys.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use bytemuck::{cast_slice, Pod, Zeroable};
+use half::f16;
+
+use crate::column::{typed_array, TypedArrayView};
+use crate::dataset::Dataset;
+use crate::dtype::{DType, MmappetType};
+use crate::error::{MmappetError, Result};
+use crate::schema::{ColumnDef, Schema};
+use crate::writer::DatasetWriter;
+
+/// An aggregation computed per group by [`GroupBy::agg`].
+///
+/// Result columns are named `count` and `{column}_{sum,min,max,mean}`.
+/// Sums of integers are `int64`/`uint64` (wrapping on overflow), sums of
+/// floats and all means are `float64`, and minima/maxima keep the column's
+/// dtype. NaNs are skipped by `Min` and `Max`. Custom dtypes are aggregated
+/// by their numeric value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Agg<'a> {
+    Count,
+    Sum(&'a str),
+    Min(&'a str),
+    Max(&'a str),
+    Mean(&'a str),
+}
+
+impl Agg<'_> {
+    fn output_name(&self) -> String {
+        match self {
+            Agg::Count => "count".to_string(),
+            Agg::Sum(column) => format!("{}_sum", column),
+            Agg::Min(column) => format!("{}_min", column),
+            Agg::Max(column) => format!("{}_max", column),
+            Agg::Mean(column) => format!("{}_mean", column),
+        }
+    }
+}
+
+impl Dataset {
+    /// Group rows by the values of column `key`, see [`GroupBy::agg`].
+    ///
+    /// Unlike [`Dataset::group_spans`] the key column needn't be sorted.
+    pub fn group_by(&self, key: &str) -> GroupBy<'_> {
+        GroupBy {
+            dataset: self,
+            key: key.to_string(),
+        }
+    }
+}
+
+/// Rows of a dataset grouped by a key column, created by [`Dataset::group_by`].
+pub struct GroupBy<'a> {
+    dataset: &'a Dataset,
+    key: String,
+}
+
+impl GroupBy<'_> {
+    /// Compute `aggs` for every distinct key.
+    ///
+    /// The result has the key column first, then one column per aggregation,
+    /// with one row per key in order of first appearance. Keys are compared
+    /// bitwise, so e.g. `0.0` and `-0.0` form separate groups.
+    pub fn agg(&self, aggs: &[Agg<'_>]) -> Result<AggTable> {
+        let ds = self.dataset;
+        let key = ds
+            .column(&self.key)
+            .ok_or_else(|| ds.column_not_found(&self.key))?;
+        key.check_scalar(&self.key)?;
+        if matches!(key.dtype(), DType::Utf8 | DType::Categorical) {
+            return Err(MmappetError::InvalidDType {
+                dtype: key.dtype().to_string(),
+                message: format!("column '{}' can't be a group key", self.key),
+            });
+        }
+
+        let size = key.dtype().size_bytes();
+        let mut ids: HashMap<&[u8], u32> = HashMap::new();
+        let mut first_rows = Vec::new();
+        let groups: Vec<u32> = key
+            .as_bytes()
+            .chunks_exact(size)
+            .enumerate()
+            .map(|(row, bytes)| {
+                *ids.entry(bytes).or_insert_with(|| {
+                    first_rows.push(row);
+                    first_rows.len() as u32 - 1
+                })
+            })
+            .collect();
+
+        let mut defs = vec![(self.key.clone(), key.dtype())];
+        let mut key_bytes = Vec::new();
+        key.gather_bytes(&first_rows, &mut key_bytes);
+        let mut buffers = vec![AlignedBytes::new(&key_bytes)];
+
+        for agg in aggs {
+            let (dtype, bytes) = match agg {
+                Agg::Count => {
+                    let mut counts = vec![0u64; first_rows.len()];
+                    for &g in &groups {
+                        counts[g as usize] += 1;
+                    }
+                    (DType::UInt64, AlignedBytes::new(cast_slice(&counts)))
+                }
+                Agg::Sum(name) | Agg::Min(name) | Agg::Max(name) | Agg::Mean(name) => {
+                    let column = ds.column(name).ok_or_else(|| ds.column_not_found(name))?;
+                    column.check_scalar(name)?;
+                    let grouping = Grouping {
+                        groups: &groups,
+                        first_rows: &first_rows,
+                    };
+                    aggregate_column(column.as_typed_array(), column.dtype(), *agg, &grouping)
+                        .map_err(|e| e.with_column(name))?
+                }
+            };
+            defs.push((agg.output_name(), dtype));
+            buffers.push(bytes);
+        }
+
+        let defs: Vec<(&str, DType)> = defs.iter().map(|(n, d)| (n.as_str(), *d)).collect();
+        Ok(AggTable {
+            schema: Schema::new(&defs)?,
+            buffers,
+            len: first_rows.len(),
+        })
+    }
+}
+
+/// Group of every row, and the first row of every group.
+struct Grouping<'g> {
+    groups: &'g [u32],
+    first_rows: &'g [usize],
+}
+
+/// Numeric element types that can be summed and compared.
+trait Numeric: Pod + PartialOrd {
+    type Sum: Pod;
+    const SUM_DTYPE: DType;
+
+    fn add(sum: Self::Sum, value: Self) -> Self::Sum;
+
+    fn to_f64(self) -> f64;
+}
+
+macro_rules! numeric {
+    ($($t:ty => $sum:ty, $dtype:expr, $add:expr);* $(;)?) => {
+        $(impl Numeric for $t {
+            type Sum = $sum;
+            const SUM_DTYPE: DType = $dtype;
+
+            fn add(sum: $sum, value: $t) -> $sum {
+                $add(sum, value as $sum)
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+        })*
+    };
+}
+
+numeric! {
+    u8 => u64, DType::UInt64, u64::wrapping_add;
+    u16 => u64, DType::UInt64, u64::wrapping_add;
+    u32 => u64, DType::UInt64, u64::wrapping_add;
+    u64 => u64, DType::UInt64, u64::wrapping_add;
+    i8 => i64, DType::Int64, i64::wrapping_add;
+    i16 => i64, DType::Int64, i64::wrapping_add;
+    i32 => i64, DType::Int64, i64::wrapping_add;
+    i64 => i64, DType::Int64, i64::wrapping_add;
+    f32 => f64, DType::Float64, |a: f64, b: f64| a + b;
+    f64 => f64, DType::Float64, |a: f64, b: f64| a + b;
+}
+
+// Sums and means of halves are computed in f64
+impl Numeric for f16 {
+    type Sum = f64;
+    const SUM_DTYPE: DType = DType::Float64;
+
+    fn add(sum: f64, value: f16) -> f64 {
+        sum + value.to_f64()
+    }
+
+    fn to_f64(self) -> f64 {
+        f16::to_f64(self)
+    }
+}
+
+fn aggregate_column(
+    array: TypedArrayView<'_>,
+    dtype: DType,
+    agg: Agg<'_>,
+    grouping: &Grouping<'_>,
+) -> Result<(DType, AlignedBytes)> {
+    fn run<T: Numeric>(
+        data: &[T],
+        dtype: DType,
+        agg: Agg<'_>,
+        g: &Grouping<'_>,
+    ) -> (DType, AlignedBytes) {
+        let n = g.first_rows.len();
+        match agg {
+            Agg::Sum(_) => {
+                let mut sums = vec![T::Sum::zeroed(); n];
+                for (&group, &value) in g.groups.iter().zip(data) {
+                    let sum = &mut sums[group as usize];
+                    *sum = T::add(*sum, value);
+                }
+                (T::SUM_DTYPE, AlignedBytes::new(cast_slice(&sums)))
+            }
+            Agg::Min(_) | Agg::Max(_) => {
+                let is_max = matches!(agg, Agg::Max(_));
+                let mut best: Vec<T> = g.first_rows.iter().map(|&row| data[row]).collect();
+                for (&group, &value) in g.groups.iter().zip(data) {
+                    let current = best[group as usize];
+                    let better = if is_max {
+                        value > current
+                    } else {
+                        value < current
+                    };
+                    // A value is NaN iff it's unordered with itself
+                    if better || current.partial_cmp(&current).is_none() {
+                        best[group as usize] = value;
+                    }
+                }
+                (dtype, AlignedBytes::new(cast_slice(&best)))
+            }
+            Agg::Mean(_) => {
+                let mut sums = vec![0.0f64; n];
+                let mut counts = vec![0u64; n];
+                for (&group, &value) in g.groups.iter().zip(data) {
+                    sums[group as usize] += value.to_f64();
+                    counts[group as usize] += 1;
+                }
+                let means: Vec<f64> = sums
+                    .iter()
+                    .zip(&counts)
+                    .map(|(&s, &c)| s / c as f64)
+                    .collect();
+                (DType::Float64, AlignedBytes::new(cast_slice(&means)))
+            }
+            Agg::Count => unreachable!("counts don't read a column"),
+        }
+    }
+
+    let g = grouping;
+    Ok(match array {
+        TypedArrayView::UInt8(a) | TypedArrayView::Bool(a) => {
+            run(a.as_slice().unwrap(), dtype, agg, g)
+        }
+        TypedArrayView::Int8(a) => run(a.as_slice().unwrap(), dtype, agg, g),
+        TypedArrayView::UInt16(a) => run(a.as_slice().unwrap(), dtype, agg, g),
+        TypedArrayView::Int16(a) => run(a.as_slice().unwrap(), dtype, agg, g),
+        TypedArrayView::UInt32(a) => run(a.as_slice().unwrap(), dtype, agg, g),
+        TypedArrayView::Int32(a) => run(a.as_slice().unwrap(), dtype, agg, g),
+        TypedArrayView::UInt64(a) => run(a.as_slice().unwrap(), dtype, agg, g),
+        TypedArrayView::Int64(a) | TypedArrayView::TimestampMillis(a) | TypedArrayView::TimestampMicros(a) => {
+            run(a.as_slice().unwrap(), dtype, agg, g)
+        }
+        TypedArrayView::Float16(a) => run(a.as_slice().unwrap(), dtype, agg, g),
+        TypedArrayView::Float32(a) => run(a.as_slice().unwrap(), dtype, agg, g),
+        TypedArrayView::Float64(a) => run(a.as_slice().unwrap(), dtype, agg, g),
+        TypedArrayView::Utf8(_) | TypedArrayView::Categorical(_) => {
+            return Err(MmappetError::InvalidDType {
+                dtype: array.dtype().to_string(),
+                message: "labels can't be aggregated".to_string(),
+            })
+        }
+        TypedArrayView::Custom(a) => {
+            let to_f64 = a.def().to_f64.ok_or_else(|| MmappetError::InvalidDType {
+                dtype: a.def().name.to_string(),
+                message: "has no numeric value to aggregate".to_string(),
+            })?;
+            let values: Vec<f64> = (0..a.len()).map(|i| to_f64(a.get(i))).collect();
+            run(&values, DType::Float64, agg, g)
+        }
+    })
+}
+
+/// Bytes stored in `u64` words, so they can be viewed as any dtype up to
+/// 8-byte alignment.
+struct AlignedBytes {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl AlignedBytes {
+    fn new(bytes: &[u8]) -> Self {
+        let mut words = vec![0u64; bytes.len().div_ceil(8)];
+        bytemuck::cast_slice_mut::<u64, u8>(&mut words)[..bytes.len()].copy_from_slice(bytes);
+        AlignedBytes {
+            words,
+            len: bytes.len(),
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &cast_slice::<u64, u8>(&self.words)[..self.len]
+    }
+}
+
+/// In-memory result of [`GroupBy::agg`], one row per group.
+pub struct AggTable {
+    schema: Schema,
+    buffers: Vec<AlignedBytes>,
+    len: usize,
+}
+
+impl AggTable {
+    /// Get the schema.
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Number of groups.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if there are no groups, i.e. the dataset was empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get a typed slice directly by name.
+    ///
+    /// Returns an error if the column doesn't exist or the type doesn't match.
+    pub fn get<T: MmappetType>(&self, name: &str) -> Result<&[T]> {
+        let col = self.column_def(name)?;
+        if col.dtype != T::DTYPE {
+            return Err(MmappetError::TypeMismatch {
+                column: Some(name.to_string()),
+                expected: T::DTYPE,
+                actual: col.dtype,
+            });
+        }
+        Ok(cast_slice(self.buffers[col.index].as_bytes()))
+    }
+
+    /// Get a column as dynamically-typed array enum.
+    ///
+    /// Returns an error if the column doesn't exist.
+    pub fn typed_array(&self, name: &str) -> Result<TypedArrayView<'_>> {
+        let col = self.column_def(name)?;
+        Ok(typed_array(col.dtype, self.buffers[col.index].as_bytes()))
+    }
+
+    /// Write the table to a new dataset in `dir`. Returns the number of rows.
+    pub fn write_to<P: AsRef<Path>>(&self, dir: P) -> Result<usize> {
+        let mut writer = DatasetWriter::create(dir, &self.schema)?;
+        for (index, buffer) in self.buffers.iter().enumerate() {
+            writer.write_bytes(index, buffer.as_bytes(), self.len)?;
+        }
+        writer.finish()
+    }
+
+    fn column_def(&self, name: &str) -> Result<&ColumnDef> {
+        self.schema
+            .get(name)
+            .ok_or_else(|| MmappetError::ColumnNotFound {
+                path: None,
+                column: name.to_string(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::write_dataset;
+
+    #[test]
+    fn test_group_by_agg() {
+        let dir = write_dataset(
+            "group-by",
+            &[
+                ("frame", &[2, 1, 2, 1, 3]),
+                ("intensity", &[10, 5, 30, 7, 1]),
+            ],
+        );
+        let ds = Dataset::open(&dir).unwrap();
+
+        let table = ds
+            .group_by("frame")
+            .agg(&[
+                Agg::Count,
+                Agg::Sum("intensity"),
+                Agg::Min("intensity"),
+                Agg::Mean("intensity"),
+            ])
+            .unwrap();
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.get::<u32>("frame").unwrap(), &[2, 1, 3]);
+        assert_eq!(table.get::<u64>("count").unwrap(), &[2, 2, 1]);
+        assert_eq!(table.get::<u64>("intensity_sum").unwrap(), &[40, 12, 1]);
+        assert_eq!(table.get::<u32>("intensity_min").unwrap(), &[10, 5, 1]);
+        assert_eq!(
+            table.get::<f64>("intensity_mean").unwrap(),
+            &[20.0, 6.0, 1.0]
+        );
+        assert!(table.get::<f64>("count").is_err());
+
+        assert!(matches!(
+            ds.group_by("frame").agg(&[Agg::Max("missing")]),
+            Err(MmappetError::ColumnNotFound { .. })
+        ));
+        assert!(matches!(
+            ds.group_by("frame").agg(&[Agg::Count, Agg::Count]),
+            Err(MmappetError::DuplicateColumnName { .. })
+        ));
+
+        let out = dir.with_extension("agg");
+        assert_eq!(table.write_to(&out).unwrap(), 3);
+        let written = Dataset::open(&out).unwrap();
+        assert_eq!(written.get::<u64>("intensity_sum").unwrap(), &[40, 12, 1]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+        std::fs::remove_dir_all(out).unwrap();
+    }
+}
@@ -0,0 +1,281 @@
+//! Async access to datasets for tokio services (feature `tokio`).
+//!
+//! Opening, scanning and exporting all touch pages of memory-mapped files,
+//! and a page fault blocks the thread it happens on. The functions here do
+//! that work on tokio's blocking threads, so a dataset on a slow disk never
+//! stalls the runtime.
+//!
+//! ```rust,no_run
+//! # async fn run() -> mmappet::Result<()> {
+//! use std::sync::Arc;
+//! use mmappet::{aio, Dataset};
+//!
+//! let ds = Arc::new(Dataset::open_async("peaks.mmappet").await?);
+//! let mut chunks = aio::chunks(Arc::clone(&ds), 1 << 16);
+//! while let Some(chunk) = std::future::poll_fn(|cx| {
+//!     futures_core::Stream::poll_next(std::pin::Pin::new(&mut chunks), cx)
+//! })
+//! .await
+//! {
+//!     let intensity: &[u32] = chunk.view().get("intensity")?;
+//! }
+//! aio::export_csv(ds, "peaks.csv", &[], &mmappet::CsvOptions::default()).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::ops::Range;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use tokio::sync::mpsc;
+
+use crate::csv::CsvOptions;
+use crate::dataset::Dataset;
+use crate::error::{MmappetError, Result};
+use crate::view::DatasetView;
+
+/// Chunks a stream reads ahead of the one being consumed.
+const CHUNKS_AHEAD: usize = 2;
+
+/// Granularity at which pages of a chunk are faulted in.
+const PAGE_SIZE: usize = 4096;
+
+impl Dataset {
+    /// [`Dataset::open`] on a blocking thread.
+    pub async fn open_async<P: AsRef<Path>>(path: P) -> Result<Dataset> {
+        let path = path.as_ref().to_path_buf();
+        blocking(move || Dataset::open(path)).await
+    }
+}
+
+/// A range of rows of a shared dataset, as yielded by [`chunks`].
+#[derive(Clone)]
+pub struct ChunkView {
+    dataset: Arc<Dataset>,
+    rows: Range<usize>,
+}
+
+impl ChunkView {
+    /// The dataset the chunk belongs to.
+    pub fn dataset(&self) -> &Arc<Dataset> {
+        &self.dataset
+    }
+
+    /// Row range of the dataset covered by the chunk.
+    pub fn rows(&self) -> Range<usize> {
+        self.rows.clone()
+    }
+
+    /// Number of rows in the chunk.
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Check if the chunk has no rows.
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Borrow the chunk's rows as a zero-copy view.
+    pub fn view(&self) -> DatasetView<'_> {
+        DatasetView::new(&self.dataset, self.rows.clone())
+    }
+}
+
+/// Stream of consecutive chunks of a dataset, created by [`chunks`].
+pub struct ChunkStream {
+    receiver: mpsc::Receiver<ChunkView>,
+}
+
+impl Stream for ChunkStream {
+    type Item = ChunkView;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ChunkView>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// Stream the dataset in consecutive chunks of `chunk_rows` rows, like
+/// [`Dataset::chunks`].
+///
+/// A blocking thread faults in the pages of every column of a chunk before
+/// it is yielded, staying up to two chunks ahead of the consumer; reading a
+/// chunk's view then doesn't block. The thread stops when the stream is
+/// dropped. The last chunk may be shorter.
+///
+/// # Panics
+///
+/// Panics if `chunk_rows` is zero or if called outside a tokio runtime.
+pub fn chunks(dataset: Arc<Dataset>, chunk_rows: usize) -> ChunkStream {
+    assert!(chunk_rows > 0, "chunk_rows must be non-zero");
+    let (sender, receiver) = mpsc::channel(CHUNKS_AHEAD);
+    tokio::task::spawn_blocking(move || {
+        let len = dataset.len();
+        for start in (0..len).step_by(chunk_rows) {
+            let rows = start..(start + chunk_rows).min(len);
+            fault_in(&dataset, rows.clone());
+            let chunk = ChunkView { dataset: Arc::clone(&dataset), rows };
+            if sender.blocking_send(chunk).is_err() {
+                break;
+            }
+        }
+    });
+    ChunkStream { receiver }
+}
+
+/// [`Dataset::export_csv`] of all rows to a new file at `path`, on a
+/// blocking thread.
+pub async fn export_csv<P: AsRef<Path>>(
+    dataset: Arc<Dataset>,
+    path: P,
+    columns: &[&str],
+    options: &CsvOptions,
+) -> Result<usize> {
+    let columns = owned_names(columns);
+    let options = options.clone();
+    write_file(path.as_ref(), move |file| {
+        let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+        dataset.export_csv(file, &columns, .., &options)
+    })
+    .await
+}
+
+/// [`Dataset::export_jsonl`] of all rows to a new file at `path`, on a
+/// blocking thread.
+pub async fn export_jsonl<P: AsRef<Path>>(
+    dataset: Arc<Dataset>,
+    path: P,
+    columns: &[&str],
+) -> Result<usize> {
+    let columns = owned_names(columns);
+    write_file(path.as_ref(), move |file| {
+        let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+        dataset.export_jsonl(file, &columns, ..)
+    })
+    .await
+}
+
+/// [`Dataset::export_npy`] on a blocking thread.
+pub async fn export_npy<P: AsRef<Path>>(dataset: Arc<Dataset>, name: &str, path: P) -> Result<()> {
+    let name = name.to_string();
+    let path = path.as_ref().to_path_buf();
+    blocking(move || dataset.export_npy(&name, path)).await
+}
+
+/// [`Dataset::export_npz`] on a blocking thread.
+pub async fn export_npz<P: AsRef<Path>>(dataset: Arc<Dataset>, path: P) -> Result<()> {
+    let path = path.as_ref().to_path_buf();
+    blocking(move || dataset.export_npz(path)).await
+}
+
+/// [`Dataset::to_arrow_ipc`] on a blocking thread.
+#[cfg(feature = "arrow")]
+pub async fn to_arrow_ipc<P: AsRef<Path>>(dataset: Arc<Dataset>, path: P) -> Result<()> {
+    let path = path.as_ref().to_path_buf();
+    blocking(move || dataset.to_arrow_ipc(path)).await
+}
+
+/// [`Dataset::export_parquet`] on a blocking thread.
+#[cfg(feature = "parquet")]
+pub async fn export_parquet<P: AsRef<Path>>(dataset: Arc<Dataset>, path: P) -> Result<()> {
+    let path = path.as_ref().to_path_buf();
+    blocking(move || dataset.export_parquet(path)).await
+}
+
+/// Run `f` on a blocking thread, passing on its panics.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(value) => value,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// Create the file at `path` and run `write` on it on a blocking thread.
+async fn write_file<T: Send + 'static>(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<T> + Send + 'static,
+) -> Result<T> {
+    let path = path.to_path_buf();
+    blocking(move || {
+        let file = File::create(&path).map_err(|e| MmappetError::io(&path, e))?;
+        let mut file = BufWriter::new(file);
+        let value = write(&mut file)?;
+        file.into_inner()
+            .map_err(|e| MmappetError::io(&path, e.into_error()))?
+            .sync_all()
+            .map_err(|e| MmappetError::io(&path, e))?;
+        Ok(value)
+    })
+    .await
+}
+
+fn owned_names(columns: &[&str]) -> Vec<String> {
+    columns.iter().map(|name| name.to_string()).collect()
+}
+
+/// Read one byte of every page holding `rows` of the dataset's columns.
+fn fault_in(dataset: &Dataset, rows: Range<usize>) {
+    for name in dataset.column_names() {
+        let Some(column) = dataset.column(name) else {
+            continue;
+        };
+        let bytes = column.as_bytes();
+        let size = column.row_size();
+        let end = (rows.end * size).min(bytes.len());
+        for offset in (rows.start * size..end).step_by(PAGE_SIZE) {
+            std::hint::black_box(bytes[offset]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::write_dataset;
+    use std::future::poll_fn;
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread().build().unwrap()
+    }
+
+    #[test]
+    fn test_async_chunks_and_exports() {
+        let values: Vec<u32> = (0..10).collect();
+        let dir = write_dataset("aio", &[("frame", &values)]);
+        let csv = dir.with_extension("csv");
+
+        runtime().block_on(async {
+            let ds = Arc::new(Dataset::open_async(&dir).await.unwrap());
+            let mut stream = chunks(Arc::clone(&ds), 4);
+            let mut seen = Vec::new();
+            while let Some(chunk) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+                seen.push(chunk.rows());
+                assert_eq!(chunk.view().get::<u32>("frame").unwrap(), &values[chunk.rows()]);
+            }
+            assert_eq!(seen, [0..4, 4..8, 8..10]);
+
+            // Dropping a stream early stops its thread
+            let mut stream = chunks(Arc::clone(&ds), 1);
+            poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await.unwrap();
+            drop(stream);
+
+            let rows = export_csv(Arc::clone(&ds), &csv, &[], &CsvOptions::default())
+                .await
+                .unwrap();
+            assert_eq!(rows, 10);
+            assert!(Dataset::open_async(dir.join("missing")).await.is_err());
+        });
+        let text = std::fs::read_to_string(&csv).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines, ["frame", "0", "1", "2", "3", "4", "5", "6", "7", "8", "9"]);
+
+        std::fs::remove_file(csv).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,323 @@
+//! Appending rows to existing datasets.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use bytemuck::cast_slice;
+
+use crate::categorical::{read_categories, write_categories, CategoryEncoder};
+use crate::column::{EncodedFile, TypedArrayView};
+use crate::commit::Commit;
+use crate::dtype::ByteOrder;
+use crate::dataset::{column_file, Dataset};
+use crate::edit::shared_columns;
+use crate::error::{MmappetError, Result};
+use crate::journal::AppendJournal;
+use crate::nullable::{extend_validity, validity_path};
+use crate::storage::unshare;
+use crate::strings::data_path;
+use crate::writer::view_bytes;
+
+impl Dataset {
+    /// Append a batch of rows, given as one named array per column.
+    ///
+    /// Every column of the schema must appear exactly once, with the schema's
+    /// dtype, and all arrays must hold the same number of rows (`width`
+    /// values each for array columns); otherwise nothing is written. If
+    /// writing any column file fails, all files are truncated back to their
+    /// previous length, and if the process dies mid-append, the next append
+    /// or [`Dataset::open_mut`] does so from the `append.journal` left behind;
+    /// readers only ever see committed rows. Appends through different
+    /// handles are serialized by a lock on the journal; one whose handle was
+    /// opened before another handle's append fails with
+    /// [`MmappetError::StaleDataset`] and writes nothing, so reopen the dataset
+    /// and retry. On success the dataset is
+    /// re-mapped, so [`Dataset::len`] includes the new rows. Columns with a validity bitmap
+    /// get the new rows marked valid. Strings are appended to the column's
+    /// string data, their offsets to the column file, and labels new to a
+    /// categorical column to its dictionary. Files hard-linked to other
+    /// datasets, e.g. by `mmappet-cli dedupe-storage`, are copied first, so
+    /// those datasets don't change. Returns the new row count.
+    ///
+    /// Reference columns can't be appended to, since their file belongs to
+    /// another dataset, and neither can columns sharing files with the
+    /// dataset this one was edited from (see [`Dataset::edit`]), compressed or
+    /// delta-encoded columns, datasets opened with only some of their columns,
+    /// with computed columns (see [`Dataset::with_computed`]) or stored in
+    /// another byte order than the host's.
+    pub fn append_batch(&mut self, batch: &[(&str, TypedArrayView<'_>)]) -> Result<usize> {
+        if self.is_projected() {
+            return Err(MmappetError::InvalidBatch {
+                path: Some(self.path().to_path_buf()),
+                message: "dataset was opened with only some of its columns".to_string(),
+            });
+        }
+        if let Some(name) = self.column_names().find(|name| self.is_computed(name)) {
+            return Err(MmappetError::InvalidBatch {
+                path: Some(self.path().to_path_buf()),
+                message: format!("column '{}' is computed", name),
+            });
+        }
+        if self.schema().byte_order() != ByteOrder::native() {
+            return Err(MmappetError::ForeignByteOrder {
+                path: Some(self.path().to_path_buf()),
+                column: None,
+                byte_order: self.schema().byte_order(),
+            });
+        }
+        let shared = shared_columns(self.path())?;
+        let mut ordered = Vec::with_capacity(self.num_columns());
+        for col_def in self.schema().columns() {
+            let mut matches = batch.iter().filter(|(name, _)| *name == col_def.name);
+            let array = match (matches.next(), matches.next()) {
+                (Some((_, array)), None) => array,
+                (None, _) => {
+                    return Err(MmappetError::InvalidBatch {
+                        path: Some(self.path().to_path_buf()),
+                        message: format!("column '{}' is missing", col_def.name),
+                    })
+                }
+                (Some(_), Some(_)) => {
+                    return Err(MmappetError::InvalidBatch {
+                        path: Some(self.path().to_path_buf()),
+                        message: format!("column '{}' is given twice", col_def.name),
+                    })
+                }
+            };
+            if col_def.reference.is_some() {
+                return Err(MmappetError::InvalidReference {
+                    path: Some(self.path().to_path_buf()),
+                    column: col_def.name.clone(),
+                    message: "reference columns can't be appended to".to_string(),
+                });
+            }
+            if array.dtype() != col_def.dtype {
+                return Err(MmappetError::TypeMismatch {
+                    column: Some(col_def.name.clone()),
+                    expected: col_def.dtype,
+                    actual: array.dtype(),
+                });
+            }
+            let (col_path, _) = column_file(self.path(), self.schema(), col_def)?;
+            if shared.contains(&col_def.name) {
+                return Err(MmappetError::ReadOnly {
+                    path: col_path,
+                    column: col_def.name.clone(),
+                });
+            }
+            if let Some(encoded) = EncodedFile::find(&col_path) {
+                return Err(MmappetError::ReadOnly {
+                    path: encoded.path().to_path_buf(),
+                    column: col_def.name.clone(),
+                });
+            }
+            ordered.push((col_path, array));
+        }
+        if let Some((name, _)) = batch
+            .iter()
+            .find(|(name, _)| self.schema().get(name).is_none())
+        {
+            return Err(self.column_not_found(name));
+        }
+
+        let rows = match (self.schema().columns().next(), ordered.first()) {
+            (Some(col_def), Some((_, array))) if array.len().is_multiple_of(col_def.width()) => {
+                array.len() / col_def.width()
+            }
+            (Some(col_def), Some((_, array))) => {
+                return Err(MmappetError::InvalidBatch {
+                    path: Some(self.path().to_path_buf()),
+                    message: format!(
+                        "{} values don't fill rows of {} in column '{}'",
+                        array.len(),
+                        col_def.width(),
+                        col_def.name
+                    ),
+                })
+            }
+            _ => 0,
+        };
+        for (col_def, (_, array)) in self.schema().columns().zip(&ordered) {
+            if array.len() != rows * col_def.width() {
+                return Err(MmappetError::LengthMismatch {
+                    path: Some(self.path().to_path_buf()),
+                    column: col_def.name.clone(),
+                    expected: rows * col_def.width(),
+                    actual: array.len(),
+                });
+            }
+        }
+
+        // Fails if another handle appended since this one was opened
+        let journal = AppendJournal::begin(self.path(), self.schema(), self.len())?;
+        // Readers opening the dataset from now on only see committed rows
+        let generation = match Commit::read(self.path())? {
+            Some(commit) => commit.generation,
+            None => {
+                Commit { generation: 0, rows: self.len() }.write(self.path())?;
+                0
+            }
+        };
+        let mut opened: Vec<(PathBuf, File, u64)> = Vec::with_capacity(ordered.len());
+        let result = (|| {
+            let mut append = |path: &Path, bytes: &[u8]| -> Result<u64> {
+                // Appending to a file linked elsewhere would change it there too
+                unshare(path)?;
+                let mut file = OpenOptions::new()
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| MmappetError::io(path, e))?;
+                let len = file
+                    .metadata()
+                    .map_err(|e| MmappetError::io(path, e))?
+                    .len();
+                let handle = file.try_clone().map_err(|e| MmappetError::io(path, e))?;
+                opened.push((path.to_path_buf(), handle, len));
+                file.write_all(bytes).map_err(|e| MmappetError::io(path, e))?;
+                Ok(len)
+            };
+            for (path, array) in &ordered {
+                match array {
+                    TypedArrayView::Utf8(strings) => {
+                        let base = append(&data_path(path), strings.bytes())?;
+                        let ends: Vec<u64> =
+                            strings.relative_ends().map(|end| base + end).collect();
+                        append(path, cast_slice(&ends))?;
+                    }
+                    // The dictionary only grows, so it's safe to extend first
+                    TypedArrayView::Categorical(labels) => {
+                        let mut encoder = CategoryEncoder::with_categories(read_categories(path)?);
+                        let known = encoder.categories().len();
+                        let codes: Vec<u32> = labels.iter().map(|l| encoder.encode(l)).collect();
+                        if encoder.categories().len() > known {
+                            write_categories(path, encoder.categories())?;
+                        }
+                        append(path, cast_slice(&codes))?;
+                    }
+                    _ => {
+                        append(path, &view_bytes(array))?;
+                    }
+                }
+            }
+            for (path, file, _) in &opened {
+                file.sync_all().map_err(|e| MmappetError::io(path, e))?;
+            }
+            Ok(())
+        })();
+
+        if let Err(err) = result {
+            for (_, file, len) in &opened {
+                let _ = file.set_len(*len);
+            }
+            journal.finish(self.len())?;
+            return Err(err);
+        }
+
+        for col_def in self.schema().columns() {
+            let path = validity_path(self.path(), col_def.index);
+            if path.exists() {
+                extend_validity(&path, self.len(), rows)?;
+            }
+        }
+        let rows = self.len() + rows;
+        Commit { generation: generation + 1, rows }.write(self.path())?;
+        journal.finish(rows)?;
+
+        *self = self.reopen()?;
+        Ok(self.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::write_dataset;
+    use ndarray::ArrayView1;
+
+    #[test]
+    fn test_append_batch() {
+        let dir = write_dataset("append", &[("frame", &[1, 1]), ("tof", &[10, 11])]);
+        let mut ds = Dataset::open(&dir).unwrap();
+
+        let frames = [2u32, 2, 3];
+        let tofs = [12u32, 13, 14];
+        let rows = ds
+            .append_batch(&[
+                ("tof", TypedArrayView::UInt32(ArrayView1::from(&tofs))),
+                ("frame", TypedArrayView::UInt32(ArrayView1::from(&frames))),
+            ])
+            .unwrap();
+        assert_eq!(rows, 5);
+        assert_eq!(ds.get::<u32>("frame").unwrap(), &[1, 1, 2, 2, 3]);
+        assert_eq!(ds.get::<u32>("tof").unwrap(), &[10, 11, 12, 13, 14]);
+
+        // Missing column, wrong length and unknown column leave the files as they are
+        let batch = [("frame", TypedArrayView::UInt32(ArrayView1::from(&frames)))];
+        assert!(matches!(
+            ds.append_batch(&batch),
+            Err(MmappetError::InvalidBatch { .. })
+        ));
+        let batch = [
+            ("frame", TypedArrayView::UInt32(ArrayView1::from(&frames))),
+            ("tof", TypedArrayView::UInt32(ArrayView1::from(&tofs[..2]))),
+        ];
+        assert!(matches!(
+            ds.append_batch(&batch),
+            Err(MmappetError::LengthMismatch { .. })
+        ));
+        let batch = [
+            ("frame", TypedArrayView::UInt32(ArrayView1::from(&frames))),
+            ("tof", TypedArrayView::UInt32(ArrayView1::from(&tofs))),
+            ("mz", TypedArrayView::UInt32(ArrayView1::from(&tofs))),
+        ];
+        assert!(matches!(
+            ds.append_batch(&batch),
+            Err(MmappetError::ColumnNotFound { .. })
+        ));
+        assert_eq!(Dataset::open(&dir).unwrap().len(), 5);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_append_from_stale_handle() {
+        let dir = write_dataset("append-stale", &[("tof", &[10, 11])]);
+        let mut first = Dataset::open(&dir).unwrap();
+        let mut second = Dataset::open(&dir).unwrap();
+        let tofs = [12u32];
+        let batch = [("tof", TypedArrayView::UInt32(ArrayView1::from(&tofs)))];
+
+        assert_eq!(first.append_batch(&batch).unwrap(), 3);
+        assert!(matches!(
+            second.append_batch(&batch),
+            Err(MmappetError::StaleDataset { expected: 2, actual: 3, .. })
+        ));
+        assert_eq!(Dataset::open(&dir).unwrap().get::<u32>("tof").unwrap(), &[10, 11, 12]);
+
+        let mut second = Dataset::open(&dir).unwrap();
+        assert_eq!(second.append_batch(&batch).unwrap(), 4);
+        assert_eq!(first.append_batch(&batch).unwrap_err().path(), Some(dir.as_path()));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_append_to_deduplicated_files() {
+        let dir = write_dataset("append-deduped-a", &[("tof", &[10, 11])]);
+        let other = write_dataset("append-deduped-b", &[("tof", &[10, 11])]);
+        // As `mmappet-cli dedupe-storage` leaves identical column files
+        std::fs::remove_file(other.join("0.bin")).unwrap();
+        std::fs::hard_link(dir.join("0.bin"), other.join("0.bin")).unwrap();
+
+        let mut ds = Dataset::open(&other).unwrap();
+        let tofs = [12u32];
+        ds.append_batch(&[("tof", TypedArrayView::UInt32(ArrayView1::from(&tofs)))])
+            .unwrap();
+        assert_eq!(ds.get::<u32>("tof").unwrap(), &[10, 11, 12]);
+        assert_eq!(Dataset::open(&dir).unwrap().get::<u32>("tof").unwrap(), &[10, 11]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+        std::fs::remove_dir_all(other).unwrap();
+    }
+}
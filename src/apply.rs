@@ -0,0 +1,248 @@
+//! Persisting transformed columns as new columns of a dataset.
+//!
+//! Values are written to `.apply/{column}.bin` inside the dataset, which is
+//! renamed to the column file once complete; adding the column to the
+//! schema then makes it visible. A staging file left behind by an
+//! interrupted run is picked up where it ends by the next run, if
+//! `.apply/{column}.json` shows it was staged by the same kind of call.
+
+use std::fs::{self, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+
+use bytemuck::cast_slice;
+use serde::{Deserialize, Serialize};
+
+use crate::dataset::Dataset;
+use crate::dtype::{ByteOrder, MmappetType};
+use crate::error::{MmappetError, Result};
+use crate::schema::Schema;
+
+/// Directory inside the dataset where new columns are written.
+const STAGING_DIR: &str = ".apply";
+
+/// Rows transformed and synced to disk at a time.
+const APPLY_CHUNK_ROWS: usize = 1 << 20;
+
+/// What a staging file was written from; a run only resumes a stage with
+/// an equal manifest.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct StageManifest {
+    input: String,
+    input_dtype: String,
+    output_dtype: String,
+    rows: usize,
+    chunk_rows: usize,
+}
+
+impl Dataset {
+    /// Store `f` of every value of the column `input` as the new column
+    /// `output`, e.g. m/z values calibrated from time-of-flight indices.
+    ///
+    /// The input is streamed in chunks of 2^20 rows, each synced to disk
+    /// before the next, so memory use doesn't grow with the dataset. If the
+    /// process dies, running the same call again resumes after the last
+    /// synced chunk instead of starting over. Values staged from another
+    /// input, dtype or row count are discarded; `f` can't be compared, so a
+    /// resumed call must pass the same one. The column is added to the
+    /// schema once all its values are written, and the dataset is re-mapped
+    /// to include it. It has no validity bitmap; null rows of the input get
+    /// `f` of whatever value they store.
+    ///
+    /// Must not run while rows are appended to the dataset. Returns an error
+    /// if `input` doesn't exist, holds arrays or isn't of dtype `In`, if
+    /// `output` is invalid or taken, or if the dataset was opened with only
+    /// some of its columns, has computed columns (see
+    /// [`Dataset::with_computed`]) or isn't stored in the host's byte order.
+    ///
+    /// ```rust,no_run
+    /// use mmappet::Dataset;
+    ///
+    /// let mut ds = Dataset::open("peaks.mmappet")?;
+    /// ds.apply_column::<u32, f64>("tof", "mz", |tof| (1.2e-4 * tof as f64 + 0.3).powi(2))?;
+    /// let mz: &[f64] = ds.get("mz")?;
+    /// # Ok::<(), mmappet::MmappetError>(())
+    /// ```
+    pub fn apply_column<In: MmappetType, Out: MmappetType>(
+        &mut self,
+        input: &str,
+        output: &str,
+        f: impl Fn(In) -> Out,
+    ) -> Result<()> {
+        self.apply_chunked(input, output, f, APPLY_CHUNK_ROWS)
+    }
+
+    /// [`Dataset::apply_column`] syncing `chunk_rows` rows at a time.
+    fn apply_chunked<In: MmappetType, Out: MmappetType>(
+        &mut self,
+        input: &str,
+        output: &str,
+        f: impl Fn(In) -> Out,
+        chunk_rows: usize,
+    ) -> Result<()> {
+        if self.is_projected() {
+            return Err(MmappetError::InvalidBatch {
+                path: Some(self.path().to_path_buf()),
+                message: "dataset was opened with only some of its columns".to_string(),
+            });
+        }
+        if let Some(name) = self.column_names().find(|name| self.is_computed(name)) {
+            return Err(MmappetError::InvalidBatch {
+                path: Some(self.path().to_path_buf()),
+                message: format!("column '{}' is computed", name),
+            });
+        }
+        let mut schema = Schema::from_path(self.path())?;
+        if schema.byte_order() != ByteOrder::native() {
+            return Err(MmappetError::ForeignByteOrder {
+                path: Some(self.path().to_path_buf()),
+                column: None,
+                byte_order: schema.byte_order(),
+            });
+        }
+        let index = schema.len();
+        schema
+            .push(output, Out::DTYPE)
+            .map_err(|e| e.with_path(self.path()))?;
+        self.mapped_column(input)?.check_scalar(input)?;
+        let values = self.get::<In>(input)?;
+
+        let staging = self.path().join(STAGING_DIR);
+        fs::create_dir_all(&staging).map_err(|e| MmappetError::io(&staging, e))?;
+        let staged = staging.join(format!("{}.bin", output));
+        let manifest_path = staging.join(format!("{}.json", output));
+        let manifest = StageManifest {
+            input: input.to_string(),
+            input_dtype: In::DTYPE.to_string(),
+            output_dtype: Out::DTYPE.to_string(),
+            rows: values.len(),
+            chunk_rows,
+        };
+        let resumable = fs::read(&manifest_path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<StageManifest>(&bytes).ok())
+            .is_some_and(|staged| staged == manifest);
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(!resumable)
+            .open(&staged)
+            .map_err(|e| MmappetError::io(&staged, e))?;
+        if !resumable {
+            let json = serde_json::to_vec(&manifest).expect("manifest serializes");
+            fs::write(&manifest_path, json).map_err(|e| MmappetError::io(&manifest_path, e))?;
+        }
+        // Only whole rows of chunks that were synced are kept
+        let size = Out::DTYPE.size_bytes();
+        let written = file
+            .metadata()
+            .map_err(|e| MmappetError::io(&staged, e))?
+            .len() as usize;
+        let done = (written / size).min(values.len()) / chunk_rows * chunk_rows;
+        file.set_len((done * size) as u64)
+            .and_then(|_| file.seek(SeekFrom::Start((done * size) as u64)))
+            .map_err(|e| MmappetError::io(&staged, e))?;
+        let mut transformed = Vec::with_capacity(chunk_rows.min(values.len() - done));
+        for chunk in values[done..].chunks(chunk_rows) {
+            transformed.clear();
+            transformed.extend(chunk.iter().map(|&value| f(value)));
+            file.write_all(cast_slice(&transformed))
+                .and_then(|_| file.sync_data())
+                .map_err(|e| MmappetError::io(&staged, e))?;
+        }
+        drop(file);
+
+        let target = self.path().join(format!("{}.bin", index));
+        fs::rename(&staged, &target).map_err(|e| MmappetError::io(&staged, e))?;
+        fs::remove_file(&manifest_path).map_err(|e| MmappetError::io(&manifest_path, e))?;
+        // Other columns may still be staged
+        let _ = fs::remove_dir(&staging);
+        write_schema(self.path(), &schema)?;
+        *self = self.reopen()?;
+        Ok(())
+    }
+}
+
+/// Replace the schema files of the dataset in `dir` with `schema`, each at
+/// once.
+fn write_schema(dir: &Path, schema: &Schema) -> Result<()> {
+    let mut files = vec![("schema.txt", schema.to_string())];
+    // Readers prefer schema.json, so it's replaced last
+    if schema.has_attributes() {
+        files.push(("schema.json", schema.to_json()));
+    }
+    for (name, content) in files {
+        let path = dir.join(name);
+        let tmp_path = dir.join(format!("{}.tmp", name));
+        fs::write(&tmp_path, content).map_err(|e| MmappetError::io(&tmp_path, e))?;
+        fs::rename(&tmp_path, &path).map_err(|e| MmappetError::io(&path, e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::write_dataset;
+
+    #[test]
+    fn test_apply_column() {
+        let dir = write_dataset("apply", &[("frame", &[1, 1, 2]), ("tof", &[10, 11, 12])]);
+        let mut ds = Dataset::open(&dir).unwrap();
+
+        // A run that died after syncing one chunk of two rows and writing
+        // part of the next resumes after the synced chunk
+        let staging = dir.join(STAGING_DIR);
+        let stage = |output: &str, input: &str| {
+            fs::create_dir_all(&staging).unwrap();
+            let staged: Vec<f64> = vec![-1.0, -2.0, -3.0];
+            let mut bytes = cast_slice::<f64, u8>(&staged).to_vec();
+            bytes.truncate(20);
+            fs::write(staging.join(format!("{}.bin", output)), bytes).unwrap();
+            let manifest = StageManifest {
+                input: input.to_string(),
+                input_dtype: "uint32".to_string(),
+                output_dtype: "float64".to_string(),
+                rows: 3,
+                chunk_rows: 2,
+            };
+            let json = serde_json::to_vec(&manifest).unwrap();
+            fs::write(staging.join(format!("{}.json", output)), json).unwrap();
+        };
+        stage("mz", "tof");
+        ds.apply_chunked::<u32, f64>("tof", "mz", |tof| tof as f64 * 0.5, 2)
+            .unwrap();
+        assert_eq!(ds.get::<f64>("mz").unwrap(), &[-1.0, -2.0, 6.0]);
+        assert_eq!(ds.schema().column_names(), ["frame", "tof", "mz"]);
+        assert!(!staging.exists());
+
+        // A stage of another input, or without a manifest, is recomputed
+        stage("from_tof", "frame");
+        ds.apply_chunked::<u32, f64>("tof", "from_tof", |tof| tof as f64 * 0.5, 2)
+            .unwrap();
+        assert_eq!(ds.get::<f64>("from_tof").unwrap(), &[5.0, 5.5, 6.0]);
+        stage("scaled", "tof");
+        fs::remove_file(staging.join("scaled.json")).unwrap();
+        ds.apply_chunked::<u32, f64>("tof", "scaled", |tof| tof as f64 * 0.5, 2)
+            .unwrap();
+        assert_eq!(ds.get::<f64>("scaled").unwrap(), &[5.0, 5.5, 6.0]);
+        assert!(!staging.exists());
+
+        ds.apply_column::<u32, f64>("tof", "mz_new", |tof| tof as f64 * 0.5)
+            .unwrap();
+        let reopened = Dataset::open(&dir).unwrap();
+        assert_eq!(reopened.get::<f64>("mz_new").unwrap(), &[5.0, 5.5, 6.0]);
+        assert!(matches!(
+            ds.apply_column::<u32, f64>("tof", "mz", |tof| tof as f64),
+            Err(MmappetError::DuplicateColumnName { .. })
+        ));
+        assert!(matches!(
+            ds.apply_column::<f32, f64>("tof", "x", |tof| tof as f64),
+            Err(MmappetError::TypeMismatch { .. })
+        ));
+        assert!(ds.apply_column::<u32, u32>("missing", "x", |x| x).is_err());
+        assert_eq!(Dataset::open(&dir).unwrap().num_columns(), 6);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,552 @@
+//! Arrow interop on the `arrow` crate.
+//!
+//! [`DatasetView::to_record_batch`] exports rows as an Arrow [`RecordBatch`]
+//! whose value buffers share the columns' mapped files instead of copying
+//! them. The buffers keep the mappings alive, so batches may outlive the
+//! dataset; batches of a dataset opened for writing see later writes to it.
+//! Offsets of strings, validity bitmaps, booleans (bit-packed in Arrow) and
+//! the labels of categorical columns are copied.
+
+use std::ops::Range;
+use std::path::Path;
+use std::ptr::NonNull;
+use std::sync::Arc;
+
+use ::arrow::array::{
+    make_array, Array, ArrayData, ArrayRef, AsArray, BooleanArray, DictionaryArray,
+    GenericStringArray, LargeStringArray, OffsetSizeTrait, RecordBatch, RecordBatchOptions,
+    UInt32Array,
+};
+use ::arrow::buffer::{BooleanBuffer, Buffer, MutableBuffer, NullBuffer, OffsetBuffer};
+use ::arrow::datatypes::{DataType, Field, Schema as ArrowSchema, SchemaRef, TimeUnit, UInt32Type};
+use ::arrow::error::ArrowError;
+
+use crate::dataset::Dataset;
+use crate::dtype::DType;
+use crate::error::{MmappetError, Result};
+use crate::schema::{ColumnDef, Schema};
+use crate::storage::Storage;
+use crate::view::DatasetView;
+use crate::writer::{create_atomically, DatasetWriter, Values};
+
+/// Time zone of exported timestamps.
+const UTC: &str = "UTC";
+
+/// Arrow type of the values of a column of `dtype`.
+fn data_type(dtype: DType) -> DataType {
+    match dtype {
+        DType::UInt8 => DataType::UInt8,
+        DType::Int8 => DataType::Int8,
+        DType::UInt16 => DataType::UInt16,
+        DType::Int16 => DataType::Int16,
+        DType::UInt32 => DataType::UInt32,
+        DType::Int32 => DataType::Int32,
+        DType::UInt64 => DataType::UInt64,
+        DType::Int64 => DataType::Int64,
+        DType::Float16 => DataType::Float16,
+        DType::Float32 => DataType::Float32,
+        DType::Float64 => DataType::Float64,
+        DType::Bool => DataType::Boolean,
+        DType::Utf8 => DataType::LargeUtf8,
+        DType::Categorical => {
+            DataType::Dictionary(Box::new(DataType::UInt32), Box::new(DataType::LargeUtf8))
+        }
+        DType::TimestampMillis => DataType::Timestamp(TimeUnit::Millisecond, Some(UTC.into())),
+        DType::TimestampMicros => DataType::Timestamp(TimeUnit::Microsecond, Some(UTC.into())),
+        DType::Custom(_) => DataType::FixedSizeBinary(dtype.size_bytes() as i32),
+    }
+}
+
+/// Arrow field of column `def`, nullable if it has a validity bitmap.
+fn field(def: &ColumnDef, nullable: bool) -> Field {
+    let values = data_type(def.dtype);
+    let data_type = if def.shape.is_empty() {
+        values
+    } else {
+        let item = Arc::new(Field::new("item", values, false));
+        DataType::FixedSizeList(item, def.width() as i32)
+    };
+    Field::new(&def.name, data_type, nullable)
+}
+
+/// Arrow schema of `columns` of `dataset`.
+pub(crate) fn arrow_schema(dataset: &Dataset, columns: &[&ColumnDef]) -> Result<SchemaRef> {
+    let mut fields = Vec::with_capacity(columns.len());
+    for def in columns {
+        fields.push(field(def, dataset.nullable(&def.name)?.has_validity()));
+    }
+    Ok(Arc::new(ArrowSchema::new(fields)))
+}
+
+/// Rows `rows` of `columns` of `dataset` as a record batch of `schema`,
+/// their [`arrow_schema`].
+pub(crate) fn record_batch(
+    dataset: &Dataset,
+    columns: &[&ColumnDef],
+    schema: &SchemaRef,
+    rows: Range<usize>,
+) -> Result<RecordBatch> {
+    let mut arrays = Vec::with_capacity(columns.len());
+    for (def, field) in columns.iter().zip(schema.fields()) {
+        arrays.push(export_column(dataset, def, field.data_type(), rows.clone())?);
+    }
+    let options = RecordBatchOptions::new().with_row_count(Some(rows.len()));
+    RecordBatch::try_new_with_options(Arc::clone(schema), arrays, &options).map_err(arrow_error)
+}
+
+/// `bytes`, which lie in `storage`, as an Arrow buffer holding on to the
+/// storage rather than a copy.
+fn share(storage: &Arc<Storage>, bytes: &[u8]) -> Buffer {
+    if bytes.is_empty() {
+        return Buffer::from(MutableBuffer::new(0));
+    }
+    let ptr = NonNull::new(bytes.as_ptr() as *mut u8).expect("slices aren't null");
+    // SAFETY: `bytes` lie in `storage`, whose mapping or allocation doesn't
+    // move and lives as long as the buffer's reference to it.
+    unsafe { Buffer::from_custom_allocation(ptr, bytes.len(), Arc::clone(storage) as _) }
+}
+
+/// Rows `rows` of column `def` as an Arrow array of type `field_type`.
+fn export_column(
+    dataset: &Dataset,
+    def: &ColumnDef,
+    field_type: &DataType,
+    rows: Range<usize>,
+) -> Result<ArrayRef> {
+    let column = dataset.mapped_column(&def.name)?;
+    let nullable = dataset.nullable(&def.name)?;
+    let nulls = nullable.has_validity().then(|| {
+        let valid: Vec<bool> = rows.clone().map(|row| nullable.is_valid(row)).collect();
+        NullBuffer::from(valid)
+    });
+    let (storage, strings) = column.shared_storage();
+    let size = column.row_size();
+    let bytes = &column.as_bytes()[rows.start * size..rows.end * size];
+
+    let array: ArrayRef = match def.dtype {
+        DType::Utf8 => {
+            let view = column.as_str_array().unwrap().slice(rows);
+            let offsets: Vec<i64> = std::iter::once(0)
+                .chain(view.relative_ends().map(|end| end as i64))
+                .collect();
+            let data = share(strings.unwrap(), view.bytes());
+            let strings = LargeStringArray::try_new(OffsetBuffer::new(offsets.into()), data, nulls);
+            Arc::new(strings.map_err(arrow_error)?)
+        }
+        DType::Categorical => {
+            let labels = column.as_categorical().unwrap();
+            let codes = UInt32Array::new(share(storage, bytes).into(), nulls);
+            let labels = LargeStringArray::from_iter_values(labels.categories());
+            let dictionary = DictionaryArray::<UInt32Type>::try_new(codes, Arc::new(labels));
+            Arc::new(dictionary.map_err(arrow_error)?)
+        }
+        dtype => {
+            let values = match dtype {
+                DType::Bool => BooleanBuffer::from_iter(bytes.iter().map(|&b| b != 0)).into_inner(),
+                _ => share(storage, bytes),
+            };
+            let values = ArrayData::builder(data_type(dtype))
+                .len(rows.len() * def.width())
+                .add_buffer(values);
+            let data = if def.shape.is_empty() {
+                values.nulls(nulls)
+            } else {
+                ArrayData::builder(field_type.clone())
+                    .len(rows.len())
+                    .add_child_data(values.build().map_err(arrow_error)?)
+                    .nulls(nulls)
+            };
+            make_array(data.build().map_err(arrow_error)?)
+        }
+    };
+    Ok(array)
+}
+
+impl DatasetView<'_> {
+    /// Export the rows as an Arrow record batch, one array per column,
+    /// without copying the values.
+    ///
+    /// | mmappet | Arrow |
+    /// |---------|-------|
+    /// | integers, floats | the same, `float16` as half floats |
+    /// | `bool` | boolean |
+    /// | `timestamp_ms`, `timestamp_us` | timestamp in ms or µs, UTC |
+    /// | `utf8` | large UTF-8 (`utf8` is also imported) |
+    /// | `categorical` | dictionary of `u32` codes to large UTF-8 labels (any integer codes are imported) |
+    /// | custom dtypes | fixed-size binary (export only) |
+    /// | `T[N]` array columns | fixed-size list of `N` values |
+    ///
+    /// The value buffers share the columns' mapped files and keep them
+    /// alive, so the batch may outlive the dataset. Returns an error if a
+    /// column can't be mapped.
+    ///
+    /// ```rust,no_run
+    /// use mmappet::{Dataset, DatasetWriter};
+    ///
+    /// let ds = Dataset::open("data.mmappet")?;
+    /// for batch in ds.iter_record_batches(1 << 20) {
+    ///     let batch = batch?;
+    ///     println!("{} rows of {:?}", batch.num_rows(), batch.schema());
+    /// }
+    ///
+    /// let copy = ds.iter_record_batches(1 << 20).collect::<Result<Vec<_>, _>>()?;
+    /// DatasetWriter::from_record_batches("copy.mmappet", copy)?;
+    /// # Ok::<(), mmappet::MmappetError>(())
+    /// ```
+    pub fn to_record_batch(&self) -> Result<RecordBatch> {
+        let dataset = self.dataset();
+        let columns: Vec<&ColumnDef> = dataset.schema().columns().collect();
+        let schema = arrow_schema(dataset, &columns)?;
+        record_batch(dataset, &columns, &schema, self.rows())
+    }
+}
+
+impl Dataset {
+    /// Export all rows as an Arrow record batch, see
+    /// [`DatasetView::to_record_batch`].
+    pub fn to_record_batch(&self) -> Result<RecordBatch> {
+        self.slice(..).to_record_batch()
+    }
+
+    /// Iterate over the dataset as Arrow record batches of `chunk_rows` rows,
+    /// the last one possibly shorter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_rows` is zero.
+    pub fn iter_record_batches(
+        &self,
+        chunk_rows: usize,
+    ) -> impl Iterator<Item = Result<RecordBatch>> + '_ {
+        self.chunks(chunk_rows).map(|view| view.to_record_batch())
+    }
+}
+
+/// Dtype of values of Arrow type `data_type`.
+fn scalar_type(data_type: &DataType) -> Option<DType> {
+    Some(match data_type {
+        DataType::UInt8 => DType::UInt8,
+        DataType::Int8 => DType::Int8,
+        DataType::UInt16 => DType::UInt16,
+        DataType::Int16 => DType::Int16,
+        DataType::UInt32 => DType::UInt32,
+        DataType::Int32 => DType::Int32,
+        DataType::UInt64 => DType::UInt64,
+        DataType::Int64 => DType::Int64,
+        DataType::Float16 => DType::Float16,
+        DataType::Float32 => DType::Float32,
+        DataType::Float64 => DType::Float64,
+        DataType::Boolean => DType::Bool,
+        DataType::Utf8 | DataType::LargeUtf8 => DType::Utf8,
+        DataType::Timestamp(TimeUnit::Millisecond, _) => DType::TimestampMillis,
+        DataType::Timestamp(TimeUnit::Microsecond, _) => DType::TimestampMicros,
+        _ => return None,
+    })
+}
+
+/// Dtype of a column of Arrow type `data_type`, and its width if it's a
+/// fixed-size list.
+fn column_type(data_type: &DataType) -> Option<(DType, Option<usize>)> {
+    match data_type {
+        DataType::Dictionary(key, labels) => {
+            let labels = matches!(**labels, DataType::Utf8 | DataType::LargeUtf8);
+            (key.is_dictionary_key_type() && labels).then_some((DType::Categorical, None))
+        }
+        DataType::FixedSizeList(item, width) => match scalar_type(item.data_type()) {
+            Some(DType::Utf8) | None => None,
+            Some(dtype) => Some((dtype, Some(*width as usize))),
+        },
+        _ => scalar_type(data_type).map(|dtype| (dtype, None)),
+    }
+}
+
+impl Schema {
+    /// The mmappet schema of the Arrow schema `schema`, with types mapped as
+    /// by [`DatasetView::to_record_batch`].
+    ///
+    /// Returns an error naming every column whose Arrow type has no mmappet
+    /// dtype.
+    pub fn from_arrow(schema: &ArrowSchema) -> Result<Schema> {
+        let mut columns = Vec::with_capacity(schema.fields().len());
+        let mut shapes = Vec::new();
+        let mut unsupported = Vec::new();
+        for field in schema.fields() {
+            match column_type(field.data_type()) {
+                Some((dtype, width)) => {
+                    columns.push((field.name().as_str(), dtype));
+                    shapes.extend(width.map(|width| (field.name(), width)));
+                }
+                None => unsupported.push(format!("'{}' ({})", field.name(), field.data_type())),
+            }
+        }
+        if !unsupported.is_empty() {
+            return Err(MmappetError::Import {
+                path: None,
+                offset: None,
+                message: format!("no mmappet dtype for columns {}", unsupported.join(", ")),
+            });
+        }
+        let mut mmappet_schema = Schema::new(&columns)?;
+        for (name, width) in shapes {
+            mmappet_schema.set_shape(name, &[width])?;
+        }
+        Ok(mmappet_schema)
+    }
+}
+
+/// Strings of `array`, `""` for nulls.
+fn read_strs<'b, O: OffsetSizeTrait>(
+    array: &'b GenericStringArray<O>,
+    validity: Option<&[bool]>,
+) -> Vec<&'b str> {
+    (0..array.len())
+        .map(|row| match validity {
+            Some(validity) if !validity[row] => "",
+            _ => array.value(row),
+        })
+        .collect()
+}
+
+/// Strings of a UTF-8 or large UTF-8 array, `""` for nulls.
+fn strs<'b>(array: &'b dyn Array, validity: Option<&[bool]>) -> Vec<&'b str> {
+    match array.data_type() {
+        DataType::Utf8 => read_strs(array.as_string::<i32>(), validity),
+        _ => read_strs(array.as_string::<i64>(), validity),
+    }
+}
+
+/// Values of column `def` of a batch, and one validity flag per row if it
+/// has nulls.
+fn import_column<'b>(def: &ColumnDef, array: &'b dyn Array) -> (Values<'b>, Option<Vec<bool>>) {
+    let validity: Option<Vec<bool>> = array
+        .nulls()
+        .filter(|nulls| nulls.null_count() > 0)
+        .map(|nulls| nulls.iter().collect());
+    let values = match def.dtype {
+        DType::Utf8 => Values::Strs(strs(array, validity.as_deref())),
+        DType::Categorical => {
+            let dictionary = array.as_any_dictionary();
+            let labels = strs(dictionary.values().as_ref(), None);
+            let codes = dictionary.normalized_keys();
+            let values = (0..array.len()).map(|row| match &validity {
+                Some(validity) if !validity[row] => "",
+                _ => labels[codes[row]],
+            });
+            Values::Labels(values.collect())
+        }
+        dtype => {
+            let data = array.to_data();
+            let data = if def.shape.is_empty() {
+                data
+            } else {
+                let width = def.width();
+                data.child_data()[0].slice(data.offset() * width, data.len() * width)
+            };
+            let mut bytes = if dtype == DType::Bool {
+                BooleanArray::from(data)
+                    .values()
+                    .iter()
+                    .map(u8::from)
+                    .collect()
+            } else {
+                let size = dtype.size_bytes();
+                let start = data.offset() * size;
+                data.buffers()[0][start..start + data.len() * size].to_vec()
+            };
+            if let Some(validity) = &validity {
+                zero_nulls(&mut bytes, validity);
+            }
+            Values::Bytes(bytes)
+        }
+    };
+    (values, validity)
+}
+
+/// Zero the values of null rows in `bytes`, holding one row per flag; nulls
+/// are stored as zero.
+fn zero_nulls(bytes: &mut [u8], validity: &[bool]) {
+    let row_size = bytes.len() / validity.len().max(1);
+    for (row, valid) in validity.iter().enumerate() {
+        if !valid {
+            bytes[row * row_size..(row + 1) * row_size].fill(0);
+        }
+    }
+}
+
+impl DatasetWriter {
+    /// Create a dataset from Arrow record batches, with the schema of the
+    /// first one.
+    ///
+    /// Returns the number of rows written, or an error if there are no
+    /// batches, a column's Arrow type has no mmappet dtype or the batches
+    /// differ in columns.
+    pub fn from_record_batches<P: AsRef<Path>>(
+        dir: P,
+        batches: impl IntoIterator<Item = RecordBatch>,
+    ) -> Result<usize> {
+        let dir = dir.as_ref();
+        let mut batches = batches.into_iter();
+        let first = batches.next().ok_or_else(|| MmappetError::InvalidBatch {
+            path: Some(dir.to_path_buf()),
+            message: "no record batches".to_string(),
+        })?;
+        let schema = Schema::from_arrow(&first.schema())?;
+        create_atomically(dir, |tmp_dir| {
+            let mut writer = DatasetWriter::create(tmp_dir, &schema)?;
+            for batch in std::iter::once(first).chain(batches) {
+                writer.write_record_batch(&batch)?;
+            }
+            writer.finish()
+        })
+    }
+
+    /// Append an Arrow record batch, whose columns must match the schema in
+    /// name, order, type and shape.
+    ///
+    /// Nulls are kept in validity bitmaps. Nothing is written if the batch
+    /// doesn't match the schema.
+    pub fn write_record_batch(&mut self, batch: &RecordBatch) -> Result<()> {
+        let schema = Schema::from_arrow(&batch.schema())?;
+        let names = |schema: &Schema| -> Vec<String> {
+            schema
+                .columns()
+                .map(|c| format!("{} {}", c.type_name(), c.name))
+                .collect()
+        };
+        let (expected, actual) = (names(self.schema()), names(&schema));
+        if expected != actual {
+            return Err(MmappetError::InvalidBatch {
+                path: None,
+                message: format!(
+                    "columns [{}] differ from [{}]",
+                    actual.join(", "),
+                    expected.join(", ")
+                ),
+            });
+        }
+        let columns = schema
+            .columns()
+            .zip(batch.columns())
+            .map(|(def, array)| import_column(def, array.as_ref()))
+            .collect();
+        self.write_columns(columns, batch.num_rows())
+    }
+}
+
+pub(crate) fn arrow_error(e: ArrowError) -> MmappetError {
+    MmappetError::InvalidBatch {
+        path: None,
+        message: e.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::arrow::datatypes::Float64Type;
+
+    #[test]
+    fn test_record_batch_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("mmappet-arrow-{}", std::process::id()));
+        let copy = dir.with_extension("copy");
+        let mut schema = Schema::new(&[
+            ("tof", DType::UInt32),
+            ("mz", DType::Float64),
+            ("xy", DType::Float32),
+            ("peptide", DType::Utf8),
+            ("charge", DType::Categorical),
+            ("time", DType::TimestampMicros),
+            ("decoy", DType::Bool),
+        ])
+        .unwrap();
+        schema.set_shape("xy", &[2]).unwrap();
+        let rows = 20usize;
+        let tofs: Vec<Option<u32>> = (0..rows as u32)
+            .map(|i| (i % 3 != 0).then_some(i))
+            .collect();
+        let peptides: Vec<String> = (0..rows).map(|i| "PEPTIDE"[..i % 7].to_string()).collect();
+        let charges: Vec<&str> = (0..rows).map(|i| ["2+", "3+"][i % 2]).collect();
+        let decoys: Vec<u8> = (0..rows).map(|i| (i % 3 == 0) as u8).collect();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write_opt("tof", &tofs).unwrap();
+        writer
+            .write("mz", &(0..rows).map(|i| i as f64 / 4.0).collect::<Vec<_>>())
+            .unwrap();
+        writer
+            .write("xy", &(0..2 * rows).map(|i| i as f32).collect::<Vec<_>>())
+            .unwrap();
+        let strs: Vec<&str> = peptides.iter().map(String::as_str).collect();
+        writer.write_str("peptide", &strs).unwrap();
+        writer.write_categorical("charge", &charges).unwrap();
+        writer
+            .write_timestamps("time", &(0..rows as i64).collect::<Vec<_>>())
+            .unwrap();
+        writer.write_bytes(6, &decoys, rows).unwrap();
+        writer.finish().unwrap();
+
+        let ds = Dataset::open(&dir).unwrap();
+        let batch = ds.to_record_batch().unwrap();
+        assert_eq!((batch.num_rows(), batch.num_columns()), (rows, 7));
+        assert_eq!(
+            Schema::from_arrow(&batch.schema()).unwrap().to_string(),
+            ds.schema().to_string()
+        );
+        // Values are the mapped ones, and stay valid without the dataset
+        let mz = batch
+            .column(1)
+            .as_primitive::<Float64Type>()
+            .values()
+            .as_ptr();
+        assert_eq!(mz, ds.get::<f64>("mz").unwrap().as_ptr());
+        drop(ds);
+        assert_eq!(batch.column(1).as_primitive::<Float64Type>().value(3), 0.75);
+        assert_eq!(batch.column(3).as_string::<i64>().value(4), "PEPT");
+
+        // Chunks starting mid-byte of the validity bitmaps
+        let ds = Dataset::open(&dir).unwrap();
+        let batches = ds
+            .iter_record_batches(7)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            DatasetWriter::from_record_batches(&copy, batches).unwrap(),
+            rows
+        );
+
+        let out = Dataset::open(&copy).unwrap();
+        assert_eq!(out.schema().to_string(), ds.schema().to_string());
+        let tof = out.nullable("tof").unwrap();
+        assert_eq!(tof.iter_opt::<u32>().unwrap().collect::<Vec<_>>(), tofs);
+        for name in ["mz", "xy", "time", "decoy"] {
+            assert_eq!(out[name].as_bytes(), ds[name].as_bytes(), "{}", name);
+        }
+        assert_eq!(
+            out["peptide"].iter_str().unwrap().collect::<Vec<_>>(),
+            peptides
+        );
+        let labels = out["charge"].as_categorical().unwrap();
+        assert_eq!(labels.iter().collect::<Vec<_>>(), charges);
+
+        let bad = copy.with_extension("bad");
+        let schema = Schema::new(&[("tof", DType::UInt32)]).unwrap();
+        let mut writer = DatasetWriter::create(&bad, &schema).unwrap();
+        assert!(writer
+            .write_record_batch(&out.to_record_batch().unwrap())
+            .is_err());
+
+        // Columns without a mmappet dtype are all named
+        let fields = vec![
+            Field::new("a", DataType::Int64, false),
+            Field::new("b", DataType::Date32, false),
+            Field::new("c", DataType::Binary, false),
+        ];
+        let err = Schema::from_arrow(&ArrowSchema::new(fields))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("'b' (Date32), 'c' (Binary)"), "{}", err);
+
+        for dir in [dir, copy, bad] {
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
@@ -1,10 +1,20 @@
 //! mmappet CLI - command line tool for inspecting mmappet datasets.
 
 use anyhow::Result;
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::fs;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hasher;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{mpsc, Mutex};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use mmappet::{Dataset, TypedArrayView};
+use mmappet::{
+    BoolStyle, Column, ColumnAttributes, ColumnStats, CsvOptions, DType, Dataset, DiffOptions,
+    Expr, MmappetError, NullableColumn, TimestampStyle, TypedArrayView, ValueFormat,
+};
 
 #[derive(Parser)]
 #[command(name = "mmappet-cli")]
@@ -16,10 +26,14 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Show dataset info (schema, row count)
+    /// Show dataset info (schema, row count, per-column min/max)
     Info {
         /// Path to the mmappet dataset directory
         path: PathBuf,
+
+        /// Only show the schema, skipping the min/max scan over the data
+        #[arg(long)]
+        fast: bool,
     },
 
     /// Print first N rows of specified columns
@@ -34,6 +48,34 @@ enum Commands {
         /// Columns to show (comma-separated, or all if not specified)
         #[arg(short, long)]
         columns: Option<String>,
+
+        /// Only show rows matching an expression, e.g. "intensity > 1e5 and ms_level == 2"
+        #[arg(short, long = "where", value_name = "EXPR")]
+        where_: Option<String>,
+
+        #[command(flatten)]
+        format: FormatArgs,
+    },
+
+    /// Print last N rows of specified columns
+    Tail {
+        /// Path to the mmappet dataset directory
+        path: PathBuf,
+
+        /// Number of rows to show
+        #[arg(short, long, default_value = "10")]
+        n: usize,
+
+        /// Columns to show (comma-separated, or all if not specified)
+        #[arg(short, long)]
+        columns: Option<String>,
+
+        /// Only show rows matching an expression, e.g. "intensity > 1e5 and ms_level == 2"
+        #[arg(short, long = "where", value_name = "EXPR")]
+        where_: Option<String>,
+
+        #[command(flatten)]
+        format: FormatArgs,
     },
 
     /// Show statistics for numeric columns
@@ -58,36 +100,669 @@ enum Commands {
         /// Width of the plot in characters
         #[arg(short, long, default_value = "60")]
         width: usize,
+
+        /// Plot a histogram of all values in this many bins instead of rows
+        #[arg(short, long)]
+        bins: Option<usize>,
+    },
+
+    /// List mmappet datasets found under a directory
+    Ls {
+        /// Directory to scan for datasets
+        root: PathBuf,
+
+        /// Descend into subdirectories that are not datasets themselves
+        #[arg(short, long)]
+        recursive: bool,
+
+        /// Output format
+        #[arg(long, value_enum, default_value = "table")]
+        format: ListFormat,
+    },
+
+    /// Replace byte-identical column files under a directory with hardlinks
+    ///
+    /// Linked files share storage: modifying one in place (e.g. through a
+    /// mutable mapping) modifies all of them.
+    DedupeStorage {
+        /// Directory to scan for datasets (recursively)
+        root: PathBuf,
+
+        /// Only report what would be linked
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Print a CREATE TABLE statement and COPY-compatible data for loading into a database
+    ExportSql {
+        /// Path to the mmappet dataset directory
+        path: PathBuf,
+
+        /// Target database
+        #[arg(long, value_enum, default_value = "postgres")]
+        dialect: SqlDialect,
+
+        /// Table name (defaults to the dataset directory name without extension)
+        #[arg(long)]
+        table: Option<String>,
+
+        /// Only print the CREATE TABLE statement
+        #[arg(long)]
+        schema_only: bool,
+
+        #[command(flatten)]
+        format: FormatArgs,
+    },
+
+    /// Print rows as CSV
+    ExportCsv {
+        /// Path to the mmappet dataset directory
+        path: PathBuf,
+
+        /// Columns to export (comma-separated, or all if not specified)
+        #[arg(short, long)]
+        columns: Option<String>,
+
+        /// First row to export
+        #[arg(long, default_value = "0")]
+        start: usize,
+
+        /// Row after the last one to export (defaults to the row count)
+        #[arg(long)]
+        end: Option<usize>,
+
+        /// Field separator (an ASCII character)
+        #[arg(long, default_value = ",")]
+        delimiter: char,
+
+        /// Leave out the header line of column names
+        #[arg(long)]
+        no_header: bool,
+
+        #[command(flatten)]
+        format: FormatArgs,
+    },
+
+    /// Write a column to a NumPy .npy file, or all columns to an .npz archive
+    ExportNumpy {
+        /// Path to the mmappet dataset directory
+        path: PathBuf,
+
+        /// Path of the .npy or .npz file to create
+        output: PathBuf,
+
+        /// Column to write as a .npy file (all columns as an .npz archive if not specified)
+        #[arg(short, long)]
+        column: Option<String>,
+    },
+
+    /// Write a dataset without duplicate rows, keeping the first of each
+    Unique {
+        /// Path to the mmappet dataset directory
+        input: PathBuf,
+
+        /// Path of the dataset directory to create
+        output: PathBuf,
+
+        /// Columns whose values identify duplicates (comma-separated, or all if not specified)
+        #[arg(short, long)]
+        keys: Option<String>,
+    },
+
+    /// Convert a NumPy .npy file or .npz archive into a dataset
+    ImportNumpy {
+        /// Path to the .npy or .npz file
+        input: PathBuf,
+
+        /// Path of the dataset directory to create
+        output: PathBuf,
+    },
+
+    /// Show or set dataset metadata; every change is kept in a provenance log
+    Meta {
+        /// Path to the mmappet dataset directory
+        path: PathBuf,
+
+        #[command(subcommand)]
+        action: MetaAction,
+    },
+
+    /// Manage persistent secondary indexes used by lookups
+    Index {
+        #[command(subcommand)]
+        action: IndexAction,
+    },
+
+    /// Check files, lengths, non-finite values, sorted flags and checksums
+    Validate {
+        /// Path to the mmappet dataset directory
+        path: PathBuf,
+
+        /// Record the current checksums in the metadata first
+        #[arg(long)]
+        record_checksums: bool,
+    },
+
+    /// Compare two datasets: schemas, row counts and values, column by column
+    Diff {
+        /// Path to the first mmappet dataset directory
+        left: PathBuf,
+
+        /// Path to the second mmappet dataset directory
+        right: PathBuf,
+
+        /// Largest absolute difference of floats counted as equal (bitwise if 0)
+        #[arg(long, default_value = "0")]
+        tolerance: f64,
+
+        /// Largest difference of floats counted as equal, relative to their magnitude
+        #[arg(long, default_value = "0")]
+        rel_tolerance: f64,
+    },
+
+    /// Load column data into the page cache ahead of latency-sensitive queries
+    Warm {
+        /// Path to the mmappet dataset directory
+        path: PathBuf,
+
+        /// Columns to warm (comma-separated, or all if not specified)
+        #[arg(short, long)]
+        columns: Option<String>,
+
+        /// First row to warm
+        #[arg(long, default_value = "0")]
+        start: usize,
+
+        /// Row after the last one to warm (defaults to the row count)
+        #[arg(long)]
+        end: Option<usize>,
+
+        /// Read the pages before exiting instead of only requesting them
+        #[arg(long)]
+        wait: bool,
+    },
+
+    /// Serve datasets over HTTP for dashboards and other tools
+    ///
+    /// Each dataset is served under /<name>/, its directory name without extension:
+    /// /<name>/schema, /<name>/rows?offset=0&limit=100&columns=a,b&format=json|arrow
+    /// and /<name>/stats/<column>. / lists the datasets. Responses are JSON, or Arrow
+    /// IPC files for format=arrow (with the arrow feature).
+    Serve {
+        /// Paths to the mmappet dataset directories
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: String,
+
+        /// Requests answered at the same time; further connections wait
+        #[arg(long, default_value_t = 8)]
+        threads: usize,
+    },
+
+    /// Convert a Bruker timsTOF .d directory into a dataset
+    #[cfg(feature = "bruker")]
+    ImportBruker {
+        /// Path to the .d directory
+        input: PathBuf,
+
+        /// Path of the dataset directory to create
+        output: PathBuf,
+    },
+
+    /// Convert an mzML file into a dataset
+    #[cfg(feature = "mzml")]
+    ImportMzml {
+        /// Path to the mzML file
+        input: PathBuf,
+
+        /// Path of the dataset directory to create
+        output: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum MetaAction {
+    /// Print metadata (all keys, or only the given ones)
+    Get {
+        keys: Vec<String>,
+    },
+
+    /// Set one or more KEY=VALUE pairs
+    Set {
+        #[arg(required = true, value_name = "KEY=VALUE")]
+        pairs: Vec<String>,
+    },
+
+    /// Print the provenance log, oldest change first
+    History,
+}
+
+#[derive(Subcommand)]
+enum IndexAction {
+    /// Build the index of a column and store it as COLUMN.idx
+    Build {
+        /// Path to the mmappet dataset directory
+        path: PathBuf,
+
+        /// Column to index
+        column: String,
+    },
+
+    /// Build the zone map (per-chunk min/max) of a column, stored as COLUMN.zones
+    Zones {
+        /// Path to the mmappet dataset directory
+        path: PathBuf,
+
+        /// Column to map
+        column: String,
+    },
+
+    /// Build a Bloom filter of an integer column, stored as COLUMN.bloom
+    Bloom {
+        /// Path to the mmappet dataset directory
+        path: PathBuf,
+
+        /// Column to build the filter for
+        column: String,
     },
 }
 
+/// Value formatting flags shared by commands that print rows.
+#[derive(Args)]
+struct FormatArgs {
+    /// Digits after the decimal point for floats (6 for head and tail, the
+    /// shortest exact text for exports if not specified)
+    #[arg(long)]
+    precision: Option<usize>,
+
+    /// Use scientific notation for non-zero floats with magnitude below this
+    #[arg(long)]
+    sci_below: Option<f64>,
+
+    /// Use scientific notation for floats with magnitude at or above this
+    #[arg(long)]
+    sci_above: Option<f64>,
+
+    /// Separator between groups of three integer digits (e.g. ',')
+    #[arg(long)]
+    thousands_sep: Option<char>,
+
+    /// How to render bool columns
+    #[arg(long, value_enum, default_value = "true-false")]
+    bool_style: BoolStyleArg,
+
+    /// How to render timestamp columns (iso8601 if not specified, space for export-sql)
+    #[arg(long, value_enum)]
+    timestamp_style: Option<TimestampStyleArg>,
+}
+
+impl FormatArgs {
+    /// The format given by the flags, falling back to `defaults` for the
+    /// precision and timestamp style.
+    fn to_value_format(&self, defaults: ValueFormat) -> ValueFormat {
+        ValueFormat {
+            precision: self.precision.or(defaults.precision),
+            sci_below: self.sci_below,
+            sci_above: self.sci_above,
+            thousands_separator: self.thousands_sep,
+            bool_style: match self.bool_style {
+                BoolStyleArg::TrueFalse => BoolStyle::TrueFalse,
+                BoolStyleArg::OneZero => BoolStyle::OneZero,
+            },
+            timestamp_style: match self.timestamp_style {
+                Some(TimestampStyleArg::Iso8601) => TimestampStyle::Iso8601,
+                Some(TimestampStyleArg::Space) => TimestampStyle::Space,
+                Some(TimestampStyleArg::Ticks) => TimestampStyle::Ticks,
+                None => defaults.timestamp_style,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BoolStyleArg {
+    TrueFalse,
+    OneZero,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TimestampStyleArg {
+    Iso8601,
+    Space,
+    Ticks,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum SqlDialect {
+    Postgres,
+    Clickhouse,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ListFormat {
+    Table,
+    Json,
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Info { path } => cmd_info(&path),
-        Commands::Head { path, n, columns } => cmd_head(&path, n, columns),
+        Commands::Info { path, fast } => cmd_info(&path, fast),
+        Commands::Head { path, n, columns, where_, format } => {
+            let format = format.to_value_format(ValueFormat::default());
+            cmd_head(&path, n, columns, where_.as_deref(), &format, false)
+        }
+        Commands::Tail { path, n, columns, where_, format } => {
+            let format = format.to_value_format(ValueFormat::default());
+            cmd_head(&path, n, columns, where_.as_deref(), &format, true)
+        }
         Commands::Stats { path } => cmd_stats(&path),
-        Commands::Plot { path, n, column, width } => cmd_plot(&path, n, column, width),
+        Commands::Plot { path, n, column, width, bins } => cmd_plot(&path, n, column, width, bins),
+        Commands::Ls { root, recursive, format } => cmd_ls(&root, recursive, format),
+        Commands::DedupeStorage { root, dry_run } => cmd_dedupe_storage(&root, dry_run),
+        Commands::ExportSql { path, dialect, table, schema_only, format } => {
+            // Floats round-trip and timestamps are in the form both databases read
+            let defaults = ValueFormat {
+                precision: None,
+                timestamp_style: TimestampStyle::Space,
+                ..ValueFormat::default()
+            };
+            cmd_export_sql(&path, dialect, table, schema_only, &format.to_value_format(defaults))
+        }
+        Commands::ExportCsv { path, columns, start, end, delimiter, no_header, format } => {
+            let delimiter = u8::try_from(delimiter)
+                .ok()
+                .filter(u8::is_ascii)
+                .ok_or_else(|| anyhow::anyhow!("Delimiter '{}' is not an ASCII character", delimiter))?;
+            let format = format.to_value_format(CsvOptions::default().format);
+            let options = CsvOptions { delimiter, format, header: !no_header };
+            cmd_export_csv(&path, columns, start, end, &options)
+        }
+        Commands::ExportNumpy { path, output, column } => {
+            let ds = Dataset::open(&path)?;
+            match column {
+                Some(column) => ds.export_npy(&column, &output)?,
+                None => ds.export_npz(&output)?,
+            }
+            Ok(())
+        }
+        Commands::Unique { input, output, keys } => {
+            let ds = Dataset::open(&input)?;
+            let keys: Vec<&str> = match &keys {
+                Some(cols) => cols.split(',').map(|s| s.trim()).collect(),
+                None => Vec::new(),
+            };
+            let rows = ds.write_unique(&keys, &output)?;
+            record_source(&input, &output)?;
+            println!("Wrote {} of {} rows to {}", rows, ds.len(), output.display());
+            Ok(())
+        }
+        Commands::ImportNumpy { input, output } => {
+            let ds = match input.extension().and_then(|e| e.to_str()) {
+                Some("npz") => Dataset::from_npz(&input, &output)?,
+                _ => Dataset::from_npy(&input, &output)?,
+            };
+            record_source(&input, &output)?;
+            println!("Wrote {} rows to {}", ds.len(), output.display());
+            Ok(())
+        }
+        Commands::Meta { path, action } => cmd_meta(&path, action),
+        Commands::Index { action } => cmd_index(action),
+        Commands::Validate { path, record_checksums } => cmd_validate(&path, record_checksums),
+        Commands::Diff { left, right, tolerance, rel_tolerance } => {
+            cmd_diff(&left, &right, tolerance, rel_tolerance)
+        }
+        Commands::Warm { path, columns, start, end, wait } => cmd_warm(&path, columns, start, end, wait),
+        Commands::Serve { paths, addr, threads } => cmd_serve(&paths, &addr, threads),
+        #[cfg(feature = "bruker")]
+        Commands::ImportBruker { input, output } => {
+            let rows = mmappet::import_bruker(&input, &output)?;
+            record_source(&input, &output)?;
+            println!("Wrote {} rows to {}", rows, output.display());
+            Ok(())
+        }
+        #[cfg(feature = "mzml")]
+        Commands::ImportMzml { input, output } => {
+            let rows = mmappet::import_mzml(&input, &output)?;
+            record_source(&input, &output)?;
+            println!("Wrote {} rows to {}", rows, output.display());
+            Ok(())
+        }
+    }
+}
+
+/// Describe the schema.json attributes of a column other than its unit, which
+/// is part of the column label.
+fn attribute_details(attributes: &ColumnAttributes) -> String {
+    let mut parts = Vec::new();
+    if attributes.sorted {
+        parts.push("sorted".to_string());
+    }
+    if attributes.scale.is_some() || attributes.offset.is_some() {
+        parts.push(format!(
+            "value = raw * {} + {}",
+            attributes.scale.unwrap_or(1.0),
+            attributes.offset.unwrap_or(0.0)
+        ));
+    }
+    if let Some(null) = attributes.null_value {
+        parts.push(format!("null={}", null));
+    }
+    if let Some(description) = &attributes.description {
+        parts.push(description.clone());
     }
+    parts.join("; ")
 }
 
-fn cmd_info(path: &PathBuf) -> Result<()> {
+fn cmd_info(path: &PathBuf, fast: bool) -> Result<()> {
     let ds = Dataset::open(path)?;
 
+    // Map every column without reading it, so residency is measured before
+    // the min/max scan below pulls all pages in
+    for name in ds.column_names() {
+        ds.column(name);
+    }
+    let memory = ds.memory_report()?;
+
     println!("Dataset: {}", path.display());
     println!("Rows: {}", ds.len());
     println!("Columns: {}", ds.num_columns());
+    println!("Format version: {}", ds.schema().version());
+    println!("Byte order: {}", ds.schema().byte_order());
     println!();
     println!("Schema:");
-    for col_def in ds.schema().columns() {
-        println!("  {:>2}. {} ({})", col_def.index, col_def.name, col_def.dtype);
+
+    let labels: Vec<String> = ds
+        .schema()
+        .columns()
+        .map(|c| {
+            let mut label = match &c.reference {
+                Some(r) => format!("{} ({}) @{}/{}", c.name, c.type_name(), r.dataset.display(), r.column),
+                None => format!("{} ({})", c.name, c.type_name()),
+            };
+            if let Some(unit) = &c.attributes.unit {
+                label.push_str(&format!(" [{}]", unit));
+            }
+            label
+        })
+        .collect();
+    let label_width = labels.iter().map(|l| l.len()).max().unwrap_or(0);
+    let format = ValueFormat::default();
+
+    for (col_def, label) in ds.schema().columns().zip(&labels) {
+        let details = attribute_details(&col_def.attributes);
+        if fast {
+            println!("  {:>2}. {}", col_def.index, label);
+            if !details.is_empty() {
+                println!("      {}", details);
+            }
+            continue;
+        }
+        let column = ds.nullable(&col_def.name)?;
+        let mut summary = match summarize_column(&column, &format) {
+            Some(s) => match s.nan_count {
+                Some(nan) => format!("min={}, max={}, nan={}", s.min, s.max, nan),
+                None => format!("min={}, max={}", s.min, s.max),
+            },
+            None if column.null_count() > 0 => "(all null)".to_string(),
+            None => "(empty)".to_string(),
+        };
+        if column.has_validity() {
+            summary.push_str(&format!(", null={}", column.null_count()));
+        }
+        println!("  {:>2}. {:<label_w$}  {}", col_def.index, label, summary, label_w = label_width);
+        if !details.is_empty() {
+            println!("      {}", details);
+        }
+    }
+
+    println!();
+    println!("Memory (resident before this command):");
+    let name_width = memory.columns.iter().map(|c| c.name.len()).chain([5]).max().unwrap();
+    for col in &memory.columns {
+        println!(
+            "  {:<name_w$}  {}",
+            col.name,
+            residency(col.resident_bytes, col.mapped_bytes),
+            name_w = name_width
+        );
     }
+    println!(
+        "  {:<name_w$}  {}",
+        "total",
+        residency(memory.resident_bytes(), memory.mapped_bytes()),
+        name_w = name_width
+    );
 
     Ok(())
 }
 
-fn cmd_head(path: &PathBuf, n: usize, columns: Option<String>) -> Result<()> {
+/// `resident of mapped (percent)`, or only the mapped size if residency is unknown.
+fn residency(resident: Option<usize>, mapped: usize) -> String {
+    match resident {
+        Some(resident) if mapped > 0 => format!(
+            "{} of {} resident ({:.0}%)",
+            format_size(resident as u64),
+            format_size(mapped as u64),
+            100.0 * resident as f64 / mapped as f64
+        ),
+        Some(_) => format_size(0),
+        None => format!("{} mapped", format_size(mapped as u64)),
+    }
+}
+
+/// Rendered min/max of a column, plus the NaN count for float columns.
+struct ColumnSummary {
+    min: String,
+    max: String,
+    nan_count: Option<usize>,
+}
+
+/// Compute the min/max summary of the non-null rows of a column; `None` if
+/// there are none.
+fn summarize_column(nullable: &NullableColumn, format: &ValueFormat) -> Option<ColumnSummary> {
+    fn ints<T: Copy + Ord + Into<i128>>(
+        mut values: impl Iterator<Item = T>,
+        format: &ValueFormat,
+    ) -> Option<ColumnSummary> {
+        let first = values.next()?;
+        let (min, max) = values.fold((first, first), |(min, max), x| (min.min(x), max.max(x)));
+        Some(ColumnSummary {
+            min: format.format_int(min),
+            max: format.format_int(max),
+            nan_count: None,
+        })
+    }
+
+    fn floats(values: impl Iterator<Item = f64>, format: &ValueFormat) -> Option<ColumnSummary> {
+        let mut min = f64::INFINITY;
+        let mut max = f64::NEG_INFINITY;
+        let mut nan_count = 0;
+        for x in values {
+            if x.is_nan() {
+                nan_count += 1;
+            } else {
+                min = min.min(x);
+                max = max.max(x);
+            }
+        }
+        if min > max {
+            // No non-NaN values
+            if nan_count == 0 {
+                return None;
+            }
+            min = f64::NAN;
+            max = f64::NAN;
+        }
+        Some(ColumnSummary {
+            min: format.format_float(min),
+            max: format.format_float(max),
+            nan_count: Some(nan_count),
+        })
+    }
+
+    let col = nullable.column();
+    // Values of array columns are flattened, `width` of them per row
+    let width = col.width().max(1);
+    let valid = |k: usize| nullable.is_valid(k / width);
+
+    match col.as_typed_array() {
+        TypedArrayView::UInt8(arr) => ints(valid_values(arr.iter(), valid).copied(), format),
+        TypedArrayView::Int8(arr) => ints(valid_values(arr.iter(), valid).copied(), format),
+        TypedArrayView::UInt16(arr) => ints(valid_values(arr.iter(), valid).copied(), format),
+        TypedArrayView::Int16(arr) => ints(valid_values(arr.iter(), valid).copied(), format),
+        TypedArrayView::UInt32(arr) => ints(valid_values(arr.iter(), valid).copied(), format),
+        TypedArrayView::Int32(arr) => ints(valid_values(arr.iter(), valid).copied(), format),
+        TypedArrayView::UInt64(arr) => ints(valid_values(arr.iter(), valid).copied(), format),
+        TypedArrayView::Int64(arr) => ints(valid_values(arr.iter(), valid).copied(), format),
+        TypedArrayView::Float16(arr) => floats(valid_values(arr.iter(), valid).map(|x| x.to_f64()), format),
+        TypedArrayView::Float32(arr) => floats(valid_values(arr.iter(), valid).map(|&x| x as f64), format),
+        TypedArrayView::Float64(arr) => floats(valid_values(arr.iter(), valid).copied(), format),
+        TypedArrayView::TimestampMillis(arr) | TypedArrayView::TimestampMicros(arr) => {
+            let per_second = col.dtype().ticks_per_second().unwrap();
+            Some(ColumnSummary {
+                min: format.format_timestamp(*valid_values(arr.iter(), valid).min()?, per_second),
+                max: format.format_timestamp(*valid_values(arr.iter(), valid).max()?, per_second),
+                nan_count: None,
+            })
+        }
+        TypedArrayView::Bool(arr) => {
+            let min = valid_values(arr.iter(), valid).map(|&x| x != 0).min()?;
+            let max = valid_values(arr.iter(), valid).map(|&x| x != 0).max()?;
+            Some(ColumnSummary {
+                min: format.format_bool(min),
+                max: format.format_bool(max),
+                nan_count: None,
+            })
+        }
+        TypedArrayView::Utf8(arr) => Some(ColumnSummary {
+            min: valid_values(arr.iter(), valid).min()?.to_string(),
+            max: valid_values(arr.iter(), valid).max()?.to_string(),
+            nan_count: None,
+        }),
+        TypedArrayView::Categorical(arr) => Some(ColumnSummary {
+            min: valid_values(arr.iter(), valid).min()?.to_string(),
+            max: valid_values(arr.iter(), valid).max()?.to_string(),
+            nan_count: None,
+        }),
+        TypedArrayView::Custom(arr) => {
+            arr.def().to_f64?;
+            floats((0..arr.len()).filter(|&i| valid(i)).filter_map(|i| arr.to_f64(i)), format)
+        }
+    }
+}
+
+/// The values whose position passes `valid`.
+fn valid_values<I: Iterator>(values: I, valid: impl Fn(usize) -> bool) -> impl Iterator<Item = I::Item> {
+    values.enumerate().filter(move |(k, _)| valid(*k)).map(|(_, value)| value)
+}
+
+/// Print the first `n` rows, or the last ones if `tail`, that match `filter`.
+fn cmd_head(path: &PathBuf, n: usize, columns: Option<String>, filter: Option<&str>, format: &ValueFormat, tail: bool) -> Result<()> {
     let ds = Dataset::open(path)?;
 
     let col_names: Vec<&str> = match &columns {
@@ -95,7 +770,24 @@ fn cmd_head(path: &PathBuf, n: usize, columns: Option<String>) -> Result<()> {
         None => ds.schema().column_names(),
     };
 
-    let n = n.min(ds.len());
+    // Stops scanning once n matching rows are found; the tail keeps the last n
+    let rows: Vec<usize> = match (filter, tail) {
+        (Some(text), false) => ds.matching_rows(&Expr::parse(text)?)?.take(n).collect(),
+        (Some(text), true) => {
+            let mut last = VecDeque::with_capacity(n);
+            for row in ds.matching_rows(&Expr::parse(text)?)? {
+                if last.len() == n {
+                    last.pop_front();
+                }
+                if n > 0 {
+                    last.push_back(row);
+                }
+            }
+            last.into()
+        }
+        (None, false) => (0..n.min(ds.len())).collect(),
+        (None, true) => (ds.len().saturating_sub(n)..ds.len()).collect(),
+    };
 
     // Print header
     for (i, name) in col_names.iter().enumerate() {
@@ -107,25 +799,13 @@ fn cmd_head(path: &PathBuf, n: usize, columns: Option<String>) -> Result<()> {
     println!();
 
     // Print rows
-    for row_idx in 0..n {
+    for row_idx in rows {
+        let row = ds.row(row_idx);
         for (col_idx, name) in col_names.iter().enumerate() {
             if col_idx > 0 {
                 print!("\t");
             }
-            let col = &ds[*name];
-            match col.as_typed_array() {
-                TypedArrayView::UInt8(arr) => print!("{}", arr[row_idx]),
-                TypedArrayView::Int8(arr) => print!("{}", arr[row_idx]),
-                TypedArrayView::UInt16(arr) => print!("{}", arr[row_idx]),
-                TypedArrayView::Int16(arr) => print!("{}", arr[row_idx]),
-                TypedArrayView::UInt32(arr) => print!("{}", arr[row_idx]),
-                TypedArrayView::Int32(arr) => print!("{}", arr[row_idx]),
-                TypedArrayView::UInt64(arr) => print!("{}", arr[row_idx]),
-                TypedArrayView::Int64(arr) => print!("{}", arr[row_idx]),
-                TypedArrayView::Float32(arr) => print!("{:.6}", arr[row_idx]),
-                TypedArrayView::Float64(arr) => print!("{:.6}", arr[row_idx]),
-                TypedArrayView::Bool(arr) => print!("{}", arr[row_idx] != 0),
-            }
+            print!("{}", format.format_value(&row.get(name)?));
         }
         println!();
     }
@@ -140,47 +820,61 @@ fn cmd_stats(path: &PathBuf) -> Result<()> {
     println!("Rows: {}", ds.len());
     println!();
 
-    for col_def in ds.schema().columns() {
-        let col = &ds[&col_def.name];
-        print!("{} ({}):", col_def.name, col_def.dtype);
+    for (name, stats) in ds.describe()? {
+        let col_def = ds.schema().get(name).unwrap();
+        print!("{} ({}):", name, col_def.type_name());
+        let nulls = match stats.nulls {
+            0 => String::new(),
+            n => format!(", nulls={}", n),
+        };
 
-        match col.as_typed_array() {
-            TypedArrayView::UInt32(arr) => {
-                let min = arr.iter().min().copied().unwrap_or(0);
-                let max = arr.iter().max().copied().unwrap_or(0);
-                let sum: u64 = arr.iter().map(|&x| x as u64).sum();
-                let mean = sum as f64 / arr.len() as f64;
-                println!(" min={}, max={}, mean={:.2}", min, max, mean);
+        match col_def.dtype {
+            // Columns without numeric values only get a count
+            _ if stats.min.is_nan() => println!(" count={}{}", stats.count, nulls),
+            DType::Float16 | DType::Float32 | DType::Float64 | DType::Custom(_) => println!(
+                " count={}, min={:.6}, max={:.6}, mean={:.6}, std={:.6}{}",
+                stats.count,
+                stats.min,
+                stats.max,
+                stats.mean(),
+                stats.std(),
+                nulls
+            ),
+            DType::TimestampMillis | DType::TimestampMicros if stats.count > 0 => {
+                let per_second = col_def.dtype.ticks_per_second().unwrap();
+                let format = ValueFormat::default();
+                println!(
+                    " count={}, min={}, max={}, mean={}, std={:.3}s{}",
+                    stats.count,
+                    format.format_timestamp(stats.min as i64, per_second),
+                    format.format_timestamp(stats.max as i64, per_second),
+                    format.format_timestamp(stats.mean().round() as i64, per_second),
+                    stats.std() / per_second as f64,
+                    nulls
+                )
             }
-            TypedArrayView::UInt64(arr) => {
-                let min = arr.iter().min().copied().unwrap_or(0);
-                let max = arr.iter().max().copied().unwrap_or(0);
-                let sum: u128 = arr.iter().map(|&x| x as u128).sum();
-                let mean = sum as f64 / arr.len() as f64;
-                println!(" min={}, max={}, mean={:.2}", min, max, mean);
-            }
-            TypedArrayView::Float32(arr) => {
-                let min = arr.iter().cloned().fold(f32::INFINITY, f32::min);
-                let max = arr.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
-                let sum: f64 = arr.iter().map(|&x| x as f64).sum();
-                let mean = sum / arr.len() as f64;
-                println!(" min={:.6}, max={:.6}, mean={:.6}", min, max, mean);
-            }
-            TypedArrayView::Float64(arr) => {
-                let min = arr.iter().cloned().fold(f64::INFINITY, f64::min);
-                let max = arr.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
-                let sum: f64 = arr.iter().sum();
-                let mean = sum / arr.len() as f64;
-                println!(" min={:.6}, max={:.6}, mean={:.6}", min, max, mean);
-            }
-            _ => println!(" (stats not available for this type)"),
+            _ => println!(
+                " count={}, min={}, max={}, mean={:.2}, std={:.2}{}",
+                stats.count,
+                stats.min,
+                stats.max,
+                stats.mean(),
+                stats.std(),
+                nulls
+            ),
         }
     }
 
     Ok(())
 }
 
-fn cmd_plot(path: &PathBuf, n: usize, column: Option<String>, width: usize) -> Result<()> {
+fn cmd_plot(
+    path: &PathBuf,
+    n: usize,
+    column: Option<String>,
+    width: usize,
+    bins: Option<usize>,
+) -> Result<()> {
     let ds = Dataset::open(path)?;
 
     // Find column to plot
@@ -193,37 +887,44 @@ fn cmd_plot(path: &PathBuf, n: usize, column: Option<String>, width: usize) -> R
 
     let col = ds.column(&col_name)
         .ok_or_else(|| anyhow::anyhow!("Column not found: {}", col_name))?;
+    if col.width() > 1 {
+        anyhow::bail!("Column {} ({}) holds arrays, which can't be plotted", col_name, col.dtype());
+    }
+
+    if let Some(bins) = bins {
+        return plot_histogram(&col_name, col, bins.max(1), width);
+    }
 
     let n = n.min(ds.len());
 
     // Extract values as f64 for plotting
-    let values: Vec<f64> = match col.as_typed_array() {
-        TypedArrayView::UInt8(arr) => arr.iter().take(n).map(|&x| x as f64).collect(),
-        TypedArrayView::Int8(arr) => arr.iter().take(n).map(|&x| x as f64).collect(),
-        TypedArrayView::UInt16(arr) => arr.iter().take(n).map(|&x| x as f64).collect(),
-        TypedArrayView::Int16(arr) => arr.iter().take(n).map(|&x| x as f64).collect(),
-        TypedArrayView::UInt32(arr) => arr.iter().take(n).map(|&x| x as f64).collect(),
-        TypedArrayView::Int32(arr) => arr.iter().take(n).map(|&x| x as f64).collect(),
-        TypedArrayView::UInt64(arr) => arr.iter().take(n).map(|&x| x as f64).collect(),
-        TypedArrayView::Int64(arr) => arr.iter().take(n).map(|&x| x as f64).collect(),
-        TypedArrayView::Float32(arr) => arr.iter().take(n).map(|&x| x as f64).collect(),
-        TypedArrayView::Float64(arr) => arr.iter().take(n).copied().collect(),
-        TypedArrayView::Bool(arr) => arr.iter().take(n).map(|&x| x as f64).collect(),
-    };
+    let array = col.as_typed_array();
+    let values: Vec<f64> = (0..n)
+        .map(|i| array.get_f64(i))
+        .collect::<Option<_>>()
+        .ok_or_else(|| {
+            anyhow::anyhow!("Column {} ({}) has no numeric values", col_name, col.dtype())
+        })?;
 
     if values.is_empty() {
         println!("No data to plot");
         return Ok(());
     }
 
-    // Find min/max for scaling
-    let min_val = values.iter().cloned().fold(f64::INFINITY, f64::min);
-    let max_val = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    // Min/max for scaling
+    let stats = ColumnStats::of(&values);
+    let (min_val, max_val) = (stats.min, stats.max);
     let range = max_val - min_val;
 
     // Print header
     println!("Column: {} ({})  Rows: 0..{}", col_name, col.dtype(), n);
-    println!("Range: [{:.4}, {:.4}]", min_val, max_val);
+    println!(
+        "Range: [{:.4}, {:.4}]  Mean: {:.4}  Std: {:.4}",
+        min_val,
+        max_val,
+        stats.mean(),
+        stats.std()
+    );
     println!();
 
     // Calculate label width for alignment
@@ -248,3 +949,1045 @@ fn cmd_plot(path: &PathBuf, n: usize, column: Option<String>, width: usize) -> R
 
     Ok(())
 }
+
+/// Plot the counts of a column's values in `bins` bins as ASCII bars.
+fn plot_histogram(col_name: &str, col: &Column, bins: usize, width: usize) -> Result<()> {
+    let histogram = col
+        .histogram(bins)
+        .map_err(|e| anyhow::anyhow!("Column {} ({}): {}", col_name, col.dtype(), e))?;
+    let max_count = histogram.counts.iter().copied().max().unwrap_or(0);
+
+    println!("Column: {} ({})  Values: {}", col_name, col.dtype(), histogram.total());
+    println!();
+
+    let count_width = max_count.to_string().len();
+    for (i, &count) in histogram.counts.iter().enumerate() {
+        let bar_len = match max_count {
+            0 => 0,
+            max => (count as f64 / max as f64 * width as f64).round() as usize,
+        };
+        let close = if i + 1 == bins { ']' } else { ')' };
+        println!(
+            "[{:>12.4}, {:>12.4}{} {:>count_w$} │{}",
+            histogram.edges[i],
+            histogram.edges[i + 1],
+            close,
+            count,
+            "█".repeat(bar_len),
+            count_w = count_width
+        );
+    }
+
+    Ok(())
+}
+
+/// Summary of a dataset discovered by `ls`.
+struct DatasetEntry {
+    name: String,
+    rows: usize,
+    columns: usize,
+    size_bytes: u64,
+    modified: Option<SystemTime>,
+}
+
+fn cmd_ls(root: &Path, recursive: bool, format: ListFormat) -> Result<()> {
+    if !root.is_dir() {
+        anyhow::bail!("Not a directory: {}", root.display());
+    }
+
+    let mut entries = Vec::new();
+    scan_datasets(root, root, recursive, &mut entries)?;
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    match format {
+        ListFormat::Table => {
+            let name_width = entries.iter().map(|e| e.name.len()).max().unwrap_or(0).max(4);
+            println!(
+                "{:<name_w$}  {:>14}  {:>7}  {:>10}  modified",
+                "name", "rows", "columns", "size",
+                name_w = name_width
+            );
+            for e in &entries {
+                println!(
+                    "{:<name_w$}  {:>14}  {:>7}  {:>10}  {}",
+                    e.name,
+                    e.rows,
+                    e.columns,
+                    format_size(e.size_bytes),
+                    e.modified.map(format_timestamp).unwrap_or_else(|| "-".to_string()),
+                    name_w = name_width
+                );
+            }
+        }
+        ListFormat::Json => {
+            println!("[");
+            for (i, e) in entries.iter().enumerate() {
+                let modified = match e.modified {
+                    Some(t) => format!("\"{}\"", format_timestamp(t)),
+                    None => "null".to_string(),
+                };
+                println!(
+                    "  {{\"name\": \"{}\", \"rows\": {}, \"columns\": {}, \"size_bytes\": {}, \"modified\": {}}}{}",
+                    json_escape(&e.name),
+                    e.rows,
+                    e.columns,
+                    e.size_bytes,
+                    modified,
+                    if i + 1 < entries.len() { "," } else { "" }
+                );
+            }
+            println!("]");
+        }
+    }
+
+    Ok(())
+}
+
+/// Check if `dir` holds a `schema.txt` or `schema.json`.
+fn has_schema(dir: &Path) -> bool {
+    dir.join("schema.txt").is_file() || dir.join("schema.json").is_file()
+}
+
+/// Collect datasets in `dir` (and below it, if `recursive`) into `entries`.
+///
+/// Directories containing a schema are treated as dataset candidates and
+/// are never descended into. Candidates that fail to open are reported on stderr.
+fn scan_datasets(root: &Path, dir: &Path, recursive: bool, entries: &mut Vec<DatasetEntry>) -> Result<()> {
+    if has_schema(dir) {
+        let name = match dir.strip_prefix(root) {
+            Ok(rel) if !rel.as_os_str().is_empty() => rel.display().to_string(),
+            _ => dir.display().to_string(),
+        };
+        match Dataset::open(dir) {
+            Ok(ds) => {
+                let (size_bytes, modified) = directory_usage(dir)?;
+                entries.push(DatasetEntry {
+                    name,
+                    rows: ds.len(),
+                    columns: ds.num_columns(),
+                    size_bytes,
+                    modified,
+                });
+            }
+            Err(e) => eprintln!("warning: skipping {}: {}", dir.display(), e),
+        }
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let sub = entry.path();
+        if recursive || has_schema(&sub) {
+            scan_datasets(root, &sub, recursive, entries)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Total size of the regular files in `dir` and the latest modification time among them.
+fn directory_usage(dir: &Path) -> Result<(u64, Option<SystemTime>)> {
+    let mut size = 0;
+    let mut modified: Option<SystemTime> = None;
+    for entry in fs::read_dir(dir)? {
+        let meta = entry?.metadata()?;
+        if !meta.is_file() {
+            continue;
+        }
+        size += meta.len();
+        if let Ok(t) = meta.modified() {
+            modified = Some(modified.map_or(t, |m| m.max(t)));
+        }
+    }
+    Ok((size, modified))
+}
+
+/// Format a byte count using binary units (e.g. "1.5 GiB").
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Format a timestamp as UTC `YYYY-MM-DDTHH:MM:SSZ`.
+fn format_timestamp(t: SystemTime) -> String {
+    let secs = match t.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+    let days = secs.div_euclid(86_400);
+    let rem = secs.rem_euclid(86_400);
+
+    // Civil-from-days conversion (proleptic Gregorian calendar)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Escape a string for inclusion in a JSON string literal.
+fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn cmd_export_sql(path: &Path, dialect: SqlDialect, table: Option<String>, schema_only: bool, format: &ValueFormat) -> Result<()> {
+    let ds = Dataset::open(path)?;
+
+    let table = match table {
+        Some(t) => t,
+        None => path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .ok_or_else(|| anyhow::anyhow!("Cannot derive a table name from {}", path.display()))?,
+    };
+    let quote = |ident: &str| match dialect {
+        SqlDialect::Postgres => format!("\"{}\"", ident.replace('"', "\"\"")),
+        SqlDialect::Clickhouse => format!("`{}`", ident.replace('`', "\\`")),
+    };
+
+    let mut out = BufWriter::new(io::stdout().lock());
+
+    // Columns with a validity bitmap may hold nulls; the others are declared without them
+    let nullable = ds
+        .schema()
+        .columns()
+        .map(|c| ds.nullable(&c.name))
+        .collect::<mmappet::Result<Vec<_>>>()?;
+    let column_defs: Vec<String> = ds
+        .schema()
+        .columns()
+        .zip(&nullable)
+        .map(|(c, n)| {
+            let sql_type = match dialect {
+                SqlDialect::Postgres => {
+                    let not_null = if n.has_validity() { "" } else { " NOT NULL" };
+                    format!("{}{}{}", sql_type(c.dtype, dialect), "[]".repeat(c.shape.len()), not_null)
+                }
+                // ClickHouse arrays can't be Nullable, so only scalars are wrapped
+                SqlDialect::Clickhouse if n.has_validity() && c.shape.is_empty() => match c.dtype {
+                    DType::Categorical => "LowCardinality(Nullable(String))".to_string(),
+                    dtype => format!("Nullable({})", sql_type(dtype, dialect)),
+                },
+                SqlDialect::Clickhouse => c.shape.iter().fold(sql_type(c.dtype, dialect).to_string(), |t, _| format!("Array({})", t)),
+            };
+            format!("{} {}", quote(&c.name), sql_type)
+        })
+        .collect();
+    write!(out, "CREATE TABLE {} (\n    {}\n)", quote(&table), column_defs.join(",\n    "))?;
+    match dialect {
+        SqlDialect::Postgres => writeln!(out, ";")?,
+        SqlDialect::Clickhouse => writeln!(out, " ENGINE = MergeTree ORDER BY tuple();")?,
+    }
+
+    if schema_only {
+        return Ok(());
+    }
+
+    let column_list: Vec<String> = ds.schema().columns().map(|c| quote(&c.name)).collect();
+    match dialect {
+        SqlDialect::Postgres => writeln!(out, "COPY {} ({}) FROM stdin;", quote(&table), column_list.join(", "))?,
+        SqlDialect::Clickhouse => writeln!(out, "INSERT INTO {} ({}) FORMAT TabSeparated", quote(&table), column_list.join(", "))?,
+    }
+
+    let arrays: Vec<(TypedArrayView, &[usize])> = ds
+        .schema()
+        .columns()
+        .map(|c| (ds[&c.name].as_typed_array(), c.shape.as_slice()))
+        .collect();
+    // Array values are written as Postgres `{..}` or ClickHouse `[..]` literals
+    let brackets = match dialect {
+        SqlDialect::Postgres => ("{", "}"),
+        SqlDialect::Clickhouse => ("[", "]"),
+    };
+    for row in 0..ds.len() {
+        for (i, ((arr, shape), n)) in arrays.iter().zip(&nullable).enumerate() {
+            if i > 0 {
+                out.write_all(b"\t")?;
+            }
+            // `\N` is the null token of both COPY text and TabSeparated; a null
+            // row of a ClickHouse array column is loaded as an empty array
+            if !n.is_valid(row) {
+                match (dialect, shape.is_empty()) {
+                    (SqlDialect::Clickhouse, false) => out.write_all(b"[]")?,
+                    _ => out.write_all(b"\\N")?,
+                }
+                continue;
+            }
+            if shape.is_empty() {
+                write_sql_value(&mut out, arr, row, dialect, format)?;
+                continue;
+            }
+            let width: usize = shape.iter().product();
+            let values = (row * width..(row + 1) * width)
+                .map(|k| {
+                    let mut buf = Vec::new();
+                    write_sql_value(&mut buf, arr, k, dialect, format)?;
+                    Ok(String::from_utf8_lossy(&buf).into_owned())
+                })
+                .collect::<io::Result<Vec<String>>>()?;
+            out.write_all(nest(&values, shape, brackets, ",").as_bytes())?;
+        }
+        out.write_all(b"\n")?;
+    }
+
+    if dialect == SqlDialect::Postgres {
+        writeln!(out, "\\.")?;
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
+/// Write rows `start..end` of some or all columns to stdout as CSV.
+fn cmd_export_csv(path: &Path, columns: Option<String>, start: usize, end: Option<usize>, options: &CsvOptions) -> Result<()> {
+    let ds = Dataset::open(path)?;
+    let col_names: Vec<&str> = match &columns {
+        Some(cols) => cols.split(',').map(|s| s.trim()).collect(),
+        None => Vec::new(),
+    };
+    let end = end.unwrap_or(ds.len());
+    if start > end || end > ds.len() {
+        anyhow::bail!("Row range {}..{} out of bounds for {} rows", start, end, ds.len());
+    }
+    ds.export_csv(io::stdout().lock(), &col_names, start..end, options)?;
+    Ok(())
+}
+
+/// Column type used for a dtype in the target database.
+fn sql_type(dtype: DType, dialect: SqlDialect) -> &'static str {
+    match dialect {
+        // Postgres has no unsigned types, so unsigned values get the next wider type.
+        SqlDialect::Postgres => match dtype {
+            DType::UInt8 | DType::Int8 | DType::Int16 => "smallint",
+            DType::UInt16 | DType::Int32 => "integer",
+            DType::UInt32 | DType::Int64 => "bigint",
+            DType::TimestampMillis | DType::TimestampMicros => "timestamp",
+            DType::UInt64 => "numeric(20, 0)",
+            DType::Float16 | DType::Float32 => "real",
+            DType::Float64 => "double precision",
+            DType::Bool => "boolean",
+            DType::Utf8 | DType::Categorical | DType::Custom(_) => "text",
+        },
+        SqlDialect::Clickhouse => match dtype {
+            DType::UInt8 => "UInt8",
+            DType::Int8 => "Int8",
+            DType::UInt16 => "UInt16",
+            DType::Int16 => "Int16",
+            DType::UInt32 => "UInt32",
+            DType::Int32 => "Int32",
+            DType::UInt64 => "UInt64",
+            DType::Int64 => "Int64",
+            DType::TimestampMillis => "DateTime64(3, 'UTC')",
+            DType::TimestampMicros => "DateTime64(6, 'UTC')",
+            DType::Float16 | DType::Float32 => "Float32",
+            DType::Float64 => "Float64",
+            DType::Bool => "Bool",
+            DType::Utf8 | DType::Custom(_) => "String",
+            DType::Categorical => "LowCardinality(String)",
+        },
+    }
+}
+
+/// Write one value in the text format accepted by `COPY` / `TabSeparated`,
+/// rendered by `format`.
+fn write_sql_value(out: &mut impl Write, arr: &TypedArrayView, row: usize, dialect: SqlDialect, format: &ValueFormat) -> io::Result<()> {
+    let float = |x: f64| match dialect {
+        SqlDialect::Postgres if x.is_nan() => Some(&b"NaN"[..]),
+        SqlDialect::Postgres if x == f64::INFINITY => Some(&b"Infinity"[..]),
+        SqlDialect::Postgres if x == f64::NEG_INFINITY => Some(&b"-Infinity"[..]),
+        _ => None,
+    };
+
+    let special = match arr {
+        TypedArrayView::Float16(a) => float(a[row].to_f64()),
+        TypedArrayView::Float32(a) => float(a[row] as f64),
+        TypedArrayView::Float64(a) => float(a[row]),
+        _ => None,
+    };
+    if let Some(text) = special {
+        return out.write_all(text);
+    }
+
+    // Backslashes, tabs and line breaks are escaped for both formats
+    let text = format.format_at(arr, row);
+    for c in text.chars() {
+        match c {
+            '\\' => out.write_all(b"\\\\")?,
+            '\t' => out.write_all(b"\\t")?,
+            '\n' => out.write_all(b"\\n")?,
+            '\r' => out.write_all(b"\\r")?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    Ok(())
+}
+
+/// Nest the values of one row into brackets following its `shape`, e.g.
+/// `[[1, 2], [3, 4]]` for shape `[2, 2]`; a scalar's only value is returned
+/// as it is.
+fn nest(values: &[String], shape: &[usize], (open, close): (&str, &str), separator: &str) -> String {
+    match shape.split_first() {
+        None => values[0].clone(),
+        Some((&len, inner)) => {
+            let parts: Vec<String> = values
+                .chunks(values.len() / len)
+                .map(|part| nest(part, inner, (open, close), separator))
+                .collect();
+            format!("{}{}{}", open, parts.join(separator), close)
+        }
+    }
+}
+
+/// Name and version recorded as the tool in provenance logs.
+fn tool_name() -> String {
+    format!("mmappet-cli {}", env!("CARGO_PKG_VERSION"))
+}
+
+fn cmd_meta(path: &Path, action: MetaAction) -> Result<()> {
+    let ds = Dataset::open(path)?;
+
+    match action {
+        MetaAction::Get { keys } => {
+            let metadata = ds.metadata()?;
+            if keys.is_empty() {
+                for (key, value) in &metadata {
+                    println!("{}={}", key, value);
+                }
+            } else {
+                for key in &keys {
+                    let value = metadata
+                        .get(key)
+                        .ok_or_else(|| anyhow::anyhow!("No metadata key '{}'", key))?;
+                    println!("{}={}", key, value);
+                }
+            }
+        }
+        MetaAction::Set { pairs } => {
+            let changes = pairs
+                .iter()
+                .map(|pair| {
+                    pair.split_once('=')
+                        .ok_or_else(|| anyhow::anyhow!("Expected KEY=VALUE, got '{}'", pair))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            ds.set_metadata(&changes, &tool_name())?;
+        }
+        MetaAction::History => {
+            for entry in ds.provenance()? {
+                let changes: Vec<String> = entry.changes.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+                let time = format_timestamp(UNIX_EPOCH + std::time::Duration::from_secs(entry.timestamp));
+                println!("{}  {}  {}", time, entry.tool, changes.join(" "));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn cmd_index(action: IndexAction) -> Result<()> {
+    match action {
+        IndexAction::Build { path, column } => {
+            let ds = Dataset::open(&path)?;
+            ds.build_index(&column)?;
+            println!("Indexed {} rows of '{}' in {}", ds.len(), column, path.display());
+        }
+        IndexAction::Zones { path, column } => {
+            let ds = Dataset::open(&path)?;
+            ds.build_zone_map(&column)?;
+            println!("Mapped {} rows of '{}' in {}", ds.len(), column, path.display());
+        }
+        IndexAction::Bloom { path, column } => {
+            let ds = Dataset::open(&path)?;
+            ds.build_bloom_filter(&column)?;
+            println!("Built Bloom filter over {} rows of '{}' in {}", ds.len(), column, path.display());
+        }
+    }
+
+    Ok(())
+}
+
+fn cmd_validate(path: &PathBuf, record_checksums: bool) -> Result<()> {
+    if record_checksums {
+        Dataset::open(path)?.record_checksums(&tool_name())?;
+    }
+    let report = Dataset::validate(path)?;
+
+    println!("Dataset: {}", path.display());
+    let name_width = report.columns.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    for col in &report.columns {
+        let mut parts = vec![match col.rows {
+            Some(rows) => format!("{} rows", rows),
+            None => "unreadable".to_string(),
+        }];
+        if col.nan_count > 0 || col.inf_count > 0 {
+            parts.push(format!("nan={}, inf={}", col.nan_count, col.inf_count));
+        }
+        match col.sorted {
+            Some(true) => parts.push("sorted".to_string()),
+            Some(false) => parts.push("NOT sorted".to_string()),
+            None => {}
+        }
+        match col.checksum_ok {
+            Some(true) => parts.push("checksum ok".to_string()),
+            Some(false) => parts.push("checksum MISMATCH".to_string()),
+            None => {}
+        }
+        println!("  {:<name_w$}  {}", col.name, parts.join(", "), name_w = name_width);
+    }
+
+    if report.is_ok() {
+        println!("OK");
+        return Ok(());
+    }
+    println!();
+    println!("Issues:");
+    for issue in &report.issues {
+        println!("  {}", issue);
+    }
+    anyhow::bail!("{} issue(s) found", report.issues.len())
+}
+
+fn cmd_diff(left: &PathBuf, right: &PathBuf, tolerance: f64, rel_tolerance: f64) -> Result<()> {
+    let options = DiffOptions {
+        abs_tolerance: tolerance,
+        rel_tolerance,
+        ..Default::default()
+    };
+    let report = mmappet::diff(&Dataset::open(left)?, &Dataset::open(right)?, &options)?;
+
+    println!("Left:  {} ({} rows)", left.display(), report.left_rows);
+    println!("Right: {} ({} rows)", right.display(), report.right_rows);
+    for difference in &report.schema {
+        println!("  {}", difference);
+    }
+    let name_width = report.columns.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    for col in &report.columns {
+        let status = match col.first_mismatch {
+            Some(row) => format!("{} rows differ, first at row {}", col.mismatches, row),
+            None => "identical".to_string(),
+        };
+        println!("  {:<name_w$}  {}", col.name, status, name_w = name_width);
+    }
+
+    if report.is_identical() {
+        println!("IDENTICAL");
+        return Ok(());
+    }
+    anyhow::bail!("datasets differ")
+}
+
+fn cmd_warm(path: &PathBuf, columns: Option<String>, start: usize, end: Option<usize>, wait: bool) -> Result<()> {
+    let ds = Dataset::open(path)?;
+    let col_names: Vec<&str> = match &columns {
+        Some(cols) => cols.split(',').map(|s| s.trim()).collect(),
+        None => ds.schema().column_names(),
+    };
+    let end = end.unwrap_or(ds.len());
+    if start > end || end > ds.len() {
+        anyhow::bail!("Row range {}..{} out of bounds for {} rows", start, end, ds.len());
+    }
+
+    let bytes = ds.prefetch(&col_names, start..end)?;
+    if wait {
+        // Reading one byte per page faults it in
+        for name in &col_names {
+            let col = &ds[*name];
+            let size = col.dtype().size_bytes() * col.width();
+            let data = &col.as_bytes()[start * size..end * size];
+            let sum = data.iter().step_by(4096).fold(0u8, |acc, &b| acc.wrapping_add(b));
+            std::hint::black_box(sum);
+        }
+    }
+
+    println!(
+        "{} {:.1} MB of {} columns in {}",
+        if wait { "Warmed" } else { "Requested" },
+        bytes as f64 / 1e6,
+        col_names.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// A dataset served by `serve`.
+struct Served {
+    name: String,
+    dataset: Dataset,
+}
+
+/// Rows returned by `/rows` without a `limit`.
+const DEFAULT_ROW_LIMIT: usize = 100;
+
+/// Most rows `/rows` returns for one request.
+const MAX_ROW_LIMIT: usize = 1 << 20;
+
+/// Rows exported at a time while a `/rows` response is streamed.
+const STREAM_CHUNK_ROWS: usize = 1024;
+
+/// Longest a request may take to arrive or its response to be sent.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Most bytes of a request's line and headers.
+const MAX_HEAD_BYTES: u64 = 16 << 10;
+
+/// Writes a response body to the socket as it's produced.
+type WriteBody<'a> = Box<dyn FnOnce(&mut dyn Write) -> mmappet::Result<()> + 'a>;
+
+/// Body of an HTTP response.
+enum Body<'a> {
+    Bytes(Vec<u8>),
+    Stream(WriteBody<'a>),
+}
+
+/// Status, content type and body of an HTTP response, or status and message of an error.
+type Response<'a> = std::result::Result<(u16, &'static str, Body<'a>), (u16, String)>;
+
+fn cmd_serve(paths: &[PathBuf], addr: &str, threads: usize) -> Result<()> {
+    let mut served: Vec<Served> = Vec::new();
+    for path in paths {
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .ok_or_else(|| anyhow::anyhow!("Cannot derive a dataset name from {}", path.display()))?;
+        if served.iter().any(|s| s.name == name) {
+            anyhow::bail!("Two datasets are named '{}'", name);
+        }
+        served.push(Served { name, dataset: Dataset::open(path)? });
+    }
+
+    let listener = TcpListener::bind(addr)?;
+    println!("Serving {} dataset(s) on http://{}/", served.len(), listener.local_addr()?);
+    // Accepted connections wait for one of `threads` workers; when as many
+    // are queued, accepting waits too
+    let threads = threads.max(1);
+    let (sender, receiver) = mpsc::sync_channel::<TcpStream>(threads);
+    let receiver = Mutex::new(receiver);
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let Ok(stream) = receiver.lock().unwrap().recv() else {
+                    break;
+                };
+                if let Err(e) = handle_request(stream, &served) {
+                    eprintln!("Request failed: {}", e);
+                }
+            });
+        }
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => sender.send(stream).expect("workers run until the sender is dropped"),
+                Err(e) => eprintln!("Connection failed: {}", e),
+            }
+        }
+        drop(sender);
+    });
+    Ok(())
+}
+
+/// Read the request line of a request on `stream`, skipping its headers.
+///
+/// Returns `None` if the request line and headers are longer than
+/// `MAX_HEAD_BYTES`.
+fn read_request_line(stream: &TcpStream) -> io::Result<Option<String>> {
+    let mut reader = BufReader::new(stream).take(MAX_HEAD_BYTES);
+    let mut request = String::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            return Ok(Some(request));
+        }
+        if !line.ends_with('\n') && reader.limit() == 0 {
+            return Ok(None);
+        }
+        if request.is_empty() {
+            request = line.clone();
+        }
+    }
+}
+
+/// Answer one request on `stream`, then close it.
+fn handle_request(stream: TcpStream, served: &[Served]) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let response = match read_request_line(&stream)? {
+        None => Err((431, format!("request line and headers exceed {} bytes", MAX_HEAD_BYTES))),
+        Some(request) => {
+            let mut parts = request.split_whitespace();
+            match (parts.next(), parts.next()) {
+                (Some("GET"), Some(target)) => route(served, target),
+                _ => Err((405, "only GET requests are supported".to_string())),
+            }
+        }
+    };
+    let (status, content_type, body) = response.unwrap_or_else(|(status, message)| {
+        let body = serde_json::json!({ "error": message }).to_string().into_bytes();
+        (status, "application/json", Body::Bytes(body))
+    });
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        431 => "Request Header Fields Too Large",
+        _ => "Internal Server Error",
+    };
+
+    let mut out = BufWriter::new(&stream);
+    write!(out, "HTTP/1.1 {} {}\r\nContent-Type: {}\r\n", status, reason, content_type)?;
+    // Streamed bodies end when the connection is closed
+    if let Body::Bytes(body) = &body {
+        write!(out, "Content-Length: {}\r\n", body.len())?;
+    }
+    write!(out, "Access-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n")?;
+    match body {
+        Body::Bytes(body) => out.write_all(&body)?,
+        Body::Stream(write_body) => write_body(&mut out).map_err(io::Error::other)?,
+    }
+    out.flush()
+}
+
+/// Dispatch a request for `target`, a path with an optional query string.
+fn route<'a>(served: &'a [Served], target: &str) -> Response<'a> {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let segments: Vec<String> = path.split('/').filter(|s| !s.is_empty()).map(percent_decode).collect();
+    let params: HashMap<String, String> = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (query_decode(key), query_decode(value)))
+        .collect();
+
+    let Some((name, rest)) = segments.split_first() else {
+        let list: Vec<_> = served
+            .iter()
+            .map(|s| serde_json::json!({ "name": s.name, "rows": s.dataset.len(), "columns": s.dataset.num_columns() }))
+            .collect();
+        return json_response(&serde_json::Value::Array(list));
+    };
+    let served = served.iter().find(|s| &s.name == name).ok_or_else(|| (404, format!("no dataset '{}'", name)))?;
+    let ds = &served.dataset;
+    match rest.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["schema"] => {
+            let columns: Vec<_> = ds
+                .schema()
+                .columns()
+                .map(|def| serde_json::json!({ "name": def.name, "type": def.type_name() }))
+                .collect();
+            json_response(&serde_json::json!({ "name": served.name, "rows": ds.len(), "columns": columns }))
+        }
+        ["rows"] => {
+            let number = |key: &str, default: usize| match params.get(key) {
+                Some(value) => value.parse().map_err(|_| (400, format!("invalid {} '{}'", key, value))),
+                None => Ok(default),
+            };
+            let offset = number("offset", 0)?;
+            let limit = number("limit", DEFAULT_ROW_LIMIT)?;
+            if limit > MAX_ROW_LIMIT {
+                return Err((400, format!("limit {} is above the maximum of {}", limit, MAX_ROW_LIMIT)));
+            }
+            if offset > ds.len() {
+                return Err((400, format!("offset {} is past the {} rows", offset, ds.len())));
+            }
+            let rows = offset..ds.len().min(offset.saturating_add(limit));
+            let columns: Vec<String> = match params.get("columns") {
+                Some(columns) => columns.split(',').map(|c| c.trim().to_string()).collect(),
+                None => Vec::new(),
+            };
+            if let Some(missing) = columns.iter().find(|c| ds.schema().get(c).is_none()) {
+                return Err((404, format!("no column '{}'", missing)));
+            }
+            match params.get("format").map(String::as_str) {
+                None | Some("json") => {
+                    let write_body = move |out: &mut dyn Write| {
+                        let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+                        write_json_rows(out, ds, &columns, rows)
+                    };
+                    Ok((200, "application/json", Body::Stream(Box::new(write_body))))
+                }
+                #[cfg(feature = "arrow")]
+                Some("arrow") => {
+                    let write_body = move |out: &mut dyn Write| {
+                        let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+                        ds.export_arrow_ipc(out, &columns, rows)
+                    };
+                    Ok((200, "application/vnd.apache.arrow.file", Body::Stream(Box::new(write_body))))
+                }
+                Some(format) => Err((400, format!("unsupported format '{}'", format))),
+            }
+        }
+        ["stats", column] => {
+            let nullable = ds.nullable(column).map_err(server_error)?;
+            let stats = nullable
+                .stats()
+                .ok_or_else(|| (400, format!("no statistics for column '{}' of type {}", column, nullable.column().dtype())))?;
+            json_response(&serde_json::json!({
+                "column": column,
+                "count": stats.count,
+                "nulls": stats.nulls,
+                "min": stats.min,
+                "max": stats.max,
+                "mean": stats.mean(),
+                "std": stats.std(),
+            }))
+        }
+        _ => Err((404, format!("unknown path '{}'", path))),
+    }
+}
+
+fn json_response(value: &serde_json::Value) -> Response<'static> {
+    Ok((200, "application/json", Body::Bytes(value.to_string().into_bytes())))
+}
+
+/// Write `rows` of `columns` as a JSON array of objects, exporting
+/// `STREAM_CHUNK_ROWS` rows at a time.
+fn write_json_rows(out: &mut dyn Write, ds: &Dataset, columns: &[&str], rows: std::ops::Range<usize>) -> mmappet::Result<()> {
+    let mut lines = Vec::new();
+    let mut separator = "";
+    out.write_all(b"[")?;
+    for start in rows.clone().step_by(STREAM_CHUNK_ROWS) {
+        lines.clear();
+        ds.export_jsonl(&mut lines, columns, start..rows.end.min(start + STREAM_CHUNK_ROWS))?;
+        // Strings are escaped, so each object is one line
+        for line in lines.split(|&b| b == b'\n').filter(|line| !line.is_empty()) {
+            out.write_all(separator.as_bytes())?;
+            out.write_all(line)?;
+            separator = ",";
+        }
+    }
+    out.write_all(b"]")?;
+    Ok(())
+}
+
+/// Status and message for a library error; missing columns are 404s.
+fn server_error(e: MmappetError) -> (u16, String) {
+    match e {
+        MmappetError::ColumnNotFound { .. } => (404, e.to_string()),
+        _ => (500, e.to_string()),
+    }
+}
+
+/// Decode a query string key or value, where `+` stands for a space.
+fn query_decode(s: &str) -> String {
+    percent_decode(&s.replace('+', " "))
+}
+
+/// Decode `%XX` escapes of a URL component.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Record the input of an import in the new dataset's provenance log.
+fn record_source(input: &Path, output: &Path) -> Result<()> {
+    let source = input.to_string_lossy();
+    Dataset::open(output)?.set_metadata(&[("source", &source), ("converted_by", &tool_name())], &tool_name())?;
+    Ok(())
+}
+
+fn cmd_dedupe_storage(root: &Path, dry_run: bool) -> Result<()> {
+    if !root.is_dir() {
+        anyhow::bail!("Not a directory: {}", root.display());
+    }
+
+    let mut files = Vec::new();
+    collect_column_files(root, &mut files)?;
+    files.sort();
+
+    // Only files of equal size can be identical; hash those, then compare bytes.
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    for path in files {
+        let size = fs::metadata(&path)?.len();
+        if size > 0 {
+            by_size.entry(size).or_default().push(path);
+        }
+    }
+
+    let mut linked = 0;
+    let mut saved: u64 = 0;
+    let mut sizes: Vec<u64> = by_size.keys().copied().collect();
+    sizes.sort_unstable();
+
+    for size in sizes {
+        let paths = &by_size[&size];
+        if paths.len() < 2 {
+            continue;
+        }
+
+        let mut by_hash: HashMap<u64, Vec<&PathBuf>> = HashMap::new();
+        for path in paths {
+            by_hash.entry(hash_file(path)?).or_default().push(path);
+        }
+
+        let mut groups: Vec<Vec<&PathBuf>> = by_hash.into_values().collect();
+        groups.sort();
+        for candidates in &groups {
+            // Each file is linked to the first earlier file it is identical to.
+            let mut originals: Vec<&PathBuf> = Vec::new();
+            'candidate: for &path in candidates {
+                let mut original = None;
+                for &o in &originals {
+                    if is_same_file(o, path)? {
+                        // Already shares storage with a kept file
+                        continue 'candidate;
+                    }
+                    if files_equal(o, path)? {
+                        original = Some(o);
+                        break;
+                    }
+                }
+                let Some(original) = original else {
+                    originals.push(path);
+                    continue;
+                };
+
+                println!("{} -> {}", path.display(), original.display());
+                if !dry_run {
+                    replace_with_hardlink(original, path)?;
+                }
+                linked += 1;
+                saved += size;
+            }
+        }
+    }
+
+    let verb = if dry_run { "Would link" } else { "Linked" };
+    println!("{} {} duplicate files, saving {}", verb, linked, format_size(saved));
+
+    Ok(())
+}
+
+/// Collect `N.bin` column files of all datasets in and below `dir`.
+fn collect_column_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let is_dataset = has_schema(dir);
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+        if file_type.is_dir() {
+            collect_column_files(&path, files)?;
+        } else if is_dataset && file_type.is_file() && is_column_file_name(&entry.file_name().to_string_lossy()) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn is_column_file_name(name: &str) -> bool {
+    name.strip_suffix(".bin")
+        .is_some_and(|stem| !stem.is_empty() && stem.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Hash file contents; collisions are resolved by comparing bytes.
+fn hash_file(path: &Path) -> Result<u64> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.write(&buf[..n]);
+    }
+    Ok(hasher.finish())
+}
+
+fn files_equal(a: &Path, b: &Path) -> Result<bool> {
+    let mut fa = io::BufReader::with_capacity(1 << 20, fs::File::open(a)?);
+    let mut fb = io::BufReader::with_capacity(1 << 20, fs::File::open(b)?);
+    let mut buf_a = vec![0u8; 1 << 16];
+    let mut buf_b = vec![0u8; 1 << 16];
+    loop {
+        let n = fa.read(&mut buf_a)?;
+        if n == 0 {
+            return Ok(fb.read(&mut buf_b[..1])? == 0);
+        }
+        fb.read_exact(&mut buf_b[..n])?;
+        if buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+    }
+}
+
+/// Whether two paths already refer to the same file (e.g. an existing hardlink).
+#[cfg(unix)]
+fn is_same_file(a: &Path, b: &Path) -> Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+    let (ma, mb) = (fs::metadata(a)?, fs::metadata(b)?);
+    Ok(ma.dev() == mb.dev() && ma.ino() == mb.ino())
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_a: &Path, _b: &Path) -> Result<bool> {
+    Ok(false)
+}
+
+/// Atomically replace `duplicate` with a hardlink to `original`.
+fn replace_with_hardlink(original: &Path, duplicate: &Path) -> Result<()> {
+    let mut tmp = duplicate.as_os_str().to_owned();
+    tmp.push(".dedupe-tmp");
+    let tmp = PathBuf::from(tmp);
+    fs::hard_link(original, &tmp)?;
+    if let Err(e) = fs::rename(&tmp, duplicate) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+    Ok(())
+}
@@ -0,0 +1,243 @@
+//! Bloom filters for ruling out equality matches without a scan.
+//!
+//! A `<column>.bloom` file answers "might this value occur in the column?"
+//! with no false negatives and about 1% false positives, so lookups of absent
+//! values return at once. Layout, all words native-endian `u64`: magic, row
+//! count, hash count, bit-array word count, then the bit array.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use bytemuck::{bytes_of, cast_slice};
+
+use crate::dataset::Dataset;
+use crate::dtype::{DType, MmappetType};
+use crate::error::{MmappetError, Result};
+use crate::group::key_bits;
+use crate::storage::Storage;
+
+/// Magic bytes at the start of a `<column>.bloom` file.
+const BLOOM_MAGIC: &[u8; 8] = b"MMPBLOM1";
+
+/// Size of the header: magic, row count, hash count, word count.
+const HEADER_LEN: usize = 32;
+
+/// Filter bits per row; with [`HASHES`] probes this gives ~1% false positives.
+const BITS_PER_ROW: usize = 10;
+
+/// Bits probed per value.
+const HASHES: u64 = 7;
+
+impl Dataset {
+    /// Build a Bloom filter of a column and persist it as `<name>.bloom`.
+    ///
+    /// Later calls to [`Dataset::lookup`] and [`Dataset::may_contain`], and
+    /// equality tests in expressions, use it to skip values that don't occur.
+    /// Only integer columns are supported. [`DatasetWriter::bloom_filter`]
+    /// builds one while writing.
+    ///
+    /// [`DatasetWriter::bloom_filter`]: crate::DatasetWriter::bloom_filter
+    pub fn build_bloom_filter(&self, name: &str) -> Result<()> {
+        let column = self
+            .column(name)
+            .ok_or_else(|| self.column_not_found(name))?;
+        column.check_scalar(name)?;
+        check_dtype(name, column.dtype())?;
+
+        let words = (self.len() * BITS_PER_ROW).div_ceil(64).max(1);
+        let mut bits = vec![0u64; words];
+        for key in column.as_bytes().chunks_exact(column.dtype().size_bytes()) {
+            for bit in probes(key_bits(key), words * 64) {
+                bits[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+
+        let mut buf = Vec::with_capacity(HEADER_LEN + words * 8);
+        buf.extend_from_slice(BLOOM_MAGIC);
+        for word in [self.len() as u64, HASHES, words as u64] {
+            buf.extend_from_slice(&word.to_ne_bytes());
+        }
+        buf.extend_from_slice(cast_slice(&bits));
+
+        let path = self.path().join(format!("{}.bloom", name));
+        fs::write(&path, buf).map_err(|e| MmappetError::io(&path, e))?;
+        self.cache_bloom_filter(name, BloomFilter::open(&path)?.map(Arc::new));
+        Ok(())
+    }
+
+    /// Check whether `value` might occur in column `name`.
+    ///
+    /// `false` means it certainly doesn't; `true` means it may, and is also
+    /// returned when the column has no up-to-date Bloom filter. Returns an
+    /// error if the column doesn't exist or the type doesn't match.
+    pub fn may_contain<T: MmappetType>(&self, name: &str, value: T) -> Result<bool> {
+        self.get::<T>(name)?;
+        Ok(self
+            .bloom_filter(name)?
+            .is_none_or(|bloom| bloom.contains(bytes_of(&value))))
+    }
+
+    /// The Bloom filter of a column, if one is persisted and up to date.
+    pub(crate) fn bloom_filter(&self, name: &str) -> Result<Option<Arc<BloomFilter>>> {
+        if let Some(bloom) = self.cached_bloom_filter(name) {
+            return Ok(bloom);
+        }
+        let path = self.path().join(format!("{}.bloom", name));
+        let bloom = BloomFilter::open(&path)?
+            .filter(|bloom| bloom.rows == self.len())
+            .map(Arc::new);
+        self.cache_bloom_filter(name, bloom.clone());
+        Ok(bloom)
+    }
+}
+
+/// Reject dtypes Bloom filters aren't built for.
+pub(crate) fn check_dtype(name: &str, dtype: DType) -> Result<()> {
+    match dtype {
+        DType::UInt8
+        | DType::Int8
+        | DType::UInt16
+        | DType::Int16
+        | DType::UInt32
+        | DType::Int32
+        | DType::UInt64
+        | DType::Int64
+        | DType::TimestampMillis
+        | DType::TimestampMicros => Ok(()),
+        _ => Err(MmappetError::InvalidDType {
+            dtype: dtype.to_string(),
+            message: format!("Bloom filters need an integer column, '{}' isn't", name),
+        }),
+    }
+}
+
+/// Bit positions probed for a key among `bits` bits, by double hashing.
+fn probes(key: u64, bits: usize) -> impl Iterator<Item = usize> {
+    let h1 = mix(key);
+    let h2 = mix(key ^ 0x9e37_79b9_7f4a_7c15) | 1;
+    (0..HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bits as u64) as usize)
+}
+
+/// Finalizer of MurmurHash3, spreading every input bit over the output.
+fn mix(mut x: u64) -> u64 {
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    x ^ (x >> 33)
+}
+
+/// A memory-mapped `<column>.bloom` file.
+pub(crate) struct BloomFilter {
+    /// Row count of the dataset the filter was built for.
+    rows: usize,
+    mmap: Storage,
+}
+
+impl BloomFilter {
+    /// Map a filter written by [`Dataset::build_bloom_filter`]; `Ok(None)` if absent.
+    fn open(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let mmap = Storage::open(path, false)?;
+        let invalid = || MmappetError::InvalidIndex {
+            path: path.to_path_buf(),
+            offset: None,
+        };
+
+        if mmap.get(..8) != Some(&BLOOM_MAGIC[..]) || mmap.len() < HEADER_LEN {
+            return Err(invalid());
+        }
+        let word = |i: usize| u64::from_ne_bytes(mmap[8 + i * 8..16 + i * 8].try_into().unwrap());
+        let (rows, hashes, words) = (word(0) as usize, word(1), word(2) as usize);
+        if hashes != HASHES
+            || words == 0
+            || Some(mmap.len()) != words.checked_mul(8).map(|len| HEADER_LEN + len)
+        {
+            return Err(invalid());
+        }
+
+        Ok(Some(BloomFilter { rows, mmap }))
+    }
+
+    fn bits(&self) -> &[u64] {
+        cast_slice(&self.mmap[HEADER_LEN..])
+    }
+
+    /// Whether the value with raw bytes `key` may be in the column.
+    pub(crate) fn contains(&self, key: &[u8]) -> bool {
+        let bits = self.bits();
+        probes(key_bits(key), bits.len() * 64).all(|bit| bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Whether a column of `dtype` may hold a value equal to `x`.
+    ///
+    /// Values that no element of `dtype` equals exactly, and integers beyond
+    /// the exactly representable range of `f64`, always may be.
+    pub(crate) fn contains_f64(&self, dtype: DType, x: f64) -> bool {
+        const EXACT: f64 = (1u64 << 53) as f64;
+        if x.fract() != 0.0 || x.abs() > EXACT {
+            return true;
+        }
+        macro_rules! probe {
+            ($t:ty) => {
+                if x < <$t>::MIN as f64 || x > <$t>::MAX as f64 {
+                    true
+                } else {
+                    self.contains(&(x as $t).to_ne_bytes())
+                }
+            };
+        }
+        match dtype {
+            DType::UInt8 => probe!(u8),
+            DType::Int8 => probe!(i8),
+            DType::UInt16 => probe!(u16),
+            DType::Int16 => probe!(i16),
+            DType::UInt32 => probe!(u32),
+            DType::Int32 => probe!(i32),
+            DType::UInt64 => probe!(u64),
+            DType::Int64 | DType::TimestampMillis | DType::TimestampMicros => probe!(i64),
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::col;
+    use crate::test_util::write_dataset;
+
+    #[test]
+    fn test_bloom_filter() {
+        let ids: Vec<u32> = (0..10_000).map(|i| i * 2).collect();
+        let dir = write_dataset("bloom", &[("id", &ids)]);
+        let ds = Dataset::open(&dir).unwrap();
+        assert!(ds.may_contain("id", 1u32).unwrap());
+
+        ds.build_bloom_filter("id").unwrap();
+        let ds = Dataset::open(&dir).unwrap();
+        assert!(ids.iter().all(|&id| ds.may_contain("id", id).unwrap()));
+        let false_positives = (0..10_000u32)
+            .filter(|i| ds.may_contain("id", i * 2 + 1).unwrap())
+            .count();
+        assert!(false_positives < 300, "{} false positives", false_positives);
+        assert!(ds.may_contain("id", 1i32).is_err());
+
+        assert_eq!(ds.lookup("id", 4242u32).unwrap().rows(), &[2121]);
+        let missing = (0..10_000u32)
+            .map(|i| i * 2 + 1)
+            .find(|&id| !ds.may_contain("id", id).unwrap())
+            .unwrap();
+        assert!(ds.lookup("id", missing).unwrap().is_empty());
+        let expr = col("id").eq(missing as f64).or(col("id").eq(4242.0));
+        assert_eq!(
+            ds.matching_rows(&expr).unwrap().collect::<Vec<_>>(),
+            vec![2121]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,283 @@
+//! Bruker timsTOF `.d` import (requires the `bruker` feature).
+
+use std::path::Path;
+
+use rusqlite::Connection;
+
+use crate::dtype::DType;
+use crate::error::{MmappetError, Result};
+use crate::pipeline;
+use crate::schema::Schema;
+use crate::storage::Storage;
+use crate::writer::{create_atomically, DatasetWriter};
+
+/// A row of the `Frames` table in `analysis.tdf`.
+struct FrameEntry {
+    id: u32,
+    tims_id: usize,
+    num_peaks: usize,
+}
+
+/// Decoded peaks of a single frame.
+#[derive(Debug, Default, PartialEq)]
+struct FramePeaks {
+    scan: Vec<u32>,
+    tof: Vec<u32>,
+    intensity: Vec<u32>,
+}
+
+/// Convert a Bruker timsTOF `.d` directory into an mmappet dataset at `out`.
+///
+/// Writes one row per peak with `uint32` columns `frame`, `scan`, `tof` and
+/// `intensity`. Frames are decompressed in parallel and written in frame order;
+/// only a few frames are in flight at a time, so memory use does not grow with
+/// the size of the acquisition. Returns the number of rows written.
+pub fn import_bruker<P: AsRef<Path>, Q: AsRef<Path>>(d_path: P, out: Q) -> Result<usize> {
+    let d_path = d_path.as_ref();
+    let frames = read_frames(&d_path.join("analysis.tdf"))?;
+
+    let bin_path = d_path.join("analysis.tdf_bin");
+    if !bin_path.exists() {
+        return Err(MmappetError::Import {
+            path: Some(bin_path),
+            offset: None,
+            message: "file is missing".to_string(),
+        });
+    }
+    let bin = Storage::open(&bin_path, false)?;
+
+    create_atomically(out.as_ref(), |tmp_dir| {
+        let mut writer = DatasetWriter::create(
+            tmp_dir,
+            &Schema::new(&[
+                ("frame", DType::UInt32),
+                ("scan", DType::UInt32),
+                ("tof", DType::UInt32),
+                ("intensity", DType::UInt32),
+            ])?,
+        )?;
+
+        pipeline::run(
+            pipeline::default_workers(),
+            |feed| {
+                for frame in frames.iter().filter(|f| f.num_peaks > 0) {
+                    if !feed(frame) {
+                        break;
+                    }
+                }
+                Ok(())
+            },
+            |frame: &FrameEntry| {
+                decode_frame(&bin, frame.tims_id)
+                    .map(|peaks| (frame.id, peaks))
+                    .map_err(|e| MmappetError::Import {
+                        path: Some(bin_path.clone()),
+                        offset: Some(frame.tims_id as u64),
+                        message: format!("frame {}: {}", frame.id, e),
+                    })
+            },
+            |(id, peaks)| {
+                writer.write_at(0, &vec![id; peaks.scan.len()])?;
+                writer.write_at(1, &peaks.scan)?;
+                writer.write_at(2, &peaks.tof)?;
+                writer.write_at(3, &peaks.intensity)
+            },
+        )?;
+
+        writer.finish()
+    })
+}
+
+/// Read frame locations from the `Frames` table, ordered by frame id.
+fn read_frames(tdf_path: &Path) -> Result<Vec<FrameEntry>> {
+    if !tdf_path.exists() {
+        return Err(MmappetError::Import {
+            path: Some(tdf_path.to_path_buf()),
+            offset: None,
+            message: "file is missing".to_string(),
+        });
+    }
+    let sql_err = |e: rusqlite::Error| MmappetError::Import {
+        path: Some(tdf_path.to_path_buf()),
+        offset: None,
+        message: e.to_string(),
+    };
+
+    let conn = Connection::open(tdf_path).map_err(sql_err)?;
+    let mut stmt = conn
+        .prepare("SELECT Id, TimsId, NumPeaks FROM Frames ORDER BY Id")
+        .map_err(sql_err)?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(FrameEntry {
+                id: row.get(0)?,
+                tims_id: row.get::<_, i64>(1)? as usize,
+                num_peaks: row.get::<_, i64>(2)? as usize,
+            })
+        })
+        .map_err(sql_err)?;
+
+    rows.collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(sql_err)
+}
+
+/// Decompress and decode the frame blob starting at `offset` in `analysis.tdf_bin`.
+///
+/// A blob is a `u32` byte count (including the 8-byte header), a `u32` scan
+/// count and a zstd-compressed, byte-shuffled array of `u32` words.
+fn decode_frame(bin: &[u8], offset: usize) -> std::result::Result<FramePeaks, String> {
+    let header = bin
+        .get(offset..offset + 8)
+        .ok_or_else(|| format!("blob offset {} out of bounds", offset))?;
+    let byte_count = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let compressed = bin
+        .get(offset + 8..offset + byte_count)
+        .ok_or_else(|| format!("blob at offset {} truncated", offset))?;
+
+    let raw = zstd::stream::decode_all(compressed).map_err(|e| e.to_string())?;
+    let words = unshuffle(&raw)?;
+    decode_peaks(&words)
+}
+
+/// Undo the byte-plane shuffle: byte `j` of word `i` is stored at `j * n + i`.
+fn unshuffle(raw: &[u8]) -> std::result::Result<Vec<u32>, String> {
+    if !raw.len().is_multiple_of(4) {
+        return Err(format!(
+            "decompressed size {} is not a multiple of 4",
+            raw.len()
+        ));
+    }
+    let n = raw.len() / 4;
+    Ok((0..n)
+        .map(|i| u32::from_le_bytes([raw[i], raw[n + i], raw[2 * n + i], raw[3 * n + i]]))
+        .collect())
+}
+
+/// Decode frame words into per-peak scan, tof and intensity values.
+///
+/// Layout: `words[0]` is the scan count `S`; `words[1..S]` hold twice the peak
+/// count of scans `0..S-1` (the last scan takes the remaining peaks); then
+/// `(tof_delta, intensity)` pairs follow. Tof values are cumulative per scan
+/// and offset by one.
+fn decode_peaks(words: &[u32]) -> std::result::Result<FramePeaks, String> {
+    let scan_count = match words.first() {
+        Some(&count) => count as usize,
+        None => return Ok(FramePeaks::default()),
+    };
+    if scan_count > words.len() {
+        return Err(format!("scan count {} exceeds blob size", scan_count));
+    }
+    let peak_count = (words.len() - scan_count) / 2;
+
+    let mut peaks = FramePeaks {
+        scan: Vec::with_capacity(peak_count),
+        tof: Vec::with_capacity(peak_count),
+        intensity: Vec::with_capacity(peak_count),
+    };
+
+    let mut start = 0;
+    for scan in 0..scan_count {
+        let end = if scan + 1 < scan_count {
+            start + words[scan + 1] as usize / 2
+        } else {
+            peak_count
+        };
+        if end > peak_count {
+            return Err(format!("scan {} extends past the last peak", scan));
+        }
+
+        let mut tof = 0u32;
+        for peak in start..end {
+            tof = tof.wrapping_add(words[scan_count + 2 * peak]);
+            peaks.scan.push(scan as u32);
+            peaks.tof.push(tof.wrapping_sub(1));
+            peaks.intensity.push(words[scan_count + 2 * peak + 1]);
+        }
+        start = end;
+    }
+
+    Ok(peaks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shuffle(words: &[u32]) -> Vec<u8> {
+        let n = words.len();
+        let mut raw = vec![0u8; n * 4];
+        for (i, word) in words.iter().enumerate() {
+            for (j, byte) in word.to_le_bytes().iter().enumerate() {
+                raw[j * n + i] = *byte;
+            }
+        }
+        raw
+    }
+
+    // Two scans: scan 0 has peaks (tof 10, 100) and (tof 15, 200), scan 1 has (tof 20, 300).
+    const WORDS: [u32; 8] = [2, 4, 11, 100, 5, 200, 21, 300];
+
+    #[test]
+    fn test_decode_peaks() {
+        let peaks = decode_peaks(&WORDS).unwrap();
+        assert_eq!(peaks.scan, vec![0, 0, 1]);
+        assert_eq!(peaks.tof, vec![10, 15, 20]);
+        assert_eq!(peaks.intensity, vec![100, 200, 300]);
+    }
+
+    #[test]
+    fn test_decode_frame() {
+        let compressed = zstd::stream::encode_all(&shuffle(&WORDS)[..], 0).unwrap();
+        let mut bin = vec![0xAA; 3]; // unrelated leading bytes
+        bin.extend_from_slice(&((compressed.len() + 8) as u32).to_le_bytes());
+        bin.extend_from_slice(&2u32.to_le_bytes());
+        bin.extend_from_slice(&compressed);
+
+        let peaks = decode_frame(&bin, 3).unwrap();
+        assert_eq!(peaks, decode_peaks(&WORDS).unwrap());
+        assert!(decode_frame(&bin, bin.len()).is_err());
+    }
+
+    #[test]
+    fn test_import_bruker() {
+        let tmp = std::env::temp_dir().join(format!("mmappet-bruker-{}", std::process::id()));
+        let d_path = tmp.join("run.d");
+        let out = tmp.join("run.mmappet");
+        let _ = std::fs::remove_dir_all(&tmp);
+        std::fs::create_dir_all(&d_path).unwrap();
+
+        // Frame 1 holds WORDS, frame 2 is empty, frame 3 has a single peak.
+        let mut bin = Vec::new();
+        let mut offsets = Vec::new();
+        for words in [&WORDS[..], &[1, 8, 42][..]] {
+            let compressed = zstd::stream::encode_all(&shuffle(words)[..], 0).unwrap();
+            offsets.push(bin.len() as i64);
+            bin.extend_from_slice(&((compressed.len() + 8) as u32).to_le_bytes());
+            bin.extend_from_slice(&words[0].to_le_bytes());
+            bin.extend_from_slice(&compressed);
+        }
+        std::fs::write(d_path.join("analysis.tdf_bin"), &bin).unwrap();
+
+        let conn = Connection::open(d_path.join("analysis.tdf")).unwrap();
+        conn.execute_batch("CREATE TABLE Frames (Id INTEGER, TimsId INTEGER, NumPeaks INTEGER)")
+            .unwrap();
+        for (id, tims_id, num_peaks) in [(3, offsets[1], 1), (1, offsets[0], 3), (2, 0, 0)] {
+            conn.execute(
+                "INSERT INTO Frames VALUES (?1, ?2, ?3)",
+                rusqlite::params![id, tims_id, num_peaks],
+            )
+            .unwrap();
+        }
+        drop(conn);
+
+        assert_eq!(import_bruker(&d_path, &out).unwrap(), 4);
+
+        let ds = crate::Dataset::open(&out).unwrap();
+        assert_eq!(ds.get::<u32>("frame").unwrap(), &[1, 1, 1, 3]);
+        assert_eq!(ds.get::<u32>("scan").unwrap(), &[0, 0, 1, 0]);
+        assert_eq!(ds.get::<u32>("tof").unwrap(), &[10, 15, 20, 7]);
+        assert_eq!(ds.get::<u32>("intensity").unwrap(), &[100, 200, 300, 42]);
+
+        std::fs::remove_dir_all(&tmp).unwrap();
+    }
+}
@@ -0,0 +1,238 @@
+//! Memory budget shared by lazily mapped datasets.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::column::Column;
+use crate::dataset::Dataset;
+use crate::dtype::MmappetType;
+use crate::error::{MmappetError, Result};
+use crate::schema::Schema;
+
+/// Source of unique ids for [`BudgetedDataset`] handles.
+static NEXT_DATASET_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Upper bound on the bytes mapped by a set of [`BudgetedDataset`]s.
+///
+/// Cloning yields another handle to the same budget, so one budget can span a
+/// single dataset or a whole process. When mapping a column pushes the total
+/// over the limit, the least recently used columns are unmapped until it fits
+/// again; they are re-mapped on their next access. A single column larger than
+/// the limit is still mapped.
+#[derive(Clone)]
+pub struct MemoryBudget {
+    state: Arc<Mutex<BudgetState>>,
+}
+
+struct BudgetState {
+    limit: usize,
+    used: usize,
+    clock: u64,
+    /// Mapped columns keyed by `(dataset id, schema index)`.
+    entries: HashMap<(u64, usize), Entry>,
+}
+
+struct Entry {
+    column: Arc<Column>,
+    last_used: u64,
+}
+
+impl MemoryBudget {
+    /// Create a budget of `limit` mapped bytes.
+    pub fn new(limit: usize) -> Self {
+        MemoryBudget {
+            state: Arc::new(Mutex::new(BudgetState {
+                limit,
+                used: 0,
+                clock: 0,
+                entries: HashMap::new(),
+            })),
+        }
+    }
+
+    /// The limit in bytes.
+    pub fn limit(&self) -> usize {
+        self.lock().limit
+    }
+
+    /// Bytes currently mapped through this budget.
+    pub fn mapped_bytes(&self) -> usize {
+        self.lock().used
+    }
+
+    /// Number of columns currently mapped through this budget.
+    pub fn mapped_columns(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BudgetState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl BudgetState {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    /// Unmap least recently used columns, except `keep`, until within the limit.
+    fn evict(&mut self, keep: (u64, usize)) {
+        while self.used > self.limit {
+            let victim = self
+                .entries
+                .iter()
+                .filter(|(key, _)| **key != keep)
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| *key);
+            match victim {
+                Some(key) => self.remove(key),
+                None => break,
+            }
+        }
+    }
+
+    fn remove(&mut self, key: (u64, usize)) {
+        if let Some(entry) = self.entries.remove(&key) {
+            self.used -= entry.column.as_bytes().len();
+        }
+    }
+}
+
+/// A dataset whose columns are mapped on first access under a [`MemoryBudget`].
+///
+/// Opening reads the schema and file sizes, as [`Dataset::open`] does, so
+/// only committed rows are seen. Columns are returned as `Arc<Column>`, so a
+/// handle stays valid even after the budget unmaps the column; the memory is
+/// released once the last handle is dropped.
+pub struct BudgetedDataset {
+    id: u64,
+    dataset: Dataset,
+    budget: MemoryBudget,
+}
+
+impl BudgetedDataset {
+    /// Open a dataset without mapping any column.
+    pub fn open<P: AsRef<Path>>(path: P, budget: &MemoryBudget) -> Result<Self> {
+        Ok(BudgetedDataset {
+            id: NEXT_DATASET_ID.fetch_add(1, Ordering::Relaxed),
+            dataset: Dataset::open(path)?,
+            budget: budget.clone(),
+        })
+    }
+
+    /// Get a column by name, mapping it if it isn't mapped.
+    pub fn column(&self, name: &str) -> Result<Arc<Column>> {
+        let position = self
+            .schema()
+            .columns()
+            .position(|c| c.name == name)
+            .ok_or_else(|| self.dataset.column_not_found(name))?;
+        let key = (self.id, position);
+
+        let mut state = self.budget.lock();
+        let now = state.tick();
+        if let Some(entry) = state.entries.get_mut(&key) {
+            entry.last_used = now;
+            return Ok(Arc::clone(&entry.column));
+        }
+
+        let column = Arc::new(self.dataset.open_column(name)?);
+        state.used += column.as_bytes().len();
+        state.entries.insert(
+            key,
+            Entry {
+                column: Arc::clone(&column),
+                last_used: now,
+            },
+        );
+        state.evict(key);
+        Ok(column)
+    }
+
+    /// Copy a column out as a typed `Vec`.
+    ///
+    /// Returns an error if the column doesn't exist or the type doesn't match.
+    pub fn to_vec<T: MmappetType>(&self, name: &str) -> Result<Vec<T>> {
+        let column = self.column(name)?;
+        column
+            .as_slice::<T>()
+            .map(<[T]>::to_vec)
+            .ok_or(MmappetError::TypeMismatch {
+                column: Some(name.to_string()),
+                expected: T::DTYPE,
+                actual: column.dtype(),
+            })
+    }
+
+    /// Get the schema.
+    pub fn schema(&self) -> &Schema {
+        self.dataset.schema()
+    }
+
+    /// Get the number of rows.
+    pub fn len(&self) -> usize {
+        self.dataset.len()
+    }
+
+    /// Check if the dataset is empty.
+    pub fn is_empty(&self) -> bool {
+        self.dataset.is_empty()
+    }
+
+    /// Get the dataset directory path.
+    pub fn path(&self) -> &Path {
+        self.dataset.path()
+    }
+
+    /// The budget this dataset maps its columns under.
+    pub fn budget(&self) -> &MemoryBudget {
+        &self.budget
+    }
+}
+
+impl Drop for BudgetedDataset {
+    fn drop(&mut self) {
+        let mut state = self.budget.lock();
+        for position in 0..self.dataset.num_columns() {
+            state.remove((self.id, position));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::write_dataset;
+
+    #[test]
+    fn test_budget_evicts_least_recently_used() {
+        let values: Vec<u32> = (0..1000).collect();
+        let dir = write_dataset("budget", &[("a", &values), ("b", &values), ("c", &values)]);
+        // Room for two of the three 4000-byte columns
+        let budget = MemoryBudget::new(9000);
+        let ds = BudgetedDataset::open(&dir, &budget).unwrap();
+        assert_eq!(ds.len(), 1000);
+        assert_eq!(budget.mapped_bytes(), 0);
+
+        let a = ds.column("a").unwrap();
+        ds.column("b").unwrap();
+        ds.column("a").unwrap();
+        ds.column("c").unwrap();
+        // "b" was least recently used
+        assert_eq!(budget.mapped_columns(), 2);
+        assert_eq!(budget.mapped_bytes(), 8000);
+
+        // Handles outlive eviction, and evicted columns are re-mapped
+        ds.column("b").unwrap();
+        assert_eq!(a.as_slice::<u32>().unwrap()[999], 999);
+        assert_eq!(ds.to_vec::<u32>("b").unwrap()[5], 5);
+        assert_eq!(budget.mapped_columns(), 2);
+
+        drop(ds);
+        assert_eq!(budget.mapped_bytes(), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,196 @@
+//! C API for reading datasets from C and C++, see `include/mmappet.h`.
+//!
+//! Functions report failure by returning `NULL`; [`mmappet_last_error`]
+//! then describes it.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::ptr;
+
+use crate::dataset::Dataset;
+use crate::dtype::DType;
+use crate::error::MmappetError;
+
+type CResult<T> = std::result::Result<T, String>;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Remember `error` as the last error of this thread.
+fn set_error(error: impl ToString) {
+    // Messages can't hold a NUL, but paths in them could
+    let message = error.to_string().replace('\0', "\\0");
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).ok());
+}
+
+/// Return the value of `result`, or remember its error and return `fallback`.
+fn or_error<T, E: ToString>(result: std::result::Result<T, E>, fallback: T) -> T {
+    result.unwrap_or_else(|e| {
+        set_error(e);
+        fallback
+    })
+}
+
+/// Read the NUL-terminated UTF-8 string `text`.
+///
+/// # Safety
+///
+/// `text` must be null or point to a NUL-terminated string.
+unsafe fn read_str<'a>(text: *const c_char, what: &str) -> CResult<&'a str> {
+    if text.is_null() {
+        return Err(format!("{} is NULL", what));
+    }
+    CStr::from_ptr(text).to_str().map_err(|_| format!("{} isn't UTF-8", what))
+}
+
+/// Code of `dtype` in the C API, or `None` if its values can't be read
+/// through a pointer.
+fn dtype_code(dtype: DType) -> Option<i32> {
+    Some(match dtype {
+        DType::UInt8 => 0,
+        DType::Int8 => 1,
+        DType::UInt16 => 2,
+        DType::Int16 => 3,
+        DType::UInt32 => 4,
+        DType::Int32 => 5,
+        DType::UInt64 => 6,
+        DType::Int64 => 7,
+        DType::Float16 => 8,
+        DType::Float32 => 9,
+        DType::Float64 => 10,
+        DType::Bool => 11,
+        DType::Categorical => 12,
+        DType::TimestampMillis => 13,
+        DType::TimestampMicros => 14,
+        DType::Utf8 | DType::Custom(_) => return None,
+    })
+}
+
+/// Open the dataset directory `path`.
+///
+/// Returns a handle to free with [`mmappet_close`], or `NULL` on error.
+///
+/// # Safety
+///
+/// `path` must be null or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn mmappet_open(path: *const c_char) -> *mut Dataset {
+    let dataset = read_str(path, "path").and_then(|p| Dataset::open(p).map_err(|e| e.to_string()));
+    or_error(dataset.map(|ds| Box::into_raw(Box::new(ds))), ptr::null_mut())
+}
+
+/// Close a dataset, unmapping its columns; pointers to them become invalid.
+///
+/// # Safety
+///
+/// `dataset` must be null or a handle from [`mmappet_open`] that wasn't
+/// closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn mmappet_close(dataset: *mut Dataset) {
+    if !dataset.is_null() {
+        drop(Box::from_raw(dataset));
+    }
+}
+
+/// Number of rows of a dataset, 0 if `dataset` is `NULL`.
+///
+/// # Safety
+///
+/// `dataset` must be null or an open handle from [`mmappet_open`].
+#[no_mangle]
+pub unsafe extern "C" fn mmappet_num_rows(dataset: *const Dataset) -> usize {
+    dataset.as_ref().map_or(0, Dataset::len)
+}
+
+/// Pointer to the values of column `name`, valid until the dataset is
+/// closed.
+///
+/// Stores the number of values (rows times the width of array columns) in
+/// `len` and the dtype code in `dtype`, if they aren't `NULL`. Categorical
+/// columns give their `uint32_t` codes. Returns `NULL` if the column doesn't
+/// exist or holds text or a custom dtype.
+///
+/// # Safety
+///
+/// `dataset` must be null or an open handle from [`mmappet_open`], `name`
+/// null or a NUL-terminated string, and `len` and `dtype` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn mmappet_get_column_ptr(
+    dataset: *const Dataset,
+    name: *const c_char,
+    len: *mut usize,
+    dtype: *mut i32,
+) -> *const c_void {
+    let Some(dataset) = dataset.as_ref() else {
+        set_error("dataset is NULL");
+        return ptr::null();
+    };
+    let column = read_str(name, "column name").and_then(|name| {
+        let column = dataset.mapped_column(name).map_err(|e| e.to_string())?;
+        let code = dtype_code(column.dtype()).ok_or_else(|| {
+            let dtype = column.dtype().as_str().to_string();
+            let message = format!("column '{}' has no fixed-size values", name);
+            MmappetError::InvalidDType { dtype, message }.to_string()
+        })?;
+        Ok((column, code))
+    });
+    let Some((column, code)) = or_error(column.map(Some), None) else {
+        return ptr::null();
+    };
+    if let Some(len) = len.as_mut() {
+        *len = column.len() * column.width();
+    }
+    if let Some(dtype) = dtype.as_mut() {
+        *dtype = code;
+    }
+    column.as_bytes().as_ptr().cast()
+}
+
+/// Message of the last error on this thread, or `NULL` if there was none.
+///
+/// The message stays valid until the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn mmappet_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::Schema;
+    use crate::writer::DatasetWriter;
+
+    #[test]
+    fn test_capi() {
+        let dir = std::env::temp_dir().join(format!("mmappet-capi-{}", std::process::id()));
+        let schema = Schema::new(&[("mz", DType::Float64), ("name", DType::Utf8)]).unwrap();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write("mz", &[1.5f64, 2.5, 3.5]).unwrap();
+        writer.write_str("name", &["a", "b", "c"]).unwrap();
+        writer.finish().unwrap();
+
+        let path = CString::new(dir.to_str().unwrap()).unwrap();
+        unsafe {
+            let ds = mmappet_open(path.as_ptr());
+            assert!(!ds.is_null());
+            assert_eq!(mmappet_num_rows(ds), 3);
+            let (mut len, mut dtype) = (0, -1);
+            let values = mmappet_get_column_ptr(ds, c"mz".as_ptr(), &mut len, &mut dtype);
+            assert_eq!((len, dtype), (3, 10));
+            assert_eq!(std::slice::from_raw_parts(values.cast::<f64>(), len), [1.5, 2.5, 3.5]);
+
+            let text = mmappet_get_column_ptr(ds, c"name".as_ptr(), &mut len, ptr::null_mut());
+            assert!(text.is_null());
+            let message = CStr::from_ptr(mmappet_last_error()).to_str().unwrap();
+            assert!(message.contains("'name'"), "{}", message);
+            let missing = mmappet_get_column_ptr(ds, c"missing".as_ptr(), &mut len, &mut dtype);
+            assert!(missing.is_null());
+            mmappet_close(ds);
+
+            assert!(mmappet_open(c"/nonexistent/mmappet".as_ptr()).is_null());
+            assert!(!mmappet_last_error().is_null());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
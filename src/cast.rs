@@ -0,0 +1,360 @@
+//! Conversion of numeric columns to other dtypes, e.g. narrowing `uint64`
+//! counters to `uint32`.
+
+use std::fmt;
+use std::path::Path;
+
+use bytemuck::cast_slice;
+use half::f16;
+
+use crate::column::{Column, TypedArrayView};
+use crate::dataset::Dataset;
+use crate::dtype::{DType, MmappetType};
+use crate::error::{MmappetError, Result};
+use crate::schema::Schema;
+use crate::writer::DatasetWriter;
+
+/// How a cast handles values the target dtype can't hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CastMode {
+    /// Fail with [`MmappetError::CastOverflow`] on values out of range, NaN
+    /// cast to integers and finite floats that overflow a narrower float.
+    Checked,
+    /// Clamp values to the range of the target dtype; NaN becomes 0.
+    Saturating,
+}
+
+/// A value read for casting, wide enough for every numeric dtype.
+#[derive(Clone, Copy)]
+enum Num {
+    Int(i128),
+    Float(f64),
+}
+
+impl fmt::Display for Num {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Num::Int(v) => write!(f, "{}", v),
+            Num::Float(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// A dtype values can be cast to.
+trait CastTarget: MmappetType {
+    /// Convert `value`, or `None` if it's out of range and not `saturate`.
+    fn from_num(value: Num, saturate: bool) -> Option<Self>;
+}
+
+macro_rules! int_target {
+    ($($t:ty),*) => {$(
+        impl CastTarget for $t {
+            fn from_num(value: Num, saturate: bool) -> Option<Self> {
+                let value = match value {
+                    Num::Int(v) => v,
+                    Num::Float(v) if v.is_nan() => return saturate.then_some(0),
+                    // Truncates toward zero; infinities saturate beyond any target
+                    Num::Float(v) => v as i128,
+                };
+                match <$t>::try_from(value) {
+                    Ok(v) => Some(v),
+                    Err(_) if saturate => Some(if value < 0 { <$t>::MIN } else { <$t>::MAX }),
+                    Err(_) => None,
+                }
+            }
+        }
+    )*};
+}
+
+int_target!(u8, i8, u16, i16, u32, i32, u64, i64);
+
+impl CastTarget for f64 {
+    fn from_num(value: Num, _saturate: bool) -> Option<Self> {
+        Some(match value {
+            Num::Int(v) => v as f64,
+            Num::Float(v) => v,
+        })
+    }
+}
+
+impl CastTarget for f32 {
+    fn from_num(value: Num, saturate: bool) -> Option<Self> {
+        let wide = f64::from_num(value, saturate)?;
+        match wide as f32 {
+            v if v.is_infinite() && wide.is_finite() => {
+                saturate.then(|| f32::MAX.copysign(v))
+            }
+            v => Some(v),
+        }
+    }
+}
+
+impl CastTarget for f16 {
+    fn from_num(value: Num, saturate: bool) -> Option<Self> {
+        let wide = f64::from_num(value, saturate)?;
+        match f16::from_f64(wide) {
+            v if v.is_infinite() && wide.is_finite() => {
+                saturate.then_some(if wide < 0.0 { f16::MIN } else { f16::MAX })
+            }
+            v => Some(v),
+        }
+    }
+}
+
+/// The values of `array` for casting, or `None` if they aren't numbers.
+fn nums<'a>(array: &'a TypedArrayView<'_>) -> Option<Box<dyn Iterator<Item = Num> + 'a>> {
+    let int = |v: i128| Num::Int(v);
+    Some(match array {
+        TypedArrayView::UInt8(a) => Box::new(a.iter().map(move |&v| int(v.into()))),
+        TypedArrayView::Int8(a) => Box::new(a.iter().map(move |&v| int(v.into()))),
+        TypedArrayView::UInt16(a) => Box::new(a.iter().map(move |&v| int(v.into()))),
+        TypedArrayView::Int16(a) => Box::new(a.iter().map(move |&v| int(v.into()))),
+        TypedArrayView::UInt32(a) => Box::new(a.iter().map(move |&v| int(v.into()))),
+        TypedArrayView::Int32(a) => Box::new(a.iter().map(move |&v| int(v.into()))),
+        TypedArrayView::UInt64(a) => Box::new(a.iter().map(move |&v| int(v.into()))),
+        TypedArrayView::Int64(a)
+        | TypedArrayView::TimestampMillis(a)
+        | TypedArrayView::TimestampMicros(a) => Box::new(a.iter().map(move |&v| int(v.into()))),
+        TypedArrayView::Bool(a) => Box::new(a.iter().map(move |&v| int((v != 0).into()))),
+        TypedArrayView::Float16(a) => Box::new(a.iter().map(|v| Num::Float(v.to_f64()))),
+        TypedArrayView::Float32(a) => Box::new(a.iter().map(|&v| Num::Float(v.into()))),
+        TypedArrayView::Float64(a) => Box::new(a.iter().map(|&v| Num::Float(v))),
+        TypedArrayView::Utf8(_) | TypedArrayView::Categorical(_) | TypedArrayView::Custom(_) => {
+            return None
+        }
+    })
+}
+
+fn cast_values<T: CastTarget>(
+    array: &TypedArrayView<'_>,
+    width: usize,
+    mode: CastMode,
+) -> Result<Vec<T>> {
+    let values = nums(array).ok_or_else(|| not_numeric(array.dtype()))?;
+    let saturate = mode == CastMode::Saturating;
+    values
+        .enumerate()
+        .map(|(i, value)| {
+            T::from_num(value, saturate).ok_or_else(|| MmappetError::CastOverflow {
+                column: None,
+                row: i / width.max(1),
+                value: value.to_string(),
+                dtype: T::DTYPE,
+            })
+        })
+        .collect()
+}
+
+/// Cast `array`, holding `width` values per row, to `dtype` and return the
+/// bytes of the result.
+pub(crate) fn cast_bytes(
+    array: &TypedArrayView<'_>,
+    width: usize,
+    dtype: DType,
+    mode: CastMode,
+) -> Result<Vec<u8>> {
+    fn bytes<T: CastTarget>(values: Result<Vec<T>>) -> Result<Vec<u8>> {
+        values.map(|v| cast_slice(&v).to_vec())
+    }
+    match dtype {
+        DType::UInt8 => bytes(cast_values::<u8>(array, width, mode)),
+        DType::Int8 => bytes(cast_values::<i8>(array, width, mode)),
+        DType::UInt16 => bytes(cast_values::<u16>(array, width, mode)),
+        DType::Int16 => bytes(cast_values::<i16>(array, width, mode)),
+        DType::UInt32 => bytes(cast_values::<u32>(array, width, mode)),
+        DType::Int32 => bytes(cast_values::<i32>(array, width, mode)),
+        DType::UInt64 => bytes(cast_values::<u64>(array, width, mode)),
+        DType::Int64 | DType::TimestampMillis | DType::TimestampMicros => {
+            bytes(cast_values::<i64>(array, width, mode))
+        }
+        DType::Float16 => bytes(cast_values::<f16>(array, width, mode)),
+        DType::Float32 => bytes(cast_values::<f32>(array, width, mode)),
+        DType::Float64 => bytes(cast_values::<f64>(array, width, mode)),
+        DType::Bool | DType::Utf8 | DType::Categorical | DType::Custom(_) => {
+            Err(not_numeric(dtype))
+        }
+    }
+}
+
+/// Read the values of `T` held by unaligned `bytes`.
+fn values_of<T: MmappetType>(bytes: &[u8]) -> Vec<T> {
+    bytes.chunks_exact(size_of::<T>()).map(bytemuck::pod_read_unaligned).collect()
+}
+
+fn not_numeric(dtype: DType) -> MmappetError {
+    MmappetError::InvalidDType {
+        dtype: dtype.as_str().to_string(),
+        message: "only numbers can be cast".to_string(),
+    }
+}
+
+impl Column {
+    /// Convert the values to `T`, `width` values per row for array columns.
+    ///
+    /// Integers, floats, bools (as 0 or 1) and timestamp ticks can be cast
+    /// to any integer or float type; floats are truncated toward zero when
+    /// cast to integers. Returns an error for other dtypes, or in
+    /// [`CastMode::Checked`] for the first value `T` can't hold.
+    ///
+    /// ```rust,no_run
+    /// use mmappet::{CastMode, Dataset};
+    ///
+    /// let ds = Dataset::open("data.mmappet")?;
+    /// let counts: Vec<u32> = ds["count"].cast(CastMode::Checked)?;
+    /// # Ok::<(), mmappet::MmappetError>(())
+    /// ```
+    pub fn cast<T: MmappetType>(&self, mode: CastMode) -> Result<Vec<T>> {
+        self.cast_to(T::DTYPE, mode).map(|bytes| values_of(&bytes))
+    }
+
+    /// Cast to `dtype` as by [`Column::cast`] and return the bytes of the
+    /// result.
+    fn cast_to(&self, dtype: DType, mode: CastMode) -> Result<Vec<u8>> {
+        cast_bytes(&self.as_typed_array(), self.width(), dtype, mode)
+    }
+}
+
+impl Dataset {
+    /// Convert the values of column `name` to `T`, see [`Column::cast`].
+    pub fn cast_column<T: MmappetType>(&self, name: &str, mode: CastMode) -> Result<Vec<T>> {
+        let column = self.column(name).ok_or_else(|| self.column_not_found(name))?;
+        let bytes = column.cast_to(T::DTYPE, mode).map_err(|e| e.with_column(name))?;
+        Ok(values_of(&bytes))
+    }
+
+    /// Write a copy of the dataset to `dir` with the columns in `dtypes`
+    /// cast to new dtypes, e.g. to narrow counters that fit a smaller type.
+    ///
+    /// Shapes and validity bitmaps are kept and reference columns copied.
+    /// Each cast column is converted in memory in one go. Returns an error
+    /// if a column doesn't exist or can't be cast, see [`Column::cast`], or
+    /// the number of rows written.
+    pub fn write_cast<P: AsRef<Path>>(
+        &self,
+        dir: P,
+        dtypes: &[(&str, DType)],
+        mode: CastMode,
+    ) -> Result<usize> {
+        for &(name, _) in dtypes {
+            self.column(name).ok_or_else(|| self.column_not_found(name))?;
+        }
+        let target = |name: &str| dtypes.iter().find(|(n, _)| *n == name).map(|&(_, d)| d);
+        let defs: Vec<(&str, DType)> = self
+            .schema()
+            .columns()
+            .map(|col| (col.name.as_str(), target(&col.name).unwrap_or(col.dtype)))
+            .collect();
+        let mut schema = Schema::new(&defs)?;
+        for col in self.schema().columns() {
+            schema.set_shape(&col.name, &col.shape)?;
+        }
+        let mut writer = DatasetWriter::create(dir, &schema)?;
+
+        for (index, &(name, dtype)) in defs.iter().enumerate() {
+            let nullable = self.nullable(name)?;
+            let column = nullable.column();
+            if nullable.has_validity() {
+                writer.track_validity(index);
+            }
+            if target(name).is_some() {
+                let bytes = column.cast_to(dtype, mode).map_err(|e| e.with_column(name))?;
+                writer.write_bytes(index, &bytes, column.len())?;
+            } else if let Some(strings) = column.as_str_array() {
+                writer.write_strs(index, strings.iter())?;
+            } else if let Some(labels) = column.as_categorical() {
+                writer.write_labels(index, labels.iter())?;
+            } else {
+                writer.write_bytes(index, column.as_bytes(), column.len())?;
+            }
+            if nullable.has_validity() {
+                for row in (0..column.len()).filter(|&row| !nullable.is_valid(row)) {
+                    writer.set_null(index, row);
+                }
+            }
+        }
+        writer.finish()
+    }
+}
+
+impl DatasetWriter {
+    /// Append values to a column by name, cast to its dtype, see
+    /// [`Column::cast`]; `width` values per row for array columns.
+    ///
+    /// Returns an error if the column doesn't exist, the values don't fill
+    /// whole rows or can't be cast.
+    pub fn write_cast(
+        &mut self,
+        column: &str,
+        values: &TypedArrayView<'_>,
+        mode: CastMode,
+    ) -> Result<()> {
+        let col = self
+            .schema()
+            .get(column)
+            .ok_or_else(|| MmappetError::ColumnNotFound {
+                path: None,
+                column: column.to_string(),
+            })?;
+        let (index, dtype, width) = (col.index, col.dtype, col.width());
+        let bytes = cast_bytes(values, width, dtype, mode).map_err(|e| e.with_column(column))?;
+        let rows = self.check_rows(index, values.len())?;
+        self.write_bytes(index, &bytes, rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::ArrayView1;
+
+    #[test]
+    fn test_cast() {
+        let dir = std::env::temp_dir().join(format!("mmappet-cast-{}", std::process::id()));
+        let schema = Schema::new(&[
+            ("count", DType::UInt64),
+            ("mz", DType::Float64),
+            ("name", DType::Utf8),
+        ])
+        .unwrap();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write_opt("count", &[Some(7u64), None, Some(1 << 40)]).unwrap();
+        writer.write("mz", &[-1.5f64, f64::NAN, 1e300]).unwrap();
+        writer.write_str("name", &["a", "b", "c"]).unwrap();
+        writer.finish().unwrap();
+        let ds = Dataset::open(&dir).unwrap();
+
+        let err = ds.cast_column::<u32>("count", CastMode::Checked).unwrap_err();
+        assert!(matches!(err, MmappetError::CastOverflow { row: 2, .. }), "{}", err);
+        assert_eq!(err.column(), Some("count"));
+        assert_eq!(ds.cast_column::<u32>("count", CastMode::Saturating).unwrap(), [7, 0, u32::MAX]);
+        assert_eq!(ds["count"].cast::<f32>(CastMode::Checked).unwrap(), [7.0, 0.0, 1099511627776.0]);
+        assert_eq!(ds["mz"].cast::<i8>(CastMode::Saturating).unwrap(), [-1, 0, i8::MAX]);
+        assert!(ds["mz"].cast::<i8>(CastMode::Checked).is_err());
+        assert!(ds["mz"].cast::<f32>(CastMode::Checked).is_err());
+        assert_eq!(ds["mz"].cast::<f32>(CastMode::Saturating).unwrap()[2], f32::MAX);
+        assert!(ds.cast_column::<u32>("name", CastMode::Saturating).is_err());
+        assert!(ds.cast_column::<u32>("missing", CastMode::Saturating).is_err());
+
+        let out = dir.with_extension("narrow");
+        let dtypes = [("count", DType::UInt32), ("mz", DType::Float32)];
+        assert!(ds.write_cast(&out, &dtypes, CastMode::Checked).is_err());
+        assert_eq!(ds.write_cast(&out, &dtypes, CastMode::Saturating).unwrap(), 3);
+        let narrow = Dataset::open(&out).unwrap();
+        assert_eq!(narrow.get::<u32>("count").unwrap(), &[7, 0, u32::MAX]);
+        assert!(!narrow.nullable("count").unwrap().is_valid(1));
+        assert_eq!(narrow.get::<f32>("mz").unwrap()[0], -1.5);
+        assert_eq!(narrow["name"].as_str_array().unwrap().get(2), "c");
+
+        let schema = Schema::new(&[("tof", DType::UInt16)]).unwrap();
+        let mut writer = DatasetWriter::create(&out, &schema).unwrap();
+        let values = [1u64, 70_000];
+        let values = TypedArrayView::UInt64(ArrayView1::from(&values));
+        assert!(writer.write_cast("tof", &values, CastMode::Checked).is_err());
+        writer.write_cast("tof", &values, CastMode::Saturating).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(Dataset::open(&out).unwrap().get::<u16>("tof").unwrap(), &[1, u16::MAX]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_dir_all(&out).unwrap();
+    }
+}
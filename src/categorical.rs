@@ -0,0 +1,226 @@
+//! Dictionary-encoded (categorical) columns.
+//!
+//! A [`DType::Categorical`] column stores a `u32` code per row in `{N}.bin`
+//! and its dictionary in `{N}.cat`, one label per line in code order, escaped
+//! like `meta.txt` values. Codes are checked against the dictionary when the
+//! column is mapped, so accessors never fail.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::dataset::Dataset;
+use crate::dtype::DType;
+use crate::error::{MmappetError, Result};
+use crate::meta::{escape, unescape};
+
+/// Codes and labels of a [`DType::Categorical`] column or a range of its rows.
+#[derive(Debug, Clone, Copy)]
+pub struct CategoricalView<'a> {
+    codes: &'a [u32],
+    categories: &'a [String],
+}
+
+impl<'a> CategoricalView<'a> {
+    /// View `codes` into checked `categories`.
+    pub(crate) fn new(codes: &'a [u32], categories: &'a [String]) -> Self {
+        CategoricalView { codes, categories }
+    }
+
+    /// Number of rows.
+    pub fn len(&self) -> usize {
+        self.codes.len()
+    }
+
+    /// Check if empty.
+    pub fn is_empty(&self) -> bool {
+        self.codes.is_empty()
+    }
+
+    /// Get the code of row `i`, its label's index in [`CategoricalView::categories`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is out of bounds.
+    pub fn code(&self, i: usize) -> u32 {
+        self.codes[i]
+    }
+
+    /// Get the label of row `i`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is out of bounds.
+    pub fn value(&self, i: usize) -> &'a str {
+        &self.categories[self.codes[i] as usize]
+    }
+
+    /// All labels, indexed by code. Some may not be used by any row.
+    pub fn categories(&self) -> &'a [String] {
+        self.categories
+    }
+
+    /// Codes of all rows.
+    pub fn codes(&self) -> &'a [u32] {
+        self.codes
+    }
+
+    /// Iterate over the labels of all rows.
+    pub fn iter(&self) -> impl Iterator<Item = &'a str> + 'a {
+        let categories = self.categories;
+        self.codes.iter().map(move |&code| categories[code as usize].as_str())
+    }
+
+    /// View of the rows in `rows`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rows` is out of bounds.
+    pub(crate) fn slice(&self, rows: std::ops::Range<usize>) -> Self {
+        CategoricalView::new(&self.codes[rows], self.categories)
+    }
+}
+
+impl Dataset {
+    /// Get the codes and labels of a [`DType::Categorical`] column by name.
+    ///
+    /// Returns an error if the column doesn't exist or isn't categorical.
+    pub fn categorical(&self, name: &str) -> Result<CategoricalView<'_>> {
+        let column = self.mapped_column(name)?;
+        column.as_categorical().ok_or_else(|| MmappetError::TypeMismatch {
+            column: Some(name.to_string()),
+            expected: DType::Categorical,
+            actual: column.dtype(),
+        })
+    }
+}
+
+/// Path of the dictionary of the column file at `path`.
+pub(crate) fn categories_path(path: &Path) -> PathBuf {
+    path.with_extension("cat")
+}
+
+/// Read the dictionary of the column file at `path`.
+pub(crate) fn read_categories(path: &Path) -> Result<Vec<String>> {
+    let cat_path = categories_path(path);
+    let content = fs::read_to_string(&cat_path).map_err(|e| MmappetError::io(&cat_path, e))?;
+    Ok(content.split_terminator('\n').map(unescape).collect())
+}
+
+/// Check that every code has a label.
+pub(crate) fn check_codes(path: &Path, codes: &[u32], categories: &[String]) -> Result<()> {
+    match codes.iter().position(|&code| code as usize >= categories.len()) {
+        Some(row) => Err(MmappetError::InvalidCategories {
+            path: categories_path(path),
+            column: None,
+            message: format!(
+                "code {} of row {} has no label, there are {} categories",
+                codes[row],
+                row,
+                categories.len()
+            ),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Write the dictionary of the column file at `path`, replacing it atomically.
+pub(crate) fn write_categories(path: &Path, categories: &[String]) -> Result<()> {
+    let cat_path = categories_path(path);
+    let content: String = categories.iter().map(|c| escape(c) + "\n").collect();
+    let tmp_path = cat_path.with_extension("cat.tmp");
+    fs::write(&tmp_path, content).map_err(|e| MmappetError::io(&tmp_path, e))?;
+    fs::rename(&tmp_path, &cat_path).map_err(|e| MmappetError::io(&cat_path, e))
+}
+
+/// Dictionary built while writing a column, assigning codes to labels in
+/// order of first appearance.
+#[derive(Default)]
+pub(crate) struct CategoryEncoder {
+    categories: Vec<String>,
+    codes: HashMap<String, u32>,
+}
+
+impl CategoryEncoder {
+    /// Continue an existing dictionary.
+    pub(crate) fn with_categories(categories: Vec<String>) -> Self {
+        let codes = categories
+            .iter()
+            .enumerate()
+            .map(|(code, label)| (label.clone(), code as u32))
+            .collect();
+        CategoryEncoder { categories, codes }
+    }
+
+    /// Code of `label`, added to the dictionary if new.
+    pub(crate) fn encode(&mut self, label: &str) -> u32 {
+        if let Some(&code) = self.codes.get(label) {
+            return code;
+        }
+        let code = self.categories.len() as u32;
+        self.categories.push(label.to_string());
+        self.codes.insert(label.to_string(), code);
+        code
+    }
+
+    pub(crate) fn categories(&self) -> &[String] {
+        &self.categories
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::Schema;
+    use crate::writer::DatasetWriter;
+
+    #[test]
+    fn test_categorical_column() {
+        let dir = std::env::temp_dir().join(format!("mmappet-categorical-{}", std::process::id()));
+        let schema = Schema::new(&[("id", DType::UInt32), ("run", DType::Categorical)]).unwrap();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write::<u32>("id", &[1, 2, 3, 4]).unwrap();
+        writer.write_categorical("run", &["b", "a\tx"]).unwrap();
+        writer.write_categorical("run", &["b", ""]).unwrap();
+        assert_eq!(writer.finish().unwrap(), 4);
+
+        let mut ds = Dataset::open(&dir).unwrap();
+        let run = ds.categorical("run").unwrap();
+        assert_eq!(run.categories(), ["b", "a\tx", ""]);
+        assert_eq!(run.codes(), &[0, 1, 0, 2]);
+        assert_eq!((run.code(3), run.value(1)), (2, "a\tx"));
+        assert!(matches!(
+            ds.categorical("id"),
+            Err(MmappetError::TypeMismatch { .. })
+        ));
+
+        // Sorted by label; copies get a dictionary of their own
+        let sorted = dir.with_extension("sorted");
+        ds.write_permuted(&ds.argsort("run").unwrap(), &sorted).unwrap();
+        let sorted_ds = Dataset::open(&sorted).unwrap();
+        let labels: Vec<&str> = sorted_ds.categorical("run").unwrap().iter().collect();
+        assert_eq!(labels, ["", "a\tx", "b", "b"]);
+        assert_eq!(sorted_ds.get::<u32>("id").unwrap(), &[4, 2, 1, 3]);
+
+        // Appended labels are encoded with the existing dictionary
+        let batch = [
+            ("id", sorted_ds["id"].slice_typed_array(..2)),
+            ("run", sorted_ds["run"].slice_typed_array(..2)),
+        ];
+        assert_eq!(ds.append_batch(&batch).unwrap(), 6);
+        let run = ds.categorical("run").unwrap();
+        assert_eq!(run.categories(), ["b", "a\tx", ""]);
+        assert_eq!(run.codes(), &[0, 1, 0, 2, 2, 1]);
+        drop(sorted_ds);
+
+        write_categories(&dir.join("1.bin"), &["b".to_string()]).unwrap();
+        let ds = Dataset::open(&dir).unwrap();
+        assert!(matches!(
+            ds.categorical("run"),
+            Err(MmappetError::InvalidCategories { .. })
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(sorted).unwrap();
+    }
+}
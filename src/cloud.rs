@@ -0,0 +1,272 @@
+//! Datasets in object stores (S3, GCS, Azure), through the `object_store`
+//! crate.
+//!
+//! Reads go through [`RemoteDataset`](crate::RemoteDataset), whose page
+//! cache only the transport differs for; [`upload`] writes a dataset.
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::ops::Range;
+use std::path::Path;
+
+use object_store::aws::AmazonS3Builder;
+use object_store::azure::MicrosoftAzureBuilder;
+use object_store::gcp::GoogleCloudStorageBuilder;
+use object_store::local::LocalFileSystem;
+use object_store::path::Path as StorePath;
+use object_store::{ObjectStore, ObjectStoreScheme, PutPayload, WriteMultipart};
+use tokio::runtime::Runtime;
+use url::Url;
+
+use crate::error::{MmappetError, Result};
+use crate::journal::JOURNAL_FILE;
+
+/// Size of the parts of multipart uploads; smaller files are put at once.
+const PART_SIZE: usize = 8 << 20;
+/// Parts of one file uploaded at the same time.
+const CONCURRENT_PARTS: usize = 4;
+/// Files readers need to find a dataset, uploaded after all others.
+const SCHEMA_FILES: [&str; 3] = ["meta.txt", "schema.json", "schema.txt"];
+
+/// An object store and the prefix of a dataset in it.
+pub(crate) struct Store {
+    /// URL of the dataset, without a trailing `/`.
+    url: String,
+    store: Box<dyn ObjectStore>,
+    prefix: StorePath,
+    /// Runs the store's requests for the blocking API.
+    runtime: Runtime,
+}
+
+impl Store {
+    /// Connect to the store of `url`, e.g. `s3://bucket/run42.mmappet`,
+    /// with credentials from the environment.
+    pub(crate) fn parse(url: &str) -> Result<Self> {
+        let url = url.trim_end_matches('/');
+        let invalid = |message: String| MmappetError::ObjectStore {
+            url: url.to_string(),
+            message,
+        };
+        let parsed = Url::parse(url).map_err(|e| invalid(e.to_string()))?;
+        let (scheme, prefix) =
+            ObjectStoreScheme::parse(&parsed).map_err(|e| invalid(e.to_string()))?;
+        let store: Box<dyn ObjectStore> = match scheme {
+            ObjectStoreScheme::Local => Box::new(LocalFileSystem::new()),
+            ObjectStoreScheme::AmazonS3 => Box::new(
+                AmazonS3Builder::from_env()
+                    .with_url(url)
+                    .build()
+                    .map_err(|e| invalid(e.to_string()))?,
+            ),
+            ObjectStoreScheme::GoogleCloudStorage => Box::new(
+                GoogleCloudStorageBuilder::from_env()
+                    .with_url(url)
+                    .build()
+                    .map_err(|e| invalid(e.to_string()))?,
+            ),
+            ObjectStoreScheme::MicrosoftAzure => Box::new(
+                MicrosoftAzureBuilder::from_env()
+                    .with_url(url)
+                    .build()
+                    .map_err(|e| invalid(e.to_string()))?,
+            ),
+            _ => return Err(invalid("unsupported URL scheme".to_string())),
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| invalid(e.to_string()))?;
+        Ok(Store {
+            url: url.to_string(),
+            store,
+            prefix,
+            runtime,
+        })
+    }
+
+    fn location(&self, file: &str) -> StorePath {
+        self.prefix.child(file)
+    }
+
+    pub(crate) fn error(&self, file: &str, message: String) -> MmappetError {
+        MmappetError::ObjectStore {
+            url: format!("{}/{}", self.url, file),
+            message,
+        }
+    }
+
+    /// The text of `file`, or `None` if the store doesn't have it.
+    pub(crate) fn get_text(&self, file: &str) -> Result<Option<String>> {
+        let location = self.location(file);
+        let bytes = self.runtime.block_on(async {
+            match self.store.get(&location).await {
+                Ok(result) => result.bytes().await.map(Some),
+                Err(object_store::Error::NotFound { .. }) => Ok(None),
+                Err(e) => Err(e),
+            }
+        });
+        match bytes.map_err(|e| self.error(file, e.to_string()))? {
+            Some(bytes) => String::from_utf8(bytes.to_vec())
+                .map(Some)
+                .map_err(|_| self.error(file, "not UTF-8".to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Size of `file` in bytes.
+    pub(crate) fn size(&self, file: &str) -> Result<usize> {
+        let location = self.location(file);
+        match self.runtime.block_on(self.store.head(&location)) {
+            Ok(meta) => Ok(meta.size as usize),
+            Err(object_store::Error::NotFound { .. }) => {
+                Err(self.error(file, "missing column file".to_string()))
+            }
+            Err(e) => Err(self.error(file, e.to_string())),
+        }
+    }
+
+    /// Bytes `range` of `file`, with one ranged GET.
+    pub(crate) fn fetch(&self, file: &str, range: Range<usize>) -> Result<Vec<u8>> {
+        let location = self.location(file);
+        let range = range.start as u64..range.end as u64;
+        let bytes = self
+            .runtime
+            .block_on(self.store.get_range(&location, range.clone()))
+            .map_err(|e| self.error(file, e.to_string()))?;
+        if bytes.len() as u64 != range.end - range.start {
+            let message = format!(
+                "expected {} bytes, got {}",
+                range.end - range.start,
+                bytes.len()
+            );
+            return Err(self.error(file, message));
+        }
+        Ok(bytes.to_vec())
+    }
+
+    /// Upload the local file `path` as `file`, in parts if it's large.
+    fn put_file(&self, path: &Path, file: &str) -> Result<()> {
+        let location = self.location(file);
+        let mut local = File::open(path).map_err(|e| MmappetError::io(path, e))?;
+        let size = local
+            .metadata()
+            .map_err(|e| MmappetError::io(path, e))?
+            .len() as usize;
+        let upload = async {
+            if size <= PART_SIZE {
+                let mut data = Vec::with_capacity(size);
+                local
+                    .read_to_end(&mut data)
+                    .map_err(|e| MmappetError::io(path, e))?;
+                let put = self.store.put(&location, PutPayload::from(data)).await;
+                return put.map(|_| ()).map_err(|e| self.error(file, e.to_string()));
+            }
+            let parts = self.store.put_multipart(&location).await;
+            let mut writer = WriteMultipart::new_with_chunk_size(
+                parts.map_err(|e| self.error(file, e.to_string()))?,
+                PART_SIZE,
+            );
+            let mut buffer = vec![0; PART_SIZE];
+            loop {
+                let read = match local.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(read) => read,
+                    Err(e) => {
+                        let _ = writer.abort().await;
+                        return Err(MmappetError::io(path, e));
+                    }
+                };
+                if let Err(e) = writer.wait_for_capacity(CONCURRENT_PARTS).await {
+                    let _ = writer.abort().await;
+                    return Err(self.error(file, e.to_string()));
+                }
+                writer.write(&buffer[..read]);
+            }
+            writer
+                .finish()
+                .await
+                .map(|_| ())
+                .map_err(|e| self.error(file, e.to_string()))
+        };
+        self.runtime.block_on(upload)
+    }
+}
+
+/// Upload the dataset in the local directory `dir` to `url`, e.g.
+/// `s3://bucket/run42.mmappet`, to be read with
+/// [`RemoteDataset::open`](crate::RemoteDataset::open).
+///
+/// Column files are uploaded first, large ones as multipart uploads, and
+/// `schema.txt`, `schema.json` and `meta.txt` last, so readers never find a
+/// schema whose files are missing. Credentials are taken from the
+/// environment (`AWS_*`, `GOOGLE_*`, `AZURE_*`). Subdirectories and an
+/// unfinished append's journal aren't uploaded.
+///
+/// Returns an error if `url` isn't an `s3://`, `gs://`, `az://` or
+/// `file://` URL, `dir` isn't a dataset or a request fails; files uploaded
+/// before the error are left in place.
+///
+/// ```rust,no_run
+/// mmappet::upload("run42.mmappet", "s3://lake/runs/run42.mmappet")?;
+/// let ds = mmappet::RemoteDataset::open("s3://lake/runs/run42.mmappet")?;
+/// # Ok::<(), mmappet::MmappetError>(())
+/// ```
+pub fn upload<P: AsRef<Path>>(dir: P, url: &str) -> Result<()> {
+    let dir = dir.as_ref();
+    if !dir.join("schema.txt").is_file() {
+        return Err(MmappetError::MissingSchema {
+            path: dir.to_path_buf(),
+        });
+    }
+    let store = Store::parse(url)?;
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).map_err(|e| MmappetError::io(dir, e))? {
+        let entry = entry.map_err(|e| MmappetError::io(dir, e))?;
+        let file_type = entry
+            .file_type()
+            .map_err(|e| MmappetError::io(entry.path(), e))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if file_type.is_file() && name != JOURNAL_FILE && !name.ends_with(".tmp") {
+            files.push(name);
+        }
+    }
+    // Column files sort before the schema files, which go in the order given
+    files.sort_by_key(|name| (SCHEMA_FILES.iter().position(|f| f == name), name.clone()));
+    for name in files {
+        store.put_file(&dir.join(&name), &name)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::RemoteDataset;
+    use crate::test_util::write_dataset;
+
+    #[test]
+    fn test_upload_and_read() {
+        let dir = write_dataset("cloud", &[("frame", &[1, 1, 2]), ("tof", &[10, 11, 12])]);
+        let target = dir.with_extension("uploaded");
+        let url = format!("file://{}", target.display());
+
+        upload(&dir, &url).unwrap();
+        assert!(target.join("schema.txt").is_file());
+        let ds = RemoteDataset::open(&url).unwrap();
+        assert_eq!(ds.len(), 3);
+        assert_eq!(ds.get::<u32>("tof").unwrap(), [10, 11, 12]);
+        assert_eq!(ds.slice::<u32>("frame", 1..3).unwrap(), [1, 2]);
+
+        // Missing datasets and unsupported schemes are errors
+        let missing = format!("file://{}", dir.with_extension("missing").display());
+        assert!(RemoteDataset::open(&missing).is_err());
+        assert!(matches!(
+            upload(&dir, "memory:///x"),
+            Err(MmappetError::ObjectStore { .. })
+        ));
+        assert!(upload(dir.join("missing"), &url).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(target).unwrap();
+    }
+}
@@ -1,43 +1,382 @@
 //! Column types for mmappet datasets.
 
-use std::fs::File;
-use std::path::Path;
+use std::ops::{Bound, Range, RangeBounds};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use bytemuck::cast_slice;
-use memmap2::Mmap;
-use ndarray::ArrayView1;
+use bytemuck::{cast_slice, cast_slice_mut};
+use half::f16;
+use ndarray::{Array1, ArrayView1, ArrayView2, ArrayViewD, CowArray, Ix1};
 
-use crate::dtype::{DType, MmappetType};
+use crate::categorical::{self, CategoricalView};
+use crate::compress;
+use crate::delta;
+use crate::dtype::{ByteOrder, DType, MmappetType};
 use crate::error::{MmappetError, Result};
+use crate::readahead::Readahead;
+use crate::registry::{self, CustomArrayView};
+use crate::storage::Storage;
+use crate::strings::{self, StrArrayView};
+
+/// Expected access pattern of a column, passed to the OS as an `madvise`
+/// hint. Hints are ignored on platforms without `madvise`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advice {
+    /// No special treatment; the default.
+    Normal,
+    /// Pages will be read in order: read ahead aggressively and free pages
+    /// soon after they were read.
+    Sequential,
+    /// Pages will be read in random order: read ahead less.
+    Random,
+    /// Pages will be needed soon: start reading them in.
+    WillNeed,
+    /// Pages won't be needed soon: drop them from memory. They are read from
+    /// the file again on the next access.
+    DontNeed,
+}
 
 /// Type-erased column data holding the mmap and metadata.
+///
+/// Columns are mapped read-only unless opened with [`Column::open_mut`].
+/// Where files can't be mapped, e.g. on `wasm32`, or when a dataset is
+/// opened with [`crate::OpenOptions::buffered`], they're read into memory.
 pub struct Column {
-    mmap: Mmap,
+    storage: Arc<Storage>,
+    writable: bool,
     dtype: DType,
+    /// Number of rows.
     len: usize,
+    /// Shape of the values of each row, see [`Column::with_shape`].
+    shape: Vec<usize>,
+    /// Values per row, the product of `shape`.
+    width: usize,
+    /// Whether the file is in foreign byte order and was copied into memory
+    /// byte-swapped.
+    swapped: bool,
+    /// String data of a [`DType::Utf8`] column, whose file holds the offsets.
+    strings: Option<Arc<Storage>>,
+    /// Dictionary of a [`DType::Categorical`] column, whose file holds the codes.
+    categories: Option<Vec<String>>,
 }
 
 impl Column {
     /// Open a column from a binary file.
+    ///
+    /// If only its compressed (`DatasetWriter::compress`, with the `zstd`
+    /// feature) or delta-encoded file exists (see
+    /// [`crate::DatasetWriter::delta_encode`]), the column is decoded into
+    /// memory instead.
     pub fn open<P: AsRef<Path>>(path: P, dtype: DType) -> Result<Self> {
-        let path = path.as_ref();
-        let file = File::open(path)?;
-        let mmap = unsafe { Mmap::map(&file)? };
+        Self::open_buffered(path.as_ref(), dtype, false)
+    }
+
+    /// Open a column as by [`Column::open`], reading the file into memory
+    /// instead of mapping it if `buffered`.
+    fn open_buffered(path: &Path, dtype: DType, buffered: bool) -> Result<Self> {
+        if let Some(encoded) = EncodedFile::find(path) {
+            return Self::open_encoded(path, &encoded, dtype, false);
+        }
+        let storage = Storage::open(path, buffered)?;
+        let len = checked_len(path, storage.len(), dtype)?;
+
+        Column {
+            storage: Arc::new(storage),
+            writable: false,
+            dtype,
+            len,
+            shape: Vec::new(),
+            width: 1,
+            swapped: false,
+            strings: None,
+            categories: None,
+        }
+        .with_sidecars(path, buffered)
+    }
+
+    /// Open a column from a binary file whose values are in `byte_order`.
+    ///
+    /// Files in the host's byte order are mapped as by [`Column::open`].
+    /// Others are read into anonymous memory with every value byte-swapped, so
+    /// all accessors see native values; see [`Column::is_swapped`]. Custom
+    /// dtypes can't be swapped, since their layout is unknown.
+    pub fn open_with_byte_order<P: AsRef<Path>>(
+        path: P,
+        dtype: DType,
+        byte_order: ByteOrder,
+    ) -> Result<Self> {
+        Self::open_as(path.as_ref(), dtype, byte_order, false)
+    }
+
+    /// Open a column as by [`Column::open_with_byte_order`], reading files in
+    /// native byte order into memory too if `buffered`.
+    pub(crate) fn open_as(
+        path: &Path,
+        dtype: DType,
+        byte_order: ByteOrder,
+        buffered: bool,
+    ) -> Result<Self> {
+        let size = dtype.size_bytes();
+        if byte_order == ByteOrder::native() || size == 1 {
+            return Self::open_buffered(path, dtype, buffered);
+        }
+        if let DType::Custom(name) = dtype {
+            return Err(MmappetError::InvalidDType {
+                dtype: name.to_string(),
+                message: format!("can't read {}-endian values of a custom dtype", byte_order),
+            });
+        }
+
+        if let Some(encoded) = EncodedFile::find(path) {
+            // Deltas are decoded to native values
+            let swap = matches!(encoded, EncodedFile::Compressed(_));
+            return Self::open_encoded(path, &encoded, dtype, swap);
+        }
 
-        let element_size = dtype.size_bytes();
-        let file_size = mmap.len();
+        let mut storage = Storage::open(path, true)?;
+        let len = checked_len(path, storage.len(), dtype)?;
+        swap_bytes(storage.as_mut_slice(), size);
 
-        if file_size % element_size != 0 {
+        Column {
+            storage: Arc::new(storage),
+            writable: false,
+            dtype,
+            len,
+            shape: Vec::new(),
+            width: 1,
+            swapped: len > 0,
+            strings: None,
+            categories: None,
+        }
+        .with_sidecars(path, buffered)
+    }
+
+    /// Open the first `values` values of a column file as by
+    /// [`Column::open_as`], ignoring any more an append may have written.
+    pub(crate) fn open_prefix(
+        path: &Path,
+        dtype: DType,
+        byte_order: ByteOrder,
+        buffered: bool,
+        values: usize,
+    ) -> Result<Self> {
+        // Other files can't be appended to
+        let swap = byte_order != ByteOrder::native() && dtype.size_bytes() > 1;
+        if swap || EncodedFile::find(path).is_some() {
+            return Self::open_as(path, dtype, byte_order, buffered);
+        }
+        let storage = Storage::open_prefix(path, values * dtype.size_bytes(), buffered)?;
+        let len = checked_len(path, storage.len(), dtype)?;
+
+        Column {
+            storage: Arc::new(storage),
+            writable: false,
+            dtype,
+            len,
+            shape: Vec::new(),
+            width: 1,
+            swapped: false,
+            strings: None,
+            categories: None,
+        }
+        .with_sidecars(path, buffered)
+    }
+
+    /// Decode the encoded file of the column file `path` into memory,
+    /// byte-swapping the values if `swap`.
+    fn open_encoded(path: &Path, encoded: &EncodedFile, dtype: DType, swap: bool) -> Result<Self> {
+        let size = encoded.decoded_size(dtype)?;
+        let len = checked_len(path, size, dtype)?;
+        let mut storage = Storage::zeroed(size);
+        encoded.decode(dtype, storage.as_mut_slice())?;
+        if swap {
+            swap_bytes(storage.as_mut_slice(), dtype.size_bytes());
+        }
+
+        Column {
+            storage: Arc::new(storage),
+            writable: false,
+            dtype,
+            len,
+            shape: Vec::new(),
+            width: 1,
+            swapped: swap,
+            strings: None,
+            categories: None,
+        }
+        .with_sidecars(path, false)
+    }
+
+    /// Open a column from a binary file for in-place modification.
+    ///
+    /// Changes are written back to the file by the OS; call [`Column::flush`]
+    /// to make sure they reached the disk.
+    ///
+    /// String columns can't be modified in place.
+    pub fn open_mut<P: AsRef<Path>>(path: P, dtype: DType) -> Result<Self> {
+        Self::open_mut_as(path.as_ref(), dtype, false)
+    }
+
+    /// Open a column as by [`Column::open_mut`]; if `buffered`, the file is
+    /// read into memory and written back on [`Column::flush`] and drop.
+    pub(crate) fn open_mut_as(path: &Path, dtype: DType, buffered: bool) -> Result<Self> {
+        if dtype == DType::Utf8 {
+            return Err(MmappetError::InvalidDType {
+                dtype: dtype.to_string(),
+                message: "string columns can't be modified in place".to_string(),
+            });
+        }
+        let storage = Storage::open_mut(path, buffered)?;
+        let len = checked_len(path, storage.len(), dtype)?;
+
+        Column {
+            storage: Arc::new(storage),
+            writable: true,
+            dtype,
+            len,
+            shape: Vec::new(),
+            width: 1,
+            swapped: false,
+            strings: None,
+            categories: None,
+        }
+        .with_sidecars(path, buffered)
+    }
+
+    /// Copy `array` into a column held in memory, named `path` in errors.
+    pub(crate) fn from_array(path: &Path, array: &TypedArray) -> Result<Self> {
+        let (storage, strings, categories) = match array {
+            TypedArray::UInt8(arr) | TypedArray::Bool(arr) => (storage_of(arr.iter()), None, None),
+            TypedArray::Int8(arr) => (storage_of(arr.iter()), None, None),
+            TypedArray::UInt16(arr) => (storage_of(arr.iter()), None, None),
+            TypedArray::Int16(arr) => (storage_of(arr.iter()), None, None),
+            TypedArray::UInt32(arr) => (storage_of(arr.iter()), None, None),
+            TypedArray::Int32(arr) => (storage_of(arr.iter()), None, None),
+            TypedArray::UInt64(arr) => (storage_of(arr.iter()), None, None),
+            TypedArray::Int64(arr)
+            | TypedArray::TimestampMillis(arr)
+            | TypedArray::TimestampMicros(arr) => (storage_of(arr.iter()), None, None),
+            TypedArray::Float16(arr) => (storage_of(arr.iter()), None, None),
+            TypedArray::Float32(arr) => (storage_of(arr.iter()), None, None),
+            TypedArray::Float64(arr) => (storage_of(arr.iter()), None, None),
+            TypedArray::Utf8(values) => {
+                let ends = values.iter().scan(0, |end, value| {
+                    *end += value.len() as u64;
+                    Some(*end)
+                });
+                let ends: Vec<u64> = ends.collect();
+                let data = storage_of(values.concat().as_bytes().iter());
+                (storage_of(ends.iter()), Some(Arc::new(data)), None)
+            }
+            TypedArray::Categorical { codes, categories } => {
+                categorical::check_codes(path, codes, categories)?;
+                (storage_of(codes.iter()), None, Some(categories.clone()))
+            }
+            TypedArray::Custom { dtype, bytes } => {
+                registry::lookup(dtype).ok_or_else(|| MmappetError::UnknownDType {
+                    dtype: dtype.to_string(),
+                })?;
+                (storage_of(bytes.iter()), None, None)
+            }
+        };
+        let dtype = array.dtype();
+        let len = checked_len(path, storage.len(), dtype)?;
+
+        Ok(Column {
+            storage: Arc::new(storage),
+            writable: false,
+            dtype,
+            len,
+            shape: Vec::new(),
+            width: 1,
+            swapped: false,
+            strings,
+            categories,
+        })
+    }
+
+    /// Load the files next to the column file that its values refer to: the
+    /// string data of a [`DType::Utf8`] column or the dictionary of a
+    /// [`DType::Categorical`] one, checked against the column's values.
+    fn with_sidecars(mut self, path: &Path, buffered: bool) -> Result<Self> {
+        match self.dtype {
+            DType::Utf8 => {
+                let data = Storage::open(&strings::data_path(path), buffered)?;
+                strings::check_strings(path, cast_slice(self.as_bytes()), &data)?;
+                self.strings = Some(Arc::new(data));
+            }
+            DType::Categorical => {
+                let categories = categorical::read_categories(path)?;
+                categorical::check_codes(path, cast_slice(self.as_bytes()), &categories)?;
+                self.categories = Some(categories);
+            }
+            _ => {}
+        }
+        Ok(self)
+    }
+
+    /// Treat the values as rows of `shape`, for columns of fixed-size arrays
+    /// such as `float32[4]` or matrices such as `float32[3,4]`.
+    ///
+    /// [`Column::len`] then counts rows, while slices and typed arrays still
+    /// hold all values, row after row; see [`Column::as_array2`] and
+    /// [`Column::as_array_d`]. Returns an error if the values don't fill
+    /// whole rows.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a dimension is zero.
+    pub(crate) fn with_shape(mut self, path: &Path, shape: &[usize]) -> Result<Self> {
+        assert!(!shape.contains(&0), "dimensions must be non-zero");
+        let width = shape.iter().product();
+        let values = self.len * self.width;
+        if !values.is_multiple_of(width) {
             return Err(MmappetError::InvalidFileSize {
                 path: path.to_path_buf(),
-                actual: file_size,
-                element_size,
+                column: None,
+                actual: self.storage.len(),
+                element_size: self.dtype.size_bytes() * width,
             });
         }
+        self.len = values / width;
+        self.shape = shape.to_vec();
+        self.width = width;
+        Ok(self)
+    }
 
-        let len = file_size / element_size;
+    /// Number of rows of `width` values in a column file, from its metadata
+    /// and without mapping it.
+    pub(crate) fn file_len<P: AsRef<Path>>(path: P, dtype: DType, width: usize) -> Result<usize> {
+        let path = path.as_ref();
+        let file_size = match EncodedFile::find(path) {
+            Some(encoded) => encoded.decoded_size(dtype)?,
+            None => std::fs::metadata(path)
+                .map_err(|e| MmappetError::io(path, e))?
+                .len() as usize,
+        };
+        let values = checked_len(path, file_size, dtype)?;
+        if !values.is_multiple_of(width) {
+            return Err(MmappetError::InvalidFileSize {
+                path: path.to_path_buf(),
+                column: None,
+                actual: file_size,
+                element_size: dtype.size_bytes() * width,
+            });
+        }
+        Ok(values / width)
+    }
 
-        Ok(Column { mmap, dtype, len })
+    /// Number of whole rows of `width` values in a column file, ignoring a
+    /// partly written one at its end.
+    pub(crate) fn file_rows<P: AsRef<Path>>(path: P, dtype: DType, width: usize) -> Result<usize> {
+        let path = path.as_ref();
+        let file_size = match EncodedFile::find(path) {
+            Some(encoded) => encoded.decoded_size(dtype)?,
+            None => std::fs::metadata(path)
+                .map_err(|e| MmappetError::io(path, e))?
+                .len() as usize,
+        };
+        Ok(file_size / (dtype.size_bytes() * width))
     }
 
     /// Get the data type.
@@ -45,54 +384,564 @@ impl Column {
         self.dtype
     }
 
-    /// Get the number of elements.
+    /// Get the number of rows, which is the number of values unless the
+    /// column holds arrays.
     pub fn len(&self) -> usize {
         self.len
     }
 
+    /// Values per row, the product of [`Column::shape`].
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Shape of the values of each row, empty unless the column holds arrays.
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    /// Return [`MmappetError::NotScalar`] if the column holds arrays.
+    pub(crate) fn check_scalar(&self, name: &str) -> Result<()> {
+        if self.width == 1 {
+            return Ok(());
+        }
+        Err(MmappetError::NotScalar {
+            column: name.to_string(),
+            width: self.width,
+        })
+    }
+
+    /// Size of a row in bytes.
+    pub(crate) fn row_size(&self) -> usize {
+        self.dtype.size_bytes() * self.width
+    }
+
     /// Check if column is empty.
     pub fn is_empty(&self) -> bool {
         self.len == 0
     }
 
-    /// Get raw bytes.
+    /// Check if the file is in foreign byte order, so the column holds a
+    /// byte-swapped copy of it instead of mapping it, see
+    /// [`Column::open_with_byte_order`].
+    pub fn is_swapped(&self) -> bool {
+        self.swapped
+    }
+
+    /// Check if the column maps its file rather than holding a copy of it.
+    pub(crate) fn is_mapped(&self) -> bool {
+        self.storage.is_mapped()
+    }
+
+    /// Check if the column was opened with [`Column::open_mut`].
+    pub fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    /// Tell the OS how the column is going to be accessed.
+    ///
+    /// E.g. [`Advice::Sequential`] before a full scan of a column much larger
+    /// than memory, or [`Advice::Random`] before point lookups.
+    pub fn advise(&self, advice: Advice) -> Result<()> {
+        self.advise_bytes(advice, 0..self.storage.len())
+    }
+
+    /// Apply `advice` to a byte range of the mapping.
+    pub(crate) fn advise_bytes(&self, advice: Advice, bytes: Range<usize>) -> Result<()> {
+        self.storage.advise(advice, bytes)
+    }
+
+    /// Ask for the mapping to be backed by transparent huge pages.
+    ///
+    /// Best effort: errors, e.g. for filesystems without huge page support,
+    /// are ignored, and it does nothing outside Linux. `MAP_HUGETLB` isn't
+    /// used since it only applies to hugetlbfs files.
+    pub(crate) fn advise_huge_pages(&self) {
+        self.storage.advise_huge_pages();
+    }
+
+    /// Lock the column's pages in RAM (`mlock`), reading them in first, so
+    /// accessing it never waits on the disk.
+    ///
+    /// Pages stay locked until [`Column::unlock`] or the column is dropped.
+    /// Returns [`MmappetError::LockLimit`] if the process may not lock that
+    /// much memory, and an error on platforms without `mlock`.
+    pub fn lock_resident(&self) -> Result<()> {
+        let bytes = self.as_bytes();
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        #[cfg(unix)]
+        {
+            // SAFETY: the range is the whole mapping.
+            if unsafe { libc::mlock(bytes.as_ptr().cast(), bytes.len()) } == 0 {
+                return Ok(());
+            }
+            let err = std::io::Error::last_os_error();
+            if matches!(err.raw_os_error(), Some(libc::ENOMEM) | Some(libc::EAGAIN)) {
+                let mut limit = libc::rlimit {
+                    rlim_cur: 0,
+                    rlim_max: 0,
+                };
+                // SAFETY: `limit` is a valid rlimit to write to.
+                let ok = unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } == 0;
+                if ok && limit.rlim_cur != libc::RLIM_INFINITY {
+                    return Err(MmappetError::LockLimit {
+                        path: None,
+                        column: None,
+                        requested: bytes.len(),
+                        limit: limit.rlim_cur,
+                    });
+                }
+            }
+            Err(err.into())
+        }
+        #[cfg(not(unix))]
+        Err(std::io::Error::from(std::io::ErrorKind::Unsupported).into())
+    }
+
+    /// Release a lock taken by [`Column::lock_resident`]; the pages may be
+    /// evicted again. Does nothing if the column isn't locked.
+    pub fn unlock(&self) -> Result<()> {
+        let bytes = self.as_bytes();
+        #[cfg(unix)]
+        // SAFETY: the range is the whole mapping.
+        if !bytes.is_empty() && unsafe { libc::munlock(bytes.as_ptr().cast(), bytes.len()) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        #[cfg(not(unix))]
+        let _ = bytes;
+        Ok(())
+    }
+
+    /// Get raw bytes, in the host's byte order.
     pub fn as_bytes(&self) -> &[u8] {
-        &self.mmap[..]
+        &self.storage
     }
 
-    /// Try to get as a typed slice.
+    /// The storage of the values and, for a [`DType::Utf8`] column, of the
+    /// string data, for buffers that share it instead of copying.
+    #[cfg(feature = "arrow")]
+    pub(crate) fn shared_storage(&self) -> (&Arc<Storage>, Option<&Arc<Storage>>) {
+        (&self.storage, self.strings.as_ref())
+    }
+
+    /// Get raw bytes for modification.
+    ///
+    /// Returns `None` if the column was opened read-only.
+    pub fn as_bytes_mut(&mut self) -> Option<&mut [u8]> {
+        if !self.writable {
+            return None;
+        }
+        // SAFETY: the mapping is writable and `&mut self` guarantees no other
+        // borrow of it exists. Readahead threads only read through a pointer.
+        let storage = &self.storage;
+        Some(unsafe { std::slice::from_raw_parts_mut(storage.as_mut_ptr(), storage.len()) })
+    }
+
+    /// Try to get as a typed slice, holding all values row after row.
     ///
     /// Returns `None` if the requested type doesn't match the column's dtype.
     pub fn as_slice<T: MmappetType>(&self) -> Option<&[T]> {
         if T::DTYPE == self.dtype {
-            Some(cast_slice(&self.mmap[..]))
+            Some(cast_slice(self.as_bytes()))
         } else {
             None
         }
     }
 
+    /// Copy the values to the heap, e.g. to keep them after the column is
+    /// unmapped or send them to another thread.
+    ///
+    /// Returns `None` if the requested type doesn't match the column's dtype.
+    pub fn to_vec<T: MmappetType>(&self) -> Option<Vec<T>> {
+        self.as_slice().map(<[T]>::to_vec)
+    }
+
+    /// Try to get as a mutable typed slice.
+    ///
+    /// Returns `None` if the column was opened read-only or the requested
+    /// type doesn't match the column's dtype.
+    pub fn as_slice_mut<T: MmappetType>(&mut self) -> Option<&mut [T]> {
+        if T::DTYPE == self.dtype {
+            self.as_bytes_mut().map(cast_slice_mut)
+        } else {
+            None
+        }
+    }
+
+    /// Write modified pages back to the file and wait until they're on disk.
+    ///
+    /// Does nothing for read-only columns.
+    pub fn flush(&self) -> Result<()> {
+        if self.writable {
+            self.storage.flush()?;
+        }
+        Ok(())
+    }
+
     /// Try to get as ndarray ArrayView1.
     ///
     /// Returns `None` if the requested type doesn't match the column's dtype.
-    pub fn as_array<T: MmappetType>(&self) -> Option<ArrayView1<T>> {
+    pub fn as_array<T: MmappetType>(&self) -> Option<ArrayView1<'_, T>> {
         self.as_slice::<T>().map(ArrayView1::from)
     }
 
+    /// Try to get as ndarray ArrayView2 of shape `(rows, width)`, the
+    /// values of each row flattened.
+    ///
+    /// Returns `None` if the requested type doesn't match the column's dtype.
+    pub fn as_array2<T: MmappetType>(&self) -> Option<ArrayView2<'_, T>> {
+        let data = self.as_slice::<T>()?;
+        Some(ArrayView2::from_shape((self.len, self.width), data).unwrap())
+    }
+
+    /// Try to get as ndarray ArrayViewD of shape `(rows, ..shape)`, e.g.
+    /// `(rows, 3, 4)` for `float32[3,4]`.
+    ///
+    /// Returns `None` if the requested type doesn't match the column's dtype.
+    pub fn as_array_d<T: MmappetType>(&self) -> Option<ArrayViewD<'_, T>> {
+        let data = self.as_slice::<T>()?;
+        let shape: Vec<usize> = std::iter::once(self.len).chain(self.shape.iter().copied()).collect();
+        Some(ArrayViewD::from_shape(shape, data).unwrap())
+    }
+
+    /// View a float32 column, or a float16 column converted to f32.
+    ///
+    /// Borrows the mapped data for float32 and allocates for float16.
+    /// Returns `None` for other dtypes.
+    pub fn as_f32_array(&self) -> Option<CowArray<'_, f32, Ix1>> {
+        match self.dtype {
+            DType::Float32 => self.as_array::<f32>().map(CowArray::from),
+            DType::Float16 => {
+                let data = self.as_slice::<f16>()?;
+                Some(CowArray::from(data.iter().map(|x| x.to_f32()).collect::<Array1<f32>>()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Iterate over the column in consecutive slices of `chunk_len` elements.
+    ///
+    /// The last chunk may be shorter. Returns `None` if the requested type
+    /// doesn't match the column's dtype.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_len` is zero.
+    pub fn chunks<T: MmappetType>(&self, chunk_len: usize) -> Option<Chunks<'_, T>> {
+        assert!(chunk_len > 0, "chunk_len must be non-zero");
+        let data = self.as_slice::<T>()?;
+        Some(Chunks {
+            column: self,
+            data,
+            chunk_len,
+            offset: 0,
+            readahead: None,
+        })
+    }
+
+    /// Start prefetching the column's pages `distance` bytes ahead of the
+    /// position reported to the returned [`Readahead`].
+    pub(crate) fn readahead(&self, distance: usize) -> Readahead {
+        Readahead::spawn(Arc::clone(&self.storage), distance)
+    }
+
     /// Get as dynamically-typed array enum.
     pub fn as_typed_array(&self) -> TypedArrayView<'_> {
-        match self.dtype {
-            DType::UInt8 => TypedArrayView::UInt8(ArrayView1::from(cast_slice(&self.mmap[..]))),
-            DType::Int8 => TypedArrayView::Int8(ArrayView1::from(cast_slice(&self.mmap[..]))),
-            DType::UInt16 => TypedArrayView::UInt16(ArrayView1::from(cast_slice(&self.mmap[..]))),
-            DType::Int16 => TypedArrayView::Int16(ArrayView1::from(cast_slice(&self.mmap[..]))),
-            DType::UInt32 => TypedArrayView::UInt32(ArrayView1::from(cast_slice(&self.mmap[..]))),
-            DType::Int32 => TypedArrayView::Int32(ArrayView1::from(cast_slice(&self.mmap[..]))),
-            DType::UInt64 => TypedArrayView::UInt64(ArrayView1::from(cast_slice(&self.mmap[..]))),
-            DType::Int64 => TypedArrayView::Int64(ArrayView1::from(cast_slice(&self.mmap[..]))),
-            DType::Float32 => TypedArrayView::Float32(ArrayView1::from(cast_slice(&self.mmap[..]))),
-            DType::Float64 => TypedArrayView::Float64(ArrayView1::from(cast_slice(&self.mmap[..]))),
-            DType::Bool => TypedArrayView::Bool(ArrayView1::from(cast_slice(&self.mmap[..]))),
+        if let Some(strings) = self.as_str_array() {
+            return TypedArrayView::Utf8(strings);
+        }
+        if let Some(categories) = self.as_categorical() {
+            return TypedArrayView::Categorical(categories);
+        }
+        typed_array(self.dtype, self.as_bytes())
+    }
+
+    /// Get the codes and labels of a [`DType::Categorical`] column.
+    ///
+    /// Returns `None` if the column isn't categorical.
+    pub fn as_categorical(&self) -> Option<CategoricalView<'_>> {
+        let categories = self.categories.as_ref()?;
+        Some(CategoricalView::new(cast_slice(self.as_bytes()), categories))
+    }
+
+    /// Get the strings of a [`DType::Utf8`] column.
+    pub(crate) fn as_str_array(&self) -> Option<StrArrayView<'_>> {
+        let data = self.strings.as_ref()?;
+        Some(StrArrayView::new(0, cast_slice(self.as_bytes()), data))
+    }
+
+    /// Get a typed ArrayView1 over the values of `rows`, without copying.
+    ///
+    /// Returns `None` if the requested type doesn't match the column's dtype.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rows` is out of bounds.
+    pub fn slice<T: MmappetType>(
+        &self,
+        rows: impl RangeBounds<usize>,
+    ) -> Option<ArrayView1<'_, T>> {
+        let rows = checked_range(rows, self.len);
+        self.as_slice::<T>()
+            .map(|data| ArrayView1::from(&data[rows.start * self.width..rows.end * self.width]))
+    }
+
+    /// Index of the first element not less than `value`, by binary search.
+    ///
+    /// This is where `value` would be inserted to keep the column sorted, as
+    /// `numpy.searchsorted` with `side='left'`. The column must be sorted in
+    /// ascending order; otherwise the result is unspecified. Returns `None` if
+    /// the requested type doesn't match the column's dtype.
+    pub fn search_sorted<T: MmappetType + PartialOrd>(&self, value: T) -> Option<usize> {
+        self.as_slice::<T>()
+            .map(|data| data.partition_point(|x| *x < value))
+    }
+
+    /// Gather the values of the rows at `indices` into an owned array, in the
+    /// given order.
+    ///
+    /// Returns an error if the requested type doesn't match the column's dtype
+    /// or an index is out of bounds.
+    pub fn take<T: MmappetType>(&self, indices: &[usize]) -> Result<Array1<T>> {
+        let data = self.as_slice::<T>().ok_or(MmappetError::TypeMismatch {
+            column: None,
+            expected: T::DTYPE,
+            actual: self.dtype,
+        })?;
+        check_indices(indices, self.len)?;
+        let width = self.width;
+        Ok(indices
+            .iter()
+            .flat_map(|&i| &data[i * width..(i + 1) * width])
+            .copied()
+            .collect())
+    }
+
+    /// Append the raw bytes of the rows at `indices` to `buf`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an index is out of bounds.
+    pub(crate) fn gather_bytes(&self, indices: &[usize], buf: &mut Vec<u8>) {
+        let bytes = self.as_bytes();
+        let size = self.row_size();
+        buf.reserve(indices.len() * size);
+        for &i in indices {
+            buf.extend_from_slice(&bytes[i * size..(i + 1) * size]);
+        }
+    }
+
+    /// Get the values of `rows` as dynamically-typed array enum.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rows` is out of bounds.
+    pub fn slice_typed_array(&self, rows: impl RangeBounds<usize>) -> TypedArrayView<'_> {
+        let rows = checked_range(rows, self.len);
+        if let Some(strings) = self.as_str_array() {
+            return TypedArrayView::Utf8(strings.slice(rows));
+        }
+        if let Some(categories) = self.as_categorical() {
+            return TypedArrayView::Categorical(categories.slice(rows));
         }
+        let size = self.row_size();
+        typed_array(
+            self.dtype,
+            &self.as_bytes()[rows.start * size..rows.end * size],
+        )
+    }
+}
+
+/// Reverse the bytes of every `size`-byte value in `bytes`.
+pub(crate) fn swap_bytes(bytes: &mut [u8], size: usize) {
+    if size > 1 {
+        for value in bytes.chunks_exact_mut(size) {
+            value.reverse();
+        }
+    }
+}
+
+/// View `bytes` as an array of `dtype` elements.
+///
+/// # Panics
+///
+/// Panics for [`DType::Utf8`] and [`DType::Categorical`], whose values
+/// aren't in `bytes`.
+pub(crate) fn typed_array(dtype: DType, bytes: &[u8]) -> TypedArrayView<'_> {
+    match dtype {
+        DType::UInt8 => TypedArrayView::UInt8(ArrayView1::from(cast_slice(bytes))),
+        DType::Int8 => TypedArrayView::Int8(ArrayView1::from(cast_slice(bytes))),
+        DType::UInt16 => TypedArrayView::UInt16(ArrayView1::from(cast_slice(bytes))),
+        DType::Int16 => TypedArrayView::Int16(ArrayView1::from(cast_slice(bytes))),
+        DType::UInt32 => TypedArrayView::UInt32(ArrayView1::from(cast_slice(bytes))),
+        DType::Int32 => TypedArrayView::Int32(ArrayView1::from(cast_slice(bytes))),
+        DType::UInt64 => TypedArrayView::UInt64(ArrayView1::from(cast_slice(bytes))),
+        DType::Int64 => TypedArrayView::Int64(ArrayView1::from(cast_slice(bytes))),
+        DType::Float16 => TypedArrayView::Float16(ArrayView1::from(cast_slice(bytes))),
+        DType::Float32 => TypedArrayView::Float32(ArrayView1::from(cast_slice(bytes))),
+        DType::Float64 => TypedArrayView::Float64(ArrayView1::from(cast_slice(bytes))),
+        DType::Bool => TypedArrayView::Bool(ArrayView1::from(cast_slice(bytes))),
+        DType::TimestampMillis => TypedArrayView::TimestampMillis(ArrayView1::from(cast_slice(bytes))),
+        DType::TimestampMicros => TypedArrayView::TimestampMicros(ArrayView1::from(cast_slice(bytes))),
+        DType::Utf8 => panic!("string columns have no fixed-size elements"),
+        DType::Categorical => panic!("categorical columns need their dictionary"),
+        DType::Custom(name) => {
+            let def = registry::lookup(name)
+                .unwrap_or_else(|| panic!("dtype '{}' is not registered", name));
+            TypedArrayView::Custom(CustomArrayView::new(def, bytes))
+        }
+    }
+}
+
+/// Check that all `indices` are below `len`.
+pub(crate) fn check_indices(indices: &[usize], len: usize) -> Result<()> {
+    match indices.iter().find(|&&i| i >= len) {
+        Some(i) => Err(MmappetError::InvalidSelection {
+            path: None,
+            message: format!("row {} out of bounds for {} rows", i, len),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Resolve `rows` against a length of `len`.
+///
+/// # Panics
+///
+/// Panics if the range is decreasing or ends past `len`.
+pub(crate) fn checked_range(rows: impl RangeBounds<usize>, len: usize) -> Range<usize> {
+    let start = match rows.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start + 1,
+        Bound::Unbounded => 0,
+    };
+    let end = match rows.end_bound() {
+        Bound::Included(&end) => end + 1,
+        Bound::Excluded(&end) => end,
+        Bound::Unbounded => len,
+    };
+    assert!(
+        start <= end,
+        "row range starts at {} but ends at {}",
+        start,
+        end
+    );
+    assert!(
+        end <= len,
+        "row range end {} out of bounds for {} rows",
+        end,
+        len
+    );
+    start..end
+}
+
+/// Number of `dtype` elements in a file of `file_size` bytes.
+fn checked_len(path: &Path, file_size: usize, dtype: DType) -> Result<usize> {
+    let element_size = dtype.size_bytes();
+
+    if !file_size.is_multiple_of(element_size) {
+        return Err(MmappetError::InvalidFileSize {
+            path: path.to_path_buf(),
+            column: None,
+            actual: file_size,
+            element_size,
+        });
+    }
+
+    Ok(file_size / element_size)
+}
+
+/// File standing in for a missing column file, decoded into memory when the
+/// column is opened.
+pub(crate) enum EncodedFile {
+    /// zstd-compressed blocks, see `compress.rs`.
+    Compressed(PathBuf),
+    /// Deltas of sorted integers, see `delta.rs`.
+    Delta(PathBuf),
+}
+
+impl EncodedFile {
+    /// The encoded file of the column file `path`, if `path` doesn't exist.
+    pub(crate) fn find(path: &Path) -> Option<Self> {
+        compress::compressed_file(path)
+            .map(EncodedFile::Compressed)
+            .or_else(|| delta::delta_file(path).map(EncodedFile::Delta))
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        match self {
+            EncodedFile::Compressed(path) | EncodedFile::Delta(path) => path,
+        }
+    }
+
+    /// Size in bytes of the decoded values, from the file's header.
+    fn decoded_size(&self, dtype: DType) -> Result<usize> {
+        match self {
+            EncodedFile::Compressed(path) => compress::uncompressed_len(path),
+            EncodedFile::Delta(path) => Ok(delta::decoded_len(path)? * dtype.size_bytes()),
+        }
+    }
+
+    fn decode(&self, dtype: DType, out: &mut [u8]) -> Result<()> {
+        match self {
+            EncodedFile::Compressed(path) => compress::decompress(path, out),
+            EncodedFile::Delta(path) => delta::decode(path, dtype, out),
+        }
+    }
+}
+
+/// Sequential iterator over fixed-size chunks of a column.
+///
+/// Created by [`Column::chunks`].
+pub struct Chunks<'a, T> {
+    column: &'a Column,
+    data: &'a [T],
+    chunk_len: usize,
+    offset: usize,
+    readahead: Option<Readahead>,
+}
+
+impl<'a, T: MmappetType> Chunks<'a, T> {
+    /// Prefetch pages on a background thread, staying `distance` elements
+    /// ahead of the chunk currently being consumed.
+    ///
+    /// Useful when the column lives on slow or cold storage: page faults are
+    /// taken by the prefetcher while the consumer is busy with earlier chunks.
+    pub fn with_readahead(mut self, distance: usize) -> Self {
+        let element_size = std::mem::size_of::<T>();
+        let readahead = self
+            .column
+            .readahead(distance.saturating_mul(element_size));
+        readahead.advance(self.offset * element_size);
+        self.readahead = Some(readahead);
+        self
+    }
+}
+
+impl<'a, T: MmappetType> Iterator for Chunks<'a, T> {
+    type Item = &'a [T];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.data.len() {
+            return None;
+        }
+
+        let end = (self.offset + self.chunk_len).min(self.data.len());
+        let chunk = &self.data[self.offset..end];
+        self.offset = end;
+
+        if let Some(readahead) = &self.readahead {
+            readahead.advance(self.offset * std::mem::size_of::<T>());
+        }
+
+        Some(chunk)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.data.len() - self.offset).div_ceil(self.chunk_len);
+        (remaining, Some(remaining))
     }
 }
 
@@ -106,9 +955,15 @@ pub enum TypedArrayView<'a> {
     Int32(ArrayView1<'a, i32>),
     UInt64(ArrayView1<'a, u64>),
     Int64(ArrayView1<'a, i64>),
+    Float16(ArrayView1<'a, f16>),
     Float32(ArrayView1<'a, f32>),
     Float64(ArrayView1<'a, f64>),
     Bool(ArrayView1<'a, u8>), // Bool stored as u8
+    TimestampMillis(ArrayView1<'a, i64>),
+    TimestampMicros(ArrayView1<'a, i64>),
+    Utf8(StrArrayView<'a>),
+    Categorical(CategoricalView<'a>),
+    Custom(CustomArrayView<'a>),
 }
 
 impl<'a> TypedArrayView<'a> {
@@ -123,9 +978,14 @@ impl<'a> TypedArrayView<'a> {
             TypedArrayView::Int32(arr) => arr.len(),
             TypedArrayView::UInt64(arr) => arr.len(),
             TypedArrayView::Int64(arr) => arr.len(),
+            TypedArrayView::Float16(arr) => arr.len(),
             TypedArrayView::Float32(arr) => arr.len(),
             TypedArrayView::Float64(arr) => arr.len(),
             TypedArrayView::Bool(arr) => arr.len(),
+            TypedArrayView::TimestampMillis(arr) | TypedArrayView::TimestampMicros(arr) => arr.len(),
+            TypedArrayView::Utf8(arr) => arr.len(),
+            TypedArrayView::Categorical(arr) => arr.len(),
+            TypedArrayView::Custom(arr) => arr.len(),
         }
     }
 
@@ -134,6 +994,38 @@ impl<'a> TypedArrayView<'a> {
         self.len() == 0
     }
 
+    /// Get the element at `index` as `f64`, booleans as `1.0`/`0.0` and
+    /// timestamps as their ticks.
+    ///
+    /// Returns `None` for strings, categories and custom dtypes without a
+    /// numeric value.
+    /// 64-bit integers beyond 2^53 are rounded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn get_f64(&self, index: usize) -> Option<f64> {
+        Some(match self {
+            TypedArrayView::UInt8(arr) => arr[index] as f64,
+            TypedArrayView::Int8(arr) => arr[index] as f64,
+            TypedArrayView::UInt16(arr) => arr[index] as f64,
+            TypedArrayView::Int16(arr) => arr[index] as f64,
+            TypedArrayView::UInt32(arr) => arr[index] as f64,
+            TypedArrayView::Int32(arr) => arr[index] as f64,
+            TypedArrayView::UInt64(arr) => arr[index] as f64,
+            TypedArrayView::Int64(arr) => arr[index] as f64,
+            TypedArrayView::Float16(arr) => arr[index].to_f64(),
+            TypedArrayView::Float32(arr) => arr[index] as f64,
+            TypedArrayView::Float64(arr) => arr[index],
+            TypedArrayView::Bool(arr) => (arr[index] != 0) as u8 as f64,
+            TypedArrayView::TimestampMillis(arr) | TypedArrayView::TimestampMicros(arr) => {
+                arr[index] as f64
+            }
+            TypedArrayView::Utf8(_) | TypedArrayView::Categorical(_) => return None,
+            TypedArrayView::Custom(arr) => return arr.to_f64(index),
+        })
+    }
+
     /// Get the dtype.
     pub fn dtype(&self) -> DType {
         match self {
@@ -145,9 +1037,292 @@ impl<'a> TypedArrayView<'a> {
             TypedArrayView::Int32(_) => DType::Int32,
             TypedArrayView::UInt64(_) => DType::UInt64,
             TypedArrayView::Int64(_) => DType::Int64,
+            TypedArrayView::Float16(_) => DType::Float16,
             TypedArrayView::Float32(_) => DType::Float32,
             TypedArrayView::Float64(_) => DType::Float64,
             TypedArrayView::Bool(_) => DType::Bool,
+            TypedArrayView::TimestampMillis(_) => DType::TimestampMillis,
+            TypedArrayView::TimestampMicros(_) => DType::TimestampMicros,
+            TypedArrayView::Utf8(_) => DType::Utf8,
+            TypedArrayView::Categorical(_) => DType::Categorical,
+            TypedArrayView::Custom(arr) => DType::Custom(arr.def().name),
+        }
+    }
+
+    /// Copy the elements to the heap, see [`TypedArray`].
+    pub fn to_owned_array(&self) -> TypedArray {
+        match self {
+            TypedArrayView::UInt8(arr) => TypedArray::UInt8(arr.to_owned()),
+            TypedArrayView::Int8(arr) => TypedArray::Int8(arr.to_owned()),
+            TypedArrayView::UInt16(arr) => TypedArray::UInt16(arr.to_owned()),
+            TypedArrayView::Int16(arr) => TypedArray::Int16(arr.to_owned()),
+            TypedArrayView::UInt32(arr) => TypedArray::UInt32(arr.to_owned()),
+            TypedArrayView::Int32(arr) => TypedArray::Int32(arr.to_owned()),
+            TypedArrayView::UInt64(arr) => TypedArray::UInt64(arr.to_owned()),
+            TypedArrayView::Int64(arr) => TypedArray::Int64(arr.to_owned()),
+            TypedArrayView::Float16(arr) => TypedArray::Float16(arr.to_owned()),
+            TypedArrayView::Float32(arr) => TypedArray::Float32(arr.to_owned()),
+            TypedArrayView::Float64(arr) => TypedArray::Float64(arr.to_owned()),
+            TypedArrayView::Bool(arr) => TypedArray::Bool(arr.to_owned()),
+            TypedArrayView::TimestampMillis(arr) => TypedArray::TimestampMillis(arr.to_owned()),
+            TypedArrayView::TimestampMicros(arr) => TypedArray::TimestampMicros(arr.to_owned()),
+            TypedArrayView::Utf8(arr) => TypedArray::Utf8(arr.iter().map(String::from).collect()),
+            TypedArrayView::Categorical(arr) => TypedArray::Categorical {
+                codes: arr.codes().to_vec(),
+                categories: arr.categories().to_vec(),
+            },
+            TypedArrayView::Custom(arr) => TypedArray::Custom {
+                dtype: arr.def().name,
+                bytes: arr.as_bytes().to_vec(),
+            },
+        }
+    }
+}
+
+/// Copy `values` into memory.
+fn storage_of<'a, T: bytemuck::Pod>(values: impl ExactSizeIterator<Item = &'a T>) -> Storage {
+    let mut storage = Storage::zeroed(values.len() * size_of::<T>());
+    // Storage in memory is 8-byte aligned, like mappings
+    let slots: &mut [T] = cast_slice_mut(storage.as_mut_slice());
+    for (slot, value) in slots.iter_mut().zip(values) {
+        *slot = *value;
+    }
+    storage
+}
+
+/// Heap copy of a [`TypedArrayView`], independent of the dataset it was
+/// read from, or values for [`crate::Dataset::from_columns`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypedArray {
+    UInt8(Array1<u8>),
+    Int8(Array1<i8>),
+    UInt16(Array1<u16>),
+    Int16(Array1<i16>),
+    UInt32(Array1<u32>),
+    Int32(Array1<i32>),
+    UInt64(Array1<u64>),
+    Int64(Array1<i64>),
+    Float16(Array1<f16>),
+    Float32(Array1<f32>),
+    Float64(Array1<f64>),
+    Bool(Array1<u8>), // Bool stored as u8
+    TimestampMillis(Array1<i64>),
+    TimestampMicros(Array1<i64>),
+    Utf8(Vec<String>),
+    /// Codes of the rows and the labels they index.
+    Categorical { codes: Vec<u32>, categories: Vec<String> },
+    /// Raw bytes of the elements of the registered dtype `dtype`.
+    Custom { dtype: &'static str, bytes: Vec<u8> },
+}
+
+impl TypedArray {
+    /// Get the number of elements.
+    pub fn len(&self) -> usize {
+        match self {
+            TypedArray::UInt8(arr) => arr.len(),
+            TypedArray::Int8(arr) => arr.len(),
+            TypedArray::UInt16(arr) => arr.len(),
+            TypedArray::Int16(arr) => arr.len(),
+            TypedArray::UInt32(arr) => arr.len(),
+            TypedArray::Int32(arr) => arr.len(),
+            TypedArray::UInt64(arr) => arr.len(),
+            TypedArray::Int64(arr) => arr.len(),
+            TypedArray::Float16(arr) => arr.len(),
+            TypedArray::Float32(arr) => arr.len(),
+            TypedArray::Float64(arr) => arr.len(),
+            TypedArray::Bool(arr) => arr.len(),
+            TypedArray::TimestampMillis(arr) | TypedArray::TimestampMicros(arr) => arr.len(),
+            TypedArray::Utf8(strings) => strings.len(),
+            TypedArray::Categorical { codes, .. } => codes.len(),
+            TypedArray::Custom { dtype, bytes } => match registry::lookup(dtype) {
+                Some(def) => bytes.len() / def.size,
+                None => 0,
+            },
         }
     }
+
+    /// Check if empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the dtype.
+    pub fn dtype(&self) -> DType {
+        match self {
+            TypedArray::UInt8(_) => DType::UInt8,
+            TypedArray::Int8(_) => DType::Int8,
+            TypedArray::UInt16(_) => DType::UInt16,
+            TypedArray::Int16(_) => DType::Int16,
+            TypedArray::UInt32(_) => DType::UInt32,
+            TypedArray::Int32(_) => DType::Int32,
+            TypedArray::UInt64(_) => DType::UInt64,
+            TypedArray::Int64(_) => DType::Int64,
+            TypedArray::Float16(_) => DType::Float16,
+            TypedArray::Float32(_) => DType::Float32,
+            TypedArray::Float64(_) => DType::Float64,
+            TypedArray::Bool(_) => DType::Bool,
+            TypedArray::TimestampMillis(_) => DType::TimestampMillis,
+            TypedArray::TimestampMicros(_) => DType::TimestampMicros,
+            TypedArray::Utf8(_) => DType::Utf8,
+            TypedArray::Categorical { .. } => DType::Categorical,
+            TypedArray::Custom { dtype, .. } => DType::Custom(dtype),
+        }
+    }
+}
+
+macro_rules! typed_array_from {
+    ($($t:ty => $variant:ident),*) => {$(
+        impl From<Vec<$t>> for TypedArray {
+            fn from(values: Vec<$t>) -> Self {
+                TypedArray::$variant(Array1::from(values))
+            }
+        }
+    )*};
+}
+
+typed_array_from!(
+    u8 => UInt8, i8 => Int8, u16 => UInt16, i16 => Int16, u32 => UInt32, i32 => Int32,
+    u64 => UInt64, i64 => Int64, f16 => Float16, f32 => Float32, f64 => Float64
+);
+
+impl From<Vec<bool>> for TypedArray {
+    fn from(values: Vec<bool>) -> Self {
+        TypedArray::Bool(values.into_iter().map(u8::from).collect())
+    }
+}
+
+impl From<Vec<String>> for TypedArray {
+    fn from(values: Vec<String>) -> Self {
+        TypedArray::Utf8(values)
+    }
+}
+
+impl From<Vec<&str>> for TypedArray {
+    fn from(values: Vec<&str>) -> Self {
+        TypedArray::Utf8(values.into_iter().map(String::from).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_column(name: &str, values: &[u32]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("mmappet-{}-{}.bin", name, std::process::id()));
+        std::fs::write(&path, bytemuck::cast_slice(values)).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_chunks() {
+        let values: Vec<u32> = (0..10).collect();
+        let path = write_column("chunks", &values);
+        let col = Column::open(&path, DType::UInt32).unwrap();
+
+        let chunks: Vec<&[u32]> = col.chunks::<u32>(4).unwrap().collect();
+        assert_eq!(chunks, vec![&[0, 1, 2, 3][..], &[4, 5, 6, 7], &[8, 9]]);
+        assert!(col.chunks::<f32>(4).is_none());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_float16() {
+        let values = [f16::from_f32(1.5), f16::from_f32(-0.25), f16::NAN];
+        let path = std::env::temp_dir().join(format!("mmappet-float16-{}.bin", std::process::id()));
+        std::fs::write(&path, bytemuck::cast_slice(&values)).unwrap();
+        let col = Column::open(&path, DType::Float16).unwrap();
+
+        assert_eq!(col.len(), 3);
+        let f32s = col.as_f32_array().unwrap();
+        assert_eq!(&f32s.as_slice().unwrap()[..2], &[1.5, -0.25]);
+        assert!(f32s[2].is_nan());
+        let stats = col.stats().unwrap();
+        assert_eq!((stats.min, stats.max), (-0.25, 1.5));
+        assert!(col.as_array::<f32>().is_none());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_slice() {
+        let values: Vec<u32> = (0..10).collect();
+        let path = write_column("slice", &values);
+        let col = Column::open(&path, DType::UInt32).unwrap();
+
+        assert_eq!(col.slice::<u32>(2..5).unwrap().to_vec(), vec![2, 3, 4]);
+        assert_eq!(col.slice::<u32>(8..).unwrap().to_vec(), vec![8, 9]);
+        assert!(col.slice::<f32>(..).is_none());
+        let typed = col.slice_typed_array(..=1);
+        assert_eq!((typed.len(), typed.get_f64(1)), (2, Some(1.0)));
+        assert!(std::panic::catch_unwind(|| col.slice::<u32>(5..11)).is_err());
+
+        assert_eq!(col.search_sorted(4u32), Some(4));
+        assert_eq!(col.search_sorted(20u32), Some(10));
+        assert_eq!(col.search_sorted(4.0f32), None);
+
+        assert_eq!(col.take::<u32>(&[9, 0, 9]).unwrap().to_vec(), vec![9, 0, 9]);
+        assert!(matches!(
+            col.take::<u32>(&[3, 10]),
+            Err(MmappetError::InvalidSelection { .. })
+        ));
+        assert!(matches!(
+            col.take::<i32>(&[]),
+            Err(MmappetError::TypeMismatch { .. })
+        ));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_to_owned() {
+        let values: Vec<u32> = (0..10).collect();
+        let path = write_column("owned", &values);
+        let col = Column::open(&path, DType::UInt32).unwrap();
+
+        assert!(col.to_vec::<i32>().is_none());
+        let copy = col.to_vec::<u32>().unwrap();
+        let typed = col.slice_typed_array(8..).to_owned_array();
+        drop(col);
+        std::fs::remove_file(path).unwrap();
+        let sum = std::thread::spawn(move || copy.iter().sum::<u32>()).join().unwrap();
+        assert_eq!(sum, 45);
+        assert_eq!(typed, TypedArray::UInt32(Array1::from(vec![8, 9])));
+        assert_eq!((typed.len(), typed.dtype()), (2, DType::UInt32));
+
+        let strings = StrArrayView::new(0, &[2, 5], b"abcde");
+        let owned = TypedArrayView::Utf8(strings).to_owned_array();
+        assert_eq!(owned, TypedArray::Utf8(vec!["ab".to_string(), "cde".to_string()]));
+    }
+
+    #[test]
+    fn test_lock_resident() {
+        let values: Vec<u32> = (0..1000).collect();
+        let path = write_column("lock", &values);
+        let col = Column::open(&path, DType::UInt32).unwrap();
+
+        col.lock_resident().unwrap();
+        assert_eq!(col.resident_bytes().unwrap(), Some(4000));
+        col.unlock().unwrap();
+        assert_eq!(col.as_slice::<u32>().unwrap()[999], 999);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_chunks_with_readahead() {
+        let values: Vec<u32> = (0..100_000).collect();
+        let path = write_column("readahead", &values);
+        let col = Column::open(&path, DType::UInt32).unwrap();
+
+        let sum: u64 = col
+            .chunks::<u32>(4096)
+            .unwrap()
+            .with_readahead(16_384)
+            .flat_map(|chunk| chunk.iter().map(|&x| x as u64))
+            .sum();
+        assert_eq!(sum, values.iter().map(|&x| x as u64).sum::<u64>());
+
+        std::fs::remove_file(path).unwrap();
+    }
 }
@@ -0,0 +1,175 @@
+//! Generations, letting readers open a dataset while rows are appended to it.
+//!
+//! A dataset that was appended to has a `COMMIT` file holding the number of
+//! rows readers may see and a generation counted up by every append. The
+//! single writer replaces it once the new rows are on disk, so readers that
+//! open the dataset mid-append see the rows of the last generation, however
+//! much of the next one has been written.
+
+use std::fs;
+use std::path::Path;
+
+use crate::dataset::Dataset;
+use crate::error::{MmappetError, Result};
+
+const COMMIT_FILE: &str = "COMMIT";
+
+/// Contents of the `COMMIT` file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Commit {
+    pub(crate) generation: u64,
+    pub(crate) rows: usize,
+}
+
+impl Commit {
+    /// Read the commit of the dataset in `dir`, if it has one.
+    pub(crate) fn read(dir: &Path) -> Result<Option<Self>> {
+        let path = dir.join(COMMIT_FILE);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(MmappetError::io(&path, e)),
+        };
+        let (mut generation, mut rows) = (None, None);
+        for (i, line) in content.lines().enumerate() {
+            let invalid = |message: &str| MmappetError::InvalidMetadata {
+                path: path.clone(),
+                line: Some(i + 1),
+                message: message.to_string(),
+            };
+            match line.split_once('=') {
+                Some(("generation", value)) => {
+                    generation = Some(value.parse().map_err(|_| invalid("invalid generation"))?)
+                }
+                Some(("rows", value)) => {
+                    rows = Some(value.parse().map_err(|_| invalid("invalid row count"))?)
+                }
+                _ => return Err(invalid("expected generation=... or rows=...")),
+            }
+        }
+        match (generation, rows) {
+            (Some(generation), Some(rows)) => Ok(Some(Commit { generation, rows })),
+            _ => Err(MmappetError::InvalidMetadata {
+                path,
+                line: None,
+                message: "missing generation or row count".to_string(),
+            }),
+        }
+    }
+
+    /// Replace the commit of the dataset in `dir`, so readers see either the
+    /// old or the new one.
+    pub(crate) fn write(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(COMMIT_FILE);
+        let tmp_path = dir.join(format!("{}.tmp", COMMIT_FILE));
+        let content = format!("generation={}\nrows={}\n", self.generation, self.rows);
+        fs::File::create(&tmp_path)
+            .and_then(|mut file| {
+                std::io::Write::write_all(&mut file, content.as_bytes())?;
+                file.sync_all()
+            })
+            .map_err(|e| MmappetError::io(&tmp_path, e))?;
+        fs::rename(&tmp_path, &path).map_err(|e| MmappetError::io(&path, e))
+    }
+
+    /// Remove the commit of the dataset in `dir`, if any, e.g. when the
+    /// dataset is written anew.
+    pub(crate) fn remove(dir: &Path) -> Result<()> {
+        let path = dir.join(COMMIT_FILE);
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(MmappetError::io(&path, e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Dataset {
+    /// Number of appends committed to the dataset when it was opened, 0 for
+    /// datasets never appended to.
+    pub fn generation(&self) -> u64 {
+        self.commit().map_or(0, |commit| commit.generation)
+    }
+
+    /// Reopen the dataset if rows were committed since it was opened, e.g.
+    /// by another process appending to it. Returns whether it was reopened.
+    ///
+    /// ```rust,no_run
+    /// let mut ds = mmappet::Dataset::open("acquisition.mmappet")?;
+    /// loop {
+    ///     if ds.refresh()? {
+    ///         println!("{} rows", ds.len());
+    ///     }
+    ///     std::thread::sleep(std::time::Duration::from_millis(100));
+    /// }
+    /// # Ok::<(), mmappet::MmappetError>(())
+    /// ```
+    pub fn refresh(&mut self) -> Result<bool> {
+        if Commit::read(self.path())? == self.commit() {
+            return Ok(false);
+        }
+        *self = self.reopen()?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    use ndarray::ArrayView1;
+
+    use super::*;
+    use crate::budget::{BudgetedDataset, MemoryBudget};
+    use crate::column::TypedArrayView;
+    use crate::dtype::DType;
+    use crate::schema::Schema;
+    use crate::strings::StrArrayView;
+    use crate::writer::DatasetWriter;
+
+    #[test]
+    fn test_reader_snapshot() {
+        let dir = std::env::temp_dir().join(format!("mmappet-commit-{}", std::process::id()));
+        let schema = Schema::new(&[("tof", DType::UInt32), ("name", DType::Utf8)]).unwrap();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write_opt("tof", &[Some(1u32), None]).unwrap();
+        writer.write_str("name", &["ab", "c"]).unwrap();
+        writer.finish().unwrap();
+
+        let mut reader = Dataset::open(&dir).unwrap();
+        assert_eq!(reader.generation(), 0);
+        let mut ds = Dataset::open(&dir).unwrap();
+        ds.append_batch(&[
+            ("tof", TypedArrayView::UInt32(ArrayView1::from(&[3u32]))),
+            ("name", TypedArrayView::Utf8(StrArrayView::new(0, &[1], b"d"))),
+        ])
+        .unwrap();
+        assert_eq!(ds.generation(), 1);
+        assert_eq!(reader.len(), 2);
+        assert!(reader.refresh().unwrap());
+        assert!(!reader.refresh().unwrap());
+        assert_eq!(reader.len(), 3);
+        assert_eq!(reader.get::<u32>("tof").unwrap(), &[1, 0, 3]);
+
+        // Readers opening mid-append see the last generation
+        let append = |file: &str, bytes: &[u8]| {
+            let mut file = OpenOptions::new().append(true).open(dir.join(file)).unwrap();
+            file.write_all(bytes).unwrap();
+        };
+        append("0.bin", &[7, 0]);
+        append("1.str", b"efg");
+        append("1.bin", &6u64.to_ne_bytes());
+        append("0.valid", &[0xff]);
+        let snapshot = Dataset::open(&dir).unwrap();
+        assert_eq!(snapshot.len(), 3);
+        assert_eq!(snapshot.get::<u32>("tof").unwrap(), &[1, 0, 3]);
+        assert_eq!(snapshot.column("name").unwrap().len(), 3);
+        assert!(!snapshot.nullable("tof").unwrap().is_valid(1));
+        let budgeted = BudgetedDataset::open(&dir, &MemoryBudget::new(1 << 20)).unwrap();
+        assert_eq!(budgeted.len(), 3);
+        assert_eq!(budgeted.to_vec::<u32>("tof").unwrap(), [1, 0, 3]);
+        assert_eq!(budgeted.column("name").unwrap().len(), 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,235 @@
+//! Block-compressed column files.
+//!
+//! A column written with `DatasetWriter::compress` (`zstd` feature) is stored
+//! in `{index}.bin.zst` instead of `{index}.bin`, as independent zstd frames
+//! of fixed-size blocks and an index of where each one starts:
+//!
+//! ```text
+//! magic    b"MMPZ"
+//! version  u32, 1
+//! block    u64, uncompressed bytes per block, the last one may be shorter
+//! length   u64, uncompressed bytes of the column
+//! offsets  u64 per block plus one: start of each frame after the offsets,
+//!          the last one the end of the file
+//! frames   one zstd frame per block
+//! ```
+//!
+//! Integers are little-endian. A compressed column is decompressed into
+//! memory when first accessed and is read-only. Reading one needs the `zstd`
+//! feature.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::error::{MmappetError, Result};
+
+const MAGIC: &[u8; 4] = b"MMPZ";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 24;
+
+/// Uncompressed bytes per block, unless given otherwise.
+#[cfg(feature = "zstd")]
+pub(crate) const BLOCK_SIZE: usize = 1 << 20;
+
+/// Path of the compressed file of the column file `path`.
+pub(crate) fn compressed_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".zst");
+    PathBuf::from(name)
+}
+
+/// The compressed file standing in for the column file `path`, if `path`
+/// doesn't exist but its compressed file does.
+pub(crate) fn compressed_file(path: &Path) -> Option<PathBuf> {
+    if path.exists() {
+        return None;
+    }
+    let compressed = compressed_path(path);
+    compressed.exists().then_some(compressed)
+}
+
+/// Block size and uncompressed length of a compressed file.
+struct Header {
+    // Only needed to decompress
+    #[cfg_attr(not(feature = "zstd"), allow(dead_code))]
+    block_size: usize,
+    len: usize,
+}
+
+impl Header {
+    fn parse(path: &Path, bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+            return Err(invalid(path, "not a compressed column file"));
+        }
+        let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        if version != VERSION {
+            return Err(invalid(path, &format!("unknown version {}", version)));
+        }
+        let block_size = read_u64(bytes, 8) as usize;
+        let len = read_u64(bytes, 16) as usize;
+        if block_size == 0 {
+            return Err(invalid(path, "block size is zero"));
+        }
+        Ok(Header { block_size, len })
+    }
+
+    #[cfg(feature = "zstd")]
+    fn blocks(&self) -> usize {
+        self.len.div_ceil(self.block_size)
+    }
+}
+
+/// Uncompressed length in bytes of the compressed file `path`, reading only
+/// its header.
+pub(crate) fn uncompressed_len(path: &Path) -> Result<usize> {
+    let mut bytes = [0u8; HEADER_LEN];
+    let mut file = File::open(path).map_err(|e| MmappetError::io(path, e))?;
+    file.read_exact(&mut bytes)
+        .map_err(|_| invalid(path, "truncated header"))?;
+    Ok(Header::parse(path, &bytes)?.len)
+}
+
+/// Decompress the compressed file `path` into `out`, which must have its
+/// uncompressed length.
+#[cfg(feature = "zstd")]
+pub(crate) fn decompress(path: &Path, out: &mut [u8]) -> Result<()> {
+    let bytes = std::fs::read(path).map_err(|e| MmappetError::io(path, e))?;
+    let header = Header::parse(path, &bytes)?;
+    assert_eq!(out.len(), header.len, "output must have the uncompressed length");
+
+    let blocks = header.blocks();
+    let data_start = HEADER_LEN + 8 * (blocks + 1);
+    if bytes.len() < data_start {
+        return Err(invalid(path, "truncated block index"));
+    }
+    let offsets: Vec<usize> = (0..=blocks)
+        .map(|i| read_u64(&bytes, HEADER_LEN + 8 * i) as usize)
+        .collect();
+    let data = &bytes[data_start..];
+    if offsets[0] != 0
+        || offsets.windows(2).any(|w| w[0] > w[1])
+        || offsets[blocks] != data.len()
+    {
+        return Err(invalid(path, "block index doesn't match the file size"));
+    }
+
+    for (block, out) in out.chunks_mut(header.block_size).enumerate() {
+        let frame = &data[offsets[block]..offsets[block + 1]];
+        let written = zstd::bulk::decompress_to_buffer(frame, out)
+            .map_err(|e| invalid(path, &format!("block {}: {}", block, e)))?;
+        if written != out.len() {
+            return Err(invalid(
+                path,
+                &format!("block {} holds {} bytes, expected {}", block, written, out.len()),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "zstd"))]
+pub(crate) fn decompress(path: &Path, _out: &mut [u8]) -> Result<()> {
+    Err(invalid(path, "reading it needs mmappet's `zstd` feature"))
+}
+
+/// Replace the column file `path` by its compressed file, compressing blocks
+/// of `block_size` bytes at zstd `level`.
+#[cfg(feature = "zstd")]
+pub(crate) fn compress_file(path: &Path, level: i32, block_size: usize) -> Result<()> {
+    let raw = std::fs::read(path).map_err(|e| MmappetError::io(path, e))?;
+    let target = compressed_path(path);
+
+    let mut frames = Vec::new();
+    let mut offsets = vec![0u64];
+    for block in raw.chunks(block_size) {
+        let frame = zstd::bulk::compress(block, level).map_err(|e| MmappetError::io(path, e))?;
+        frames.extend_from_slice(&frame);
+        offsets.push(frames.len() as u64);
+    }
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + 8 * offsets.len() + frames.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(block_size as u64).to_le_bytes());
+    bytes.extend_from_slice(&(raw.len() as u64).to_le_bytes());
+    for offset in offsets {
+        bytes.extend_from_slice(&offset.to_le_bytes());
+    }
+    bytes.extend_from_slice(&frames);
+
+    std::fs::write(&target, bytes).map_err(|e| MmappetError::io(&target, e))?;
+    std::fs::remove_file(path).map_err(|e| MmappetError::io(path, e))
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+fn invalid(path: &Path, message: &str) -> MmappetError {
+    MmappetError::InvalidEncoding {
+        path: path.to_path_buf(),
+        message: message.to_string(),
+    }
+}
+
+#[cfg(all(test, feature = "zstd"))]
+mod tests {
+    use super::*;
+    use crate::column::{Column, TypedArrayView};
+    use crate::dataset::Dataset;
+    use crate::dtype::DType;
+    use crate::schema::Schema;
+    use crate::test_util::write_dataset;
+    use crate::writer::DatasetWriter;
+    use ndarray::ArrayView1;
+
+    #[test]
+    fn test_compressed_column() {
+        let dir = std::env::temp_dir().join(format!("mmappet-compressed-{}", std::process::id()));
+        let schema = Schema::new(&[("frame", DType::UInt32), ("mz", DType::Float64)]).unwrap();
+        let frames: Vec<u32> = (0..1000).map(|i| i / 10).collect();
+        let mz: Vec<f64> = (0..1000).map(|i| 400.0 + i as f64 / 8.0).collect();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.compress("frame", 3).unwrap();
+        assert!(writer.compress("scan", 3).is_err());
+        writer.write("frame", &frames).unwrap();
+        writer.write("mz", &mz).unwrap();
+        assert_eq!(writer.finish().unwrap(), 1000);
+        assert!(!dir.join("0.bin").exists());
+        assert!(std::fs::metadata(dir.join("0.bin.zst")).unwrap().len() < 4000 / 5);
+
+        let mut ds = Dataset::open_mut(&dir).unwrap();
+        assert_eq!(ds.get::<u32>("frame").unwrap(), &frames[..]);
+        assert!(matches!(ds.get_mut::<u32>("frame"), Err(MmappetError::ReadOnly { .. })));
+        ds.get_mut::<f64>("mz").unwrap()[0] = 1.0;
+        let batch = [
+            ("frame", TypedArrayView::UInt32(ArrayView1::from(&[100][..]))),
+            ("mz", TypedArrayView::Float64(ArrayView1::from(&[1.0][..]))),
+        ];
+        assert!(matches!(ds.append_batch(&batch), Err(MmappetError::ReadOnly { .. })));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_compressed_blocks() {
+        let values: Vec<u32> = (0..100).collect();
+        let dir = write_dataset("compressed-blocks", &[("tof", &values)]);
+        let path = dir.join("0.bin");
+        compress_file(&path, 1, 64).unwrap();
+        assert_eq!(Column::file_len(&path, DType::UInt32, 1).unwrap(), 100);
+        assert_eq!(Column::open(&path, DType::UInt32).unwrap().as_slice::<u32>().unwrap(), &values[..]);
+
+        let compressed = compressed_path(&path);
+        let mut bytes = std::fs::read(&compressed).unwrap();
+        bytes.truncate(bytes.len() - 1);
+        std::fs::write(&compressed, &bytes).unwrap();
+        assert!(matches!(
+            Column::open(&path, DType::UInt32),
+            Err(MmappetError::InvalidEncoding { .. })
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
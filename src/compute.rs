@@ -0,0 +1,452 @@
+//! Element-wise kernels over typed arrays: arithmetic, scaling, logarithms,
+//! clipping and comparisons.
+//!
+//! Kernels take [`TypedArrayView`]s, e.g. from [`Column::as_typed_array`],
+//! and return owned [`TypedArray`]s, ready for
+//! [`DatasetWriter::write_array`] or [`Dataset::from_columns`], or masks
+//! for [`Dataset::filter`].
+//!
+//! ```rust,no_run
+//! use mmappet::compute::{self, CmpOp};
+//!
+//! let ds = mmappet::Dataset::open("peaks.mmappet")?;
+//! let log_intensity = compute::log(&ds["intensity"].as_typed_array())?;
+//! let mask = compute::compare_scalar(&ds["intensity"].as_typed_array(), CmpOp::Gt, 1e5)?;
+//! let bright = ds.filter(&mask)?;
+//! # Ok::<(), mmappet::MmappetError>(())
+//! ```
+//!
+//! [`Column::as_typed_array`]: crate::Column::as_typed_array
+//! [`DatasetWriter::write_array`]: crate::DatasetWriter::write_array
+//! [`Dataset::from_columns`]: crate::Dataset::from_columns
+//! [`Dataset::filter`]: crate::Dataset::filter
+
+use half::f16;
+use ndarray::{Array1, ArrayView1};
+
+use crate::column::{TypedArray, TypedArrayView};
+use crate::error::{MmappetError, Result};
+use crate::expr::read_f64;
+use crate::stats::STATS_CHUNK_ROWS;
+
+/// Comparison operators of [`compare`] and [`compare_scalar`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CmpOp {
+    fn test<T: PartialOrd>(self, a: T, b: T) -> bool {
+        match self {
+            CmpOp::Eq => a == b,
+            CmpOp::Ne => a != b,
+            CmpOp::Lt => a < b,
+            CmpOp::Le => a <= b,
+            CmpOp::Gt => a > b,
+            CmpOp::Ge => a >= b,
+        }
+    }
+}
+
+/// Element type of the arithmetic kernels.
+trait Number: Copy + PartialOrd {
+    fn add(self, other: Self) -> Self;
+    fn mul(self, other: Self) -> Self;
+    /// `x` as a lower (`up`) or upper bound, rounded inwards and saturated
+    /// for integers.
+    fn bound(x: f64, up: bool) -> Self;
+}
+
+macro_rules! int_number {
+    ($($t:ty),*) => {$(
+        impl Number for $t {
+            fn add(self, other: Self) -> Self {
+                self.wrapping_add(other)
+            }
+            fn mul(self, other: Self) -> Self {
+                self.wrapping_mul(other)
+            }
+            fn bound(x: f64, up: bool) -> Self {
+                if up { x.ceil() as $t } else { x.floor() as $t }
+            }
+        }
+    )*};
+}
+
+int_number!(u8, i8, u16, i16, u32, i32, u64, i64);
+
+macro_rules! float_number {
+    ($($t:ty),*) => {$(
+        impl Number for $t {
+            fn add(self, other: Self) -> Self {
+                self + other
+            }
+            fn mul(self, other: Self) -> Self {
+                self * other
+            }
+            fn bound(x: f64, _up: bool) -> Self {
+                x as $t
+            }
+        }
+    )*};
+}
+
+float_number!(f32, f64);
+
+impl Number for f16 {
+    fn add(self, other: Self) -> Self {
+        f16::from_f32(self.to_f32() + other.to_f32())
+    }
+    fn mul(self, other: Self) -> Self {
+        f16::from_f32(self.to_f32() * other.to_f32())
+    }
+    fn bound(x: f64, _up: bool) -> Self {
+        f16::from_f64(x)
+    }
+}
+
+/// Apply `$f` to the pairs of values of two arrays of the same numeric
+/// dtype, or evaluate `$other` if the dtypes differ or aren't numeric.
+macro_rules! zip_numeric {
+    ($a:expr, $b:expr, $f:expr, $other:expr) => {
+        match ($a, $b) {
+            (TypedArrayView::UInt8(a), TypedArrayView::UInt8(b)) => {
+                TypedArray::UInt8(zip(a, b, $f))
+            }
+            (TypedArrayView::Int8(a), TypedArrayView::Int8(b)) => TypedArray::Int8(zip(a, b, $f)),
+            (TypedArrayView::UInt16(a), TypedArrayView::UInt16(b)) => {
+                TypedArray::UInt16(zip(a, b, $f))
+            }
+            (TypedArrayView::Int16(a), TypedArrayView::Int16(b)) => {
+                TypedArray::Int16(zip(a, b, $f))
+            }
+            (TypedArrayView::UInt32(a), TypedArrayView::UInt32(b)) => {
+                TypedArray::UInt32(zip(a, b, $f))
+            }
+            (TypedArrayView::Int32(a), TypedArrayView::Int32(b)) => {
+                TypedArray::Int32(zip(a, b, $f))
+            }
+            (TypedArrayView::UInt64(a), TypedArrayView::UInt64(b)) => {
+                TypedArray::UInt64(zip(a, b, $f))
+            }
+            (TypedArrayView::Int64(a), TypedArrayView::Int64(b)) => {
+                TypedArray::Int64(zip(a, b, $f))
+            }
+            (TypedArrayView::Float16(a), TypedArrayView::Float16(b)) => {
+                TypedArray::Float16(zip(a, b, $f))
+            }
+            (TypedArrayView::Float32(a), TypedArrayView::Float32(b)) => {
+                TypedArray::Float32(zip(a, b, $f))
+            }
+            (TypedArrayView::Float64(a), TypedArrayView::Float64(b)) => {
+                TypedArray::Float64(zip(a, b, $f))
+            }
+            _ => $other,
+        }
+    };
+}
+
+fn zip<T: Copy, U>(
+    a: &ArrayView1<'_, T>,
+    b: &ArrayView1<'_, T>,
+    f: impl Fn(T, T) -> U,
+) -> Array1<U> {
+    a.iter().zip(b).map(|(&x, &y)| f(x, y)).collect()
+}
+
+/// Element-wise sum of two arrays of the same numeric dtype, which the
+/// result keeps. Integers wrap around on overflow.
+///
+/// Returns an error if the lengths or dtypes differ, or the dtype isn't an
+/// integer or float one.
+pub fn add(a: &TypedArrayView<'_>, b: &TypedArrayView<'_>) -> Result<TypedArray> {
+    check_lengths(a, b)?;
+    Ok(zip_numeric!(
+        a,
+        b,
+        Number::add,
+        return Err(arithmetic_error(a, b, "added"))
+    ))
+}
+
+/// Element-wise product of two arrays of the same numeric dtype, which the
+/// result keeps. Integers wrap around on overflow.
+///
+/// Returns an error if the lengths or dtypes differ, or the dtype isn't an
+/// integer or float one.
+pub fn mul(a: &TypedArrayView<'_>, b: &TypedArrayView<'_>) -> Result<TypedArray> {
+    check_lengths(a, b)?;
+    Ok(zip_numeric!(
+        a,
+        b,
+        Number::mul,
+        return Err(arithmetic_error(a, b, "multiplied"))
+    ))
+}
+
+/// `x * factor + offset` for every value `x`, e.g. to calibrate raw values.
+///
+/// `float32` arrays stay `float32`; all others become `float64`. Returns an
+/// error if the array has no numeric values.
+pub fn scale(array: &TypedArrayView<'_>, factor: f64, offset: f64) -> Result<TypedArray> {
+    map_float(array, "scaled", |x| x * factor + offset)
+}
+
+/// Natural logarithm of every value: NaN for negative values, `-inf` for
+/// zeros.
+///
+/// `float32` arrays stay `float32`; all others become `float64`. Returns an
+/// error if the array has no numeric values.
+pub fn log(array: &TypedArrayView<'_>) -> Result<TypedArray> {
+    map_float(array, "logged", f64::ln)
+}
+
+/// Every value limited to `min..=max`, keeping the dtype. NaNs stay NaN;
+/// integer bounds are rounded inwards.
+///
+/// Returns an error if the dtype isn't an integer or float one.
+///
+/// # Panics
+///
+/// Panics if `min > max` or either is NaN.
+pub fn clip(array: &TypedArrayView<'_>, min: f64, max: f64) -> Result<TypedArray> {
+    assert!(min <= max, "clip bounds {}..={} are empty", min, max);
+    fn clip<T: Number>(array: &ArrayView1<'_, T>, min: f64, max: f64) -> Array1<T> {
+        let (min, max) = (T::bound(min, true), T::bound(max, false));
+        array
+            .iter()
+            .map(|&x| match x {
+                x if x < min => min,
+                x if x > max => max,
+                x => x,
+            })
+            .collect()
+    }
+
+    Ok(match array {
+        TypedArrayView::UInt8(a) => TypedArray::UInt8(clip(a, min, max)),
+        TypedArrayView::Int8(a) => TypedArray::Int8(clip(a, min, max)),
+        TypedArrayView::UInt16(a) => TypedArray::UInt16(clip(a, min, max)),
+        TypedArrayView::Int16(a) => TypedArray::Int16(clip(a, min, max)),
+        TypedArrayView::UInt32(a) => TypedArray::UInt32(clip(a, min, max)),
+        TypedArrayView::Int32(a) => TypedArray::Int32(clip(a, min, max)),
+        TypedArrayView::UInt64(a) => TypedArray::UInt64(clip(a, min, max)),
+        TypedArrayView::Int64(a) => TypedArray::Int64(clip(a, min, max)),
+        TypedArrayView::Float16(a) => TypedArray::Float16(clip(a, min, max)),
+        TypedArrayView::Float32(a) => TypedArray::Float32(clip(a, min, max)),
+        TypedArrayView::Float64(a) => TypedArray::Float64(clip(a, min, max)),
+        _ => return Err(unsupported(array, "clipped")),
+    })
+}
+
+/// Mask of the positions where `a op b` holds.
+///
+/// Arrays of the same dtype are compared exactly, others as `f64`, so
+/// 64-bit integers beyond 2^53 lose precision. NaNs only satisfy
+/// [`CmpOp::Ne`]. Returns an error if the lengths differ or an array has no
+/// numeric values.
+pub fn compare(a: &TypedArrayView<'_>, op: CmpOp, b: &TypedArrayView<'_>) -> Result<Vec<bool>> {
+    check_lengths(a, b)?;
+    macro_rules! test {
+        ($a:expr, $b:expr) => {
+            zip($a, $b, |x, y| op.test(x, y))
+        };
+    }
+    let mask = match (a, b) {
+        (TypedArrayView::UInt8(a), TypedArrayView::UInt8(b))
+        | (TypedArrayView::Bool(a), TypedArrayView::Bool(b)) => test!(a, b),
+        (TypedArrayView::Int8(a), TypedArrayView::Int8(b)) => test!(a, b),
+        (TypedArrayView::UInt16(a), TypedArrayView::UInt16(b)) => test!(a, b),
+        (TypedArrayView::Int16(a), TypedArrayView::Int16(b)) => test!(a, b),
+        (TypedArrayView::UInt32(a), TypedArrayView::UInt32(b)) => test!(a, b),
+        (TypedArrayView::Int32(a), TypedArrayView::Int32(b)) => test!(a, b),
+        (TypedArrayView::UInt64(a), TypedArrayView::UInt64(b)) => test!(a, b),
+        (TypedArrayView::Int64(a), TypedArrayView::Int64(b))
+        | (TypedArrayView::TimestampMillis(a), TypedArrayView::TimestampMillis(b))
+        | (TypedArrayView::TimestampMicros(a), TypedArrayView::TimestampMicros(b)) => {
+            test!(a, b)
+        }
+        (TypedArrayView::Float16(a), TypedArrayView::Float16(b)) => test!(a, b),
+        (TypedArrayView::Float32(a), TypedArrayView::Float32(b)) => test!(a, b),
+        (TypedArrayView::Float64(a), TypedArrayView::Float64(b)) => test!(a, b),
+        _ => {
+            let (a_values, b_values) = (numeric(a, "compared")?, numeric(b, "compared")?);
+            let mut mask = Vec::with_capacity(a.len());
+            let mut x = vec![0.0; STATS_CHUNK_ROWS.min(a.len())];
+            let mut y = x.clone();
+            for start in (0..a.len()).step_by(STATS_CHUNK_ROWS) {
+                let rows = STATS_CHUNK_ROWS.min(a.len() - start);
+                read_f64(a_values, start, &mut x[..rows]);
+                read_f64(b_values, start, &mut y[..rows]);
+                mask.extend(
+                    x[..rows]
+                        .iter()
+                        .zip(&y[..rows])
+                        .map(|(&x, &y)| op.test(x, y)),
+                );
+            }
+            return Ok(mask);
+        }
+    };
+    Ok(mask.to_vec())
+}
+
+/// Mask of the positions where `value op scalar` holds, comparing as `f64`.
+///
+/// 64-bit integers beyond 2^53 lose precision; NaNs only satisfy
+/// [`CmpOp::Ne`]. Returns an error if the array has no numeric values.
+pub fn compare_scalar(array: &TypedArrayView<'_>, op: CmpOp, scalar: f64) -> Result<Vec<bool>> {
+    let array = numeric(array, "compared")?;
+    let mut mask = Vec::with_capacity(array.len());
+    let mut values = vec![0.0; STATS_CHUNK_ROWS.min(array.len())];
+    for start in (0..array.len()).step_by(STATS_CHUNK_ROWS) {
+        let values = &mut values[..STATS_CHUNK_ROWS.min(array.len() - start)];
+        read_f64(array, start, values);
+        mask.extend(values.iter().map(|&x| op.test(x, scalar)));
+    }
+    Ok(mask)
+}
+
+/// Apply `f` to every value as `f64`, keeping `float32` arrays `float32`.
+fn map_float(array: &TypedArrayView<'_>, verb: &str, f: impl Fn(f64) -> f64) -> Result<TypedArray> {
+    if let TypedArrayView::Float32(a) = array {
+        return Ok(TypedArray::Float32(
+            a.iter().map(|&x| f(x as f64) as f32).collect(),
+        ));
+    }
+    let array = numeric(array, verb)?;
+    let mut out = vec![0.0; array.len()];
+    for start in (0..array.len()).step_by(STATS_CHUNK_ROWS) {
+        let chunk = &mut out[start..(start + STATS_CHUNK_ROWS).min(array.len())];
+        read_f64(array, start, chunk);
+        chunk.iter_mut().for_each(|x| *x = f(*x));
+    }
+    Ok(out.into())
+}
+
+/// `array` if its values convert to `f64`.
+fn numeric<'a, 'b>(array: &'a TypedArrayView<'b>, verb: &str) -> Result<&'a TypedArrayView<'b>> {
+    match array {
+        TypedArrayView::Custom(custom) if custom.def().to_f64.is_some() => Ok(array),
+        TypedArrayView::Utf8(_) | TypedArrayView::Categorical(_) | TypedArrayView::Custom(_) => {
+            Err(unsupported(array, verb))
+        }
+        _ => Ok(array),
+    }
+}
+
+fn check_lengths(a: &TypedArrayView<'_>, b: &TypedArrayView<'_>) -> Result<()> {
+    if a.len() == b.len() {
+        return Ok(());
+    }
+    Err(MmappetError::InvalidBatch {
+        path: None,
+        message: format!(
+            "arrays of {} and {} values can't be combined",
+            a.len(),
+            b.len()
+        ),
+    })
+}
+
+fn arithmetic_error(a: &TypedArrayView<'_>, b: &TypedArrayView<'_>, verb: &str) -> MmappetError {
+    if a.dtype() != b.dtype() {
+        return MmappetError::TypeMismatch {
+            column: None,
+            expected: a.dtype(),
+            actual: b.dtype(),
+        };
+    }
+    unsupported(a, verb)
+}
+
+fn unsupported(array: &TypedArrayView<'_>, verb: &str) -> MmappetError {
+    MmappetError::InvalidDType {
+        dtype: array.dtype().to_string(),
+        message: format!("values can't be {}", verb),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::Dataset;
+    use crate::dtype::DType;
+    use crate::schema::Schema;
+    use crate::writer::DatasetWriter;
+
+    #[test]
+    fn test_kernels() {
+        let ds = Dataset::from_columns(vec![
+            ("a", vec![1u8, 200, 3].into()),
+            ("b", vec![2u8, 100, 3].into()),
+            ("x", vec![1.0f32, f32::NAN, 100.0].into()),
+            ("t", vec![1i64, 2, 3].into()),
+            ("s", vec!["a", "b", "c"].into()),
+        ])
+        .unwrap();
+        let (a, b, x) = (
+            ds["a"].as_typed_array(),
+            ds["b"].as_typed_array(),
+            ds["x"].as_typed_array(),
+        );
+        let (t, s) = (ds["t"].as_typed_array(), ds["s"].as_typed_array());
+
+        assert_eq!(add(&a, &b).unwrap(), vec![3u8, 44, 6].into());
+        assert_eq!(mul(&a, &b).unwrap(), vec![2u8, 32, 9].into());
+        assert!(matches!(
+            add(&a, &t),
+            Err(MmappetError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            add(&s, &s),
+            Err(MmappetError::InvalidDType { .. })
+        ));
+        assert!(add(&a, &ds["b"].slice_typed_array(..2)).is_err());
+
+        assert_eq!(scale(&t, 2.0, 0.5).unwrap(), vec![2.5, 4.5, 6.5].into());
+        let TypedArray::Float32(logged) = log(&x).unwrap() else {
+            panic!("not float32")
+        };
+        assert_eq!((logged[0], logged[2]), (0.0, 100f32.ln()));
+        assert!(logged[1].is_nan());
+        assert!(log(&s).is_err());
+
+        assert_eq!(clip(&a, 1.5, 99.9).unwrap(), vec![2u8, 99, 3].into());
+        let TypedArray::Float32(clipped) = clip(&x, 0.0, 10.0).unwrap() else {
+            panic!("not float32")
+        };
+        assert_eq!((clipped[0], clipped[2]), (1.0, 10.0));
+        assert!(clipped[1].is_nan());
+
+        assert_eq!(compare(&a, CmpOp::Gt, &b).unwrap(), [false, true, false]);
+        assert_eq!(compare(&a, CmpOp::Eq, &t).unwrap(), [true, false, true]);
+        assert_eq!(
+            compare_scalar(&x, CmpOp::Lt, 50.0).unwrap(),
+            [true, false, false]
+        );
+        assert_eq!(
+            compare_scalar(&x, CmpOp::Ne, 1.0).unwrap(),
+            [false, true, true]
+        );
+        assert!(compare_scalar(&s, CmpOp::Eq, 1.0).is_err());
+
+        let dir = std::env::temp_dir().join(format!("mmappet-compute-{}", std::process::id()));
+        let schema = Schema::new(&[("sum", DType::UInt8), ("mz", DType::Float64)]).unwrap();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write_array("sum", &add(&a, &b).unwrap()).unwrap();
+        writer
+            .write_array("mz", &scale(&a, 0.5, 0.0).unwrap())
+            .unwrap();
+        assert!(writer.write_array("mz", &add(&a, &b).unwrap()).is_err());
+        assert_eq!(writer.finish().unwrap(), 3);
+        let written = Dataset::open(&dir).unwrap();
+        assert_eq!(written.get::<f64>("mz").unwrap(), &[0.5, 100.0, 1.5]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
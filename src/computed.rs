@@ -0,0 +1,204 @@
+//! Columns computed from other columns on first access.
+
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::column::{Column, TypedArray};
+use crate::dataset::Dataset;
+use crate::dtype::DType;
+use crate::error::{MmappetError, Result};
+use crate::expr::{read_f64, Expr};
+use crate::stats::STATS_CHUNK_ROWS;
+
+/// Function of the input values of a row, see [`Dataset::with_computed`].
+///
+/// Unwind safe so that [`Dataset`] stays so, e.g. for `catch_unwind`.
+type RowFn = dyn Fn(&[f64]) -> f64 + Send + Sync + RefUnwindSafe + UnwindSafe;
+
+/// How the values of a computed column are derived.
+pub(crate) enum Computed {
+    Expr(Expr),
+    Fn { inputs: Vec<String>, f: Arc<RowFn> },
+}
+
+impl Dataset {
+    /// Add the `float64` column `name`, whose value in each row is `f` of
+    /// the values of the columns `inputs` in that row, in that order.
+    ///
+    /// The column appears in [`Dataset::schema`] and reads like a stored
+    /// one, e.g. with [`Dataset::get`]. Its values are computed on first
+    /// access, chunk by chunk, and then kept in memory (8 bytes per row);
+    /// nothing is written to the dataset directory, but copies such as
+    /// [`FilteredView::write_to`](crate::FilteredView::write_to) store them
+    /// like any other column. Inputs are converted to `f64` and may be
+    /// computed columns themselves. Datasets with computed columns can't be
+    /// appended to.
+    ///
+    /// Returns an error if `name` is invalid or taken, or an input doesn't
+    /// exist, holds arrays or has no numeric values.
+    ///
+    /// ```rust,no_run
+    /// use mmappet::{col, Dataset};
+    ///
+    /// let ds = Dataset::open("peaks.mmappet")?
+    ///     .with_computed("mz_rounded", &["mz"], |x| (x[0] * 100.0).round() / 100.0)?
+    ///     .with_computed_expr("mz_error", col("mz") - col("mz_theoretical"))?;
+    /// let rounded: &[f64] = ds.get("mz_rounded")?;
+    /// # Ok::<(), mmappet::MmappetError>(())
+    /// ```
+    pub fn with_computed(
+        mut self,
+        name: &str,
+        inputs: &[&str],
+        f: impl Fn(&[f64]) -> f64 + Send + Sync + RefUnwindSafe + UnwindSafe + 'static,
+    ) -> Result<Self> {
+        for input in inputs {
+            self.check_computed_input(input)?;
+        }
+        let inputs = inputs.iter().map(|input| input.to_string()).collect();
+        self.push_computed(
+            name,
+            Computed::Fn {
+                inputs,
+                f: Arc::new(f),
+            },
+        )?;
+        Ok(self)
+    }
+
+    /// Add the `float64` column `name` holding the value of `expr` in each
+    /// row, see [`Dataset::with_computed`].
+    ///
+    /// Returns an error if `name` is invalid or taken, or a column of `expr`
+    /// doesn't exist, holds arrays or has no numeric values.
+    pub fn with_computed_expr(mut self, name: &str, expr: impl Into<Expr>) -> Result<Self> {
+        let expr = expr.into();
+        for input in expr.columns() {
+            self.check_computed_input(input)?;
+        }
+        self.push_computed(name, Computed::Expr(expr))?;
+        Ok(self)
+    }
+
+    /// Check from the schema that `name` can be an input of a computed
+    /// column, so mistakes show before the first access. Custom dtypes are
+    /// only checked then.
+    fn check_computed_input(&self, name: &str) -> Result<()> {
+        let col_def = self
+            .schema()
+            .get(name)
+            .ok_or_else(|| self.column_not_found(name))?;
+        if col_def.width() > 1 {
+            return Err(MmappetError::NotScalar {
+                column: name.to_string(),
+                width: col_def.width(),
+            });
+        }
+        if matches!(col_def.dtype, DType::Utf8 | DType::Categorical) {
+            return Err(MmappetError::InvalidDType {
+                dtype: col_def.dtype.to_string(),
+                message: format!("column '{}' has no numeric value", name),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Computed {
+    /// Compute the values of the column on `dataset`, as an in-memory column
+    /// reporting `path` in errors.
+    pub(crate) fn evaluate(&self, dataset: &Dataset, path: &Path) -> Result<Column> {
+        let len = dataset.len();
+        let mut values = vec![0.0; len];
+        match self {
+            Computed::Expr(expr) => {
+                let bound = expr.bind(dataset)?;
+                for (i, chunk) in values.chunks_mut(STATS_CHUNK_ROWS).enumerate() {
+                    bound.eval_range(i * STATS_CHUNK_ROWS, chunk);
+                }
+            }
+            Computed::Fn { inputs, f } => {
+                let names: Vec<&str> = inputs.iter().map(String::as_str).collect();
+                let arrays = dataset.numeric_arrays(&names)?;
+                let mut columns = vec![vec![0.0; STATS_CHUNK_ROWS.min(len)]; arrays.len()];
+                let mut row = vec![0.0; arrays.len()];
+                for (i, chunk) in values.chunks_mut(STATS_CHUNK_ROWS).enumerate() {
+                    for (array, column) in arrays.iter().zip(&mut columns) {
+                        read_f64(array, i * STATS_CHUNK_ROWS, &mut column[..chunk.len()]);
+                    }
+                    for (r, value) in chunk.iter_mut().enumerate() {
+                        for (x, column) in row.iter_mut().zip(&columns) {
+                            *x = column[r];
+                        }
+                        *value = f(&row);
+                    }
+                }
+            }
+        }
+        Column::from_array(path, &TypedArray::Float64(values.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::col;
+
+    #[test]
+    fn test_computed_columns() {
+        let mz: Vec<f64> = (0..10_000).map(|i| 400.0 + i as f64 * 0.001).collect();
+        let charge: Vec<u8> = (0..10_000).map(|i| 1 + (i % 3) as u8).collect();
+        let ds = Dataset::from_columns(vec![
+            ("mz", mz.clone().into()),
+            ("charge", charge.clone().into()),
+            ("name", vec!["a"; 10_000].into()),
+        ])
+        .unwrap()
+        .with_computed("mass", &["mz", "charge"], |x| x[0] * x[1])
+        .unwrap()
+        .with_computed_expr("mass_plus", col("mass") + 1.0)
+        .unwrap();
+
+        assert_eq!(
+            ds.schema().column_names(),
+            ["mz", "charge", "name", "mass", "mass_plus"]
+        );
+        assert_eq!(ds.schema().get("mass").unwrap().dtype, DType::Float64);
+        assert!(ds.is_computed("mass") && !ds.is_computed("mz"));
+        let mass = ds.get::<f64>("mass").unwrap();
+        assert_eq!(mass[9_999], mz[9_999] * 1.0);
+        assert_eq!(mass[5_000], mz[5_000] * charge[5_000] as f64);
+        assert_eq!(
+            ds.get::<f64>("mass_plus").unwrap()[5_000],
+            mass[5_000] + 1.0
+        );
+        assert!(ds.nullable("mass").unwrap().null_count() == 0);
+
+        let dir = std::env::temp_dir().join(format!("mmappet-computed-{}", std::process::id()));
+        ds.filter(&vec![true; 10_000])
+            .unwrap()
+            .write_to(&dir)
+            .unwrap();
+        let stored = Dataset::open(&dir).unwrap();
+        assert!(!stored.is_computed("mass"));
+        assert_eq!(stored.get::<f64>("mass").unwrap(), mass);
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let ds = ds.with_computed("mz2", &["mz"], |x| x[0]).unwrap();
+        assert!(matches!(
+            ds.with_computed("mz", &["mz"], |x| x[0]),
+            Err(MmappetError::DuplicateColumnName { .. })
+        ));
+        let ds = Dataset::from_columns(vec![("name", vec!["a"].into())]).unwrap();
+        assert!(ds.with_computed("x", &["name"], |x| x[0]).is_err());
+        let ds = Dataset::from_columns(vec![("name", vec!["a"].into())]).unwrap();
+        assert!(ds.with_computed_expr("x", col("missing")).is_err());
+    }
+
+    #[test]
+    fn test_dataset_unwind_safe() {
+        fn assert_unwind_safe<T: RefUnwindSafe + UnwindSafe>() {}
+        assert_unwind_safe::<Dataset>();
+    }
+}
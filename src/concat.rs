@@ -0,0 +1,312 @@
+//! Datasets read one after another as a single one.
+
+use std::fs;
+use std::ops::{Range, RangeBounds};
+use std::path::{Path, PathBuf};
+
+use crate::column::{check_indices, checked_range};
+use crate::dataset::Dataset;
+use crate::dtype::MmappetType;
+use crate::error::{MmappetError, Result};
+use crate::schema::Schema;
+use crate::view::DatasetView;
+
+/// Prefix of the part directories of a partitioned dataset.
+const PART_PREFIX: &str = "part-";
+
+/// Datasets with the same columns read as one, with rows numbered across
+/// them and nothing copied.
+///
+/// The parts are opened as by [`Dataset::open`], so their columns are mapped
+/// on first access. A partitioned dataset is a directory of parts
+/// `part-0000/`, `part-0001/`, ..., see [`ConcatDataset::open_partitioned`];
+/// a new part is written with [`DatasetWriter`](crate::DatasetWriter) to
+/// [`ConcatDataset::part_path`] and shows up when it's opened again.
+///
+/// ```rust,no_run
+/// use mmappet::{ConcatDataset, Dataset};
+///
+/// let ds = Dataset::open_many(&["run1.mmappet", "run2.mmappet"])?;
+/// let tof: Vec<u32> = ds.to_vec("tof")?;
+///
+/// let shards = ConcatDataset::open_partitioned("hourly.mmappet")?;
+/// for view in shards.slice(1_000..2_000) {
+///     let mz: &[f64] = view.get("mz")?;
+/// }
+/// # Ok::<(), mmappet::MmappetError>(())
+/// ```
+pub struct ConcatDataset {
+    schema: Schema,
+    parts: Vec<Dataset>,
+    /// First global row of every part, and the total row count last.
+    offsets: Vec<usize>,
+}
+
+impl ConcatDataset {
+    /// Open the datasets in `paths`, in that order.
+    ///
+    /// Returns an error if `paths` is empty, a dataset can't be opened or its
+    /// columns differ from the first one's in name, order, type or shape.
+    pub fn open<P: AsRef<Path>>(paths: &[P]) -> Result<Self> {
+        let parts = paths
+            .iter()
+            .map(Dataset::open)
+            .collect::<Result<Vec<_>>>()?;
+        Self::from_parts(parts)
+    }
+
+    /// Concatenate opened datasets, checked as by [`ConcatDataset::open`].
+    pub fn from_parts(parts: Vec<Dataset>) -> Result<Self> {
+        let first = parts.first().ok_or_else(|| MmappetError::InvalidConcat {
+            path: None,
+            message: "no datasets given".to_string(),
+        })?;
+        for part in &parts[1..] {
+            check_columns(first, part)?;
+        }
+        Ok(Self::new(first.schema().clone(), parts))
+    }
+
+    /// Concatenate checked parts, possibly none, with the columns `schema`.
+    pub(crate) fn new(schema: Schema, parts: Vec<Dataset>) -> Self {
+        let mut offsets = vec![0];
+        for part in &parts {
+            offsets.push(offsets.last().unwrap() + part.len());
+        }
+        ConcatDataset {
+            schema,
+            parts,
+            offsets,
+        }
+    }
+
+    /// Open the parts `part-NNNN` in the directory `path`, ordered by their
+    /// number; other entries are ignored.
+    ///
+    /// Returns an error if there are no parts or [`ConcatDataset::open`]
+    /// fails on them.
+    pub fn open_partitioned<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut numbered = Vec::new();
+        for entry in fs::read_dir(path).map_err(|e| MmappetError::io(path, e))? {
+            let entry = entry.map_err(|e| MmappetError::io(path, e))?;
+            let name = entry.file_name();
+            let number = name
+                .to_str()
+                .and_then(|n| n.strip_prefix(PART_PREFIX))
+                .and_then(|n| n.parse::<u64>().ok());
+            if let Some(number) = number {
+                if entry.path().is_dir() {
+                    numbered.push((number, entry.path()));
+                }
+            }
+        }
+        if numbered.is_empty() {
+            return Err(MmappetError::InvalidConcat {
+                path: Some(path.to_path_buf()),
+                message: format!("no {}NNNN directories", PART_PREFIX),
+            });
+        }
+        numbered.sort();
+        let paths: Vec<PathBuf> = numbered.into_iter().map(|(_, p)| p).collect();
+        Self::open(&paths)
+    }
+
+    /// Path of part `index` of the partitioned dataset in `path`, e.g.
+    /// `path/part-0003` for 3.
+    pub fn part_path<P: AsRef<Path>>(path: P, index: usize) -> PathBuf {
+        path.as_ref().join(format!("{}{:04}", PART_PREFIX, index))
+    }
+
+    /// The parts in order.
+    pub fn parts(&self) -> &[Dataset] {
+        &self.parts
+    }
+
+    /// Number of parts.
+    pub fn num_parts(&self) -> usize {
+        self.parts.len()
+    }
+
+    /// Global rows of part `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn part_rows(&self, index: usize) -> Range<usize> {
+        self.offsets[index]..self.offsets[index + 1]
+    }
+
+    /// Part holding the global row `row`, and the row within it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `row` is out of bounds.
+    pub fn locate(&self, row: usize) -> (usize, usize) {
+        assert!(row < self.len(), "row {} out of bounds for {} rows", row, self.len());
+        // The last part starting at or before `row`, skipping empty ones
+        let part = self.offsets.partition_point(|&start| start <= row) - 1;
+        (part, row - self.offsets[part])
+    }
+
+    /// Views of the parts covering the global `rows`, in order; parts
+    /// without any of them are skipped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rows` is out of bounds.
+    pub fn slice(&self, rows: impl RangeBounds<usize>) -> Vec<DatasetView<'_>> {
+        let rows = checked_range(rows, self.len());
+        let mut views = Vec::new();
+        for (index, part) in self.parts.iter().enumerate() {
+            let part_rows = self.part_rows(index);
+            let start = rows.start.max(part_rows.start);
+            let end = rows.end.min(part_rows.end);
+            if start < end {
+                views.push(part.slice(start - part_rows.start..end - part_rows.start));
+            }
+        }
+        views
+    }
+
+    /// Typed slices of a column, one per part.
+    ///
+    /// Returns an error if the column doesn't exist or the type doesn't match.
+    pub fn get_parts<T: MmappetType>(&self, name: &str) -> Result<Vec<&[T]>> {
+        self.parts.iter().map(|part| part.get::<T>(name)).collect()
+    }
+
+    /// Copy a column out of all parts as one typed `Vec`.
+    ///
+    /// Returns an error if the column doesn't exist or the type doesn't match.
+    pub fn to_vec<T: MmappetType>(&self, name: &str) -> Result<Vec<T>> {
+        Ok(self.get_parts::<T>(name)?.concat())
+    }
+
+    /// Values of a column at the global `rows`, in the given order; `width`
+    /// values per row for array columns.
+    ///
+    /// Returns an error if the column doesn't exist, the type doesn't match
+    /// or a row is out of bounds.
+    pub fn take<T: MmappetType>(&self, name: &str, rows: &[usize]) -> Result<Vec<T>> {
+        check_indices(rows, self.len())?;
+        let parts = self.get_parts::<T>(name)?;
+        let width = self.schema().get(name).unwrap().width();
+        let mut values = Vec::with_capacity(rows.len() * width);
+        for &row in rows {
+            let (part, local) = self.locate(row);
+            values.extend_from_slice(&parts[part][local * width..(local + 1) * width]);
+        }
+        Ok(values)
+    }
+
+    /// Get the schema, that of the first part.
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Get the total number of rows.
+    pub fn len(&self) -> usize {
+        *self.offsets.last().unwrap()
+    }
+
+    /// Check if no part has rows.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Dataset {
+    /// Open the datasets in `paths` as one, see [`ConcatDataset::open`].
+    pub fn open_many<P: AsRef<Path>>(paths: &[P]) -> Result<ConcatDataset> {
+        ConcatDataset::open(paths)
+    }
+}
+
+/// Return an error unless `part` has the columns of `first`.
+fn check_columns(first: &Dataset, part: &Dataset) -> Result<()> {
+    let columns = |ds: &Dataset| -> Vec<String> {
+        ds.schema()
+            .columns()
+            .map(|c| format!("{} {}", c.type_name(), c.name))
+            .collect()
+    };
+    let (expected, actual) = (columns(first), columns(part));
+    if expected == actual {
+        return Ok(());
+    }
+    Err(MmappetError::InvalidConcat {
+        path: Some(part.path().to_path_buf()),
+        message: format!(
+            "columns [{}] differ from [{}] of {}",
+            actual.join(", "),
+            expected.join(", "),
+            first.path().display()
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dtype::DType;
+    use crate::test_util::write_dataset;
+    use crate::writer::DatasetWriter;
+
+    #[test]
+    fn test_concat_dataset() {
+        let a = write_dataset("concat-a", &[("tof", &[1, 2, 3])]);
+        let b = write_dataset("concat-b", &[("tof", &[4, 5])]);
+        let ds = Dataset::open_many(&[&a, &b]).unwrap();
+        assert_eq!((ds.num_parts(), ds.len()), (2, 5));
+        assert_eq!(ds.to_vec::<u32>("tof").unwrap(), vec![1, 2, 3, 4, 5]);
+        assert!(matches!(
+            Dataset::open_many::<&Path>(&[]),
+            Err(MmappetError::InvalidConcat { .. })
+        ));
+
+        let c = write_dataset("concat-c", &[("scan", &[6])]);
+        assert!(matches!(
+            Dataset::open_many(&[&a, &c]),
+            Err(MmappetError::InvalidConcat { .. })
+        ));
+
+        for dir in [a, b, c] {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_partitioned_dataset() {
+        let dir = std::env::temp_dir().join(format!("mmappet-partitioned-{}", std::process::id()));
+        let schema = Schema::new(&[("tof", DType::UInt32)]).unwrap();
+        for (index, tofs) in [&[1u32, 2, 3][..], &[], &[4, 5]].iter().enumerate() {
+            let mut writer =
+                DatasetWriter::create(ConcatDataset::part_path(&dir, index), &schema).unwrap();
+            writer.write("tof", tofs).unwrap();
+            writer.finish().unwrap();
+        }
+        fs::create_dir_all(dir.join("other")).unwrap();
+
+        let ds = ConcatDataset::open_partitioned(&dir).unwrap();
+        assert_eq!((ds.num_parts(), ds.len()), (3, 5));
+        assert_eq!(ds.part_rows(2), 3..5);
+        assert_eq!(ds.locate(3), (2, 0));
+        assert_eq!(ds.take::<u32>("tof", &[4, 0]).unwrap(), vec![5, 1]);
+        assert!(ds.take::<u32>("tof", &[5]).is_err());
+        let views = ds.slice(2..4);
+        assert_eq!(views.iter().map(|v| v.rows()).collect::<Vec<_>>(), vec![2..3, 0..1]);
+
+        let schema = Schema::new(&[("tof", DType::UInt64)]).unwrap();
+        DatasetWriter::create(ConcatDataset::part_path(&dir, 3), &schema)
+            .unwrap()
+            .finish()
+            .unwrap();
+        assert!(matches!(
+            ConcatDataset::open_partitioned(&dir),
+            Err(MmappetError::InvalidConcat { .. })
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,337 @@
+//! Covariance and correlation matrices of numeric columns.
+
+use ndarray::Array2;
+
+use crate::column::TypedArrayView;
+use crate::dataset::Dataset;
+use crate::error::{MmappetError, Result};
+use crate::expr::read_f64;
+use crate::stats::STATS_CHUNK_ROWS;
+
+/// How [`Dataset::correlation`] measures the dependence of two columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Correlation {
+    /// Pearson's coefficient, of the values: linear dependence.
+    #[default]
+    Pearson,
+    /// Spearman's coefficient, Pearson's of the values' ranks: monotonic
+    /// dependence. Ties get their average rank.
+    Spearman,
+}
+
+impl Dataset {
+    /// Sample covariance matrix of the columns `names`, in their order.
+    ///
+    /// Nulls and NaNs are skipped pairwise: each entry takes the rows where
+    /// both its columns have a value, so a sparse column doesn't shrink the
+    /// others' entries. Entries are NaN if fewer than two such rows remain.
+    /// Computed in one chunked pass. Returns an error if a column doesn't
+    /// exist, holds arrays or has no numeric value.
+    pub fn covariance(&self, names: &[&str]) -> Result<Array2<f64>> {
+        let moments = self.pair_moments(names, Correlation::Pearson)?;
+        Ok(symmetric(&moments, |m| match m.count {
+            0 | 1 => f64::NAN,
+            count => m.m2[[0, 1]] / (count - 1) as f64,
+        }))
+    }
+
+    /// Correlation matrix of the columns `names`, in their order.
+    ///
+    /// Nulls and NaNs are skipped pairwise as by [`Dataset::covariance`].
+    /// Entries of columns without spread, or with fewer than two rows
+    /// remaining, are NaN. Pearson's coefficients take one chunked pass;
+    /// Spearman's load the columns, 8 bytes per value, and rank the rows
+    /// each pair has values in. Returns an error if a column doesn't exist,
+    /// holds arrays or has no numeric value.
+    ///
+    /// ```rust,no_run
+    /// use mmappet::{Correlation, Dataset};
+    ///
+    /// let ds = Dataset::open("peaks.mmappet")?;
+    /// let r = ds.correlation(&["mz", "intensity", "mobility"], Correlation::Spearman)?;
+    /// println!("m/z vs intensity: {}", r[[0, 1]]);
+    /// # Ok::<(), mmappet::MmappetError>(())
+    /// ```
+    pub fn correlation(&self, names: &[&str], method: Correlation) -> Result<Array2<f64>> {
+        let moments = self.pair_moments(names, method)?;
+        Ok(symmetric(&moments, |m| match m.count {
+            0 | 1 => f64::NAN,
+            _ => m.m2[[0, 1]] / (m.m2[[0, 0]] * m.m2[[1, 1]]).sqrt(),
+        }))
+    }
+
+    /// Co-moments of each pair of the columns `names`, of their values or
+    /// ranks, over the rows both have a value in; see [`co_moments`].
+    fn pair_moments(&self, names: &[&str], method: Correlation) -> Result<Array2<CoMoments>> {
+        let arrays = self.numeric_arrays(names)?;
+        let columns = names
+            .iter()
+            .map(|name| self.nullable(name))
+            .collect::<Result<Vec<_>>>()?;
+        let read = |j: usize, start: usize, out: &mut [f64]| {
+            read_f64(&arrays[j], start, out);
+            columns[j].mask_nulls(start, out);
+        };
+        Ok(match method {
+            Correlation::Pearson => co_moments(self.len(), names.len(), read),
+            Correlation::Spearman => {
+                let values: Vec<Vec<f64>> = (0..names.len())
+                    .map(|j| {
+                        let mut values = vec![0.0; self.len()];
+                        read(j, 0, &mut values);
+                        values
+                    })
+                    .collect();
+                rank_moments(&values)
+            }
+        })
+    }
+
+    /// The columns `names` as typed arrays, checking that they are scalar
+    /// and convert to `f64`.
+    pub(crate) fn numeric_arrays(&self, names: &[&str]) -> Result<Vec<TypedArrayView<'_>>> {
+        names
+            .iter()
+            .map(|&name| {
+                let column = self
+                    .column(name)
+                    .ok_or_else(|| self.column_not_found(name))?;
+                column.check_scalar(name)?;
+                column
+                    .numeric_array()
+                    .ok_or_else(|| MmappetError::InvalidDType {
+                        dtype: column.dtype().to_string(),
+                        message: format!("column '{}' has no numeric value", name),
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Row count, means and co-moments (sums of products of deviations from the
+/// means) of a set of columns.
+struct CoMoments {
+    count: usize,
+    means: Vec<f64>,
+    m2: Array2<f64>,
+}
+
+impl CoMoments {
+    fn new(k: usize) -> Self {
+        CoMoments {
+            count: 0,
+            means: vec![0.0; k],
+            m2: Array2::zeros((k, k)),
+        }
+    }
+
+    /// Co-moments of `a` and `b` over the rows where neither is NaN.
+    fn of_pair(a: &[f64], b: &[f64]) -> Self {
+        let mut moments = CoMoments::new(2);
+        let rows = || (0..a.len()).filter(|&r| !a[r].is_nan() && !b[r].is_nan());
+        moments.count = rows().count();
+        if moments.count == 0 {
+            return moments;
+        }
+        for (mean, values) in moments.means.iter_mut().zip([a, b]) {
+            *mean = rows().map(|r| values[r]).sum::<f64>() / moments.count as f64;
+        }
+        let pairs = [(a, a), (a, b), (b, b)];
+        let means = [(0, 0), (0, 1), (1, 1)];
+        for ((x, y), (i, j)) in pairs.into_iter().zip(means) {
+            let (mean_x, mean_y) = (moments.means[i], moments.means[j]);
+            let m2: f64 = rows().map(|r| (x[r] - mean_x) * (y[r] - mean_y)).sum();
+            moments.m2[[i, j]] = m2;
+            moments.m2[[j, i]] = m2;
+        }
+        moments
+    }
+
+    /// Co-moments of the rows of both `self` and `other`, by Chan et al.'s
+    /// update.
+    fn merge(mut self, other: CoMoments) -> CoMoments {
+        let count = self.count + other.count;
+        if other.count == 0 {
+            return self;
+        }
+        if self.count == 0 {
+            return other;
+        }
+        let weight = self.count as f64 * other.count as f64 / count as f64;
+        let deltas: Vec<f64> = other
+            .means
+            .iter()
+            .zip(&self.means)
+            .map(|(b, a)| b - a)
+            .collect();
+        for ((i, j), m2) in self.m2.indexed_iter_mut() {
+            *m2 += other.m2[[i, j]] + deltas[i] * deltas[j] * weight;
+        }
+        for (mean, delta) in self.means.iter_mut().zip(deltas) {
+            *mean += delta * other.count as f64 / count as f64;
+        }
+        self.count = count;
+        self
+    }
+}
+
+/// Co-moments of each pair of `k` columns of `len` rows, skipping the rows
+/// where either is NaN. Only the upper triangle, `[[i, j]]` with `i <= j`,
+/// is filled.
+///
+/// `read(j, start, out)` fills `out` with the values of column `j` from row
+/// `start` on.
+fn co_moments(len: usize, k: usize, read: impl Fn(usize, usize, &mut [f64])) -> Array2<CoMoments> {
+    let rows = STATS_CHUNK_ROWS.min(len);
+    let mut chunk = vec![vec![0.0; rows]; k];
+    let mut moments = Array2::from_shape_fn((k, k), |_| CoMoments::new(2));
+    for start in (0..len).step_by(STATS_CHUNK_ROWS) {
+        let rows = STATS_CHUNK_ROWS.min(len - start);
+        for (j, values) in chunk.iter_mut().enumerate() {
+            read(j, start, &mut values[..rows]);
+        }
+        for ((i, j), pair) in moments.indexed_iter_mut() {
+            if i <= j {
+                let part = CoMoments::of_pair(&chunk[i][..rows], &chunk[j][..rows]);
+                *pair = std::mem::replace(pair, CoMoments::new(2)).merge(part);
+            }
+        }
+    }
+    moments
+}
+
+/// Co-moments of the ranks of each pair of `columns`, ranked among the
+/// rows where neither is NaN, in the upper triangle as by [`co_moments`].
+fn rank_moments(columns: &[Vec<f64>]) -> Array2<CoMoments> {
+    let k = columns.len();
+    Array2::from_shape_fn((k, k), |(i, j)| {
+        if i > j {
+            return CoMoments::new(2);
+        }
+        let (a, b) = (&columns[i], &columns[j]);
+        let kept: Vec<usize> = (0..a.len())
+            .filter(|&r| !a[r].is_nan() && !b[r].is_nan())
+            .collect();
+        CoMoments::of_pair(&ranks(a, &kept), &ranks(b, &kept))
+    })
+}
+
+/// Symmetric matrix of `f` of the upper triangle of pairwise co-moments.
+fn symmetric(moments: &Array2<CoMoments>, f: impl Fn(&CoMoments) -> f64) -> Array2<f64> {
+    Array2::from_shape_fn(moments.dim(), |(i, j)| f(&moments[[i.min(j), i.max(j)]]))
+}
+
+/// Ranks of the rows `kept` of `values`, ties getting their average rank.
+fn ranks(values: &[f64], kept: &[usize]) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..kept.len()).collect();
+    order.sort_unstable_by(|&a, &b| values[kept[a]].total_cmp(&values[kept[b]]));
+    let mut ranks = vec![0.0; kept.len()];
+    let mut start = 0;
+    while start < order.len() {
+        let value = values[kept[order[start]]];
+        let end = start
+            + order[start..]
+                .iter()
+                .take_while(|&&i| values[kept[i]] == value)
+                .count();
+        // 1-based ranks start + 1..=end share their mean
+        let rank = (start + end + 1) as f64 / 2.0;
+        for &i in &order[start..end] {
+            ranks[i] = rank;
+        }
+        start = end;
+    }
+    ranks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dtype::DType;
+    use crate::schema::Schema;
+    use crate::writer::DatasetWriter;
+
+    #[test]
+    fn test_correlation() {
+        // Enough rows for several chunks
+        let x: Vec<f64> = (0..10_000).map(|i| i as f64).collect();
+        let y: Vec<f64> = x.iter().map(|x| 2.0 * x + 1.0).collect();
+        let mut z: Vec<f64> = x.iter().map(|x| (x / 1000.0).exp()).collect();
+        z[5] = f64::NAN;
+        let ds = Dataset::from_columns(vec![
+            ("x", x.into()),
+            ("y", y.into()),
+            ("z", z.into()),
+            ("c", vec![3u16; 10_000].into()),
+            ("s", vec!["a"; 10_000].into()),
+        ])
+        .unwrap();
+
+        let cov = ds.covariance(&["x", "y"]).unwrap();
+        let var = 10_000.0 * 10_001.0 / 12.0;
+        assert!((cov[[0, 0]] - var).abs() < 1e-6 && (cov[[0, 1]] - 2.0 * var).abs() < 1e-6);
+
+        let r = ds
+            .correlation(&["x", "y", "z"], Correlation::Pearson)
+            .unwrap();
+        assert!((r[[0, 1]] - 1.0).abs() < 1e-12 && (r[[1, 0]] - 1.0).abs() < 1e-12);
+        assert!(r[[0, 2]] > 0.5 && r[[0, 2]] < 0.99);
+        assert_eq!(r[[0, 2]], r[[2, 0]]);
+
+        // Monotonic, so perfectly rank-correlated
+        let rho = ds.correlation(&["x", "z"], Correlation::Spearman).unwrap();
+        assert!((rho[[0, 1]] - 1.0).abs() < 1e-12);
+        let constant = ds.correlation(&["x", "c"], Correlation::Spearman).unwrap();
+        assert!(constant[[0, 1]].is_nan() && constant[[1, 1]].is_nan());
+
+        assert!(ds.correlation(&["x", "s"], Correlation::Pearson).is_err());
+        assert!(ds.covariance(&["missing"]).is_err());
+    }
+
+    #[test]
+    fn test_pairwise_nulls() {
+        let dir = std::env::temp_dir().join(format!("mmappet-correlation-{}", std::process::id()));
+        let schema = Schema::new(&[
+            ("x", DType::Float64),
+            ("y", DType::Float64),
+            ("z", DType::Float64),
+        ])
+        .unwrap();
+        let x: Vec<f64> = (0..100).map(|i| i as f64).collect();
+        // Nulls are stored as zero, which would break the linear relation
+        let y: Vec<Option<f64>> = x
+            .iter()
+            .map(|&x| (x % 3.0 != 0.0).then_some(2.0 * x + 1.0))
+            .collect();
+        let mut z: Vec<f64> = x.iter().map(|x| -x * x).collect();
+        z[1] = f64::NAN;
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write("x", &x).unwrap();
+        writer.write_opt("y", &y).unwrap();
+        writer.write("z", &z).unwrap();
+        writer.finish().unwrap();
+        let ds = Dataset::open(&dir).unwrap();
+
+        for method in [Correlation::Pearson, Correlation::Spearman] {
+            let r = ds.correlation(&["x", "y", "z"], method).unwrap();
+            assert!((r[[0, 1]] - 1.0).abs() < 1e-12, "{:?}", method);
+            // Each pair keeps its own rows
+            let xz = ds.correlation(&["x", "z"], method).unwrap();
+            assert_eq!(r[[0, 2]], xz[[0, 1]]);
+            assert_eq!(r[[2, 0]], xz[[1, 0]]);
+        }
+        let cov = ds.covariance(&["x", "y"]).unwrap();
+        assert!((cov[[0, 0]] - 100.0 * 101.0 / 12.0).abs() < 1e-9);
+        let xs: Vec<f64> = x.iter().copied().filter(|x| x % 3.0 != 0.0).collect();
+        let mean = xs.iter().sum::<f64>() / xs.len() as f64;
+        let var = xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (xs.len() - 1) as f64;
+        assert!((cov[[0, 1]] - 2.0 * var).abs() < 1e-9);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_ranks() {
+        let values = [3.0, 1.0, f64::NAN, 3.0, 2.0];
+        assert_eq!(ranks(&values, &[0, 1, 3, 4]), [3.5, 1.0, 3.5, 2.0]);
+    }
+}
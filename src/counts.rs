@@ -0,0 +1,147 @@
+//! Distinct values of a column and their counts.
+
+use std::collections::HashMap;
+
+use crate::column::Column;
+use crate::value::Value;
+
+/// Distinct values counted in a hash map before switching to sorting the
+/// rows, which needs less memory per distinct value.
+const HASH_DISTINCT_LIMIT: usize = 1 << 22;
+
+impl Column {
+    /// Every distinct value of the column with its number of rows, most
+    /// frequent first; equally frequent values in order of first occurrence.
+    ///
+    /// Values are compared as [`Dataset::unique_rows`](crate::Dataset::unique_rows)
+    /// compares them: strings by text, categorical columns by label and
+    /// everything else bitwise, whole rows of array columns at once.
+    /// Categorical columns are counted by code. Others are counted in a hash
+    /// map, or by sorting the rows once there are more than 2^22 distinct
+    /// values. Validity bitmaps aren't consulted.
+    ///
+    /// ```rust,no_run
+    /// let ds = mmappet::Dataset::open("peaks.mmappet")?;
+    /// for (frame, peaks) in ds["frame"].value_counts().iter().take(10) {
+    ///     println!("frame {}: {} peaks", frame, peaks);
+    /// }
+    /// # Ok::<(), mmappet::MmappetError>(())
+    /// ```
+    pub fn value_counts(&self) -> Vec<(Value<'_>, usize)> {
+        let mut counts = distinct(self, HASH_DISTINCT_LIMIT);
+        counts.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        counts
+            .into_iter()
+            .map(|(row, count)| (self.value(row), count))
+            .collect()
+    }
+
+    /// Every distinct value of the column, in order of first occurrence.
+    ///
+    /// Values are compared and counted as by [`Column::value_counts`].
+    pub fn unique(&self) -> Vec<Value<'_>> {
+        let mut first_rows: Vec<usize> = distinct(self, HASH_DISTINCT_LIMIT)
+            .into_iter()
+            .map(|(row, _)| row)
+            .collect();
+        first_rows.sort_unstable();
+        first_rows.into_iter().map(|row| self.value(row)).collect()
+    }
+}
+
+/// First row and number of rows of every distinct value of `column`, in no
+/// particular order, sorting the rows once there are more than `hash_limit`
+/// distinct values.
+fn distinct(column: &Column, hash_limit: usize) -> Vec<(usize, usize)> {
+    if let Some(labels) = column.as_categorical() {
+        let mut first_rows = vec![usize::MAX; labels.categories().len()];
+        let mut counts = vec![0; labels.categories().len()];
+        for (row, &code) in labels.codes().iter().enumerate() {
+            let code = code as usize;
+            first_rows[code] = first_rows[code].min(row);
+            counts[code] += 1;
+        }
+        return first_rows
+            .into_iter()
+            .zip(counts)
+            .filter(|&(_, count)| count > 0)
+            .collect();
+    }
+
+    let mut counts: HashMap<&[u8], (usize, usize)> = HashMap::new();
+    for (row, key) in column.row_bytes().iter().enumerate() {
+        counts.entry(key).or_insert((row, 0)).1 += 1;
+        if counts.len() > hash_limit {
+            return sorted_distinct(column);
+        }
+    }
+    counts.into_values().collect()
+}
+
+/// [`distinct`] by sorting the rows by their bytes.
+fn sorted_distinct(column: &Column) -> Vec<(usize, usize)> {
+    let keys: Vec<&[u8]> = column.row_bytes().iter().collect();
+    let mut rows: Vec<usize> = (0..keys.len()).collect();
+    // Stable, so every run of equal values starts with its first row
+    rows.sort_by(|&a, &b| keys[a].cmp(keys[b]));
+    rows.chunk_by(|&a, &b| keys[a] == keys[b])
+        .map(|run| (run[0], run.len()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::Dataset;
+    use crate::dtype::DType;
+    use crate::schema::Schema;
+    use crate::writer::DatasetWriter;
+
+    #[test]
+    fn test_value_counts() {
+        let ds = Dataset::from_columns(vec![
+            ("frame", vec![3u32, 1, 2, 1, 3, 1].into()),
+            ("name", vec!["b", "a", "b", "c", "a", "b"].into()),
+        ])
+        .unwrap();
+
+        let counts = ds["frame"].value_counts();
+        assert_eq!(
+            counts,
+            [(Value::U32(1), 3), (Value::U32(3), 2), (Value::U32(2), 1)]
+        );
+        assert_eq!(
+            ds["frame"].unique(),
+            [Value::U32(3), Value::U32(1), Value::U32(2)]
+        );
+        let counts = ds["name"].value_counts();
+        assert_eq!(
+            counts,
+            [
+                (Value::Str("b"), 3),
+                (Value::Str("a"), 2),
+                (Value::Str("c"), 1)
+            ]
+        );
+
+        let mut hashed = distinct(&ds["frame"], usize::MAX);
+        let mut sorted = distinct(&ds["frame"], 1);
+        hashed.sort_unstable();
+        sorted.sort_unstable();
+        assert_eq!(hashed, [(0, 2), (1, 3), (2, 1)]);
+        assert_eq!(sorted, hashed);
+
+        let dir = std::env::temp_dir().join(format!("mmappet-counts-{}", std::process::id()));
+        let schema = Schema::new(&[("run", DType::Categorical)]).unwrap();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write_categorical("run", &["b", "a", "a"]).unwrap();
+        writer.finish().unwrap();
+        let ds = Dataset::open(&dir).unwrap();
+        assert_eq!(
+            ds["run"].value_counts(),
+            [(Value::Str("a"), 2), (Value::Str("b"), 1)]
+        );
+        assert_eq!(ds["run"].unique(), [Value::Str("b"), Value::Str("a")]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
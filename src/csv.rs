@@ -0,0 +1,216 @@
+//! CSV export.
+
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeBounds;
+
+use crate::column::{checked_range, TypedArrayView};
+use crate::dataset::Dataset;
+use crate::error::{MmappetError, Result};
+use crate::format::ValueFormat;
+
+/// Options for [`Dataset::export_csv`].
+///
+/// The default separates fields by commas, writes floats in full,
+/// timestamps as ISO 8601 and starts with a header line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvOptions {
+    /// Separator between fields, e.g. `b'\t'` for TSV.
+    pub delimiter: u8,
+    /// Rendering of numbers, bools, timestamps and custom dtypes.
+    pub format: ValueFormat,
+    /// Start with a line of column names.
+    pub header: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: b',',
+            format: ValueFormat {
+                precision: None,
+                ..ValueFormat::default()
+            },
+            header: true,
+        }
+    }
+}
+
+impl Dataset {
+    /// Write `rows` of the given columns to `writer` as CSV, one line per row.
+    ///
+    /// An empty `columns` writes every column. Rows are formatted one at a
+    /// time, so any number of them can be written without buffering the
+    /// output. Values are rendered by [`CsvOptions::format`], nulls are empty
+    /// fields and array columns take one field per element, named `name[i]`.
+    /// Text, including numbers grouped by a separator, is quoted if it holds
+    /// the delimiter, a quote or a line break.
+    ///
+    /// Returns the number of rows written, or an error if a column doesn't
+    /// exist or writing fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rows` is out of bounds.
+    pub fn export_csv<W: Write>(
+        &self,
+        writer: W,
+        columns: &[&str],
+        rows: impl RangeBounds<usize>,
+        options: &CsvOptions,
+    ) -> Result<usize> {
+        let rows = checked_range(rows, self.len());
+        let names: Vec<&str> = if columns.is_empty() {
+            self.column_names().collect()
+        } else {
+            columns.to_vec()
+        };
+        let mut fields = Vec::with_capacity(names.len());
+        for name in &names {
+            let nullable = self.nullable(name)?;
+            let array = nullable.column().slice_typed_array(rows.clone());
+            fields.push((nullable, array));
+        }
+
+        let io = |source| MmappetError::Io { path: None, source };
+        let mut out = BufWriter::new(writer);
+        let delimiter = [options.delimiter];
+        if options.header {
+            let mut first = true;
+            for (name, (nullable, _)) in names.iter().zip(&fields) {
+                let width = nullable.column().width();
+                for i in 0..width {
+                    if !first {
+                        out.write_all(&delimiter).map_err(io)?;
+                    }
+                    first = false;
+                    let field = match width {
+                        1 => name.to_string(),
+                        _ => format!("{}[{}]", name, i),
+                    };
+                    write_text(&mut out, &field, options.delimiter).map_err(io)?;
+                }
+            }
+            out.write_all(b"\n").map_err(io)?;
+        }
+
+        for row in rows.clone() {
+            let mut first = true;
+            for (nullable, array) in &fields {
+                let width = nullable.column().width();
+                let valid = nullable.is_valid(row);
+                for i in 0..width {
+                    if !first {
+                        out.write_all(&delimiter).map_err(io)?;
+                    }
+                    first = false;
+                    if valid {
+                        let index = (row - rows.start) * width + i;
+                        write_value(&mut out, array, index, options).map_err(io)?;
+                    }
+                }
+            }
+            out.write_all(b"\n").map_err(io)?;
+        }
+        out.flush().map_err(io)?;
+        Ok(rows.len())
+    }
+}
+
+/// Write element `index` of `array`.
+fn write_value(
+    out: &mut impl Write,
+    array: &TypedArrayView<'_>,
+    index: usize,
+    options: &CsvOptions,
+) -> io::Result<()> {
+    let delimiter = options.delimiter;
+    match array {
+        TypedArrayView::Utf8(a) => write_text(out, a.get(index), delimiter),
+        TypedArrayView::Categorical(a) => write_text(out, a.value(index), delimiter),
+        _ => write_text(out, &options.format.format_at(array, index), delimiter),
+    }
+}
+
+/// Write `text`, quoted if it holds `delimiter`, a quote or a line break.
+fn write_text(out: &mut impl Write, text: &str, delimiter: u8) -> io::Result<()> {
+    if !text.bytes().any(|b| b == delimiter || b == b'"' || b == b'\n' || b == b'\r') {
+        return out.write_all(text.as_bytes());
+    }
+    write!(out, "\"{}\"", text.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dtype::DType;
+    use crate::format::TimestampStyle;
+    use crate::schema::Schema;
+    use crate::writer::DatasetWriter;
+
+    #[test]
+    fn test_export_csv() {
+        let dir = std::env::temp_dir().join(format!("mmappet-csv-{}", std::process::id()));
+        let mut schema = Schema::new(&[
+            ("id", DType::UInt32),
+            ("mz", DType::Float64),
+            ("xy", DType::Float32),
+            ("name", DType::Utf8),
+            ("time", DType::TimestampMillis),
+        ])
+        .unwrap();
+        schema.set_shape("xy", &[2]).unwrap();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write_opt("id", &[Some(1u32), None, Some(3)]).unwrap();
+        writer.write("mz", &[0.1f64, 2.0, 1234.5678]).unwrap();
+        writer.write("xy", &[1.5f32, -2.0, 0.0, 1.0, 3.25, 4.0]).unwrap();
+        writer.write_str("name", &["a", "b;c", "say \"hi\""]).unwrap();
+        writer.write_timestamps("time", &[0, 1_500, 86_400_000]).unwrap();
+        writer.finish().unwrap();
+        let ds = Dataset::open(&dir).unwrap();
+
+        let mut out = Vec::new();
+        assert_eq!(ds.export_csv(&mut out, &[], .., &CsvOptions::default()).unwrap(), 3);
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(
+            text.lines().collect::<Vec<_>>(),
+            [
+                "id,mz,xy[0],xy[1],name,time",
+                "1,0.1,1.5,-2,a,1970-01-01T00:00:00.000Z",
+                ",2,0,1,b;c,1970-01-01T00:00:01.500Z",
+                "3,1234.5678,3.25,4,\"say \"\"hi\"\"\",1970-01-02T00:00:00.000Z",
+            ]
+        );
+
+        let options = CsvOptions {
+            delimiter: b';',
+            format: ValueFormat {
+                precision: Some(2),
+                timestamp_style: TimestampStyle::Ticks,
+                ..ValueFormat::default()
+            },
+            header: false,
+        };
+        let mut out = Vec::new();
+        ds.export_csv(&mut out, &["mz", "name", "time"], 1..3, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2.00;\"b;c\";1500\n1234.57;\"say \"\"hi\"\"\";86400000\n"
+        );
+
+        // A thousands separator equal to the delimiter is quoted
+        let options = CsvOptions {
+            format: ValueFormat {
+                thousands_separator: Some(','),
+                sci_below: Some(1.0),
+                ..CsvOptions::default().format
+            },
+            ..CsvOptions::default()
+        };
+        let mut out = Vec::new();
+        ds.export_csv(&mut out, &["mz"], .., &options).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "mz\n1e-1\n2\n\"1,234.5678\"\n");
+        assert!(ds.export_csv(Vec::new(), &["missing"], .., &options).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,60 +1,246 @@
 //! Dataset type for mmappet - the main entry point.
 
-use std::collections::HashMap;
-use std::ops::Index;
+use std::collections::{HashMap, HashSet};
+use std::ops::{Index, RangeBounds};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
 
-use ndarray::ArrayView1;
+use ndarray::{Array2, ArrayView1, ArrayView2, ArrayViewD};
 
-use crate::column::Column;
-use crate::dtype::MmappetType;
+use crate::bloom::BloomFilter;
+use crate::column::{checked_range, Advice, Column, EncodedFile, TypedArray};
+use crate::commit::Commit;
+use crate::computed::Computed;
+use crate::dtype::{ByteOrder, DType, MmappetType};
+use crate::edit;
 use crate::error::{MmappetError, Result};
-use crate::schema::Schema;
+use crate::group::GroupIndex;
+use crate::index::ValueIndex;
+use crate::journal;
+use crate::nullable::validity_path;
+use crate::schema::{ColumnDef, ColumnRef, Schema};
+use crate::zonemap::ZoneMap;
+
+/// Options for opening a [`Dataset`], in the style of [`std::fs::OpenOptions`].
+///
+/// ```rust,no_run
+/// use mmappet::{Advice, OpenOptions};
+///
+/// let ds = OpenOptions::new().advice(Advice::Sequential).open("data.mmappet")?;
+/// # Ok::<(), mmappet::MmappetError>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct OpenOptions {
+    writable: bool,
+    advice: Option<Advice>,
+    huge_pages: bool,
+    buffered: bool,
+    columns: Option<Vec<String>>,
+}
+
+impl OpenOptions {
+    /// Options for opening read-only without access hints.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map columns read-write, see [`Dataset::open_mut`].
+    pub fn writable(&mut self, writable: bool) -> &mut Self {
+        self.writable = writable;
+        self
+    }
+
+    /// Give every column this access pattern hint, see [`Column::advise`].
+    pub fn advice(&mut self, advice: Advice) -> &mut Self {
+        self.advice = Some(advice);
+        self
+    }
+
+    /// Ask the OS to back columns with huge pages, which cuts TLB misses
+    /// during random access to multi-GB columns.
+    ///
+    /// Linux only (`MADV_HUGEPAGE`), and only effective where the kernel
+    /// supports transparent huge pages for the file's filesystem; ignored
+    /// everywhere else.
+    pub fn huge_pages(&mut self, huge_pages: bool) -> &mut Self {
+        self.huge_pages = huge_pages;
+        self
+    }
+
+    /// Read column files into memory instead of mapping them, e.g. for
+    /// filesystems that don't support `mmap`. Writable columns are written
+    /// back on [`Dataset::flush`] and drop.
+    ///
+    /// Always the case on targets without `mmap`, such as `wasm32`.
+    pub fn buffered(&mut self, buffered: bool) -> &mut Self {
+        self.buffered = buffered;
+        self
+    }
+
+    /// Only open the columns `names`, see [`Dataset::open_with_columns`].
+    pub fn columns(&mut self, names: &[&str]) -> &mut Self {
+        self.columns = Some(names.iter().map(|name| name.to_string()).collect());
+        self
+    }
+
+    /// Open the dataset in `path` with these options.
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<Dataset> {
+        Dataset::open_with(path.as_ref(), self)
+    }
+}
+
+/// A column file, mapped on first access.
+struct LazyColumn {
+    path: PathBuf,
+    dtype: DType,
+    shape: Vec<usize>,
+    byte_order: ByteOrder,
+    /// Path of the column's validity bitmap, which may not exist.
+    validity: PathBuf,
+    writable: bool,
+    mapped: OnceLock<Column>,
+    /// How the values are computed, for columns without a file, see
+    /// [`Dataset::with_computed`].
+    computed: Option<Computed>,
+}
+
+/// Path of datasets built by [`Dataset::from_columns`], which has no files.
+const IN_MEMORY_PATH: &str = ":memory:";
 
 /// Main entry point - a memory-mapped mmappet dataset.
+///
+/// Opening reads the schema and checks the column file sizes; each column is
+/// only mapped when first accessed.
 pub struct Dataset {
     path: PathBuf,
     schema: Schema,
-    columns: HashMap<String, Column>,
+    columns: HashMap<String, LazyColumn>,
     row_count: usize,
+    /// Generation and row count committed when the dataset was opened.
+    commit: Option<Commit>,
+    options: OpenOptions,
+    /// Hint given to columns when they're mapped.
+    advice: RwLock<Option<Advice>>,
+    group_indexes: RwLock<HashMap<String, Arc<GroupIndex>>>,
+    /// Value index of each looked-up column, `None` if it has none.
+    value_indexes: RwLock<HashMap<String, Option<Arc<ValueIndex>>>>,
+    /// Zone map of each filtered column, `None` if it has none.
+    zone_maps: RwLock<HashMap<String, Option<Arc<ZoneMap>>>>,
+    /// Bloom filter of each probed column, `None` if it has none.
+    bloom_filters: RwLock<HashMap<String, Option<Arc<BloomFilter>>>>,
 }
 
 impl Dataset {
     /// Open a dataset from a directory path.
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let path = path.as_ref().to_path_buf();
+        OpenOptions::new().open(path)
+    }
+
+    /// Open a dataset for in-place modification of its column values.
+    ///
+    /// Columns are mapped read-write, see [`Dataset::get_mut`]. Reference
+    /// columns stay read-only, since their files belong to another dataset,
+    /// and so do compressed and delta-encoded ones. Column files hard-linked
+    /// to other datasets, e.g. by `mmappet-cli dedupe-storage`, are copied
+    /// when first mapped, so writes don't reach those datasets. Rows can't be
+    /// added or removed this way. Returns [`MmappetError::ForeignByteOrder`]
+    /// if the dataset isn't in the host's byte order.
+    pub fn open_mut<P: AsRef<Path>>(path: P) -> Result<Self> {
+        OpenOptions::new().writable(true).open(path)
+    }
+
+    fn open_with(path: &Path, options: &OpenOptions) -> Result<Self> {
+        let path = path.to_path_buf();
 
         // Parse schema
-        let schema = Schema::from_path(&path)?;
+        let full_schema = Schema::from_path(&path)?;
+        // Readers go by COMMIT below; only writers may roll back a crashed append
+        if options.writable {
+            journal::recover(&path, &full_schema)?;
+        }
+        // Readers only see committed rows; writable opens need whole files
+        let commit = Commit::read(&path)?;
+        let snapshot = commit.filter(|_| !options.writable);
+        let schema = match &options.columns {
+            Some(names) => {
+                let names: Vec<&str> = names.iter().map(String::as_str).collect();
+                full_schema.project(&names).map_err(|e| e.with_path(&path))?
+            }
+            None => full_schema.clone(),
+        };
+        if options.writable && full_schema.byte_order() != ByteOrder::native() {
+            return Err(MmappetError::ForeignByteOrder {
+                path: Some(path),
+                column: None,
+                byte_order: full_schema.byte_order(),
+            });
+        }
+
+        // Files shared with the dataset this one was edited from stay read-only
+        let shared = match options.writable {
+            true => edit::shared_columns(&path)?,
+            false => HashSet::new(),
+        };
 
-        // Load all columns
+        // Locate all columns; they're mapped on first access
         let mut columns = HashMap::new();
         let mut row_count: Option<usize> = None;
 
         for col_def in schema.columns() {
-            let col_path = path.join(format!("{}.bin", col_def.index));
-
-            if !col_path.exists() {
-                return Err(MmappetError::MissingColumnFile(col_path));
-            }
-
-            let column = Column::open(&col_path, col_def.dtype)?;
+            // Files are numbered by position in the full schema
+            let full_def = full_schema.get(&col_def.name).unwrap();
+            let (col_path, byte_order) = column_file(&path, &full_schema, full_def)?;
+            let len = match snapshot {
+                // An append in progress may have written more, even part of a row
+                Some(commit) => {
+                    let rows = Column::file_rows(&col_path, col_def.dtype, col_def.width())
+                        .map_err(|e| e.with_column(&col_def.name))?;
+                    if rows < commit.rows {
+                        return Err(MmappetError::LengthMismatch {
+                            path: Some(path),
+                            column: col_def.name.clone(),
+                            expected: commit.rows,
+                            actual: rows,
+                        });
+                    }
+                    commit.rows
+                }
+                None => Column::file_len(&col_path, col_def.dtype, col_def.width())
+                    .map_err(|e| e.with_column(&col_def.name))?,
+            };
 
             // Validate all columns have same length
             match row_count {
-                None => row_count = Some(column.len()),
+                None => row_count = Some(len),
                 Some(expected) => {
-                    if column.len() != expected {
+                    if len != expected {
                         return Err(MmappetError::LengthMismatch {
-                            name: col_def.name.clone(),
+                            path: Some(path),
+                            column: col_def.name.clone(),
                             expected,
-                            actual: column.len(),
+                            actual: len,
                         });
                     }
                 }
             }
 
-            columns.insert(col_def.name.clone(), column);
+            let writable = options.writable
+                && col_def.reference.is_none()
+                && !shared.contains(&col_def.name)
+                && EncodedFile::find(&col_path).is_none();
+            columns.insert(
+                col_def.name.clone(),
+                LazyColumn {
+                    path: col_path,
+                    dtype: col_def.dtype,
+                    shape: col_def.shape.clone(),
+                    byte_order,
+                    validity: validity_path(&path, full_def.index),
+                    writable,
+                    mapped: OnceLock::new(),
+                    computed: None,
+                },
+            );
         }
 
         Ok(Dataset {
@@ -62,51 +248,385 @@ impl Dataset {
             schema,
             columns,
             row_count: row_count.unwrap_or(0),
+            commit,
+            options: options.clone(),
+            advice: RwLock::new(options.advice),
+            group_indexes: RwLock::new(HashMap::new()),
+            value_indexes: RwLock::new(HashMap::new()),
+            zone_maps: RwLock::new(HashMap::new()),
+            bloom_filters: RwLock::new(HashMap::new()),
+        })
+    }
+
+    /// Build a dataset held in memory from named columns of equal length,
+    /// e.g. as a fixture in tests.
+    ///
+    /// It has the read API of an opened dataset, but nothing can be written
+    /// to it, e.g. rows or indexes, and its [`Dataset::path`] is `:memory:`.
+    /// Returns an error if names repeat or lengths differ.
+    ///
+    /// ```rust
+    /// use mmappet::Dataset;
+    ///
+    /// let ds = Dataset::from_columns(vec![
+    ///     ("mz", vec![401.5f64, 502.25].into()),
+    ///     ("peptide", vec!["PEP", "TIDE"].into()),
+    /// ])?;
+    /// assert_eq!(ds.get::<f64>("mz")?, &[401.5, 502.25]);
+    /// # Ok::<(), mmappet::MmappetError>(())
+    /// ```
+    pub fn from_columns(columns: Vec<(&str, TypedArray)>) -> Result<Self> {
+        let path = PathBuf::from(IN_MEMORY_PATH);
+        let defs: Vec<(&str, DType)> =
+            columns.iter().map(|(name, array)| (*name, array.dtype())).collect();
+        let schema = Schema::new(&defs).map_err(|e| e.with_path(&path))?;
+
+        let mut lazy_columns = HashMap::new();
+        let row_count = columns.first().map_or(0, |(_, array)| array.len());
+        for (index, (name, array)) in columns.iter().enumerate() {
+            if array.len() != row_count {
+                return Err(MmappetError::LengthMismatch {
+                    path: Some(path),
+                    column: name.to_string(),
+                    expected: row_count,
+                    actual: array.len(),
+                });
+            }
+            let col_path = path.join(format!("{}.bin", index));
+            let column = Column::from_array(&col_path, array).map_err(|e| e.with_column(name))?;
+            lazy_columns.insert(
+                name.to_string(),
+                LazyColumn {
+                    path: col_path,
+                    dtype: array.dtype(),
+                    shape: Vec::new(),
+                    byte_order: ByteOrder::native(),
+                    validity: validity_path(&path, index),
+                    writable: false,
+                    mapped: OnceLock::from(column),
+                    computed: None,
+                },
+            );
+        }
+
+        Ok(Dataset {
+            path,
+            schema,
+            columns: lazy_columns,
+            row_count,
+            commit: None,
+            options: OpenOptions::new(),
+            advice: RwLock::new(None),
+            group_indexes: RwLock::new(HashMap::new()),
+            value_indexes: RwLock::new(HashMap::new()),
+            zone_maps: RwLock::new(HashMap::new()),
+            bloom_filters: RwLock::new(HashMap::new()),
         })
     }
 
+    /// Open only the columns `names` of the dataset in `path`.
+    ///
+    /// Other columns aren't checked, and the dataset's schema only lists
+    /// `names`, in that order. Returns an error if a name doesn't exist. Such
+    /// a dataset can't be appended to.
+    pub fn open_with_columns<P: AsRef<Path>>(path: P, names: &[&str]) -> Result<Self> {
+        OpenOptions::new().columns(names).open(path)
+    }
+
+    /// Generation and row count committed when the dataset was opened, see
+    /// [`Dataset::generation`].
+    pub(crate) fn commit(&self) -> Option<Commit> {
+        self.commit
+    }
+
+    /// Reopen the dataset with the options it was opened with.
+    pub(crate) fn reopen(&self) -> Result<Self> {
+        self.options.open(&self.path)
+    }
+
+    /// Check if only some columns were opened, see [`Dataset::open_with_columns`].
+    pub fn is_projected(&self) -> bool {
+        self.options.columns.is_some()
+    }
+
+    /// Give every column an access pattern hint, see [`Column::advise`].
+    ///
+    /// Columns that aren't mapped yet get the hint once they are.
+    pub fn advise(&self, advice: Advice) -> Result<()> {
+        *self.advice.write().unwrap() = Some(advice);
+        for (name, lazy) in &self.columns {
+            if let Some(column) = lazy.mapped.get() {
+                column.advise(advice).map_err(|e| e.with_path(&lazy.path).with_column(name))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get a column by name if it's already mapped.
+    pub(crate) fn column_if_mapped(&self, name: &str) -> Option<&Column> {
+        self.columns.get(name)?.mapped.get()
+    }
+
+    /// Get a column by name, mapping it if this is its first access.
+    ///
+    /// Returns an error if the column doesn't exist or can't be mapped.
+    pub(crate) fn mapped_column(&self, name: &str) -> Result<&Column> {
+        let lazy = self.columns.get(name).ok_or_else(|| self.column_not_found(name))?;
+        if let Some(column) = lazy.mapped.get() {
+            return Ok(column);
+        }
+        let column = self.open_column(name)?;
+        // Another thread may have mapped it first; keep theirs
+        let _ = lazy.mapped.set(column);
+        Ok(lazy.mapped.get().unwrap())
+    }
+
+    /// Map a column anew, without keeping it, e.g. for
+    /// [`BudgetedDataset`](crate::BudgetedDataset) to unmap it later.
+    ///
+    /// Returns an error if the column doesn't exist or can't be mapped.
+    pub(crate) fn open_column(&self, name: &str) -> Result<Column> {
+        let lazy = self.columns.get(name).ok_or_else(|| self.column_not_found(name))?;
+        if let Some(computed) = &lazy.computed {
+            return computed.evaluate(self, &lazy.path).map_err(|e| e.with_column(name));
+        }
+
+        // String columns are never writable; readers keep the rows counted on
+        // opening, even before a first append wrote the COMMIT file
+        let column = if lazy.writable && lazy.dtype != DType::Utf8 {
+            Column::open_mut_as(&lazy.path, lazy.dtype, self.options.buffered)
+        } else if !self.options.writable {
+            let values = self.row_count * lazy.shape.iter().product::<usize>();
+            let (dtype, byte_order) = (lazy.dtype, lazy.byte_order);
+            Column::open_prefix(&lazy.path, dtype, byte_order, self.options.buffered, values)
+        } else {
+            Column::open_as(&lazy.path, lazy.dtype, lazy.byte_order, self.options.buffered)
+        }
+        .and_then(|column| column.with_shape(&lazy.path, &lazy.shape))
+        .map_err(|e| e.with_column(name))?;
+        // The file may have changed since the dataset was opened
+        if column.len() != self.row_count {
+            return Err(MmappetError::LengthMismatch {
+                path: Some(self.path.clone()),
+                column: name.to_string(),
+                expected: self.row_count,
+                actual: column.len(),
+            });
+        }
+        if let Some(advice) = *self.advice.read().unwrap() {
+            column.advise(advice).map_err(|e| e.with_path(&lazy.path).with_column(name))?;
+        }
+        if self.options.huge_pages {
+            column.advise_huge_pages();
+        }
+        Ok(column)
+    }
+
+    /// Add the `float64` column `name`, computed on first access.
+    ///
+    /// Returns an error if the name is invalid or already taken.
+    pub(crate) fn push_computed(&mut self, name: &str, computed: Computed) -> Result<()> {
+        self.schema.push(name, DType::Float64).map_err(|e| e.with_path(&self.path))?;
+        // No such file exists, so the column has no validity bitmap either
+        let path = self.path.join(format!("{}.computed", name));
+        self.columns.insert(
+            name.to_string(),
+            LazyColumn {
+                path: path.clone(),
+                dtype: DType::Float64,
+                shape: Vec::new(),
+                byte_order: ByteOrder::native(),
+                validity: path,
+                writable: false,
+                mapped: OnceLock::new(),
+                computed: Some(computed),
+            },
+        );
+        Ok(())
+    }
+
+    /// Check if the column `name` is computed rather than stored, see
+    /// [`Dataset::with_computed`].
+    pub fn is_computed(&self, name: &str) -> bool {
+        self.columns.get(name).is_some_and(|lazy| lazy.computed.is_some())
+    }
+
+    /// Path of the file of a column, which may only exist encoded.
+    pub(crate) fn column_path(&self, name: &str) -> Result<&Path> {
+        let lazy = self.columns.get(name).ok_or_else(|| self.column_not_found(name))?;
+        Ok(&lazy.path)
+    }
+
+    /// Path of the validity bitmap of a column, see [`Dataset::nullable`].
+    pub(crate) fn validity_path(&self, name: &str) -> Result<&Path> {
+        let lazy = self.columns.get(name).ok_or_else(|| self.column_not_found(name))?;
+        Ok(&lazy.validity)
+    }
+
+    /// Ask the OS to start reading `rows` of the given columns into the page
+    /// cache in the background, so a later query doesn't wait on the disk.
+    ///
+    /// An empty `columns` prefetches every column. Returns immediately with
+    /// the number of bytes requested; like [`Dataset::advise`] this is only a
+    /// hint and does nothing on platforms without `madvise`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rows` is out of bounds.
+    pub fn prefetch(&self, columns: &[&str], rows: impl RangeBounds<usize>) -> Result<usize> {
+        let rows = checked_range(rows, self.row_count);
+        let names: Vec<&str> = if columns.is_empty() {
+            self.column_names().collect()
+        } else {
+            columns.to_vec()
+        };
+        let mut total = 0;
+        for name in names {
+            let column = self.mapped_column(name)?;
+            let size = column.row_size();
+            let bytes = rows.start * size..rows.end * size;
+            total += bytes.len();
+            column
+                .advise_bytes(Advice::WillNeed, bytes)
+                .map_err(|e| e.with_path(&self.path).with_column(name))?;
+        }
+        Ok(total)
+    }
+
     /// Get the schema.
     pub fn schema(&self) -> &Schema {
         &self.schema
     }
 
     /// Get a column by name.
+    ///
+    /// Returns `None` if the column doesn't exist or its file can't be
+    /// mapped; [`Dataset::get`] reports which.
     pub fn column(&self, name: &str) -> Option<&Column> {
-        self.columns.get(name)
+        self.mapped_column(name).ok()
     }
 
     /// Get a typed slice directly by name.
     ///
     /// Returns an error if the column doesn't exist or the type doesn't match.
     pub fn get<T: MmappetType>(&self, name: &str) -> Result<&[T]> {
-        let column = self
-            .columns
-            .get(name)
-            .ok_or_else(|| MmappetError::ColumnNotFound(name.to_string()))?;
+        let column = self.mapped_column(name)?;
 
         column.as_slice::<T>().ok_or_else(|| MmappetError::TypeMismatch {
+            column: Some(name.to_string()),
             expected: T::DTYPE,
             actual: column.dtype(),
         })
     }
 
+    /// Get a mutable typed slice by name, for datasets opened with
+    /// [`Dataset::open_mut`].
+    ///
+    /// Returns an error if the column doesn't exist, the type doesn't match or
+    /// the column is read-only. Persisted indexes, zone maps and Bloom filters
+    /// of the column are removed, since they may no longer match the data.
+    pub fn get_mut<T: MmappetType>(&mut self, name: &str) -> Result<&mut [T]> {
+        let path = self.path.clone();
+        self.mapped_column(name)?;
+        let column = self.columns.get_mut(name).unwrap().mapped.get_mut().unwrap();
+        if !column.is_writable() {
+            return Err(MmappetError::ReadOnly {
+                path,
+                column: name.to_string(),
+            });
+        }
+        if column.dtype() != T::DTYPE {
+            return Err(MmappetError::TypeMismatch {
+                column: Some(name.to_string()),
+                expected: T::DTYPE,
+                actual: column.dtype(),
+            });
+        }
+
+        self.group_indexes.get_mut().unwrap().remove(name);
+        self.value_indexes.get_mut().unwrap().remove(name);
+        self.zone_maps.get_mut().unwrap().remove(name);
+        self.bloom_filters.get_mut().unwrap().remove(name);
+        for extension in ["groups", "idx", "zones", "bloom"] {
+            let index_path = path.join(format!("{}.{}", name, extension));
+            if index_path.exists() {
+                std::fs::remove_file(&index_path).map_err(|e| MmappetError::io(&index_path, e))?;
+            }
+        }
+        Ok(column.as_slice_mut::<T>().unwrap())
+    }
+
+    /// Write all modified columns back to disk.
+    ///
+    /// Does nothing for datasets opened read-only.
+    pub fn flush(&self) -> Result<()> {
+        for column in self.columns.values().filter_map(|lazy| lazy.mapped.get()) {
+            column.flush()?;
+        }
+        Ok(())
+    }
+
     /// Get a typed ArrayView1 directly by name.
     ///
     /// Returns an error if the column doesn't exist or the type doesn't match.
-    pub fn get_array<T: MmappetType>(&self, name: &str) -> Result<ArrayView1<T>> {
-        let column = self
-            .columns
-            .get(name)
-            .ok_or_else(|| MmappetError::ColumnNotFound(name.to_string()))?;
+    pub fn get_array<T: MmappetType>(&self, name: &str) -> Result<ArrayView1<'_, T>> {
+        let column = self.mapped_column(name)?;
 
         column
             .as_array::<T>()
             .ok_or_else(|| MmappetError::TypeMismatch {
+                column: Some(name.to_string()),
+                expected: T::DTYPE,
+                actual: column.dtype(),
+            })
+    }
+
+    /// Get the rows of an array column such as `float32[4]` as a typed
+    /// ArrayView2 of shape `(rows, width)`; other columns have width 1.
+    ///
+    /// Returns an error if the column doesn't exist or the type doesn't match.
+    pub fn get_array2<T: MmappetType>(&self, name: &str) -> Result<ArrayView2<'_, T>> {
+        let column = self.mapped_column(name)?;
+
+        column
+            .as_array2::<T>()
+            .ok_or_else(|| MmappetError::TypeMismatch {
+                column: Some(name.to_string()),
+                expected: T::DTYPE,
+                actual: column.dtype(),
+            })
+    }
+
+    /// Get the rows of a column as a typed ArrayViewD of shape
+    /// `(rows, ..shape)`, e.g. `(rows, 3, 4)` for `float32[3,4]`.
+    ///
+    /// Returns an error if the column doesn't exist or the type doesn't match.
+    pub fn get_array_d<T: MmappetType>(&self, name: &str) -> Result<ArrayViewD<'_, T>> {
+        let column = self.mapped_column(name)?;
+
+        column
+            .as_array_d::<T>()
+            .ok_or_else(|| MmappetError::TypeMismatch {
+                column: Some(name.to_string()),
                 expected: T::DTYPE,
                 actual: column.dtype(),
             })
     }
 
+    /// Gather columns of the same type into a `(rows, columns)` matrix.
+    ///
+    /// The data is copied, column `j` of the result holding `names[j]`.
+    /// Returns an error if any column doesn't exist, holds arrays or the type
+    /// doesn't match.
+    pub fn as_matrix<T: MmappetType>(&self, names: &[&str]) -> Result<Array2<T>> {
+        let mut matrix = Array2::from_elem((self.row_count, names.len()), T::zeroed());
+        for (j, name) in names.iter().enumerate() {
+            self.mapped_column(name)?.check_scalar(name)?;
+            matrix.column_mut(j).assign(&self.get_array::<T>(name)?);
+        }
+        Ok(matrix)
+    }
+
     /// Number of rows (all columns have same length).
     pub fn len(&self) -> usize {
         self.row_count
@@ -131,6 +651,123 @@ impl Dataset {
     pub fn path(&self) -> &Path {
         &self.path
     }
+
+    /// Error for a column missing from this dataset.
+    pub(crate) fn column_not_found(&self, name: &str) -> MmappetError {
+        MmappetError::ColumnNotFound {
+            path: Some(self.path.clone()),
+            column: name.to_string(),
+        }
+    }
+
+    pub(crate) fn cached_group_index(&self, key: &str) -> Option<Arc<GroupIndex>> {
+        self.group_indexes.read().unwrap().get(key).cloned()
+    }
+
+    pub(crate) fn cache_group_index(&self, key: &str, index: Arc<GroupIndex>) {
+        self.group_indexes
+            .write()
+            .unwrap()
+            .insert(key.to_string(), index);
+    }
+
+    pub(crate) fn cached_value_index(&self, name: &str) -> Option<Option<Arc<ValueIndex>>> {
+        self.value_indexes.read().unwrap().get(name).cloned()
+    }
+
+    pub(crate) fn cache_value_index(&self, name: &str, index: Option<Arc<ValueIndex>>) {
+        self.value_indexes
+            .write()
+            .unwrap()
+            .insert(name.to_string(), index);
+    }
+
+    pub(crate) fn cached_zone_map(&self, name: &str) -> Option<Option<Arc<ZoneMap>>> {
+        self.zone_maps.read().unwrap().get(name).cloned()
+    }
+
+    pub(crate) fn cache_zone_map(&self, name: &str, zones: Option<Arc<ZoneMap>>) {
+        self.zone_maps
+            .write()
+            .unwrap()
+            .insert(name.to_string(), zones);
+    }
+
+    pub(crate) fn cached_bloom_filter(&self, name: &str) -> Option<Option<Arc<BloomFilter>>> {
+        self.bloom_filters.read().unwrap().get(name).cloned()
+    }
+
+    pub(crate) fn cache_bloom_filter(&self, name: &str, bloom: Option<Arc<BloomFilter>>) {
+        self.bloom_filters
+            .write()
+            .unwrap()
+            .insert(name.to_string(), bloom);
+    }
+}
+
+/// Locate the existing file backing a column of the dataset in `dir` with
+/// `schema`, and the byte order of its values.
+pub(crate) fn column_file(
+    dir: &Path,
+    schema: &Schema,
+    col_def: &ColumnDef,
+) -> Result<(PathBuf, ByteOrder)> {
+    let (col_path, byte_order) = match &col_def.reference {
+        Some(reference) => resolve_reference(dir, col_def, reference)?,
+        None => (dir.join(format!("{}.bin", col_def.index)), schema.byte_order()),
+    };
+
+    if !col_path.exists() && EncodedFile::find(&col_path).is_none() {
+        return Err(MmappetError::MissingColumnFile {
+            path: col_path,
+            column: col_def.name.clone(),
+        });
+    }
+    Ok((col_path, byte_order))
+}
+
+/// Locate the file backing a column stored in another dataset, which may be in
+/// another byte order.
+fn resolve_reference(
+    dir: &Path,
+    col_def: &ColumnDef,
+    reference: &ColumnRef,
+) -> Result<(PathBuf, ByteOrder)> {
+    let invalid = |message: String| MmappetError::InvalidReference {
+        path: Some(dir.to_path_buf()),
+        column: col_def.name.clone(),
+        message,
+    };
+
+    let target_dir = dir.join(&reference.dataset);
+    let target_schema = Schema::from_path(&target_dir)?;
+    let target = target_schema.get(&reference.column).ok_or_else(|| {
+        invalid(format!(
+            "column '{}' not found in {}",
+            reference.column,
+            target_dir.display()
+        ))
+    })?;
+
+    if target.reference.is_some() {
+        return Err(invalid(format!(
+            "'{}' in {} is itself a reference",
+            reference.column,
+            target_dir.display()
+        )));
+    }
+    if target.dtype != col_def.dtype {
+        return Err(MmappetError::TypeMismatch {
+            column: Some(col_def.name.clone()),
+            expected: col_def.dtype,
+            actual: target.dtype,
+        });
+    }
+
+    Ok((
+        target_dir.join(format!("{}.bin", target.index)),
+        target_schema.byte_order(),
+    ))
 }
 
 // Dictionary-style indexing via Index trait
@@ -138,8 +775,353 @@ impl Index<&str> for Dataset {
     type Output = Column;
 
     fn index(&self, name: &str) -> &Self::Output {
-        self.columns
-            .get(name)
-            .unwrap_or_else(|| panic!("Column not found: {}", name))
+        self.mapped_column(name).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::column::TypedArrayView;
+    use crate::test_util::write_dataset;
+    use crate::writer::DatasetWriter;
+    use std::fs;
+
+    #[test]
+    fn test_from_columns() {
+        let ds = Dataset::from_columns(vec![
+            ("frame", vec![1u32, 1, 2].into()),
+            ("mz", vec![100.5f64, 200.0, 300.25].into()),
+            ("peptide", vec!["A", "BC", ""].into()),
+            (
+                "protein",
+                TypedArray::Categorical {
+                    codes: vec![1, 0, 1],
+                    categories: vec!["P1".to_string(), "P2".to_string()],
+                },
+            ),
+        ])
+        .unwrap();
+        assert_eq!((ds.len(), ds.path()), (3, Path::new(":memory:")));
+        assert_eq!(ds.get::<f64>("mz").unwrap(), &[100.5, 200.0, 300.25]);
+        assert_eq!(ds["peptide"].as_str_array().unwrap().get(1), "BC");
+        assert_eq!(ds["protein"].as_categorical().unwrap().value(0), "P2");
+        let frame_one = crate::expr::col("frame").eq(1u32);
+        assert_eq!(ds.matching_rows(&frame_one).unwrap().collect::<Vec<_>>(), [0, 1]);
+        assert!(!ds.nullable("mz").unwrap().has_validity());
+
+        assert!(matches!(
+            Dataset::from_columns(vec![("a", vec![1u8].into()), ("b", vec![1u8, 2].into())]),
+            Err(MmappetError::LengthMismatch { .. })
+        ));
+        assert!(matches!(
+            Dataset::from_columns(vec![("a", vec![1u8].into()), ("a", vec![2u8].into())]),
+            Err(MmappetError::DuplicateColumnName { .. })
+        ));
+        let bad_code = TypedArray::Categorical { codes: vec![2], categories: Vec::new() };
+        assert!(Dataset::from_columns(vec![("c", bad_code)]).is_err());
+    }
+
+    #[test]
+    fn test_as_matrix() {
+        let dir = write_dataset("matrix", &[("a", &[1, 2, 3]), ("b", &[4, 5, 6])]);
+        let ds = Dataset::open(&dir).unwrap();
+
+        let m = ds.as_matrix::<u32>(&["b", "a"]).unwrap();
+        assert_eq!(m.shape(), &[3, 2]);
+        assert_eq!(m.row(1).to_vec(), vec![5, 2]);
+
+        assert!(matches!(
+            ds.as_matrix::<f32>(&["a"]),
+            Err(MmappetError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            ds.as_matrix::<u32>(&["missing"]),
+            Err(MmappetError::ColumnNotFound { .. })
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_array_column() {
+        let dir = std::env::temp_dir().join(format!("mmappet-array-{}", std::process::id()));
+        let mut schema = Schema::new(&[("id", DType::UInt32), ("q", DType::Float32)]).unwrap();
+        schema.set_shape("q", &[4]).unwrap();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write::<u32>("id", &[1, 2, 3]).unwrap();
+        assert!(matches!(
+            writer.write::<f32>("q", &[1.0, 0.0]),
+            Err(MmappetError::InvalidBatch { .. })
+        ));
+        let q: Vec<f32> = (0..12).map(|x| x as f32).collect();
+        writer.write::<f32>("q", &q).unwrap();
+        assert_eq!(writer.finish().unwrap(), 3);
+
+        let mut ds = Dataset::open(&dir).unwrap();
+        assert_eq!((ds.len(), ds["q"].width()), (3, 4));
+        let rows = ds.get_array2::<f32>("q").unwrap();
+        assert_eq!(rows.shape(), &[3, 4]);
+        assert_eq!(rows.row(1).to_vec(), vec![4.0, 5.0, 6.0, 7.0]);
+        assert_eq!(ds.slice(2..).get::<f32>("q").unwrap(), &q[8..]);
+        assert!(matches!(
+            ds.argsort("q"),
+            Err(MmappetError::NotScalar { width: 4, .. })
+        ));
+
+        // Copies keep the width; appended rows hold `width` values each
+        let copy = dir.with_extension("copy");
+        ds.write_permuted(&[2, 0], &copy).unwrap();
+        let copied = Dataset::open(&copy).unwrap();
+        assert_eq!(copied.get::<f32>("q").unwrap()[..5], [8.0, 9.0, 10.0, 11.0, 0.0]);
+        let extra = [-1.0f32; 4];
+        let batch = [
+            ("id", TypedArrayView::UInt32(ArrayView1::from(&[4u32]))),
+            ("q", TypedArrayView::Float32(ArrayView1::from(&extra))),
+        ];
+        assert_eq!(ds.append_batch(&batch).unwrap(), 4);
+        assert_eq!(ds.get_array2::<f32>("q").unwrap().row(3).to_vec(), extra);
+
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(copy).unwrap();
+    }
+
+    #[test]
+    fn test_matrix_column() {
+        let dir = std::env::temp_dir().join(format!("mmappet-matrix-{}", std::process::id()));
+        let mut schema = Schema::new(&[("grid", DType::UInt16)]).unwrap();
+        schema.set_shape("grid", &[2, 3]).unwrap();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write::<u16>("grid", &(0..12).collect::<Vec<_>>()).unwrap();
+        assert_eq!(writer.finish().unwrap(), 2);
+
+        let ds = Dataset::open(&dir).unwrap();
+        assert_eq!(ds["grid"].shape(), &[2, 3]);
+        let grids = ds.get_array_d::<u16>("grid").unwrap();
+        assert_eq!(grids.shape(), &[2, 2, 3]);
+        assert_eq!(grids[[1, 0, 2]], 8);
+        assert_eq!(ds.get_array2::<u16>("grid").unwrap().shape(), &[2, 6]);
+
+        // Copies keep the shape
+        let copy = dir.with_extension("copy");
+        ds.write_permuted(&[1, 0], &copy).unwrap();
+        let copied = Dataset::open(&copy).unwrap();
+        assert_eq!(copied.schema().get("grid").unwrap().type_name(), "uint16[2,3]");
+        assert_eq!(copied.get_array_d::<u16>("grid").unwrap()[[1, 0, 2]], 2);
+
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(copy).unwrap();
+    }
+
+    #[test]
+    fn test_foreign_byte_order() {
+        let values = [1u32, 2, 0x0102_0304];
+        let swapped: Vec<u32> = values.iter().map(|v| v.swap_bytes()).collect();
+        let dir = write_dataset("byte-order", &[("a", &swapped)]);
+        let foreign = match ByteOrder::native() {
+            ByteOrder::Little => ByteOrder::Big,
+            ByteOrder::Big => ByteOrder::Little,
+        };
+        fs::write(
+            dir.join("schema.txt"),
+            format!("# mmappet-byte-order {}\nuint32 a\n", foreign),
+        )
+        .unwrap();
+
+        let mut ds = Dataset::open(&dir).unwrap();
+        assert_eq!(ds.get::<u32>("a").unwrap(), &values);
+        assert!(ds["a"].is_swapped());
+        assert!(matches!(
+            Dataset::open_mut(&dir),
+            Err(MmappetError::ForeignByteOrder { .. })
+        ));
+        let more = [4u32];
+        assert!(matches!(
+            ds.append_batch(&[("a", TypedArrayView::UInt32(ArrayView1::from(&more)))]),
+            Err(MmappetError::ForeignByteOrder { .. })
+        ));
+
+        // Checksums are of the file, whatever the host
+        ds.record_checksums("test").unwrap();
+        let file = fs::read(dir.join("0.bin")).unwrap();
+        let expected = format!("crc32:{:08x}", crc32fast::hash(&file));
+        assert_eq!(ds.metadata().unwrap()["checksum.a"], expected);
+        assert!(Dataset::validate(&dir).unwrap().is_ok());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_open_mut() {
+        let dir = write_dataset("open-mut", &[("frame", &[1, 1, 2]), ("tof", &[10, 20, 30])]);
+        let mut ds = Dataset::open(&dir).unwrap();
+        assert!(matches!(ds.get_mut::<u32>("tof"), Err(MmappetError::ReadOnly { .. })));
+
+        ds.build_group_index("frame").unwrap();
+        let mut ds = Dataset::open_mut(&dir).unwrap();
+        for tof in ds.get_mut::<u32>("tof").unwrap() {
+            *tof *= 2;
+        }
+        ds.get_mut::<u32>("frame").unwrap()[2] = 3;
+        assert!(!dir.join("frame.groups").exists());
+        assert!(matches!(
+            ds.get_mut::<f32>("tof"),
+            Err(MmappetError::TypeMismatch { .. })
+        ));
+        ds.flush().unwrap();
+        drop(ds);
+
+        let ds = Dataset::open(&dir).unwrap();
+        assert_eq!(ds.get::<u32>("tof").unwrap(), &[20, 40, 60]);
+        assert_eq!(ds.get::<u32>("frame").unwrap(), &[1, 1, 3]);
+
+        // Files deduplicated into hard links get their own copy, mapped or not
+        let other = write_dataset("open-mut-deduped", &[("frame", &[1, 1, 3])]);
+        for buffered in [false, true] {
+            fs::remove_file(other.join("0.bin")).unwrap();
+            fs::hard_link(dir.join("0.bin"), other.join("0.bin")).unwrap();
+            let mut ds = OpenOptions::new()
+                .writable(true)
+                .buffered(buffered)
+                .open(&other)
+                .unwrap();
+            ds.get_mut::<u32>("frame").unwrap()[0] = 7;
+            drop(ds);
+            let linked = Dataset::open(&dir).unwrap();
+            assert_eq!(linked.get::<u32>("frame").unwrap(), &[1, 1, 3]);
+        }
+        assert_eq!(Dataset::open(&other).unwrap().get::<u32>("frame").unwrap(), &[7, 1, 3]);
+
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(other).unwrap();
+    }
+
+    #[test]
+    fn test_lazy_columns() {
+        let dir = write_dataset("lazy", &[("a", &[1, 2]), ("b", &[3, 4])]);
+        let ds = Dataset::open(&dir).unwrap();
+        assert_eq!(ds.get::<u32>("a").unwrap(), &[1, 2]);
+
+        // "b" was never mapped, so its file is only needed on first access
+        fs::remove_file(dir.join("1.bin")).unwrap();
+        assert!(matches!(ds.get::<u32>("b"), Err(MmappetError::Io { .. })));
+        assert!(ds.column("b").is_none());
+        assert_eq!(ds["a"].len(), 2);
+
+        // Sizes are still checked at open
+        fs::write(dir.join("1.bin"), [0u8; 4]).unwrap();
+        assert!(matches!(
+            Dataset::open(&dir),
+            Err(MmappetError::LengthMismatch { .. })
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_open_with_columns() {
+        let dir = write_dataset("projection", &[("a", &[1, 2]), ("b", &[3, 4]), ("c", &[5, 6])]);
+        // Unrequested columns aren't checked
+        fs::write(dir.join("1.bin"), [0u8; 4]).unwrap();
+        assert!(Dataset::open(&dir).is_err());
+
+        let mut ds = Dataset::open_with_columns(&dir, &["c", "a"]).unwrap();
+        assert!(ds.is_projected());
+        assert_eq!(ds.schema().column_names(), vec!["c", "a"]);
+        assert_eq!(ds.get::<u32>("c").unwrap(), &[5, 6]);
+        assert!(ds.column("b").is_none());
+        assert!(matches!(
+            ds.append_batch(&[]),
+            Err(MmappetError::InvalidBatch { .. })
+        ));
+
+        assert!(matches!(
+            Dataset::open_with_columns(&dir, &["a", "missing"]),
+            Err(MmappetError::ColumnNotFound { .. })
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_open_options() {
+        let dir = write_dataset("open-options", &[("tof", &[10, 20, 30])]);
+        let ds = OpenOptions::new()
+            .advice(Advice::Sequential)
+            .open(&dir)
+            .unwrap();
+        assert!(!ds["tof"].is_writable());
+
+        // Dropped pages are read back from the file
+        ds.advise(Advice::DontNeed).unwrap();
+        assert_eq!(ds.get::<u32>("tof").unwrap(), &[10, 20, 30]);
+        ds["tof"].advise(Advice::Random).unwrap();
+
+        let ds = OpenOptions::new()
+            .writable(true)
+            .huge_pages(true)
+            .open(&dir)
+            .unwrap();
+        assert!(ds["tof"].is_writable());
+        assert_eq!(ds.get::<u32>("tof").unwrap(), &[10, 20, 30]);
+
+        assert_eq!(ds.prefetch(&["tof"], 1..).unwrap(), 8);
+        assert_eq!(ds.prefetch(&[], ..).unwrap(), 12);
+        assert!(matches!(
+            ds.prefetch(&["missing"], ..),
+            Err(MmappetError::ColumnNotFound { .. })
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_open_buffered() {
+        let dir = write_dataset("buffered", &[("tof", &[10, 20, 30])]);
+        let ds = OpenOptions::new().buffered(true).open(&dir).unwrap();
+        assert!(!ds["tof"].is_mapped());
+        assert_eq!(ds.get::<u32>("tof").unwrap(), &[10, 20, 30]);
+        assert_eq!(ds.memory_report().unwrap().resident_bytes(), Some(12));
+
+        // Writable buffers are written back to the file
+        let mut ds = OpenOptions::new().buffered(true).writable(true).open(&dir).unwrap();
+        ds.get_mut::<u32>("tof").unwrap()[1] = 25;
+        drop(ds);
+        assert_eq!(Dataset::open(&dir).unwrap().get::<u32>("tof").unwrap(), &[10, 25, 30]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_reference_column() {
+        let shared = write_dataset("ref-shared", &[("axis", &[7, 8, 9])]);
+        let dir = write_dataset("ref-frame", &[("intensity", &[1, 2, 3])]);
+        let shared_name = shared.file_name().unwrap().to_str().unwrap();
+        fs::write(
+            dir.join("schema.txt"),
+            format!("uint32 intensity\nuint32 axis @../{}/axis\n", shared_name),
+        )
+        .unwrap();
+
+        let ds = Dataset::open(&dir).unwrap();
+        assert_eq!(ds.get::<u32>("axis").unwrap(), &[7, 8, 9]);
+        assert!(!dir.join("1.bin").exists());
+
+        fs::write(
+            dir.join("schema.txt"),
+            format!("uint32 intensity\nfloat32 axis @../{}/axis\n", shared_name),
+        )
+        .unwrap();
+        assert!(matches!(Dataset::open(&dir), Err(MmappetError::TypeMismatch { .. })));
+
+        fs::write(
+            dir.join("schema.txt"),
+            format!("uint32 intensity\nuint32 axis @../{}/missing\n", shared_name),
+        )
+        .unwrap();
+        assert!(matches!(Dataset::open(&dir), Err(MmappetError::InvalidReference { .. })));
+
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(shared).unwrap();
     }
 }
@@ -0,0 +1,123 @@
+//! Finding and dropping duplicate rows.
+
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+use crate::dataset::Dataset;
+use crate::error::Result;
+use crate::nullable::NullableColumn;
+use crate::strings::RowBytes;
+
+/// Rows hashed at a time, one key column after the other.
+const HASH_CHUNK_ROWS: usize = 1 << 16;
+
+impl Dataset {
+    /// Mask keeping the first row of each distinct combination of values of
+    /// the columns `keys`, or of all columns if `keys` is empty.
+    ///
+    /// Rows are hashed chunk by chunk and only rows with equal hashes are
+    /// compared, so memory grows with the number of distinct rows, not with
+    /// the size of the keys. Values are compared as [`crate::diff`] compares
+    /// them by default: strings by text, categorical columns by label and
+    /// everything else bitwise; nulls only equal nulls. Returns an error if a
+    /// column doesn't exist.
+    ///
+    /// ```rust,no_run
+    /// let peaks = mmappet::Dataset::open("merged.mmappet")?;
+    /// let keep = peaks.unique_rows(&["frame", "scan", "tof"])?;
+    /// peaks.filter(&keep)?.write_to("deduplicated.mmappet")?;
+    /// # Ok::<(), mmappet::MmappetError>(())
+    /// ```
+    pub fn unique_rows(&self, keys: &[&str]) -> Result<Vec<bool>> {
+        let names: Vec<&str> = match keys.is_empty() {
+            true => self.column_names().collect(),
+            false => keys.to_vec(),
+        };
+        let columns = names
+            .iter()
+            .map(|name| self.nullable(name))
+            .collect::<Result<Vec<_>>>()?;
+        let bytes: Vec<RowBytes> = columns.iter().map(|c| c.column().row_bytes()).collect();
+        let columns: Vec<_> = columns.iter().zip(&bytes).collect();
+        let same_keys = |a: usize, b: usize| columns.iter().all(|&c| key(c, a) == key(c, b));
+
+        let mut keep = vec![true; self.len()];
+        // First row with each hash, and other distinct rows with that hash
+        let mut first_rows: HashMap<u64, usize> = HashMap::new();
+        let mut collisions: HashMap<u64, Vec<usize>> = HashMap::new();
+        let mut hashes = Vec::with_capacity(HASH_CHUNK_ROWS);
+        for start in (0..self.len()).step_by(HASH_CHUNK_ROWS) {
+            let rows = start..(start + HASH_CHUNK_ROWS).min(self.len());
+            hashes.clear();
+            hashes.resize(rows.len(), 0);
+            for &column in &columns {
+                for (hash, row) in hashes.iter_mut().zip(rows.clone()) {
+                    let mut hasher = DefaultHasher::new();
+                    hasher.write_u64(*hash);
+                    key(column, row).hash(&mut hasher);
+                    *hash = hasher.finish();
+                }
+            }
+
+            for (&hash, row) in hashes.iter().zip(rows) {
+                let first = match first_rows.entry(hash) {
+                    Entry::Vacant(entry) => {
+                        entry.insert(row);
+                        continue;
+                    }
+                    Entry::Occupied(entry) => *entry.get(),
+                };
+                if same_keys(first, row) {
+                    keep[row] = false;
+                    continue;
+                }
+                let others = collisions.entry(hash).or_default();
+                if others.iter().any(|&other| same_keys(other, row)) {
+                    keep[row] = false;
+                } else {
+                    others.push(row);
+                }
+            }
+        }
+        Ok(keep)
+    }
+
+    /// Write the rows kept by [`Dataset::unique_rows`] to a new dataset in
+    /// `dir`. Returns the number of rows written.
+    pub fn write_unique<P: AsRef<Path>>(&self, keys: &[&str], dir: P) -> Result<usize> {
+        self.filter(&self.unique_rows(keys)?)?.write_to(dir)
+    }
+}
+
+/// The key of `row` in a column: `None` for nulls, else the bytes of its
+/// text, label or values.
+fn key<'a>(
+    (column, bytes): (&NullableColumn<'a>, &RowBytes<'a>),
+    row: usize,
+) -> Option<&'a [u8]> {
+    column.is_valid(row).then(|| bytes.get(row))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unique_rows() {
+        let ds = Dataset::from_columns(vec![
+            ("frame", vec![1u32, 1, 2, 1, 2].into()),
+            ("tof", vec![10u32, 11, 10, 10, 10].into()),
+            ("peptide", vec!["PEP", "TIDE", "PEP", "PEP", "K"].into()),
+        ])
+        .unwrap();
+
+        let keep = ds.unique_rows(&["frame", "tof"]).unwrap();
+        assert_eq!(keep, [true, true, true, false, false]);
+        let keep = ds.unique_rows(&[]).unwrap();
+        assert_eq!(keep, [true, true, true, false, true]);
+        assert_eq!(ds.unique_rows(&["peptide"]).unwrap(), [true, true, false, false, true]);
+        assert!(ds.unique_rows(&["mz"]).is_err());
+    }
+}
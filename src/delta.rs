@@ -0,0 +1,370 @@
+//! Delta-encoded integer columns.
+//!
+//! A sorted integer column written with
+//! [`DatasetWriter::delta_encode`](crate::DatasetWriter::delta_encode) is
+//! stored in `{index}.bin.delta` instead of `{index}.bin`, as the differences
+//! between consecutive values in LEB128 varints. Every [`SAMPLE_EVERY`]
+//! values a sample holds the value itself and where the varints of the
+//! following ones start:
+//!
+//! ```text
+//! magic    b"MMPD"
+//! version  u32, 1
+//! length   u64, number of values
+//! every    u64, values per sample
+//! samples  per sample a u64 value (sign-extended) and a u64 offset into
+//!          the varints
+//! varints  the differences to the previous value, except for sampled values
+//! ```
+//!
+//! Integers are little-endian. A delta-encoded column is decoded into memory
+//! when first accessed and is read-only. The samples let
+//! [`Dataset::range_by`](crate::Dataset::range_by) binary search a column
+//! that isn't decoded yet, decoding one block per bound.
+
+use std::path::{Path, PathBuf};
+
+
+use crate::dtype::{DType, MmappetType};
+use crate::error::{MmappetError, Result};
+use crate::storage::Storage;
+
+const MAGIC: &[u8; 4] = b"MMPD";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 24;
+
+/// Values per sample.
+pub(crate) const SAMPLE_EVERY: usize = 4096;
+
+/// Integer types that can be delta-encoded.
+trait DeltaInt: MmappetType + Ord {
+    /// Difference to a smaller or equal `prev`.
+    fn delta(self, prev: Self) -> u64;
+    /// Value `delta` above this one, wrapping on invalid input.
+    fn add(self, delta: u64) -> Self;
+    /// Sign-extended bits.
+    fn to_bits(self) -> u64;
+    fn from_bits(bits: u64) -> Self;
+}
+
+macro_rules! delta_int {
+    ($($t:ty),*) => {$(
+        impl DeltaInt for $t {
+            fn delta(self, prev: Self) -> u64 {
+                (self as i128 - prev as i128) as u64
+            }
+
+            fn add(self, delta: u64) -> Self {
+                (self as i128).wrapping_add(delta as i128) as $t
+            }
+
+            fn to_bits(self) -> u64 {
+                self as u64
+            }
+
+            fn from_bits(bits: u64) -> Self {
+                bits as $t
+            }
+        }
+    )*};
+}
+
+delta_int!(u8, i8, u16, i16, u32, i32, u64, i64);
+
+/// Call `$f::<T>` with the integer type `T` stored by `$dtype`.
+macro_rules! dispatch {
+    ($dtype:expr, $f:ident, $($arg:expr),*) => {
+        match $dtype {
+            DType::UInt8 => $f::<u8>($($arg),*),
+            DType::Int8 => $f::<i8>($($arg),*),
+            DType::UInt16 => $f::<u16>($($arg),*),
+            DType::Int16 => $f::<i16>($($arg),*),
+            DType::UInt32 => $f::<u32>($($arg),*),
+            DType::Int32 => $f::<i32>($($arg),*),
+            DType::UInt64 => $f::<u64>($($arg),*),
+            DType::Int64 | DType::TimestampMillis | DType::TimestampMicros => {
+                $f::<i64>($($arg),*)
+            }
+            _ => unreachable!("not an integer dtype"),
+        }
+    };
+}
+
+/// Return an error unless `dtype` is an integer dtype.
+pub(crate) fn check_dtype(name: &str, dtype: DType) -> Result<()> {
+    if dtype.is_integer() {
+        return Ok(());
+    }
+    Err(MmappetError::InvalidDType {
+        dtype: dtype.to_string(),
+        message: format!("delta encoding needs an integer column, '{}' isn't", name),
+    })
+}
+
+/// Path of the delta-encoded file of the column file `path`.
+pub(crate) fn delta_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".delta");
+    PathBuf::from(name)
+}
+
+/// The delta-encoded file standing in for the column file `path`, if `path`
+/// doesn't exist but its delta-encoded file does.
+pub(crate) fn delta_file(path: &Path) -> Option<PathBuf> {
+    if path.exists() {
+        return None;
+    }
+    let encoded = delta_path(path);
+    encoded.exists().then_some(encoded)
+}
+
+/// Replace the file `path` of the `dtype` column `name` by its delta-encoded
+/// file. Returns an error if the values decrease.
+pub(crate) fn encode_file(path: &Path, name: &str, dtype: DType) -> Result<()> {
+    let raw = std::fs::read(path).map_err(|e| MmappetError::io(path, e))?;
+    let bytes = dispatch!(dtype, encode, name, &raw)?;
+    let target = delta_path(path);
+    std::fs::write(&target, bytes).map_err(|e| MmappetError::io(&target, e))?;
+    std::fs::remove_file(path).map_err(|e| MmappetError::io(path, e))
+}
+
+fn encode<T: DeltaInt>(name: &str, raw: &[u8]) -> Result<Vec<u8>> {
+    let values: Vec<T> = raw
+        .chunks_exact(std::mem::size_of::<T>())
+        .map(bytemuck::pod_read_unaligned)
+        .collect();
+    if let Some(row) = values.windows(2).position(|w| w[1] < w[0]) {
+        return Err(MmappetError::InvalidBatch {
+            path: None,
+            message: format!(
+                "column '{}' decreases at row {}, can't delta-encode it",
+                name,
+                row + 1
+            ),
+        });
+    }
+
+    let mut samples = Vec::new();
+    let mut varints = Vec::new();
+    for block in values.chunks(SAMPLE_EVERY) {
+        samples.push((block[0].to_bits(), varints.len() as u64));
+        for pair in block.windows(2) {
+            write_varint(&mut varints, pair[1].delta(pair[0]));
+        }
+    }
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + 16 * samples.len() + varints.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(values.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&(SAMPLE_EVERY as u64).to_le_bytes());
+    for (value, offset) in samples {
+        bytes.extend_from_slice(&value.to_le_bytes());
+        bytes.extend_from_slice(&offset.to_le_bytes());
+    }
+    bytes.extend_from_slice(&varints);
+    Ok(bytes)
+}
+
+/// A mapped delta-encoded file.
+struct DeltaFile<'a> {
+    path: &'a Path,
+    mmap: Storage,
+    len: usize,
+    every: usize,
+}
+
+impl<'a> DeltaFile<'a> {
+    fn open(path: &'a Path) -> Result<Self> {
+        let mmap = Storage::open(path, false)?;
+        if mmap.len() < HEADER_LEN || &mmap[..4] != MAGIC {
+            return Err(invalid(path, "not a delta-encoded column file"));
+        }
+        let version = u32::from_le_bytes(mmap[4..8].try_into().unwrap());
+        if version != VERSION {
+            return Err(invalid(path, &format!("unknown version {}", version)));
+        }
+        let len = read_u64(&mmap, 8) as usize;
+        let every = read_u64(&mmap, 16) as usize;
+        if every == 0 {
+            return Err(invalid(path, "zero values per sample"));
+        }
+        let delta = DeltaFile { path, mmap, len, every };
+        if delta.mmap.len() < delta.varints_start() {
+            return Err(invalid(path, "truncated samples"));
+        }
+        Ok(delta)
+    }
+
+    fn samples(&self) -> usize {
+        self.len.div_ceil(self.every)
+    }
+
+    fn varints_start(&self) -> usize {
+        HEADER_LEN + 16 * self.samples()
+    }
+
+    /// First value of block `block` and the offset of its varints.
+    fn sample<T: DeltaInt>(&self, block: usize) -> (T, usize) {
+        let at = HEADER_LEN + 16 * block;
+        (T::from_bits(read_u64(&self.mmap, at)), read_u64(&self.mmap, at + 8) as usize)
+    }
+
+    /// Decode block `block` into `out`, which must have its length.
+    fn decode_block<T: DeltaInt>(&self, block: usize, out: &mut [T]) -> Result<()> {
+        let (mut value, offset) = self.sample::<T>(block);
+        let varints = &self.mmap[self.varints_start()..];
+        let mut pos = offset;
+        out[0] = value;
+        for slot in &mut out[1..] {
+            let delta = read_varint(varints, &mut pos)
+                .ok_or_else(|| invalid(self.path, &format!("truncated block {}", block)))?;
+            value = value.add(delta);
+            *slot = value;
+        }
+        Ok(())
+    }
+}
+
+/// Number of values of the delta-encoded file `path`, reading only its header.
+pub(crate) fn decoded_len(path: &Path) -> Result<usize> {
+    Ok(DeltaFile::open(path)?.len)
+}
+
+/// Decode the delta-encoded file `path` of `dtype` values into `out`, which
+/// must hold its values.
+pub(crate) fn decode(path: &Path, dtype: DType, out: &mut [u8]) -> Result<()> {
+    check_decodable(path, dtype)?;
+    dispatch!(dtype, decode_into, path, out)
+}
+
+fn decode_into<T: DeltaInt>(path: &Path, out: &mut [u8]) -> Result<()> {
+    let delta = DeltaFile::open(path)?;
+    let out: &mut [T] = bytemuck::cast_slice_mut(out);
+    assert_eq!(out.len(), delta.len, "output must hold all values");
+    for (block, out) in out.chunks_mut(delta.every).enumerate() {
+        delta.decode_block(block, out)?;
+    }
+    Ok(())
+}
+
+/// Number of leading values of the delta-encoded file `path` for which
+/// `pred` holds, by binary search over the samples and decoding one block.
+///
+/// The values must be partitioned by `pred`, as for `slice::partition_point`.
+pub(crate) fn partition_point<T: MmappetType>(
+    path: &Path,
+    pred: impl Fn(&T) -> bool,
+) -> Result<usize> {
+    check_decodable(path, T::DTYPE)?;
+    dispatch!(T::DTYPE, partition_in, path, |x| pred(&bytemuck::cast(x)))
+}
+
+fn check_decodable(path: &Path, dtype: DType) -> Result<()> {
+    if dtype.is_integer() {
+        return Ok(());
+    }
+    Err(invalid(path, &format!("delta-encoded values can't be {}", dtype)))
+}
+
+fn partition_in<I: DeltaInt>(path: &Path, pred: impl Fn(I) -> bool) -> Result<usize> {
+    let delta = DeltaFile::open(path)?;
+    let blocks: Vec<usize> = (0..delta.samples()).collect();
+    let full = blocks.partition_point(|&b| pred(delta.sample::<I>(b).0));
+    if full == 0 {
+        return Ok(0);
+    }
+
+    // Block `full - 1` starts with a value satisfying `pred`, the next one not
+    let block = full - 1;
+    let start = block * delta.every;
+    let mut values = vec![I::from_bits(0); delta.every.min(delta.len - start)];
+    delta.decode_block(block, &mut values)?;
+    Ok(start + values.partition_point(|&x| pred(x)))
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(*pos)?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte < 0x80 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+fn invalid(path: &Path, message: &str) -> MmappetError {
+    MmappetError::InvalidEncoding {
+        path: path.to_path_buf(),
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::Dataset;
+    use crate::schema::Schema;
+    use crate::writer::DatasetWriter;
+
+    #[test]
+    fn test_delta_column() {
+        let dir = std::env::temp_dir().join(format!("mmappet-delta-{}", std::process::id()));
+        let schema = Schema::new(&[
+            ("frame", DType::UInt32),
+            ("offset", DType::Int64),
+            ("mz", DType::Float64),
+        ])
+        .unwrap();
+        let frames: Vec<u32> = (0..10_000).map(|i| i / 7).collect();
+        let offsets: Vec<i64> = (0..10_000).map(|i| i64::MIN + i * i).collect();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.delta_encode("frame").unwrap();
+        writer.delta_encode("offset").unwrap();
+        assert!(matches!(writer.delta_encode("mz"), Err(MmappetError::InvalidDType { .. })));
+        writer.write("frame", &frames).unwrap();
+        writer.write("offset", &offsets).unwrap();
+        writer.write("mz", &vec![0.0f64; 10_000]).unwrap();
+        writer.finish().unwrap();
+        assert!(!dir.join("0.bin").exists());
+        assert!(std::fs::metadata(dir.join("0.bin.delta")).unwrap().len() < 12_000);
+
+        // Searched by the samples before the column is decoded
+        let ds = Dataset::open(&dir).unwrap();
+        assert_eq!(ds.range_by("frame", 600u32..=1000).unwrap().rows(), 4200..7007);
+        assert_eq!(ds.range_by("frame", 2000u32..).unwrap().rows(), 10_000..10_000);
+        assert!(ds.column_if_mapped("frame").is_none());
+        assert!(ds.range_by("frame", 0i32..).is_err());
+        assert_eq!(ds.get::<u32>("frame").unwrap(), &frames[..]);
+        assert_eq!(ds.get::<i64>("offset").unwrap(), &offsets[..]);
+        assert_eq!(ds.range_by("frame", ..1u32).unwrap().rows(), 0..7);
+
+        let mut ds = Dataset::open_mut(&dir).unwrap();
+        assert!(matches!(ds.get_mut::<u32>("frame"), Err(MmappetError::ReadOnly { .. })));
+
+        let dir2 = dir.with_extension("unsorted");
+        let schema = Schema::new(&[("frame", DType::UInt8)]).unwrap();
+        let mut writer = DatasetWriter::create(&dir2, &schema).unwrap();
+        writer.delta_encode("frame").unwrap();
+        writer.write::<u8>("frame", &[1, 2, 1]).unwrap();
+        assert!(matches!(writer.finish(), Err(MmappetError::InvalidBatch { .. })));
+
+        std::fs::remove_dir_all(dir).unwrap();
+        std::fs::remove_dir_all(dir2).unwrap();
+    }
+}
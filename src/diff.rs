@@ -0,0 +1,368 @@
+//! Comparing two datasets, e.g. the output of two writers of the same data.
+
+use std::fmt;
+
+use crate::column::Column;
+use crate::dataset::Dataset;
+use crate::dtype::DType;
+use crate::error::Result;
+use crate::nullable::NullableColumn;
+
+/// Options of [`diff`] and [`Dataset::approx_eq_with`].
+///
+/// The default compares floats bitwise; any other options compare them as
+/// numbers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiffOptions {
+    /// Largest absolute difference of float values still counted as equal.
+    pub abs_tolerance: f64,
+    /// Largest difference of float values still counted as equal, relative
+    /// to the larger magnitude of the two.
+    pub rel_tolerance: f64,
+    /// Count two NaNs as equal when comparing floats as numbers.
+    pub nan_equal: bool,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions {
+            abs_tolerance: 0.0,
+            rel_tolerance: 0.0,
+            nan_equal: true,
+        }
+    }
+}
+
+/// A difference between the schemas of two datasets, see [`diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SchemaDiff {
+    /// The column is only in the left dataset.
+    OnlyLeft { column: String },
+    /// The column is only in the right dataset.
+    OnlyRight { column: String },
+    /// The column has different dtypes; its values aren't compared.
+    DTypeMismatch {
+        column: String,
+        left: DType,
+        right: DType,
+    },
+    /// The column has different shapes; its values aren't compared.
+    ShapeMismatch {
+        column: String,
+        left: Vec<usize>,
+        right: Vec<usize>,
+    },
+    /// The column is at different positions, so its files are numbered
+    /// differently.
+    Position {
+        column: String,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl fmt::Display for SchemaDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaDiff::OnlyLeft { column } => write!(f, "column '{}' only on the left", column),
+            SchemaDiff::OnlyRight { column } => write!(f, "column '{}' only on the right", column),
+            SchemaDiff::DTypeMismatch { column, left, right } => {
+                write!(f, "column '{}': dtype {} vs {}", column, left, right)
+            }
+            SchemaDiff::ShapeMismatch { column, left, right } => {
+                write!(f, "column '{}': shape {:?} vs {:?}", column, left, right)
+            }
+            SchemaDiff::Position { column, left, right } => {
+                write!(f, "column '{}': position {} vs {}", column, left, right)
+            }
+        }
+    }
+}
+
+/// How the values of a column differ, see [`diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnDiff {
+    pub name: String,
+    /// Number of compared rows that differ.
+    pub mismatches: usize,
+    /// First row that differs, if any.
+    pub first_mismatch: Option<usize>,
+}
+
+/// Result of [`diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatasetDiff {
+    pub schema: Vec<SchemaDiff>,
+    pub left_rows: usize,
+    pub right_rows: usize,
+    /// One report per column compared, in the left schema's order.
+    pub columns: Vec<ColumnDiff>,
+}
+
+impl DatasetDiff {
+    /// Check if the datasets have the same schema, rows and values.
+    pub fn is_identical(&self) -> bool {
+        self.schema.is_empty()
+            && self.left_rows == self.right_rows
+            && self.columns.iter().all(|column| column.mismatches == 0)
+    }
+}
+
+/// Compare two datasets: their schemas, row counts and the values of the
+/// columns they share with the same dtype and shape.
+///
+/// Values are compared over the rows both datasets have. Rows differ if
+/// exactly one of them is null, or neither is and their values differ.
+/// Strings are compared by text and categorical columns by label, so
+/// dictionaries in different orders still match. With zero tolerances, the
+/// default, all other values are compared bitwise, so a dataset is only
+/// identical to a bit-for-bit copy of it (byte order aside). Otherwise
+/// floats `a` and `b` match if `|a - b|` is within either tolerance, or
+/// both are NaN and [`DiffOptions::nan_equal`] is set.
+///
+/// ```rust,no_run
+/// use mmappet::{diff, Dataset, DiffOptions};
+///
+/// let python = Dataset::open("python.mmappet")?;
+/// let rust = Dataset::open("rust.mmappet")?;
+/// let report = diff(&python, &rust, &DiffOptions::default())?;
+/// for column in report.columns.iter().filter(|c| c.mismatches > 0) {
+///     println!("{}: first differs at row {:?}", column.name, column.first_mismatch);
+/// }
+/// # Ok::<(), mmappet::MmappetError>(())
+/// ```
+pub fn diff(left: &Dataset, right: &Dataset, options: &DiffOptions) -> Result<DatasetDiff> {
+    let mut schema = Vec::new();
+    let mut shared = Vec::new();
+    for (position, left_def) in left.schema().columns().enumerate() {
+        let column = left_def.name.clone();
+        let found = right.schema().columns().enumerate().find(|(_, def)| def.name == column);
+        let Some((right_position, right_def)) = found else {
+            schema.push(SchemaDiff::OnlyLeft { column });
+            continue;
+        };
+        if position != right_position {
+            schema.push(SchemaDiff::Position {
+                column: column.clone(),
+                left: position,
+                right: right_position,
+            });
+        }
+        if left_def.dtype != right_def.dtype {
+            schema.push(SchemaDiff::DTypeMismatch {
+                column,
+                left: left_def.dtype,
+                right: right_def.dtype,
+            });
+        } else if left_def.shape != right_def.shape {
+            schema.push(SchemaDiff::ShapeMismatch {
+                column,
+                left: left_def.shape.clone(),
+                right: right_def.shape.clone(),
+            });
+        } else {
+            shared.push(column);
+        }
+    }
+    for right_def in right.schema().columns() {
+        if left.schema().get(&right_def.name).is_none() {
+            schema.push(SchemaDiff::OnlyRight {
+                column: right_def.name.clone(),
+            });
+        }
+    }
+
+    let rows = left.len().min(right.len());
+    let mut columns = Vec::with_capacity(shared.len());
+    for name in shared {
+        let (left_column, right_column) = (left.nullable(&name)?, right.nullable(&name)?);
+        let mut mismatches = 0;
+        let mut first_mismatch = None;
+        for row in 0..rows {
+            if !rows_match(&left_column, &right_column, row, options) {
+                mismatches += 1;
+                first_mismatch.get_or_insert(row);
+            }
+        }
+        columns.push(ColumnDiff {
+            name,
+            mismatches,
+            first_mismatch,
+        });
+    }
+
+    Ok(DatasetDiff {
+        schema,
+        left_rows: left.len(),
+        right_rows: right.len(),
+        columns,
+    })
+}
+
+impl Dataset {
+    /// Check if `other` has the same columns, in any order, the same number
+    /// of rows and values within `rtol` (relative) or `atol` (absolute) of
+    /// this dataset's, e.g. for regression tests of numerical pipelines whose
+    /// floats differ in the last bits across platforms.
+    ///
+    /// NaNs count as equal to each other; see [`Dataset::approx_eq_with`]
+    /// to change that, and [`diff`] for which rows differ.
+    pub fn approx_eq(&self, other: &Dataset, rtol: f64, atol: f64) -> Result<bool> {
+        let options = DiffOptions {
+            abs_tolerance: atol,
+            rel_tolerance: rtol,
+            ..Default::default()
+        };
+        self.approx_eq_with(other, &options)
+    }
+
+    /// Check if `other` matches this dataset as by [`Dataset::approx_eq`],
+    /// comparing values with `options` as [`diff`] does.
+    pub fn approx_eq_with(&self, other: &Dataset, options: &DiffOptions) -> Result<bool> {
+        let report = diff(self, other, options)?;
+        Ok(report.left_rows == report.right_rows
+            && report.schema.iter().all(|d| matches!(d, SchemaDiff::Position { .. }))
+            && report.columns.iter().all(|column| column.mismatches == 0))
+    }
+
+    /// Check if column `name` of this dataset and of `other` have the same
+    /// dtype, shape and number of rows, the same nulls and values within
+    /// `options`' tolerances.
+    ///
+    /// Returns an error if either dataset doesn't have the column.
+    pub fn column_approx_eq(
+        &self,
+        other: &Dataset,
+        name: &str,
+        options: &DiffOptions,
+    ) -> Result<bool> {
+        let (left, right) = (self.nullable(name)?, other.nullable(name)?);
+        if !comparable(left.column(), right.column()) {
+            return Ok(false);
+        }
+        Ok((0..self.len()).all(|row| rows_match(&left, &right, row, options)))
+    }
+}
+
+impl Column {
+    /// Check if `other` has the same dtype, shape and number of rows, and
+    /// values within `options`' tolerances, compared as by [`diff`].
+    ///
+    /// Validity bitmaps aren't consulted, see [`Dataset::column_approx_eq`].
+    pub fn approx_eq(&self, other: &Column, options: &DiffOptions) -> bool {
+        comparable(self, other)
+            && (0..self.len()).all(|row| values_match(self, other, row, options))
+    }
+}
+
+fn comparable(left: &Column, right: &Column) -> bool {
+    left.dtype() == right.dtype() && left.shape() == right.shape() && left.len() == right.len()
+}
+
+/// Check if `row` is null in both columns or has matching values in both.
+fn rows_match(
+    left: &NullableColumn<'_>,
+    right: &NullableColumn<'_>,
+    row: usize,
+    options: &DiffOptions,
+) -> bool {
+    match (left.is_valid(row), right.is_valid(row)) {
+        (true, true) => values_match(left.column(), right.column(), row, options),
+        (valid, other) => valid == other,
+    }
+}
+
+fn values_match(left: &Column, right: &Column, row: usize, options: &DiffOptions) -> bool {
+    let values = row * left.width()..(row + 1) * left.width();
+    let tolerant = *options != DiffOptions::default();
+    match left.dtype() {
+        DType::Utf8 | DType::Categorical => {
+            let (left, right) = (left.as_typed_array(), right.as_typed_array());
+            values.into_iter().all(|i| left.value(i) == right.value(i))
+        }
+        DType::Float16 | DType::Float32 | DType::Float64 if tolerant => {
+            let (left, right) = (left.as_typed_array(), right.as_typed_array());
+            values.into_iter().all(|i| {
+                let (a, b) = (left.get_f64(i).unwrap(), right.get_f64(i).unwrap());
+                let tolerance =
+                    options.abs_tolerance.max(options.rel_tolerance * a.abs().max(b.abs()));
+                let both_nan = options.nan_equal && a.is_nan() && b.is_nan();
+                a == b || both_nan || (a - b).abs() <= tolerance
+            })
+        }
+        _ => {
+            let bytes = row * left.row_size()..(row + 1) * left.row_size();
+            left.as_bytes()[bytes.clone()] == right.as_bytes()[bytes]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::column::TypedArray;
+
+    #[test]
+    fn test_diff() {
+        let left = Dataset::from_columns(vec![
+            ("mz", vec![401.5f64, 502.25, 603.0].into()),
+            ("peptide", vec!["PEP", "TIDE", "K"].into()),
+            ("charge", vec![2u8, 3, 1].into()),
+        ])
+        .unwrap();
+        let right = Dataset::from_columns(vec![
+            ("mz", vec![401.5f64, 502.25 + 1e-9, 603.0, 704.5].into()),
+            ("charge", vec![2u8, 3, 1, 2].into()),
+            ("peptide", vec!["PEP", "TIDE", "R", "K"].into()),
+            ("rt", TypedArray::from(vec![1.0f32; 4])),
+        ])
+        .unwrap();
+
+        let report = diff(&left, &right, &DiffOptions::default()).unwrap();
+        assert!(!report.is_identical());
+        assert_eq!((report.left_rows, report.right_rows), (3, 4));
+        assert!(report.schema.contains(&SchemaDiff::OnlyRight { column: "rt".to_string() }));
+        assert!(report.schema.contains(&SchemaDiff::Position {
+            column: "peptide".to_string(),
+            left: 1,
+            right: 2,
+        }));
+        let mismatches = |report: &DatasetDiff| -> Vec<(usize, Option<usize>)> {
+            report.columns.iter().map(|c| (c.mismatches, c.first_mismatch)).collect()
+        };
+        assert_eq!(mismatches(&report), [(1, Some(1)), (1, Some(2)), (0, None)]);
+
+        let options = DiffOptions { abs_tolerance: 1e-6, ..Default::default() };
+        let report = diff(&left, &right, &options).unwrap();
+        assert_eq!(mismatches(&report), [(0, None), (1, Some(2)), (0, None)]);
+        assert!(diff(&left, &left, &DiffOptions::default()).unwrap().is_identical());
+    }
+
+    #[test]
+    fn test_approx_eq() {
+        let left = Dataset::from_columns(vec![
+            ("rt", vec![1.0f32, f32::NAN, 3.0].into()),
+            ("frame", vec![1u32, 2, 3].into()),
+        ])
+        .unwrap();
+        let right = Dataset::from_columns(vec![
+            ("frame", vec![1u32, 2, 3].into()),
+            ("rt", vec![1.0f32 + f32::EPSILON, f32::NAN, 3.0].into()),
+        ])
+        .unwrap();
+
+        assert!(left.approx_eq(&right, 1e-6, 0.0).unwrap());
+        assert!(!left.approx_eq(&right, 0.0, 0.0).unwrap());
+        let strict_nan = DiffOptions {
+            rel_tolerance: 1e-6,
+            nan_equal: false,
+            ..Default::default()
+        };
+        assert!(!left.approx_eq_with(&right, &strict_nan).unwrap());
+        assert!(left.column_approx_eq(&right, "frame", &DiffOptions::default()).unwrap());
+        let options = DiffOptions { abs_tolerance: 1e-6, ..Default::default() };
+        assert!(left["rt"].approx_eq(&right["rt"], &options));
+        assert!(left.column_approx_eq(&right, "mz", &DiffOptions::default()).is_err());
+    }
+}
@@ -1,8 +1,15 @@
 //! Data type definitions for mmappet columns.
 
 use crate::error::{MmappetError, Result};
+use crate::registry;
 
 /// Represents all supported mmappet data types.
+///
+/// `Custom` names a dtype registered with [`crate::register_dtype`].
+/// `Utf8` columns hold variable-length strings, see [`crate::StrArrayView`].
+/// `Categorical` columns hold `u32` codes into a dictionary of labels, see
+/// [`crate::CategoricalView`]. `TimestampMillis` and `TimestampMicros`
+/// columns hold `i64` milli- or microseconds since the Unix epoch, UTC.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DType {
     UInt8,
@@ -13,23 +20,45 @@ pub enum DType {
     Int32,
     UInt64,
     Int64,
+    Float16,
     Float32,
     Float64,
     Bool,
+    Utf8,
+    Categorical,
+    TimestampMillis,
+    TimestampMicros,
+    Custom(&'static str),
 }
 
 impl DType {
-    /// Size of this dtype in bytes.
+    /// Size of this dtype in bytes; for `Utf8` the size of a string's offset,
+    /// for `Categorical` the size of a code.
+    ///
+    /// # Panics
+    ///
+    /// Panics for a `Custom` dtype that was never registered.
     pub fn size_bytes(&self) -> usize {
         match self {
             DType::UInt8 | DType::Int8 | DType::Bool => 1,
-            DType::UInt16 | DType::Int16 => 2,
-            DType::UInt32 | DType::Int32 | DType::Float32 => 4,
-            DType::UInt64 | DType::Int64 | DType::Float64 => 8,
+            DType::UInt16 | DType::Int16 | DType::Float16 => 2,
+            DType::UInt32 | DType::Int32 | DType::Float32 | DType::Categorical => 4,
+            DType::UInt64
+            | DType::Int64
+            | DType::Float64
+            | DType::Utf8
+            | DType::TimestampMillis
+            | DType::TimestampMicros => 8,
+            DType::Custom(name) => {
+                registry::lookup(name)
+                    .unwrap_or_else(|| panic!("dtype '{}' is not registered", name))
+                    .size
+            }
         }
     }
 
     /// Parse from schema string (e.g., "uint32").
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Result<Self> {
         match s.trim().to_lowercase().as_str() {
             "uint8" | "u8" => Ok(DType::UInt8),
@@ -40,10 +69,20 @@ impl DType {
             "int32" | "i32" => Ok(DType::Int32),
             "uint64" | "u64" | "size_t" => Ok(DType::UInt64),
             "int64" | "i64" => Ok(DType::Int64),
+            "float16" | "f16" | "half" => Ok(DType::Float16),
             "float32" | "f32" => Ok(DType::Float32),
             "float64" | "f64" | "double" => Ok(DType::Float64),
             "bool" | "boolean" => Ok(DType::Bool),
-            _ => Err(MmappetError::UnknownDType(s.to_string())),
+            "utf8" | "string" | "str" => Ok(DType::Utf8),
+            "categorical" | "category" => Ok(DType::Categorical),
+            "timestamp_ms" | "timestamp_millis" => Ok(DType::TimestampMillis),
+            "timestamp_us" | "timestamp_micros" => Ok(DType::TimestampMicros),
+            name => match registry::lookup(name) {
+                Some(def) => Ok(DType::Custom(def.name)),
+                None => Err(MmappetError::UnknownDType {
+                    dtype: s.to_string(),
+                }),
+            },
         }
     }
 
@@ -58,9 +97,41 @@ impl DType {
             DType::Int32 => "int32",
             DType::UInt64 => "uint64",
             DType::Int64 => "int64",
+            DType::Float16 => "float16",
             DType::Float32 => "float32",
             DType::Float64 => "float64",
             DType::Bool => "bool",
+            DType::Utf8 => "utf8",
+            DType::Categorical => "categorical",
+            DType::TimestampMillis => "timestamp_ms",
+            DType::TimestampMicros => "timestamp_us",
+            DType::Custom(name) => name,
+        }
+    }
+
+    /// Whether values are integers, timestamps included.
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            DType::UInt8
+                | DType::Int8
+                | DType::UInt16
+                | DType::Int16
+                | DType::UInt32
+                | DType::Int32
+                | DType::UInt64
+                | DType::Int64
+                | DType::TimestampMillis
+                | DType::TimestampMicros
+        )
+    }
+
+    /// Ticks per second of a timestamp dtype, `None` for other dtypes.
+    pub fn ticks_per_second(&self) -> Option<i64> {
+        match self {
+            DType::TimestampMillis => Some(1_000),
+            DType::TimestampMicros => Some(1_000_000),
+            _ => None,
         }
     }
 }
@@ -71,6 +142,49 @@ impl std::fmt::Display for DType {
     }
 }
 
+/// Byte order of the values in column files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ByteOrder {
+    Little,
+    Big,
+}
+
+impl ByteOrder {
+    /// Byte order of the host.
+    pub fn native() -> Self {
+        if cfg!(target_endian = "big") {
+            ByteOrder::Big
+        } else {
+            ByteOrder::Little
+        }
+    }
+
+    /// Parse from schema string ("little" or "big").
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.trim() {
+            "little" => Some(ByteOrder::Little),
+            "big" => Some(ByteOrder::Big),
+            _ => None,
+        }
+    }
+
+    /// Convert to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ByteOrder::Little => "little",
+            ByteOrder::Big => "big",
+        }
+    }
+}
+
+impl std::fmt::Display for ByteOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 /// Trait for Rust types that correspond to mmappet dtypes.
 ///
 /// This trait is sealed and only implemented for supported primitive types.
@@ -111,6 +225,10 @@ impl MmappetType for i64 {
     const DTYPE: DType = DType::Int64;
 }
 
+impl MmappetType for half::f16 {
+    const DTYPE: DType = DType::Float16;
+}
+
 impl MmappetType for f32 {
     const DTYPE: DType = DType::Float32;
 }
@@ -129,6 +247,8 @@ mod tests {
         assert_eq!(DType::from_str("float64").unwrap(), DType::Float64);
         assert_eq!(DType::from_str("UINT32").unwrap(), DType::UInt32);
         assert_eq!(DType::from_str("size_t").unwrap(), DType::UInt64);
+        assert_eq!(DType::from_str("timestamp_us").unwrap(), DType::TimestampMicros);
+        assert_eq!(DType::TimestampMillis.to_string(), "timestamp_ms");
         assert!(DType::from_str("invalid").is_err());
     }
 
@@ -136,6 +256,7 @@ mod tests {
     fn test_dtype_size() {
         assert_eq!(DType::UInt8.size_bytes(), 1);
         assert_eq!(DType::UInt32.size_bytes(), 4);
+        assert_eq!(DType::from_str("half").unwrap().size_bytes(), 2);
         assert_eq!(DType::Float64.size_bytes(), 8);
     }
 
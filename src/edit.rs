@@ -0,0 +1,306 @@
+//! Copy-on-write editing: a new dataset that rewrites some columns and
+//! shares the files of all others with the original.
+//!
+//! Shared columns are hard-linked into the new directory, or referenced
+//! where links can't be made (e.g. across file systems). `manifest.txt`
+//! records how each column got there, one `{how} {column}` line per column
+//! with `how` one of `linked`, `referenced` or `rewritten`, after a
+//! `# mmappet edit of {source}` line.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::column::{Column, TypedArray};
+use crate::dataset::Dataset;
+use crate::dtype::ByteOrder;
+use crate::error::{MmappetError, Result};
+use crate::nullable::validity_path;
+use crate::schema::{ColumnRef, Schema};
+use crate::writer::DatasetWriter;
+
+const MANIFEST_FILE: &str = "manifest.txt";
+
+/// Directory inside the new dataset where rewritten columns are written.
+const STAGING_DIR: &str = ".edit";
+
+/// Extensions of the index files kept next to a column, named after it.
+const SIDECARS: [&str; 4] = ["idx", "zones", "bloom", "groups"];
+
+/// Files copied rather than linked, since they're changed in place.
+const COPIED: [&str; 2] = ["meta.txt", "provenance.log"];
+
+/// Pending edit of a dataset, see [`Dataset::edit`].
+pub struct DatasetEdit<'a> {
+    dataset: &'a Dataset,
+    dir: PathBuf,
+    replaced: Vec<(String, TypedArray)>,
+}
+
+impl Dataset {
+    /// Start a copy-on-write edit that writes the result to `dir`.
+    ///
+    /// Only replaced columns get new files; the files of the others are
+    /// shared with this dataset, which is left as it is. Shared columns are
+    /// read-only in the result, even opened with [`Dataset::open_mut`], and
+    /// the result can't be appended to.
+    ///
+    /// ```rust,no_run
+    /// use mmappet::Dataset;
+    ///
+    /// let ds = Dataset::open("run.mmappet")?;
+    /// let fixed: Vec<f64> = ds.get::<f64>("mz")?.iter().map(|mz| mz * 1.0001).collect();
+    /// let edited = ds.edit("run-fixed.mmappet").replace("mz", fixed)?.commit()?;
+    /// # Ok::<(), mmappet::MmappetError>(())
+    /// ```
+    pub fn edit<P: AsRef<Path>>(&self, dir: P) -> DatasetEdit<'_> {
+        DatasetEdit {
+            dataset: self,
+            dir: dir.as_ref().to_path_buf(),
+            replaced: Vec::new(),
+        }
+    }
+}
+
+impl DatasetEdit<'_> {
+    /// Give the column `name` new values, possibly of another dtype; array
+    /// columns keep their shape and take `width` values per row.
+    ///
+    /// Returns an error if the column doesn't exist or the number of values
+    /// doesn't match.
+    pub fn replace(&mut self, name: &str, values: impl Into<TypedArray>) -> Result<&mut Self> {
+        let values = values.into();
+        let ds = self.dataset;
+        let col_def = ds.schema().get(name).ok_or_else(|| ds.column_not_found(name))?;
+        let expected = ds.len() * col_def.width();
+        if values.len() != expected {
+            return Err(MmappetError::LengthMismatch {
+                path: Some(self.dir.clone()),
+                column: name.to_string(),
+                expected,
+                actual: values.len(),
+            });
+        }
+        self.replaced.retain(|(replaced, _)| replaced != name);
+        self.replaced.push((name.to_string(), values));
+        Ok(self)
+    }
+
+    /// Write the edited dataset and open it.
+    ///
+    /// The schema is written last, so a failed edit leaves no dataset
+    /// behind. Returns an error if the original dataset isn't stored in the
+    /// host's byte order or a file can't be written.
+    pub fn commit(&self) -> Result<Dataset> {
+        let source = self.dataset.path();
+        let mut schema = Schema::from_path(source)?;
+        if schema.byte_order() != ByteOrder::native() {
+            return Err(MmappetError::ForeignByteOrder {
+                path: Some(source.to_path_buf()),
+                column: None,
+                byte_order: schema.byte_order(),
+            });
+        }
+        let source = fs::canonicalize(source).map_err(|e| MmappetError::io(source, e))?;
+        fs::create_dir_all(&self.dir).map_err(|e| MmappetError::io(&self.dir, e))?;
+
+        self.write_replaced(&mut schema)?;
+        let mut manifest = format!("# mmappet edit of {}\n", source.display());
+        let defs: Vec<_> = schema.columns().cloned().collect();
+        for col_def in defs {
+            if self.replaced.iter().any(|(name, _)| *name == col_def.name) {
+                manifest += &format!("rewritten {}\n", col_def.name);
+                continue;
+            }
+            let linked = col_def.reference.is_none() && self.link_column(&source, col_def.index)?;
+            if !linked {
+                // Referenced files stay where they are; only the bitmap is local
+                let reference = match &col_def.reference {
+                    Some(reference) => ColumnRef {
+                        dataset: fs::canonicalize(source.join(&reference.dataset))
+                            .map_err(|e| MmappetError::io(source.join(&reference.dataset), e))?,
+                        column: reference.column.clone(),
+                    },
+                    None => ColumnRef {
+                        dataset: source.clone(),
+                        column: col_def.name.clone(),
+                    },
+                };
+                schema.set_reference(&col_def.name, Some(reference))?;
+                let validity = validity_path(&source, col_def.index);
+                if validity.exists() {
+                    copy(&validity, &validity_path(&self.dir, col_def.index))?;
+                }
+            }
+            for extension in SIDECARS {
+                let file = format!("{}.{}", col_def.name, extension);
+                if source.join(&file).exists() {
+                    link_or_copy(&source.join(&file), &self.dir.join(&file))?;
+                }
+            }
+            let how = if linked { "linked" } else { "referenced" };
+            manifest += &format!("{} {}\n", how, col_def.name);
+        }
+        for file in COPIED {
+            if source.join(file).exists() {
+                copy(&source.join(file), &self.dir.join(file))?;
+            }
+        }
+
+        write_file(&self.dir.join(MANIFEST_FILE), &manifest)?;
+        if schema.has_attributes() {
+            write_file(&self.dir.join("schema.json"), &schema.to_json())?;
+        }
+        write_file(&self.dir.join("schema.txt"), &schema.to_string())?;
+        Dataset::open(&self.dir)
+    }
+
+    /// Write the replaced columns to the files of their position in
+    /// `schema`, and give them their new dtypes there.
+    fn write_replaced(&self, schema: &mut Schema) -> Result<()> {
+        if self.replaced.is_empty() {
+            return Ok(());
+        }
+        let staging = self.dir.join(STAGING_DIR);
+        let defs: Vec<_> = self.replaced.iter().map(|(n, v)| (n.as_str(), v.dtype())).collect();
+        let mut staged = Schema::new(&defs)?;
+        let mut columns = Vec::with_capacity(self.replaced.len());
+        for (name, values) in &self.replaced {
+            staged.set_shape(name, &schema.get(name).unwrap().shape)?;
+            let path = staging.join(format!("{}.bin", columns.len()));
+            columns.push(Column::from_array(&path, values).map_err(|e| e.with_column(name))?);
+        }
+        let mut writer = DatasetWriter::create(&staging, &staged)?;
+        let batch: Vec<_> = columns.iter().map(Column::as_typed_array).collect();
+        writer.write_batch(&batch)?;
+        writer.finish()?;
+
+        for (staged_index, (name, values)) in self.replaced.iter().enumerate() {
+            let index = schema.get(name).unwrap().index;
+            for (file, rest) in column_files(&staging, staged_index)? {
+                let target = self.dir.join(format!("{}.{}", index, rest));
+                fs::rename(&file, &target).map_err(|e| MmappetError::io(&file, e))?;
+            }
+            schema.set_dtype(name, values.dtype())?;
+        }
+        fs::remove_dir_all(&staging).map_err(|e| MmappetError::io(&staging, e))
+    }
+
+    /// Hard-link the files of the column at `index` of `source`; `false` if
+    /// links can't be made there, after removing any that were.
+    fn link_column(&self, source: &Path, index: usize) -> Result<bool> {
+        let mut linked = Vec::new();
+        for (file, rest) in column_files(source, index)? {
+            let target = self.dir.join(format!("{}.{}", index, rest));
+            if fs::hard_link(&file, &target).is_err() {
+                for target in linked {
+                    fs::remove_file(&target).map_err(|e| MmappetError::io(&target, e))?;
+                }
+                return Ok(false);
+            }
+            linked.push(target);
+        }
+        Ok(true)
+    }
+}
+
+/// Names of the columns whose files the dataset in `dir` shares with the
+/// dataset it was edited from, see [`Dataset::edit`].
+pub(crate) fn shared_columns(dir: &Path) -> Result<HashSet<String>> {
+    let path = dir.join(MANIFEST_FILE);
+    if !path.exists() {
+        return Ok(HashSet::new());
+    }
+    let content = fs::read_to_string(&path).map_err(|e| MmappetError::io(&path, e))?;
+    Ok(content
+        .lines()
+        .filter_map(|line| line.strip_prefix("linked "))
+        .map(String::from)
+        .collect())
+}
+
+/// Files of the column at `index` in `dir` (column file, string data,
+/// dictionary, validity bitmap and encoded files), each with the part of its
+/// name after `{index}.`.
+fn column_files(dir: &Path, index: usize) -> Result<Vec<(PathBuf, String)>> {
+    let prefix = format!("{}.", index);
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).map_err(|e| MmappetError::io(dir, e))? {
+        let entry = entry.map_err(|e| MmappetError::io(dir, e))?;
+        if let Some(rest) = entry.file_name().to_str().and_then(|n| n.strip_prefix(&prefix)) {
+            files.push((entry.path(), rest.to_string()));
+        }
+    }
+    Ok(files)
+}
+
+fn link_or_copy(from: &Path, to: &Path) -> Result<()> {
+    if fs::hard_link(from, to).is_ok() {
+        return Ok(());
+    }
+    copy(from, to)
+}
+
+fn copy(from: &Path, to: &Path) -> Result<()> {
+    fs::copy(from, to).map(drop).map_err(|e| MmappetError::io(from, e))
+}
+
+fn write_file(path: &Path, content: &str) -> Result<()> {
+    fs::write(path, content).map_err(|e| MmappetError::io(path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dtype::DType;
+
+    #[test]
+    fn test_edit() {
+        let dir = std::env::temp_dir().join(format!("mmappet-edit-{}", std::process::id()));
+        let schema = Schema::new(&[
+            ("count", DType::UInt64),
+            ("mz", DType::Float64),
+            ("name", DType::Utf8),
+        ])
+        .unwrap();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write_opt("count", &[Some(1u64), None, Some(3)]).unwrap();
+        writer.write("mz", &[1.5f64, 2.5, 3.5]).unwrap();
+        writer.write_str("name", &["a", "b", "c"]).unwrap();
+        writer.set_metadata("instrument", "tims").unwrap();
+        writer.finish().unwrap();
+        let ds = Dataset::open(&dir).unwrap();
+        ds.build_zone_map("mz").unwrap();
+
+        let out = dir.with_extension("edited");
+        let mut edit = ds.edit(&out);
+        assert!(edit.replace("mz", vec![1.0f64]).is_err());
+        assert!(edit.replace("missing", vec![1.0f64]).is_err());
+        let edited = edit.replace("count", vec![10u32, 20, 30]).unwrap().commit().unwrap();
+        assert_eq!(edited.get::<u32>("count").unwrap(), &[10, 20, 30]);
+        assert!(!edited.nullable("count").unwrap().has_validity());
+        assert_eq!(edited.get::<f64>("mz").unwrap(), &[1.5, 2.5, 3.5]);
+        assert_eq!(edited["name"].as_str_array().unwrap().get(2), "c");
+        assert_eq!(edited.metadata().unwrap()["instrument"], "tims");
+        assert!(out.join("mz.zones").exists());
+        let manifest = fs::read_to_string(out.join(MANIFEST_FILE)).unwrap();
+        assert!(manifest.contains("rewritten count\n"), "{}", manifest);
+        assert!(manifest.contains("linked mz\n"), "{}", manifest);
+        assert!(!out.join(STAGING_DIR).exists());
+
+        // The original keeps its values and shared files stay read-only
+        assert_eq!(ds.get::<u64>("count").unwrap(), &[1, 0, 3]);
+        let mut writable = Dataset::open_mut(&out).unwrap();
+        assert!(writable.get_mut::<f64>("mz").is_err());
+        writable.get_mut::<u32>("count").unwrap()[0] = 11;
+        let batch = [
+            ("count", crate::TypedArrayView::UInt32(ndarray::ArrayView1::from(&[4u32]))),
+            ("mz", crate::TypedArrayView::Float64(ndarray::ArrayView1::from(&[4.5]))),
+        ];
+        assert!(writable.append_batch(&batch).is_err());
+        assert_eq!(ds.get::<f64>("mz").unwrap(), &[1.5, 2.5, 3.5]);
+
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_dir_all(&out).unwrap();
+    }
+}
@@ -1,51 +1,395 @@
 //! Error types for the mmappet library.
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
-use crate::dtype::DType;
+use crate::dtype::{ByteOrder, DType};
 
 /// Errors that can occur when working with mmappet datasets.
+///
+/// Variants carry the dataset or file path, column name and byte offset where
+/// they are known; use [`MmappetError::path`], [`MmappetError::column`] and
+/// [`MmappetError::offset`] to read them without matching on every variant.
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum MmappetError {
-    #[error("IO error: {0}")]
-    Io(#[from] std::io::Error),
+    #[error("IO error{}: {source}", at(.path))]
+    Io {
+        path: Option<PathBuf>,
+        #[source]
+        source: std::io::Error,
+    },
 
-    #[error("Schema parse error at line {line}: {message}")]
-    SchemaParse { line: usize, message: String },
+    #[error("Schema parse error{} at line {line}: {message}", at(.path))]
+    SchemaParse {
+        path: Option<PathBuf>,
+        line: usize,
+        message: String,
+    },
 
-    #[error("Unknown dtype: {0}")]
-    UnknownDType(String),
+    #[error("Unknown dtype: {dtype}")]
+    UnknownDType { dtype: String },
 
-    #[error("Column not found: {0}")]
-    ColumnNotFound(String),
+    #[error("Invalid dtype definition '{dtype}': {message}")]
+    InvalidDType { dtype: String, message: String },
 
-    #[error("Type mismatch: expected {expected:?}, got {actual:?}")]
-    TypeMismatch { expected: DType, actual: DType },
+    #[error("Column not found: {column}{}", at(.path))]
+    ColumnNotFound {
+        path: Option<PathBuf>,
+        column: String,
+    },
+
+    #[error("Type mismatch{}: expected {expected:?}, got {actual:?}", for_column(.column))]
+    TypeMismatch {
+        column: Option<String>,
+        expected: DType,
+        actual: DType,
+    },
 
-    #[error("Column length mismatch: column '{name}' has {actual} elements, expected {expected}")]
+    #[error("Column length mismatch{}: column '{column}' has {actual} elements, expected {expected}", at(.path))]
     LengthMismatch {
-        name: String,
+        path: Option<PathBuf>,
+        column: String,
         expected: usize,
         actual: usize,
     },
 
-    #[error("Missing schema.txt in {0}")]
-    MissingSchema(PathBuf),
+    #[error("Missing schema.txt in {}", .path.display())]
+    MissingSchema { path: PathBuf },
 
-    #[error("Missing column file: {0}")]
-    MissingColumnFile(PathBuf),
+    #[error("Missing file {} of column '{column}'", .path.display())]
+    MissingColumnFile { path: PathBuf, column: String },
 
-    #[error("Invalid column file size: {path} has {actual} bytes, expected multiple of {element_size}")]
+    #[error("Invalid column file size: {} has {actual} bytes, expected multiple of {element_size}", .path.display())]
     InvalidFileSize {
         path: PathBuf,
+        column: Option<String>,
         actual: usize,
         element_size: usize,
     },
 
-    #[error("Duplicate column name: {0}")]
-    DuplicateColumnName(String),
+    #[error("Duplicate column name{}: {column}", at(.path))]
+    DuplicateColumnName {
+        path: Option<PathBuf>,
+        column: String,
+    },
+
+    #[error("Import error{}{}: {message}", at(.path), at_offset(.offset))]
+    Import {
+        path: Option<PathBuf>,
+        offset: Option<u64>,
+        message: String,
+    },
+
+    #[error("Column '{column}' is not grouped: a key value occurs in more than one run")]
+    UngroupedKey {
+        path: Option<PathBuf>,
+        column: String,
+    },
+
+    #[error("Invalid index file {}{}", .path.display(), at_offset(.offset))]
+    InvalidIndex { path: PathBuf, offset: Option<u64> },
+
+    #[error("Invalid expression '{expression}'{}: {message}", .position.map(|p| format!(" at position {}", p)).unwrap_or_default())]
+    InvalidExpression {
+        expression: String,
+        position: Option<usize>,
+        message: String,
+    },
+
+    #[error("Invalid batch{}: {message}", at(.path))]
+    InvalidBatch {
+        path: Option<PathBuf>,
+        message: String,
+    },
+
+    #[error("Invalid metadata in {}{}: {message}", .path.display(), .line.map(|l| format!(" at line {}", l)).unwrap_or_default())]
+    InvalidMetadata {
+        path: PathBuf,
+        line: Option<usize>,
+        message: String,
+    },
+
+    #[error("Invalid reference for column '{column}': {message}")]
+    InvalidReference {
+        path: Option<PathBuf>,
+        column: String,
+        message: String,
+    },
+
+    #[error("Column '{column}' in {} is read-only", .path.display())]
+    ReadOnly { path: PathBuf, column: String },
+
+    #[error("Invalid row selection{}: {message}", at(.path))]
+    InvalidSelection {
+        path: Option<PathBuf>,
+        message: String,
+    },
+
+    #[error("Unsupported format version {version}{}: this build reads up to version {supported}, upgrade mmappet", at(.path))]
+    UnsupportedVersion {
+        path: Option<PathBuf>,
+        version: u32,
+        supported: u32,
+    },
+
+    #[error("Can't lock {requested} bytes in memory{}{}: limit is {limit} bytes (RLIMIT_MEMLOCK, see `ulimit -l`)", for_column(.column), at(.path))]
+    LockLimit {
+        path: Option<PathBuf>,
+        column: Option<String>,
+        requested: usize,
+        limit: u64,
+    },
+
+    #[error("Invalid strings {}{}: {message}", .path.display(), for_column(.column))]
+    InvalidStrings {
+        path: PathBuf,
+        column: Option<String>,
+        message: String,
+    },
+
+    #[error("Column '{column}' holds arrays of {width} values, expected one value per row")]
+    NotScalar { column: String, width: usize },
+
+    #[error("Invalid categories {}{}: {message}", .path.display(), for_column(.column))]
+    InvalidCategories {
+        path: PathBuf,
+        column: Option<String>,
+        message: String,
+    },
+
+    #[error("Invalid validity bitmap {} of column '{column}': {actual} bytes, expected {expected}", .path.display())]
+    InvalidValidity {
+        path: PathBuf,
+        column: String,
+        actual: usize,
+        expected: usize,
+    },
+
+    #[error("Can't modify{}{} in place: it's stored {byte_order}-endian, unlike this host", for_column(.column), at(.path))]
+    ForeignByteOrder {
+        path: Option<PathBuf>,
+        column: Option<String>,
+        byte_order: ByteOrder,
+    },
+
+    #[error("Invalid encoded column file {}: {message}", .path.display())]
+    InvalidEncoding { path: PathBuf, message: String },
+
+    #[error("Can't concatenate datasets{}: {message}", at(.path))]
+    InvalidConcat {
+        path: Option<PathBuf>,
+        message: String,
+    },
+
+    #[error("HTTP error for {url}: {message}")]
+    Http { url: String, message: String },
+
+    #[error("Object store error for {url}: {message}")]
+    ObjectStore { url: String, message: String },
+
+    #[error("Message bus error for {bus}: {message}")]
+    MessageBus { bus: String, message: String },
+
+    #[error("Can't cast {value} in row {row}{} to {}", for_column(.column), .dtype.as_str())]
+    CastOverflow {
+        column: Option<String>,
+        row: usize,
+        value: String,
+        dtype: DType,
+    },
+
+    #[error("Invalid histogram: {message}")]
+    InvalidHistogram { message: String },
+
+    #[error("Dataset {} has {actual} rows, but was opened with {expected}: another handle appended to it, reopen it", .path.display())]
+    StaleDataset {
+        path: PathBuf,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl MmappetError {
+    /// Create an I/O error for `path`.
+    pub(crate) fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        MmappetError::Io {
+            path: Some(path.into()),
+            source,
+        }
+    }
+
+    /// The dataset or file the error refers to, if known.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            MmappetError::Io { path, .. }
+            | MmappetError::SchemaParse { path, .. }
+            | MmappetError::ColumnNotFound { path, .. }
+            | MmappetError::LengthMismatch { path, .. }
+            | MmappetError::DuplicateColumnName { path, .. }
+            | MmappetError::Import { path, .. }
+            | MmappetError::UngroupedKey { path, .. }
+            | MmappetError::InvalidBatch { path, .. }
+            | MmappetError::InvalidSelection { path, .. }
+            | MmappetError::LockLimit { path, .. }
+            | MmappetError::UnsupportedVersion { path, .. }
+            | MmappetError::ForeignByteOrder { path, .. }
+            | MmappetError::InvalidConcat { path, .. }
+            | MmappetError::InvalidReference { path, .. } => path.as_deref(),
+            MmappetError::MissingSchema { path }
+            | MmappetError::MissingColumnFile { path, .. }
+            | MmappetError::InvalidFileSize { path, .. }
+            | MmappetError::InvalidIndex { path, .. }
+            | MmappetError::InvalidMetadata { path, .. }
+            | MmappetError::InvalidValidity { path, .. }
+            | MmappetError::InvalidStrings { path, .. }
+            | MmappetError::InvalidCategories { path, .. }
+            | MmappetError::InvalidEncoding { path, .. }
+            | MmappetError::StaleDataset { path, .. }
+            | MmappetError::ReadOnly { path, .. } => Some(path),
+            MmappetError::UnknownDType { .. }
+            | MmappetError::InvalidDType { .. }
+            | MmappetError::InvalidExpression { .. }
+            | MmappetError::NotScalar { .. }
+            | MmappetError::Http { .. }
+            | MmappetError::ObjectStore { .. }
+            | MmappetError::MessageBus { .. }
+            | MmappetError::CastOverflow { .. }
+            | MmappetError::InvalidHistogram { .. }
+            | MmappetError::TypeMismatch { .. } => None,
+        }
+    }
+
+    /// The column the error refers to, if known.
+    pub fn column(&self) -> Option<&str> {
+        match self {
+            MmappetError::ColumnNotFound { column, .. }
+            | MmappetError::LengthMismatch { column, .. }
+            | MmappetError::MissingColumnFile { column, .. }
+            | MmappetError::DuplicateColumnName { column, .. }
+            | MmappetError::UngroupedKey { column, .. }
+            | MmappetError::InvalidReference { column, .. }
+            | MmappetError::InvalidValidity { column, .. }
+            | MmappetError::NotScalar { column, .. }
+            | MmappetError::ReadOnly { column, .. } => Some(column),
+            MmappetError::TypeMismatch { column, .. }
+            | MmappetError::InvalidFileSize { column, .. }
+            | MmappetError::LockLimit { column, .. }
+            | MmappetError::InvalidStrings { column, .. }
+            | MmappetError::InvalidCategories { column, .. }
+            | MmappetError::CastOverflow { column, .. }
+            | MmappetError::ForeignByteOrder { column, .. } => column.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The byte offset within [`MmappetError::path`] the error refers to, if known.
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            MmappetError::Import { offset, .. } | MmappetError::InvalidIndex { offset, .. } => {
+                *offset
+            }
+            _ => None,
+        }
+    }
+
+    /// Fill in the path if the error doesn't carry one yet.
+    pub(crate) fn with_path(mut self, dir: &Path) -> Self {
+        match &mut self {
+            MmappetError::Io { path, .. }
+            | MmappetError::SchemaParse { path, .. }
+            | MmappetError::ColumnNotFound { path, .. }
+            | MmappetError::LengthMismatch { path, .. }
+            | MmappetError::DuplicateColumnName { path, .. }
+            | MmappetError::Import { path, .. }
+            | MmappetError::UngroupedKey { path, .. }
+            | MmappetError::InvalidBatch { path, .. }
+            | MmappetError::InvalidSelection { path, .. }
+            | MmappetError::LockLimit { path, .. }
+            | MmappetError::UnsupportedVersion { path, .. }
+            | MmappetError::ForeignByteOrder { path, .. }
+            | MmappetError::InvalidReference { path, .. } => {
+                path.get_or_insert_with(|| dir.to_path_buf());
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// Fill in the column if the error doesn't carry one yet.
+    pub(crate) fn with_column(mut self, name: &str) -> Self {
+        match &mut self {
+            MmappetError::TypeMismatch { column, .. }
+            | MmappetError::InvalidFileSize { column, .. }
+            | MmappetError::LockLimit { column, .. }
+            | MmappetError::InvalidStrings { column, .. }
+            | MmappetError::InvalidCategories { column, .. }
+            | MmappetError::CastOverflow { column, .. }
+            | MmappetError::ForeignByteOrder { column, .. } => {
+                column.get_or_insert_with(|| name.to_string());
+            }
+            _ => {}
+        }
+        self
+    }
+}
+
+impl From<std::io::Error> for MmappetError {
+    fn from(source: std::io::Error) -> Self {
+        MmappetError::Io { path: None, source }
+    }
+}
+
+/// ` in <path>` for messages, or nothing when the path is unknown.
+fn at(path: &Option<PathBuf>) -> String {
+    path.as_ref()
+        .map(|p| format!(" in {}", p.display()))
+        .unwrap_or_default()
+}
+
+/// ` at byte <offset>` for messages, or nothing when the offset is unknown.
+fn at_offset(offset: &Option<u64>) -> String {
+    offset
+        .map(|o| format!(" at byte {}", o))
+        .unwrap_or_default()
+}
+
+/// ` for column '<name>'` for messages, or nothing when the column is unknown.
+fn for_column(column: &Option<String>) -> String {
+    column
+        .as_ref()
+        .map(|c| format!(" for column '{}'", c))
+        .unwrap_or_default()
 }
 
 /// Result type for mmappet operations.
 pub type Result<T> = std::result::Result<T, MmappetError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_context() {
+        let err = MmappetError::TypeMismatch {
+            column: None,
+            expected: DType::Float32,
+            actual: DType::UInt32,
+        }
+        .with_column("mz");
+        assert_eq!(err.column(), Some("mz"));
+        assert_eq!(err.path(), None);
+        assert_eq!(
+            err.to_string(),
+            "Type mismatch for column 'mz': expected Float32, got UInt32"
+        );
+
+        let err = MmappetError::ColumnNotFound {
+            path: None,
+            column: "tof".to_string(),
+        }
+        .with_path(Path::new("a.mmappet"));
+        assert_eq!(err.path(), Some(Path::new("a.mmappet")));
+        assert_eq!(err.to_string(), "Column not found: tof in a.mmappet");
+    }
+}
@@ -0,0 +1,968 @@
+//! Row expressions over dataset columns, e.g. `intensity > 1e5 and ms_level == 2`.
+//!
+//! Expressions are parsed from text with [`Expr::parse`] or built in code:
+//!
+//! ```rust,no_run
+//! use mmappet::{col, lit};
+//!
+//! let expr = col("intensity").gt(lit(100)).and(col("mz").between(400.0, 600.0));
+//! # let ds = mmappet::Dataset::open("data.mmappet")?;
+//! let mask = ds.mask(&expr)?;
+//! # Ok::<(), mmappet::MmappetError>(())
+//! ```
+
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Not, Range, Sub};
+use std::sync::Arc;
+
+use crate::bloom::BloomFilter;
+use crate::column::TypedArrayView;
+use crate::dataset::Dataset;
+use crate::error::{MmappetError, Result};
+use crate::zonemap::ZoneMap;
+
+/// An expression over the columns of one row.
+///
+/// All values are evaluated as `f64`: booleans are `1.0`/`0.0`, and a
+/// comparison or logical operator yields `1.0` when true. A row matches when
+/// the expression evaluates to a non-zero, non-NaN value. 64-bit integers
+/// beyond 2^53 lose precision.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Column(String),
+    Literal(f64),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+}
+
+/// Binary operators of an [`Expr`], from loosest to tightest binding group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl BinaryOp {
+    fn as_str(&self) -> &'static str {
+        match self {
+            BinaryOp::Or => "or",
+            BinaryOp::And => "and",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+        }
+    }
+
+    fn apply(&self, a: f64, b: f64) -> f64 {
+        let truth = |x: bool| if x { 1.0 } else { 0.0 };
+        match self {
+            BinaryOp::Or => truth(is_true(a) || is_true(b)),
+            BinaryOp::And => truth(is_true(a) && is_true(b)),
+            BinaryOp::Eq => truth(a == b),
+            BinaryOp::Ne => truth(a != b),
+            BinaryOp::Lt => truth(a < b),
+            BinaryOp::Le => truth(a <= b),
+            BinaryOp::Gt => truth(a > b),
+            BinaryOp::Ge => truth(a >= b),
+            BinaryOp::Add => a + b,
+            BinaryOp::Sub => a - b,
+            BinaryOp::Mul => a * b,
+            BinaryOp::Div => a / b,
+        }
+    }
+
+    /// Apply the operator element-wise, `out[i] = out[i] op rhs[i]`.
+    ///
+    /// The operator is matched once per slice rather than per element, so
+    /// each arm is a branch-free loop the compiler vectorizes.
+    fn apply_slice(&self, out: &mut [f64], rhs: &[f64]) {
+        macro_rules! zip {
+            (|$a:ident, $b:ident| $e:expr) => {
+                for (a, &$b) in out.iter_mut().zip(rhs) {
+                    let $a = *a;
+                    *a = $e;
+                }
+            };
+        }
+        let truth = |x: bool| x as u8 as f64;
+        match self {
+            BinaryOp::Or => zip!(|a, b| truth(is_true(a) | is_true(b))),
+            BinaryOp::And => zip!(|a, b| truth(is_true(a) & is_true(b))),
+            BinaryOp::Eq => zip!(|a, b| truth(a == b)),
+            BinaryOp::Ne => zip!(|a, b| truth(a != b)),
+            BinaryOp::Lt => zip!(|a, b| truth(a < b)),
+            BinaryOp::Le => zip!(|a, b| truth(a <= b)),
+            BinaryOp::Gt => zip!(|a, b| truth(a > b)),
+            BinaryOp::Ge => zip!(|a, b| truth(a >= b)),
+            BinaryOp::Add => zip!(|a, b| a + b),
+            BinaryOp::Sub => zip!(|a, b| a - b),
+            BinaryOp::Mul => zip!(|a, b| a * b),
+            BinaryOp::Div => zip!(|a, b| a / b),
+        }
+    }
+}
+
+fn is_true(x: f64) -> bool {
+    x != 0.0 && !x.is_nan()
+}
+
+/// Rows evaluated at a time by [`BoundExpr::mask`] and [`Dataset::matching_rows`].
+const CHUNK_ROWS: usize = 4096;
+
+/// Reference a column by name.
+pub fn col(name: &str) -> Expr {
+    Expr::Column(name.to_string())
+}
+
+/// A literal value; numbers and booleans convert to their `f64` value.
+pub fn lit<T: Into<Expr>>(value: T) -> Expr {
+    value.into()
+}
+
+macro_rules! literal_from {
+    ($($t:ty),*) => {
+        $(impl From<$t> for Expr {
+            fn from(value: $t) -> Expr {
+                Expr::Literal(value as f64)
+            }
+        })*
+    };
+}
+
+literal_from!(u8, i8, u16, i16, u32, i32, u64, i64, usize, f32, f64);
+
+impl From<bool> for Expr {
+    fn from(value: bool) -> Expr {
+        Expr::Literal(if value { 1.0 } else { 0.0 })
+    }
+}
+
+impl Expr {
+    /// Parse an expression.
+    ///
+    /// Supports column names (bare, or quoted with backticks or double quotes
+    /// when they contain other characters), numbers, `true`/`false`,
+    /// `+ - * /`, comparisons `== != < <= > >=` (`=` is accepted for `==`),
+    /// `and`/`&&`, `or`/`||`, `not`/`!` and parentheses. Returns an error
+    /// if parentheses, `not` and signs nest more than 256 deep.
+    pub fn parse(text: &str) -> Result<Expr> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser {
+            text,
+            tokens,
+            pos: 0,
+            depth: 0,
+        };
+        let expr = parser.binary(0)?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some((_, at)) => Err(parser.error(at, "unexpected trailing input")),
+        }
+    }
+
+    fn binary(self, op: BinaryOp, other: impl Into<Expr>) -> Expr {
+        Expr::Binary {
+            op,
+            left: Box::new(self),
+            right: Box::new(other.into()),
+        }
+    }
+
+    /// `self == other`.
+    pub fn eq(self, other: impl Into<Expr>) -> Expr {
+        self.binary(BinaryOp::Eq, other)
+    }
+
+    /// `self != other`.
+    pub fn ne(self, other: impl Into<Expr>) -> Expr {
+        self.binary(BinaryOp::Ne, other)
+    }
+
+    /// `self < other`.
+    pub fn lt(self, other: impl Into<Expr>) -> Expr {
+        self.binary(BinaryOp::Lt, other)
+    }
+
+    /// `self <= other`.
+    pub fn le(self, other: impl Into<Expr>) -> Expr {
+        self.binary(BinaryOp::Le, other)
+    }
+
+    /// `self > other`.
+    pub fn gt(self, other: impl Into<Expr>) -> Expr {
+        self.binary(BinaryOp::Gt, other)
+    }
+
+    /// `self >= other`.
+    pub fn ge(self, other: impl Into<Expr>) -> Expr {
+        self.binary(BinaryOp::Ge, other)
+    }
+
+    /// `self and other`.
+    pub fn and(self, other: impl Into<Expr>) -> Expr {
+        self.binary(BinaryOp::And, other)
+    }
+
+    /// `self or other`.
+    pub fn or(self, other: impl Into<Expr>) -> Expr {
+        self.binary(BinaryOp::Or, other)
+    }
+
+    /// `low <= self and self <= high`.
+    pub fn between(self, low: impl Into<Expr>, high: impl Into<Expr>) -> Expr {
+        self.clone().ge(low).and(self.le(high))
+    }
+
+    /// Names of the columns the expression reads, in order of first use.
+    pub fn columns(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        self.collect_columns(&mut names);
+        names
+    }
+
+    fn collect_columns<'e>(&'e self, names: &mut Vec<&'e str>) {
+        match self {
+            Expr::Column(name) => {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+            Expr::Literal(_) => {}
+            Expr::Neg(inner) | Expr::Not(inner) => inner.collect_columns(names),
+            Expr::Binary { left, right, .. } => {
+                left.collect_columns(names);
+                right.collect_columns(names);
+            }
+        }
+    }
+
+    /// Whether no row can match when the column `name` holds `value`, e.g.
+    /// to skip a partition with that key.
+    ///
+    /// Conservative: `false` when that isn't enough to tell.
+    pub(crate) fn never_matches_value(&self, name: &str, value: f64) -> bool {
+        let names = self.columns();
+        let columns: Vec<Option<(f64, f64)>> = names
+            .iter()
+            .map(|&n| (n == name).then_some((value, value)))
+            .collect();
+        Node::compile(self, &names).never_matches(&columns, &|_, _| false)
+    }
+
+    /// Resolve the columns against `dataset` for row-by-row evaluation.
+    ///
+    /// Zone maps and Bloom filters persisted with [`Dataset::build_zone_map`]
+    /// and [`Dataset::build_bloom_filter`] are picked up, so
+    /// [`BoundExpr::mask`] can skip ranges that can't match. Returns an error
+    /// if a column doesn't exist or has no numeric value.
+    pub fn bind<'a>(&self, dataset: &'a Dataset) -> Result<BoundExpr<'a>> {
+        let names = self.columns();
+        let mut arrays = Vec::with_capacity(names.len());
+        let mut zones = Vec::with_capacity(names.len());
+        let mut blooms = Vec::with_capacity(names.len());
+        for name in &names {
+            let column = dataset
+                .column(name)
+                .ok_or_else(|| dataset.column_not_found(name))?;
+            column.check_scalar(name)?;
+            let array = column.as_typed_array();
+            let numeric = match &array {
+                TypedArrayView::Custom(custom) => custom.def().to_f64.is_some(),
+                TypedArrayView::Utf8(_) | TypedArrayView::Categorical(_) => false,
+                _ => true,
+            };
+            if !numeric {
+                return Err(MmappetError::InvalidExpression {
+                    expression: self.to_string(),
+                    position: None,
+                    message: format!(
+                        "column '{}' ({}) has no numeric value",
+                        name,
+                        column.dtype()
+                    ),
+                });
+            }
+            arrays.push(array);
+            zones.push(dataset.zone_map(name)?);
+            blooms.push(dataset.bloom_filter(name)?);
+        }
+        let node = Node::compile(self, &names);
+        Ok(BoundExpr {
+            node,
+            arrays,
+            zones,
+            blooms,
+        })
+    }
+}
+
+impl Not for Expr {
+    type Output = Expr;
+
+    fn not(self) -> Expr {
+        Expr::Not(Box::new(self))
+    }
+}
+
+impl Neg for Expr {
+    type Output = Expr;
+
+    fn neg(self) -> Expr {
+        Expr::Neg(Box::new(self))
+    }
+}
+
+macro_rules! arithmetic_op {
+    ($trait:ident, $method:ident, $op:expr) => {
+        impl<T: Into<Expr>> $trait<T> for Expr {
+            type Output = Expr;
+
+            fn $method(self, other: T) -> Expr {
+                self.binary($op, other)
+            }
+        }
+    };
+}
+
+arithmetic_op!(Add, add, BinaryOp::Add);
+arithmetic_op!(Sub, sub, BinaryOp::Sub);
+arithmetic_op!(Mul, mul, BinaryOp::Mul);
+arithmetic_op!(Div, div, BinaryOp::Div);
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Column(name) if is_identifier(name) => write!(f, "{}", name),
+            Expr::Column(name) => write!(f, "`{}`", name),
+            Expr::Literal(x) => write!(f, "{}", x),
+            Expr::Neg(inner) => write!(f, "-({})", inner),
+            Expr::Not(inner) => write!(f, "not ({})", inner),
+            Expr::Binary { op, left, right } => write!(f, "({} {} {})", left, op.as_str(), right),
+        }
+    }
+}
+
+impl Dataset {
+    /// Iterate over the indices of rows matching `expr`, in row order.
+    ///
+    /// Rows are evaluated lazily a chunk at a time, so taking the first few
+    /// matches only scans as far as needed.
+    pub fn matching_rows(&self, expr: &Expr) -> Result<impl Iterator<Item = usize> + '_> {
+        let bound = expr.bind(self)?;
+        let len = self.len();
+        let mut mask = Vec::new();
+        Ok((0..len).step_by(CHUNK_ROWS).flat_map(move |start| {
+            let rows = start..(start + CHUNK_ROWS).min(len);
+            bound.mask(rows.clone(), &mut mask);
+            rows.zip(&mask)
+                .filter(|(_, &m)| m)
+                .map(|(row, _)| row)
+                .collect::<Vec<_>>()
+        }))
+    }
+
+    /// Evaluate `expr` on every row, `true` where it matches.
+    ///
+    /// The result can be passed to [`Dataset::filter`].
+    pub fn mask(&self, expr: &Expr) -> Result<Vec<bool>> {
+        let bound = expr.bind(self)?;
+        let mut mask = Vec::with_capacity(self.len());
+        let mut chunk = Vec::new();
+        for start in (0..self.len()).step_by(CHUNK_ROWS) {
+            bound.mask(start..(start + CHUNK_ROWS).min(self.len()), &mut chunk);
+            mask.extend_from_slice(&chunk);
+        }
+        Ok(mask)
+    }
+}
+
+/// An [`Expr`] with its columns resolved against a dataset.
+pub struct BoundExpr<'a> {
+    node: Node,
+    arrays: Vec<TypedArrayView<'a>>,
+    /// Zone map of each column, if it has one.
+    zones: Vec<Option<Arc<ZoneMap>>>,
+    /// Bloom filter of each column, if it has one.
+    blooms: Vec<Option<Arc<BloomFilter>>>,
+}
+
+impl BoundExpr<'_> {
+    /// Evaluate the expression at `row`.
+    pub fn eval(&self, row: usize) -> f64 {
+        self.node.eval(&self.arrays, row)
+    }
+
+    /// Evaluate the expression on the rows from `start` on, one per value of
+    /// `out`.
+    pub(crate) fn eval_range(&self, start: usize, out: &mut [f64]) {
+        self.node.eval_chunk(&self.arrays, start, out);
+    }
+
+    /// Whether `row` matches, i.e. evaluates to a non-zero, non-NaN value.
+    pub fn matches(&self, row: usize) -> bool {
+        is_true(self.eval(row))
+    }
+
+    /// Evaluate the expression on a range of rows at once, replacing the
+    /// contents of `out` with whether each row matches.
+    ///
+    /// Faster than calling [`BoundExpr::matches`] per row, since each node
+    /// of the expression is applied to the whole range in a tight loop, and
+    /// ranges that zone maps or Bloom filters rule out aren't read at all.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rows` is out of bounds.
+    pub fn mask(&self, rows: Range<usize>, out: &mut Vec<bool>) {
+        let bounds: Vec<Option<(f64, f64)>> = self
+            .zones
+            .iter()
+            .map(|zones| zones.as_ref().map(|z| z.bounds(rows.clone())))
+            .collect();
+        let absent = |slot: usize, x: f64| {
+            self.blooms[slot]
+                .as_ref()
+                .is_some_and(|bloom| !bloom.contains_f64(self.arrays[slot].dtype(), x))
+        };
+        let pruning = bounds.iter().any(Option::is_some) || self.blooms.iter().any(Option::is_some);
+        if pruning && self.node.never_matches(&bounds, &absent) {
+            out.clear();
+            out.resize(rows.len(), false);
+            return;
+        }
+
+        let mut values = vec![0.0; rows.len()];
+        self.node.eval_chunk(&self.arrays, rows.start, &mut values);
+        out.clear();
+        out.extend(values.iter().map(|&x| is_true(x)));
+    }
+}
+
+/// Expression tree with columns replaced by their slot in `BoundExpr::arrays`.
+enum Node {
+    Column(usize),
+    Literal(f64),
+    Neg(Box<Node>),
+    Not(Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+}
+
+impl Node {
+    fn compile(expr: &Expr, names: &[&str]) -> Node {
+        match expr {
+            Expr::Column(name) => Node::Column(names.iter().position(|n| n == name).unwrap()),
+            Expr::Literal(x) => Node::Literal(*x),
+            Expr::Neg(inner) => Node::Neg(Box::new(Node::compile(inner, names))),
+            Expr::Not(inner) => Node::Not(Box::new(Node::compile(inner, names))),
+            Expr::Binary { op, left, right } => Node::Binary(
+                *op,
+                Box::new(Node::compile(left, names)),
+                Box::new(Node::compile(right, names)),
+            ),
+        }
+    }
+
+    fn eval(&self, arrays: &[TypedArrayView<'_>], row: usize) -> f64 {
+        match self {
+            Node::Column(slot) => arrays[*slot].get_f64(row).unwrap_or(f64::NAN),
+            Node::Literal(x) => *x,
+            Node::Neg(inner) => -inner.eval(arrays, row),
+            Node::Not(inner) => {
+                if is_true(inner.eval(arrays, row)) {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+            Node::Binary(op, left, right) => {
+                let a = left.eval(arrays, row);
+                // Short-circuit the logical operators
+                match op {
+                    BinaryOp::And if !is_true(a) => 0.0,
+                    BinaryOp::Or if is_true(a) => 1.0,
+                    _ => op.apply(a, right.eval(arrays, row)),
+                }
+            }
+        }
+    }
+
+    /// Range of values the node can take, given the range of each column
+    /// (`None` where unknown). NaNs are left out.
+    fn bounds(&self, columns: &[Option<(f64, f64)>]) -> Option<(f64, f64)> {
+        match self {
+            Node::Column(slot) => columns[*slot],
+            Node::Literal(x) => Some((*x, *x)),
+            Node::Neg(inner) => inner.bounds(columns).map(|(lo, hi)| (-hi, -lo)),
+            _ => None,
+        }
+    }
+
+    /// Whether no row with column values in the given ranges can match.
+    /// `absent(slot, x)` tells if `x` certainly doesn't occur in a column.
+    ///
+    /// Conservative: `false` when that isn't enough to tell.
+    fn never_matches(
+        &self,
+        columns: &[Option<(f64, f64)>],
+        absent: &dyn Fn(usize, f64) -> bool,
+    ) -> bool {
+        let Node::Binary(op, left, right) = self else {
+            return false;
+        };
+        match (op, &**left, &**right) {
+            (BinaryOp::Eq, Node::Column(slot), Node::Literal(x))
+            | (BinaryOp::Eq, Node::Literal(x), Node::Column(slot))
+                if absent(*slot, *x) =>
+            {
+                return true;
+            }
+            _ => {}
+        }
+        match op {
+            BinaryOp::And => {
+                left.never_matches(columns, absent) || right.never_matches(columns, absent)
+            }
+            BinaryOp::Or => {
+                left.never_matches(columns, absent) && right.never_matches(columns, absent)
+            }
+            BinaryOp::Eq | BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+                let (Some((alo, ahi)), Some((blo, bhi))) =
+                    (left.bounds(columns), right.bounds(columns))
+                else {
+                    return false;
+                };
+                // Comparisons with NaN never hold, so NaN rows can't match
+                match op {
+                    BinaryOp::Eq => ahi < blo || alo > bhi,
+                    BinaryOp::Lt => alo >= bhi,
+                    BinaryOp::Le => alo > bhi,
+                    BinaryOp::Gt => ahi <= blo,
+                    _ => ahi < blo,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// Evaluate rows `start..start + out.len()` into `out`.
+    fn eval_chunk(&self, arrays: &[TypedArrayView<'_>], start: usize, out: &mut [f64]) {
+        match self {
+            Node::Column(slot) => read_f64(&arrays[*slot], start, out),
+            Node::Literal(x) => out.fill(*x),
+            Node::Neg(inner) => {
+                inner.eval_chunk(arrays, start, out);
+                out.iter_mut().for_each(|x| *x = -*x);
+            }
+            Node::Not(inner) => {
+                inner.eval_chunk(arrays, start, out);
+                out.iter_mut()
+                    .for_each(|x| *x = if is_true(*x) { 0.0 } else { 1.0 });
+            }
+            Node::Binary(op, left, right) => {
+                left.eval_chunk(arrays, start, out);
+                // Skip the right side when the logical result is already known
+                let decided = match op {
+                    BinaryOp::And => out.iter().all(|&a| !is_true(a)),
+                    BinaryOp::Or => out.iter().all(|&a| is_true(a)),
+                    _ => false,
+                };
+                if decided {
+                    out.iter_mut().for_each(|a| *a = op.apply(*a, *a));
+                    return;
+                }
+                let mut rhs = vec![0.0; out.len()];
+                right.eval_chunk(arrays, start, &mut rhs);
+                op.apply_slice(out, &rhs);
+            }
+        }
+    }
+}
+
+/// Write the values of `array` starting at `start` into `out` as `f64`.
+pub(crate) fn read_f64(array: &TypedArrayView<'_>, start: usize, out: &mut [f64]) {
+    fn convert<T: Copy + Into<f64>>(values: &[T], out: &mut [f64]) {
+        for (o, &v) in out.iter_mut().zip(values) {
+            *o = v.into();
+        }
+    }
+
+    let end = start + out.len();
+    match array {
+        TypedArrayView::UInt8(a) => convert(&a.as_slice().unwrap()[start..end], out),
+        TypedArrayView::Int8(a) => convert(&a.as_slice().unwrap()[start..end], out),
+        TypedArrayView::UInt16(a) => convert(&a.as_slice().unwrap()[start..end], out),
+        TypedArrayView::Int16(a) => convert(&a.as_slice().unwrap()[start..end], out),
+        TypedArrayView::UInt32(a) => convert(&a.as_slice().unwrap()[start..end], out),
+        TypedArrayView::Int32(a) => convert(&a.as_slice().unwrap()[start..end], out),
+        TypedArrayView::Float16(a) => {
+            for (o, v) in out.iter_mut().zip(&a.as_slice().unwrap()[start..end]) {
+                *o = v.to_f64();
+            }
+        }
+        TypedArrayView::Float32(a) => convert(&a.as_slice().unwrap()[start..end], out),
+        TypedArrayView::Float64(a) => out.copy_from_slice(&a.as_slice().unwrap()[start..end]),
+        // Rounds beyond 2^53, like `get_f64`
+        TypedArrayView::UInt64(a) => {
+            for (o, &v) in out.iter_mut().zip(&a.as_slice().unwrap()[start..end]) {
+                *o = v as f64;
+            }
+        }
+        TypedArrayView::Int64(a) | TypedArrayView::TimestampMillis(a) | TypedArrayView::TimestampMicros(a) => {
+            for (o, &v) in out.iter_mut().zip(&a.as_slice().unwrap()[start..end]) {
+                *o = v as f64;
+            }
+        }
+        TypedArrayView::Bool(a) => {
+            for (o, &v) in out.iter_mut().zip(&a.as_slice().unwrap()[start..end]) {
+                *o = (v != 0) as u8 as f64;
+            }
+        }
+        TypedArrayView::Utf8(_) | TypedArrayView::Categorical(_) | TypedArrayView::Custom(_) => {
+            for (i, o) in out.iter_mut().enumerate() {
+                *o = array.get_f64(start + i).unwrap_or(f64::NAN);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(f64),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+        && !["and", "or", "not", "true", "false"].contains(&name.to_lowercase().as_str())
+}
+
+/// Split an expression into tokens paired with their byte position.
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>> {
+    let error = |position: usize, message: &str| MmappetError::InvalidExpression {
+        expression: text.to_string(),
+        position: Some(position),
+        message: message.to_string(),
+    };
+
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        let start = i;
+        if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        if c.is_ascii_digit() || (c == b'.' && bytes.get(i + 1).is_some_and(u8::is_ascii_digit)) {
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'.') {
+                // Exponent sign, as in 1e-5
+                if matches!(bytes[i], b'e' | b'E') && matches!(bytes.get(i + 1), Some(b'+' | b'-'))
+                {
+                    i += 1;
+                }
+                i += 1;
+            }
+            let number = text[start..i]
+                .parse()
+                .map_err(|_| error(start, "invalid number"))?;
+            tokens.push((Token::Number(number), start));
+        } else if c.is_ascii_alphabetic() || c == b'_' || !c.is_ascii() {
+            let end = text[start..]
+                .find(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+                .map_or(text.len(), |len| start + len);
+            i = end;
+            let word = &text[start..end];
+            let token = match word.to_lowercase().as_str() {
+                "and" => Token::Op("and"),
+                "or" => Token::Op("or"),
+                "not" => Token::Op("not"),
+                "true" => Token::Number(1.0),
+                "false" => Token::Number(0.0),
+                _ => Token::Ident(word.to_string()),
+            };
+            tokens.push((token, start));
+        } else if c == b'`' || c == b'"' {
+            let len = text[start + 1..]
+                .find(c as char)
+                .ok_or_else(|| error(start, "unterminated quoted column name"))?;
+            tokens.push((
+                Token::Ident(text[start + 1..start + 1 + len].to_string()),
+                start,
+            ));
+            i = start + len + 2;
+        } else {
+            let two = text.get(i..i + 2).unwrap_or("");
+            let (token, len) = match (two, c) {
+                ("==", _) => (Token::Op("=="), 2),
+                ("!=", _) => (Token::Op("!="), 2),
+                ("<=", _) => (Token::Op("<="), 2),
+                (">=", _) => (Token::Op(">="), 2),
+                ("&&", _) => (Token::Op("and"), 2),
+                ("||", _) => (Token::Op("or"), 2),
+                (_, b'=') => (Token::Op("=="), 1),
+                (_, b'<') => (Token::Op("<"), 1),
+                (_, b'>') => (Token::Op(">"), 1),
+                (_, b'!') => (Token::Op("not"), 1),
+                (_, b'+') => (Token::Op("+"), 1),
+                (_, b'-') => (Token::Op("-"), 1),
+                (_, b'*') => (Token::Op("*"), 1),
+                (_, b'/') => (Token::Op("/"), 1),
+                (_, b'(') => (Token::LParen, 1),
+                (_, b')') => (Token::RParen, 1),
+                _ => return Err(error(start, "unexpected character")),
+            };
+            tokens.push((token, start));
+            i += len;
+        }
+    }
+    Ok(tokens)
+}
+
+/// Deepest nesting of parentheses, `not` and signs [`Expr::parse`] accepts,
+/// so malicious input can't overflow the stack.
+const MAX_PARSE_DEPTH: usize = 256;
+
+/// Binary operators by precedence, loosest first.
+const BINARY_LEVELS: [&[(&str, BinaryOp)]; 5] = [
+    &[("or", BinaryOp::Or)],
+    &[("and", BinaryOp::And)],
+    &[
+        ("==", BinaryOp::Eq),
+        ("!=", BinaryOp::Ne),
+        ("<", BinaryOp::Lt),
+        ("<=", BinaryOp::Le),
+        (">", BinaryOp::Gt),
+        (">=", BinaryOp::Ge),
+    ],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[("*", BinaryOp::Mul), ("/", BinaryOp::Div)],
+];
+
+/// Level of comparisons in [`BINARY_LEVELS`], which `not` binds looser than.
+const COMPARISON_LEVEL: usize = 2;
+
+/// Precedence-climbing parser over [`BINARY_LEVELS`], which keeps the stack
+/// shallow for each level of parentheses.
+struct Parser<'t> {
+    text: &'t str,
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    /// Current nesting, see [`MAX_PARSE_DEPTH`].
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<(&Token, usize)> {
+        self.tokens.get(self.pos).map(|(t, at)| (t, *at))
+    }
+
+    fn error(&self, position: usize, message: &str) -> MmappetError {
+        MmappetError::InvalidExpression {
+            expression: self.text.to_string(),
+            position: Some(position),
+            message: message.to_string(),
+        }
+    }
+
+    /// Parse with `parse` what the token at `at` nests.
+    fn nested(&mut self, at: usize, parse: fn(&mut Self) -> Result<Expr>) -> Result<Expr> {
+        if self.depth == MAX_PARSE_DEPTH {
+            return Err(self.error(at, "expression nested too deeply"));
+        }
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+
+    /// Consume the next token if it is a binary operator of `level` or a
+    /// tighter one, and return it with its level.
+    fn eat_op(&mut self, level: usize) -> Option<(BinaryOp, usize)> {
+        let Some((Token::Op(op), _)) = self.peek() else {
+            return None;
+        };
+        let found = BINARY_LEVELS[level..].iter().enumerate().find_map(|(i, ops)| {
+            let (_, b) = ops.iter().find(|(s, _)| s == op)?;
+            Some((*b, level + i))
+        });
+        if found.is_some() {
+            self.pos += 1;
+        }
+        found
+    }
+
+    /// Parse operators of `level` and tighter ones, left to right.
+    fn binary(&mut self, level: usize) -> Result<Expr> {
+        let mut left = self.not(level)?;
+        while let Some((op, op_level)) = self.eat_op(level) {
+            let right = self.binary(op_level + 1)?;
+            left = Expr::Binary {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+        Ok(left)
+    }
+
+    /// `not` applies to a comparison, so it can only start an operand of
+    /// `and`, `or` or another `not`.
+    fn not(&mut self, level: usize) -> Result<Expr> {
+        match self.peek() {
+            Some((Token::Op("not"), at)) if level <= COMPARISON_LEVEL => {
+                self.pos += 1;
+                let operand = self.nested(at, |p| p.binary(COMPARISON_LEVEL))?;
+                Ok(Expr::Not(Box::new(operand)))
+            }
+            _ => self.unary(),
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        match self.peek() {
+            Some((Token::Op("-"), at)) => {
+                self.pos += 1;
+                Ok(Expr::Neg(Box::new(self.nested(at, Self::unary)?)))
+            }
+            Some((Token::Op("+"), at)) => {
+                self.pos += 1;
+                self.nested(at, Self::unary)
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let (token, at) = match self.peek() {
+            Some((token, at)) => (token.clone(), at),
+            None => return Err(self.error(self.text.len(), "unexpected end of expression")),
+        };
+        self.pos += 1;
+        match token {
+            Token::Ident(name) => Ok(Expr::Column(name)),
+            Token::Number(x) => Ok(Expr::Literal(x)),
+            Token::LParen => {
+                let inner = self.nested(at, |p| p.binary(0))?;
+                match self.peek() {
+                    Some((Token::RParen, _)) => {
+                        self.pos += 1;
+                        Ok(inner)
+                    }
+                    Some((_, at)) => Err(self.error(at, "expected ')'")),
+                    None => Err(self.error(self.text.len(), "expected ')'")),
+                }
+            }
+            _ => Err(self.error(at, "expected a column, number or '('")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::write_dataset;
+
+    #[test]
+    fn test_parse_precedence() {
+        let expr = Expr::parse("a + 2 * b > 1e5 and not c == 0 or `d e` <= -.5").unwrap();
+        assert_eq!(
+            expr.to_string(),
+            "((((a + (2 * b)) > 100000) and not ((c == 0))) or (`d e` <= -(0.5)))"
+        );
+        assert_eq!(expr.columns(), vec!["a", "b", "c", "d e"]);
+
+        assert!(matches!(
+            Expr::parse("a > "),
+            Err(MmappetError::InvalidExpression {
+                position: Some(4),
+                ..
+            })
+        ));
+        assert!(Expr::parse("(a > 1").is_err());
+        assert!(Expr::parse("a > 1 2").is_err());
+        assert!(Expr::parse("a # 1").is_err());
+
+        let nested = format!("{}a{}", "(".repeat(256), ")".repeat(256));
+        assert!(Expr::parse(&nested).is_ok());
+        for text in [
+            format!("{}a{}", "(".repeat(257), ")".repeat(257)),
+            format!("{}a", "not ".repeat(100_000)),
+            format!("{}a", "-".repeat(100_000)),
+        ] {
+            assert!(matches!(
+                Expr::parse(&text),
+                Err(MmappetError::InvalidExpression { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_matching_rows() {
+        let dir = write_dataset(
+            "expr",
+            &[
+                ("frame", &[1, 1, 2, 2, 3, 3]),
+                ("intensity", &[10, 500, 20, 700, 30, 900]),
+            ],
+        );
+        let ds = Dataset::open(&dir).unwrap();
+
+        let expr = Expr::parse("intensity > 100 && frame != 2").unwrap();
+        let rows: Vec<usize> = ds.matching_rows(&expr).unwrap().collect();
+        assert_eq!(rows, vec![1, 5]);
+
+        let expr = Expr::parse("intensity / 10 = frame").unwrap();
+        assert_eq!(
+            ds.matching_rows(&expr).unwrap().collect::<Vec<_>>(),
+            vec![0, 2, 4]
+        );
+
+        let built = col("intensity").gt(lit(100)).and(col("frame").ne(2));
+        assert_eq!(built, Expr::parse("intensity > 100 and frame != 2").unwrap());
+        let expr = col("intensity").between(20, 700).and(!col("frame").eq(1));
+        assert_eq!(
+            ds.mask(&expr).unwrap(),
+            vec![false, false, true, true, true, false]
+        );
+        let expr = (col("intensity") * 2 - 1).ge(1000.0).or(col("frame").lt(lit(1.5)));
+        let rows: Vec<usize> = ds.matching_rows(&expr).unwrap().collect();
+        assert_eq!(rows, vec![0, 1, 3, 5]);
+
+        let expr = Expr::parse("missing > 1").unwrap();
+        assert!(matches!(
+            ds.matching_rows(&expr).map(|_| ()),
+            Err(MmappetError::ColumnNotFound { .. })
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
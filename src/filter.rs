@@ -0,0 +1,198 @@
+//! Row selections gathered from a dataset, by boolean mask or row indices.
+
+use std::path::Path;
+
+use ndarray::Array1;
+
+use crate::column::check_indices;
+use crate::dataset::Dataset;
+use crate::dtype::{DType, MmappetType};
+use crate::error::{MmappetError, Result};
+use crate::schema::Schema;
+use crate::writer::DatasetWriter;
+
+/// Rows gathered per column when materializing a selection.
+const GATHER_CHUNK_ROWS: usize = 1 << 16;
+
+impl Dataset {
+    /// Select the rows where `mask` is set.
+    ///
+    /// Returns an error if the mask length differs from the number of rows.
+    pub fn filter(&self, mask: &[bool]) -> Result<FilteredView<'_>> {
+        if mask.len() != self.len() {
+            return Err(MmappetError::InvalidSelection {
+                path: Some(self.path().to_path_buf()),
+                message: format!("mask has {} rows, dataset has {}", mask.len(), self.len()),
+            });
+        }
+        let rows = mask
+            .iter()
+            .enumerate()
+            .filter(|(_, &keep)| keep)
+            .map(|(row, _)| row)
+            .collect();
+        Ok(FilteredView {
+            dataset: self,
+            rows,
+        })
+    }
+
+    /// Select the rows at `indices`, in the given order and possibly repeated.
+    ///
+    /// Columns of the selection are gathered into owned arrays on access.
+    /// Returns an error if an index is out of bounds.
+    pub fn take(&self, indices: &[usize]) -> Result<FilteredView<'_>> {
+        check_indices(indices, self.len()).map_err(|e| e.with_path(self.path()))?;
+        Ok(FilteredView {
+            dataset: self,
+            rows: indices.to_vec(),
+        })
+    }
+}
+
+/// A selection of rows of a [`Dataset`], gathered on access.
+///
+/// Unlike [`crate::DatasetView`] the rows needn't be contiguous, so columns
+/// are copied into owned arrays rather than borrowed.
+pub struct FilteredView<'a> {
+    dataset: &'a Dataset,
+    rows: Vec<usize>,
+}
+
+impl<'a> FilteredView<'a> {
+    /// Create a selection of `rows`, which must lie within the dataset.
+    pub(crate) fn new(dataset: &'a Dataset, rows: Vec<usize>) -> Self {
+        FilteredView { dataset, rows }
+    }
+
+    /// The dataset this selection refers to.
+    pub fn dataset(&self) -> &'a Dataset {
+        self.dataset
+    }
+
+    /// Selected row numbers of the underlying dataset, in order.
+    pub fn rows(&self) -> &[usize] {
+        &self.rows
+    }
+
+    /// Get the schema.
+    pub fn schema(&self) -> &'a Schema {
+        self.dataset.schema()
+    }
+
+    /// Number of selected rows.
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Check if no rows are selected.
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Gather the selected values of a column by name, all values of each
+    /// row of array columns.
+    ///
+    /// Returns an error if the column doesn't exist or the type doesn't match.
+    pub fn get<T: MmappetType>(&self, name: &str) -> Result<Array1<T>> {
+        let data = self.dataset.get::<T>(name)?;
+        let width = self.dataset.mapped_column(name)?.width();
+        // Rows were checked when the selection was made
+        Ok(self
+            .rows
+            .iter()
+            .flat_map(|&row| &data[row * width..(row + 1) * width])
+            .copied()
+            .collect())
+    }
+
+    /// Write the selected rows to a new dataset in `dir`.
+    ///
+    /// Reference columns are copied, so the new dataset is self-contained.
+    /// Returns the number of rows written.
+    pub fn write_to<P: AsRef<Path>>(&self, dir: P) -> Result<usize> {
+        let mut writer = DatasetWriter::create(dir, &copy_schema(self.schema())?)?;
+        write_rows(self.dataset, &mut writer, &self.rows)?;
+        writer.finish()
+    }
+}
+
+/// A schema with the columns and shapes of `schema`, for copies of a dataset.
+pub(crate) fn copy_schema(schema: &Schema) -> Result<Schema> {
+    let defs: Vec<(&str, DType)> = schema
+        .columns()
+        .map(|col| (col.name.as_str(), col.dtype))
+        .collect();
+    let mut copy = Schema::new(&defs)?;
+    for col in schema.columns() {
+        copy.set_shape(&col.name, &col.shape)?;
+    }
+    Ok(copy)
+}
+
+/// Append the given rows of every column of `dataset` to `writer`, whose
+/// schema is [`copy_schema`] of the dataset's.
+pub(crate) fn write_rows(
+    dataset: &Dataset,
+    writer: &mut DatasetWriter,
+    rows: &[usize],
+) -> Result<()> {
+    let mut buf = Vec::new();
+    for (index, col) in dataset.schema().columns().enumerate() {
+        let column = dataset.column(&col.name).unwrap();
+        if let Some(strings) = column.as_str_array() {
+            writer.write_strs(index, rows.iter().map(|&row| strings.get(row)))?;
+            continue;
+        }
+        if let Some(labels) = column.as_categorical() {
+            writer.write_labels(index, rows.iter().map(|&row| labels.value(row)))?;
+            continue;
+        }
+        for rows in rows.chunks(GATHER_CHUNK_ROWS) {
+            buf.clear();
+            column.gather_bytes(rows, &mut buf);
+            writer.write_bytes(index, &buf, rows.len())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::write_dataset;
+
+    #[test]
+    fn test_filter() {
+        let dir = write_dataset(
+            "filter",
+            &[("frame", &[1, 2, 3, 4]), ("tof", &[10, 20, 30, 40])],
+        );
+        let ds = Dataset::open(&dir).unwrap();
+
+        let view = ds.filter(&[true, false, false, true]).unwrap();
+        assert_eq!(view.rows(), &[0, 3]);
+        assert_eq!(view.get::<u32>("tof").unwrap().to_vec(), vec![10, 40]);
+        assert!(view.get::<f32>("tof").is_err());
+        assert!(matches!(
+            ds.filter(&[true]),
+            Err(MmappetError::InvalidSelection { .. })
+        ));
+
+        let out = dir.with_extension("filtered");
+        assert_eq!(view.write_to(&out).unwrap(), 2);
+        let copy = Dataset::open(&out).unwrap();
+        assert_eq!(copy.get::<u32>("frame").unwrap(), &[1, 4]);
+        assert_eq!(copy.get::<u32>("tof").unwrap(), &[10, 40]);
+
+        let taken = ds.take(&[2, 0, 2]).unwrap();
+        assert_eq!(taken.get::<u32>("frame").unwrap().to_vec(), vec![3, 1, 3]);
+        assert!(matches!(
+            ds.take(&[4]),
+            Err(MmappetError::InvalidSelection { .. })
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+        std::fs::remove_dir_all(out).unwrap();
+    }
+}
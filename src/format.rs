@@ -0,0 +1,207 @@
+//! Text formatting of column values for display and export.
+
+use std::fmt::{Display, LowerExp};
+
+use crate::column::TypedArrayView;
+
+/// How booleans are rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BoolStyle {
+    /// `true` / `false`
+    #[default]
+    TrueFalse,
+    /// `1` / `0`
+    OneZero,
+}
+
+/// How timestamps are rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimestampStyle {
+    /// ISO 8601 in UTC, e.g. `2024-05-01T12:30:00.250Z`
+    #[default]
+    Iso8601,
+    /// Date and time separated by a space without a zone, e.g.
+    /// `2024-05-01 12:30:00.250`, as SQL databases read them
+    Space,
+    /// The stored ticks since the Unix epoch, e.g. `1714566600250`
+    Ticks,
+}
+
+/// Options controlling how values are rendered as text.
+///
+/// The default renders floats with 6 decimals, integers plainly, booleans
+/// as `true`/`false` and timestamps as ISO 8601.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueFormat {
+    /// Digits after the decimal point (or in the mantissa, for scientific
+    /// notation); `None` writes the shortest text that reads back as the
+    /// same value.
+    pub precision: Option<usize>,
+    /// Use scientific notation for non-zero floats with magnitude below this.
+    pub sci_below: Option<f64>,
+    /// Use scientific notation for floats with magnitude at or above this.
+    pub sci_above: Option<f64>,
+    /// Separator inserted between groups of three integer digits.
+    pub thousands_separator: Option<char>,
+    /// Rendering of bool columns.
+    pub bool_style: BoolStyle,
+    /// Rendering of timestamp columns.
+    pub timestamp_style: TimestampStyle,
+}
+
+impl Default for ValueFormat {
+    fn default() -> Self {
+        ValueFormat {
+            precision: Some(6),
+            sci_below: None,
+            sci_above: None,
+            thousands_separator: None,
+            bool_style: BoolStyle::TrueFalse,
+            timestamp_style: TimestampStyle::Iso8601,
+        }
+    }
+}
+
+impl ValueFormat {
+    /// Format an integer value.
+    pub fn format_int<I: Into<i128>>(&self, value: I) -> String {
+        self.group_thousands(value.into().to_string())
+    }
+
+    /// Format a floating point value.
+    ///
+    /// Without a precision, `f32` values are written as the shortest text
+    /// that reads back as the same `f32`.
+    pub fn format_float<F: Into<f64> + Display + LowerExp + Copy>(&self, value: F) -> String {
+        let magnitude = value.into().abs();
+        if !magnitude.is_finite() {
+            return value.to_string();
+        }
+        let scientific = magnitude != 0.0
+            && (self.sci_below.is_some_and(|t| magnitude < t)
+                || self.sci_above.is_some_and(|t| magnitude >= t));
+        match (scientific, self.precision) {
+            (true, Some(precision)) => format!("{:.*e}", precision, value.into()),
+            (true, None) => format!("{:e}", value),
+            (false, Some(precision)) => {
+                self.group_thousands(format!("{:.*}", precision, value.into()))
+            }
+            (false, None) => self.group_thousands(value.to_string()),
+        }
+    }
+
+    /// Format a boolean value.
+    pub fn format_bool(&self, value: bool) -> String {
+        match (self.bool_style, value) {
+            (BoolStyle::TrueFalse, v) => v.to_string(),
+            (BoolStyle::OneZero, true) => "1".to_string(),
+            (BoolStyle::OneZero, false) => "0".to_string(),
+        }
+    }
+
+    /// Format a timestamp of `ticks_per_second` ticks since the Unix epoch in
+    /// UTC, following the [`TimestampStyle`].
+    pub fn format_timestamp(&self, ticks: i64, ticks_per_second: i64) -> String {
+        match self.timestamp_style {
+            TimestampStyle::Iso8601 => crate::timestamp::format_iso8601(ticks, ticks_per_second),
+            TimestampStyle::Space => {
+                let text = crate::timestamp::format_iso8601(ticks, ticks_per_second);
+                text.trim_end_matches('Z').replacen('T', " ", 1)
+            }
+            TimestampStyle::Ticks => ticks.to_string(),
+        }
+    }
+
+    /// Format element `index` of a dynamically-typed array.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn format_at(&self, array: &TypedArrayView<'_>, index: usize) -> String {
+        match array {
+            TypedArrayView::UInt8(arr) => self.format_int(arr[index]),
+            TypedArrayView::Int8(arr) => self.format_int(arr[index]),
+            TypedArrayView::UInt16(arr) => self.format_int(arr[index]),
+            TypedArrayView::Int16(arr) => self.format_int(arr[index]),
+            TypedArrayView::UInt32(arr) => self.format_int(arr[index]),
+            TypedArrayView::Int32(arr) => self.format_int(arr[index]),
+            TypedArrayView::UInt64(arr) => self.format_int(arr[index]),
+            TypedArrayView::Int64(arr) => self.format_int(arr[index]),
+            TypedArrayView::Float16(arr) => self.format_float(arr[index]),
+            TypedArrayView::Float32(arr) => self.format_float(arr[index]),
+            TypedArrayView::Float64(arr) => self.format_float(arr[index]),
+            TypedArrayView::Bool(arr) => self.format_bool(arr[index] != 0),
+            TypedArrayView::TimestampMillis(arr) => self.format_timestamp(arr[index], 1_000),
+            TypedArrayView::TimestampMicros(arr) => self.format_timestamp(arr[index], 1_000_000),
+            TypedArrayView::Utf8(arr) => arr.get(index).to_string(),
+            TypedArrayView::Categorical(arr) => arr.value(index).to_string(),
+            TypedArrayView::Custom(arr) => arr.format(index, self),
+        }
+    }
+
+    /// Insert the thousands separator into the integer part of a plain number.
+    fn group_thousands(&self, text: String) -> String {
+        let separator = match self.thousands_separator {
+            Some(sep) => sep,
+            None => return text,
+        };
+
+        let (sign, unsigned) = match text.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", text.as_str()),
+        };
+        let (integer, fraction) = unsigned.split_at(unsigned.find('.').unwrap_or(unsigned.len()));
+
+        let mut out = String::with_capacity(text.len() + integer.len() / 3);
+        out.push_str(sign);
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i) % 3 == 0 {
+                out.push(separator);
+            }
+            out.push(digit);
+        }
+        out.push_str(fraction);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_format() {
+        let fmt = ValueFormat::default();
+        assert_eq!(fmt.format_float(677.962402), "677.962402");
+        assert_eq!(fmt.format_int(1234567u32), "1234567");
+        assert_eq!(fmt.format_bool(true), "true");
+    }
+
+    #[test]
+    fn test_scientific_and_separators() {
+        let fmt = ValueFormat {
+            precision: Some(2),
+            sci_below: Some(1e-3),
+            sci_above: Some(1e9),
+            thousands_separator: Some(','),
+            bool_style: BoolStyle::OneZero,
+            timestamp_style: TimestampStyle::Space,
+        };
+        assert_eq!(fmt.format_float(0.000012), "1.20e-5");
+        assert_eq!(fmt.format_float(2.5e10), "2.50e10");
+        assert_eq!(fmt.format_float(-1234567.891), "-1,234,567.89");
+        assert_eq!(fmt.format_float(0.0), "0.00");
+        assert_eq!(fmt.format_float(f64::NAN), "NaN");
+        assert_eq!(fmt.format_int(-1000i64), "-1,000");
+        assert_eq!(fmt.format_int(999u16), "999");
+        assert_eq!(fmt.format_bool(false), "0");
+        assert_eq!(fmt.format_timestamp(1_500, 1_000), "1970-01-01 00:00:01.500");
+
+        let shortest = ValueFormat { precision: None, ..fmt };
+        assert_eq!(shortest.format_float(0.1f32), "0.1");
+        assert_eq!(shortest.format_float(1234.5678), "1,234.5678");
+        assert_eq!(shortest.format_float(0.000012), "1.2e-5");
+        let ticks = ValueFormat { timestamp_style: TimestampStyle::Ticks, ..shortest };
+        assert_eq!(ticks.format_timestamp(-1_500, 1_000), "-1500");
+    }
+}
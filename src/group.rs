@@ -0,0 +1,297 @@
+//! Iteration over contiguous groups of equal keys, and persistent group indexes.
+
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::dataset::Dataset;
+use crate::dtype::{DType, MmappetType};
+use crate::error::{MmappetError, Result};
+use crate::view::DatasetView;
+
+/// Magic bytes at the start of a `<column>.groups` file.
+const GROUP_INDEX_MAGIC: &[u8; 8] = b"MMPGIDX1";
+
+impl Dataset {
+    /// Iterate over runs of equal values in a key column.
+    ///
+    /// Yields `(key, view)` for each maximal run of consecutive rows sharing
+    /// the same key, in row order. No hashing is involved, so the key column
+    /// should be sorted or run-length grouped (e.g. `frame` in acquisition
+    /// order); otherwise the same key is yielded once per run.
+    ///
+    /// Returns an error if the column doesn't exist or the type doesn't match.
+    pub fn group_spans<K: MmappetType + PartialEq>(&self, key: &str) -> Result<GroupSpans<'_, K>> {
+        self.mapped_column(key)?.check_scalar(key)?;
+        let keys = self.get::<K>(key)?;
+        Ok(GroupSpans {
+            dataset: self,
+            keys,
+            offset: 0,
+        })
+    }
+
+    /// Build the group index for a key column and persist it as `<key>.groups`.
+    ///
+    /// Each key value must occupy a single contiguous run of rows. Later calls to
+    /// [`Dataset::group`], also from other processes, load the persisted index
+    /// instead of scanning the column.
+    pub fn build_group_index(&self, key: &str) -> Result<()> {
+        let index = GroupIndex::build(self, key)?;
+        index.write(&self.path().join(format!("{}.groups", key)))?;
+        self.cache_group_index(key, Arc::new(index));
+        Ok(())
+    }
+
+    /// Get the rows whose `key` column equals `value` as a zero-copy view.
+    ///
+    /// Uses the persisted `<key>.groups` index when present and up to date,
+    /// otherwise builds the index in memory (without writing it) on first use.
+    /// Either way the index is cached, so subsequent lookups are O(1).
+    /// Returns `Ok(None)` if no row has that key.
+    pub fn group<K: MmappetType>(&self, key: &str, value: K) -> Result<Option<DatasetView<'_>>> {
+        let column = self.column(key).ok_or_else(|| self.column_not_found(key))?;
+        column.check_scalar(key)?;
+        if column.dtype() != K::DTYPE {
+            return Err(MmappetError::TypeMismatch {
+                column: Some(key.to_string()),
+                expected: K::DTYPE,
+                actual: column.dtype(),
+            });
+        }
+
+        let index = match self.cached_group_index(key) {
+            Some(index) => index,
+            None => {
+                let path = self.path().join(format!("{}.groups", key));
+                let index = match GroupIndex::read(&path)? {
+                    Some(index) if index.rows == self.len() => index,
+                    _ => GroupIndex::build(self, key)?,
+                };
+                let index = Arc::new(index);
+                self.cache_group_index(key, Arc::clone(&index));
+                index
+            }
+        };
+
+        Ok(index
+            .groups
+            .get(&key_bits(bytemuck::bytes_of(&value)))
+            .map(|rows| DatasetView::new(self, rows.clone())))
+    }
+}
+
+/// Row ranges of each key of a grouped column.
+///
+/// Keys are stored as their zero-extended bit patterns, so one index type
+/// serves every key dtype.
+pub(crate) struct GroupIndex {
+    /// Row count of the dataset the index was built for.
+    rows: usize,
+    groups: HashMap<u64, Range<usize>>,
+}
+
+impl GroupIndex {
+    /// Scan `key` and record the row range of every run.
+    fn build(dataset: &Dataset, key: &str) -> Result<Self> {
+        let column = dataset
+            .column(key)
+            .ok_or_else(|| dataset.column_not_found(key))?;
+        column.check_scalar(key)?;
+        if column.dtype() == DType::Utf8 {
+            return Err(MmappetError::InvalidDType {
+                dtype: DType::Utf8.to_string(),
+                message: format!("string column '{}' can't be a group key", key),
+            });
+        }
+        let element_size = column.dtype().size_bytes();
+        let bytes = column.as_bytes();
+
+        let mut groups = HashMap::new();
+        let mut start = 0;
+        while start < column.len() {
+            let current = &bytes[start * element_size..(start + 1) * element_size];
+            let mut end = start + 1;
+            while end < column.len()
+                && &bytes[end * element_size..(end + 1) * element_size] == current
+            {
+                end += 1;
+            }
+            if groups.insert(key_bits(current), start..end).is_some() {
+                return Err(MmappetError::UngroupedKey {
+                    path: Some(dataset.path().to_path_buf()),
+                    column: key.to_string(),
+                });
+            }
+            start = end;
+        }
+
+        Ok(GroupIndex {
+            rows: column.len(),
+            groups,
+        })
+    }
+
+    /// Write the index: magic, row count, group count, then `(key, start, end)`
+    /// triples, all as native-endian `u64`.
+    fn write(&self, path: &Path) -> Result<()> {
+        let mut buf = Vec::with_capacity(24 + self.groups.len() * 24);
+        buf.extend_from_slice(GROUP_INDEX_MAGIC);
+        buf.extend_from_slice(&(self.rows as u64).to_ne_bytes());
+        buf.extend_from_slice(&(self.groups.len() as u64).to_ne_bytes());
+
+        let mut entries: Vec<_> = self.groups.iter().collect();
+        entries.sort_by_key(|(_, rows)| rows.start);
+        for (key, rows) in entries {
+            buf.extend_from_slice(&key.to_ne_bytes());
+            buf.extend_from_slice(&(rows.start as u64).to_ne_bytes());
+            buf.extend_from_slice(&(rows.end as u64).to_ne_bytes());
+        }
+
+        fs::write(path, buf).map_err(|e| MmappetError::io(path, e))?;
+        Ok(())
+    }
+
+    /// Read an index written by [`GroupIndex::write`]; `Ok(None)` if absent.
+    fn read(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let bytes = fs::read(path).map_err(|e| MmappetError::io(path, e))?;
+        let invalid = || MmappetError::InvalidIndex {
+            path: path.to_path_buf(),
+            offset: None,
+        };
+
+        let word = |i: usize| -> Option<u64> {
+            let b = bytes.get(8 + i * 8..16 + i * 8)?;
+            Some(u64::from_ne_bytes(b.try_into().ok()?))
+        };
+        if bytes.get(..8) != Some(&GROUP_INDEX_MAGIC[..]) {
+            return Err(invalid());
+        }
+        let rows = word(0).ok_or_else(invalid)? as usize;
+        let count = word(1).ok_or_else(invalid)? as usize;
+        if bytes.len() != 24 + count.checked_mul(24).ok_or_else(invalid)? {
+            return Err(invalid());
+        }
+
+        let mut groups = HashMap::with_capacity(count);
+        for i in 0..count {
+            let key = word(2 + 3 * i).ok_or_else(invalid)?;
+            let start = word(3 + 3 * i).ok_or_else(invalid)? as usize;
+            let end = word(4 + 3 * i).ok_or_else(invalid)? as usize;
+            if start > end || end > rows {
+                return Err(invalid());
+            }
+            groups.insert(key, start..end);
+        }
+
+        Ok(Some(GroupIndex { rows, groups }))
+    }
+}
+
+/// Zero-extend a key's bytes to a `u64`.
+pub(crate) fn key_bits(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf[..bytes.len()].copy_from_slice(bytes);
+    u64::from_ne_bytes(buf)
+}
+
+/// Iterator over `(key, view)` groups, created by [`Dataset::group_spans`].
+pub struct GroupSpans<'a, K> {
+    dataset: &'a Dataset,
+    keys: &'a [K],
+    offset: usize,
+}
+
+impl<'a, K: MmappetType + PartialEq> Iterator for GroupSpans<'a, K> {
+    type Item = (K, DatasetView<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.offset;
+        let key = *self.keys.get(start)?;
+        let end = self.keys[start..]
+            .iter()
+            .position(|k| *k != key)
+            .map_or(self.keys.len(), |len| start + len);
+        self.offset = end;
+        Some((key, DatasetView::new(self.dataset, start..end)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::MmappetError;
+    use crate::test_util::write_dataset;
+
+    #[test]
+    fn test_group_spans() {
+        let dir = write_dataset(
+            "group-spans",
+            &[
+                ("frame", &[1, 1, 2, 5, 5, 5]),
+                ("tof", &[10, 11, 12, 13, 14, 15]),
+            ],
+        );
+        let ds = Dataset::open(&dir).unwrap();
+
+        let groups: Vec<(u32, Vec<u32>)> = ds
+            .group_spans::<u32>("frame")
+            .unwrap()
+            .map(|(key, view)| (key, view.get::<u32>("tof").unwrap().to_vec()))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![(1, vec![10, 11]), (2, vec![12]), (5, vec![13, 14, 15])]
+        );
+
+        assert!(matches!(
+            ds.group_spans::<f32>("frame"),
+            Err(MmappetError::TypeMismatch { .. })
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_group_index() {
+        let dir = write_dataset(
+            "group-index",
+            &[
+                ("frame", &[1, 1, 2, 5, 5, 5]),
+                ("tof", &[10, 11, 12, 13, 14, 15]),
+            ],
+        );
+        let ds = Dataset::open(&dir).unwrap();
+        ds.build_group_index("frame").unwrap();
+        assert!(dir.join("frame.groups").exists());
+
+        // A fresh handle loads the persisted index
+        let ds = Dataset::open(&dir).unwrap();
+        let view = ds.group("frame", 5u32).unwrap().unwrap();
+        assert_eq!(view.rows(), 3..6);
+        assert_eq!(view.get::<u32>("tof").unwrap(), &[13, 14, 15]);
+        assert!(ds.group("frame", 3u32).unwrap().is_none());
+        assert!(matches!(
+            ds.group("frame", 5.0f32),
+            Err(MmappetError::TypeMismatch { .. })
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_group_index_ungrouped() {
+        let dir = write_dataset("group-ungrouped", &[("frame", &[1, 2, 1])]);
+        let ds = Dataset::open(&dir).unwrap();
+        assert!(matches!(
+            ds.build_group_index("frame"),
+            Err(MmappetError::UngroupedKey { .. })
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
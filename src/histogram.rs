@@ -0,0 +1,262 @@
+//! Histograms of numeric columns.
+
+use std::ops::Range;
+
+use crate::column::{Column, TypedArrayView};
+use crate::error::{MmappetError, Result};
+use crate::expr::read_f64;
+use crate::stats::STATS_CHUNK_ROWS;
+
+/// Counts of a column's values in equal-width bins.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    /// Bin edges, one more than there are bins. Bin `i` holds values within
+    /// `edges[i]..edges[i + 1]`, the last one also values equal to its upper
+    /// edge.
+    pub edges: Vec<f64>,
+    /// Number of values in every bin.
+    pub counts: Vec<u64>,
+}
+
+impl Histogram {
+    /// Empty histogram of `bins` equal-width bins covering `min..=max`.
+    fn empty(min: f64, max: f64, bins: usize) -> Self {
+        // Divided first, so ranges as wide as f64's don't overflow
+        let width = max / bins as f64 - min / bins as f64;
+        let mut edges: Vec<f64> = (0..bins).map(|i| min + width * i as f64).collect();
+        edges.push(max);
+        Histogram {
+            edges,
+            counts: vec![0; bins],
+        }
+    }
+
+    /// Total number of values counted.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Bin of `x`, if it's within the range of the histogram.
+    pub(crate) fn bin(&self, x: f64) -> Option<usize> {
+        let (min, max) = (self.edges[0], *self.edges.last().unwrap());
+        if !(min..=max).contains(&x) {
+            return None;
+        }
+        let bins = self.counts.len();
+        let width = max / bins as f64 - min / bins as f64;
+        let mut bin = ((x / bins as f64 - min / bins as f64) / width * bins as f64) as usize;
+        bin = bin.min(bins - 1);
+        // Rounding may put values next to an edge into the neighbouring bin
+        if x < self.edges[bin] {
+            bin -= 1;
+        } else if x >= self.edges[bin + 1] && bin + 1 < bins {
+            bin += 1;
+        }
+        Some(bin)
+    }
+
+    /// Histogram of the values of both `self` and `other`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the histograms' edges differ.
+    pub fn merge(mut self, other: Histogram) -> Histogram {
+        assert_eq!(
+            self.edges, other.edges,
+            "histograms with different edges can't be merged"
+        );
+        for (count, other) in self.counts.iter_mut().zip(other.counts) {
+            *count += other;
+        }
+        self
+    }
+}
+
+impl Column {
+    /// Histogram of the column's values, all values of the arrays of array
+    /// columns, in `bins` equal-width bins spanning the range of the finite
+    /// ones.
+    ///
+    /// NaNs and infinities are skipped. If all finite values are equal, the
+    /// range is widened to `x - 0.5..=x + 0.5`, or by the precision of `x`
+    /// where that's coarser; without finite values it's `0..=1` and all
+    /// counts are zero. Takes two passes: one for the range, one for the
+    /// counts.
+    ///
+    /// Returns an error for columns without numeric values or if `bins` is
+    /// zero.
+    pub fn histogram(&self, bins: usize) -> Result<Histogram> {
+        let array = numeric_array(self)?;
+        let (min, max) = histogram_bounds(finite_range(&array, 0..array.len()));
+        let histogram = checked_histogram(min, max, bins)?;
+        Ok(range_histogram(&array, 0..array.len(), histogram))
+    }
+
+    /// Histogram of the column's values within `min..=max`, in `bins`
+    /// equal-width bins, computed in one pass.
+    ///
+    /// NaNs and values outside the range are skipped.
+    ///
+    /// Returns an error for columns without numeric values, if `bins` is zero
+    /// or if `min..max` isn't a finite, non-empty range.
+    pub fn histogram_range(&self, min: f64, max: f64, bins: usize) -> Result<Histogram> {
+        let array = numeric_array(self)?;
+        let histogram = checked_histogram(min, max, bins)?;
+        Ok(range_histogram(&array, 0..array.len(), histogram))
+    }
+}
+
+/// The column as a typed array of values converting to `f64`.
+pub(crate) fn numeric_array(column: &Column) -> Result<TypedArrayView<'_>> {
+    column
+        .numeric_array()
+        .ok_or_else(|| MmappetError::InvalidDType {
+            dtype: column.dtype().to_string(),
+            message: "column has no numeric values".to_string(),
+        })
+}
+
+/// Smallest and largest finite value in `rows` of `array`, which must convert
+/// to `f64`, if there are any.
+pub(crate) fn finite_range(array: &TypedArrayView<'_>, rows: Range<usize>) -> Option<(f64, f64)> {
+    let mut values = vec![0.0; STATS_CHUNK_ROWS.min(rows.len())];
+    let (mut min, mut max) = (f64::INFINITY, f64::NEG_INFINITY);
+    for start in rows.clone().step_by(STATS_CHUNK_ROWS) {
+        let values = &mut values[..STATS_CHUNK_ROWS.min(rows.end - start)];
+        read_f64(array, start, values);
+        for &x in values.iter().filter(|x| x.is_finite()) {
+            min = min.min(x);
+            max = max.max(x);
+        }
+    }
+    (min <= max).then_some((min, max))
+}
+
+/// Range to build a histogram over from the range of the finite values, if
+/// any, widening it if it's a single value.
+pub(crate) fn histogram_bounds(range: Option<(f64, f64)>) -> (f64, f64) {
+    match range {
+        None => (0.0, 1.0),
+        Some((min, max)) if min < max => (min, max),
+        Some((x, _)) => {
+            // Half a unit, or the value's precision once that's coarser
+            let step = (x.abs() * f64::EPSILON).max(0.5);
+            let (low, high) = (x - step, x + step);
+            match (low.is_finite(), high.is_finite()) {
+                (true, true) => (low, high),
+                (true, false) => (low, x),
+                _ => (x, high),
+            }
+        }
+    }
+}
+
+/// Empty histogram after checking its parameters.
+pub(crate) fn checked_histogram(min: f64, max: f64, bins: usize) -> Result<Histogram> {
+    if bins == 0 {
+        return Err(MmappetError::InvalidHistogram {
+            message: "a histogram needs at least one bin".to_string(),
+        });
+    }
+    if !(min.is_finite() && max.is_finite() && min < max) {
+        return Err(MmappetError::InvalidHistogram {
+            message: format!("range {}..{} is not finite and non-empty", min, max),
+        });
+    }
+    Ok(Histogram::empty(min, max, bins))
+}
+
+/// Add the values in `rows` of `array`, which must convert to `f64`, to
+/// `histogram`.
+pub(crate) fn range_histogram(
+    array: &TypedArrayView<'_>,
+    rows: Range<usize>,
+    mut histogram: Histogram,
+) -> Histogram {
+    let mut values = vec![0.0; STATS_CHUNK_ROWS.min(rows.len())];
+    for start in rows.clone().step_by(STATS_CHUNK_ROWS) {
+        let values = &mut values[..STATS_CHUNK_ROWS.min(rows.end - start)];
+        read_f64(array, start, values);
+        for &x in values.iter() {
+            if let Some(bin) = histogram.bin(x) {
+                histogram.counts[bin] += 1;
+            }
+        }
+    }
+    histogram
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dataset::Dataset;
+
+    #[test]
+    fn test_histogram() {
+        let mut values: Vec<f64> = (0..1000).map(|i| i as f64).collect();
+        values.push(f64::NAN);
+        let ds = Dataset::from_columns(vec![
+            ("x", values.into()),
+            ("c", vec![7u8; 1001].into()),
+            ("s", vec!["a"; 1001].into()),
+        ])
+        .unwrap();
+
+        let histogram = ds["x"].histogram(4).unwrap();
+        assert_eq!(histogram.edges, [0.0, 249.75, 499.5, 749.25, 999.0]);
+        assert_eq!(histogram.counts, [250, 250, 250, 250]);
+
+        // Edges are inclusive below, the last bin also above
+        let histogram = ds["x"].histogram_range(100.0, 200.0, 10).unwrap();
+        assert_eq!(histogram.counts, [10, 10, 10, 10, 10, 10, 10, 10, 10, 11]);
+        assert_eq!(histogram.total(), 101);
+
+        let constant = ds["c"].histogram(2).unwrap();
+        assert_eq!(
+            (constant.edges, constant.counts),
+            (vec![6.5, 7.0, 7.5], vec![0, 1001])
+        );
+        assert!(ds["s"].histogram(2).is_err());
+        assert!(ds["x"].histogram(0).is_err());
+        assert!(ds["x"].histogram_range(1.0, 1.0, 2).is_err());
+        assert!(ds["x"].histogram_range(0.0, f64::INFINITY, 2).is_err());
+    }
+
+    #[test]
+    fn test_histogram_extreme_values() {
+        let ds = Dataset::from_columns(vec![
+            (
+                "inf",
+                vec![f64::NEG_INFINITY, 1.0, 2.0, f64::INFINITY].into(),
+            ),
+            ("nan", vec![f64::NAN; 4].into()),
+            ("big", vec![1e17f64; 4].into()),
+            ("wide", vec![-1e308f64, 0.0, 1e308, -1e307].into()),
+            ("max", vec![f64::MAX; 4].into()),
+        ])
+        .unwrap();
+
+        // Infinities are skipped like NaNs
+        let histogram = ds["inf"].histogram(2).unwrap();
+        assert_eq!(
+            (histogram.edges, histogram.counts),
+            (vec![1.0, 1.5, 2.0], vec![1, 1])
+        );
+        let histogram = ds["nan"].histogram(2).unwrap();
+        assert_eq!(
+            (histogram.total(), histogram.edges),
+            (0, vec![0.0, 0.5, 1.0])
+        );
+
+        // Widened by the values' precision, as 1e17 + 0.5 == 1e17
+        let histogram = ds["big"].histogram(2).unwrap();
+        assert!(histogram.edges[0] < 1e17 && histogram.edges[2] > 1e17);
+        assert_eq!(histogram.total(), 4);
+        let histogram = ds["max"].histogram(2).unwrap();
+        assert!(histogram.edges.iter().all(|edge| edge.is_finite()));
+        assert_eq!(histogram.total(), 4);
+
+        let histogram = ds["wide"].histogram(4).unwrap();
+        assert_eq!(histogram.edges, [-1e308, -5e307, 0.0, 5e307, 1e308]);
+        assert_eq!(histogram.counts, [1, 1, 1, 1]);
+    }
+}
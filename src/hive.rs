@@ -0,0 +1,274 @@
+//! Datasets partitioned by the values of a key column.
+//!
+//! A partitioned dataset is a directory with one dataset per key value,
+//! named `{key}={value}`, e.g. `run_id=3/`. Every partition keeps the key
+//! column, so all of them have the same columns.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ndarray::ArrayView1;
+
+use crate::column::TypedArrayView;
+use crate::concat::ConcatDataset;
+use crate::dataset::Dataset;
+use crate::error::{MmappetError, Result};
+use crate::expr::Expr;
+use crate::schema::Schema;
+use crate::writer::DatasetWriter;
+
+/// Writes row batches split by the values of an integer key column, one
+/// dataset per value, to be read back with [`ConcatDataset::open_hive`].
+///
+/// ```rust,no_run
+/// use mmappet::{col, ConcatDataset, DType, PartitionWriter, Schema};
+///
+/// let schema = Schema::new(&[("run_id", DType::UInt32), ("mz", DType::Float64)])?;
+/// let mut writer = PartitionWriter::create("runs.mmappet", &schema, "run_id")?;
+/// # let batch = [];
+/// writer.write_batch(&batch)?;
+/// writer.finish()?;
+///
+/// // Opens runs.mmappet/run_id=3 only
+/// let run = ConcatDataset::open_hive_where("runs.mmappet", &col("run_id").eq(3))?;
+/// # Ok::<(), mmappet::MmappetError>(())
+/// ```
+pub struct PartitionWriter {
+    dir: PathBuf,
+    schema: Schema,
+    /// Index of the key column.
+    key: usize,
+    writers: BTreeMap<i64, DatasetWriter>,
+}
+
+impl PartitionWriter {
+    /// Start writing partitions of `schema` by the column `key` in `dir`.
+    ///
+    /// Returns an error if `key` doesn't exist or isn't a scalar integer
+    /// column.
+    pub fn create<P: AsRef<Path>>(dir: P, schema: &Schema, key: &str) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let col = schema.get(key).ok_or_else(|| MmappetError::ColumnNotFound {
+            path: Some(dir.clone()),
+            column: key.to_string(),
+        })?;
+        if !col.dtype.is_integer() || col.width() != 1 {
+            return Err(MmappetError::InvalidDType {
+                dtype: col.type_name(),
+                message: format!("partition key '{}' must be a scalar integer column", key),
+            });
+        }
+        fs::create_dir_all(&dir).map_err(|e| MmappetError::io(&dir, e))?;
+        Ok(PartitionWriter {
+            dir,
+            schema: schema.clone(),
+            key: col.index,
+            writers: BTreeMap::new(),
+        })
+    }
+
+    /// Append a row batch as [`DatasetWriter::write_batch`] does, each row
+    /// to the partition of its key.
+    ///
+    /// Nothing is written if the batch doesn't match the schema.
+    pub fn write_batch(&mut self, batch: &[TypedArrayView<'_>]) -> Result<()> {
+        if batch.len() != self.schema.len() {
+            return Err(self.invalid(format!(
+                "batch has {} columns, schema has {}",
+                batch.len(),
+                self.schema.len()
+            )));
+        }
+        let key = self.schema.get_by_index(self.key).unwrap();
+        if batch[self.key].dtype() != key.dtype {
+            return Err(MmappetError::TypeMismatch {
+                column: Some(key.name.clone()),
+                expected: key.dtype,
+                actual: batch[self.key].dtype(),
+            });
+        }
+        let values = key_values(&batch[self.key])
+            .ok_or_else(|| self.invalid(format!("key '{}' exceeds the range of i64", key.name)))?;
+        let mut groups: BTreeMap<i64, Vec<usize>> = BTreeMap::new();
+        for (row, value) in values.into_iter().enumerate() {
+            groups.entry(value).or_default().push(row);
+        }
+
+        for (value, rows) in groups {
+            let created = !self.writers.contains_key(&value);
+            if created {
+                let path = ConcatDataset::hive_path(&self.dir, &key.name, value);
+                self.writers.insert(value, DatasetWriter::create(path, &self.schema)?);
+            }
+            let result = self.writers.get_mut(&value).unwrap().write_rows(batch, &rows);
+            // All partitions check the batch alike, so only the first can
+            // fail on it; don't leave its partition behind empty
+            if result.is_err() && created {
+                self.writers.remove(&value);
+                let path = ConcatDataset::hive_path(&self.dir, &key.name, value);
+                fs::remove_dir_all(&path).map_err(|e| MmappetError::io(&path, e))?;
+            }
+            result?;
+        }
+        Ok(())
+    }
+
+    /// Key values written so far, in ascending order.
+    pub fn keys(&self) -> impl Iterator<Item = i64> + '_ {
+        self.writers.keys().copied()
+    }
+
+    /// Finish every partition as [`DatasetWriter::finish`] does.
+    ///
+    /// Returns the total number of rows written.
+    pub fn finish(self) -> Result<usize> {
+        let mut rows = 0;
+        for writer in self.writers.into_values() {
+            rows += writer.finish()?;
+        }
+        Ok(rows)
+    }
+
+    fn invalid(&self, message: String) -> MmappetError {
+        MmappetError::InvalidBatch {
+            path: Some(self.dir.clone()),
+            message,
+        }
+    }
+}
+
+impl ConcatDataset {
+    /// Open all partitions `{key}={value}` in the directory `path`, ordered
+    /// by value; other entries are ignored.
+    ///
+    /// Returns an error if there are no partitions, they differ in key, or
+    /// [`ConcatDataset::open`] fails on them.
+    pub fn open_hive<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_hive_where(path, &Expr::from(true))
+    }
+
+    /// Open the partitions in `path` whose key value can match `filter`, as
+    /// [`ConcatDataset::open_hive`] does.
+    ///
+    /// Partitions are skipped by their directory name alone, e.g. all but
+    /// `run_id=3` for `col("run_id").eq(3)`; the rows of the opened ones
+    /// still need filtering unless `filter` only compares the key.
+    pub fn open_hive_where<P: AsRef<Path>>(path: P, filter: &Expr) -> Result<Self> {
+        let path = path.as_ref();
+        let mut key: Option<String> = None;
+        let mut partitions = Vec::new();
+        for entry in fs::read_dir(path).map_err(|e| MmappetError::io(path, e))? {
+            let entry = entry.map_err(|e| MmappetError::io(path, e))?;
+            let name = entry.file_name();
+            let Some((name, value)) = name.to_str().and_then(|n| n.split_once('=')) else {
+                continue;
+            };
+            let Ok(value) = value.parse::<i64>() else {
+                continue;
+            };
+            if !entry.path().is_dir() {
+                continue;
+            }
+            match &key {
+                Some(key) if key != name => {
+                    return Err(MmappetError::InvalidConcat {
+                        path: Some(path.to_path_buf()),
+                        message: format!("partitions by both '{}' and '{}'", key, name),
+                    });
+                }
+                Some(_) => {}
+                None => key = Some(name.to_string()),
+            }
+            partitions.push((value, entry.path()));
+        }
+        let Some(key) = key else {
+            return Err(MmappetError::InvalidConcat {
+                path: Some(path.to_path_buf()),
+                message: "no key=value directories".to_string(),
+            });
+        };
+        partitions.sort();
+
+        let schema = Schema::from_path(&partitions[0].1)?;
+        let parts = partitions
+            .iter()
+            .filter(|(value, _)| !filter.never_matches_value(&key, *value as f64))
+            .map(|(_, path)| Dataset::open(path))
+            .collect::<Result<Vec<_>>>()?;
+        if parts.is_empty() {
+            return Ok(Self::new(schema, parts));
+        }
+        Self::from_parts(parts)
+    }
+
+    /// Path of the partition with `key` equal to `value` in `path`, e.g.
+    /// `path/run_id=3`.
+    pub fn hive_path<P: AsRef<Path>>(path: P, key: &str, value: i64) -> PathBuf {
+        path.as_ref().join(format!("{}={}", key, value))
+    }
+}
+
+/// Values of an integer array as `i64`, `None` if one doesn't fit.
+fn key_values(array: &TypedArrayView<'_>) -> Option<Vec<i64>> {
+    fn widen<T: Copy + TryInto<i64>>(values: &ArrayView1<'_, T>) -> Option<Vec<i64>> {
+        values.iter().map(|&v| v.try_into().ok()).collect()
+    }
+
+    match array {
+        TypedArrayView::UInt8(a) => widen(a),
+        TypedArrayView::Int8(a) => widen(a),
+        TypedArrayView::UInt16(a) => widen(a),
+        TypedArrayView::Int16(a) => widen(a),
+        TypedArrayView::UInt32(a) => widen(a),
+        TypedArrayView::Int32(a) => widen(a),
+        TypedArrayView::UInt64(a) => widen(a),
+        TypedArrayView::Int64(a)
+        | TypedArrayView::TimestampMillis(a)
+        | TypedArrayView::TimestampMicros(a) => widen(a),
+        _ => unreachable!("not an integer array"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dtype::DType;
+    use crate::expr::{col, lit};
+
+    #[test]
+    fn test_hive_partitions() {
+        let dir = std::env::temp_dir().join(format!("mmappet-hive-{}", std::process::id()));
+        let schema = Schema::new(&[("run_id", DType::Int32), ("tof", DType::UInt32)]).unwrap();
+        assert!(PartitionWriter::create(&dir, &schema, "tof").is_ok());
+        let float = Schema::new(&[("mz", DType::Float64)]).unwrap();
+        assert!(PartitionWriter::create(&dir, &float, "mz").is_err());
+
+        let mut writer = PartitionWriter::create(&dir, &schema, "run_id").unwrap();
+        let batch = |runs: &'static [i32], tofs: &'static [u32]| {
+            [
+                TypedArrayView::Int32(ArrayView1::from(runs)),
+                TypedArrayView::UInt32(ArrayView1::from(tofs)),
+            ]
+        };
+        writer.write_batch(&batch(&[3, -1, 3], &[10, 20, 30])).unwrap();
+        writer.write_batch(&batch(&[12, 3], &[40, 50])).unwrap();
+        assert!(writer.write_batch(&batch(&[7], &[60, 70])).is_err());
+        assert_eq!(writer.keys().collect::<Vec<_>>(), vec![-1, 3, 12]);
+        assert_eq!(writer.finish().unwrap(), 5);
+        assert!(!dir.join("run_id=7").exists());
+
+        let all = ConcatDataset::open_hive(&dir).unwrap();
+        assert_eq!(all.to_vec::<u32>("tof").unwrap(), vec![20, 10, 30, 50, 40]);
+        let run = ConcatDataset::open_hive_where(&dir, &col("run_id").eq(3)).unwrap();
+        assert_eq!(run.num_parts(), 1);
+        assert_eq!(run.to_vec::<u32>("tof").unwrap(), vec![10, 30, 50]);
+        let later = ConcatDataset::open_hive_where(&dir, &lit(0).lt(col("run_id"))).unwrap();
+        assert_eq!(later.num_parts(), 2);
+        let none = ConcatDataset::open_hive_where(&dir, &col("run_id").eq(4)).unwrap();
+        assert!(none.is_empty());
+        assert_eq!(none.schema().len(), 2);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
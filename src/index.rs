@@ -0,0 +1,213 @@
+//! Persistent secondary indexes for equality lookups.
+//!
+//! A `<column>.idx` file holds the column's values in sorted order next to
+//! the row each value came from, so a lookup is a binary search instead of a
+//! scan. Layout, all integers native-endian `u64`: magic, row count, entry
+//! count, element size, the sorted values (zero-padded to a multiple of 8
+//! bytes), then one row id per entry.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use bytemuck::{bytes_of, cast_slice};
+
+use crate::dataset::Dataset;
+use crate::dtype::{DType, MmappetType};
+use crate::error::{MmappetError, Result};
+use crate::filter::FilteredView;
+use crate::storage::Storage;
+
+/// Magic bytes at the start of a `<column>.idx` file.
+const VALUE_INDEX_MAGIC: &[u8; 8] = b"MMPVIDX1";
+
+/// Size of the header: magic, row count, entry count, element size.
+const HEADER_LEN: usize = 32;
+
+impl Dataset {
+    /// Build the secondary index for a column and persist it as `<name>.idx`.
+    ///
+    /// Later calls to [`Dataset::lookup`], also from other processes, use it
+    /// instead of scanning the column. String, categorical and custom dtype
+    /// columns can't be indexed.
+    pub fn build_index(&self, name: &str) -> Result<()> {
+        let column = self
+            .column(name)
+            .ok_or_else(|| self.column_not_found(name))?;
+        column.check_scalar(name)?;
+        match column.dtype() {
+            DType::Custom(dtype) => {
+                return Err(MmappetError::InvalidDType {
+                    dtype: dtype.to_string(),
+                    message: "custom dtypes can't be indexed".to_string(),
+                });
+            }
+            DType::Utf8 | DType::Categorical => {
+                return Err(MmappetError::InvalidDType {
+                    dtype: column.dtype().to_string(),
+                    message: "only numeric columns can be indexed".to_string(),
+                });
+            }
+            _ => {}
+        }
+
+        let row_ids = self.argsort(name)?;
+        let indices: Vec<usize> = row_ids.iter().map(|&row| row as usize).collect();
+        let mut buf = Vec::with_capacity(HEADER_LEN + row_ids.len() * 16);
+        buf.extend_from_slice(VALUE_INDEX_MAGIC);
+        for word in [self.len(), row_ids.len(), column.dtype().size_bytes()] {
+            buf.extend_from_slice(&(word as u64).to_ne_bytes());
+        }
+        column.gather_bytes(&indices, &mut buf);
+        buf.resize(buf.len().next_multiple_of(8), 0);
+        buf.extend_from_slice(cast_slice(&row_ids));
+
+        let path = self.path().join(format!("{}.idx", name));
+        fs::write(&path, buf).map_err(|e| MmappetError::io(&path, e))?;
+        self.cache_value_index(name, ValueIndex::open(&path)?.map(Arc::new));
+        Ok(())
+    }
+
+    /// Select the rows where column `name` equals `value`, in row order.
+    ///
+    /// Uses the persisted `<name>.idx` index when present and up to date,
+    /// and scans the column otherwise. Values ruled out by the column's Bloom
+    /// filter return at once. Returns an error if the column doesn't exist or
+    /// the type doesn't match.
+    pub fn lookup<T: MmappetType + PartialOrd>(
+        &self,
+        name: &str,
+        value: T,
+    ) -> Result<FilteredView<'_>> {
+        let data = self.get::<T>(name)?;
+        if let Some(bloom) = self.bloom_filter(name)? {
+            if !bloom.contains(bytes_of(&value)) {
+                return Ok(FilteredView::new(self, Vec::new()));
+            }
+        }
+
+        let index = match self.cached_value_index(name) {
+            Some(index) => index,
+            None => {
+                let path = self.path().join(format!("{}.idx", name));
+                let index = ValueIndex::open(&path)?
+                    .filter(|index| index.rows == self.len())
+                    .map(Arc::new);
+                self.cache_value_index(name, index.clone());
+                index
+            }
+        };
+
+        let rows = match index {
+            Some(index) => {
+                let values: &[T] =
+                    cast_slice(&index.values()[..index.count * T::DTYPE.size_bytes()]);
+                let start = values.partition_point(|x| *x < value);
+                let end = values.partition_point(|x| *x <= value);
+                index.row_ids()[start..end]
+                    .iter()
+                    .map(|&row| row as usize)
+                    .collect()
+            }
+            None => data
+                .iter()
+                .enumerate()
+                .filter(|(_, x)| **x == value)
+                .map(|(row, _)| row)
+                .collect(),
+        };
+        Ok(FilteredView::new(self, rows))
+    }
+}
+
+/// A memory-mapped `<column>.idx` file.
+pub(crate) struct ValueIndex {
+    /// Row count of the dataset the index was built for.
+    rows: usize,
+    count: usize,
+    /// Byte length of the padded values block.
+    values_len: usize,
+    mmap: Storage,
+}
+
+impl ValueIndex {
+    /// Map an index written by [`Dataset::build_index`]; `Ok(None)` if absent.
+    fn open(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let mmap = Storage::open(path, false)?;
+        let invalid = |offset: Option<u64>| MmappetError::InvalidIndex {
+            path: path.to_path_buf(),
+            offset,
+        };
+
+        if mmap.get(..8) != Some(&VALUE_INDEX_MAGIC[..]) || mmap.len() < HEADER_LEN {
+            return Err(invalid(Some(0)));
+        }
+        let word =
+            |i: usize| u64::from_ne_bytes(mmap[8 + i * 8..16 + i * 8].try_into().unwrap()) as usize;
+        let (rows, count, size) = (word(0), word(1), word(2));
+        let values_len = count
+            .checked_mul(size)
+            .map(|len| len.next_multiple_of(8))
+            .ok_or_else(|| invalid(Some(16)))?;
+        if Some(mmap.len())
+            != count
+                .checked_mul(8)
+                .map(|ids| HEADER_LEN + values_len + ids)
+        {
+            return Err(invalid(None));
+        }
+
+        Ok(Some(ValueIndex {
+            rows,
+            count,
+            values_len,
+            mmap,
+        }))
+    }
+
+    /// The sorted values, including padding.
+    fn values(&self) -> &[u8] {
+        &self.mmap[HEADER_LEN..HEADER_LEN + self.values_len]
+    }
+
+    /// Row of each sorted value.
+    fn row_ids(&self) -> &[u64] {
+        cast_slice(&self.mmap[HEADER_LEN + self.values_len..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::write_dataset;
+
+    #[test]
+    fn test_index_lookup() {
+        let dir = write_dataset(
+            "index",
+            &[("id", &[30, 10, 20, 10]), ("tof", &[1, 2, 3, 4])],
+        );
+        let ds = Dataset::open(&dir).unwrap();
+        assert_eq!(ds.lookup("id", 10u32).unwrap().rows(), &[1, 3]);
+
+        ds.build_index("id").unwrap();
+        assert!(dir.join("id.idx").exists());
+        let ds = Dataset::open(&dir).unwrap();
+        let hits = ds.lookup("id", 10u32).unwrap();
+        assert_eq!(hits.rows(), &[1, 3]);
+        assert_eq!(hits.get::<u32>("tof").unwrap().to_vec(), vec![2, 4]);
+        assert!(ds.lookup("id", 15u32).unwrap().is_empty());
+        assert!(ds.lookup("id", 10.0f32).is_err());
+
+        fs::write(dir.join("id.idx"), b"garbage").unwrap();
+        assert!(matches!(
+            Dataset::open(&dir).unwrap().lookup("id", 10u32),
+            Err(MmappetError::InvalidIndex { .. })
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,524 @@
+//! Streaming ingestion from a message bus (feature `ingest`).
+//!
+//! An [`Ingestor`] decodes messages into rows, buffers them and appends
+//! them to a dataset in batches with [`Dataset::append_batch`], so every
+//! batch is committed atomically and survives a crash of the process. A
+//! [`Source`] hands out messages and is told to acknowledge them once their
+//! rows are committed: [`KafkaSource`] (feature `kafka`) commits consumer
+//! offsets and [`NatsSource`] (feature `nats`) acks JetStream messages.
+//! Delivery is at least once: messages whose rows were committed just
+//! before a crash are delivered again.
+//!
+//! ```rust,no_run
+//! use std::sync::atomic::AtomicBool;
+//! use mmappet::{Dataset, IngestOptions, Ingestor, JsonDecoder, KafkaSource};
+//!
+//! let ds = Dataset::open("telemetry.mmappet")?;
+//! let mut ingestor = Ingestor::new(ds, JsonDecoder, IngestOptions::default())?;
+//! let mut source = KafkaSource::new("localhost:9092", "mmappet", &["telemetry"])?;
+//! ingestor.run(&mut source, &AtomicBool::new(false))?;
+//! # Ok::<(), mmappet::MmappetError>(())
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::dataset::Dataset;
+use crate::dtype::DType;
+use crate::error::{MmappetError, Result};
+use crate::jsonl::JsonBatch;
+
+/// A decoded row: values by column name, as in [`Dataset::from_jsonl`].
+pub type Record = serde_json::Map<String, serde_json::Value>;
+
+/// Turns the payload of a message into rows.
+///
+/// Implemented by [`JsonDecoder`] and by closures, e.g. for a binary format.
+pub trait Decoder {
+    /// The rows of `payload`, or why it can't be decoded.
+    fn decode(&mut self, payload: &[u8]) -> std::result::Result<Vec<Record>, String>;
+}
+
+impl<F> Decoder for F
+where
+    F: FnMut(&[u8]) -> std::result::Result<Vec<Record>, String>,
+{
+    fn decode(&mut self, payload: &[u8]) -> std::result::Result<Vec<Record>, String> {
+        self(payload)
+    }
+}
+
+/// Decodes messages holding a JSON object, or several separated by
+/// whitespace such as JSON lines.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonDecoder;
+
+impl Decoder for JsonDecoder {
+    fn decode(&mut self, payload: &[u8]) -> std::result::Result<Vec<Record>, String> {
+        serde_json::Deserializer::from_slice(payload)
+            .into_iter::<serde_json::Value>()
+            .map(|value| match value {
+                Ok(serde_json::Value::Object(record)) => Ok(record),
+                Ok(_) => Err("not a JSON object".to_string()),
+                Err(e) => Err(e.to_string()),
+            })
+            .collect()
+    }
+}
+
+/// Where an [`Ingestor`] gets its messages from.
+pub trait Source {
+    /// Wait up to `timeout` for the next message and return its payload, or
+    /// `None` if none arrived.
+    fn receive(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>>;
+
+    /// Acknowledge all messages received so far, whose rows are committed.
+    fn commit(&mut self) -> Result<()>;
+}
+
+/// Options of an [`Ingestor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IngestOptions {
+    /// Rows after which a batch is committed.
+    pub batch_rows: usize,
+    /// Longest time a received message waits to be committed.
+    pub batch_interval: Duration,
+}
+
+impl Default for IngestOptions {
+    fn default() -> Self {
+        IngestOptions {
+            batch_rows: 1 << 16,
+            batch_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Appends rows decoded from messages to a dataset in batches, see the
+/// [module docs](self).
+pub struct Ingestor<D> {
+    dataset: Dataset,
+    decoder: D,
+    options: IngestOptions,
+    batch: JsonBatch,
+    rejected: u64,
+    last_rejection: Option<String>,
+}
+
+impl<D: Decoder> Ingestor<D> {
+    /// Ingest into `dataset` the rows `decoder` finds in messages.
+    ///
+    /// Returns an error if the schema has custom dtypes, which can't be
+    /// read from JSON.
+    pub fn new(dataset: Dataset, decoder: D, options: IngestOptions) -> Result<Self> {
+        if let Some(def) = dataset
+            .schema()
+            .columns()
+            .find(|d| matches!(d.dtype, DType::Custom(_)))
+        {
+            return Err(MmappetError::InvalidDType {
+                dtype: def.type_name(),
+                message: format!("column '{}' can't be read from JSON", def.name),
+            });
+        }
+        let batch = JsonBatch::new(dataset.schema());
+        Ok(Ingestor {
+            dataset,
+            decoder,
+            options,
+            batch,
+            rejected: 0,
+            last_rejection: None,
+        })
+    }
+
+    /// The dataset, with the rows committed so far.
+    pub fn dataset(&self) -> &Dataset {
+        &self.dataset
+    }
+
+    /// Stop ingesting and return the dataset; rows not committed are lost.
+    pub fn into_dataset(self) -> Dataset {
+        self.dataset
+    }
+
+    /// Rows decoded but not committed yet.
+    pub fn pending_rows(&self) -> usize {
+        self.batch.rows()
+    }
+
+    /// Number of messages and rows rejected so far.
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    /// Why the last message or row was rejected.
+    pub fn last_rejection(&self) -> Option<&str> {
+        self.last_rejection.as_deref()
+    }
+
+    /// Decode `payload` and add its rows to the batch. Returns the number of
+    /// rows added.
+    ///
+    /// A message that can't be decoded, or a row with a value that doesn't
+    /// fit its column, is rejected and counted rather than failing: sending
+    /// it again wouldn't help. Every column of the schema needs a value,
+    /// since appended rows can't be null.
+    pub fn push(&mut self, payload: &[u8]) -> usize {
+        let records = match self.decoder.decode(payload) {
+            Ok(records) => records,
+            Err(message) => {
+                self.reject(message);
+                return 0;
+            }
+        };
+        let mut added = 0;
+        for record in &records {
+            let missing = self
+                .dataset
+                .schema()
+                .columns()
+                .find(|def| record.get(&def.name).is_none_or(serde_json::Value::is_null));
+            let pushed = match missing {
+                Some(def) => Err(format!("column '{}' has no value", def.name)),
+                None => self.batch.push(record),
+            };
+            match pushed {
+                Ok(()) => added += 1,
+                Err(message) => self.reject(message),
+            }
+        }
+        added
+    }
+
+    /// Commit the pending rows as one batch, see [`Dataset::append_batch`].
+    /// Returns the new row count.
+    ///
+    /// If appending fails, the rows stay pending and nothing is written.
+    pub fn flush(&mut self) -> Result<usize> {
+        self.batch.append(&mut self.dataset)
+    }
+
+    /// Ingest messages from `source` until `stop` is set.
+    ///
+    /// A batch is committed, and its messages acknowledged, once it holds
+    /// [`IngestOptions::batch_rows`] rows or its first message is
+    /// [`IngestOptions::batch_interval`] old, and when stopping. Returns the
+    /// first error of the source or of appending; messages of the batch
+    /// that failed aren't acknowledged, so they're delivered again.
+    pub fn run<S: Source>(&mut self, source: &mut S, stop: &AtomicBool) -> Result<()> {
+        let interval = self.options.batch_interval;
+        // Time the first message not yet acknowledged was received
+        let mut oldest: Option<Instant> = None;
+        while !stop.load(Ordering::Relaxed) {
+            let wait = oldest.map_or(interval, |t| interval.saturating_sub(t.elapsed()));
+            if let Some(payload) = source.receive(wait)? {
+                oldest.get_or_insert_with(Instant::now);
+                self.push(&payload);
+            }
+            let due = self.batch.rows() >= self.options.batch_rows
+                || oldest.is_some_and(|t| t.elapsed() >= interval);
+            if due {
+                self.flush()?;
+                source.commit()?;
+                oldest = None;
+            }
+        }
+        if oldest.is_some() {
+            self.flush()?;
+            source.commit()?;
+        }
+        Ok(())
+    }
+
+    fn reject(&mut self, message: String) {
+        self.rejected += 1;
+        self.last_rejection = Some(message);
+    }
+}
+
+/// Messages of Kafka topics, read by a consumer group whose offsets are
+/// committed with the rows (feature `kafka`).
+#[cfg(feature = "kafka")]
+pub struct KafkaSource {
+    consumer: rdkafka::consumer::BaseConsumer,
+    brokers: String,
+    /// Whether messages were received since the last commit.
+    received: bool,
+}
+
+#[cfg(feature = "kafka")]
+impl KafkaSource {
+    /// Consume `topics` from the brokers `brokers` (`host:port`, separated
+    /// by commas) as the consumer group `group`, starting at the earliest
+    /// message when the group has no committed offsets.
+    pub fn new(brokers: &str, group: &str, topics: &[&str]) -> Result<Self> {
+        let mut config = rdkafka::ClientConfig::new();
+        config
+            .set("bootstrap.servers", brokers)
+            .set("group.id", group)
+            .set("auto.offset.reset", "earliest");
+        Self::with_config(&config, topics)
+    }
+
+    /// Consume `topics` with a consumer configured by `config`, e.g. for
+    /// authentication. Automatic offset commits are turned off.
+    pub fn with_config(config: &rdkafka::ClientConfig, topics: &[&str]) -> Result<Self> {
+        use rdkafka::consumer::Consumer;
+
+        let brokers = config
+            .get("bootstrap.servers")
+            .unwrap_or_default()
+            .to_string();
+        let error = |e: rdkafka::error::KafkaError| MmappetError::MessageBus {
+            bus: format!("kafka://{}", brokers),
+            message: e.to_string(),
+        };
+        let mut config = config.clone();
+        config.set("enable.auto.commit", "false");
+        let consumer: rdkafka::consumer::BaseConsumer = config.create().map_err(error)?;
+        consumer.subscribe(topics).map_err(error)?;
+        Ok(KafkaSource {
+            consumer,
+            brokers,
+            received: false,
+        })
+    }
+
+    fn error(&self, e: rdkafka::error::KafkaError) -> MmappetError {
+        MmappetError::MessageBus {
+            bus: format!("kafka://{}", self.brokers),
+            message: e.to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "kafka")]
+impl Source for KafkaSource {
+    fn receive(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>> {
+        use rdkafka::Message;
+
+        match self.consumer.poll(timeout) {
+            None => Ok(None),
+            Some(Ok(message)) => {
+                self.received = true;
+                Ok(Some(message.payload().unwrap_or_default().to_vec()))
+            }
+            Some(Err(e)) => Err(self.error(e)),
+        }
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        use rdkafka::consumer::{CommitMode, Consumer};
+
+        if self.received {
+            // The consumer's position is past every message received
+            self.consumer
+                .commit_consumer_state(CommitMode::Sync)
+                .map_err(|e| self.error(e))?;
+            self.received = false;
+        }
+        Ok(())
+    }
+}
+
+/// Messages of a NATS JetStream consumer, acknowledged once their rows are
+/// committed (feature `nats`).
+///
+/// The durable pull consumer must exist, with explicit acknowledgement and
+/// an `ack_wait` longer than [`IngestOptions::batch_interval`].
+#[cfg(feature = "nats")]
+pub struct NatsSource {
+    url: String,
+    runtime: tokio::runtime::Runtime,
+    messages: async_nats::jetstream::consumer::pull::Stream,
+    /// Messages received since the last commit.
+    pending: Vec<async_nats::jetstream::Message>,
+}
+
+#[cfg(feature = "nats")]
+impl NatsSource {
+    /// Connect to the server at `url`, e.g. `nats://localhost:4222`, and
+    /// read from the consumer `consumer` of the stream `stream`.
+    pub fn connect(url: &str, stream: &str, consumer: &str) -> Result<Self> {
+        use async_nats::jetstream::consumer::pull;
+
+        let error = |message: String| MmappetError::MessageBus {
+            bus: url.to_string(),
+            message,
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| error(e.to_string()))?;
+        let messages = runtime.block_on(async {
+            let client = async_nats::connect(url)
+                .await
+                .map_err(|e| error(e.to_string()))?;
+            let stream = async_nats::jetstream::new(client)
+                .get_stream(stream)
+                .await
+                .map_err(|e| error(e.to_string()))?;
+            let consumer = stream
+                .get_consumer::<pull::Config>(consumer)
+                .await
+                .map_err(|e| error(e.to_string()))?;
+            consumer.messages().await.map_err(|e| error(e.to_string()))
+        })?;
+        Ok(NatsSource {
+            url: url.to_string(),
+            runtime,
+            messages,
+            pending: Vec::new(),
+        })
+    }
+
+    fn error(&self, message: String) -> MmappetError {
+        MmappetError::MessageBus {
+            bus: self.url.clone(),
+            message,
+        }
+    }
+}
+
+#[cfg(feature = "nats")]
+impl Source for NatsSource {
+    fn receive(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>> {
+        use futures_core::Stream;
+        use std::pin::Pin;
+
+        let messages = &mut self.messages;
+        let next = std::future::poll_fn(|cx| Pin::new(&mut *messages).poll_next(cx));
+        match self
+            .runtime
+            .block_on(async { tokio::time::timeout(timeout, next).await })
+        {
+            Err(_) => Ok(None),
+            Ok(None) => Err(self.error("consumer stopped".to_string())),
+            Ok(Some(Err(e))) => Err(self.error(e.to_string())),
+            Ok(Some(Ok(message))) => {
+                let payload = message.payload.to_vec();
+                self.pending.push(message);
+                Ok(Some(payload))
+            }
+        }
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        for message in std::mem::take(&mut self.pending) {
+            self.runtime
+                .block_on(message.ack())
+                .map_err(|e| self.error(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::Schema;
+    use crate::writer::DatasetWriter;
+    use std::collections::VecDeque;
+
+    /// Messages from memory, stopping the ingestor when they run out.
+    struct Queue<'a> {
+        messages: VecDeque<&'static str>,
+        committed: Vec<usize>,
+        received: usize,
+        stop: &'a AtomicBool,
+    }
+
+    impl Source for Queue<'_> {
+        fn receive(&mut self, _timeout: Duration) -> Result<Option<Vec<u8>>> {
+            let message = self.messages.pop_front();
+            self.received += message.is_some() as usize;
+            if self.messages.is_empty() {
+                self.stop.store(true, Ordering::Relaxed);
+            }
+            Ok(message.map(|m| m.as_bytes().to_vec()))
+        }
+
+        fn commit(&mut self) -> Result<()> {
+            self.committed.push(self.received);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_ingest_batches() {
+        let dir = std::env::temp_dir().join(format!("mmappet-ingest-{}", std::process::id()));
+        let mut schema = Schema::new(&[
+            ("id", DType::UInt32),
+            ("xy", DType::Float32),
+            ("host", DType::Utf8),
+            ("level", DType::Categorical),
+        ])
+        .unwrap();
+        schema.set_shape("xy", &[2]).unwrap();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write("id", &[0u32]).unwrap();
+        writer.write("xy", &[0.0f32, 0.0]).unwrap();
+        writer.write_str("host", &["a"]).unwrap();
+        writer.write_categorical("level", &["info"]).unwrap();
+        writer.finish().unwrap();
+
+        let ds = Dataset::open(&dir).unwrap();
+        let options = IngestOptions {
+            batch_rows: 2,
+            batch_interval: Duration::from_secs(60),
+        };
+        let mut ingestor = Ingestor::new(ds, JsonDecoder, options).unwrap();
+        let stop = AtomicBool::new(false);
+        let mut queue = Queue {
+            messages: VecDeque::from([
+                r#"{"id":1,"xy":[1,2],"host":"b","level":"warn"}"#,
+                "not json",
+                r#"{"id":2,"xy":[3,4],"host":"c","level":"info"}
+                   {"id":3,"xy":[5],"host":"d","level":"info"}"#,
+                r#"{"id":4,"xy":[7,8],"host":"e"}"#,
+                r#"{"id":5,"xy":[9,10],"host":"f","level":"error"}"#,
+            ]),
+            committed: Vec::new(),
+            received: 0,
+            stop: &stop,
+        };
+        ingestor.run(&mut queue, &stop).unwrap();
+
+        // Batches of two rows, and the rest when stopping
+        assert_eq!(queue.committed, [3, 5]);
+        assert_eq!(ingestor.pending_rows(), 0);
+        assert_eq!(ingestor.rejected(), 3);
+        assert_eq!(
+            ingestor.last_rejection(),
+            Some("column 'level' has no value")
+        );
+        let ds = ingestor.into_dataset();
+        assert_eq!(ds.get::<u32>("id").unwrap(), [0, 1, 2, 5]);
+        assert_eq!(
+            ds.get::<f32>("xy").unwrap(),
+            [0.0, 0.0, 1.0, 2.0, 3.0, 4.0, 9.0, 10.0]
+        );
+        assert_eq!(
+            ds["host"].iter_str().unwrap().collect::<Vec<_>>(),
+            ["a", "b", "c", "f"]
+        );
+        let level = ds.categorical("level").unwrap();
+        assert_eq!(
+            level.iter().collect::<Vec<_>>(),
+            ["info", "warn", "info", "error"]
+        );
+
+        // Rows that fail to append stay pending
+        let mut ingestor = Ingestor::new(ds, JsonDecoder, options).unwrap();
+        assert_eq!(
+            ingestor.push(br#"{"id":6,"xy":[0,0],"host":"g","level":"info"}"#),
+            1
+        );
+        std::fs::remove_file(dir.join("0.bin")).unwrap();
+        assert!(ingestor.flush().is_err());
+        assert_eq!(ingestor.pending_rows(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,230 @@
+//! Arrow IPC files (Feather version 2), on the `arrow` crate.
+//!
+//! [`Dataset::to_arrow_ipc`] writes the dataset in record batches of
+//! `IPC_BATCH_ROWS` rows, exported without copying as by
+//! [`DatasetView::to_record_batch`](crate::DatasetView::to_record_batch), so
+//! only one batch of a column is read at a time; [`Dataset::export_arrow_ipc`]
+//! does the same for a range of rows into any writer. The labels of
+//! categorical columns are written once, as dictionaries before the first
+//! batch. [`Dataset::from_arrow_ipc`] reads a file batch by batch, also
+//! with LZ4- or zstd-compressed bodies.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::ops::{Range, RangeBounds};
+use std::path::Path;
+
+use ::arrow::error::ArrowError;
+use ::arrow::ipc::reader::FileReader;
+use ::arrow::ipc::writer::FileWriter;
+
+use crate::arrow::{arrow_error, arrow_schema, record_batch};
+use crate::column::checked_range;
+use crate::dataset::Dataset;
+use crate::error::{MmappetError, Result};
+use crate::schema::{ColumnDef, Schema};
+use crate::writer::{create_atomically, DatasetWriter};
+
+/// Rows per record batch written by [`Dataset::to_arrow_ipc`].
+const IPC_BATCH_ROWS: usize = 1 << 20;
+
+/// Error of writing an IPC file to `path`, or to a writer without a path.
+fn write_error(path: Option<&Path>, e: ArrowError) -> MmappetError {
+    match (path, e) {
+        (Some(path), ArrowError::IoError(_, e)) => MmappetError::io(path, e),
+        (None, ArrowError::IoError(_, e)) => e.into(),
+        (_, e) => arrow_error(e),
+    }
+}
+
+impl Dataset {
+    /// Write the dataset to the Arrow IPC file (Feather version 2) `path`,
+    /// in record batches of about a million rows.
+    ///
+    /// Batches share the mapped files, so datasets larger than memory can be
+    /// exported. See [`DatasetView::to_record_batch`](crate::DatasetView::to_record_batch)
+    /// for how types are mapped.
+    pub fn to_arrow_ipc<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.write_arrow_ipc(path.as_ref(), IPC_BATCH_ROWS)
+    }
+
+    fn write_arrow_ipc(&self, path: &Path, batch_rows: usize) -> Result<()> {
+        let file = File::create(path).map_err(|e| MmappetError::io(path, e))?;
+        let mut file = BufWriter::new(file);
+        let columns: Vec<&ColumnDef> = self.schema().columns().collect();
+        self.write_ipc(&mut file, Some(path), &columns, 0..self.len(), batch_rows)?;
+        file.flush().map_err(|e| MmappetError::io(path, e))
+    }
+
+    /// Write `rows` of `columns` (all if empty) to `writer` as an Arrow IPC
+    /// file, as [`Dataset::to_arrow_ipc`] does to a file. Only the given
+    /// columns are mapped.
+    ///
+    /// The output is written in many small pieces, so wrap unbuffered
+    /// writers such as sockets in a [`BufWriter`].
+    ///
+    /// Returns an error if a column doesn't exist.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rows` is out of bounds.
+    pub fn export_arrow_ipc<W: Write>(
+        &self,
+        writer: W,
+        columns: &[&str],
+        rows: impl RangeBounds<usize>,
+    ) -> Result<()> {
+        let rows = checked_range(rows, self.len());
+        let columns = if columns.is_empty() {
+            self.schema().columns().collect()
+        } else {
+            let def = |name: &&str| {
+                self.schema()
+                    .get(name)
+                    .ok_or_else(|| self.column_not_found(name))
+            };
+            columns.iter().map(def).collect::<Result<Vec<_>>>()?
+        };
+        self.write_ipc(writer, None, &columns, rows, IPC_BATCH_ROWS)
+    }
+
+    fn write_ipc<W: Write>(
+        &self,
+        writer: W,
+        path: Option<&Path>,
+        columns: &[&ColumnDef],
+        rows: Range<usize>,
+        batch_rows: usize,
+    ) -> Result<()> {
+        let schema = arrow_schema(self, columns)?;
+        let mut writer = FileWriter::try_new(writer, &schema).map_err(|e| write_error(path, e))?;
+        for start in rows.clone().step_by(batch_rows) {
+            let end = rows.end.min(start + batch_rows);
+            let batch = record_batch(self, columns, &schema, start..end)?;
+            writer.write(&batch).map_err(|e| write_error(path, e))?;
+        }
+        writer.finish().map_err(|e| write_error(path, e))
+    }
+
+    /// Create the dataset `dir` from the Arrow IPC file (Feather version 2)
+    /// `path`, reading it batch by batch, and open it.
+    ///
+    /// Returns an error naming all columns without a mmappet dtype, see
+    /// [`DatasetView::to_record_batch`](crate::DatasetView::to_record_batch),
+    /// or if the file is malformed.
+    pub fn from_arrow_ipc<P: AsRef<Path>, Q: AsRef<Path>>(path: P, dir: Q) -> Result<Dataset> {
+        let (path, dir) = (path.as_ref(), dir.as_ref());
+        let invalid = |e: ArrowError| MmappetError::InvalidEncoding {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        };
+        let file = File::open(path).map_err(|e| MmappetError::io(path, e))?;
+        let reader = FileReader::try_new(BufReader::new(file), None).map_err(invalid)?;
+        let schema = Schema::from_arrow(&reader.schema()).map_err(|e| e.with_path(path))?;
+        create_atomically(dir, |tmp_dir| {
+            let mut writer = DatasetWriter::create(tmp_dir, &schema)?;
+            for batch in reader {
+                writer.write_record_batch(&batch.map_err(invalid)?)?;
+            }
+            writer.finish()
+        })?;
+        Dataset::open(dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dtype::DType;
+
+    #[test]
+    fn test_arrow_ipc_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("mmappet-ipc-{}", std::process::id()));
+        let file = dir.with_extension("arrow");
+        let copy = dir.with_extension("copy");
+        let mut schema = Schema::new(&[
+            ("tof", DType::UInt32),
+            ("xy", DType::Float32),
+            ("peptide", DType::Utf8),
+            ("charge", DType::Categorical),
+            ("time", DType::TimestampMillis),
+        ])
+        .unwrap();
+        schema.set_shape("xy", &[2]).unwrap();
+        let rows = 30usize;
+        let tofs: Vec<Option<u32>> = (0..rows as u32)
+            .map(|i| (i % 4 != 1).then_some(i))
+            .collect();
+        let peptides: Vec<String> = (0..rows).map(|i| "PEPTIDE"[..i % 7].to_string()).collect();
+        let strs: Vec<&str> = peptides.iter().map(String::as_str).collect();
+        let charges: Vec<&str> = (0..rows).map(|i| ["2+", "3+", "4+"][i % 3]).collect();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write_opt("tof", &tofs).unwrap();
+        writer
+            .write("xy", &(0..2 * rows).map(|i| i as f32).collect::<Vec<_>>())
+            .unwrap();
+        writer.write_str("peptide", &strs).unwrap();
+        writer.write_categorical("charge", &charges).unwrap();
+        writer
+            .write_timestamps("time", &(0..rows as i64).collect::<Vec<_>>())
+            .unwrap();
+        writer.finish().unwrap();
+
+        let ds = Dataset::open(&dir).unwrap();
+        ds.write_arrow_ipc(&file, 8).unwrap();
+        let out = Dataset::from_arrow_ipc(&file, &copy).unwrap();
+        assert_eq!(out.len(), rows);
+        assert_eq!(out.schema().to_string(), ds.schema().to_string());
+        let tof = out.nullable("tof").unwrap();
+        assert_eq!(tof.iter_opt::<u32>().unwrap().collect::<Vec<_>>(), tofs);
+        assert_eq!(out["xy"].as_bytes(), ds["xy"].as_bytes());
+        assert_eq!(out["time"].as_bytes(), ds["time"].as_bytes());
+        assert_eq!(out["peptide"].iter_str().unwrap().collect::<Vec<_>>(), strs);
+        assert_eq!(
+            out["charge"]
+                .as_categorical()
+                .unwrap()
+                .iter()
+                .collect::<Vec<_>>(),
+            charges
+        );
+
+        // A range of rows into a writer
+        let mut bytes = Vec::new();
+        ds.export_arrow_ipc(&mut bytes, &[], 5..12).unwrap();
+        std::fs::write(&file, &bytes).unwrap();
+        std::fs::remove_dir_all(&copy).unwrap();
+        let out = Dataset::from_arrow_ipc(&file, &copy).unwrap();
+        assert_eq!(
+            out["peptide"].iter_str().unwrap().collect::<Vec<_>>(),
+            strs[5..12]
+        );
+
+        // Only some columns
+        let mut bytes = Vec::new();
+        ds.export_arrow_ipc(&mut bytes, &["time", "tof"], ..)
+            .unwrap();
+        std::fs::write(&file, &bytes).unwrap();
+        std::fs::remove_dir_all(&copy).unwrap();
+        let out = Dataset::from_arrow_ipc(&file, &copy).unwrap();
+        assert_eq!(out.column_names().collect::<Vec<_>>(), ["time", "tof"]);
+        assert_eq!(out["time"].as_bytes(), ds["time"].as_bytes());
+        assert!(matches!(
+            ds.export_arrow_ipc(Vec::new(), &["missing"], ..),
+            Err(MmappetError::ColumnNotFound { .. })
+        ));
+
+        let mut bytes = std::fs::read(&file).unwrap();
+        bytes.truncate(bytes.len() - 1);
+        std::fs::write(&file, &bytes).unwrap();
+        assert!(matches!(
+            Dataset::from_arrow_ipc(&file, dir.with_extension("bad")),
+            Err(MmappetError::InvalidEncoding { .. })
+        ));
+
+        std::fs::remove_file(file).unwrap();
+        for dir in [dir, copy] {
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
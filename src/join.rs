@@ -0,0 +1,196 @@
+//! Hash joins between two datasets on a key column.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::column::Column;
+use crate::dataset::Dataset;
+use crate::dtype::DType;
+use crate::error::{MmappetError, Result};
+use crate::schema::Schema;
+use crate::writer::DatasetWriter;
+
+/// Rows gathered per column while writing a join result.
+const GATHER_CHUNK_ROWS: usize = 1 << 16;
+
+/// Marks a left row without a match in [`join`] pairs.
+const NO_MATCH: usize = usize::MAX;
+
+/// Which rows [`join`] keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinType {
+    /// Only rows whose key occurs on both sides.
+    Inner,
+    /// Every left row; right columns of unmatched rows are NaN for floats and
+    /// zero otherwise.
+    Left,
+}
+
+/// Join `left` and `right` on the key column `on` and write the result to a
+/// new dataset in `dir`.
+///
+/// The result holds the key column, the other left columns and then the
+/// other right columns; right column names that clash with left ones get a
+/// `_right` suffix. Rows follow the left dataset's order, a left row matching
+/// several right rows being repeated once per match in right order. Keys are
+/// compared bitwise and must have the same dtype on both sides.
+///
+/// ```rust,no_run
+/// use mmappet::{join, Dataset, JoinType};
+///
+/// let peaks = Dataset::open("peaks.mmappet")?;
+/// let frames = Dataset::open("frames.mmappet")?;
+/// let joined = join(&peaks, &frames, "frame", JoinType::Inner, "joined.mmappet")?;
+/// # Ok::<(), mmappet::MmappetError>(())
+/// ```
+pub fn join<P: AsRef<Path>>(
+    left: &Dataset,
+    right: &Dataset,
+    on: &str,
+    how: JoinType,
+    dir: P,
+) -> Result<Dataset> {
+    let left_key = left.column(on).ok_or_else(|| left.column_not_found(on))?;
+    let right_key = right.column(on).ok_or_else(|| right.column_not_found(on))?;
+    if left_key.dtype() != right_key.dtype() {
+        return Err(MmappetError::TypeMismatch {
+            column: Some(on.to_string()),
+            expected: left_key.dtype(),
+            actual: right_key.dtype(),
+        });
+    }
+
+    let mut right_rows: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    for (row, key) in right_key.row_bytes().iter().enumerate() {
+        right_rows.entry(key).or_default().push(row);
+    }
+
+    let mut left_idx = Vec::new();
+    let mut right_idx = Vec::new();
+    for (row, key) in left_key.row_bytes().iter().enumerate() {
+        match right_rows.get(key) {
+            Some(matches) => {
+                left_idx.extend(std::iter::repeat_n(row, matches.len()));
+                right_idx.extend_from_slice(matches);
+            }
+            None if how == JoinType::Left => {
+                left_idx.push(row);
+                right_idx.push(NO_MATCH);
+            }
+            None => {}
+        }
+    }
+
+    let mut names: Vec<String> = Vec::new();
+    let mut sources: Vec<(&Column, &[usize])> = Vec::new();
+    for col in left.schema().columns() {
+        names.push(col.name.clone());
+        sources.push((left.column(&col.name).unwrap(), &left_idx));
+    }
+    for col in right.schema().columns().filter(|c| c.name != on) {
+        let name = if left.schema().get(&col.name).is_some() {
+            format!("{}_right", col.name)
+        } else {
+            col.name.clone()
+        };
+        names.push(name);
+        sources.push((right.column(&col.name).unwrap(), &right_idx));
+    }
+
+    let defs: Vec<(&str, DType)> = names
+        .iter()
+        .zip(&sources)
+        .map(|(name, (column, _))| (name.as_str(), column.dtype()))
+        .collect();
+    let mut schema = Schema::new(&defs)?;
+    for (name, (column, _)) in names.iter().zip(&sources) {
+        schema.set_shape(name, column.shape())?;
+    }
+    let mut writer = DatasetWriter::create(&dir, &schema)?;
+
+    let mut buf = Vec::new();
+    for (index, (column, rows)) in sources.iter().enumerate() {
+        // Missing strings and labels are empty
+        if let Some(strings) = column.as_str_array() {
+            let values = rows.iter().map(|&row| if row == NO_MATCH { "" } else { strings.get(row) });
+            writer.write_strs(index, values)?;
+            continue;
+        }
+        if let Some(labels) = column.as_categorical() {
+            let values = rows.iter().map(|&row| if row == NO_MATCH { "" } else { labels.value(row) });
+            writer.write_labels(index, values)?;
+            continue;
+        }
+        let fill = missing_value(column.dtype()).repeat(column.width());
+        for rows in rows.chunks(GATHER_CHUNK_ROWS) {
+            buf.clear();
+            for run in rows.chunk_by(|a, b| (*a == NO_MATCH) == (*b == NO_MATCH)) {
+                if run[0] == NO_MATCH {
+                    for _ in run {
+                        buf.extend_from_slice(&fill);
+                    }
+                } else {
+                    column.gather_bytes(run, &mut buf);
+                }
+            }
+            writer.write_bytes(index, &buf, rows.len())?;
+        }
+    }
+    writer.finish()?;
+
+    Dataset::open(dir)
+}
+
+/// Bytes of the value written for a missing row of `dtype`.
+fn missing_value(dtype: DType) -> Vec<u8> {
+    match dtype {
+        DType::Float32 => f32::NAN.to_ne_bytes().to_vec(),
+        DType::Float64 => f64::NAN.to_ne_bytes().to_vec(),
+        _ => vec![0; dtype.size_bytes()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::write_dataset;
+
+    #[test]
+    fn test_join() {
+        let peaks = write_dataset(
+            "join-peaks",
+            &[("frame", &[1, 2, 2, 5]), ("tof", &[10, 20, 21, 50])],
+        );
+        let frames = write_dataset(
+            "join-frames",
+            &[
+                ("frame", &[2, 1, 3]),
+                ("tof", &[7, 8, 9]),
+                ("ms", &[1, 2, 1]),
+            ],
+        );
+        let left = Dataset::open(&peaks).unwrap();
+        let right = Dataset::open(&frames).unwrap();
+        let out = peaks.with_extension("joined");
+
+        let inner = join(&left, &right, "frame", JoinType::Inner, &out).unwrap();
+        assert_eq!(
+            inner.column_names().collect::<Vec<_>>(),
+            ["frame", "tof", "tof_right", "ms"]
+        );
+        assert_eq!(inner.get::<u32>("frame").unwrap(), &[1, 2, 2]);
+        assert_eq!(inner.get::<u32>("tof_right").unwrap(), &[8, 7, 7]);
+        assert_eq!(inner.get::<u32>("ms").unwrap(), &[2, 1, 1]);
+        std::fs::remove_dir_all(&out).unwrap();
+
+        let outer = join(&left, &right, "frame", JoinType::Left, &out).unwrap();
+        assert_eq!(outer.get::<u32>("tof").unwrap(), &[10, 20, 21, 50]);
+        assert_eq!(outer.get::<u32>("ms").unwrap(), &[2, 1, 1, 0]);
+
+        assert!(join(&left, &right, "missing", JoinType::Inner, &out).is_err());
+
+        std::fs::remove_dir_all(peaks).unwrap();
+        std::fs::remove_dir_all(frames).unwrap();
+        std::fs::remove_dir_all(out).unwrap();
+    }
+}
@@ -0,0 +1,297 @@
+//! Write-ahead journal making appends crash-safe.
+//!
+//! While [`Dataset::append_batch`](crate::Dataset::append_batch) writes,
+//! `append.journal` holds the row count before the append, and the appending
+//! process holds a lock on it. A journal left over from a crash is rolled
+//! back, truncating the column files to that count, under the same lock: by
+//! the next append, or when the dataset is opened with
+//! [`Dataset::open_mut`](crate::Dataset::open_mut). Readers never touch it;
+//! they only see the rows of the `COMMIT` file, which a crashed append
+//! didn't change.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::commit::Commit;
+use crate::dataset::column_file;
+use crate::dtype::DType;
+use crate::error::{MmappetError, Result};
+use crate::nullable::validity_path;
+use crate::schema::Schema;
+use crate::strings::data_path;
+
+pub(crate) const JOURNAL_FILE: &str = "append.journal";
+
+/// Journal of an append in progress, holding the lock on the journal file.
+pub(crate) struct AppendJournal {
+    path: PathBuf,
+    file: File,
+}
+
+impl AppendJournal {
+    /// Record that the dataset in `dir` has `rows` committed rows, before
+    /// appending to it, after rolling back an append to it that didn't
+    /// finish, if any.
+    ///
+    /// Fails if the dataset no longer has `rows` rows once locked, since
+    /// another handle appended to it after the caller counted them.
+    pub(crate) fn begin(dir: &Path, schema: &Schema, rows: usize) -> Result<Self> {
+        let path = dir.join(JOURNAL_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(|e| MmappetError::io(&path, e))?;
+        file.lock().map_err(|e| MmappetError::io(&path, e))?;
+        if let Some(committed) = recorded_rows(&mut file, &path)? {
+            roll_back(dir, schema, committed)?;
+        }
+        // On failure the journal is empty or holds the row count just rolled
+        // back to, so recovering from it changes nothing
+        check_rows(dir, schema, rows)?;
+        let mut journal = AppendJournal { path, file };
+        journal.record(rows)?;
+        Ok(journal)
+    }
+
+    /// Record `rows` as committed, i.e. the new row count once all files are
+    /// written or the old one after rolling back, and remove the journal.
+    pub(crate) fn finish(mut self, rows: usize) -> Result<()> {
+        // A reader that finds the file before it's removed then truncates
+        // nothing
+        self.record(rows)?;
+        drop(self.file);
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(MmappetError::io(&self.path, e))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn record(&mut self, rows: usize) -> Result<()> {
+        let path = &self.path;
+        self.file.set_len(0).map_err(|e| MmappetError::io(path, e))?;
+        self.file.seek(SeekFrom::Start(0)).map_err(|e| MmappetError::io(path, e))?;
+        writeln!(self.file, "{}", rows).map_err(|e| MmappetError::io(path, e))?;
+        self.file.sync_all().map_err(|e| MmappetError::io(path, e))
+    }
+}
+
+/// Roll back an append to the dataset in `dir` that didn't finish, if any,
+/// see [`roll_back`], then remove the journal.
+///
+/// Does nothing while another process is appending, or about to: a journal
+/// that's locked or has no row count yet belongs to it.
+pub(crate) fn recover(dir: &Path, schema: &Schema) -> Result<()> {
+    let path = dir.join(JOURNAL_FILE);
+    let mut file = match OpenOptions::new().read(true).write(true).open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(MmappetError::io(&path, e)),
+    };
+    if file.try_lock().is_err() {
+        return Ok(());
+    }
+    let Some(rows) = recorded_rows(&mut file, &path)? else {
+        return Ok(());
+    };
+    roll_back(dir, schema, rows)?;
+    // Still locked, so no append can start using the file before it's gone
+    fs::remove_file(&path).map_err(|e| MmappetError::io(&path, e))
+}
+
+/// Row count recorded in the journal `file`, if any; an empty journal was
+/// never written to, so no column was either.
+fn recorded_rows(file: &mut File, path: &Path) -> Result<Option<usize>> {
+    let mut content = String::new();
+    file.read_to_string(&mut content).map_err(|e| MmappetError::io(path, e))?;
+    Ok(content.trim().parse::<usize>().ok())
+}
+
+/// Fail if the `COMMIT` file or a local column file of the dataset in `dir`
+/// holds another number of rows than `rows`.
+fn check_rows(dir: &Path, schema: &Schema, rows: usize) -> Result<()> {
+    let stale = |actual| MmappetError::StaleDataset {
+        path: dir.to_path_buf(),
+        expected: rows,
+        actual,
+    };
+    if let Some(commit) = Commit::read(dir)?.filter(|commit| commit.rows != rows) {
+        return Err(stale(commit.rows));
+    }
+    for col_def in schema.columns().filter(|c| c.reference.is_none()) {
+        let (col_path, _) = column_file(dir, schema, col_def)?;
+        let len = match fs::metadata(&col_path) {
+            Ok(metadata) => metadata.len() as usize,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(MmappetError::io(&col_path, e)),
+        };
+        let row_size = col_def.dtype.size_bytes() * col_def.width();
+        if row_size > 0 && len != rows * row_size {
+            return Err(stale(len / row_size));
+        }
+    }
+    Ok(())
+}
+
+/// Truncate the local column files, string data and validity bitmaps of the
+/// dataset in `dir` to `rows`, its committed row count.
+fn roll_back(dir: &Path, schema: &Schema, rows: usize) -> Result<()> {
+    for col_def in schema.columns().filter(|c| c.reference.is_none()) {
+        let (col_path, _) = column_file(dir, schema, col_def)?;
+        if !col_path.exists() {
+            continue;
+        }
+        let row_size = col_def.dtype.size_bytes() * col_def.width();
+        truncate(&col_path, (rows * row_size) as u64)?;
+        if col_def.dtype == DType::Utf8 {
+            truncate(&data_path(&col_path), last_end(&col_path, rows)?)?;
+        }
+        truncate_validity(&validity_path(dir, col_def.index), rows)?;
+    }
+    // The append may have been committed before the journal was finished
+    if let Some(commit) = Commit::read(dir)?.filter(|commit| commit.rows != rows) {
+        Commit { generation: commit.generation + 1, rows }.write(dir)?;
+    }
+    Ok(())
+}
+
+/// Shorten the file `path` to `len` bytes if it's longer.
+fn truncate(path: &Path, len: u64) -> Result<()> {
+    let file = OpenOptions::new().write(true).open(path).map_err(|e| MmappetError::io(path, e))?;
+    let current = file.metadata().map_err(|e| MmappetError::io(path, e))?.len();
+    if current > len {
+        file.set_len(len).map_err(|e| MmappetError::io(path, e))?;
+        file.sync_all().map_err(|e| MmappetError::io(path, e))?;
+    }
+    Ok(())
+}
+
+/// End offset of the last of the first `rows` strings of the string column
+/// file `path`.
+fn last_end(path: &Path, rows: usize) -> Result<u64> {
+    if rows == 0 {
+        return Ok(0);
+    }
+    let mut file = File::open(path).map_err(|e| MmappetError::io(path, e))?;
+    let mut end = [0; 8];
+    file.seek(SeekFrom::Start((rows as u64 - 1) * 8))
+        .and_then(|_| file.read_exact(&mut end))
+        .map_err(|e| MmappetError::io(path, e))?;
+    Ok(u64::from_ne_bytes(end))
+}
+
+/// Shorten the validity bitmap `path`, if any, to `rows` rows.
+fn truncate_validity(path: &Path, rows: usize) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let mut bytes = fs::read(path).map_err(|e| MmappetError::io(path, e))?;
+    if bytes.len() <= rows.div_ceil(8) {
+        return Ok(());
+    }
+    bytes.truncate(rows.div_ceil(8));
+    if !rows.is_multiple_of(8) {
+        *bytes.last_mut().unwrap() &= (1u8 << (rows % 8)) - 1;
+    }
+    fs::write(path, &bytes).map_err(|e| MmappetError::io(path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::column::TypedArrayView;
+    use crate::dataset::Dataset;
+    use crate::test_util::write_dataset;
+    use crate::writer::DatasetWriter;
+    use ndarray::ArrayView1;
+
+    #[test]
+    fn test_recover_append() {
+        let dir = std::env::temp_dir().join(format!("mmappet-journal-{}", std::process::id()));
+        let schema = Schema::new(&[("tof", DType::UInt32), ("name", DType::Utf8)]).unwrap();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write_opt("tof", &[Some(1u32), None]).unwrap();
+        writer.write_str("name", &["ab", "c"]).unwrap();
+        writer.finish().unwrap();
+        Commit { generation: 0, rows: 2 }.write(&dir).unwrap();
+
+        // An append that crashed after writing some of the files
+        let crash = || {
+            drop(AppendJournal::begin(&dir, &schema, 2).unwrap());
+            let append = |file: &str, bytes: &[u8]| {
+                let mut file = OpenOptions::new().append(true).open(dir.join(file)).unwrap();
+                file.write_all(bytes).unwrap();
+            };
+            append("0.bin", &7u32.to_ne_bytes());
+            append("1.str", b"def");
+            append("0.valid", &[0xff]);
+        };
+        crash();
+
+        // Readers see the committed rows and leave the files alone
+        let ds = Dataset::open(&dir).unwrap();
+        assert_eq!(ds.len(), 2);
+        assert_eq!(ds.get::<u32>("tof").unwrap(), &[1, 0]);
+        assert!(!ds.nullable("tof").unwrap().is_valid(1));
+        assert_eq!(fs::read(dir.join("1.str")).unwrap(), b"abcdef");
+        assert!(dir.join(JOURNAL_FILE).exists());
+
+        // Writers roll back
+        let ds = Dataset::open_mut(&dir).unwrap();
+        assert_eq!(ds.get::<u32>("tof").unwrap(), &[1, 0]);
+        assert_eq!(fs::read(dir.join("1.str")).unwrap(), b"abc");
+        assert!(!dir.join(JOURNAL_FILE).exists());
+        drop(ds);
+        crash();
+        AppendJournal::begin(&dir, &schema, 2).unwrap().finish(2).unwrap();
+        assert_eq!(fs::read(dir.join("0.bin")).unwrap().len(), 8);
+        assert!(!dir.join(JOURNAL_FILE).exists());
+
+        // Appends in progress are left alone, even before they take the lock
+        let journal = AppendJournal::begin(&dir, &schema, 2).unwrap();
+        let mut file = OpenOptions::new().append(true).open(dir.join("0.bin")).unwrap();
+        file.write_all(&[7; 4]).unwrap();
+        assert_eq!(Dataset::open(&dir).unwrap().len(), 2);
+        assert!(Dataset::open_mut(&dir).is_err());
+        assert_eq!(fs::read(dir.join("0.bin")).unwrap().len(), 12);
+        journal.finish(2).unwrap();
+        assert!(!dir.join(JOURNAL_FILE).exists());
+        File::create(dir.join(JOURNAL_FILE)).unwrap();
+        recover(&dir, &schema).unwrap();
+        assert!(dir.join(JOURNAL_FILE).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_during_append() {
+        let dir = write_dataset("journal-concurrent", &[("tof", &[0])]);
+        let writer = {
+            let dir = dir.clone();
+            std::thread::spawn(move || {
+                let mut ds = Dataset::open(&dir).unwrap();
+                for i in 1..200u32 {
+                    let batch = [i, i];
+                    let tofs = TypedArrayView::UInt32(ArrayView1::from(&batch));
+                    ds.append_batch(&[("tof", tofs)]).unwrap();
+                }
+            })
+        };
+        // Every reader sees whole batches, however far the writer got
+        while !writer.is_finished() {
+            let ds = Dataset::open(&dir).unwrap();
+            let tofs = ds.get::<u32>("tof").unwrap();
+            assert_eq!(tofs.len() % 2, 1);
+            assert!(tofs.iter().enumerate().all(|(row, &tof)| tof == (row as u32).div_ceil(2)));
+        }
+        writer.join().unwrap();
+        assert_eq!(Dataset::open(&dir).unwrap().len(), 399);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
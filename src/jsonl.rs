@@ -0,0 +1,441 @@
+//! JSON Lines export and import: one flat JSON object per row, keyed by
+//! column names.
+
+use std::io::{BufRead, BufWriter, Write};
+use std::ops::RangeBounds;
+use std::path::Path;
+
+use half::f16;
+use serde_json::{Map, Value};
+
+use crate::column::{checked_range, TypedArrayView};
+use crate::dataset::Dataset;
+use crate::dtype::DType;
+use crate::error::{MmappetError, Result};
+use crate::format::ValueFormat;
+use crate::schema::{ColumnDef, Schema};
+use crate::timestamp::{format_iso8601, parse_iso8601};
+use crate::writer::{create_atomically, DatasetWriter, Values};
+#[cfg(feature = "ingest")]
+use crate::categorical::{CategoricalView, CategoryEncoder};
+#[cfg(feature = "ingest")]
+use crate::column::typed_array;
+#[cfg(feature = "ingest")]
+use crate::storage::Storage;
+#[cfg(feature = "ingest")]
+use crate::strings::StrArrayView;
+
+/// Rows read by [`Dataset::from_jsonl`] before they're written.
+const JSONL_BATCH_ROWS: usize = 1 << 16;
+
+impl Dataset {
+    /// Write `rows` of the given columns to `writer` as JSON Lines, one
+    /// object per row keyed by column name.
+    ///
+    /// An empty `columns` writes every column. Rows are written one at a
+    /// time, so any number of them can be written without buffering the
+    /// output. Nulls and non-finite floats are `null`, timestamps ISO 8601
+    /// strings in UTC, array columns JSON arrays and custom dtypes their
+    /// formatted text.
+    ///
+    /// Returns the number of rows written, or an error if a column doesn't
+    /// exist or writing fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rows` is out of bounds.
+    pub fn export_jsonl<W: Write>(
+        &self,
+        writer: W,
+        columns: &[&str],
+        rows: impl RangeBounds<usize>,
+    ) -> Result<usize> {
+        let rows = checked_range(rows, self.len());
+        let names: Vec<&str> = if columns.is_empty() {
+            self.column_names().collect()
+        } else {
+            columns.to_vec()
+        };
+        let mut fields = Vec::with_capacity(names.len());
+        for name in &names {
+            let nullable = self.nullable(name)?;
+            let array = nullable.column().slice_typed_array(rows.clone());
+            // Keys are escaped once
+            let key = serde_json::to_string(name).unwrap() + ":";
+            fields.push((key, nullable, array));
+        }
+
+        let io = |source| MmappetError::Io { path: None, source };
+        let mut out = BufWriter::new(writer);
+        for row in rows.clone() {
+            let mut separator = "{";
+            for (key, nullable, array) in &fields {
+                out.write_all(separator.as_bytes()).map_err(io)?;
+                out.write_all(key.as_bytes()).map_err(io)?;
+                separator = ",";
+                if !nullable.is_valid(row) {
+                    out.write_all(b"null").map_err(io)?;
+                    continue;
+                }
+                let width = nullable.column().width();
+                let start = (row - rows.start) * width;
+                if width == 1 {
+                    write_value(&mut out, array, start).map_err(|e| io(e.into()))?;
+                    continue;
+                }
+                for i in 0..width {
+                    out.write_all(if i == 0 { b"[" } else { b"," }).map_err(io)?;
+                    write_value(&mut out, array, start + i).map_err(|e| io(e.into()))?;
+                }
+                out.write_all(b"]").map_err(io)?;
+            }
+            out.write_all(if fields.is_empty() { b"{}\n" } else { b"}\n" }).map_err(io)?;
+        }
+        out.flush().map_err(io)?;
+        Ok(rows.len())
+    }
+
+    /// Create the dataset `dir` with `schema` from the JSON Lines read from
+    /// `reader`, and open it.
+    ///
+    /// Every non-empty line must hold a JSON object. Keys it lacks or that
+    /// are `null` are nulls of their column, and keys not in the schema are
+    /// ignored. Numbers must fit their column's dtype, timestamps may be ISO
+    /// 8601 strings in UTC or integer ticks, and array columns take arrays
+    /// of `width` values. Lines are written in batches, so input larger than
+    /// memory can be imported.
+    ///
+    /// Returns an error naming the line of the first value that doesn't fit
+    /// its column, or if the schema has custom dtypes; `dir` is then left as
+    /// it was.
+    pub fn from_jsonl<R: BufRead, P: AsRef<Path>>(
+        reader: R,
+        dir: P,
+        schema: &Schema,
+    ) -> Result<Dataset> {
+        let dir = dir.as_ref();
+        if let Some(def) = schema.columns().find(|d| matches!(d.dtype, DType::Custom(_))) {
+            return Err(MmappetError::InvalidDType {
+                dtype: def.type_name(),
+                message: format!("column '{}' can't be read from JSON", def.name),
+            });
+        }
+
+        create_atomically(dir, |tmp_dir| {
+            let mut writer = DatasetWriter::create(tmp_dir, schema)?;
+            let mut batch = JsonBatch::new(schema);
+            for (number, line) in reader.lines().enumerate() {
+                let line = line.map_err(|source| MmappetError::Io { path: None, source })?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let invalid = |message: String| MmappetError::Import {
+                    path: Some(dir.to_path_buf()),
+                    offset: None,
+                    message: format!("line {}: {}", number + 1, message),
+                };
+                let object = match serde_json::from_str(&line) {
+                    Ok(Value::Object(object)) => object,
+                    Ok(_) => return Err(invalid("not a JSON object".to_string())),
+                    Err(e) => return Err(invalid(e.to_string())),
+                };
+                batch.push(&object).map_err(invalid)?;
+                if batch.rows() == JSONL_BATCH_ROWS {
+                    batch.flush(&mut writer)?;
+                }
+            }
+            batch.flush(&mut writer)?;
+            writer.finish()
+        })?;
+        Dataset::open(dir)
+    }
+}
+
+/// Write element `index` of `array` as a JSON value.
+fn write_value(
+    out: &mut impl Write,
+    array: &TypedArrayView<'_>,
+    index: usize,
+) -> serde_json::Result<()> {
+    match array {
+        TypedArrayView::UInt8(a) => serde_json::to_writer(out, &a[index]),
+        TypedArrayView::Int8(a) => serde_json::to_writer(out, &a[index]),
+        TypedArrayView::UInt16(a) => serde_json::to_writer(out, &a[index]),
+        TypedArrayView::Int16(a) => serde_json::to_writer(out, &a[index]),
+        TypedArrayView::UInt32(a) => serde_json::to_writer(out, &a[index]),
+        TypedArrayView::Int32(a) => serde_json::to_writer(out, &a[index]),
+        TypedArrayView::UInt64(a) => serde_json::to_writer(out, &a[index]),
+        TypedArrayView::Int64(a) => serde_json::to_writer(out, &a[index]),
+        TypedArrayView::Float16(a) => serde_json::to_writer(out, &a[index].to_f32()),
+        TypedArrayView::Float32(a) => serde_json::to_writer(out, &a[index]),
+        TypedArrayView::Float64(a) => serde_json::to_writer(out, &a[index]),
+        TypedArrayView::Bool(a) => serde_json::to_writer(out, &(a[index] != 0)),
+        TypedArrayView::TimestampMillis(a) => {
+            serde_json::to_writer(out, &format_iso8601(a[index], 1_000))
+        }
+        TypedArrayView::TimestampMicros(a) => {
+            serde_json::to_writer(out, &format_iso8601(a[index], 1_000_000))
+        }
+        TypedArrayView::Utf8(a) => serde_json::to_writer(out, a.get(index)),
+        TypedArrayView::Categorical(a) => serde_json::to_writer(out, a.value(index)),
+        TypedArrayView::Custom(a) => {
+            serde_json::to_writer(out, &a.format(index, &ValueFormat::default()))
+        }
+    }
+}
+
+/// Rows read from JSON, held column by column until they're written.
+pub(crate) struct JsonBatch {
+    columns: Vec<ColumnDef>,
+    bytes: Vec<Vec<u8>>,
+    strings: Vec<Vec<String>>,
+    validity: Vec<Vec<bool>>,
+    rows: usize,
+}
+
+impl JsonBatch {
+    pub(crate) fn new(schema: &Schema) -> Self {
+        let count = schema.len();
+        JsonBatch {
+            columns: schema.columns().cloned().collect(),
+            bytes: vec![Vec::new(); count],
+            strings: vec![Vec::new(); count],
+            validity: vec![Vec::new(); count],
+            rows: 0,
+        }
+    }
+
+    /// Number of rows added since the last write.
+    pub(crate) fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Add the row of `object`, or return why it doesn't fit the schema and
+    /// leave the batch as it was.
+    pub(crate) fn push(&mut self, object: &Map<String, Value>) -> std::result::Result<(), String> {
+        let pushed = self.push_values(object);
+        if pushed.is_err() {
+            let rows = self.rows;
+            for (i, def) in self.columns.iter().enumerate() {
+                self.validity[i].truncate(rows);
+                self.strings[i].truncate(rows);
+                self.bytes[i].truncate(rows * def.width() * def.dtype.size_bytes());
+            }
+        }
+        pushed
+    }
+
+    fn push_values(&mut self, object: &Map<String, Value>) -> std::result::Result<(), String> {
+        for (i, def) in self.columns.iter().enumerate() {
+            let value = object.get(&def.name).filter(|v| !v.is_null());
+            let mismatch = |value: &Value| {
+                format!("column '{}': {} isn't a {} value", def.name, value, def.type_name())
+            };
+            self.validity[i].push(value.is_some());
+            if matches!(def.dtype, DType::Utf8 | DType::Categorical) {
+                let text = match value {
+                    Some(value) => value.as_str().ok_or_else(|| mismatch(value))?,
+                    None => "",
+                };
+                self.strings[i].push(text.to_string());
+                continue;
+            }
+
+            let bytes = &mut self.bytes[i];
+            match value {
+                None => bytes.resize(bytes.len() + def.width() * def.dtype.size_bytes(), 0),
+                Some(value) if def.width() == 1 => {
+                    push_scalar(bytes, def.dtype, value).ok_or_else(|| mismatch(value))?
+                }
+                Some(Value::Array(items)) if items.len() == def.width() => {
+                    for item in items {
+                        push_scalar(bytes, def.dtype, item).ok_or_else(|| mismatch(item))?;
+                    }
+                }
+                Some(value) => return Err(mismatch(value)),
+            }
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Write the rows added so far.
+    fn flush(&mut self, writer: &mut DatasetWriter) -> Result<()> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+        let mut columns = Vec::with_capacity(self.columns.len());
+        for (i, def) in self.columns.iter().enumerate() {
+            let validity = std::mem::take(&mut self.validity[i]);
+            let strings = || self.strings[i].iter().map(String::as_str).collect();
+            let values = match def.dtype {
+                DType::Utf8 => Values::Strs(strings()),
+                DType::Categorical => Values::Labels(strings()),
+                _ => Values::Bytes(std::mem::take(&mut self.bytes[i])),
+            };
+            columns.push((values, validity.contains(&false).then_some(validity)));
+        }
+        writer.write_columns(columns, std::mem::take(&mut self.rows))?;
+        self.strings.iter_mut().for_each(Vec::clear);
+        Ok(())
+    }
+
+    /// Append the rows added so far to `dataset` as one batch, see
+    /// [`Dataset::append_batch`]; they're kept if that fails. Returns the
+    /// new row count. Nulls aren't kept, so rows with them shouldn't be added.
+    #[cfg(feature = "ingest")]
+    pub(crate) fn append(&mut self, dataset: &mut Dataset) -> Result<usize> {
+        if self.rows == 0 {
+            return Ok(dataset.len());
+        }
+        // Values are viewed in place, so they need the alignment of a mapping
+        let mut aligned = Vec::with_capacity(self.columns.len());
+        let mut strings = Vec::with_capacity(self.columns.len());
+        let mut encoders = Vec::with_capacity(self.columns.len());
+        for (i, def) in self.columns.iter().enumerate() {
+            let mut storage = Storage::zeroed(self.bytes[i].len());
+            storage.as_mut_slice().copy_from_slice(&self.bytes[i]);
+            aligned.push(storage);
+            let ends: Vec<u64> = self.strings[i]
+                .iter()
+                .scan(0, |end, s| {
+                    *end += s.len() as u64;
+                    Some(*end)
+                })
+                .collect();
+            strings.push((ends, self.strings[i].concat()));
+            let mut encoder = CategoryEncoder::default();
+            let codes: Vec<u32> = match def.dtype {
+                DType::Categorical => self.strings[i].iter().map(|s| encoder.encode(s)).collect(),
+                _ => Vec::new(),
+            };
+            encoders.push((encoder, codes));
+        }
+        let mut batch = Vec::with_capacity(self.columns.len());
+        for (i, def) in self.columns.iter().enumerate() {
+            let array = match def.dtype {
+                DType::Utf8 => {
+                    let (ends, data) = &strings[i];
+                    TypedArrayView::Utf8(StrArrayView::new(0, ends, data.as_bytes()))
+                }
+                DType::Categorical => {
+                    let (encoder, codes) = &encoders[i];
+                    TypedArrayView::Categorical(CategoricalView::new(codes, encoder.categories()))
+                }
+                dtype => typed_array(dtype, &aligned[i]),
+            };
+            batch.push((def.name.as_str(), array));
+        }
+        let rows = dataset.append_batch(&batch)?;
+        self.rows = 0;
+        self.bytes.iter_mut().for_each(Vec::clear);
+        self.strings.iter_mut().for_each(Vec::clear);
+        self.validity.iter_mut().for_each(Vec::clear);
+        Ok(rows)
+    }
+}
+
+/// Append a JSON value as an element of `dtype`, or return `None` if it
+/// isn't one.
+fn push_scalar(bytes: &mut Vec<u8>, dtype: DType, value: &Value) -> Option<()> {
+    fn int<T: TryFrom<i64> + TryFrom<u64>>(value: &Value) -> Option<T> {
+        match value.as_i64() {
+            Some(v) => T::try_from(v).ok(),
+            None => T::try_from(value.as_u64()?).ok(),
+        }
+    }
+    fn ticks(value: &Value, per_second: i64) -> Option<i64> {
+        value.as_i64().or_else(|| parse_iso8601(value.as_str()?, per_second))
+    }
+
+    match dtype {
+        DType::UInt8 => bytes.push(int::<u8>(value)?),
+        DType::Int8 => bytes.extend_from_slice(&int::<i8>(value)?.to_ne_bytes()),
+        DType::UInt16 => bytes.extend_from_slice(&int::<u16>(value)?.to_ne_bytes()),
+        DType::Int16 => bytes.extend_from_slice(&int::<i16>(value)?.to_ne_bytes()),
+        DType::UInt32 => bytes.extend_from_slice(&int::<u32>(value)?.to_ne_bytes()),
+        DType::Int32 => bytes.extend_from_slice(&int::<i32>(value)?.to_ne_bytes()),
+        DType::UInt64 => bytes.extend_from_slice(&int::<u64>(value)?.to_ne_bytes()),
+        DType::Int64 => bytes.extend_from_slice(&int::<i64>(value)?.to_ne_bytes()),
+        DType::Float16 => {
+            let value = f16::from_f64(value.as_f64()?);
+            bytes.extend_from_slice(&value.to_bits().to_ne_bytes())
+        }
+        DType::Float32 => bytes.extend_from_slice(&(value.as_f64()? as f32).to_ne_bytes()),
+        DType::Float64 => bytes.extend_from_slice(&value.as_f64()?.to_ne_bytes()),
+        DType::Bool => bytes.push(value.as_bool()? as u8),
+        DType::TimestampMillis => bytes.extend_from_slice(&ticks(value, 1_000)?.to_ne_bytes()),
+        DType::TimestampMicros => {
+            bytes.extend_from_slice(&ticks(value, 1_000_000)?.to_ne_bytes())
+        }
+        DType::Utf8 | DType::Categorical | DType::Custom(_) => return None,
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jsonl_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("mmappet-jsonl-{}", std::process::id()));
+        let copy = dir.with_extension("copy");
+        let mut schema = Schema::new(&[
+            ("id", DType::UInt32),
+            ("xy", DType::Float32),
+            ("name", DType::Utf8),
+            ("charge", DType::Categorical),
+            ("time", DType::TimestampMillis),
+        ])
+        .unwrap();
+        schema.set_shape("xy", &[2]).unwrap();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write_opt("id", &[Some(1u32), None, Some(3)]).unwrap();
+        writer.write("xy", &[1.5f32, -2.0, f32::NAN, 1.0, 3.25, 4.0]).unwrap();
+        writer.write_str("name", &["a", "b\"c", ""]).unwrap();
+        writer.write_categorical("charge", &["2+", "3+", "2+"]).unwrap();
+        writer.write_timestamps("time", &[0, 1_500, -1]).unwrap();
+        writer.finish().unwrap();
+        let ds = Dataset::open(&dir).unwrap();
+
+        let mut out = Vec::new();
+        assert_eq!(ds.export_jsonl(&mut out, &[], ..).unwrap(), 3);
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[1],
+            r#"{"id":null,"xy":[null,1.0],"name":"b\"c","charge":"3+","time":"1970-01-01T00:00:01.500Z"}"#
+        );
+
+        // Extra keys are ignored, missing ones are nulls
+        let input = format!("{}\n\n{}\n{{\"id\":7,\"extra\":true}}\n", lines[0], lines[2]);
+        let out = Dataset::from_jsonl(input.as_bytes(), &copy, &schema).unwrap();
+        assert_eq!(out.len(), 3);
+        let ids = out.nullable("id").unwrap();
+        assert_eq!(ids.iter_opt::<u32>().unwrap().collect::<Vec<_>>(), [Some(1), Some(3), Some(7)]);
+        assert_eq!(out.get::<f32>("xy").unwrap(), [1.5, -2.0, 3.25, 4.0, 0.0, 0.0]);
+        assert_eq!(out.nullable("xy").unwrap().null_count(), 1);
+        assert_eq!(out["name"].iter_str().unwrap().collect::<Vec<_>>(), ["a", "", ""]);
+        assert_eq!(out.get::<i64>("time").ok(), None);
+        assert_eq!(out["time"].as_timestamps().unwrap(), [0, -1, 0]);
+
+        // A failed import leaves the dataset there as it was, or none at all
+        let Err(bad) = Dataset::from_jsonl(&b"{\"id\":-1}\n"[..], &copy, &schema) else {
+            panic!("negative id imported");
+        };
+        assert!(bad.to_string().contains("line 1"), "{}", bad);
+        assert_eq!(Dataset::open(&copy).unwrap().len(), 3);
+        std::fs::remove_dir_all(&copy).unwrap();
+        let input = format!("{}\n{{\"id\":", lines[0]);
+        let Err(bad) = Dataset::from_jsonl(input.as_bytes(), &copy, &schema) else {
+            panic!("truncated line imported");
+        };
+        assert!(bad.to_string().contains("line 2"), "{}", bad);
+        assert!(!copy.exists());
+        let parent = std::fs::read_dir(copy.parent().unwrap()).unwrap();
+        let name = copy.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(!parent.flatten().any(|e| e.file_name().to_string_lossy().contains(&name)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
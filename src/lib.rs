@@ -22,17 +22,155 @@
 //! let ids: &[u32] = ds.get("id").unwrap();
 //! ```
 
+// Lets `#[derive(MmappetRow)]` output refer to `::mmappet` in unit tests
+#[cfg(all(test, feature = "derive"))]
+extern crate self as mmappet;
+
+mod aggregate;
+#[cfg(feature = "tokio")]
+pub mod aio;
+mod append;
+mod apply;
+#[cfg(feature = "arrow")]
+mod arrow;
+mod bloom;
+#[cfg(feature = "capi")]
+mod capi;
+#[cfg(feature = "bruker")]
+mod bruker;
+mod budget;
+mod cast;
+mod categorical;
+#[cfg(feature = "object_store")]
+mod cloud;
 mod column;
+mod commit;
+mod concat;
+mod correlation;
+mod counts;
+mod csv;
+mod compress;
+pub mod compute;
+mod computed;
 mod dataset;
+mod dedup;
+mod delta;
+mod diff;
 mod dtype;
+mod edit;
 mod error;
+mod expr;
+mod filter;
+mod format;
+mod group;
+mod hive;
+mod histogram;
+mod index;
+#[cfg(feature = "ingest")]
+mod ingest;
+#[cfg(feature = "arrow")]
+mod ipc;
+mod join;
+mod journal;
+mod jsonl;
+#[cfg(feature = "nalgebra")]
+mod linalg;
+mod memory;
+mod meta;
+mod npy;
+mod nullable;
+#[cfg(feature = "parquet")]
+mod parquet;
+#[cfg(feature = "polars")]
+mod polars;
+#[cfg(feature = "mzml")]
+mod mzml;
+#[cfg(feature = "parallel")]
+mod parallel;
+#[cfg(any(feature = "bruker", feature = "mzml"))]
+mod pipeline;
+mod quantile;
+mod readahead;
+mod registry;
+#[cfg(feature = "remote")]
+mod remote;
+mod row;
+mod sample;
 mod schema;
+mod shuffle;
+mod simd;
+mod sort;
+mod split;
+mod stats;
+mod storage;
+mod strings;
+#[cfg(test)]
+mod test_util;
+mod timestamp;
+mod validate;
+mod value;
+mod view;
+#[cfg(feature = "watch")]
+mod watch;
+mod zonemap;
+mod writer;
 
-pub use column::{Column, TypedArrayView};
-pub use dataset::Dataset;
-pub use dtype::{DType, MmappetType};
+pub use aggregate::{Agg, AggTable, GroupBy};
+#[cfg(feature = "arrow")]
+pub use ::arrow::array::RecordBatch;
+#[cfg(feature = "bruker")]
+pub use bruker::import_bruker;
+pub use budget::{BudgetedDataset, MemoryBudget};
+pub use cast::CastMode;
+pub use categorical::CategoricalView;
+#[cfg(feature = "object_store")]
+pub use cloud::upload;
+pub use column::{Advice, Chunks, Column, TypedArray, TypedArrayView};
+pub use concat::ConcatDataset;
+pub use correlation::Correlation;
+pub use csv::CsvOptions;
+pub use dataset::{Dataset, OpenOptions};
+pub use diff::{diff, ColumnDiff, DatasetDiff, DiffOptions, SchemaDiff};
+pub use dtype::{ByteOrder, DType, MmappetType};
+pub use edit::DatasetEdit;
 pub use error::{MmappetError, Result};
-pub use schema::{ColumnDef, Schema};
+pub use expr::{col, lit, BinaryOp, BoundExpr, Expr};
+pub use filter::FilteredView;
+pub use format::{BoolStyle, TimestampStyle, ValueFormat};
+pub use group::GroupSpans;
+pub use hive::PartitionWriter;
+pub use histogram::Histogram;
+#[cfg(feature = "ingest")]
+pub use ingest::{Decoder, IngestOptions, Ingestor, JsonDecoder, Record, Source};
+#[cfg(feature = "kafka")]
+pub use ingest::KafkaSource;
+#[cfg(feature = "nats")]
+pub use ingest::NatsSource;
+pub use join::{join, JoinType};
+pub use memory::{ColumnMemory, MemoryReport};
+pub use meta::ProvenanceEntry;
+pub use nullable::NullableColumn;
+#[cfg(feature = "mzml")]
+pub use mzml::{import_mzml, import_mzml_from};
+pub use registry::{register_dtype, CustomArrayView, CustomDType};
+#[cfg(feature = "remote")]
+pub use remote::{RemoteChunks, RemoteDataset};
+pub use row::{MmappetRow, Rows};
+#[cfg(feature = "derive")]
+pub use mmappet_derive::MmappetRow;
+pub use schema::{ColumnAttributes, ColumnDef, Schema, FORMAT_VERSION};
+pub use split::split;
+pub use stats::ColumnStats;
+pub use strings::StrArrayView;
+pub use validate::{ColumnReport, ValidationIssue, ValidationReport};
+pub use value::{Row, Value};
+pub use view::{DatasetChunks, DatasetView};
+#[cfg(feature = "watch")]
+pub use watch::Watcher;
+pub use writer::DatasetWriter;
 
 // Re-export commonly used ndarray types for convenience
-pub use ndarray::ArrayView1;
+pub use ndarray::{Array2, ArrayView1, ArrayView2, ArrayViewD, CowArray};
+
+// Half-precision floats stored by float16 columns
+pub use half::f16;
@@ -0,0 +1,89 @@
+//! nalgebra conversions for column groups (requires the `nalgebra` feature).
+
+use nalgebra::{DMatrix, DVector, Scalar};
+
+use crate::dataset::Dataset;
+use crate::dtype::MmappetType;
+use crate::error::{MmappetError, Result};
+use crate::writer::DatasetWriter;
+
+impl Dataset {
+    /// Copy columns of the same type into a `(rows, columns)` nalgebra matrix.
+    ///
+    /// Column `j` of the result holds `names[j]`. Returns an error if any
+    /// column doesn't exist or the type doesn't match.
+    pub fn to_dmatrix<T: MmappetType + Scalar>(&self, names: &[&str]) -> Result<DMatrix<T>> {
+        // nalgebra storage is column-major, so columns can be appended as-is.
+        let mut data = Vec::with_capacity(self.len() * names.len());
+        for name in names {
+            data.extend_from_slice(self.get::<T>(name)?);
+        }
+        Ok(DMatrix::from_vec(self.len(), names.len(), data))
+    }
+
+    /// Copy a single column into a nalgebra vector.
+    pub fn to_dvector<T: MmappetType + Scalar>(&self, name: &str) -> Result<DVector<T>> {
+        Ok(DVector::from_column_slice(self.get::<T>(name)?))
+    }
+}
+
+impl DatasetWriter {
+    /// Append the columns of a matrix to the dataset columns `names`.
+    ///
+    /// Column `j` of the matrix is appended to `names[j]`.
+    pub fn write_dmatrix<T: MmappetType + Scalar>(
+        &mut self,
+        names: &[&str],
+        matrix: &DMatrix<T>,
+    ) -> Result<()> {
+        if names.len() != matrix.ncols() {
+            return Err(MmappetError::InvalidBatch {
+                path: None,
+                message: format!(
+                    "{} column names for a matrix with {} columns",
+                    names.len(),
+                    matrix.ncols()
+                ),
+            });
+        }
+        // Column-major storage: each matrix column is a contiguous run.
+        let rows = matrix.nrows();
+        for (j, name) in names.iter().enumerate() {
+            self.write(name, &matrix.as_slice()[j * rows..(j + 1) * rows])?;
+        }
+        Ok(())
+    }
+
+    /// Append a vector to a dataset column.
+    pub fn write_dvector<T: MmappetType + Scalar>(
+        &mut self,
+        name: &str,
+        vector: &DVector<T>,
+    ) -> Result<()> {
+        self.write(name, vector.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dtype::DType;
+    use crate::schema::Schema;
+
+    #[test]
+    fn test_dmatrix_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("mmappet-linalg-{}", std::process::id()));
+        let schema = Schema::new(&[("x", DType::Float64), ("y", DType::Float64)]).unwrap();
+        let matrix = DMatrix::from_row_slice(3, 2, &[1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
+
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write_dmatrix(&["x", "y"], &matrix).unwrap();
+        writer.finish().unwrap();
+
+        let ds = Dataset::open(&dir).unwrap();
+        assert_eq!(ds.to_dmatrix::<f64>(&["x", "y"]).unwrap(), matrix);
+        assert_eq!(ds.to_dvector::<f64>("y").unwrap().as_slice(), &[4.0, 5.0, 6.0]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
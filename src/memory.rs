@@ -0,0 +1,177 @@
+//! Mapped and resident memory of datasets.
+
+use crate::column::Column;
+use crate::dataset::Dataset;
+use crate::error::Result;
+
+/// Memory held by one column, see [`Dataset::memory_report`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnMemory {
+    pub name: String,
+    /// Size of the mapping, 0 if the column isn't mapped yet.
+    pub mapped_bytes: usize,
+    /// Bytes of the mapping currently in RAM, `None` where the OS can't
+    /// tell.
+    pub resident_bytes: Option<usize>,
+}
+
+/// Per-column mapped and resident bytes of a dataset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// One entry per column, in schema order.
+    pub columns: Vec<ColumnMemory>,
+}
+
+impl MemoryReport {
+    /// Total mapped bytes of all columns.
+    pub fn mapped_bytes(&self) -> usize {
+        self.columns.iter().map(|c| c.mapped_bytes).sum()
+    }
+
+    /// Total resident bytes of all columns, `None` where the OS can't tell.
+    pub fn resident_bytes(&self) -> Option<usize> {
+        self.columns.iter().map(|c| c.resident_bytes).sum()
+    }
+}
+
+impl Dataset {
+    /// Report how much of every column is mapped and resident in RAM.
+    ///
+    /// Columns that weren't accessed yet aren't mapped and are reported as
+    /// empty; this doesn't map them, nor read any page.
+    pub fn memory_report(&self) -> Result<MemoryReport> {
+        let mut columns = Vec::with_capacity(self.num_columns());
+        for name in self.column_names() {
+            let memory = match self.column_if_mapped(name) {
+                Some(column) => ColumnMemory {
+                    name: name.to_string(),
+                    mapped_bytes: column.as_bytes().len(),
+                    resident_bytes: column
+                        .resident_bytes()
+                        .map_err(|e| e.with_path(self.path()).with_column(name))?,
+                },
+                None => ColumnMemory {
+                    name: name.to_string(),
+                    mapped_bytes: 0,
+                    resident_bytes: Some(0),
+                },
+            };
+            columns.push(memory);
+        }
+        Ok(MemoryReport { columns })
+    }
+}
+
+impl Column {
+    /// Bytes of the column currently in RAM, `None` where the OS can't tell.
+    ///
+    /// Uses `mincore` on Unix and `QueryWorkingSetEx` on Windows. Doesn't
+    /// read any page. Columns copied into memory are always resident.
+    pub fn resident_bytes(&self) -> Result<Option<usize>> {
+        let bytes = self.as_bytes();
+        if bytes.is_empty() || !self.is_mapped() {
+            return Ok(Some(bytes.len()));
+        }
+        let pages = resident_pages(bytes)?;
+        Ok(pages.map(|(resident, page_size)| (resident * page_size).min(bytes.len())))
+    }
+}
+
+/// Number of resident pages of `bytes`, which must start at a page boundary,
+/// and the page size.
+#[cfg(unix)]
+fn resident_pages(bytes: &[u8]) -> Result<Option<(usize, usize)>> {
+    // SAFETY: sysconf has no preconditions.
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let mut status = vec![0; bytes.len().div_ceil(page_size)];
+    // SAFETY: `bytes` is a mapping starting at a page boundary and `status`
+    // holds one entry per page of it.
+    let ret = unsafe {
+        libc::mincore(
+            bytes.as_ptr() as *mut libc::c_void,
+            bytes.len(),
+            status.as_mut_ptr(),
+        )
+    };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let resident = status.iter().filter(|&&s| s & 1 != 0).count();
+    Ok(Some((resident, page_size)))
+}
+
+#[cfg(windows)]
+fn resident_pages(bytes: &[u8]) -> Result<Option<(usize, usize)>> {
+    use windows_sys::Win32::System::ProcessStatus::{
+        QueryWorkingSetEx, PSAPI_WORKING_SET_EX_INFORMATION,
+    };
+    use windows_sys::Win32::System::Threading::GetCurrentProcess;
+
+    const PAGE_SIZE: usize = 4096;
+    /// Pages queried per call.
+    const BATCH: usize = 4096;
+
+    let pages = bytes.len().div_ceil(PAGE_SIZE);
+    let mut info: Vec<PSAPI_WORKING_SET_EX_INFORMATION> = Vec::with_capacity(BATCH);
+    let mut resident = 0;
+    for start in (0..pages).step_by(BATCH) {
+        info.clear();
+        info.extend((start..pages.min(start + BATCH)).map(|page| {
+            PSAPI_WORKING_SET_EX_INFORMATION {
+                VirtualAddress: bytes[page * PAGE_SIZE..].as_ptr() as *mut _,
+                ..Default::default()
+            }
+        }));
+        // SAFETY: `info` holds initialized entries whose addresses point into
+        // the mapping; the call only fills in their attributes.
+        let ok = unsafe {
+            QueryWorkingSetEx(
+                GetCurrentProcess(),
+                info.as_mut_ptr() as *mut _,
+                std::mem::size_of_val(info.as_slice()) as u32,
+            )
+        };
+        if ok == 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        // Bit 0 of the attributes is the "valid" (resident) flag
+        // SAFETY: every bit pattern of the union is a valid `usize`.
+        resident += info
+            .iter()
+            .filter(|i| unsafe { i.VirtualAttributes.Flags } & 1 != 0)
+            .count();
+    }
+    Ok(Some((resident, PAGE_SIZE)))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn resident_pages(_bytes: &[u8]) -> Result<Option<(usize, usize)>> {
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::write_dataset;
+
+    #[test]
+    fn test_memory_report() {
+        let values: Vec<u32> = (0..10_000).collect();
+        let dir = write_dataset("memory", &[("a", &values), ("b", &values)]);
+        let ds = Dataset::open(&dir).unwrap();
+
+        let report = ds.memory_report().unwrap();
+        assert_eq!(report.mapped_bytes(), 0);
+
+        // Reading a column makes all of it resident
+        assert_eq!(ds.get::<u32>("a").unwrap().iter().sum::<u32>(), 49_995_000);
+        let report = ds.memory_report().unwrap();
+        assert_eq!(report.columns[0].name, "a");
+        assert_eq!(report.columns[0].mapped_bytes, 40_000);
+        assert_eq!(report.columns[0].resident_bytes, Some(40_000));
+        assert_eq!(report.columns[1].mapped_bytes, 0);
+        assert_eq!(report.resident_bytes(), Some(40_000));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
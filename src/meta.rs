@@ -0,0 +1,217 @@
+//! Key/value metadata with an append-only provenance log.
+//!
+//! Metadata lives in `meta.txt` as `key=value` lines. Every change is also
+//! appended to `provenance.log`, one line per change set:
+//! `{unix seconds}\t{tool}\t{key=value}\t...`. Values are escaped so that
+//! neither file contains raw tabs or newlines.
+
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::dataset::Dataset;
+use crate::error::{MmappetError, Result};
+
+const META_FILE: &str = "meta.txt";
+const PROVENANCE_FILE: &str = "provenance.log";
+
+/// One change set recorded in the provenance log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProvenanceEntry {
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// Tool that made the change, e.g. `mmappet-cli 0.1.0`.
+    pub tool: String,
+    /// Keys and values set by the change, in the order given.
+    pub changes: Vec<(String, String)>,
+}
+
+impl Dataset {
+    /// Current metadata, empty if none was ever set.
+    pub fn metadata(&self) -> Result<BTreeMap<String, String>> {
+        read_metadata(self.path())
+    }
+
+    /// Set metadata keys and record the change in the provenance log.
+    ///
+    /// Keys must be non-empty and contain no `=` or whitespace. `meta.txt` is
+    /// replaced atomically; the log is only ever appended to.
+    pub fn set_metadata(&self, changes: &[(&str, &str)], tool: &str) -> Result<()> {
+        set_metadata(self.path(), changes, tool)
+    }
+
+    /// All recorded change sets, oldest first.
+    pub fn provenance(&self) -> Result<Vec<ProvenanceEntry>> {
+        let path = self.path().join(PROVENANCE_FILE);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let content = fs::read_to_string(&path).map_err(|e| MmappetError::io(&path, e))?;
+
+        let mut entries = Vec::new();
+        for (line_num, line) in content.lines().enumerate() {
+            let invalid = |message: &str| MmappetError::InvalidMetadata {
+                path: path.clone(),
+                line: Some(line_num + 1),
+                message: message.to_string(),
+            };
+            let mut fields = line.split('\t');
+            let timestamp = fields
+                .next()
+                .and_then(|t| t.parse().ok())
+                .ok_or_else(|| invalid("expected a timestamp"))?;
+            let tool = unescape(fields.next().ok_or_else(|| invalid("expected a tool"))?);
+            let changes = fields
+                .map(|field| {
+                    let (key, value) = field
+                        .split_once('=')
+                        .ok_or_else(|| invalid("expected 'key=value'"))?;
+                    Ok((key.to_string(), unescape(value)))
+                })
+                .collect::<Result<_>>()?;
+            entries.push(ProvenanceEntry {
+                timestamp,
+                tool,
+                changes,
+            });
+        }
+        Ok(entries)
+    }
+}
+
+/// Read `meta.txt` of the dataset in `dir`.
+pub(crate) fn read_metadata(dir: &Path) -> Result<BTreeMap<String, String>> {
+    let path = dir.join(META_FILE);
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let content = fs::read_to_string(&path).map_err(|e| MmappetError::io(&path, e))?;
+
+    let mut metadata = BTreeMap::new();
+    for (line_num, line) in content.lines().enumerate() {
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| MmappetError::InvalidMetadata {
+                path: path.clone(),
+                line: Some(line_num + 1),
+                message: "expected 'key=value'".to_string(),
+            })?;
+        metadata.insert(key.to_string(), unescape(value));
+    }
+    Ok(metadata)
+}
+
+/// Check that `key` is non-empty and contains no `=` or whitespace.
+pub(crate) fn check_key(dir: &Path, key: &str) -> Result<()> {
+    if key.is_empty() || key.contains(|c: char| c == '=' || c.is_whitespace()) {
+        return Err(MmappetError::InvalidMetadata {
+            path: dir.join(META_FILE),
+            line: None,
+            message: format!("invalid key '{}'", key),
+        });
+    }
+    Ok(())
+}
+
+pub(crate) fn set_metadata(dir: &Path, changes: &[(&str, &str)], tool: &str) -> Result<()> {
+    for (key, _) in changes {
+        check_key(dir, key)?;
+    }
+
+    let mut metadata = read_metadata(dir)?;
+    for (key, value) in changes {
+        metadata.insert(key.to_string(), value.to_string());
+    }
+
+    // Log first: a change is never visible without its provenance
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let mut line = format!("{}\t{}", timestamp, escape(tool));
+    for (key, value) in changes {
+        line.push_str(&format!("\t{}={}", key, escape(value)));
+    }
+    line.push('\n');
+    let log_path = dir.join(PROVENANCE_FILE);
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .and_then(|mut log| log.write_all(line.as_bytes()))
+        .map_err(|e| MmappetError::io(&log_path, e))?;
+
+    let mut content = String::new();
+    for (key, value) in &metadata {
+        content.push_str(&format!("{}={}\n", key, escape(value)));
+    }
+    let meta_path = dir.join(META_FILE);
+    let tmp_path = dir.join(format!("{}.tmp", META_FILE));
+    fs::write(&tmp_path, content).map_err(|e| MmappetError::io(&tmp_path, e))?;
+    fs::rename(&tmp_path, &meta_path).map_err(|e| MmappetError::io(&meta_path, e))?;
+    Ok(())
+}
+
+pub(crate) fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+pub(crate) fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::write_dataset;
+
+    #[test]
+    fn test_metadata_and_provenance() {
+        let dir = write_dataset("meta", &[("frame", &[1, 2])]);
+        let ds = Dataset::open(&dir).unwrap();
+        assert!(ds.metadata().unwrap().is_empty());
+
+        ds.set_metadata(&[("source", "run42.d"), ("note", "a\tb\nc\\")], "tool-1")
+            .unwrap();
+        ds.set_metadata(&[("source", "run43.d")], "tool-2").unwrap();
+        assert!(ds.set_metadata(&[("bad key", "x")], "tool-3").is_err());
+
+        let metadata = ds.metadata().unwrap();
+        assert_eq!(metadata["source"], "run43.d");
+        assert_eq!(metadata["note"], "a\tb\nc\\");
+
+        let history = ds.provenance().unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].tool, "tool-1");
+        assert_eq!(
+            history[0].changes,
+            vec![
+                ("source".to_string(), "run42.d".to_string()),
+                ("note".to_string(), "a\tb\nc\\".to_string())
+            ]
+        );
+        assert_eq!(history[1].changes[0].1, "run43.d");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,415 @@
+//! mzML import (requires the `mzml` feature).
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use base64::Engine;
+use flate2::read::ZlibDecoder;
+use quick_xml::events::BytesStart;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use crate::dtype::DType;
+use crate::error::{MmappetError, Result};
+use crate::pipeline;
+use crate::schema::Schema;
+use crate::writer::{create_atomically, DatasetWriter};
+
+// PSI-MS controlled vocabulary accessions used by the importer.
+const MS_LEVEL: &str = "MS:1000511";
+const SCAN_START_TIME: &str = "MS:1000016";
+const MZ_ARRAY: &str = "MS:1000514";
+const INTENSITY_ARRAY: &str = "MS:1000515";
+const FLOAT_32: &str = "MS:1000521";
+const FLOAT_64: &str = "MS:1000523";
+const INT_32: &str = "MS:1000519";
+const INT_64: &str = "MS:1000522";
+const ZLIB: &str = "MS:1000574";
+const NO_COMPRESSION: &str = "MS:1000576";
+const UNIT_MINUTE: &str = "UO:0000031";
+
+/// Numeric encoding of a binary data array.
+#[derive(Clone, Copy)]
+enum Encoding {
+    Float32,
+    Float64,
+    Int32,
+    Int64,
+}
+
+/// Which array a `binaryDataArray` holds.
+#[derive(Clone, Copy, PartialEq)]
+enum ArrayKind {
+    Mz,
+    Intensity,
+    Other,
+}
+
+/// State of the `binaryDataArray` being parsed.
+struct ArrayState {
+    kind: ArrayKind,
+    encoding: Encoding,
+    zlib: bool,
+    base64: String,
+}
+
+/// A parsed `spectrum` whose binary arrays are not decoded yet.
+#[derive(Default)]
+struct RawSpectrum {
+    index: u32,
+    ms_level: u8,
+    rt: f64,
+    mz: Option<ArrayState>,
+    intensity: Option<ArrayState>,
+}
+
+/// A spectrum with decoded peak arrays.
+struct Spectrum {
+    index: u32,
+    ms_level: u8,
+    rt: f64,
+    mz: Vec<f64>,
+    intensity: Vec<f64>,
+}
+
+/// Convert an mzML file into an mmappet dataset at `out`.
+///
+/// Spectra are flattened into one row per peak with columns
+/// `spectrum_index` (`uint32`), `mz` (`float64`), `intensity` (`float32`),
+/// `rt` (`float64`, seconds) and `ms_level` (`uint8`). The file is parsed as a
+/// stream on one thread while binary arrays are decoded on worker threads, so
+/// memory use is bounded by a few spectra in flight. Numpress-compressed
+/// arrays are not supported. Returns the number of rows written.
+pub fn import_mzml<P: AsRef<Path>, Q: AsRef<Path>>(mzml_path: P, out: Q) -> Result<usize> {
+    let mzml_path = mzml_path.as_ref();
+    let file = File::open(mzml_path).map_err(|e| MmappetError::io(mzml_path, e))?;
+    import_mzml_from(BufReader::new(file), out).map_err(|e| e.with_path(mzml_path))
+}
+
+/// Like [`import_mzml`], reading the mzML document from `reader`.
+pub fn import_mzml_from<R: BufRead + Send, Q: AsRef<Path>>(reader: R, out: Q) -> Result<usize> {
+    create_atomically(out.as_ref(), |tmp_dir| {
+        let mut writer = DatasetWriter::create(
+            tmp_dir,
+            &Schema::new(&[
+                ("spectrum_index", DType::UInt32),
+                ("mz", DType::Float64),
+                ("intensity", DType::Float32),
+                ("rt", DType::Float64),
+                ("ms_level", DType::UInt8),
+            ])?,
+        )?;
+
+        pipeline::run(
+            pipeline::default_workers(),
+            |feed| parse_spectra(reader, feed),
+            decode_spectrum,
+            |spectrum| write_spectrum(&mut writer, &spectrum),
+        )?;
+
+        writer.finish()
+    })
+}
+
+/// Parse spectra from an mzML document, handing each one to `feed`.
+///
+/// Stops early (successfully) if `feed` returns `false`.
+fn parse_spectra<R: BufRead>(reader: R, feed: &mut dyn FnMut(RawSpectrum) -> bool) -> Result<()> {
+    let mut reader = Reader::from_reader(reader);
+    let mut buf = Vec::new();
+    let mut spectrum: Option<RawSpectrum> = None;
+    let mut array: Option<ArrayState> = None;
+    let mut in_binary = false;
+    let mut spectrum_count = 0u32;
+
+    loop {
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(|e| import_error(reader.buffer_position(), e))?;
+
+        match event {
+            Event::Start(e) | Event::Empty(e)
+                if spectrum.is_some() || e.local_name().as_ref() == "spectrum" =>
+            {
+                match e.local_name().as_ref() {
+                    "spectrum" => {
+                        let index = match attr(&e, "index")? {
+                            Some(v) => v.parse().map_err(|_| {
+                                import_error(reader.buffer_position(), "invalid spectrum index")
+                            })?,
+                            None => spectrum_count,
+                        };
+                        spectrum_count += 1;
+                        spectrum = Some(RawSpectrum {
+                            index,
+                            ..Default::default()
+                        });
+                    }
+                    "binaryDataArray" => {
+                        array = Some(ArrayState {
+                            kind: ArrayKind::Other,
+                            encoding: Encoding::Float64,
+                            zlib: false,
+                            base64: String::new(),
+                        });
+                    }
+                    "binary" => in_binary = true,
+                    "cvParam" => {
+                        let accession = attr(&e, "accession")?.unwrap_or_default();
+                        if let Some(state) = array.as_mut() {
+                            match accession.as_str() {
+                                MZ_ARRAY => state.kind = ArrayKind::Mz,
+                                INTENSITY_ARRAY => state.kind = ArrayKind::Intensity,
+                                FLOAT_32 => state.encoding = Encoding::Float32,
+                                FLOAT_64 => state.encoding = Encoding::Float64,
+                                INT_32 => state.encoding = Encoding::Int32,
+                                INT_64 => state.encoding = Encoding::Int64,
+                                ZLIB => state.zlib = true,
+                                NO_COMPRESSION => state.zlib = false,
+                                a if a.starts_with("MS:100231") => {
+                                    return Err(import_error(
+                                        reader.buffer_position(),
+                                        format!("unsupported array compression {}", a),
+                                    ));
+                                }
+                                _ => {}
+                            }
+                        } else if let Some(state) = spectrum.as_mut() {
+                            let value = attr(&e, "value")?.unwrap_or_default();
+                            match accession.as_str() {
+                                MS_LEVEL => {
+                                    state.ms_level = value.parse().map_err(|_| {
+                                        import_error(reader.buffer_position(), "invalid ms level")
+                                    })?;
+                                }
+                                SCAN_START_TIME => {
+                                    let rt: f64 = value.parse().map_err(|_| {
+                                        import_error(
+                                            reader.buffer_position(),
+                                            "invalid scan start time",
+                                        )
+                                    })?;
+                                    let minutes =
+                                        attr(&e, "unitAccession")?.as_deref() == Some(UNIT_MINUTE);
+                                    state.rt = if minutes { rt * 60.0 } else { rt };
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::Text(e) if in_binary => {
+                if let Some(state) = array.as_mut() {
+                    state.base64.push_str(e.trim());
+                }
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                "binary" => in_binary = false,
+                "binaryDataArray" => {
+                    if let (Some(state), Some(spec)) = (array.take(), spectrum.as_mut()) {
+                        match state.kind {
+                            ArrayKind::Mz => spec.mz = Some(state),
+                            ArrayKind::Intensity => spec.intensity = Some(state),
+                            ArrayKind::Other => {}
+                        }
+                    }
+                }
+                "spectrum" => {
+                    if let Some(spec) = spectrum.take() {
+                        if !feed(spec) {
+                            return Ok(());
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(())
+}
+
+/// Decode the binary arrays of a parsed spectrum.
+fn decode_spectrum(raw: RawSpectrum) -> Result<Spectrum> {
+    let decode = |array: Option<ArrayState>| match array {
+        Some(state) => decode_array(&state)
+            .map_err(|msg| message_error(format!("spectrum {}: {}", raw.index, msg))),
+        None => Ok(Vec::new()),
+    };
+    Ok(Spectrum {
+        index: raw.index,
+        ms_level: raw.ms_level,
+        rt: raw.rt,
+        mz: decode(raw.mz)?,
+        intensity: decode(raw.intensity)?,
+    })
+}
+
+fn write_spectrum(writer: &mut DatasetWriter, spec: &Spectrum) -> Result<()> {
+    if spec.mz.len() != spec.intensity.len() {
+        return Err(message_error(format!(
+            "spectrum {}: {} m/z values but {} intensities",
+            spec.index,
+            spec.mz.len(),
+            spec.intensity.len()
+        )));
+    }
+    let n = spec.mz.len();
+    let intensity: Vec<f32> = spec.intensity.iter().map(|&x| x as f32).collect();
+
+    writer.write_at(0, &vec![spec.index; n])?;
+    writer.write_at(1, &spec.mz)?;
+    writer.write_at(2, &intensity)?;
+    writer.write_at(3, &vec![spec.rt; n])?;
+    writer.write_at(4, &vec![spec.ms_level; n])?;
+    Ok(())
+}
+
+/// Decode a base64 (optionally zlib-compressed) little-endian array into f64 values.
+fn decode_array(state: &ArrayState) -> std::result::Result<Vec<f64>, String> {
+    let mut bytes = base64::engine::general_purpose::STANDARD
+        .decode(&state.base64)
+        .map_err(|e| format!("invalid base64: {}", e))?;
+
+    if state.zlib {
+        let mut inflated = Vec::new();
+        ZlibDecoder::new(&bytes[..])
+            .read_to_end(&mut inflated)
+            .map_err(|e| format!("invalid zlib data: {}", e))?;
+        bytes = inflated;
+    }
+
+    let width = match state.encoding {
+        Encoding::Float32 | Encoding::Int32 => 4,
+        Encoding::Float64 | Encoding::Int64 => 8,
+    };
+    if bytes.len() % width != 0 {
+        return Err(format!(
+            "array size {} is not a multiple of {}",
+            bytes.len(),
+            width
+        ));
+    }
+
+    Ok(bytes
+        .chunks_exact(width)
+        .map(|b| match state.encoding {
+            Encoding::Float32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Encoding::Int32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Encoding::Float64 => f64::from_le_bytes(b.try_into().unwrap()),
+            Encoding::Int64 => i64::from_le_bytes(b.try_into().unwrap()) as f64,
+        })
+        .collect())
+}
+
+fn attr(e: &BytesStart, name: &str) -> Result<Option<String>> {
+    e.try_get_attribute(name)
+        .map(|a| a.map(|a| a.value.into_owned()))
+        .map_err(|err| message_error(format!("invalid attribute: {}", err)))
+}
+
+fn message_error(message: String) -> MmappetError {
+    MmappetError::Import {
+        path: None,
+        offset: None,
+        message,
+    }
+}
+
+fn import_error<E: std::fmt::Display>(position: u64, err: E) -> MmappetError {
+    MmappetError::Import {
+        path: None,
+        offset: Some(position),
+        message: err.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+
+    fn encode_f64(values: &[f64], zlib: bool) -> String {
+        let mut bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        if zlib {
+            let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&bytes).unwrap();
+            bytes = encoder.finish().unwrap();
+        }
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    }
+
+    fn encode_f32(values: &[f32]) -> String {
+        let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    }
+
+    #[test]
+    fn test_import_mzml() {
+        let doc = format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<mzML><run><spectrumList count="2">
+  <spectrum index="0" id="scan=1" defaultArrayLength="2">
+    <cvParam cvRef="MS" accession="MS:1000511" name="ms level" value="1"/>
+    <scanList><scan>
+      <cvParam cvRef="MS" accession="MS:1000016" name="scan start time" value="0.5" unitAccession="UO:0000031"/>
+    </scan></scanList>
+    <binaryDataArrayList count="2">
+      <binaryDataArray>
+        <cvParam cvRef="MS" accession="MS:1000523" name="64-bit float"/>
+        <cvParam cvRef="MS" accession="MS:1000574" name="zlib compression"/>
+        <cvParam cvRef="MS" accession="MS:1000514" name="m/z array"/>
+        <binary>{}</binary>
+      </binaryDataArray>
+      <binaryDataArray>
+        <cvParam cvRef="MS" accession="MS:1000521" name="32-bit float"/>
+        <cvParam cvRef="MS" accession="MS:1000576" name="no compression"/>
+        <cvParam cvRef="MS" accession="MS:1000515" name="intensity array"/>
+        <binary>{}</binary>
+      </binaryDataArray>
+    </binaryDataArrayList>
+  </spectrum>
+  <spectrum index="1" id="scan=2" defaultArrayLength="1">
+    <cvParam cvRef="MS" accession="MS:1000511" name="ms level" value="2"/>
+    <cvParam cvRef="MS" accession="MS:1000016" name="scan start time" value="31.5" unitAccession="UO:0000010"/>
+    <binaryDataArrayList count="2">
+      <binaryDataArray>
+        <cvParam cvRef="MS" accession="MS:1000523" name="64-bit float"/>
+        <cvParam cvRef="MS" accession="MS:1000514" name="m/z array"/>
+        <binary>{}</binary>
+      </binaryDataArray>
+      <binaryDataArray>
+        <cvParam cvRef="MS" accession="MS:1000521" name="32-bit float"/>
+        <cvParam cvRef="MS" accession="MS:1000515" name="intensity array"/>
+        <binary>{}</binary>
+      </binaryDataArray>
+    </binaryDataArrayList>
+  </spectrum>
+</spectrumList></run></mzML>"#,
+            encode_f64(&[400.5, 401.25], true),
+            encode_f32(&[10.0, 20.0]),
+            encode_f64(&[250.125], false),
+            encode_f32(&[5.5]),
+        );
+
+        let out = std::env::temp_dir().join(format!("mmappet-mzml-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&out);
+        assert_eq!(import_mzml_from(doc.as_bytes(), &out).unwrap(), 3);
+
+        let ds = crate::Dataset::open(&out).unwrap();
+        assert_eq!(ds.get::<u32>("spectrum_index").unwrap(), &[0, 0, 1]);
+        assert_eq!(ds.get::<f64>("mz").unwrap(), &[400.5, 401.25, 250.125]);
+        assert_eq!(ds.get::<f32>("intensity").unwrap(), &[10.0, 20.0, 5.5]);
+        assert_eq!(ds.get::<f64>("rt").unwrap(), &[30.0, 30.0, 31.5]);
+        assert_eq!(ds.get::<u8>("ms_level").unwrap(), &[1, 1, 2]);
+
+        std::fs::remove_dir_all(&out).unwrap();
+    }
+}
@@ -0,0 +1,755 @@
+//! NumPy `.npy` files (one column) and `.npz` archives (one `.npy` file per
+//! column).
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+
+use crate::column::{swap_bytes, Column, TypedArrayView};
+use crate::dataset::Dataset;
+use crate::dtype::DType;
+use crate::error::{MmappetError, Result};
+use crate::schema::Schema;
+use crate::storage::Storage;
+use crate::writer::{create_atomically, view_bytes, DatasetWriter};
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+/// Rows converted at a time.
+const NPY_CHUNK_ROWS: usize = 1 << 16;
+/// Byte order mark of multi-byte NumPy dtypes on this machine.
+const NATIVE: char = if cfg!(target_endian = "little") { '<' } else { '>' };
+
+const LOCAL_HEADER: u64 = 0x0403_4b50;
+const CENTRAL_HEADER: u64 = 0x0201_4b50;
+const END_OF_CENTRAL: u64 = 0x0605_4b50;
+const ZIP64_END: u64 = 0x0606_4b50;
+const ZIP64_LOCATOR: u64 = 0x0706_4b50;
+/// Value of 32-bit zip fields whose value is in the zip64 extra field.
+const ZIP64_MARK: u64 = 0xFFFF_FFFF;
+/// DOS date of 1980-01-01, the earliest a zip entry can have.
+const DOS_EPOCH: u64 = 0x21;
+
+type Npy<T> = std::result::Result<T, String>;
+
+/// A column as it's written to a `.npy` file.
+struct NpyColumn<'d> {
+    column: &'d Column,
+    header: Vec<u8>,
+    /// Code points per value of text columns, which NumPy stores as UCS-4.
+    chars: usize,
+    /// Bytes of the header and values.
+    len: u64,
+}
+
+impl<'d> NpyColumn<'d> {
+    fn new(column: &'d Column, name: &str) -> Result<Self> {
+        let dtype = column.dtype();
+        let mut chars = 0;
+        let code = match dtype {
+            DType::Bool => "b1",
+            DType::UInt8 => "u1",
+            DType::Int8 => "i1",
+            DType::UInt16 => "u2",
+            DType::Int16 => "i2",
+            DType::UInt32 => "u4",
+            DType::Int32 => "i4",
+            DType::UInt64 => "u8",
+            DType::Int64 => "i8",
+            DType::Float16 => "f2",
+            DType::Float32 => "f4",
+            DType::Float64 => "f8",
+            DType::TimestampMillis => "M8[ms]",
+            DType::TimestampMicros => "M8[us]",
+            DType::Utf8 => {
+                let strs = column.as_str_array().unwrap();
+                chars = strs.iter().map(|s| s.chars().count()).max().unwrap_or(0);
+                "U"
+            }
+            DType::Categorical => {
+                let labels = column.as_categorical().unwrap();
+                chars = labels.categories().iter().map(|s| s.chars().count()).max().unwrap_or(0);
+                "U"
+            }
+            DType::Custom(_) => {
+                return Err(MmappetError::InvalidDType {
+                    dtype: dtype.as_str().to_string(),
+                    message: format!("column '{}' has no NumPy dtype", name),
+                })
+            }
+        };
+        let descr = match dtype {
+            DType::Bool | DType::UInt8 | DType::Int8 => format!("|{}", code),
+            // NumPy has no zero-length text
+            DType::Utf8 | DType::Categorical => {
+                chars = chars.max(1);
+                format!("{}U{}", NATIVE, chars)
+            }
+            _ => format!("{}{}", NATIVE, code),
+        };
+        let mut shape = vec![column.len()];
+        shape.extend(column.shape());
+        let header = npy_header(&descr, &shape);
+        let size = match chars {
+            0 => dtype.size_bytes(),
+            chars => chars * 4,
+        };
+        let len = header.len() as u64 + (column.len() * column.width() * size) as u64;
+        Ok(NpyColumn {
+            column,
+            header,
+            chars,
+            len,
+        })
+    }
+
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&self.header)?;
+        let rows = self.column.len();
+        let mut buf = Vec::new();
+        for start in (0..rows).step_by(NPY_CHUNK_ROWS) {
+            let array = self.column.slice_typed_array(start..rows.min(start + NPY_CHUNK_ROWS));
+            buf.clear();
+            match &array {
+                TypedArrayView::Utf8(strs) => {
+                    strs.iter().for_each(|s| push_ucs4(&mut buf, s, self.chars));
+                    out.write_all(&buf)?;
+                }
+                TypedArrayView::Categorical(labels) => {
+                    labels.iter().for_each(|s| push_ucs4(&mut buf, s, self.chars));
+                    out.write_all(&buf)?;
+                }
+                _ => out.write_all(&view_bytes(&array))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Header of a version 1.0 `.npy` file, padded so that the values start at
+/// a multiple of 64 bytes.
+fn npy_header(descr: &str, shape: &[usize]) -> Vec<u8> {
+    let dims: Vec<String> = shape.iter().map(usize::to_string).collect();
+    let shape = match dims.len() {
+        1 => format!("({},)", dims[0]),
+        _ => format!("({})", dims.join(", ")),
+    };
+    let dict = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}", descr, shape);
+    let padding = (64 - (MAGIC.len() + 4 + dict.len() + 1) % 64) % 64;
+    let mut header = MAGIC.to_vec();
+    header.extend([1, 0]);
+    header.extend(((dict.len() + padding + 1) as u16).to_le_bytes());
+    header.extend(dict.bytes());
+    header.resize(header.len() + padding, b' ');
+    header.push(b'\n');
+    header
+}
+
+/// Append `text` as `chars` UCS-4 code points, padded with zeros.
+fn push_ucs4(buf: &mut Vec<u8>, text: &str, chars: usize) {
+    let start = buf.len();
+    for c in text.chars() {
+        buf.extend_from_slice(&(c as u32).to_ne_bytes());
+    }
+    buf.resize(start + chars * 4, 0);
+}
+
+/// Append the `bytes` lowest bytes of `value`, little-endian as in zip files.
+fn put(buf: &mut Vec<u8>, value: u64, bytes: usize) {
+    buf.extend_from_slice(&value.to_le_bytes()[..bytes]);
+}
+
+/// Append the fields zip local and central headers share, from the version
+/// needed to extract to the length of the extra field, for a stored entry.
+fn put_entry_fields(buf: &mut Vec<u8>, name: &str, crc: u32, len: u64, extra: &[u8]) {
+    put(buf, if extra.is_empty() { 20 } else { 45 }, 2);
+    put(buf, 0, 2);
+    put(buf, 0, 2);
+    put(buf, 0, 2);
+    put(buf, DOS_EPOCH, 2);
+    put(buf, crc as u64, 4);
+    put(buf, len.min(ZIP64_MARK), 4);
+    put(buf, len.min(ZIP64_MARK), 4);
+    put(buf, name.len() as u64, 2);
+    put(buf, extra.len() as u64, 2);
+}
+
+/// Zip64 extra field holding the values of `fields` too large for 32 bits.
+fn zip64_extra(fields: &[u64]) -> Vec<u8> {
+    let large: Vec<u64> = fields.iter().copied().filter(|&v| v >= ZIP64_MARK).collect();
+    let mut extra = Vec::new();
+    if !large.is_empty() {
+        put(&mut extra, 1, 2);
+        put(&mut extra, large.len() as u64 * 8, 2);
+        large.iter().for_each(|&v| put(&mut extra, v, 8));
+    }
+    extra
+}
+
+/// Feeds written bytes to a CRC-32.
+struct CrcWriter<W> {
+    inner: W,
+    hasher: crc32fast::Hasher,
+}
+
+impl<W: Write> Write for CrcWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Dataset {
+    /// Write the column `name` to the NumPy file `path`, with the row count
+    /// as the first dimension and the shape of array columns after it.
+    ///
+    /// Text and categorical columns are written as fixed-width `U` text as
+    /// wide as their longest value, timestamps as `datetime64[ms]` or
+    /// `datetime64[us]`. NumPy arrays have no nulls, so nulls are written
+    /// as the zeros or empty text they're stored as.
+    ///
+    /// Returns an error if the column doesn't exist or has a custom dtype.
+    pub fn export_npy<P: AsRef<Path>>(&self, name: &str, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let column = self.column(name).ok_or_else(|| self.column_not_found(name))?;
+        let npy = NpyColumn::new(column, name)?;
+        let file = File::create(path).map_err(|e| MmappetError::io(path, e))?;
+        let mut out = BufWriter::new(file);
+        npy.write_to(&mut out)
+            .and_then(|_| out.flush())
+            .map_err(|e| MmappetError::io(path, e))
+    }
+
+    /// Write every column to the NumPy archive `path`, as the uncompressed
+    /// `<name>.npy` entries `numpy.savez` writes; see
+    /// [`export_npy`](Self::export_npy) for the arrays.
+    ///
+    /// Columns are copied chunk by chunk, so datasets larger than memory can
+    /// be exported. Returns an error if a column has a custom dtype.
+    pub fn export_npz<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let columns = self
+            .column_names()
+            .map(|name| {
+                let column = self.column(name).ok_or_else(|| self.column_not_found(name))?;
+                Ok((format!("{}.npy", name), NpyColumn::new(column, name)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let file = File::create(path).map_err(|e| MmappetError::io(path, e))?;
+        self.write_npz(BufWriter::new(file), &columns)
+            .map_err(|e| MmappetError::io(path, e))
+    }
+
+    fn write_npz(
+        &self,
+        mut out: BufWriter<File>,
+        columns: &[(String, NpyColumn<'_>)],
+    ) -> io::Result<()> {
+        let mut central = Vec::new();
+        let mut position = 0;
+        for (name, npy) in columns {
+            let extra = zip64_extra(&[npy.len, npy.len]);
+            let mut local = Vec::new();
+            put(&mut local, LOCAL_HEADER, 4);
+            put_entry_fields(&mut local, name, 0, npy.len, &extra);
+            local.extend(name.bytes());
+            local.extend(&extra);
+            out.write_all(&local)?;
+            let mut values = CrcWriter {
+                inner: &mut out,
+                hasher: crc32fast::Hasher::new(),
+            };
+            npy.write_to(&mut values)?;
+            let crc = values.hasher.finalize();
+            // The checksum is only known once the values are written
+            let end = position + local.len() as u64 + npy.len;
+            out.seek(SeekFrom::Start(position + 14))?;
+            out.write_all(&crc.to_le_bytes())?;
+            out.seek(SeekFrom::Start(end))?;
+
+            let extra = zip64_extra(&[npy.len, npy.len, position]);
+            put(&mut central, CENTRAL_HEADER, 4);
+            put(&mut central, 45, 2);
+            put_entry_fields(&mut central, name, crc, npy.len, &extra);
+            // Comment length, disk, internal and external attributes
+            put(&mut central, 0, 6);
+            put(&mut central, 0, 4);
+            put(&mut central, position.min(ZIP64_MARK), 4);
+            central.extend(name.bytes());
+            central.extend(&extra);
+            position = end;
+        }
+
+        let (count, size) = (columns.len() as u64, central.len() as u64);
+        if count >= 0xFFFF || size >= ZIP64_MARK || position >= ZIP64_MARK {
+            let record = position + size;
+            put(&mut central, ZIP64_END, 4);
+            put(&mut central, 44, 8);
+            put(&mut central, 45, 2);
+            put(&mut central, 45, 2);
+            put(&mut central, 0, 8);
+            put(&mut central, count, 8);
+            put(&mut central, count, 8);
+            put(&mut central, size, 8);
+            put(&mut central, position, 8);
+            put(&mut central, ZIP64_LOCATOR, 4);
+            put(&mut central, 0, 4);
+            put(&mut central, record, 8);
+            put(&mut central, 1, 4);
+        }
+        put(&mut central, END_OF_CENTRAL, 4);
+        put(&mut central, 0, 4);
+        put(&mut central, count.min(0xFFFF), 2);
+        put(&mut central, count.min(0xFFFF), 2);
+        put(&mut central, size.min(ZIP64_MARK), 4);
+        put(&mut central, position.min(ZIP64_MARK), 4);
+        put(&mut central, 0, 2);
+        out.write_all(&central)?;
+        out.flush()
+    }
+}
+
+/// Text elements of a NumPy array.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Text {
+    /// `U`: UCS-4 code points.
+    Ucs4,
+    /// `S`: bytes, read as UTF-8.
+    Bytes,
+}
+
+/// The header of a `.npy` file.
+#[derive(Debug)]
+struct NpyHeader {
+    dtype: DType,
+    /// Bytes per element.
+    size: usize,
+    /// Elements are in the other byte order than this machine's.
+    swap: bool,
+    text: Option<Text>,
+    shape: Vec<usize>,
+    fortran_order: bool,
+}
+
+impl NpyHeader {
+    fn rows(&self) -> usize {
+        self.shape.first().copied().unwrap_or(1)
+    }
+
+    fn row_size(&self) -> usize {
+        self.shape.iter().skip(1).product::<usize>() * self.size
+    }
+}
+
+/// Read the header of a `.npy` file, leaving `reader` at its values.
+fn read_header(reader: &mut dyn Read) -> Npy<NpyHeader> {
+    let mut read = |len: usize| -> Npy<Vec<u8>> {
+        let mut buf = vec![0; len];
+        reader.read_exact(&mut buf).map_err(|e| e.to_string())?;
+        Ok(buf)
+    };
+    let start = read(8)?;
+    if &start[..6] != MAGIC {
+        return Err("not a .npy file".into());
+    }
+    let len = match start[6] {
+        1 => u16::from_le_bytes(read(2)?.try_into().unwrap()) as usize,
+        2 | 3 => u32::from_le_bytes(read(4)?.try_into().unwrap()) as usize,
+        major => return Err(format!(".npy version {} isn't supported", major)),
+    };
+    let dict = String::from_utf8(read(len)?).map_err(|_| "header isn't text")?;
+    let value = |key: &str| -> Npy<&str> {
+        let start = dict
+            .find(&format!("'{}':", key))
+            .ok_or_else(|| format!("header lacks '{}'", key))?;
+        Ok(dict[start + key.len() + 3..].trim_start())
+    };
+
+    let descr = value("descr")?;
+    let quote = descr.chars().next().filter(|&c| c == '\'' || c == '"');
+    let descr = quote
+        .and_then(|quote| descr[1..].split(quote).next())
+        .ok_or("structured dtypes aren't supported")?;
+    let (order, code) = match descr.chars().next() {
+        Some(c @ ('<' | '>' | '|' | '=')) => (c, &descr[1..]),
+        _ => ('=', descr),
+    };
+    let (dtype, size, text) = match code {
+        "b1" => (DType::Bool, 1, None),
+        "u1" => (DType::UInt8, 1, None),
+        "i1" => (DType::Int8, 1, None),
+        "u2" => (DType::UInt16, 2, None),
+        "i2" => (DType::Int16, 2, None),
+        "u4" => (DType::UInt32, 4, None),
+        "i4" => (DType::Int32, 4, None),
+        "u8" => (DType::UInt64, 8, None),
+        "i8" => (DType::Int64, 8, None),
+        "f2" => (DType::Float16, 2, None),
+        "f4" => (DType::Float32, 4, None),
+        "f8" => (DType::Float64, 8, None),
+        "M8[ms]" => (DType::TimestampMillis, 8, None),
+        "M8[us]" => (DType::TimestampMicros, 8, None),
+        _ => {
+            let chars = code.get(1..).and_then(|n| n.parse::<usize>().ok()).filter(|&n| n > 0);
+            match (code.as_bytes()[0], chars) {
+                (b'U', Some(chars)) => (DType::Utf8, chars * 4, Some(Text::Ucs4)),
+                (b'S', Some(chars)) => (DType::Utf8, chars, Some(Text::Bytes)),
+                _ => return Err(format!("dtype '{}' has no mmappet dtype", descr)),
+            }
+        }
+    };
+
+    let shape = value("shape")?
+        .strip_prefix('(')
+        .and_then(|shape| shape.split(')').next())
+        .ok_or("shape isn't a tuple")?;
+    let shape = shape
+        .split(',')
+        .map(str::trim)
+        .filter(|dim| !dim.is_empty())
+        .map(|dim| dim.parse().map_err(|_| format!("invalid dimension '{}'", dim)))
+        .collect::<Npy<Vec<usize>>>()?;
+    Ok(NpyHeader {
+        dtype,
+        size,
+        swap: (order == '<' || order == '>') && order != NATIVE,
+        text,
+        shape,
+        fortran_order: value("fortran_order")?.starts_with("True"),
+    })
+}
+
+/// Elements of `shape` stored column-major, reordered row-major.
+fn c_order(bytes: &[u8], shape: &[usize], size: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut index = vec![0; shape.len()];
+    for _ in 0..bytes.len() / size {
+        let mut offset = 0;
+        for (&i, &dim) in index.iter().zip(shape).rev() {
+            offset = offset * dim + i;
+        }
+        out.extend_from_slice(&bytes[offset * size..(offset + 1) * size]);
+        for (i, &dim) in index.iter_mut().zip(shape).rev() {
+            *i += 1;
+            if *i < dim {
+                break;
+            }
+            *i = 0;
+        }
+    }
+    out
+}
+
+/// Decode a text element, dropping the zeros it's padded with.
+fn decode_text(item: &[u8], text: Text) -> Npy<String> {
+    match text {
+        Text::Ucs4 => {
+            let codes: Vec<u32> = item
+                .chunks_exact(4)
+                .map(|c| u32::from_ne_bytes(c.try_into().unwrap()))
+                .collect();
+            let end = codes.iter().rposition(|&c| c != 0).map_or(0, |i| i + 1);
+            codes[..end]
+                .iter()
+                .map(|&c| char::from_u32(c).ok_or_else(|| format!("invalid code point {:#x}", c)))
+                .collect()
+        }
+        Text::Bytes => {
+            let end = item.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+            String::from_utf8(item[..end].to_vec()).map_err(|_| "text isn't UTF-8".into())
+        }
+    }
+}
+
+/// An array to import: a `.npy` file, or an entry of a `.npz` archive.
+struct NpyArray<'m> {
+    name: &'m str,
+    /// Zip compression method: 0 for stored, 8 for deflate.
+    method: u64,
+    data: &'m [u8],
+}
+
+impl<'m> NpyArray<'m> {
+    fn reader(&self) -> Npy<Box<dyn Read + 'm>> {
+        match self.method {
+            0 => Ok(Box::new(self.data)),
+            #[cfg(feature = "deflate")]
+            8 => Ok(Box::new(flate2::read::DeflateDecoder::new(self.data))),
+            #[cfg(not(feature = "deflate"))]
+            8 => Err("reading compressed arrays needs mmappet's `deflate` feature".into()),
+            method => Err(format!("zip compression method {} isn't supported", method)),
+        }
+    }
+}
+
+/// Read `len` bytes at `pos` of a zip archive as a little-endian number.
+fn le(bytes: &[u8], pos: usize, len: usize) -> Npy<u64> {
+    let field = bytes.get(pos..pos + len).ok_or("zip archive is truncated")?;
+    Ok(field.iter().rev().fold(0, |value, &b| value << 8 | b as u64))
+}
+
+/// The `.npy` files of a zip archive, in the order of its central directory.
+fn zip_arrays(bytes: &[u8]) -> Npy<Vec<NpyArray<'_>>> {
+    let last = bytes.len().checked_sub(22).ok_or("not a zip archive")?;
+    // The end record is followed by a comment of at most 64 KiB
+    let end = (last.saturating_sub(0xFFFF)..=last)
+        .rev()
+        .find(|&pos| le(bytes, pos, 4) == Ok(END_OF_CENTRAL))
+        .ok_or("not a zip archive")?;
+    let (mut count, mut pos) = (le(bytes, end + 10, 2)?, le(bytes, end + 16, 4)? as usize);
+    if end >= 20 && le(bytes, end - 20, 4)? == ZIP64_LOCATOR {
+        let record = le(bytes, end - 12, 8)? as usize;
+        if le(bytes, record, 4)? != ZIP64_END {
+            return Err("zip64 end record is missing".into());
+        }
+        count = le(bytes, record + 32, 8)?;
+        pos = le(bytes, record + 48, 8)? as usize;
+    }
+
+    let mut arrays = Vec::new();
+    for _ in 0..count {
+        if le(bytes, pos, 4)? != CENTRAL_HEADER {
+            return Err("zip central directory is malformed".into());
+        }
+        let method = le(bytes, pos + 10, 2)?;
+        let name_len = le(bytes, pos + 28, 2)? as usize;
+        let extra_len = le(bytes, pos + 30, 2)? as usize;
+        let comment_len = le(bytes, pos + 32, 2)? as usize;
+        let name = bytes.get(pos + 46..pos + 46 + name_len).ok_or("zip archive is truncated")?;
+        let name = std::str::from_utf8(name).map_err(|_| "zip entry name isn't UTF-8")?;
+
+        // Uncompressed size, compressed size and offset, from the zip64
+        // extra field if they don't fit in 32 bits
+        let mut fields = [
+            le(bytes, pos + 24, 4)?,
+            le(bytes, pos + 20, 4)?,
+            le(bytes, pos + 42, 4)?,
+        ];
+        let (mut extra, extra_end) = (pos + 46 + name_len, pos + 46 + name_len + extra_len);
+        while extra + 4 <= extra_end {
+            let (id, len) = (le(bytes, extra, 2)?, le(bytes, extra + 2, 2)? as usize);
+            let mut field = extra + 4;
+            for value in fields.iter_mut().filter(|_| id == 1) {
+                if *value == ZIP64_MARK {
+                    *value = le(bytes, field, 8)?;
+                    field += 8;
+                }
+            }
+            extra += 4 + len;
+        }
+        pos = extra_end + comment_len;
+        if name.ends_with('/') {
+            continue;
+        }
+        let name = name
+            .strip_suffix(".npy")
+            .ok_or_else(|| format!("zip entry '{}' isn't a .npy file", name))?;
+
+        let [_, size, local] = fields.map(|v| v as usize);
+        if le(bytes, local, 4)? != LOCAL_HEADER {
+            return Err(format!("zip entry '{}' is malformed", name));
+        }
+        let (name_len, extra_len) = (le(bytes, local + 26, 2)?, le(bytes, local + 28, 2)?);
+        let start = local + 30 + name_len as usize + extra_len as usize;
+        let data = bytes.get(start..start + size).ok_or("zip archive is truncated")?;
+        arrays.push(NpyArray { name, method, data });
+    }
+    Ok(arrays)
+}
+
+/// Create the dataset `dir` with a column per array of the NumPy file
+/// `path`, and open it.
+fn import_arrays(path: &Path, dir: &Path, arrays: &[NpyArray<'_>]) -> Result<Dataset> {
+    let invalid = |name: &str, message: String| MmappetError::InvalidEncoding {
+        path: path.to_path_buf(),
+        message: format!("array '{}': {}", name, message),
+    };
+    let mut headers = Vec::with_capacity(arrays.len());
+    for array in arrays {
+        let header = array.reader().and_then(|mut reader| read_header(&mut reader));
+        headers.push(header.map_err(|e| invalid(array.name, e))?);
+    }
+    let rows = headers.first().map_or(0, NpyHeader::rows);
+    let pairs: Vec<(&str, DType)> =
+        arrays.iter().zip(&headers).map(|(a, h)| (a.name, h.dtype)).collect();
+    let mut schema = Schema::new(&pairs)?;
+    for (array, header) in arrays.iter().zip(&headers) {
+        if header.rows() != rows {
+            let message = format!("has {} rows, the first array {}", header.rows(), rows);
+            return Err(invalid(array.name, message));
+        }
+        if header.shape.len() > 1 {
+            schema.set_shape(array.name, &header.shape[1..])?;
+        }
+    }
+
+    create_atomically(dir, |tmp_dir| {
+        let mut writer = DatasetWriter::create(tmp_dir, &schema)?;
+        let mut buf = Vec::new();
+        for (index, (array, header)) in arrays.iter().zip(&headers).enumerate() {
+            let mut reader = array.reader().map_err(|e| invalid(array.name, e))?;
+            read_header(&mut reader).map_err(|e| invalid(array.name, e))?;
+            // Column-major arrays are reordered in one piece
+            let reorder = header.fortran_order && header.shape.len() > 1;
+            let chunk_rows = if reorder { rows } else { NPY_CHUNK_ROWS };
+            let mut done = 0;
+            while done < rows {
+                let count = chunk_rows.min(rows - done);
+                buf.resize(count * header.row_size(), 0);
+                reader.read_exact(&mut buf).map_err(|e| invalid(array.name, e.to_string()))?;
+                if reorder {
+                    buf = c_order(&buf, &header.shape, header.size);
+                }
+                match header.text {
+                    None => {
+                        if header.swap {
+                            swap_bytes(&mut buf, header.size);
+                        }
+                        writer.write_bytes(index, &buf, count)?;
+                    }
+                    Some(text) => {
+                        if header.swap && text == Text::Ucs4 {
+                            swap_bytes(&mut buf, 4);
+                        }
+                        let strings = buf
+                            .chunks(header.size)
+                            .map(|item| decode_text(item, text))
+                            .collect::<Npy<Vec<String>>>()
+                            .map_err(|e| invalid(array.name, e))?;
+                        writer.write_strs(index, strings.iter().map(String::as_str))?;
+                    }
+                }
+                done += count;
+            }
+        }
+        writer.finish()
+    })?;
+    Dataset::open(dir)
+}
+
+impl Dataset {
+    /// Create the dataset `dir` from the NumPy file `path`, with one column
+    /// named after the file, and open it.
+    ///
+    /// The first dimension of the array is the rows, further ones the shape
+    /// of an array column. Booleans, integers, floats, `datetime64[ms]` /
+    /// `datetime64[us]` and `U` / UTF-8 `S` text keep their dtype, in either
+    /// byte order and memory layout. Returns an error for other dtypes, e.g.
+    /// pickled objects or records.
+    pub fn from_npy<P: AsRef<Path>, Q: AsRef<Path>>(path: P, dir: Q) -> Result<Dataset> {
+        let path = path.as_ref();
+        let map = Storage::open(path, false)?;
+        let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("values");
+        let array = NpyArray {
+            name,
+            method: 0,
+            data: &map[..],
+        };
+        import_arrays(path, dir.as_ref(), &[array])
+    }
+
+    /// Create the dataset `dir` from the NumPy archive `path`, with a column
+    /// per array, and open it.
+    ///
+    /// Arrays are read as by [`from_npy`](Self::from_npy) and must have the
+    /// same number of rows. Archives of `numpy.savez_compressed` need the
+    /// `deflate` feature.
+    pub fn from_npz<P: AsRef<Path>, Q: AsRef<Path>>(path: P, dir: Q) -> Result<Dataset> {
+        let path = path.as_ref();
+        let map = Storage::open(path, false)?;
+        let arrays = zip_arrays(&map).map_err(|message| MmappetError::InvalidEncoding {
+            path: path.to_path_buf(),
+            message,
+        })?;
+        import_arrays(path, dir.as_ref(), &arrays)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_npz_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("mmappet-npz-{}", std::process::id()));
+        let file = dir.with_extension("npz");
+        let copy = dir.with_extension("copy");
+        let mut schema = Schema::new(&[
+            ("tof", DType::UInt32),
+            ("xy", DType::Float32),
+            ("decoy", DType::Bool),
+            ("peptide", DType::Utf8),
+            ("protein", DType::Categorical),
+            ("time", DType::TimestampMicros),
+        ])
+        .unwrap();
+        schema.set_shape("xy", &[2]).unwrap();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write("tof", &[7u32, 8, 9]).unwrap();
+        writer.write("xy", &[1.5f32, -2.0, 0.0, 1.0, 3.25, 4.0]).unwrap();
+        writer.write_bytes(2, &[1, 0, 1], 3).unwrap();
+        writer.write_str("peptide", &["PEP", "", "Ωmega"]).unwrap();
+        writer.write_categorical("protein", &["P1", "P2", "P1"]).unwrap();
+        writer.write_timestamps("time", &[0, 1_500, -1]).unwrap();
+        writer.finish().unwrap();
+        let ds = Dataset::open(&dir).unwrap();
+
+        ds.export_npz(&file).unwrap();
+        let out = Dataset::from_npz(&file, &copy).unwrap();
+        assert_eq!(out.column_names().collect::<Vec<_>>(), ds.column_names().collect::<Vec<_>>());
+        for name in ["tof", "xy", "decoy", "time"] {
+            assert_eq!(out[name].dtype(), ds[name].dtype());
+            assert_eq!(out[name].shape(), ds[name].shape());
+            assert_eq!(out[name].as_bytes(), ds[name].as_bytes());
+        }
+        let peptides = ["PEP", "", "Ωmega"];
+        assert_eq!(out["peptide"].iter_str().unwrap().collect::<Vec<_>>(), peptides);
+        assert_eq!(out["protein"].iter_str().unwrap().collect::<Vec<_>>(), ["P1", "P2", "P1"]);
+        std::fs::remove_dir_all(&copy).unwrap();
+
+        let npy = dir.with_extension("npy");
+        ds.export_npy("xy", &npy).unwrap();
+        let bytes = std::fs::read(&npy).unwrap();
+        assert_eq!(bytes.len(), 128 + 24);
+        assert!(std::str::from_utf8(&bytes[10..128]).unwrap().contains("'shape': (3, 2), "));
+        let out = Dataset::from_npy(&npy, &copy).unwrap();
+        let name = npy.file_stem().unwrap().to_str().unwrap();
+        assert_eq!(out.column_names().collect::<Vec<_>>(), [name]);
+        assert_eq!(out[name].shape(), [2]);
+        assert_eq!(out[name].as_bytes(), ds["xy"].as_bytes());
+        std::fs::remove_dir_all(&copy).unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_file(&file).unwrap();
+        std::fs::remove_file(&npy).unwrap();
+    }
+
+    #[test]
+    fn test_npy_decoding() {
+        let mut bytes = npy_header(">i2", &[2, 3]);
+        let order = bytes.windows(5).position(|w| w == b"False").unwrap();
+        bytes[order..order + 5].copy_from_slice(b"True ");
+        // Column-major [[1, 2, 3], [4, 5, 6]]
+        for v in [1i16, 4, 2, 5, 3, 6] {
+            bytes.extend(v.to_be_bytes());
+        }
+        let mut reader = &bytes[..];
+        let header = read_header(&mut reader).unwrap();
+        assert_eq!((header.dtype, header.size, header.shape.as_slice()), (DType::Int16, 2, &[2, 3][..]));
+        assert!(header.fortran_order && header.swap == cfg!(target_endian = "little"));
+        let values = c_order(reader, &header.shape, 2);
+        let values: Vec<i16> = values.chunks(2).map(|v| i16::from_be_bytes([v[0], v[1]])).collect();
+        assert_eq!(values, [1, 2, 3, 4, 5, 6]);
+
+        assert_eq!(decode_text(b"ab\0\0", Text::Bytes).unwrap(), "ab");
+        let mut reader = &npy_header("|O", &[1])[..];
+        assert_eq!(read_header(&mut reader).unwrap_err(), "dtype '|O' has no mmappet dtype");
+        let mut reader = &npy_header("<U3", &[4])[..];
+        assert_eq!(read_header(&mut reader).unwrap().text, Some(Text::Ucs4));
+    }
+}
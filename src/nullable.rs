@@ -0,0 +1,260 @@
+//! Nullable columns, marked by optional validity bitmaps.
+//!
+//! `{N}.valid` next to column file `{N}.bin` holds one bit per row, least
+//! significant bit first, set for valid values and clear for nulls. Columns
+//! without such a file have no nulls. Null rows still hold a value in the
+//! column file, written as zero.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+
+use crate::column::Column;
+use crate::dataset::Dataset;
+use crate::dtype::MmappetType;
+use crate::error::{MmappetError, Result};
+use crate::expr::read_f64;
+use crate::stats::{ColumnStats, STATS_CHUNK_ROWS};
+use crate::storage::Storage;
+
+/// A column together with its validity bitmap, see [`Dataset::nullable`].
+pub struct NullableColumn<'a> {
+    column: &'a Column,
+    validity: Option<Storage>,
+}
+
+impl<'a> NullableColumn<'a> {
+    /// The column's values, including the placeholders of null rows.
+    pub fn column(&self) -> &'a Column {
+        self.column
+    }
+
+    /// Check if the column has a validity bitmap.
+    pub fn has_validity(&self) -> bool {
+        self.validity.is_some()
+    }
+
+    /// Get the number of rows.
+    pub fn len(&self) -> usize {
+        self.column.len()
+    }
+
+    /// Check if the column is empty.
+    pub fn is_empty(&self) -> bool {
+        self.column.is_empty()
+    }
+
+    /// Check if row `i` holds a value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is out of bounds.
+    pub fn is_valid(&self, i: usize) -> bool {
+        assert!(i < self.len(), "row {} out of bounds for length {}", i, self.len());
+        match &self.validity {
+            Some(bits) => bits[i / 8] & (1 << (i % 8)) != 0,
+            None => true,
+        }
+    }
+
+    /// Number of null rows.
+    pub fn null_count(&self) -> usize {
+        let Some(bits) = &self.validity else {
+            return 0;
+        };
+        let len = self.len();
+        let full = len / 8;
+        let mut valid: usize = bits[..full].iter().map(|b| b.count_ones() as usize).sum();
+        if !len.is_multiple_of(8) {
+            valid += (bits[full] & ((1 << (len % 8)) - 1)).count_ones() as usize;
+        }
+        len - valid
+    }
+
+    /// Iterate over the values, `None` for those of null rows.
+    ///
+    /// Returns `None` if the requested type doesn't match the column's dtype.
+    pub fn iter_opt<T: MmappetType>(&self) -> Option<impl Iterator<Item = Option<T>> + '_> {
+        let values = self.column.as_slice::<T>()?;
+        let width = self.column.width();
+        Some(
+            values
+                .iter()
+                .enumerate()
+                .map(move |(i, &value)| self.is_valid(i / width).then_some(value)),
+        )
+    }
+
+    /// Compute count, min, max, sum and spread of the non-null values, and
+    /// the number of nulls.
+    ///
+    /// Returns `None` for custom dtypes without a numeric value.
+    pub fn stats(&self) -> Option<ColumnStats> {
+        if self.validity.is_none() {
+            return self.column.stats();
+        }
+        let array = self.column.numeric_array()?;
+        let len = array.len();
+        let mut values = vec![0.0; STATS_CHUNK_ROWS.min(len)];
+        let mut stats = ColumnStats::default();
+        for start in (0..len).step_by(STATS_CHUNK_ROWS) {
+            let values = &mut values[..STATS_CHUNK_ROWS.min(len - start)];
+            read_f64(&array, start, values);
+            self.mask_nulls(start, values);
+            stats = stats.merge(ColumnStats::of(values));
+        }
+        stats.nulls = self.null_count();
+        Some(stats)
+    }
+
+    /// Set the values of null rows to NaN, where `values` were read from
+    /// value `start` on, e.g. by `read_f64`.
+    pub(crate) fn mask_nulls(&self, start: usize, values: &mut [f64]) {
+        if self.validity.is_none() {
+            return;
+        }
+        let width = self.column.width();
+        for (i, value) in values.iter_mut().enumerate() {
+            if !self.is_valid((start + i) / width) {
+                *value = f64::NAN;
+            }
+        }
+    }
+}
+
+impl Dataset {
+    /// Get a column with its validity bitmap, if it has one.
+    ///
+    /// Returns an error if the column doesn't exist or its bitmap doesn't
+    /// have one bit per row.
+    pub fn nullable(&self, name: &str) -> Result<NullableColumn<'_>> {
+        let column = self.mapped_column(name)?;
+        let path = self.validity_path(name)?;
+        if !path.exists() {
+            return Ok(NullableColumn {
+                column,
+                validity: None,
+            });
+        }
+
+        let bits = Storage::open(path, false)?;
+        let expected = column.len().div_ceil(8);
+        // Appends in progress extend the bitmaps of snapshots
+        if bits.len() < expected || (bits.len() > expected && self.commit().is_none()) {
+            return Err(MmappetError::InvalidValidity {
+                path: path.to_path_buf(),
+                column: name.to_string(),
+                actual: bits.len(),
+                expected,
+            });
+        }
+        Ok(NullableColumn {
+            column,
+            validity: Some(bits),
+        })
+    }
+}
+
+/// Path of the validity bitmap of column file `index` of the dataset in `dir`.
+pub(crate) fn validity_path(dir: &Path, index: usize) -> PathBuf {
+    dir.join(format!("{}.valid", index))
+}
+
+/// Validity bitmap built while writing a column.
+#[derive(Default)]
+pub(crate) struct ValidityBuilder {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl ValidityBuilder {
+    /// Append `count` valid rows.
+    pub(crate) fn push_valid(&mut self, count: usize) {
+        for i in self.len..self.len + count {
+            if i.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            self.bytes[i / 8] |= 1 << (i % 8);
+        }
+        self.len += count;
+    }
+
+    /// Mark row `i` as null.
+    pub(crate) fn set_null(&mut self, i: usize) {
+        self.bytes[i / 8] &= !(1 << (i % 8));
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+/// Mark rows `rows..rows + added` valid in the bitmap at `path`, which has
+/// one bit for each of the first `rows` rows. The file is replaced atomically.
+pub(crate) fn extend_validity(path: &Path, rows: usize, added: usize) -> Result<()> {
+    let mut bytes = fs::read(path).map_err(|e| MmappetError::io(path, e))?;
+    bytes.resize((rows + added).div_ceil(8), 0);
+    for i in rows..rows + added {
+        bytes[i / 8] |= 1 << (i % 8);
+    }
+    let tmp_path = path.with_extension("valid.tmp");
+    fs::write(&tmp_path, &bytes).map_err(|e| MmappetError::io(&tmp_path, e))?;
+    fs::rename(&tmp_path, path).map_err(|e| MmappetError::io(path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::column::TypedArrayView;
+    use crate::dtype::DType;
+    use crate::schema::Schema;
+    use crate::writer::DatasetWriter;
+    use ndarray::ArrayView1;
+
+    #[test]
+    fn test_nullable_column() {
+        let dir = std::env::temp_dir().join(format!("mmappet-nullable-{}", std::process::id()));
+        let schema = Schema::new(&[("id", DType::UInt32), ("charge", DType::Int8)]).unwrap();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        let ids: Vec<u32> = (0..10).collect();
+        writer.write::<u32>("id", &ids).unwrap();
+        writer.write::<i8>("charge", &[2, 3]).unwrap();
+        let charges: Vec<Option<i8>> = (0..8).map(|i| (i % 3 != 0).then_some(i)).collect();
+        writer.write_opt::<i8>("charge", &charges).unwrap();
+        assert_eq!(writer.finish().unwrap(), 10);
+        assert!(dir.join("1.valid").exists());
+        assert!(!dir.join("0.valid").exists());
+
+        let mut ds = Dataset::open(&dir).unwrap();
+        let id = ds.nullable("id").unwrap();
+        assert!(!id.has_validity());
+        assert_eq!(id.null_count(), 0);
+
+        let charge = ds.nullable("charge").unwrap();
+        let expected: Vec<Option<i8>> = [Some(2), Some(3)].into_iter().chain(charges).collect();
+        assert_eq!(charge.iter_opt::<i8>().unwrap().collect::<Vec<_>>(), expected);
+        assert!(charge.iter_opt::<u32>().is_none());
+        assert_eq!(charge.null_count(), 3);
+        let stats = charge.stats().unwrap();
+        assert_eq!((stats.count, stats.min, stats.max), (7, 1.0, 7.0));
+
+        // Appended rows are valid
+        let (more_ids, more_charges) = ([10u32], [-1i8]);
+        ds.append_batch(&[
+            ("id", TypedArrayView::UInt32(ArrayView1::from(&more_ids))),
+            ("charge", TypedArrayView::Int8(ArrayView1::from(&more_charges))),
+        ])
+        .unwrap();
+        let charge = ds.nullable("charge").unwrap();
+        assert_eq!((charge.len(), charge.null_count()), (11, 3));
+        assert!(charge.is_valid(10));
+
+        fs::write(dir.join("1.valid"), [0xFF]).unwrap();
+        assert!(matches!(
+            ds.nullable("charge"),
+            Err(MmappetError::InvalidValidity { .. })
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+//! Multi-threaded variants of column and dataset scans (`parallel` feature).
+//!
+//! Work is split into chunks of rows processed on rayon's global thread pool;
+//! results are identical to the single-threaded methods.
+
+use rayon::prelude::*;
+
+use crate::column::Column;
+use crate::dataset::Dataset;
+use crate::dtype::MmappetType;
+use crate::error::Result;
+use crate::expr::Expr;
+use crate::filter::FilteredView;
+use crate::histogram::{
+    checked_histogram, finite_range, histogram_bounds, numeric_array, range_histogram, Histogram,
+};
+use crate::stats::{range_stats, ColumnStats, STATS_PART_ROWS};
+
+/// Rows handled per task. Matches the zone size, so whole zones are pruned.
+const PAR_CHUNK_ROWS: usize = 1 << 16;
+
+impl Column {
+    /// Apply `f` to every value on multiple threads, keeping row order.
+    ///
+    /// Returns `None` if the requested type doesn't match the column's dtype.
+    pub fn par_map<T, U, F>(&self, f: F) -> Option<Vec<U>>
+    where
+        T: MmappetType + Sync,
+        U: Send,
+        F: Fn(T) -> U + Sync + Send,
+    {
+        let data = self.as_slice::<T>()?;
+        Some(
+            data.par_iter()
+                .with_min_len(PAR_CHUNK_ROWS)
+                .map(|&x| f(x))
+                .collect(),
+        )
+    }
+
+    /// [`Column::stats`] on multiple threads.
+    pub fn par_stats(&self) -> Option<ColumnStats> {
+        let array = self.numeric_array()?;
+        let len = array.len();
+        let parts: Vec<ColumnStats> = (0..len.div_ceil(STATS_PART_ROWS))
+            .into_par_iter()
+            .map(|i| range_stats(&array, i * STATS_PART_ROWS..((i + 1) * STATS_PART_ROWS).min(len)))
+            .collect();
+        Some(parts.into_iter().fold(ColumnStats::default(), ColumnStats::merge))
+    }
+
+    /// [`Column::histogram`] on multiple threads.
+    pub fn par_histogram(&self, bins: usize) -> Result<Histogram> {
+        let array = numeric_array(self)?;
+        let len = array.len();
+        let range = (0..len.div_ceil(PAR_CHUNK_ROWS))
+            .into_par_iter()
+            .filter_map(|i| {
+                let rows = i * PAR_CHUNK_ROWS..((i + 1) * PAR_CHUNK_ROWS).min(len);
+                finite_range(&array, rows)
+            })
+            .reduce_with(|a, b| (a.0.min(b.0), a.1.max(b.1)));
+        let (min, max) = histogram_bounds(range);
+        self.par_histogram_range(min, max, bins)
+    }
+
+    /// [`Column::histogram_range`] on multiple threads.
+    pub fn par_histogram_range(&self, min: f64, max: f64, bins: usize) -> Result<Histogram> {
+        let array = numeric_array(self)?;
+        let empty = checked_histogram(min, max, bins)?;
+        let len = array.len();
+        Ok((0..len.div_ceil(PAR_CHUNK_ROWS))
+            .into_par_iter()
+            .map(|i| {
+                let rows = i * PAR_CHUNK_ROWS..((i + 1) * PAR_CHUNK_ROWS).min(len);
+                range_histogram(&array, rows, empty.clone())
+            })
+            .reduce(|| empty.clone(), Histogram::merge))
+    }
+}
+
+impl Dataset {
+    /// [`Dataset::mask`] on multiple threads.
+    pub fn par_mask(&self, expr: &Expr) -> Result<Vec<bool>> {
+        let bound = expr.bind(self)?;
+        let mut mask = vec![false; self.len()];
+        mask.par_chunks_mut(PAR_CHUNK_ROWS)
+            .enumerate()
+            .for_each_init(Vec::new, |chunk, (i, out)| {
+                let start = i * PAR_CHUNK_ROWS;
+                bound.mask(start..start + out.len(), chunk);
+                out.copy_from_slice(chunk);
+            });
+        Ok(mask)
+    }
+
+    /// Select the rows matching `expr`, evaluated on multiple threads.
+    ///
+    /// Equivalent to `self.filter(&self.mask(expr)?)`.
+    pub fn par_filter(&self, expr: &Expr) -> Result<FilteredView<'_>> {
+        let bound = expr.bind(self)?;
+        let len = self.len();
+        let rows = (0..len.div_ceil(PAR_CHUNK_ROWS))
+            .into_par_iter()
+            .flat_map_iter(|i| {
+                let rows = i * PAR_CHUNK_ROWS..((i + 1) * PAR_CHUNK_ROWS).min(len);
+                let mut mask = Vec::new();
+                bound.mask(rows.clone(), &mut mask);
+                rows.zip(mask).filter(|(_, m)| *m).map(|(row, _)| row)
+            })
+            .collect();
+        Ok(FilteredView::new(self, rows))
+    }
+
+    /// [`Dataset::argsort`] on multiple threads.
+    pub fn par_argsort(&self, name: &str) -> Result<Vec<u64>> {
+        self.argsort_with(name, |perm, cmp| perm.par_sort_by(cmp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::col;
+    use crate::test_util::write_dataset;
+
+    #[test]
+    fn test_parallel() {
+        let values: Vec<u32> = (0..200_000).map(|i| (i * 7919) % 1000).collect();
+        let dir = write_dataset("parallel", &[("x", &values)]);
+        let ds = Dataset::open(&dir).unwrap();
+
+        let doubled = ds["x"].par_map(|x: u32| x * 2).unwrap();
+        assert_eq!(doubled[..3], [0, 1838, 1676]);
+        assert!(ds["x"].par_map(|x: f32| x).is_none());
+        assert_eq!(ds["x"].par_stats(), ds["x"].stats());
+        assert_eq!(ds["x"].par_histogram(7).unwrap(), ds["x"].histogram(7).unwrap());
+
+        let expr = col("x").lt(10);
+        assert_eq!(ds.par_mask(&expr).unwrap(), ds.mask(&expr).unwrap());
+        let rows: Vec<usize> = ds.matching_rows(&expr).unwrap().collect();
+        assert_eq!(ds.par_filter(&expr).unwrap().rows(), rows);
+        assert_eq!(ds.par_argsort("x").unwrap(), ds.argsort("x").unwrap());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
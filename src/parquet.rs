@@ -0,0 +1,165 @@
+//! Parquet files, on the `parquet` crate.
+//!
+//! [`Dataset::export_parquet`] writes a row group per `ROW_GROUP_ROWS` rows
+//! from record batches sharing the mapped files, so only one row group of a
+//! column is read at a time, and [`Dataset::from_parquet`] reads a file
+//! batch by batch.
+//!
+//! Columns are converted through Arrow, with types mapped as by
+//! [`DatasetView::to_record_batch`](crate::DatasetView::to_record_batch).
+//! The Arrow schema is kept in the file, so unsigned, categorical and array
+//! columns come back as they were written. Nullable columns are OPTIONAL.
+//! Pages are written uncompressed; reading also supports Snappy, gzip and
+//! zstd compression.
+
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use ::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use ::parquet::arrow::ArrowWriter;
+use ::parquet::file::properties::WriterProperties;
+
+use crate::arrow::{arrow_schema, record_batch};
+use crate::dataset::Dataset;
+use crate::error::{MmappetError, Result};
+use crate::schema::{ColumnDef, Schema};
+use crate::writer::{create_atomically, DatasetWriter};
+
+/// Rows per row group written by [`Dataset::export_parquet`].
+const ROW_GROUP_ROWS: usize = 1 << 20;
+/// Rows per record batch read by [`Dataset::from_parquet`].
+const READ_BATCH_ROWS: usize = 1 << 16;
+
+impl Dataset {
+    /// Write the dataset to the Parquet file `path`, in row groups of about
+    /// a million rows.
+    ///
+    /// Row groups are written from record batches sharing the mapped files,
+    /// so datasets larger than memory can be exported.
+    pub fn export_parquet<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.write_parquet(path.as_ref(), ROW_GROUP_ROWS)
+    }
+
+    fn write_parquet(&self, path: &Path, group_rows: usize) -> Result<()> {
+        let failed =
+            |e: ::parquet::errors::ParquetError| MmappetError::io(path, std::io::Error::other(e));
+        let file = File::create(path).map_err(|e| MmappetError::io(path, e))?;
+        let properties = WriterProperties::builder()
+            .set_max_row_group_size(group_rows)
+            .build();
+        let columns: Vec<&ColumnDef> = self.schema().columns().collect();
+        let schema = arrow_schema(self, &columns)?;
+        let mut writer =
+            ArrowWriter::try_new(file, Arc::clone(&schema), Some(properties)).map_err(failed)?;
+        for view in self.chunks(group_rows) {
+            let batch = record_batch(self, &columns, &schema, view.rows())?;
+            writer.write(&batch).map_err(failed)?;
+        }
+        writer.close().map_err(failed)?;
+        Ok(())
+    }
+
+    /// Create the dataset `dir` from the Parquet file `path`, reading it
+    /// batch by batch, and open it.
+    ///
+    /// Returns an error naming all columns without a mmappet dtype, e.g.
+    /// nested ones or INT96 timestamps, or if the file is malformed.
+    pub fn from_parquet<P: AsRef<Path>, Q: AsRef<Path>>(path: P, dir: Q) -> Result<Dataset> {
+        let (path, dir) = (path.as_ref(), dir.as_ref());
+        let invalid = |message: String| MmappetError::InvalidEncoding {
+            path: path.to_path_buf(),
+            message,
+        };
+        let file = File::open(path).map_err(|e| MmappetError::io(path, e))?;
+        let builder =
+            ParquetRecordBatchReaderBuilder::try_new(file).map_err(|e| invalid(e.to_string()))?;
+        let schema = Schema::from_arrow(builder.schema()).map_err(|e| e.with_path(path))?;
+        let reader = builder
+            .with_batch_size(READ_BATCH_ROWS)
+            .build()
+            .map_err(|e| invalid(e.to_string()))?;
+        create_atomically(dir, |tmp_dir| {
+            let mut writer = DatasetWriter::create(tmp_dir, &schema)?;
+            for batch in reader {
+                writer.write_record_batch(&batch.map_err(|e| invalid(e.to_string()))?)?;
+            }
+            writer.finish()
+        })?;
+        Dataset::open(dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dtype::DType;
+
+    #[test]
+    fn test_parquet_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("mmappet-parquet-{}", std::process::id()));
+        let file = dir.with_extension("parquet");
+        let copy = dir.with_extension("copy");
+        let mut schema = Schema::new(&[
+            ("tof", DType::UInt32),
+            ("charge", DType::Int8),
+            ("mz", DType::Float64),
+            ("decoy", DType::Bool),
+            ("peptide", DType::Utf8),
+            ("protein", DType::Categorical),
+            ("time", DType::TimestampMicros),
+            ("xy", DType::Float32),
+        ])
+        .unwrap();
+        schema.set_shape("xy", &[2]).unwrap();
+        let rows = 30usize;
+        let tofs: Vec<Option<u32>> = (0..rows as u32)
+            .map(|i| (i % 4 != 1).then_some(i))
+            .collect();
+        let charges: Vec<i8> = (0..rows as i8).map(|i| i - 10).collect();
+        let mzs: Vec<f64> = (0..rows).map(|i| i as f64 * 0.5).collect();
+        let decoys: Vec<u8> = (0..rows).map(|i| (i % 3 == 0) as u8).collect();
+        let peptides: Vec<String> = (0..rows).map(|i| "PEPTIDE"[..i % 7].to_string()).collect();
+        let strs: Vec<&str> = peptides.iter().map(String::as_str).collect();
+        let proteins: Vec<&str> = (0..rows).map(|i| ["P1", "P2", "P3"][i % 3]).collect();
+        let times: Vec<i64> = (0..rows as i64).collect();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write_opt("tof", &tofs).unwrap();
+        writer.write("charge", &charges).unwrap();
+        writer.write("mz", &mzs).unwrap();
+        writer.write_bytes(3, &decoys, rows).unwrap();
+        writer.write_str("peptide", &strs).unwrap();
+        writer.write_categorical("protein", &proteins).unwrap();
+        writer.write_timestamps("time", &times).unwrap();
+        writer
+            .write("xy", &(0..2 * rows).map(|i| i as f32).collect::<Vec<_>>())
+            .unwrap();
+        writer.finish().unwrap();
+
+        let ds = Dataset::open(&dir).unwrap();
+        ds.write_parquet(&file, 8).unwrap();
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(&file).unwrap()).unwrap();
+        assert_eq!(builder.metadata().num_row_groups(), 4);
+
+        let out = Dataset::from_parquet(&file, &copy).unwrap();
+        assert_eq!(out.schema().to_string(), ds.schema().to_string());
+        let tof = out.nullable("tof").unwrap();
+        assert_eq!(tof.iter_opt::<u32>().unwrap().collect::<Vec<_>>(), tofs);
+        for name in ["charge", "mz", "decoy", "time", "xy"] {
+            assert_eq!(out[name].as_bytes(), ds[name].as_bytes(), "{}", name);
+        }
+        assert_eq!(out["peptide"].iter_str().unwrap().collect::<Vec<_>>(), strs);
+        let labels = out["protein"].as_categorical().unwrap();
+        assert_eq!(labels.iter().collect::<Vec<_>>(), proteins);
+
+        std::fs::write(&file, b"PAR1 not a Parquet file").unwrap();
+        assert!(matches!(
+            Dataset::from_parquet(&file, dir.with_extension("bad")),
+            Err(MmappetError::InvalidEncoding { .. })
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_dir_all(&copy).unwrap();
+        std::fs::remove_file(&file).unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+//! Pipeline-parallel execution for conversions.
+//!
+//! A conversion is split into three stages running concurrently: a producer
+//! thread reading input items, a pool of workers decoding them, and the calling
+//! thread consuming decoded items in input order (typically writing them to a
+//! [`ColumnSink`](crate::sink::ColumnSink)). Stages are connected by bounded
+//! channels, so memory use stays proportional to the number of workers.
+
+use std::collections::BTreeMap;
+use std::sync::mpsc::sync_channel;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Number of decode workers to use by default.
+pub(crate) fn default_workers() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Run `produce`, `decode` and `consume` as a pipeline.
+///
+/// `produce` runs on its own thread and hands items to the `feed` callback it
+/// is given; `feed` returns `false` once the pipeline has stopped (after an
+/// error), at which point `produce` should return. Items are decoded on
+/// `workers` threads and passed to `consume` on the calling thread in the
+/// order they were produced.
+///
+/// The first error from any stage is returned; remaining work is abandoned.
+pub(crate) fn run<T, U, E, P, D, C>(
+    workers: usize,
+    produce: P,
+    decode: D,
+    mut consume: C,
+) -> Result<(), E>
+where
+    T: Send,
+    U: Send,
+    E: Send,
+    P: FnOnce(&mut dyn FnMut(T) -> bool) -> Result<(), E> + Send,
+    D: Fn(T) -> Result<U, E> + Sync,
+    C: FnMut(U) -> Result<(), E>,
+{
+    let workers = workers.max(1);
+    let capacity = 2 * workers;
+
+    thread::scope(|s| {
+        let (job_tx, job_rx) = sync_channel::<(usize, T)>(capacity);
+        let (result_tx, result_rx) = sync_channel::<(usize, Result<U, E>)>(capacity);
+
+        let producer = s.spawn(move || {
+            let mut seq = 0;
+            produce(&mut |item| {
+                let sent = job_tx.send((seq, item)).is_ok();
+                seq += 1;
+                sent
+            })
+        });
+
+        // Workers share the job receiver; it is dropped once they all exit,
+        // which unblocks the producer if the pipeline stops early.
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        for _ in 0..workers {
+            let job_rx = Arc::clone(&job_rx);
+            let result_tx = result_tx.clone();
+            let decode = &decode;
+            s.spawn(move || loop {
+                let job = job_rx.lock().unwrap().recv();
+                let Ok((seq, item)) = job else { break };
+                if result_tx.send((seq, decode(item))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(job_rx);
+        drop(result_tx);
+
+        // Results arrive out of order; hold them until their turn.
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        let mut outcome = Ok(());
+        'receive: for (seq, result) in result_rx.iter() {
+            pending.insert(seq, result);
+            while let Some(result) = pending.remove(&next) {
+                next += 1;
+                if let Err(e) = result.and_then(&mut consume) {
+                    outcome = Err(e);
+                    break 'receive;
+                }
+            }
+        }
+        drop(result_rx);
+
+        let produced = producer.join().expect("pipeline producer panicked");
+        outcome.and(produced)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_preserves_order() {
+        let mut out = Vec::new();
+        run(
+            4,
+            |feed| {
+                for i in 0..1000u64 {
+                    if !feed(i) {
+                        break;
+                    }
+                }
+                Ok::<_, String>(())
+            },
+            |i| Ok(i * i),
+            |x| {
+                out.push(x);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(out, (0..1000u64).map(|i| i * i).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_run_stops_on_error() {
+        let mut consumed = 0;
+        let result = run(
+            3,
+            |feed| {
+                for i in 0.. {
+                    if !feed(i) {
+                        break;
+                    }
+                }
+                Ok(())
+            },
+            |i: u64| {
+                if i == 50 {
+                    Err(format!("bad item {}", i))
+                } else {
+                    Ok(i)
+                }
+            },
+            |_| {
+                consumed += 1;
+                Ok(())
+            },
+        );
+        assert_eq!(result, Err("bad item 50".to_string()));
+        assert_eq!(consumed, 50);
+    }
+}
@@ -0,0 +1,410 @@
+//! Polars DataFrame conversion (feature `polars`).
+
+use std::path::Path;
+
+use ::polars::datatypes::{
+    Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, Int8Type, UInt16Type, UInt32Type,
+    UInt64Type, UInt8Type,
+};
+use ::polars::prelude::{
+    CategoricalOrdering, Column as PolarsColumn, DataFrame, DataType, PolarsNumericType,
+    PolarsResult, Series, TimeUnit,
+};
+use bytemuck::cast_slice;
+use polars_arrow::array::{
+    Array, BinaryViewArray, BooleanArray, FixedSizeListArray, PrimitiveArray, Utf8ViewArray,
+};
+use polars_arrow::bitmap::Bitmap;
+use polars_arrow::datatypes::{ArrowDataType, Field, TimeUnit as ArrowTimeUnit};
+use polars_arrow::types::NativeType;
+
+use crate::column::{Column, TypedArrayView};
+use crate::dataset::Dataset;
+use crate::dtype::{DType, MmappetType};
+use crate::error::{MmappetError, Result};
+use crate::schema::Schema;
+use crate::writer::{create_atomically, DatasetWriter, Values};
+
+impl Dataset {
+    /// Copy the dataset into a Polars [`DataFrame`].
+    ///
+    /// Values are copied: a `DataFrame` owns its buffers and may outlive the
+    /// dataset, so it can't borrow the mapping. Nulls are kept, array
+    /// columns become fixed-size `Array` columns, timestamps `Datetime` in
+    /// UTC and categorical columns `Categorical`. Polars has no `f16`, so
+    /// `float16` columns are widened to `Float32`, and custom dtypes become
+    /// `Binary` columns of their raw bytes.
+    pub fn to_polars(&self) -> Result<DataFrame> {
+        let columns = self
+            .schema()
+            .columns()
+            .map(|def| {
+                let nullable = self.nullable(&def.name)?;
+                let validity = nullable.has_validity().then(|| {
+                    (0..self.len())
+                        .map(|i| nullable.is_valid(i))
+                        .collect::<Bitmap>()
+                });
+                let series = to_series(&def.name, nullable.column(), validity);
+                series
+                    .map(PolarsColumn::from)
+                    .map_err(|e| self.polars_error(e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        DataFrame::new(columns).map_err(|e| self.polars_error(e))
+    }
+
+    fn polars_error(&self, e: ::polars::error::PolarsError) -> MmappetError {
+        MmappetError::InvalidBatch {
+            path: Some(self.path().to_path_buf()),
+            message: e.to_string(),
+        }
+    }
+}
+
+/// Series of the values of `column`, with `validity` per row.
+fn to_series(name: &str, column: &Column, validity: Option<Bitmap>) -> PolarsResult<Series> {
+    fn primitive<T: NativeType>(
+        values: impl Iterator<Item = T>,
+        dtype: ArrowDataType,
+    ) -> Box<dyn Array> {
+        PrimitiveArray::new(dtype, values.collect::<Vec<T>>().into(), None).boxed()
+    }
+
+    let values = match column.as_typed_array() {
+        TypedArrayView::UInt8(a) => primitive(a.iter().copied(), ArrowDataType::UInt8),
+        TypedArrayView::Int8(a) => primitive(a.iter().copied(), ArrowDataType::Int8),
+        TypedArrayView::UInt16(a) => primitive(a.iter().copied(), ArrowDataType::UInt16),
+        TypedArrayView::Int16(a) => primitive(a.iter().copied(), ArrowDataType::Int16),
+        TypedArrayView::UInt32(a) => primitive(a.iter().copied(), ArrowDataType::UInt32),
+        TypedArrayView::Int32(a) => primitive(a.iter().copied(), ArrowDataType::Int32),
+        TypedArrayView::UInt64(a) => primitive(a.iter().copied(), ArrowDataType::UInt64),
+        TypedArrayView::Int64(a) => primitive(a.iter().copied(), ArrowDataType::Int64),
+        TypedArrayView::Float16(a) => {
+            primitive(a.iter().map(|x| x.to_f32()), ArrowDataType::Float32)
+        }
+        TypedArrayView::Float32(a) => primitive(a.iter().copied(), ArrowDataType::Float32),
+        TypedArrayView::Float64(a) => primitive(a.iter().copied(), ArrowDataType::Float64),
+        TypedArrayView::TimestampMillis(a) => primitive(
+            a.iter().copied(),
+            ArrowDataType::Timestamp(ArrowTimeUnit::Millisecond, Some("UTC".into())),
+        ),
+        TypedArrayView::TimestampMicros(a) => primitive(
+            a.iter().copied(),
+            ArrowDataType::Timestamp(ArrowTimeUnit::Microsecond, Some("UTC".into())),
+        ),
+        TypedArrayView::Bool(a) => {
+            let values: Bitmap = a.iter().map(|&x| x != 0).collect();
+            BooleanArray::new(ArrowDataType::Boolean, values, None).boxed()
+        }
+        TypedArrayView::Utf8(a) => {
+            Utf8ViewArray::from_slice_values(a.iter().collect::<Vec<_>>()).boxed()
+        }
+        TypedArrayView::Categorical(a) => {
+            Utf8ViewArray::from_slice_values(a.iter().collect::<Vec<_>>()).boxed()
+        }
+        TypedArrayView::Custom(a) => {
+            let values: Vec<&[u8]> = a.as_bytes().chunks(a.def().size).collect();
+            BinaryViewArray::from_slice_values(values).boxed()
+        }
+    };
+    // Arrays nest from the innermost dimension out; nulls are rows
+    let mut array = values;
+    let mut len = array.len();
+    for &size in column.shape().iter().rev() {
+        len /= size.max(1);
+        let field = Field::new("item".into(), array.dtype().clone(), true);
+        let dtype = ArrowDataType::FixedSizeList(Box::new(field), size);
+        array = FixedSizeListArray::new(dtype, len, array, None).boxed();
+    }
+    let series = Series::from_arrow(name.into(), array.with_validity(validity))?;
+    match column.dtype() {
+        DType::Categorical => {
+            series.cast(&DataType::Categorical(None, CategoricalOrdering::Physical))
+        }
+        _ => Ok(series),
+    }
+}
+
+impl DatasetWriter {
+    /// Create the dataset `dir` from a Polars [`DataFrame`], with a column
+    /// per column of the frame.
+    ///
+    /// Nulls are kept in validity bitmaps, `Array` columns become array
+    /// columns and `Datetime` columns in milliseconds or microseconds
+    /// timestamps. Returns the number of rows written, or an error naming
+    /// all columns without a mmappet dtype, e.g. lists, structs or
+    /// nanosecond datetimes, or arrays holding nulls.
+    pub fn from_polars<P: AsRef<Path>>(dir: P, df: &DataFrame) -> Result<usize> {
+        let dir = dir.as_ref();
+        let mut defs = Vec::with_capacity(df.width());
+        let mut unsupported = Vec::new();
+        for column in df.get_columns() {
+            let series = column.as_materialized_series();
+            match polars_dtype(series) {
+                Some((dtype, shape)) => defs.push((series.name().as_str(), dtype, shape)),
+                None => unsupported.push(format!("{} ({})", series.name(), series.dtype())),
+            }
+        }
+        if !unsupported.is_empty() {
+            return Err(MmappetError::Import {
+                path: Some(dir.to_path_buf()),
+                offset: None,
+                message: format!("no mmappet dtype for columns {}", unsupported.join(", ")),
+            });
+        }
+
+        let pairs: Vec<(&str, DType)> =
+            defs.iter().map(|&(name, dtype, _)| (name, dtype)).collect();
+        let mut schema = Schema::new(&pairs).map_err(|e| e.with_path(dir))?;
+        for (name, _, shape) in &defs {
+            if !shape.is_empty() {
+                schema
+                    .set_shape(name, shape)
+                    .map_err(|e| e.with_path(dir))?;
+            }
+        }
+        let polars_error = |e: ::polars::error::PolarsError| MmappetError::Import {
+            path: Some(dir.to_path_buf()),
+            offset: None,
+            message: e.to_string(),
+        };
+        // Values of array columns are flattened, row after row
+        let mut flattened = Vec::with_capacity(defs.len());
+        for (column, (name, _, _)) in df.get_columns().iter().zip(&defs) {
+            let series = column.as_materialized_series().rechunk();
+            let validity = (series.null_count() > 0).then(|| {
+                series
+                    .is_not_null()
+                    .into_no_null_iter()
+                    .collect::<Vec<bool>>()
+            });
+            let mut values = series.clone();
+            while let DataType::Array(_, _) = values.dtype() {
+                values = values.array().map_err(polars_error)?.get_inner();
+            }
+            if values.null_count() > 0 {
+                // Values may only be null in null rows
+                let width = (values.len() / series.len().max(1)).max(1);
+                let valid: Vec<bool> = values.is_not_null().into_no_null_iter().collect();
+                let row_valid = |row: usize| validity.as_ref().is_none_or(|v| v[row]);
+                let mut rows = valid.chunks(width).enumerate();
+                if rows.any(|(row, valid)| row_valid(row) && valid.contains(&false)) {
+                    return Err(MmappetError::Import {
+                        path: Some(dir.to_path_buf()),
+                        offset: None,
+                        message: format!("array column '{}' holds nulls within arrays", name),
+                    });
+                }
+            }
+            flattened.push((values, validity));
+        }
+        let mut columns = Vec::with_capacity(defs.len());
+        for ((values, validity), (_, dtype, _)) in flattened.iter().zip(&defs) {
+            let values = series_values(values, *dtype).map_err(polars_error)?;
+            columns.push((values, validity.clone()));
+        }
+
+        create_atomically(dir, |tmp_dir| {
+            let mut writer = DatasetWriter::create(tmp_dir, &schema)?;
+            writer.write_columns(columns, df.height())?;
+            writer.finish()
+        })
+    }
+}
+
+/// The mmappet dtype and shape of a Polars column, if it has one.
+fn polars_dtype(series: &Series) -> Option<(DType, Vec<usize>)> {
+    let mut shape = Vec::new();
+    let mut dtype = series.dtype();
+    while let DataType::Array(inner, size) = dtype {
+        shape.push(*size);
+        dtype = inner;
+    }
+    let dtype = match dtype {
+        DataType::UInt8 => DType::UInt8,
+        DataType::Int8 => DType::Int8,
+        DataType::UInt16 => DType::UInt16,
+        DataType::Int16 => DType::Int16,
+        DataType::UInt32 => DType::UInt32,
+        DataType::Int32 => DType::Int32,
+        DataType::UInt64 => DType::UInt64,
+        DataType::Int64 => DType::Int64,
+        DataType::Float32 => DType::Float32,
+        DataType::Float64 => DType::Float64,
+        DataType::Boolean => DType::Bool,
+        DataType::String => DType::Utf8,
+        DataType::Categorical(_, _) => DType::Categorical,
+        DataType::Datetime(TimeUnit::Milliseconds, _) => DType::TimestampMillis,
+        DataType::Datetime(TimeUnit::Microseconds, _) => DType::TimestampMicros,
+        _ => return None,
+    };
+    Some((dtype, shape))
+}
+
+/// Values of a rechunked series of `dtype`, nulls stored as zero or "".
+fn series_values(series: &Series, dtype: DType) -> PolarsResult<Values<'_>> {
+    fn bytes<T: PolarsNumericType>(series: &Series) -> PolarsResult<Values<'_>>
+    where
+        T::Native: MmappetType,
+    {
+        let values: Vec<T::Native> = series
+            .unpack::<T>()?
+            .iter()
+            .map(Option::unwrap_or_default)
+            .collect();
+        Ok(Values::Bytes(cast_slice(&values).to_vec()))
+    }
+
+    match dtype {
+        DType::UInt8 => bytes::<UInt8Type>(series),
+        DType::Int8 => bytes::<Int8Type>(series),
+        DType::UInt16 => bytes::<UInt16Type>(series),
+        DType::Int16 => bytes::<Int16Type>(series),
+        DType::UInt32 => bytes::<UInt32Type>(series),
+        DType::Int32 => bytes::<Int32Type>(series),
+        DType::UInt64 => bytes::<UInt64Type>(series),
+        DType::Float32 => bytes::<Float32Type>(series),
+        DType::Float64 => bytes::<Float64Type>(series),
+        DType::Int64 => bytes::<Int64Type>(series),
+        DType::TimestampMillis | DType::TimestampMicros => {
+            let ticks: Vec<i64> = series
+                .datetime()?
+                .physical()
+                .iter()
+                .map(Option::unwrap_or_default)
+                .collect();
+            Ok(Values::Bytes(cast_slice(&ticks).to_vec()))
+        }
+        DType::Bool => {
+            let bools = series
+                .bool()?
+                .iter()
+                .map(|b| b.unwrap_or(false) as u8)
+                .collect();
+            Ok(Values::Bytes(bools))
+        }
+        DType::Utf8 => Ok(Values::Strs(
+            series
+                .str()?
+                .iter()
+                .map(Option::unwrap_or_default)
+                .collect(),
+        )),
+        DType::Categorical => {
+            let labels = series
+                .categorical()?
+                .iter_str()
+                .map(Option::unwrap_or_default)
+                .collect();
+            Ok(Values::Labels(labels))
+        }
+        DType::Float16 | DType::Custom(_) => unreachable!("not a Polars dtype"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::column::TypedArray;
+
+    #[test]
+    fn test_polars_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("mmappet-polars-{}", std::process::id()));
+        let copy = dir.with_extension("copy");
+        let schema = Schema::new(&[
+            ("id", DType::UInt32),
+            ("mz", DType::Float64),
+            ("xy", DType::Float32),
+            ("name", DType::Utf8),
+            ("charge", DType::Categorical),
+            ("time", DType::TimestampMillis),
+            ("flag", DType::Bool),
+        ])
+        .map(|mut schema| {
+            schema.set_shape("xy", &[2]).unwrap();
+            schema
+        })
+        .unwrap();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer
+            .write_opt("id", &[Some(1u32), None, Some(3)])
+            .unwrap();
+        writer.write("mz", &[0.5f64, 2.0, 1234.5]).unwrap();
+        writer
+            .write("xy", &[1.5f32, -2.0, 0.0, 1.0, 3.25, 4.0])
+            .unwrap();
+        writer.write_str("name", &["a", "", "PEPTIDE"]).unwrap();
+        writer
+            .write_categorical("charge", &["2+", "3+", "2+"])
+            .unwrap();
+        writer
+            .write_timestamps("time", &[0, 1_500, 86_400_000])
+            .unwrap();
+        writer.write_bytes(6, &[1, 0, 1], 3).unwrap();
+        writer.finish().unwrap();
+        let ds = Dataset::open(&dir).unwrap();
+
+        let df = ds.to_polars().unwrap();
+        assert_eq!(df.shape(), (3, 7));
+        let id: Vec<Option<u32>> = df.column("id").unwrap().u32().unwrap().iter().collect();
+        assert_eq!(id, [Some(1), None, Some(3)]);
+        assert_eq!(
+            df.column("xy").unwrap().dtype(),
+            &DataType::Array(Box::new(DataType::Float32), 2)
+        );
+        assert!(matches!(
+            df.column("charge").unwrap().dtype(),
+            DataType::Categorical(_, _)
+        ));
+        assert!(matches!(
+            df.column("time").unwrap().dtype(),
+            DataType::Datetime(TimeUnit::Milliseconds, _)
+        ));
+
+        assert_eq!(DatasetWriter::from_polars(&copy, &df).unwrap(), 3);
+        let copied = Dataset::open(&copy).unwrap();
+        assert_eq!(copied.schema().to_string(), ds.schema().to_string());
+        let ids: Vec<Option<u32>> = copied.nullable("id").unwrap().iter_opt().unwrap().collect();
+        assert_eq!(ids, [Some(1), None, Some(3)]);
+        assert_eq!(
+            copied.get::<f32>("xy").unwrap(),
+            ds.get::<f32>("xy").unwrap()
+        );
+        assert_eq!(
+            copied.strings("name").unwrap().iter().collect::<Vec<_>>(),
+            ["a", "", "PEPTIDE"]
+        );
+        assert_eq!(
+            copied
+                .categorical("charge")
+                .unwrap()
+                .iter()
+                .collect::<Vec<_>>(),
+            ["2+", "3+", "2+"]
+        );
+        for name in ["mz", "time", "flag"] {
+            assert_eq!(copied[name].as_bytes(), ds[name].as_bytes(), "{}", name);
+        }
+
+        // Columns without a mmappet dtype are all named
+        let f16 =
+            Dataset::from_columns(vec![("h", TypedArray::from(vec![half::f16::ONE]))]).unwrap();
+        assert_eq!(
+            f16.to_polars().unwrap().column("h").unwrap().dtype(),
+            &DataType::Float32
+        );
+        let df = DataFrame::new(vec![
+            PolarsColumn::new("ok".into(), &[1i64]),
+            PolarsColumn::new("d".into(), &[1i64])
+                .cast(&DataType::Duration(TimeUnit::Milliseconds))
+                .unwrap(),
+        ])
+        .unwrap();
+        let err = DatasetWriter::from_polars(dir.with_extension("bad"), &df).unwrap_err();
+        assert!(err.to_string().contains("d (duration[ms])"), "{}", err);
+
+        std::fs::remove_dir_all(dir).unwrap();
+        std::fs::remove_dir_all(copy).unwrap();
+    }
+}
@@ -0,0 +1,192 @@
+//! Quantiles of numeric columns, exact or estimated in one pass.
+
+use crate::column::{Column, TypedArrayView};
+use crate::expr::read_f64;
+use crate::stats::STATS_CHUNK_ROWS;
+
+/// Columns with up to this many values are copied and sorted for exact
+/// quantiles (128 MiB of `f64`); longer ones are estimated in one pass.
+const EXACT_QUANTILE_VALUES: usize = 1 << 24;
+
+impl Column {
+    /// Quantiles `qs` of the column's values, all values of the arrays of
+    /// array columns, e.g. `&[0.5, 0.99]` for the median and p99.
+    ///
+    /// NaNs are skipped and quantiles fall between values as by linear
+    /// interpolation (NumPy's default); NaN if there are no values. Columns
+    /// of up to 2^24 values are sorted in memory for exact quantiles. Longer
+    /// ones are streamed once through P² estimators (Jain & Chlamtac), which
+    /// keep five values per quantile and are typically within a fraction of
+    /// a percent of the value range.
+    ///
+    /// Returns `None` for columns without numeric values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a quantile isn't within `0..=1`.
+    pub fn quantiles(&self, qs: &[f64]) -> Option<Vec<f64>> {
+        Some(quantiles(&self.numeric_array()?, qs, EXACT_QUANTILE_VALUES))
+    }
+}
+
+fn quantiles(array: &TypedArrayView<'_>, qs: &[f64], exact_limit: usize) -> Vec<f64> {
+    for &q in qs {
+        assert!((0.0..=1.0).contains(&q), "quantile {} is not within 0..=1", q);
+    }
+    let len = array.len();
+    let mut chunk = vec![0.0; STATS_CHUNK_ROWS.min(len)];
+    let mut values = Vec::new();
+    let mut estimators: Vec<P2> = qs.iter().map(|&q| P2::new(q)).collect();
+    let exact = len <= exact_limit;
+    for start in (0..len).step_by(STATS_CHUNK_ROWS) {
+        let chunk = &mut chunk[..STATS_CHUNK_ROWS.min(len - start)];
+        read_f64(array, start, chunk);
+        let valid = chunk.iter().copied().filter(|x| !x.is_nan());
+        if exact {
+            values.extend(valid);
+        } else {
+            for x in valid {
+                estimators.iter_mut().for_each(|p2| p2.add(x));
+            }
+        }
+    }
+    if !exact {
+        return estimators.iter().map(P2::estimate).collect();
+    }
+    values.sort_unstable_by(f64::total_cmp);
+    qs.iter().map(|&q| interpolate(&values, q)).collect()
+}
+
+/// Quantile `q` of the sorted `values`, NaN if there are none.
+fn interpolate(values: &[f64], q: f64) -> f64 {
+    if values.is_empty() {
+        return f64::NAN;
+    }
+    let position = q * (values.len() - 1) as f64;
+    let below = position.floor() as usize;
+    let above = (below + 1).min(values.len() - 1);
+    let fraction = position - below as f64;
+    values[below] + fraction * (values[above] - values[below])
+}
+
+/// P² estimator of one quantile: five markers whose heights approximate the
+/// minimum, the quantiles `q / 2`, `q`, `(1 + q) / 2` and the maximum.
+struct P2 {
+    q: f64,
+    count: usize,
+    heights: [f64; 5],
+    /// Actual marker positions, 1-based ranks.
+    positions: [f64; 5],
+    /// Desired marker positions and their increments per value.
+    desired: [f64; 5],
+    increments: [f64; 5],
+}
+
+impl P2 {
+    fn new(q: f64) -> Self {
+        P2 {
+            q,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [1.0, 2.0, 3.0, 4.0, 5.0],
+            desired: [1.0, 1.0 + 2.0 * q, 1.0 + 4.0 * q, 3.0 + 2.0 * q, 5.0],
+            increments: [0.0, q / 2.0, q, (1.0 + q) / 2.0, 1.0],
+        }
+    }
+
+    fn add(&mut self, x: f64) {
+        let h = &mut self.heights;
+        if self.count < 5 {
+            h[self.count] = x;
+            self.count += 1;
+            if self.count == 5 {
+                h.sort_unstable_by(f64::total_cmp);
+            }
+            return;
+        }
+        self.count += 1;
+
+        // Cell of x, widening the outer markers to the new extremes
+        let cell = if x < h[0] {
+            h[0] = x;
+            0
+        } else if x >= h[4] {
+            h[4] = x;
+            3
+        } else {
+            (0..4).find(|&i| x < h[i + 1]).unwrap()
+        };
+        let n = &mut self.positions;
+        for position in &mut n[cell + 1..] {
+            *position += 1.0;
+        }
+        for (desired, increment) in self.desired.iter_mut().zip(self.increments) {
+            *desired += increment;
+        }
+
+        // Move the middle markers towards their desired positions
+        for i in 1..4 {
+            let d = self.desired[i] - n[i];
+            if (d < 1.0 || n[i + 1] - n[i] <= 1.0) && (d > -1.0 || n[i - 1] - n[i] >= -1.0) {
+                continue;
+            }
+            let d = d.signum();
+            let parabolic = h[i]
+                + d / (n[i + 1] - n[i - 1])
+                    * ((n[i] - n[i - 1] + d) * (h[i + 1] - h[i]) / (n[i + 1] - n[i])
+                        + (n[i + 1] - n[i] - d) * (h[i] - h[i - 1]) / (n[i] - n[i - 1]));
+            h[i] = if h[i - 1] < parabolic && parabolic < h[i + 1] {
+                parabolic
+            } else {
+                let j = if d > 0.0 { i + 1 } else { i - 1 };
+                h[i] + d * (h[j] - h[i]) / (n[j] - n[i])
+            };
+            n[i] += d;
+        }
+    }
+
+    fn estimate(&self) -> f64 {
+        if self.count <= 5 {
+            let mut values = self.heights[..self.count].to_vec();
+            values.sort_unstable_by(f64::total_cmp);
+            return interpolate(&values, self.q);
+        }
+        match self.q {
+            0.0 => self.heights[0],
+            1.0 => self.heights[4],
+            _ => self.heights[2],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::Dataset;
+
+    #[test]
+    fn test_quantiles() {
+        // 0..10_000 shuffled, with NaNs in between
+        let mut values: Vec<f64> = (0..10_000).map(|i| ((i * 7919) % 10_000) as f64).collect();
+        values.extend([f64::NAN; 10]);
+        let ds = Dataset::from_columns(vec![("x", values.into())]).unwrap();
+        let column = &ds["x"];
+
+        let exact = column.quantiles(&[0.0, 0.25, 0.5, 0.99, 1.0]).unwrap();
+        assert_eq!(exact, [0.0, 2499.75, 4999.5, 9899.01, 9999.0]);
+
+        let array = column.as_typed_array();
+        let estimated = quantiles(&array, &[0.0, 0.25, 0.5, 0.99, 1.0], 100);
+        assert_eq!((estimated[0], estimated[4]), (0.0, 9999.0));
+        for (estimate, exact) in estimated.iter().zip(&exact) {
+            assert!((estimate - exact).abs() < 100.0, "{} vs {}", estimate, exact);
+        }
+
+        let few = Dataset::from_columns(vec![("x", vec![3.0f64, 1.0, 2.0].into())]).unwrap();
+        let array = few["x"].as_typed_array();
+        assert_eq!(quantiles(&array, &[0.5], 0), [2.0]);
+        assert_eq!(quantiles(&array, &[0.5], 10), [2.0]);
+        let empty = Dataset::from_columns(vec![("x", Vec::<f64>::new().into())]).unwrap();
+        assert!(empty["x"].quantiles(&[0.5]).unwrap()[0].is_nan());
+    }
+}
@@ -0,0 +1,106 @@
+//! Background readahead for sequential scans.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use crate::column::Advice;
+use crate::storage::Storage;
+
+/// Granularity at which pages are touched ahead of the consumer.
+const PAGE_SIZE: usize = 4096;
+
+/// Shared state between the consumer and the prefetch thread.
+struct State {
+    /// Byte offset the consumer has reached.
+    position: AtomicUsize,
+    stop: AtomicBool,
+}
+
+/// Touches pages of a mapping on a background thread, staying a fixed
+/// number of bytes ahead of the consumer.
+///
+/// Copies in memory, which are all there is on targets without `mmap` such
+/// as `wasm32`, have nothing to read in, so no thread is spawned for them.
+/// The thread is stopped and joined when the `Readahead` is dropped.
+pub(crate) struct Readahead {
+    state: Arc<State>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Readahead {
+    /// Start prefetching `mmap`, keeping `distance` bytes ahead of the position
+    /// reported through [`Readahead::advance`].
+    pub(crate) fn spawn(mmap: Arc<Storage>, distance: usize) -> Self {
+        let state = Arc::new(State {
+            position: AtomicUsize::new(0),
+            stop: AtomicBool::new(false),
+        });
+
+        let handle = mmap.is_mapped().then(|| {
+            let thread_state = Arc::clone(&state);
+            thread::spawn(move || prefetch_loop(&mmap, distance, &thread_state))
+        });
+
+        Readahead { state, handle }
+    }
+
+    /// Report that the consumer has reached byte offset `position`.
+    pub(crate) fn advance(&self, position: usize) {
+        self.state.position.store(position, Ordering::Release);
+        if let Some(handle) = &self.handle {
+            handle.thread().unpark();
+        }
+    }
+}
+
+impl Drop for Readahead {
+    fn drop(&mut self) {
+        self.state.stop.store(true, Ordering::Release);
+        if let Some(handle) = self.handle.take() {
+            handle.thread().unpark();
+            let _ = handle.join();
+        }
+    }
+}
+
+fn prefetch_loop(mmap: &Storage, distance: usize, state: &State) {
+    let mut touched = 0;
+
+    while !state.stop.load(Ordering::Acquire) && touched < mmap.len() {
+        let target = state
+            .position
+            .load(Ordering::Acquire)
+            .saturating_add(distance)
+            .min(mmap.len());
+
+        if touched >= target {
+            thread::park();
+            continue;
+        }
+
+        let _ = mmap.advise(Advice::WillNeed, touched..target);
+
+        while touched < target {
+            if state.stop.load(Ordering::Relaxed) {
+                return;
+            }
+            // Reading one byte per page is enough to fault it in.
+            // SAFETY: `touched < mmap.len()`, so the pointer is in bounds.
+            unsafe { std::ptr::read_volatile(mmap.as_ptr().add(touched)) };
+            touched += PAGE_SIZE;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_thread_for_buffered_storage() {
+        let readahead = Readahead::spawn(Arc::new(Storage::zeroed(1 << 20)), 1 << 16);
+        assert!(readahead.handle.is_none());
+        readahead.advance(1 << 20);
+    }
+}
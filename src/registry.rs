@@ -0,0 +1,202 @@
+//! Registry of user-defined fixed-size dtypes.
+
+use std::sync::RwLock;
+
+use crate::dtype::DType;
+use crate::error::{MmappetError, Result};
+use crate::format::ValueFormat;
+
+/// Registered custom dtypes, in registration order. Entries are never removed.
+static REGISTRY: RwLock<Vec<CustomDType>> = RwLock::new(Vec::new());
+
+/// Definition of a custom fixed-size dtype.
+///
+/// Once registered with [`register_dtype`], the name can be used in
+/// `schema.txt` and columns of the type open, print and export like builtin
+/// ones. Elements are handed to the callbacks as raw native-endian bytes of
+/// length `size`.
+///
+/// ```rust,no_run
+/// use mmappet::{register_dtype, CustomDType, DType, MmappetType};
+///
+/// // A (scan, tof) pair packed into one 8-byte element
+/// #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+/// #[repr(C)]
+/// struct ScanTof { scan: u32, tof: u32 }
+///
+/// impl MmappetType for ScanTof {
+///     const DTYPE: DType = DType::Custom("scantof");
+/// }
+///
+/// register_dtype(CustomDType::new("scantof", 8, |bytes, _| {
+///     let v: ScanTof = bytemuck::pod_read_unaligned(bytes);
+///     format!("{}:{}", v.scan, v.tof)
+/// }))
+/// .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct CustomDType {
+    /// Name used in `schema.txt` (matched case-insensitively).
+    pub name: &'static str,
+    /// Size of one element in bytes.
+    pub size: usize,
+    /// Render one element as text.
+    pub format: fn(&[u8], &ValueFormat) -> String,
+    /// Numeric value of one element, enabling min/max/mean statistics and plots.
+    pub to_f64: Option<fn(&[u8]) -> f64>,
+}
+
+impl CustomDType {
+    /// Define a dtype without numeric statistics.
+    pub fn new(name: &'static str, size: usize, format: fn(&[u8], &ValueFormat) -> String) -> Self {
+        CustomDType {
+            name,
+            size,
+            format,
+            to_f64: None,
+        }
+    }
+
+    /// Enable statistics using `to_f64` as the numeric value of an element.
+    pub fn with_stats(mut self, to_f64: fn(&[u8]) -> f64) -> Self {
+        self.to_f64 = Some(to_f64);
+        self
+    }
+}
+
+/// Register a custom dtype for the rest of the process.
+///
+/// Returns the [`DType`] identifying it. Fails if `size` is zero or the name
+/// is already taken by a builtin or previously registered dtype.
+pub fn register_dtype(def: CustomDType) -> Result<DType> {
+    let taken = DType::from_str(def.name).is_ok();
+    if taken || def.size == 0 || def.name.split_whitespace().count() != 1 {
+        return Err(MmappetError::InvalidDType {
+            dtype: def.name.to_string(),
+            message: if taken {
+                "name is already registered".to_string()
+            } else {
+                "expected a single-word name and a non-zero size".to_string()
+            },
+        });
+    }
+
+    REGISTRY
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(def);
+    Ok(DType::Custom(def.name))
+}
+
+/// Look up a registered dtype by name, ignoring case.
+pub(crate) fn lookup(name: &str) -> Option<CustomDType> {
+    REGISTRY
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .find(|def| def.name.eq_ignore_ascii_case(name))
+        .copied()
+}
+
+/// Column data of a custom dtype, see [`crate::TypedArrayView::Custom`].
+#[derive(Clone, Copy)]
+pub struct CustomArrayView<'a> {
+    def: CustomDType,
+    bytes: &'a [u8],
+}
+
+impl<'a> CustomArrayView<'a> {
+    pub(crate) fn new(def: CustomDType, bytes: &'a [u8]) -> Self {
+        CustomArrayView { def, bytes }
+    }
+
+    /// The dtype definition.
+    pub fn def(&self) -> &CustomDType {
+        &self.def
+    }
+
+    /// Get the number of elements.
+    pub fn len(&self) -> usize {
+        self.bytes.len() / self.def.size
+    }
+
+    /// Check if empty.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Raw bytes of all elements.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Raw bytes of the element at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn get(&self, index: usize) -> &'a [u8] {
+        &self.bytes[index * self.def.size..(index + 1) * self.def.size]
+    }
+
+    /// Render the element at `index` with the dtype's formatter.
+    pub fn format(&self, index: usize, format: &ValueFormat) -> String {
+        (self.def.format)(self.get(index), format)
+    }
+
+    /// Numeric value of the element at `index`, if the dtype has statistics.
+    pub fn to_f64(&self, index: usize) -> Option<f64> {
+        self.def.to_f64.map(|f| f(self.get(index)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::Dataset;
+    use crate::dtype::MmappetType;
+
+    #[derive(Clone, Copy, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+    #[repr(C)]
+    struct ScanTof {
+        scan: u32,
+        tof: u32,
+    }
+
+    impl MmappetType for ScanTof {
+        const DTYPE: DType = DType::Custom("test_scantof");
+    }
+
+    #[test]
+    fn test_custom_dtype() {
+        let def = CustomDType::new("test_scantof", 8, |bytes, _| {
+            let v: ScanTof = bytemuck::pod_read_unaligned(bytes);
+            format!("{}:{}", v.scan, v.tof)
+        })
+        .with_stats(|bytes| bytemuck::pod_read_unaligned::<ScanTof>(bytes).tof as f64);
+        assert_eq!(register_dtype(def).unwrap(), ScanTof::DTYPE);
+        assert!(register_dtype(def).is_err());
+        assert!(register_dtype(CustomDType::new("uint32", 4, |_, _| String::new())).is_err());
+
+        let dir = std::env::temp_dir().join(format!("mmappet-custom-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("schema.txt"), "TEST_SCANTOF pair\n").unwrap();
+        let pairs = [ScanTof { scan: 1, tof: 10 }, ScanTof { scan: 2, tof: 20 }];
+        std::fs::write(dir.join("0.bin"), bytemuck::cast_slice(&pairs)).unwrap();
+
+        let ds = Dataset::open(&dir).unwrap();
+        assert_eq!(ds.len(), 2);
+        assert_eq!(ds.schema().get("pair").unwrap().dtype.as_str(), "test_scantof");
+        assert_eq!(ds.get::<ScanTof>("pair").unwrap(), &pairs);
+
+        let array = ds["pair"].as_typed_array();
+        assert_eq!(array.len(), 2);
+        assert_eq!(ValueFormat::default().format_at(&array, 1), "2:20");
+        match array {
+            crate::TypedArrayView::Custom(view) => assert_eq!(view.to_f64(0), Some(10.0)),
+            _ => panic!("expected a custom array"),
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,635 @@
+//! Read-only datasets served over HTTP, read through Range requests.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::marker::PhantomData;
+use std::net::TcpStream;
+use std::ops::{Range, RangeBounds};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use bytemuck::cast_slice_mut;
+
+#[cfg(feature = "object_store")]
+use crate::cloud::Store;
+use crate::column::{checked_range, swap_bytes};
+use crate::dtype::{ByteOrder, MmappetType};
+use crate::error::{MmappetError, Result};
+use crate::schema::Schema;
+
+/// Default size of cached pages.
+const PAGE_SIZE: usize = 1 << 20;
+/// Default number of cached pages.
+const CACHE_PAGES: usize = 64;
+/// How long to wait for a response before giving up.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A dataset on an HTTP file server, e.g. `http://files:8080/runs/a.mmappet`.
+///
+/// Opening reads the schema and the sizes of the column files; values are then
+/// fetched with Range requests for just the rows asked for, through an LRU
+/// cache of fixed-size pages. Since pages may be evicted, values are returned
+/// as owned vectors rather than slices.
+///
+/// Plain `http://` URLs are read with Range requests. With the
+/// `object_store` feature, `s3://`, `gs://`, `az://` and `file://` URLs are
+/// read with ranged GETs through the `object_store` crate, with credentials
+/// taken from the environment (`AWS_*`, `GOOGLE_*`, `AZURE_*`); see
+/// [`upload`](crate::upload) for writing datasets there. Only columns whose
+/// files are stored unencoded in the dataset itself are supported (no
+/// references to other datasets, no compressed or delta-encoded files).
+///
+/// ```rust,no_run
+/// use mmappet::RemoteDataset;
+///
+/// let ds = RemoteDataset::open("http://files:8080/runs/a.mmappet")?;
+/// let tof: Vec<u32> = ds.slice("tof", 0..1000)?;
+/// for chunk in ds.chunks::<f32>("intensity", 1 << 20)? {
+///     println!("{}", chunk?.iter().sum::<f32>());
+/// }
+/// # Ok::<(), mmappet::MmappetError>(())
+/// ```
+pub struct RemoteDataset {
+    server: Server,
+    schema: Schema,
+    row_count: usize,
+    cache: Mutex<PageCache>,
+}
+
+impl RemoteDataset {
+    /// Open the dataset at `url`, caching up to 64 pages of 1 MiB.
+    pub fn open(url: &str) -> Result<Self> {
+        Self::open_with_cache(url, PAGE_SIZE, CACHE_PAGES)
+    }
+
+    /// Open the dataset at `url`, caching up to `pages` pages of `page_size`
+    /// bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `page_size` or `pages` is zero.
+    pub fn open_with_cache(url: &str, page_size: usize, pages: usize) -> Result<Self> {
+        assert!(page_size > 0 && pages > 0, "page_size and pages must be non-zero");
+        let server = Server::parse(url)?;
+        let schema = match server.get_text("schema.json")? {
+            Some(json) => Schema::parse_json(&json),
+            None => match server.get_text("schema.txt")? {
+                Some(text) => Schema::parse(&text),
+                None => Err(server.error("schema.txt", "missing schema".to_string())),
+            },
+        }?;
+
+        let mut row_count = None;
+        let mut sizes = Vec::with_capacity(schema.len());
+        for def in schema.columns() {
+            let file = format!("{}.bin", def.index);
+            if def.reference.is_some() {
+                let message = format!("column '{}' refers to another dataset", def.name);
+                return Err(server.error(&file, message));
+            }
+            let size = server.size(&file)?;
+            let row_size = def.dtype.size_bytes() * def.width();
+            if !size.is_multiple_of(row_size) {
+                let message = format!("{} bytes aren't whole rows of {} bytes", size, row_size);
+                return Err(server.error(&file, message));
+            }
+            let len = size / row_size;
+            match row_count {
+                None => row_count = Some(len),
+                Some(expected) if len != expected => {
+                    return Err(MmappetError::LengthMismatch {
+                        path: None,
+                        column: def.name.clone(),
+                        expected,
+                        actual: len,
+                    });
+                }
+                Some(_) => {}
+            }
+            sizes.push(size);
+        }
+
+        Ok(RemoteDataset {
+            server,
+            schema,
+            row_count: row_count.unwrap_or(0),
+            cache: Mutex::new(PageCache {
+                page_size,
+                capacity: pages,
+                sizes,
+                pages: HashMap::new(),
+                tick: 0,
+            }),
+        })
+    }
+
+    /// Get the schema.
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Get the number of rows.
+    pub fn len(&self) -> usize {
+        self.row_count
+    }
+
+    /// Check if the dataset has no rows.
+    pub fn is_empty(&self) -> bool {
+        self.row_count == 0
+    }
+
+    /// Get all values of a column, row after row.
+    ///
+    /// Returns an error if the column doesn't exist, the type doesn't match
+    /// or a request fails.
+    pub fn get<T: MmappetType>(&self, name: &str) -> Result<Vec<T>> {
+        self.slice(name, ..)
+    }
+
+    /// Get the values of `rows` of a column, fetching only the pages they're
+    /// on that aren't cached.
+    ///
+    /// Returns an error if the column doesn't exist, the type doesn't match
+    /// or a request fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rows` is out of bounds.
+    pub fn slice<T: MmappetType>(
+        &self,
+        name: &str,
+        rows: impl RangeBounds<usize>,
+    ) -> Result<Vec<T>> {
+        let def = self.schema.get(name).ok_or_else(|| MmappetError::ColumnNotFound {
+            path: None,
+            column: name.to_string(),
+        })?;
+        if T::DTYPE != def.dtype {
+            return Err(MmappetError::TypeMismatch {
+                column: Some(name.to_string()),
+                expected: T::DTYPE,
+                actual: def.dtype,
+            });
+        }
+        let rows = checked_range(rows, self.row_count);
+        let width = def.width();
+        let mut values = vec![T::zeroed(); rows.len() * width];
+        let bytes: &mut [u8] = cast_slice_mut(&mut values);
+        let row_size = std::mem::size_of::<T>() * width;
+        let range = rows.start * row_size..rows.end * row_size;
+        self.cache.lock().unwrap().read(&self.server, def.index, range, bytes)?;
+        if self.schema.byte_order() != ByteOrder::native() {
+            swap_bytes(bytes, std::mem::size_of::<T>());
+        }
+        Ok(values)
+    }
+
+    /// Iterate over the values of a column in chunks of `chunk_rows` rows,
+    /// each fetched when the iterator reaches it. The last chunk may be
+    /// shorter.
+    ///
+    /// Returns an error if the column doesn't exist or the type doesn't
+    /// match.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_rows` is zero.
+    pub fn chunks<T: MmappetType>(
+        &self,
+        name: &str,
+        chunk_rows: usize,
+    ) -> Result<RemoteChunks<'_, T>> {
+        assert!(chunk_rows > 0, "chunk_rows must be non-zero");
+        self.slice::<T>(name, 0..0)?;
+        Ok(RemoteChunks {
+            dataset: self,
+            name: name.to_string(),
+            chunk_rows,
+            offset: 0,
+            _marker: PhantomData,
+        })
+    }
+}
+
+/// Iterator over chunks of a column of a [`RemoteDataset`], see
+/// [`RemoteDataset::chunks`].
+pub struct RemoteChunks<'a, T> {
+    dataset: &'a RemoteDataset,
+    name: String,
+    chunk_rows: usize,
+    offset: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: MmappetType> Iterator for RemoteChunks<'_, T> {
+    type Item = Result<Vec<T>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.dataset.len() {
+            return None;
+        }
+        let end = (self.offset + self.chunk_rows).min(self.dataset.len());
+        let chunk = self.dataset.slice(&self.name, self.offset..end);
+        self.offset = end;
+        Some(chunk)
+    }
+}
+
+/// Pages of column files, evicting the least recently used.
+struct PageCache {
+    page_size: usize,
+    /// Maximum number of pages.
+    capacity: usize,
+    /// Size of each column file, by schema position.
+    sizes: Vec<usize>,
+    /// Pages by column and page number, with the tick they were last used.
+    pages: HashMap<(usize, usize), (Vec<u8>, u64)>,
+    tick: u64,
+}
+
+impl PageCache {
+    /// Read `bytes` of the file of column `index` into `out`, fetching runs
+    /// of missing pages with one request each.
+    fn read(
+        &mut self,
+        server: &Server,
+        index: usize,
+        bytes: Range<usize>,
+        out: &mut [u8],
+    ) -> Result<()> {
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        let page_size = self.page_size;
+        let mut copy = |page: usize, data: &[u8]| {
+            let start = (page * page_size).max(bytes.start);
+            let end = (page * page_size + data.len()).min(bytes.end);
+            let from = start - page * page_size..end - page * page_size;
+            out[start - bytes.start..end - bytes.start].copy_from_slice(&data[from]);
+        };
+
+        let last = bytes.end.div_ceil(page_size);
+        let mut page = bytes.start / page_size;
+        while page < last {
+            self.tick += 1;
+            if let Some((data, used)) = self.pages.get_mut(&(index, page)) {
+                *used = self.tick;
+                copy(page, data);
+                page += 1;
+                continue;
+            }
+            let mut end = page + 1;
+            while end < last && !self.pages.contains_key(&(index, end)) {
+                end += 1;
+            }
+            let file = format!("{}.bin", index);
+            let range = page * page_size..(end * page_size).min(self.sizes[index]);
+            let data = server.fetch(&file, range)?;
+            for (i, data) in data.chunks(page_size).enumerate() {
+                copy(page + i, data);
+                self.insert((index, page + i), data.to_vec());
+            }
+            page = end;
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, key: (usize, usize), data: Vec<u8>) {
+        if self.pages.len() >= self.capacity {
+            let oldest = self.pages.iter().min_by_key(|(_, (_, used))| *used).map(|(k, _)| *k);
+            if let Some(oldest) = oldest {
+                self.pages.remove(&oldest);
+            }
+        }
+        self.pages.insert(key, (data, self.tick));
+    }
+}
+
+/// Where the files of a remote dataset are fetched from.
+enum Server {
+    Http(HttpServer),
+    #[cfg(feature = "object_store")]
+    Store(Store),
+}
+
+impl Server {
+    fn parse(url: &str) -> Result<Self> {
+        #[cfg(feature = "object_store")]
+        if !url.starts_with("http://") {
+            return Store::parse(url).map(Server::Store);
+        }
+        HttpServer::parse(url).map(Server::Http)
+    }
+
+    fn error(&self, file: &str, message: String) -> MmappetError {
+        match self {
+            Server::Http(server) => server.error(file, message),
+            #[cfg(feature = "object_store")]
+            Server::Store(store) => store.error(file, message),
+        }
+    }
+
+    /// The text of `file`, or `None` if there is no such file.
+    fn get_text(&self, file: &str) -> Result<Option<String>> {
+        match self {
+            Server::Http(server) => server.get_text(file),
+            #[cfg(feature = "object_store")]
+            Server::Store(store) => store.get_text(file),
+        }
+    }
+
+    /// Size of `file` in bytes.
+    fn size(&self, file: &str) -> Result<usize> {
+        match self {
+            Server::Http(server) => server.size(file),
+            #[cfg(feature = "object_store")]
+            Server::Store(store) => store.size(file),
+        }
+    }
+
+    /// Bytes `range` of `file`.
+    fn fetch(&self, file: &str, range: Range<usize>) -> Result<Vec<u8>> {
+        match self {
+            Server::Http(server) => server.fetch(file, range),
+            #[cfg(feature = "object_store")]
+            Server::Store(store) => store.fetch(file, range),
+        }
+    }
+}
+
+/// An HTTP server and the directory of a dataset on it.
+struct HttpServer {
+    /// `host[:port]`, for the `Host` header.
+    authority: String,
+    host: String,
+    port: u16,
+    /// Path of the dataset directory, ending in `/`.
+    path: String,
+}
+
+/// Status and body of an HTTP response.
+struct Response {
+    status: u16,
+    content_length: Option<usize>,
+    body: Vec<u8>,
+}
+
+impl HttpServer {
+    fn parse(url: &str) -> Result<Self> {
+        let invalid = |message: &str| MmappetError::Http {
+            url: url.to_string(),
+            message: message.to_string(),
+        };
+        let rest = url.strip_prefix("http://").ok_or_else(|| {
+            invalid("only http:// URLs are supported; serve https through a local proxy")
+        })?;
+        let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| invalid("invalid port"))?),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(invalid("missing host"));
+        }
+        Ok(HttpServer {
+            authority: authority.to_string(),
+            host: host.to_string(),
+            port,
+            path: format!("{}/", path.trim_end_matches('/')),
+        })
+    }
+
+    fn url(&self, file: &str) -> String {
+        format!("http://{}{}{}", self.authority, self.path, file)
+    }
+
+    fn error(&self, file: &str, message: String) -> MmappetError {
+        MmappetError::Http {
+            url: self.url(file),
+            message,
+        }
+    }
+
+    /// The text of `file`, or `None` if the server doesn't have it.
+    fn get_text(&self, file: &str) -> Result<Option<String>> {
+        let response = self.request("GET", file, None)?;
+        match response.status {
+            404 => Ok(None),
+            200 => String::from_utf8(response.body)
+                .map(Some)
+                .map_err(|_| self.error(file, "not UTF-8".to_string())),
+            status => Err(self.error(file, format!("status {}", status))),
+        }
+    }
+
+    /// Size of `file` in bytes, from a `HEAD` request.
+    fn size(&self, file: &str) -> Result<usize> {
+        let response = self.request("HEAD", file, None)?;
+        match (response.status, response.content_length) {
+            (200, Some(size)) => Ok(size),
+            (200, None) => Err(self.error(file, "no Content-Length".to_string())),
+            (404, _) => Err(self.error(file, "missing column file".to_string())),
+            (status, _) => Err(self.error(file, format!("status {}", status))),
+        }
+    }
+
+    /// Bytes `range` of `file`. Servers ignoring the Range header are
+    /// tolerated, at the cost of transferring the whole file.
+    fn fetch(&self, file: &str, range: Range<usize>) -> Result<Vec<u8>> {
+        let mut response = self.request("GET", file, Some(&range))?;
+        let body = match response.status {
+            206 => response.body,
+            200 if response.body.len() >= range.end => {
+                response.body.truncate(range.end);
+                response.body.split_off(range.start)
+            }
+            status => return Err(self.error(file, format!("status {}", status))),
+        };
+        if body.len() != range.len() {
+            let message = format!("expected {} bytes, got {}", range.len(), body.len());
+            return Err(self.error(file, message));
+        }
+        Ok(body)
+    }
+
+    /// Send one request on a new connection and read the response.
+    fn request(&self, method: &str, file: &str, range: Option<&Range<usize>>) -> Result<Response> {
+        self.try_request(method, file, range).map_err(|e| self.error(file, e.to_string()))
+    }
+
+    fn try_request(
+        &self,
+        method: &str,
+        file: &str,
+        range: Option<&Range<usize>>,
+    ) -> io::Result<Response> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        let mut head = format!(
+            "{} {}{} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n",
+            method, self.path, file, self.authority
+        );
+        if let Some(range) = range {
+            head.push_str(&format!("Range: bytes={}-{}\r\n", range.start, range.end - 1));
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes())?;
+
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let status = line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| invalid("invalid status line"))?;
+        let (mut content_length, mut chunked) = (None, false);
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(invalid("response ends in the headers"));
+            }
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                let value = value.trim();
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.parse().ok();
+                } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                    chunked = value.eq_ignore_ascii_case("chunked");
+                }
+            }
+        }
+
+        // Responses to HEAD have headers only
+        let mut body = Vec::new();
+        match (method, content_length) {
+            ("HEAD", _) => {}
+            _ if chunked => read_chunked(&mut reader, &mut body)?,
+            (_, Some(len)) => {
+                body.resize(len, 0);
+                reader.read_exact(&mut body)?;
+            }
+            (_, None) => {
+                reader.read_to_end(&mut body)?;
+            }
+        }
+        Ok(Response {
+            status,
+            content_length,
+            body,
+        })
+    }
+}
+
+/// Read a body in chunked transfer encoding, ignoring trailers.
+fn read_chunked(reader: &mut impl BufRead, body: &mut Vec<u8>) -> io::Result<()> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let size = line.trim_end().split(';').next().unwrap_or("");
+        let size = usize::from_str_radix(size.trim(), 16)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid chunk size"))?;
+        if size == 0 {
+            return Ok(());
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..])?;
+        line.clear();
+        reader.read_line(&mut line)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::write_dataset;
+    use std::fs;
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Serve the files of `dir` under `/data/`, counting requests; returns the
+    /// dataset's URL.
+    fn serve(dir: PathBuf, requests: Arc<AtomicUsize>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/data", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let (mut request, mut range) = (String::new(), None);
+                reader.read_line(&mut request).unwrap();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(bytes) = line.trim().strip_prefix("Range: bytes=") {
+                        let (start, end) = bytes.split_once('-').unwrap();
+                        range = Some(start.parse().unwrap()..end.parse::<usize>().unwrap() + 1);
+                    }
+                }
+                requests.fetch_add(1, Ordering::SeqCst);
+                let mut parts = request.split_whitespace();
+                let (method, path) = (parts.next().unwrap(), parts.next().unwrap());
+                let response = match fs::read(dir.join(path.trim_start_matches("/data/"))) {
+                    Ok(bytes) => {
+                        let (status, body) = match range {
+                            Some(range) => ("206 Partial Content", bytes[range].to_vec()),
+                            None => ("200 OK", bytes),
+                        };
+                        let head = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\n\r\n", status, body.len());
+                        let mut response = head.into_bytes();
+                        if method != "HEAD" {
+                            response.extend(body);
+                        }
+                        response
+                    }
+                    Err(_) => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_vec(),
+                };
+                stream.write_all(&response).unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn test_remote_dataset() {
+        let tof: Vec<u32> = (0..10).map(|i| i * 10).collect();
+        let dir = write_dataset("remote", &[("tof", &tof), ("frame", &[1; 10])]);
+        let requests = Arc::new(AtomicUsize::new(0));
+        let url = serve(dir.clone(), Arc::clone(&requests));
+
+        // schema.json (missing), schema.txt and the sizes of two files
+        let ds = RemoteDataset::open_with_cache(&url, 16, 2).unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+        assert_eq!(ds.len(), 10);
+
+        // Rows 1..3 are in the first page of 16 bytes
+        assert_eq!(ds.slice::<u32>("tof", 1..3).unwrap(), [10, 20]);
+        assert_eq!(ds.slice::<u32>("tof", 0..4).unwrap(), [0, 10, 20, 30]);
+        assert_eq!(requests.load(Ordering::SeqCst), 5);
+        // The two missing pages are fetched in one request
+        assert_eq!(ds.get::<u32>("tof").unwrap(), tof);
+        assert_eq!(requests.load(Ordering::SeqCst), 6);
+
+        let chunks: Vec<Vec<u32>> = ds.chunks("frame", 4).unwrap().map(|c| c.unwrap()).collect();
+        assert_eq!(chunks, [vec![1; 4], vec![1; 4], vec![1; 2]]);
+        assert!(matches!(ds.get::<f32>("tof"), Err(MmappetError::TypeMismatch { .. })));
+        assert!(matches!(ds.get::<u32>("missing"), Err(MmappetError::ColumnNotFound { .. })));
+        assert!(matches!(
+            RemoteDataset::open(&format!("{}/nothing", url)),
+            Err(MmappetError::Http { .. })
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
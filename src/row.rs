@@ -0,0 +1,208 @@
+//! Mapping between Rust structs and dataset rows.
+
+use std::marker::PhantomData;
+
+use crate::column::Column;
+use crate::dataset::Dataset;
+use crate::dtype::DType;
+use crate::error::{MmappetError, Result};
+use crate::schema::Schema;
+use crate::writer::DatasetWriter;
+
+/// A struct whose fields are the columns of a dataset row.
+///
+/// Usually derived with `#[derive(MmappetRow)]` (feature `derive`):
+///
+/// ```rust,ignore
+/// use mmappet::{Dataset, DatasetWriter, MmappetRow};
+///
+/// #[derive(MmappetRow)]
+/// struct Peak {
+///     tof: u32,
+///     intensity: u32,
+///     mz: f32,
+/// }
+///
+/// let mut writer = DatasetWriter::create("peaks.mmappet", &Peak::schema())?;
+/// writer.push(&Peak { tof: 10, intensity: 200, mz: 401.5 })?;
+/// writer.finish()?;
+///
+/// let ds = Dataset::open("peaks.mmappet")?;
+/// for peak in ds.rows::<Peak>()? {
+///     println!("{} {}", peak.mz, peak.intensity);
+/// }
+/// ```
+pub trait MmappetRow: Sized {
+    /// Column names and dtypes, in field order.
+    const COLUMNS: &'static [(&'static str, DType)];
+
+    /// Schema with one column per field.
+    ///
+    /// # Panics
+    ///
+    /// Panics if two fields map to the same column name.
+    fn schema() -> Schema {
+        Schema::new(Self::COLUMNS).expect("MmappetRow column names must be unique")
+    }
+
+    /// Build the value at `row` from `columns`, given in field order and
+    /// already checked to match [`MmappetRow::COLUMNS`].
+    fn read(columns: &[&Column], row: usize) -> Self;
+
+    /// Append the fields to the writer columns at schema positions `indices`,
+    /// given in field order and already checked to match the field dtypes.
+    fn write(&self, writer: &mut DatasetWriter, indices: &[usize]) -> Result<()>;
+}
+
+impl Dataset {
+    /// Iterate over all rows as values of `R`.
+    ///
+    /// Returns an error if a column of `R` is missing, holds arrays or has
+    /// another dtype. Columns of the dataset that `R` doesn't mention are ignored.
+    pub fn rows<R: MmappetRow>(&self) -> Result<Rows<'_, R>> {
+        let columns = R::COLUMNS
+            .iter()
+            .map(|&(name, dtype)| {
+                let column = self
+                    .column(name)
+                    .ok_or_else(|| self.column_not_found(name))?;
+                column.check_scalar(name)?;
+                if column.dtype() != dtype {
+                    return Err(MmappetError::TypeMismatch {
+                        column: Some(name.to_string()),
+                        expected: dtype,
+                        actual: column.dtype(),
+                    });
+                }
+                Ok(column)
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Rows {
+            columns,
+            row: 0,
+            len: self.len(),
+            marker: PhantomData,
+        })
+    }
+}
+
+impl DatasetWriter {
+    /// Append one row given as a value of `R`.
+    ///
+    /// Nothing is written if a column of `R` is missing from the schema, holds
+    /// arrays or has another dtype. Schema columns that `R` doesn't mention are left behind,
+    /// which makes [`DatasetWriter::finish`] fail.
+    pub fn push<R: MmappetRow>(&mut self, row: &R) -> Result<()> {
+        let indices = R::COLUMNS
+            .iter()
+            .map(|&(name, dtype)| {
+                let col = self
+                    .schema()
+                    .get(name)
+                    .ok_or_else(|| MmappetError::ColumnNotFound {
+                        path: None,
+                        column: name.to_string(),
+                    })?;
+                if col.dtype != dtype {
+                    return Err(MmappetError::TypeMismatch {
+                        column: Some(name.to_string()),
+                        expected: col.dtype,
+                        actual: dtype,
+                    });
+                }
+                if col.width() > 1 {
+                    return Err(MmappetError::NotScalar {
+                        column: name.to_string(),
+                        width: col.width(),
+                    });
+                }
+                Ok(col.index)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        row.write(self, &indices)
+    }
+}
+
+/// Iterator over the rows of a dataset, created by [`Dataset::rows`].
+pub struct Rows<'a, R> {
+    columns: Vec<&'a Column>,
+    row: usize,
+    len: usize,
+    marker: PhantomData<R>,
+}
+
+impl<R: MmappetRow> Iterator for Rows<'_, R> {
+    type Item = R;
+
+    fn next(&mut self) -> Option<R> {
+        if self.row >= self.len {
+            return None;
+        }
+        let value = R::read(&self.columns, self.row);
+        self.row += 1;
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.len - self.row;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<R: MmappetRow> ExactSizeIterator for Rows<'_, R> {}
+
+#[cfg(all(test, feature = "derive"))]
+mod tests {
+    use crate::{Dataset, DatasetWriter, MmappetError, MmappetRow};
+
+    #[derive(MmappetRow, Debug, PartialEq)]
+    struct Peak {
+        tof: u32,
+        #[mmappet(rename = "inten")]
+        intensity: u32,
+        mz: f32,
+    }
+
+    #[derive(MmappetRow)]
+    struct Tof {
+        tof: u64,
+    }
+
+    #[test]
+    fn test_derive_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("mmappet-rows-{}", std::process::id()));
+        assert_eq!(Peak::schema().column_names(), vec!["tof", "inten", "mz"]);
+
+        let peaks = [
+            Peak {
+                tof: 1,
+                intensity: 10,
+                mz: 400.5,
+            },
+            Peak {
+                tof: 2,
+                intensity: 20,
+                mz: 512.25,
+            },
+        ];
+        let mut writer = DatasetWriter::create(&dir, &Peak::schema()).unwrap();
+        for peak in &peaks {
+            writer.push(peak).unwrap();
+        }
+        assert!(matches!(
+            writer.push(&Tof { tof: 3 }),
+            Err(MmappetError::TypeMismatch { .. })
+        ));
+        assert_eq!(writer.finish().unwrap(), 2);
+
+        let ds = Dataset::open(&dir).unwrap();
+        let rows = ds.rows::<Peak>().unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows.collect::<Vec<_>>(), peaks);
+        assert!(ds.rows::<Tof>().is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,206 @@
+//! Random samples of rows, e.g. for quick looks at very large datasets.
+
+use crate::concat::ConcatDataset;
+use crate::dataset::Dataset;
+
+/// Small seeded generator (SplitMix64): the same seed gives the same sample
+/// on every platform.
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `(0, 1]`, so its logarithm is finite.
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in `0..n`.
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+}
+
+/// Uniform sample of `n` rows of a stream of rows offered chunk by chunk,
+/// without knowing its length in advance (Li's Algorithm L, which skips
+/// ahead instead of drawing a number per row).
+pub(crate) struct Reservoir {
+    rng: Rng,
+    rows: Vec<usize>,
+    capacity: usize,
+    /// Rows offered so far.
+    seen: usize,
+    /// Next row to enter the reservoir once it's full.
+    next: usize,
+    weight: f64,
+}
+
+impl Reservoir {
+    pub(crate) fn new(capacity: usize, seed: u64) -> Self {
+        Reservoir {
+            rng: Rng::new(seed),
+            rows: Vec::with_capacity(capacity),
+            capacity,
+            seen: 0,
+            next: 0,
+            weight: 1.0,
+        }
+    }
+
+    /// Offer the next `count` rows of the stream.
+    pub(crate) fn extend(&mut self, count: usize) {
+        let end = self.seen + count;
+        let fill = (self.capacity - self.rows.len()).min(count);
+        self.rows.extend(self.seen..self.seen + fill);
+        if fill > 0 && self.rows.len() == self.capacity {
+            self.weight = self.draw_weight();
+            self.next = (self.seen + fill - 1).saturating_add(self.skip());
+        }
+        self.seen += fill;
+        let full = self.capacity > 0 && self.rows.len() == self.capacity;
+        while full && self.next < end {
+            let slot = self.rng.below(self.capacity);
+            self.rows[slot] = self.next;
+            self.weight *= self.draw_weight();
+            self.next = self.next.saturating_add(self.skip());
+        }
+        self.seen = end;
+    }
+
+    /// The sampled rows, in order.
+    pub(crate) fn into_rows(mut self) -> Vec<usize> {
+        self.rows.sort_unstable();
+        self.rows
+    }
+
+    fn draw_weight(&mut self) -> f64 {
+        (self.rng.next_f64().ln() / self.capacity as f64).exp()
+    }
+
+    /// Distance to the next row entering the reservoir.
+    fn skip(&mut self) -> usize {
+        let skip = (self.rng.next_f64().ln() / (-self.weight).ln_1p()).floor();
+        // Saturates for weights so close to 0 that no later row enters
+        (skip as usize).saturating_add(1)
+    }
+}
+
+/// Rows kept when each is kept with probability `p`, in order, drawing one
+/// number per kept row rather than per row.
+fn bernoulli_rows(len: usize, p: f64, seed: u64) -> Vec<usize> {
+    assert!((0.0..=1.0).contains(&p), "fraction {} is not within 0..=1", p);
+    if p == 0.0 {
+        return Vec::new();
+    }
+    if p == 1.0 {
+        return (0..len).collect();
+    }
+    let mut rng = Rng::new(seed);
+    let mut rows = Vec::with_capacity((len as f64 * p * 1.1) as usize);
+    let mut row = 0usize;
+    loop {
+        // Rows skipped before the next kept one are geometrically distributed
+        let skip = (rng.next_f64().ln() / (-p).ln_1p()).floor();
+        row = row.saturating_add(skip as usize);
+        if row >= len {
+            return rows;
+        }
+        rows.push(row);
+        row += 1;
+    }
+}
+
+impl Dataset {
+    /// Rows of a uniform random sample of `n` rows without replacement, in
+    /// order, or all rows if there are fewer. The same `seed` gives the same
+    /// sample.
+    ///
+    /// Takes time in the order of `n`, not of the row count, so it's cheap
+    /// on billions of rows. Gather the sample with [`Dataset::take`]:
+    ///
+    /// ```rust,no_run
+    /// let ds = mmappet::Dataset::open("peaks.mmappet")?;
+    /// let rows = ds.sample(10_000, 42);
+    /// ds.take(&rows)?.write_to("peaks_sample.mmappet")?;
+    /// # Ok::<(), mmappet::MmappetError>(())
+    /// ```
+    pub fn sample(&self, n: usize, seed: u64) -> Vec<usize> {
+        let mut reservoir = Reservoir::new(n, seed);
+        reservoir.extend(self.len());
+        reservoir.into_rows()
+    }
+
+    /// Rows of a random sample keeping each row with probability `p`, in
+    /// order. The same `seed` gives the same sample.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `p` isn't within `0..=1`.
+    pub fn sample_fraction(&self, p: f64, seed: u64) -> Vec<usize> {
+        bernoulli_rows(self.len(), p, seed)
+    }
+}
+
+impl ConcatDataset {
+    /// Rows of a uniform random sample of `n` rows across all parts, as by
+    /// [`Dataset::sample`]; sampling streams through the parts one by one.
+    pub fn sample(&self, n: usize, seed: u64) -> Vec<usize> {
+        let mut reservoir = Reservoir::new(n, seed);
+        for part in self.parts() {
+            reservoir.extend(part.len());
+        }
+        reservoir.into_rows()
+    }
+
+    /// Rows of a random sample keeping each row with probability `p`, as by
+    /// [`Dataset::sample_fraction`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `p` isn't within `0..=1`.
+    pub fn sample_fraction(&self, p: f64, seed: u64) -> Vec<usize> {
+        bernoulli_rows(self.len(), p, seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample() {
+        let ds = Dataset::from_columns(vec![("id", (0..10_000u32).collect::<Vec<_>>().into())])
+            .unwrap();
+
+        let rows = ds.sample(100, 7);
+        assert_eq!(rows.len(), 100);
+        assert!(rows.windows(2).all(|w| w[0] < w[1]));
+        assert!(*rows.last().unwrap() < 10_000);
+        assert_eq!(rows, ds.sample(100, 7));
+        assert_ne!(rows, ds.sample(100, 8));
+        assert_eq!(ds.sample(20_000, 7).len(), 10_000);
+
+        // Streaming in chunks gives the same sample
+        let mut reservoir = Reservoir::new(100, 7);
+        for _ in 0..10 {
+            reservoir.extend(1_000);
+        }
+        assert_eq!(reservoir.into_rows(), rows);
+
+        let rows = ds.sample_fraction(0.1, 7);
+        assert!((800..1200).contains(&rows.len()));
+        assert!(rows.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(ds.sample_fraction(1.0, 7).len(), 10_000);
+        assert!(ds.sample_fraction(0.0, 7).is_empty());
+    }
+}
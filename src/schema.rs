@@ -2,9 +2,11 @@
 
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use crate::dtype::DType;
+use serde::{Deserialize, Serialize};
+
+use crate::dtype::{ByteOrder, DType};
 use crate::error::{MmappetError, Result};
 
 /// A single column definition from the schema.
@@ -16,22 +18,146 @@ pub struct ColumnDef {
     pub name: String,
     /// Data type.
     pub dtype: DType,
+    /// Shape of the values of each row: empty for one value, `[4]` for
+    /// arrays declared as `float32[4]`, `[3, 4]` for matrices declared as
+    /// `float32[3,4]`.
+    pub shape: Vec<usize>,
+    /// Column stored in another dataset, if this column is a reference.
+    pub reference: Option<ColumnRef>,
+    /// Descriptive attributes, only stored in schema.json.
+    pub attributes: ColumnAttributes,
+}
+
+/// Optional descriptors of a column, see [`Schema::parse_json`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColumnAttributes {
+    /// Values never decrease; checked by [`Dataset::validate`](crate::Dataset::validate).
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub sorted: bool,
+    /// Physical unit of the values, e.g. `ns` or `Th`.
+    #[serde(alias = "units", skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    /// Free-form description.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Value standing for a missing one, compared as `f64`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub null_value: Option<f64>,
+    /// Factor converting stored values to physical ones, see
+    /// [`ColumnAttributes::physical`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<f64>,
+    /// Added to stored values after scaling.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<f64>,
+}
+
+impl ColumnDef {
+    /// Type as written in schemas, e.g. `uint32` or `float32[4]`.
+    pub fn type_name(&self) -> String {
+        type_name(self.dtype, &self.shape)
+    }
+
+    /// Values per row, the product of [`ColumnDef::shape`].
+    pub fn width(&self) -> usize {
+        self.shape.iter().product()
+    }
 }
 
+impl ColumnAttributes {
+    /// Convert a stored value to its physical value, `value * scale + offset`.
+    pub fn physical(&self, value: f64) -> f64 {
+        value * self.scale.unwrap_or(1.0) + self.offset.unwrap_or(0.0)
+    }
+}
+
+/// A column of another dataset, referenced instead of stored locally.
+///
+/// Declared in schema.txt as `{dtype} {name} @{dataset}/{column}`, e.g.
+/// `float64 mz_axis @../calibration.mmappet/mz`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnRef {
+    /// Path of the other dataset, relative to the referencing dataset's directory.
+    pub dataset: PathBuf,
+    /// Column name in the other dataset.
+    pub column: String,
+}
+
+impl ColumnRef {
+    /// Parse the `@{dataset}/{column}` form.
+    fn parse(s: &str) -> Option<Self> {
+        let target = s.strip_prefix('@')?;
+        let (dataset, column) = target.rsplit_once('/')?;
+        if dataset.is_empty() || column.is_empty() {
+            return None;
+        }
+        Some(ColumnRef {
+            dataset: PathBuf::from(dataset),
+            column: column.to_string(),
+        })
+    }
+}
+
+/// Newest format version this build reads and the one it writes.
+///
+/// Declared on the first line of schema.txt as `# mmappet-format {version}`;
+/// schemas without that line are version 1.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Prefix of the format version line.
+const VERSION_PREFIX: &str = "# mmappet-format ";
+/// Prefix of the byte order line.
+const BYTE_ORDER_PREFIX: &str = "# mmappet-byte-order ";
+
 /// Parsed schema from schema.txt.
 #[derive(Debug, Clone)]
 pub struct Schema {
     columns: Vec<ColumnDef>,
     name_to_index: HashMap<String, usize>,
+    version: u32,
+    byte_order: ByteOrder,
 }
 
 impl Schema {
+    /// Build a schema of locally stored columns from `(name, dtype)` pairs.
+    ///
+    /// Names must be non-empty, contain no whitespace and be unique.
+    pub fn new(columns: &[(&str, DType)]) -> Result<Self> {
+        let mut content = format!(
+            "{}{}\n{}{}\n",
+            VERSION_PREFIX,
+            FORMAT_VERSION,
+            BYTE_ORDER_PREFIX,
+            ByteOrder::native()
+        );
+        for (index, (name, dtype)) in columns.iter().enumerate() {
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return Err(MmappetError::SchemaParse {
+                    path: None,
+                    line: index + 1,
+                    message: format!("Invalid column name: '{}'", name),
+                });
+            }
+            content.push_str(&format!("{} {}\n", dtype, name));
+        }
+        Self::parse(&content)
+    }
+
     /// Parse schema from schema.txt content.
     ///
-    /// Format: `{dtype} {colname}` per line (e.g., "uint32 tof")
+    /// Format: `{dtype} {colname}` per line (e.g., "uint32 tof" or
+    /// "float32[4] quaternion" for rows of four values), optionally
+    /// followed by a reference to a column of another dataset (see [`ColumnRef`]).
+    /// Lines starting with `#` are comments, except the format version and
+    /// byte order (`# mmappet-byte-order little|big`) lines; schemas without
+    /// the latter are in the host's byte order. Returns [`MmappetError::UnsupportedVersion`] for versions newer than
+    /// [`FORMAT_VERSION`].
     pub fn parse(content: &str) -> Result<Self> {
         let mut columns = Vec::new();
         let mut name_to_index = HashMap::new();
+        let mut version = 1;
+        let mut byte_order = ByteOrder::native();
 
         for (line_num, line) in content.lines().enumerate() {
             let line = line.trim();
@@ -40,43 +166,321 @@ impl Schema {
             if line.is_empty() {
                 continue;
             }
+            if let Some(number) = line.strip_prefix(VERSION_PREFIX) {
+                version = number.trim().parse().map_err(|_| MmappetError::SchemaParse {
+                    path: None,
+                    line: line_num + 1,
+                    message: format!("Invalid format version: {}", number),
+                })?;
+                if version > FORMAT_VERSION {
+                    return Err(MmappetError::UnsupportedVersion {
+                        path: None,
+                        version,
+                        supported: FORMAT_VERSION,
+                    });
+                }
+                continue;
+            }
+            if let Some(order) = line.strip_prefix(BYTE_ORDER_PREFIX) {
+                byte_order = ByteOrder::from_str(order).ok_or_else(|| MmappetError::SchemaParse {
+                    path: None,
+                    line: line_num + 1,
+                    message: format!("Invalid byte order: {}", order),
+                })?;
+                continue;
+            }
+            if line.starts_with('#') {
+                continue;
+            }
 
-            // Split into dtype and name
+            // Split into dtype, name and optional reference
             let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() != 2 {
+            if parts.len() != 2 && parts.len() != 3 {
                 return Err(MmappetError::SchemaParse {
+                    path: None,
                     line: line_num + 1,
                     message: format!("Expected 'dtype name', got: {}", line),
                 });
             }
 
-            let dtype = DType::from_str(parts[0])?;
+            let (dtype, shape) = parse_type(parts[0]).map_err(|e| MmappetError::SchemaParse {
+                path: None,
+                line: line_num + 1,
+                message: e.to_string(),
+            })?;
             let name = parts[1].to_string();
+            let reference = match parts.get(2) {
+                Some(target) => match ColumnRef::parse(target) {
+                    Some(reference) => Some(reference),
+                    None => {
+                        return Err(MmappetError::SchemaParse {
+                            path: None,
+                            line: line_num + 1,
+                            message: format!("Expected '@dataset/column', got: {}", target),
+                        })
+                    }
+                },
+                None => None,
+            };
 
             // Check for duplicates
             if name_to_index.contains_key(&name) {
-                return Err(MmappetError::DuplicateColumnName(name));
+                return Err(MmappetError::DuplicateColumnName {
+                    path: None,
+                    column: name,
+                });
             }
 
             let index = columns.len();
             name_to_index.insert(name.clone(), index);
-            columns.push(ColumnDef { index, name, dtype });
+            columns.push(ColumnDef {
+                index,
+                name,
+                dtype,
+                shape,
+                reference,
+                attributes: ColumnAttributes::default(),
+            });
         }
 
         Ok(Schema {
             columns,
             name_to_index,
+            version,
+            byte_order,
         })
     }
 
-    /// Load schema from a directory path.
+    /// Parse schema from schema.json content.
+    ///
+    /// Format: `{"format_version": 1, "columns": [{"name": "tof", "dtype":
+    /// "uint32"}, ...]}`. Columns may also have a `reference` written as in
+    /// schema.txt (`@dataset/column`) and the [`ColumnAttributes`] fields.
+    /// Unknown fields are ignored.
+    pub fn parse_json(content: &str) -> Result<Self> {
+        let json: JsonSchema =
+            serde_json::from_str(content).map_err(|e| MmappetError::SchemaParse {
+                path: None,
+                line: e.line(),
+                message: e.to_string(),
+            })?;
+        if json.format_version > FORMAT_VERSION {
+            return Err(MmappetError::UnsupportedVersion {
+                path: None,
+                version: json.format_version,
+                supported: FORMAT_VERSION,
+            });
+        }
+
+        let mut columns = Vec::with_capacity(json.columns.len());
+        let mut name_to_index = HashMap::new();
+        for (index, col) in json.columns.into_iter().enumerate() {
+            if name_to_index.insert(col.name.clone(), index).is_some() {
+                return Err(MmappetError::DuplicateColumnName {
+                    path: None,
+                    column: col.name,
+                });
+            }
+            columns.push(ColumnDef {
+                index,
+                name: col.name,
+                dtype: col.dtype.0,
+                shape: col.dtype.1,
+                reference: col.reference,
+                attributes: col.attributes,
+            });
+        }
+
+        Ok(Schema {
+            columns,
+            name_to_index,
+            version: json.format_version,
+            byte_order: json.byte_order,
+        })
+    }
+
+    /// Format the schema as schema.json content, including column attributes.
+    pub fn to_json(&self) -> String {
+        let json = JsonSchema {
+            format_version: self.version,
+            byte_order: self.byte_order,
+            columns: self
+                .columns
+                .iter()
+                .map(|col| JsonColumn {
+                    name: col.name.clone(),
+                    dtype: (col.dtype, col.shape.clone()),
+                    reference: col.reference.clone(),
+                    attributes: col.attributes.clone(),
+                })
+                .collect(),
+        };
+        serde_json::to_string_pretty(&json).unwrap() + "\n"
+    }
+
+    /// Check if any column has attributes, which only schema.json can hold.
+    pub fn has_attributes(&self) -> bool {
+        self.columns
+            .iter()
+            .any(|col| col.attributes != ColumnAttributes::default())
+    }
+
+    /// Load schema from a directory path, preferring schema.json over
+    /// schema.txt.
     pub fn from_path<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let json_path = dir.as_ref().join("schema.json");
+        if json_path.exists() {
+            let content =
+                fs::read_to_string(&json_path).map_err(|e| MmappetError::io(&json_path, e))?;
+            return Self::parse_json(&content).map_err(|e| e.with_path(&json_path));
+        }
+
         let schema_path = dir.as_ref().join("schema.txt");
         if !schema_path.exists() {
-            return Err(MmappetError::MissingSchema(dir.as_ref().to_path_buf()));
+            return Err(MmappetError::MissingSchema {
+                path: dir.as_ref().to_path_buf(),
+            });
         }
-        let content = fs::read_to_string(&schema_path)?;
-        Self::parse(&content)
+        let content =
+            fs::read_to_string(&schema_path).map_err(|e| MmappetError::io(&schema_path, e))?;
+        Self::parse(&content).map_err(|e| e.with_path(&schema_path))
+    }
+
+    /// Set the attributes of the column `name`.
+    ///
+    /// Returns an error if the column doesn't exist.
+    pub fn set_attributes(&mut self, name: &str, attributes: ColumnAttributes) -> Result<()> {
+        let index = *self
+            .name_to_index
+            .get(name)
+            .ok_or_else(|| MmappetError::ColumnNotFound {
+                path: None,
+                column: name.to_string(),
+            })?;
+        self.columns[index].attributes = attributes;
+        Ok(())
+    }
+
+    /// Give every row of the column `name` values of `shape`, e.g. `&[4]`
+    /// for `float32[4]`; see [`ColumnDef::shape`].
+    ///
+    /// Returns an error if the column doesn't exist, `shape` has a zero
+    /// dimension or the column's dtype has no fixed size.
+    pub fn set_shape(&mut self, name: &str, shape: &[usize]) -> Result<()> {
+        let index = *self
+            .name_to_index
+            .get(name)
+            .ok_or_else(|| MmappetError::ColumnNotFound {
+                path: None,
+                column: name.to_string(),
+            })?;
+        check_shape(self.columns[index].dtype, shape)?;
+        self.columns[index].shape = shape.to_vec();
+        Ok(())
+    }
+
+    /// Change the dtype of the column `name`, e.g. for new values written
+    /// in its place.
+    pub(crate) fn set_dtype(&mut self, name: &str, dtype: DType) -> Result<()> {
+        let index = *self
+            .name_to_index
+            .get(name)
+            .ok_or_else(|| MmappetError::ColumnNotFound {
+                path: None,
+                column: name.to_string(),
+            })?;
+        check_shape(dtype, &self.columns[index].shape)?;
+        self.columns[index].dtype = dtype;
+        Ok(())
+    }
+
+    /// Make the column `name` a reference to a column of another dataset, or
+    /// a locally stored one for `None`.
+    pub(crate) fn set_reference(&mut self, name: &str, reference: Option<ColumnRef>) -> Result<()> {
+        let index = *self
+            .name_to_index
+            .get(name)
+            .ok_or_else(|| MmappetError::ColumnNotFound {
+                path: None,
+                column: name.to_string(),
+            })?;
+        self.columns[index].reference = reference;
+        Ok(())
+    }
+
+    /// Add a locally stored column of one value per row after the others.
+    ///
+    /// Returns an error if the name is invalid or already taken.
+    pub(crate) fn push(&mut self, name: &str, dtype: DType) -> Result<()> {
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(MmappetError::SchemaParse {
+                path: None,
+                line: self.columns.len() + 1,
+                message: format!("Invalid column name: '{}'", name),
+            });
+        }
+        if self.name_to_index.contains_key(name) {
+            return Err(MmappetError::DuplicateColumnName {
+                path: None,
+                column: name.to_string(),
+            });
+        }
+        self.name_to_index.insert(name.to_string(), self.columns.len());
+        self.columns.push(ColumnDef {
+            index: self.columns.len(),
+            name: name.to_string(),
+            dtype,
+            shape: Vec::new(),
+            reference: None,
+            attributes: ColumnAttributes::default(),
+        });
+        Ok(())
+    }
+
+    /// Schema of only the columns `names`, in that order and renumbered.
+    ///
+    /// Returns an error if a name doesn't exist or is given twice.
+    pub fn project(&self, names: &[&str]) -> Result<Schema> {
+        let mut columns = Vec::with_capacity(names.len());
+        let mut name_to_index = HashMap::new();
+        for &name in names {
+            let col = self.get(name).ok_or_else(|| MmappetError::ColumnNotFound {
+                path: None,
+                column: name.to_string(),
+            })?;
+            if name_to_index.insert(name.to_string(), columns.len()).is_some() {
+                return Err(MmappetError::DuplicateColumnName {
+                    path: None,
+                    column: name.to_string(),
+                });
+            }
+            columns.push(ColumnDef {
+                index: columns.len(),
+                ..col.clone()
+            });
+        }
+        Ok(Schema {
+            columns,
+            name_to_index,
+            version: self.version,
+            byte_order: self.byte_order,
+        })
+    }
+
+    /// Format version of the dataset, see [`FORMAT_VERSION`].
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Byte order of the column files.
+    pub fn byte_order(&self) -> ByteOrder {
+        self.byte_order
+    }
+
+    /// Change the declared byte order, e.g. to the host's for a schema taken
+    /// from another dataset.
+    pub(crate) fn set_byte_order(&mut self, byte_order: ByteOrder) {
+        self.byte_order = byte_order;
     }
 
     /// Get column definition by name.
@@ -110,10 +514,171 @@ impl Schema {
     }
 }
 
+/// schema.json content.
+#[derive(Serialize, Deserialize)]
+struct JsonSchema {
+    #[serde(default = "legacy_version")]
+    format_version: u32,
+    #[serde(default = "ByteOrder::native")]
+    byte_order: ByteOrder,
+    columns: Vec<JsonColumn>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonColumn {
+    #[serde(deserialize_with = "de_name")]
+    name: String,
+    #[serde(serialize_with = "ser_type", deserialize_with = "de_type")]
+    dtype: (DType, Vec<usize>),
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "ser_reference",
+        deserialize_with = "de_reference"
+    )]
+    reference: Option<ColumnRef>,
+    #[serde(flatten)]
+    attributes: ColumnAttributes,
+}
+
+/// Version of schemas that don't declare one.
+fn legacy_version() -> u32 {
+    1
+}
+
+// Checked while deserializing, so errors point at the offending line
+fn de_name<'de, D: serde::Deserializer<'de>>(d: D) -> std::result::Result<String, D::Error> {
+    let name = String::deserialize(d)?;
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(serde::de::Error::custom(format!("Invalid column name: '{}'", name)));
+    }
+    Ok(name)
+}
+
+fn de_type<'de, D: serde::Deserializer<'de>>(
+    d: D,
+) -> std::result::Result<(DType, Vec<usize>), D::Error> {
+    parse_type(&String::deserialize(d)?).map_err(serde::de::Error::custom)
+}
+
+fn de_reference<'de, D: serde::Deserializer<'de>>(
+    d: D,
+) -> std::result::Result<Option<ColumnRef>, D::Error> {
+    let target = String::deserialize(d)?;
+    ColumnRef::parse(&target).map(Some).ok_or_else(|| {
+        serde::de::Error::custom(format!("Expected '@dataset/column', got: {}", target))
+    })
+}
+
+fn ser_type<S: serde::Serializer>(
+    (dtype, shape): &(DType, Vec<usize>),
+    s: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    s.collect_str(&type_name(*dtype, shape))
+}
+
+/// Format a dtype with its row shape, e.g. `float32[3,4]`.
+fn type_name(dtype: DType, shape: &[usize]) -> String {
+    if shape.is_empty() {
+        return dtype.to_string();
+    }
+    let dims: Vec<String> = shape.iter().map(usize::to_string).collect();
+    format!("{}[{}]", dtype, dims.join(","))
+}
+
+/// Parse a dtype with an optional row shape, e.g. `float32[4]` or
+/// `float32[3,4]`.
+fn parse_type(s: &str) -> Result<(DType, Vec<usize>)> {
+    let (name, shape) = match s.strip_suffix(']').and_then(|s| s.split_once('[')) {
+        Some((name, dims)) => {
+            let shape = dims
+                .split(',')
+                .map(|dim| dim.trim().parse())
+                .collect::<std::result::Result<_, _>>()
+                .map_err(|_| MmappetError::InvalidDType {
+                    dtype: s.to_string(),
+                    message: "array dimensions must be positive integers".to_string(),
+                })?;
+            (name, shape)
+        }
+        None => (s, Vec::new()),
+    };
+    let dtype = DType::from_str(name)?;
+    check_shape(dtype, &shape)?;
+    Ok((dtype, shape))
+}
+
+/// Check that rows of values of `shape` and `dtype` can be stored.
+fn check_shape(dtype: DType, shape: &[usize]) -> Result<()> {
+    let message = if shape.contains(&0) {
+        "array dimensions must be positive integers"
+    } else if !shape.is_empty() && matches!(dtype, DType::Utf8 | DType::Categorical) {
+        "only fixed-size dtypes can form arrays"
+    } else {
+        return Ok(());
+    };
+    Err(MmappetError::InvalidDType {
+        dtype: type_name(dtype, shape),
+        message: message.to_string(),
+    })
+}
+
+fn ser_reference<S: serde::Serializer>(
+    reference: &Option<ColumnRef>,
+    s: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    let reference = reference.as_ref().unwrap();
+    s.collect_str(&format_args!("@{}/{}", reference.dataset.display(), reference.column))
+}
+
+/// Formats the schema as schema.txt content, starting with the format version
+/// and byte order.
+/// Column attributes are left out, see [`Schema::to_json`].
+impl std::fmt::Display for Schema {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}{}", VERSION_PREFIX, self.version)?;
+        writeln!(f, "{}{}", BYTE_ORDER_PREFIX, self.byte_order)?;
+        for col in &self.columns {
+            write!(f, "{} {}", col.type_name(), col.name)?;
+            if let Some(reference) = &col.reference {
+                write!(f, " @{}/{}", reference.dataset.display(), reference.column)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_schema_new_roundtrip() {
+        let schema = Schema::new(&[("tof", DType::UInt32), ("mz", DType::Float64)]).unwrap();
+        let header = format!(
+            "# mmappet-format 1\n# mmappet-byte-order {}\n",
+            ByteOrder::native()
+        );
+        assert_eq!(
+            schema.to_string(),
+            format!("{}uint32 tof\nfloat64 mz\n", header)
+        );
+        assert!(Schema::new(&[("a b", DType::UInt8)]).is_err());
+        assert!(Schema::new(&[("a", DType::UInt8), ("a", DType::Int8)]).is_err());
+
+        let content = format!("{}uint32 intensity\nfloat64 mz @../cal.mmappet/mz\n", header);
+        assert_eq!(Schema::parse(&content).unwrap().to_string(), content);
+
+        let schema = Schema::parse("# mmappet-byte-order big\nuint32 a\n").unwrap();
+        assert_eq!(schema.byte_order(), ByteOrder::Big);
+        assert!(schema.to_string().contains("# mmappet-byte-order big\n"));
+        assert!(matches!(
+            Schema::parse("# mmappet-byte-order middle\n"),
+            Err(MmappetError::SchemaParse { line: 1, .. })
+        ));
+    }
+
     #[test]
     fn test_parse_schema() {
         let content = "uint32 tof\nuint32 intensity\nfloat32 score\nfloat32 mz";
@@ -138,11 +703,107 @@ mod tests {
         assert_eq!(schema.len(), 2);
     }
 
+    #[test]
+    fn test_parse_schema_version() {
+        let schema = Schema::parse("uint32 a\n").unwrap();
+        assert_eq!(schema.version(), 1);
+        let schema = Schema::parse("# mmappet-format 1\n# a comment\nuint32 a\n").unwrap();
+        assert_eq!((schema.version(), schema.len()), (1, 1));
+
+        assert!(matches!(
+            Schema::parse("# mmappet-format 99\nuint32 a\n"),
+            Err(MmappetError::UnsupportedVersion { version: 99, .. })
+        ));
+        assert!(matches!(
+            Schema::parse("# mmappet-format two\n"),
+            Err(MmappetError::SchemaParse { line: 1, .. })
+        ));
+    }
+
     #[test]
     fn test_parse_schema_duplicate_error() {
         let content = "uint32 col\nfloat32 col";
         let result = Schema::parse(content);
-        assert!(matches!(result, Err(MmappetError::DuplicateColumnName(_))));
+        assert!(matches!(result, Err(MmappetError::DuplicateColumnName { .. })));
+    }
+
+    #[test]
+    fn test_parse_schema_reference() {
+        let content = "float64 mz_axis @../calibration.mmappet/mz\nuint32 intensity";
+        let schema = Schema::parse(content).unwrap();
+
+        let axis = schema.get("mz_axis").unwrap();
+        assert_eq!(
+            axis.reference,
+            Some(ColumnRef {
+                dataset: PathBuf::from("../calibration.mmappet"),
+                column: "mz".to_string(),
+            })
+        );
+        assert_eq!(schema.get("intensity").unwrap().reference, None);
+
+        let result = Schema::parse("float64 mz_axis ../calibration.mmappet/mz");
+        assert!(matches!(result, Err(MmappetError::SchemaParse { line: 1, .. })));
+    }
+
+    #[test]
+    fn test_schema_json() {
+        let content = r#"{
+            "columns": [
+                {"name": "tof", "dtype": "uint32", "sorted": true, "scale": 0.5, "offset": 10,
+                 "comment": "ignored"},
+                {"name": "mz", "dtype": "float32", "units": "Th", "null_value": -1.0},
+                {"name": "axis", "dtype": "float64", "reference": "@../cal.mmappet/mz"}
+            ]
+        }"#;
+        let schema = Schema::parse_json(content).unwrap();
+        assert_eq!(schema.version(), 1);
+        assert_eq!(schema.column_names(), vec!["tof", "mz", "axis"]);
+        assert!(schema.get("tof").unwrap().attributes.sorted);
+        let mz = &schema.get("mz").unwrap().attributes;
+        assert_eq!((mz.unit.as_deref(), mz.null_value), (Some("Th"), Some(-1.0)));
+        assert_eq!(mz.physical(2.0), 2.0);
+        let tof = &schema.get("tof").unwrap().attributes;
+        assert_eq!((tof.scale, tof.offset), (Some(0.5), Some(10.0)));
+        assert_eq!(tof.physical(4.0), 12.0);
+        assert_eq!(schema.get("axis").unwrap().reference.as_ref().unwrap().column, "mz");
+
+        let roundtrip = Schema::parse_json(&schema.to_json()).unwrap();
+        assert_eq!(roundtrip.to_json(), schema.to_json());
+        assert_eq!(roundtrip.get("mz").unwrap().attributes, *mz);
+
+        assert!(matches!(
+            Schema::parse_json("{\n\"columns\": [{\"name\": \"a\", \"dtype\": \"int128\"}]}"),
+            Err(MmappetError::SchemaParse { line: 2, .. })
+        ));
+        assert_eq!(schema.byte_order(), ByteOrder::native());
+        let schema = Schema::parse_json(r#"{"byte_order": "big", "columns": []}"#).unwrap();
+        assert_eq!(schema.byte_order(), ByteOrder::Big);
+        assert!(matches!(
+            Schema::parse_json(r#"{"format_version": 99, "columns": []}"#),
+            Err(MmappetError::UnsupportedVersion { version: 99, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_schema_array() {
+        let schema = Schema::parse("uint32 id\nfloat32[4] quaternion\n").unwrap();
+        let quaternion = schema.get("quaternion").unwrap();
+        assert_eq!((quaternion.dtype, quaternion.width()), (DType::Float32, 4));
+        assert_eq!(quaternion.type_name(), "float32[4]");
+        assert_eq!(schema.get("id").unwrap().width(), 1);
+        assert!(schema.to_string().ends_with("uint32 id\nfloat32[4] quaternion\n"));
+        let roundtrip = Schema::parse_json(&schema.to_json()).unwrap();
+        assert_eq!(roundtrip.get("quaternion").unwrap().shape, [4]);
+
+        let schema = Schema::parse("uint16[3,4] grid\n").unwrap();
+        let grid = schema.get("grid").unwrap();
+        assert_eq!((grid.shape.as_slice(), grid.width()), (&[3, 4][..], 12));
+        assert_eq!(grid.type_name(), "uint16[3,4]");
+
+        for content in ["float32[0] q", "float32[4 q", "float32[2,] q", "utf8[2] names"] {
+            assert!(Schema::parse(content).is_err(), "{}", content);
+        }
     }
 
     #[test]
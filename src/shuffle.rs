@@ -0,0 +1,123 @@
+//! Writing the rows of a dataset in random order, e.g. before training on it.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::dataset::Dataset;
+use crate::error::{MmappetError, Result};
+use crate::filter::{copy_schema, write_rows};
+use crate::sample::Rng;
+use crate::writer::DatasetWriter;
+
+/// Rows expected per bucket; the row numbers of one bucket are held in memory.
+const SHUFFLE_BUCKET_ROWS: usize = 1 << 22;
+/// Directory within the output holding the row numbers of each bucket.
+const STAGING_DIR: &str = ".shuffle";
+
+impl Dataset {
+    /// Write the rows in a random order to a new dataset in `dir`. The same
+    /// `seed` gives the same order. Returns the number of rows written.
+    ///
+    /// Rows are first dealt at random into buckets of a few million rows,
+    /// whose row numbers are spilled to files in `dir`, and then each bucket
+    /// is shuffled in memory and written in turn. Memory thus stays bounded
+    /// by the bucket size rather than by the row count.
+    ///
+    /// ```rust,no_run
+    /// let ds = mmappet::Dataset::open("psms.mmappet")?;
+    /// ds.write_shuffled("psms_shuffled.mmappet", 42)?;
+    /// # Ok::<(), mmappet::MmappetError>(())
+    /// ```
+    pub fn write_shuffled<P: AsRef<Path>>(&self, dir: P, seed: u64) -> Result<usize> {
+        self.write_shuffled_in(dir.as_ref(), seed, SHUFFLE_BUCKET_ROWS)
+    }
+
+    fn write_shuffled_in(&self, dir: &Path, seed: u64, bucket_rows: usize) -> Result<usize> {
+        let mut writer = DatasetWriter::create(dir, &copy_schema(self.schema())?)?;
+        let mut rng = Rng::new(seed);
+        let buckets = self.len().div_ceil(bucket_rows).max(1);
+        if buckets == 1 {
+            let mut rows: Vec<usize> = (0..self.len()).collect();
+            shuffle(&mut rows, &mut rng);
+            write_rows(self, &mut writer, &rows)?;
+            return writer.finish();
+        }
+
+        let staging = dir.join(STAGING_DIR);
+        fs::create_dir_all(&staging).map_err(|e| MmappetError::io(&staging, e))?;
+        let bucket_path = |bucket: usize| staging.join(format!("{}.rows", bucket));
+        let mut files = (0..buckets)
+            .map(|bucket| {
+                let path = bucket_path(bucket);
+                let file = File::create(&path).map_err(|e| MmappetError::io(&path, e))?;
+                Ok(BufWriter::new(file))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for row in 0..self.len() {
+            let bucket = rng.below(buckets);
+            files[bucket]
+                .write_all(&(row as u64).to_le_bytes())
+                .map_err(|e| MmappetError::io(bucket_path(bucket), e))?;
+        }
+        for (bucket, file) in files.into_iter().enumerate() {
+            file.into_inner()
+                .map_err(|e| MmappetError::io(bucket_path(bucket), e.into_error()))?;
+        }
+
+        for bucket in 0..buckets {
+            let path = bucket_path(bucket);
+            let bytes = fs::read(&path).map_err(|e| MmappetError::io(&path, e))?;
+            let mut rows: Vec<usize> = bytes
+                .chunks_exact(8)
+                .map(|b| u64::from_le_bytes(b.try_into().unwrap()) as usize)
+                .collect();
+            shuffle(&mut rows, &mut rng);
+            write_rows(self, &mut writer, &rows)?;
+        }
+        fs::remove_dir_all(&staging).map_err(|e| MmappetError::io(&staging, e))?;
+        writer.finish()
+    }
+}
+
+/// Fisher-Yates shuffle.
+fn shuffle(rows: &mut [usize], rng: &mut Rng) {
+    for i in (1..rows.len()).rev() {
+        rows.swap(i, rng.below(i + 1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_shuffled() {
+        let dir = std::env::temp_dir().join(format!("mmappet-shuffle-{}", std::process::id()));
+        let ids: Vec<u32> = (0..1000).collect();
+        let names: Vec<String> = ids.iter().map(|i| format!("row{}", i)).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let ds = Dataset::from_columns(vec![("id", ids.clone().into()), ("name", names.into())])
+            .unwrap();
+
+        for (out, bucket_rows) in [(dir.join("one"), 1 << 20), (dir.join("many"), 64)] {
+            assert_eq!(ds.write_shuffled_in(&out, 42, bucket_rows).unwrap(), 1000);
+            let shuffled = Dataset::open(&out).unwrap();
+            let got = shuffled.get::<u32>("id").unwrap().to_vec();
+            assert_ne!(got, ids);
+            let names = shuffled.column("name").unwrap().as_str_array().unwrap();
+            assert!(got.iter().enumerate().all(|(i, id)| names.get(i) == format!("row{}", id)));
+            let mut sorted = got.clone();
+            sorted.sort_unstable();
+            assert_eq!(sorted, ids);
+            assert!(!out.join(STAGING_DIR).exists());
+        }
+
+        ds.write_shuffled(dir.join("again"), 42).unwrap();
+        let again = Dataset::open(dir.join("again")).unwrap();
+        let one = Dataset::open(dir.join("one")).unwrap();
+        assert_eq!(again.get::<u32>("id").unwrap(), one.get::<u32>("id").unwrap());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,114 @@
+//! Sorting rows by a column.
+
+use std::cmp::Ordering;
+use std::path::Path;
+
+use crate::column::TypedArrayView;
+use crate::dataset::Dataset;
+use crate::error::{MmappetError, Result};
+
+impl Dataset {
+    /// Permutation of row indices that sorts the dataset by column `name`.
+    ///
+    /// The sort is stable, so rows with equal values keep their order. Floats
+    /// are ordered by [`f64::total_cmp`], which puts NaNs last. Custom dtypes
+    /// are ordered by their numeric value, and rejected if they have none.
+    /// Strings and categorical labels are ordered by their bytes.
+    pub fn argsort(&self, name: &str) -> Result<Vec<u64>> {
+        self.argsort_with(name, |perm, cmp| perm.sort_by(cmp))
+    }
+
+    /// [`Dataset::argsort`] with a custom stable sort of the row indices.
+    pub(crate) fn argsort_with(
+        &self,
+        name: &str,
+        sort: impl Fn(&mut [u64], &(dyn Fn(&u64, &u64) -> Ordering + Sync)),
+    ) -> Result<Vec<u64>> {
+        let column = self
+            .column(name)
+            .ok_or_else(|| self.column_not_found(name))?;
+        column.check_scalar(name)?;
+        let mut perm: Vec<u64> = (0..self.len() as u64).collect();
+
+        macro_rules! sort_ord {
+            ($a:expr) => {{
+                let data = $a.as_slice().unwrap();
+                sort(&mut perm, &|&i, &j| data[i as usize].cmp(&data[j as usize]));
+            }};
+        }
+        macro_rules! sort_float {
+            ($a:expr) => {{
+                let data = $a.as_slice().unwrap();
+                sort(&mut perm, &|&i, &j| data[i as usize].total_cmp(&data[j as usize]));
+            }};
+        }
+
+        match column.as_typed_array() {
+            TypedArrayView::UInt8(a) | TypedArrayView::Bool(a) => sort_ord!(a),
+            TypedArrayView::Int8(a) => sort_ord!(a),
+            TypedArrayView::UInt16(a) => sort_ord!(a),
+            TypedArrayView::Int16(a) => sort_ord!(a),
+            TypedArrayView::UInt32(a) => sort_ord!(a),
+            TypedArrayView::Int32(a) => sort_ord!(a),
+            TypedArrayView::UInt64(a) => sort_ord!(a),
+            TypedArrayView::Int64(a) | TypedArrayView::TimestampMillis(a) | TypedArrayView::TimestampMicros(a) => sort_ord!(a),
+            TypedArrayView::Float16(a) => sort_float!(a),
+            TypedArrayView::Float32(a) => sort_float!(a),
+            TypedArrayView::Float64(a) => sort_float!(a),
+            TypedArrayView::Utf8(a) => {
+                sort(&mut perm, &|&i, &j| a.get(i as usize).cmp(a.get(j as usize)));
+            }
+            TypedArrayView::Categorical(a) => {
+                sort(&mut perm, &|&i, &j| a.value(i as usize).cmp(a.value(j as usize)));
+            }
+            TypedArrayView::Custom(a) => {
+                let to_f64 = a.def().to_f64.ok_or_else(|| MmappetError::InvalidDType {
+                    dtype: a.def().name.to_string(),
+                    message: format!("column '{}' has no numeric value to sort by", name),
+                })?;
+                let keys: Vec<f64> = (0..a.len()).map(|i| to_f64(a.get(i))).collect();
+                sort(&mut perm, &|&i, &j| keys[i as usize].total_cmp(&keys[j as usize]));
+            }
+        }
+        Ok(perm)
+    }
+
+    /// Write the rows in the order given by `permutation` to a new dataset in
+    /// `dir`, e.g. a sorted copy using the result of [`Dataset::argsort`].
+    ///
+    /// Returns an error if an index is out of bounds. Returns the number of
+    /// rows written.
+    pub fn write_permuted<P: AsRef<Path>>(&self, permutation: &[u64], dir: P) -> Result<usize> {
+        let indices: Vec<usize> = permutation.iter().map(|&i| i as usize).collect();
+        self.take(&indices)?.write_to(dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::write_dataset;
+
+    #[test]
+    fn test_argsort() {
+        let dir = write_dataset(
+            "argsort",
+            &[("mz", &[30, 10, 20, 10]), ("id", &[0, 1, 2, 3])],
+        );
+        let ds = Dataset::open(&dir).unwrap();
+
+        let perm = ds.argsort("mz").unwrap();
+        assert_eq!(perm, vec![1, 3, 2, 0]);
+        assert!(ds.argsort("missing").is_err());
+
+        let out = dir.with_extension("sorted");
+        assert_eq!(ds.write_permuted(&perm, &out).unwrap(), 4);
+        let sorted = Dataset::open(&out).unwrap();
+        assert_eq!(sorted.get::<u32>("mz").unwrap(), &[10, 10, 20, 30]);
+        assert_eq!(sorted.get::<u32>("id").unwrap(), &[1, 3, 2, 0]);
+        assert!(ds.write_permuted(&[4], dir.with_extension("bad")).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+        std::fs::remove_dir_all(out).unwrap();
+    }
+}
@@ -0,0 +1,183 @@
+//! Splitting a dataset into disjoint random parts, e.g. train and test sets.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::dataset::Dataset;
+use crate::error::{MmappetError, Result};
+use crate::filter::{copy_schema, write_rows};
+use crate::sample::Rng;
+use crate::writer::DatasetWriter;
+
+/// Outputs a row can be tagged with, one byte per row.
+const OUTPUT_TAGS: usize = 1 << 8;
+/// Rows gathered at a time when writing an output.
+const SPLIT_CHUNK_ROWS: usize = 1 << 16;
+
+/// Write the rows of `ds` to one new dataset per directory of `dirs`, each
+/// getting its fraction of `fractions` of the rows, chosen at random.
+///
+/// Every row goes to exactly one output, and rows keep their order within
+/// each. With `stratify_by`, each distinct value (or label, or string) of
+/// that column is split by the fractions on its own, so every output gets
+/// its share of each class. The same `seed` gives the same split. Each
+/// row's output is drawn in one pass, kept as a byte per row, and every
+/// output is then written by a scan over the rows tagged with it. Returns
+/// the number of rows written to each output.
+///
+/// Returns an error if `fractions` and `dirs` differ in length, there are
+/// more than 256 outputs, a fraction is negative or they don't add up to 1,
+/// or the column doesn't exist.
+///
+/// ```rust,no_run
+/// use mmappet::{split, Dataset};
+///
+/// let ds = Dataset::open("psms.mmappet")?;
+/// let dirs = ["train.mmappet", "test.mmappet"];
+/// let rows = split(&ds, &[0.8, 0.2], 42, Some("decoy"), &dirs)?;
+/// # Ok::<(), mmappet::MmappetError>(())
+/// ```
+pub fn split<P: AsRef<Path>>(
+    ds: &Dataset,
+    fractions: &[f64],
+    seed: u64,
+    stratify_by: Option<&str>,
+    dirs: &[P],
+) -> Result<Vec<usize>> {
+    let invalid = |message: String| MmappetError::InvalidSelection {
+        path: Some(ds.path().to_path_buf()),
+        message,
+    };
+    if fractions.len() != dirs.len() {
+        return Err(invalid(format!(
+            "{} fractions for {} outputs",
+            fractions.len(),
+            dirs.len()
+        )));
+    }
+    let total: f64 = fractions.iter().sum();
+    if fractions.iter().any(|f| *f < 0.0 || f.is_nan()) || (total - 1.0).abs() > 1e-9 {
+        return Err(invalid(format!("fractions {:?} don't add up to 1", fractions)));
+    }
+
+    if dirs.len() > OUTPUT_TAGS {
+        return Err(invalid(format!("{} outputs, at most {}", dirs.len(), OUTPUT_TAGS)));
+    }
+
+    // Rows of each class, classes in order of first occurrence, counted in a
+    // first pass; the second counts them down
+    let column = stratify_by.map(|name| ds.mapped_column(name)).transpose()?;
+    let keys = column.as_ref().map(|column| column.row_bytes());
+    let mut class_of: HashMap<&[u8], usize> = HashMap::new();
+    let mut class_rows: Vec<usize> = Vec::new();
+    match &keys {
+        Some(keys) => {
+            for key in keys.iter() {
+                let class = *class_of.entry(key).or_insert_with(|| {
+                    class_rows.push(0);
+                    class_rows.len() - 1
+                });
+                class_rows[class] += 1;
+            }
+        }
+        None => class_rows.push(ds.len()),
+    }
+
+    // Rows each output is still owed of each class
+    let mut owed: Vec<Vec<usize>> =
+        class_rows.iter().map(|&rows| quotas(rows, fractions)).collect();
+
+    // Each row goes to an output drawn with weights the rows it's still owed
+    // of the row's class, which deals every class out as a random shuffle
+    // would, in one pass and a byte per row
+    let mut rng = Rng::new(seed);
+    let mut tags: Vec<u8> = Vec::with_capacity(ds.len());
+    for row in 0..ds.len() {
+        let class = match &keys {
+            Some(keys) => class_of[keys.get(row)],
+            None => 0,
+        };
+        let owed = &mut owed[class];
+        let mut draw = rng.below(class_rows[class]);
+        let mut output = 0;
+        while draw >= owed[output] {
+            draw -= owed[output];
+            output += 1;
+        }
+        owed[output] -= 1;
+        class_rows[class] -= 1;
+        tags.push(output as u8);
+    }
+
+    let schema = copy_schema(ds.schema())?;
+    let mut written = Vec::with_capacity(dirs.len());
+    let mut rows = Vec::with_capacity(SPLIT_CHUNK_ROWS);
+    for (output, dir) in dirs.iter().enumerate() {
+        let mut writer = DatasetWriter::create(dir, &schema)?;
+        for (start, chunk) in tags.chunks(SPLIT_CHUNK_ROWS).enumerate() {
+            rows.clear();
+            let start = start * SPLIT_CHUNK_ROWS;
+            let tagged = (start..).zip(chunk).filter(|(_, &tag)| tag as usize == output);
+            rows.extend(tagged.map(|(row, _)| row));
+            write_rows(ds, &mut writer, &rows)?;
+        }
+        written.push(writer.finish()?);
+    }
+    Ok(written)
+}
+
+/// Rows of a class of `rows` rows each output gets, by `fractions`; rounding
+/// leaves at most a row or two, which go to the last output.
+fn quotas(rows: usize, fractions: &[f64]) -> Vec<usize> {
+    let mut quotas = Vec::with_capacity(fractions.len());
+    let mut start = 0;
+    let mut cumulative = 0.0;
+    for fraction in fractions {
+        cumulative += fraction;
+        let end = ((cumulative * rows as f64).round() as usize).clamp(start, rows);
+        quotas.push(end - start);
+        start = end;
+    }
+    *quotas.last_mut().unwrap() += rows - start;
+    quotas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        let dir = std::env::temp_dir().join(format!("mmappet-split-{}", std::process::id()));
+        let labels: Vec<&str> =
+            (0..1000).map(|i| if i % 10 == 0 { "rare" } else { "common" }).collect();
+        let ds = Dataset::from_columns(vec![
+            ("id", (0..1000u32).collect::<Vec<_>>().into()),
+            ("label", labels.into()),
+        ])
+        .unwrap();
+
+        let dirs = [dir.join("train"), dir.join("test")];
+        let rows = split(&ds, &[0.8, 0.2], 42, Some("label"), &dirs).unwrap();
+        assert_eq!(rows, [800, 200]);
+        let train = Dataset::open(&dirs[0]).unwrap();
+        let test = Dataset::open(&dirs[1]).unwrap();
+        let mut ids: Vec<u32> = train.get::<u32>("id").unwrap().to_vec();
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+        ids.extend_from_slice(test.get::<u32>("id").unwrap());
+        ids.sort_unstable();
+        assert_eq!(ids, (0..1000).collect::<Vec<_>>());
+        let rare = test.get::<u32>("id").unwrap().iter().filter(|id| *id % 10 == 0).count();
+        assert_eq!(rare, 20);
+
+        let unstratified = [dir.join("a"), dir.join("b"), dir.join("c")];
+        let rows = split(&ds, &[0.5, 0.3, 0.2], 42, None, &unstratified).unwrap();
+        assert_eq!(rows, [500, 300, 200]);
+        assert!(split(&ds, &[0.5, 0.4], 42, None, &dirs).is_err());
+        assert!(split(&ds, &[1.0], 42, None, &dirs).is_err());
+        let many = vec![dir.join("many"); 257];
+        assert!(split(&ds, &[1.0 / 257.0; 257], 42, None, &many).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,256 @@
+//! Summary statistics of numeric columns.
+
+use std::ops::Range;
+
+use crate::column::{Column, TypedArrayView};
+use crate::dataset::Dataset;
+use crate::error::Result;
+use crate::expr::read_f64;
+use crate::simd;
+
+/// Rows converted to `f64` at a time while computing statistics.
+pub(crate) const STATS_CHUNK_ROWS: usize = 4096;
+/// Rows whose statistics are computed on their own and then merged in row
+/// order, so that multi-threaded scans round exactly like single-threaded ones.
+pub(crate) const STATS_PART_ROWS: usize = 1 << 16;
+
+/// Count, range, sum and spread of a column's values, computed as `f64`,
+/// and its number of nulls.
+///
+/// NaNs are skipped. 64-bit integers beyond 2^53 lose precision.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColumnStats {
+    /// Number of values taken into account, i.e. non-NaN, non-null ones.
+    pub count: usize,
+    /// Smallest value, `+inf` if there are none.
+    pub min: f64,
+    /// Largest value, `-inf` if there are none.
+    pub max: f64,
+    pub sum: f64,
+    /// Sum of the squared deviations of the values from their mean.
+    pub m2: f64,
+    /// Number of null rows.
+    pub nulls: usize,
+}
+
+impl ColumnStats {
+    /// Mean of the values, NaN if there are none.
+    pub fn mean(&self) -> f64 {
+        self.sum / self.count as f64
+    }
+
+    /// Sample variance of the values (divided by `count - 1`), NaN if there
+    /// are fewer than two.
+    pub fn variance(&self) -> f64 {
+        match self.count {
+            0 | 1 => f64::NAN,
+            count => self.m2 / (count - 1) as f64,
+        }
+    }
+
+    /// Sample standard deviation of the values, NaN if there are fewer than
+    /// two.
+    pub fn std(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// Statistics of the values of both `self` and `other`.
+    pub fn merge(self, other: ColumnStats) -> ColumnStats {
+        let count = self.count + other.count;
+        // Chan et al.'s update, exact for any split of the values
+        let m2 = match (self.count, other.count) {
+            (0, _) => other.m2,
+            (_, 0) => self.m2,
+            (a, b) => {
+                let delta = other.mean() - self.mean();
+                self.m2 + other.m2 + delta * delta * (a as f64 * b as f64 / count as f64)
+            }
+        };
+        ColumnStats {
+            count,
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+            sum: self.sum + other.sum,
+            m2,
+            nulls: self.nulls + other.nulls,
+        }
+    }
+
+    /// Statistics of the single value `x`.
+    pub(crate) fn one(x: f64) -> ColumnStats {
+        ColumnStats {
+            count: 1,
+            min: x,
+            max: x,
+            sum: x,
+            ..ColumnStats::default()
+        }
+    }
+
+    /// Statistics of the non-NaN `values`.
+    pub fn of(values: &[f64]) -> ColumnStats {
+        let mut stats = ColumnStats::default();
+        for &x in values.iter().filter(|x| !x.is_nan()) {
+            stats.count += 1;
+            stats.min = stats.min.min(x);
+            stats.max = stats.max.max(x);
+            stats.sum += x;
+        }
+        stats.m2 = simd::squared_deviations(values, stats.mean(), |x| x);
+        stats
+    }
+
+    /// Statistics of `count` values that aren't numbers, such as strings.
+    fn non_numeric(count: usize) -> ColumnStats {
+        ColumnStats {
+            count,
+            min: f64::NAN,
+            max: f64::NAN,
+            sum: f64::NAN,
+            m2: f64::NAN,
+            nulls: 0,
+        }
+    }
+}
+
+impl Default for ColumnStats {
+    /// Statistics of no values.
+    fn default() -> Self {
+        ColumnStats {
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            sum: 0.0,
+            m2: 0.0,
+            nulls: 0,
+        }
+    }
+}
+
+impl Column {
+    /// Compute count, min, max, sum and spread of the column's values, all
+    /// values of the arrays of array columns.
+    ///
+    /// Returns `None` for custom dtypes without a numeric value.
+    pub fn stats(&self) -> Option<ColumnStats> {
+        let array = self.numeric_array()?;
+        let len = array.len();
+        Some((0..len).step_by(STATS_PART_ROWS).fold(ColumnStats::default(), |stats, start| {
+            stats.merge(range_stats(&array, start..(start + STATS_PART_ROWS).min(len)))
+        }))
+    }
+
+    /// The column as a typed array, if its values convert to `f64`.
+    pub(crate) fn numeric_array(&self) -> Option<TypedArrayView<'_>> {
+        let array = self.as_typed_array();
+        match &array {
+            TypedArrayView::Custom(custom) if custom.def().to_f64.is_none() => None,
+            TypedArrayView::Utf8(_) | TypedArrayView::Categorical(_) => None,
+            _ => Some(array),
+        }
+    }
+}
+
+impl Dataset {
+    /// Statistics of every column, in schema order, as by
+    /// [`NullableColumn::stats`](crate::NullableColumn::stats).
+    ///
+    /// Columns without numeric values, such as strings, only get `count` and
+    /// `nulls`; their `min`, `max`, `sum` and `m2` are NaN. Returns an error
+    /// if a validity bitmap is invalid.
+    ///
+    /// ```rust,no_run
+    /// let ds = mmappet::Dataset::open("peaks.mmappet")?;
+    /// for (name, stats) in ds.describe()? {
+    ///     println!("{}: mean={} std={} nulls={}", name, stats.mean(), stats.std(), stats.nulls);
+    /// }
+    /// # Ok::<(), mmappet::MmappetError>(())
+    /// ```
+    pub fn describe(&self) -> Result<Vec<(&str, ColumnStats)>> {
+        self.column_names()
+            .map(|name| {
+                let nullable = self.nullable(name)?;
+                #[cfg(feature = "parallel")]
+                let stats = match nullable.has_validity() {
+                    true => nullable.stats(),
+                    false => nullable.column().par_stats(),
+                };
+                #[cfg(not(feature = "parallel"))]
+                let stats = nullable.stats();
+                let stats = stats.unwrap_or_else(|| {
+                    let nulls = nullable.null_count();
+                    let count = (nullable.len() - nulls) * nullable.column().width();
+                    ColumnStats { nulls, ..ColumnStats::non_numeric(count) }
+                });
+                Ok((name, stats))
+            })
+            .collect()
+    }
+}
+
+/// Statistics of `rows` of `array`, which must convert to `f64`.
+///
+/// `f32`, `f64`, `u32` and `i64` columns use vectorized kernels; other
+/// dtypes are converted to `f64` a chunk at a time.
+pub(crate) fn range_stats(array: &TypedArrayView<'_>, rows: Range<usize>) -> ColumnStats {
+    if let Some(stats) = simd::stats(array, rows.clone()) {
+        return stats;
+    }
+    let mut values = vec![0.0; STATS_CHUNK_ROWS.min(rows.len())];
+    let mut stats = ColumnStats::default();
+    for start in rows.clone().step_by(STATS_CHUNK_ROWS) {
+        let values = &mut values[..STATS_CHUNK_ROWS.min(rows.end - start)];
+        read_f64(array, start, values);
+        stats = stats.merge(ColumnStats::of(values));
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::Dataset;
+    use crate::test_util::write_dataset;
+
+    #[test]
+    fn test_stats() {
+        let values: Vec<u32> = (1..=10_000).collect();
+        let dir = write_dataset("stats", &[("x", &values)]);
+        let ds = Dataset::open(&dir).unwrap();
+
+        let stats = ds["x"].stats().unwrap();
+        assert_eq!(stats.count, 10_000);
+        assert_eq!((stats.min, stats.max), (1.0, 10_000.0));
+        assert_eq!(stats.sum, 50_005_000.0);
+        assert_eq!(stats.mean(), 5000.5);
+
+        let merged = stats.merge(ColumnStats::of(&[f64::NAN, -1.0]));
+        assert_eq!((merged.count, merged.min), (10_001, -1.0));
+        assert!(ColumnStats::default().mean().is_nan());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_describe() {
+        let ds = Dataset::from_columns(vec![
+            ("x", (1..=10_000u32).collect::<Vec<_>>().into()),
+            ("y", [2.0f64, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0].repeat(1250).into()),
+            ("name", vec!["a"; 10_000].into()),
+        ])
+        .unwrap();
+
+        let described = ds.describe().unwrap();
+        let names: Vec<&str> = described.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ["x", "y", "name"]);
+        let x = described[0].1;
+        assert_eq!((x.count, x.nulls, x.mean()), (10_000, 0, 5000.5));
+        assert!((x.variance() - 10_000.0 * 10_001.0 / 12.0).abs() < 1e-6);
+        let y = described[1].1;
+        assert!((y.std() - (40_000.0f64 / 9999.0).sqrt()).abs() < 1e-12);
+        let name = described[2].1;
+        assert_eq!(name.count, 10_000);
+        assert!(name.min.is_nan() && name.std().is_nan());
+        assert!(ColumnStats::one(1.0).std().is_nan());
+    }
+}
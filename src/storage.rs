@@ -0,0 +1,256 @@
+//! Bytes of column and index files: mapped where the platform supports it,
+//! read into memory where it doesn't (e.g. `wasm32`) or when asked to.
+
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::ops::{Deref, Range};
+use std::path::{Path, PathBuf};
+
+use memmap2::{Mmap, MmapMut, MmapOptions, MmapRaw};
+
+use crate::column::Advice;
+use crate::error::{MmappetError, Result};
+
+/// Whether memmap2 can map files on this target; elsewhere it only has a
+/// stub that fails at runtime.
+const CAN_MAP: bool = cfg!(any(unix, windows));
+
+/// The bytes of a file, or of a decoded copy of one.
+pub(crate) enum Storage {
+    /// A shared mapping of the file.
+    Mapped(MmapRaw),
+    /// A copy in memory.
+    Buffered(Buffer),
+}
+
+impl Storage {
+    /// Map `path` read-only, or read it if `buffered` or the platform can't
+    /// map files.
+    pub(crate) fn open(path: &Path, buffered: bool) -> Result<Self> {
+        if buffered || !CAN_MAP {
+            return Buffer::read(path, None).map(Storage::Buffered);
+        }
+        let file = File::open(path).map_err(|e| MmappetError::io(path, e))?;
+        // SAFETY: mmappet files are only modified through mappings or by
+        // replacing them, see `Column::open_mut`.
+        let mmap = unsafe { Mmap::map(&file).map_err(|e| MmappetError::io(path, e))? };
+        Ok(Storage::Mapped(MmapRaw::from(mmap)))
+    }
+
+    /// Map or read the first `len` bytes of `path` as by [`Storage::open`],
+    /// or all of it if it's shorter, e.g. while rows are being appended.
+    pub(crate) fn open_prefix(path: &Path, len: usize, buffered: bool) -> Result<Self> {
+        if buffered || !CAN_MAP {
+            let mut buffer = Buffer::read(path, None)?;
+            buffer.len = buffer.len.min(len);
+            return Ok(Storage::Buffered(buffer));
+        }
+        let file = File::open(path).map_err(|e| MmappetError::io(path, e))?;
+        let file_len = file.metadata().map_err(|e| MmappetError::io(path, e))?.len();
+        let len = len.min(file_len as usize);
+        // SAFETY: as for `open`; appends don't change the mapped bytes.
+        let mmap = unsafe {
+            MmapOptions::new().len(len).map(&file).map_err(|e| MmappetError::io(path, e))?
+        };
+        Ok(Storage::Mapped(MmapRaw::from(mmap)))
+    }
+
+    /// Map `path` read-write, or if `buffered` or the platform can't map
+    /// files, read it and write it back on [`Storage::flush`] and drop.
+    ///
+    /// A file hard-linked to other paths is copied first, see [`unshare`].
+    pub(crate) fn open_mut(path: &Path, buffered: bool) -> Result<Self> {
+        unshare(path)?;
+        if buffered || !CAN_MAP {
+            return Buffer::read(path, Some(path.to_path_buf())).map(Storage::Buffered);
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|e| MmappetError::io(path, e))?;
+        // SAFETY: as for `open`; writes go through `Column::as_bytes_mut`.
+        let mmap = unsafe { MmapMut::map_mut(&file).map_err(|e| MmappetError::io(path, e))? };
+        Ok(Storage::Mapped(MmapRaw::from(mmap)))
+    }
+
+    /// `len` zeroed bytes in memory, e.g. for decoding a file into.
+    pub(crate) fn zeroed(len: usize) -> Self {
+        Storage::Buffered(Buffer::zeroed(len))
+    }
+
+    /// Whether the bytes are a mapping of the file.
+    pub(crate) fn is_mapped(&self) -> bool {
+        matches!(self, Storage::Mapped(_))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Storage::Mapped(mmap) => mmap.len(),
+            Storage::Buffered(buffer) => buffer.len,
+        }
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const u8 {
+        self.as_mut_ptr()
+    }
+
+    /// Pointer to write through; only valid for writable storage.
+    pub(crate) fn as_mut_ptr(&self) -> *mut u8 {
+        match self {
+            Storage::Mapped(mmap) => mmap.as_mut_ptr(),
+            Storage::Buffered(buffer) => buffer.words.cast(),
+        }
+    }
+
+    /// Get the bytes for modification before the storage is shared.
+    pub(crate) fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: `&mut self` guarantees no other borrow of the bytes exists,
+        // and storage created from files is only written while that holds.
+        unsafe { std::slice::from_raw_parts_mut(self.as_mut_ptr(), self.len()) }
+    }
+
+    /// Apply `advice` to a byte range of a mapping; does nothing for copies
+    /// in memory and on platforms without `madvise`.
+    pub(crate) fn advise(&self, advice: Advice, bytes: Range<usize>) -> Result<()> {
+        let Storage::Mapped(mmap) = self else {
+            return Ok(());
+        };
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        #[cfg(unix)]
+        {
+            use memmap2::{Advice as Madvise, UncheckedAdvice};
+
+            let (offset, len) = (bytes.start, bytes.len());
+            match advice {
+                Advice::Normal => mmap.advise_range(Madvise::Normal, offset, len)?,
+                Advice::Sequential => mmap.advise_range(Madvise::Sequential, offset, len)?,
+                Advice::Random => mmap.advise_range(Madvise::Random, offset, len)?,
+                Advice::WillNeed => mmap.advise_range(Madvise::WillNeed, offset, len)?,
+                // SAFETY: mappings are shared file mappings, so dropped pages
+                // keep their contents and are read back from the page cache
+                // or the file.
+                Advice::DontNeed => unsafe {
+                    mmap.unchecked_advise_range(UncheckedAdvice::DontNeed, offset, len)?
+                },
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = (mmap, advice);
+        Ok(())
+    }
+
+    /// Ask for a mapping to be backed by transparent huge pages; best effort,
+    /// see [`crate::OpenOptions::huge_pages`].
+    pub(crate) fn advise_huge_pages(&self) {
+        #[cfg(target_os = "linux")]
+        if let Storage::Mapped(mmap) = self {
+            let _ = mmap.advise(memmap2::Advice::HugePage);
+        }
+    }
+
+    /// Write changes back to the file and wait until they're on disk.
+    pub(crate) fn flush(&self) -> io::Result<()> {
+        match self {
+            Storage::Mapped(mmap) => mmap.flush(),
+            Storage::Buffered(buffer) => buffer.write_back(),
+        }
+    }
+}
+
+impl Deref for Storage {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: the bytes live as long as `self` and are only written
+        // through `&mut` access to the column holding them.
+        unsafe { std::slice::from_raw_parts(self.as_ptr(), self.len()) }
+    }
+}
+
+/// Bytes in memory, 8-byte aligned so that values of any dtype can be viewed
+/// in place as they can in a mapping.
+pub(crate) struct Buffer {
+    words: *mut u64,
+    /// Number of allocated words.
+    capacity: usize,
+    len: usize,
+    /// File the bytes are written back to.
+    path: Option<PathBuf>,
+}
+
+// SAFETY: `Buffer` owns its allocation like a `Box<[u64]>`; writes only
+// happen through `&mut` access to the column holding it.
+unsafe impl Send for Buffer {}
+unsafe impl Sync for Buffer {}
+
+impl Buffer {
+    fn zeroed(len: usize) -> Self {
+        let words = Box::<[u64]>::into_raw(vec![0; len.div_ceil(8)].into_boxed_slice());
+        Buffer {
+            words: words.cast(),
+            capacity: words.len(),
+            len,
+            path: None,
+        }
+    }
+
+    /// Read the file `path`, to write back to `write_back` if given.
+    fn read(path: &Path, write_back: Option<PathBuf>) -> Result<Self> {
+        let bytes = fs::read(path).map_err(|e| MmappetError::io(path, e))?;
+        let mut buffer = Buffer::zeroed(bytes.len());
+        buffer.path = write_back;
+        // SAFETY: the buffer holds at least `bytes.len()` bytes.
+        unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), buffer.words.cast(), bytes.len()) };
+        Ok(buffer)
+    }
+
+    fn write_back(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        // SAFETY: the buffer holds `len` initialized bytes.
+        let bytes = unsafe { std::slice::from_raw_parts(self.words.cast::<u8>(), self.len) };
+        let file = OpenOptions::new().write(true).truncate(true).open(path)?;
+        io::Write::write_all(&mut &file, bytes)?;
+        file.sync_all()
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        // Like a mapping, a writable buffer keeps its changes without a flush
+        let _ = self.write_back();
+        // SAFETY: `words` and `capacity` come from the boxed slice made in
+        // `zeroed`, which is freed only here.
+        let words = std::ptr::slice_from_raw_parts_mut(self.words, self.capacity);
+        drop(unsafe { Box::from_raw(words) });
+    }
+}
+
+/// Give `path` a file of its own if it's hard-linked to other paths, e.g. by
+/// `mmappet-cli dedupe-storage`, so writing to it leaves them as they are.
+///
+/// The file is copied next to `path` and renamed over it, so `path` always
+/// holds the whole file. Links can only be detected on Unix; elsewhere this
+/// does nothing.
+pub(crate) fn unshare(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let metadata = fs::metadata(path).map_err(|e| MmappetError::io(path, e))?;
+        if metadata.nlink() > 1 {
+            let mut tmp_path = path.as_os_str().to_owned();
+            tmp_path.push(".unshare-tmp");
+            let tmp_path = PathBuf::from(tmp_path);
+            fs::copy(path, &tmp_path)
+                .and_then(|_| File::open(&tmp_path)?.sync_all())
+                .map_err(|e| MmappetError::io(&tmp_path, e))?;
+            fs::rename(&tmp_path, path).map_err(|e| MmappetError::io(path, e))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,258 @@
+//! Variable-length string columns.
+//!
+//! A [`DType::Utf8`] column stores the end offset of every string as a `u64`
+//! in `{N}.bin` and the concatenated UTF-8 bytes in `{N}.str`; string `i`
+//! spans `offsets[i - 1]..offsets[i]` (from 0 for the first). Both files are
+//! checked when the column is mapped, so accessors never fail.
+
+use std::path::Path;
+
+use crate::categorical::CategoricalView;
+use crate::column::Column;
+use crate::dataset::Dataset;
+use crate::dtype::DType;
+use crate::error::{MmappetError, Result};
+
+/// Strings of a [`DType::Utf8`] column or a range of its rows.
+#[derive(Debug, Clone, Copy)]
+pub struct StrArrayView<'a> {
+    /// Offset where the first string starts.
+    start: u64,
+    ends: &'a [u64],
+    data: &'a [u8],
+}
+
+impl<'a> StrArrayView<'a> {
+    /// View strings given their end offsets into checked `data`, the first
+    /// starting at `start`.
+    pub(crate) fn new(start: u64, ends: &'a [u64], data: &'a [u8]) -> Self {
+        StrArrayView { start, ends, data }
+    }
+
+    /// Number of strings.
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    /// Check if empty.
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    /// Get string `i`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is out of bounds.
+    pub fn get(&self, i: usize) -> &'a str {
+        let start = if i == 0 { self.start } else { self.ends[i - 1] };
+        let bytes = &self.data[start as usize..self.ends[i] as usize];
+        // SAFETY: the offsets were checked to be ascending char boundaries of
+        // valid UTF-8 when the column was mapped.
+        unsafe { std::str::from_utf8_unchecked(bytes) }
+    }
+
+    /// Iterate over the strings.
+    pub fn iter(&self) -> impl Iterator<Item = &'a str> + 'a {
+        let strings = *self;
+        (0..self.len()).map(move |i| strings.get(i))
+    }
+
+    /// View of the strings in `rows`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rows` is out of bounds.
+    pub(crate) fn slice(&self, rows: std::ops::Range<usize>) -> Self {
+        let start = if rows.start == 0 { self.start } else { self.ends[rows.start - 1] };
+        StrArrayView::new(start, &self.ends[rows], self.data)
+    }
+
+    /// Bytes of all strings, concatenated.
+    pub(crate) fn bytes(&self) -> &'a [u8] {
+        let end = self.ends.last().map_or(self.start, |&end| end);
+        &self.data[self.start as usize..end as usize]
+    }
+
+    /// End offsets of the strings, counted from the start of the first.
+    pub(crate) fn relative_ends(&self) -> impl Iterator<Item = u64> + '_ {
+        self.ends.iter().map(move |&end| end - self.start)
+    }
+}
+
+impl Column {
+    /// Get string `i` of a [`DType::Utf8`] column.
+    ///
+    /// Returns `None` if the column doesn't hold strings.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is out of bounds.
+    pub fn as_str_slice(&self, i: usize) -> Option<&str> {
+        self.as_str_array().map(|strings| strings.get(i))
+    }
+
+    /// Iterate over the strings of a [`DType::Utf8`] column.
+    ///
+    /// Returns `None` if the column doesn't hold strings.
+    pub fn iter_str(&self) -> Option<impl Iterator<Item = &str> + '_> {
+        self.as_str_array().map(|strings| strings.iter())
+    }
+
+    /// Bytes of the rows: their strings for [`DType::Utf8`] columns, their
+    /// labels for [`DType::Categorical`] ones, their fixed-size values
+    /// otherwise.
+    pub(crate) fn row_bytes(&self) -> RowBytes<'_> {
+        if let Some(strings) = self.as_str_array() {
+            return RowBytes::Strings(strings);
+        }
+        if let Some(labels) = self.as_categorical() {
+            return RowBytes::Labels(labels);
+        }
+        RowBytes::Values {
+            bytes: self.as_bytes(),
+            size: self.row_size(),
+        }
+    }
+}
+
+/// Bytes of the rows of a column, see [`Column::row_bytes`].
+pub(crate) enum RowBytes<'a> {
+    Strings(StrArrayView<'a>),
+    Labels(CategoricalView<'a>),
+    Values { bytes: &'a [u8], size: usize },
+}
+
+impl<'a> RowBytes<'a> {
+    /// Bytes of row `row`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `row` is out of bounds.
+    pub(crate) fn get(&self, row: usize) -> &'a [u8] {
+        match self {
+            RowBytes::Strings(strings) => strings.get(row).as_bytes(),
+            RowBytes::Labels(labels) => labels.value(row).as_bytes(),
+            RowBytes::Values { bytes, size } => &bytes[row * size..(row + 1) * size],
+        }
+    }
+
+    /// Iterate over the bytes of every row.
+    pub(crate) fn iter(&self) -> Box<dyn Iterator<Item = &'a [u8]> + 'a> {
+        match *self {
+            RowBytes::Strings(strings) => Box::new(strings.iter().map(str::as_bytes)),
+            RowBytes::Labels(labels) => Box::new(labels.iter().map(str::as_bytes)),
+            RowBytes::Values { bytes, size } => Box::new(bytes.chunks_exact(size)),
+        }
+    }
+}
+
+impl Dataset {
+    /// Get the strings of a [`DType::Utf8`] column by name.
+    ///
+    /// Returns an error if the column doesn't exist or doesn't hold strings.
+    pub fn strings(&self, name: &str) -> Result<StrArrayView<'_>> {
+        let column = self.mapped_column(name)?;
+        column.as_str_array().ok_or_else(|| MmappetError::TypeMismatch {
+            column: Some(name.to_string()),
+            expected: DType::Utf8,
+            actual: column.dtype(),
+        })
+    }
+}
+
+/// Path of the string data of the column file at `path`.
+pub(crate) fn data_path(path: &Path) -> std::path::PathBuf {
+    path.with_extension("str")
+}
+
+/// Check that `ends` are ascending char boundaries of valid UTF-8 `data`.
+///
+/// Bytes after the last string are ignored, e.g. those of an interrupted
+/// append.
+pub(crate) fn check_strings(path: &Path, ends: &[u64], data: &[u8]) -> Result<()> {
+    let invalid = |message: String| MmappetError::InvalidStrings {
+        path: data_path(path),
+        column: None,
+        message,
+    };
+    let used = ends.last().map_or(0, |&end| end);
+    if used > data.len() as u64 {
+        return Err(invalid(format!(
+            "offsets end at byte {}, but the file has {} bytes",
+            used,
+            data.len()
+        )));
+    }
+    let text = std::str::from_utf8(&data[..used as usize])
+        .map_err(|e| invalid(format!("invalid UTF-8 at byte {}", e.valid_up_to())))?;
+    let mut start = 0;
+    for (row, &end) in ends.iter().enumerate() {
+        if end < start || !text.is_char_boundary(end as usize) {
+            return Err(invalid(format!("invalid offset {} of row {}", end, row)));
+        }
+        start = end;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::Schema;
+    use crate::writer::DatasetWriter;
+
+    #[test]
+    fn test_string_column() {
+        let dir = std::env::temp_dir().join(format!("mmappet-strings-{}", std::process::id()));
+        let schema = Schema::new(&[("id", DType::UInt32), ("peptide", DType::Utf8)]).unwrap();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write::<u32>("id", &[1, 2, 3, 4]).unwrap();
+        writer.write_str("peptide", &["PEPTIDE", ""]).unwrap();
+        writer.write_str("peptide", &["ÄCDK", "MK"]).unwrap();
+        assert_eq!(writer.finish().unwrap(), 4);
+
+        let ds = Dataset::open(&dir).unwrap();
+        assert_eq!(ds["peptide"].as_str_slice(2), Some("ÄCDK"));
+        assert!(ds["id"].as_str_slice(0).is_none());
+        let all: Vec<&str> = ds["peptide"].iter_str().unwrap().collect();
+        assert_eq!(all, ["PEPTIDE", "", "ÄCDK", "MK"]);
+        let tail: Vec<&str> = ds.strings("peptide").unwrap().slice(1..3).iter().collect();
+        assert_eq!(tail, ["", "ÄCDK"]);
+        assert!(matches!(
+            ds.strings("id"),
+            Err(MmappetError::TypeMismatch { .. })
+        ));
+
+        // Rows can be filtered, sorted and appended
+        let sorted = dir.with_extension("sorted");
+        ds.write_permuted(&ds.argsort("peptide").unwrap(), &sorted).unwrap();
+        let sorted_ds = Dataset::open(&sorted).unwrap();
+        let all: Vec<&str> = sorted_ds["peptide"].iter_str().unwrap().collect();
+        assert_eq!(all, ["", "MK", "PEPTIDE", "ÄCDK"]);
+        assert_eq!(sorted_ds.get::<u32>("id").unwrap(), &[2, 4, 1, 3]);
+        drop(sorted_ds);
+
+        let mut ds = ds;
+        let more = Dataset::open(&sorted).unwrap();
+        let batch = [
+            ("id", more["id"].slice_typed_array(..2)),
+            ("peptide", more["peptide"].slice_typed_array(1..3)),
+        ];
+        assert_eq!(ds.append_batch(&batch).unwrap(), 6);
+        let all: Vec<&str> = ds["peptide"].iter_str().unwrap().collect();
+        assert_eq!(all, ["PEPTIDE", "", "ÄCDK", "MK", "MK", "PEPTIDE"]);
+
+        // A string cut in the middle of a character is rejected
+        std::fs::write(dir.join("1.str"), "PEPTIDE\u{c4}").unwrap();
+        std::fs::write(dir.join("1.bin"), bytemuck::cast_slice(&[7u64, 8, 8, 8, 8, 8])).unwrap();
+        let ds = Dataset::open(&dir).unwrap();
+        assert!(matches!(
+            ds.strings("peptide"),
+            Err(MmappetError::InvalidStrings { .. })
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+        std::fs::remove_dir_all(sorted).unwrap();
+    }
+}
@@ -0,0 +1,19 @@
+//! Fixtures shared by unit tests.
+
+use std::fs;
+use std::path::PathBuf;
+
+/// Write a dataset with `u32` columns to a fresh temporary directory.
+pub(crate) fn write_dataset(name: &str, columns: &[(&str, &[u32])]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mmappet-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut schema = String::new();
+    for (i, (col_name, values)) in columns.iter().enumerate() {
+        schema.push_str(&format!("uint32 {}\n", col_name));
+        fs::write(dir.join(format!("{}.bin", i)), bytemuck::cast_slice(values)).unwrap();
+    }
+    fs::write(dir.join("schema.txt"), schema).unwrap();
+    dir
+}
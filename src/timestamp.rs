@@ -0,0 +1,197 @@
+//! Timestamp columns.
+//!
+//! [`DType::TimestampMillis`](crate::DType::TimestampMillis) and
+//! [`DType::TimestampMicros`](crate::DType::TimestampMicros) columns store the
+//! milli- or microseconds since the Unix epoch as an `i64` per value, UTC.
+//! Sorting, statistics and expressions use these ticks.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytemuck::cast_slice;
+
+use crate::column::Column;
+
+impl Column {
+    /// Ticks of a timestamp column, see
+    /// [`DType::ticks_per_second`](crate::DType::ticks_per_second).
+    ///
+    /// Returns `None` if the column doesn't hold timestamps.
+    pub fn as_timestamps(&self) -> Option<&[i64]> {
+        self.dtype().ticks_per_second()?;
+        Some(cast_slice(self.as_bytes()))
+    }
+
+    /// Iterate over the values of a timestamp column as [`SystemTime`]s.
+    ///
+    /// Returns `None` if the column doesn't hold timestamps.
+    pub fn as_datetimes(&self) -> Option<impl Iterator<Item = SystemTime> + '_> {
+        let per_second = self.dtype().ticks_per_second()?;
+        let ticks = self.as_timestamps()?;
+        Some(ticks.iter().map(move |&t| to_system_time(t, per_second)))
+    }
+}
+
+/// Time `ticks` of `per_second` ticks after the Unix epoch.
+pub(crate) fn to_system_time(ticks: i64, per_second: i64) -> SystemTime {
+    let seconds = ticks.div_euclid(per_second);
+    let nanos = (ticks.rem_euclid(per_second) * (1_000_000_000 / per_second)) as u32;
+    let time = if seconds >= 0 {
+        UNIX_EPOCH + Duration::from_secs(seconds as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs())
+    };
+    time + Duration::from_nanos(nanos as u64)
+}
+
+/// Ticks of `per_second` from the Unix epoch to `time`, rounded down.
+pub(crate) fn to_ticks(time: SystemTime, per_second: i64) -> i64 {
+    let nanos_per_tick = (1_000_000_000 / per_second) as u128;
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => (after.as_nanos() / nanos_per_tick) as i64,
+        Err(before) => -(before.duration().as_nanos().div_ceil(nanos_per_tick) as i64),
+    }
+}
+
+/// Format `ticks` of `per_second` since the Unix epoch as ISO 8601 in UTC,
+/// with as many fractional digits as a tick needs.
+pub(crate) fn format_iso8601(ticks: i64, per_second: i64) -> String {
+    let seconds = ticks.div_euclid(per_second);
+    let fraction = ticks.rem_euclid(per_second);
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    let second_of_day = seconds.rem_euclid(86_400);
+    let mut text = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        second_of_day / 3600,
+        second_of_day / 60 % 60,
+        second_of_day % 60
+    );
+    if per_second > 1 {
+        let digits = per_second.ilog10() as usize;
+        text.push_str(&format!(".{:0digits$}", fraction, digits = digits));
+    }
+    text.push('Z');
+    text
+}
+
+/// Parse ISO 8601 text in UTC like [`format_iso8601`] writes, e.g.
+/// `2024-05-01T12:30:00.250Z`, to ticks of `per_second`; digits finer than
+/// a tick are dropped. Returns `None` if the text isn't such a time.
+pub(crate) fn parse_iso8601(text: &str, per_second: i64) -> Option<i64> {
+    fn number(text: &str, digits: usize) -> Option<i64> {
+        if text.len() != digits || !text.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        text.parse().ok()
+    }
+
+    let text = text.strip_suffix('Z').or_else(|| text.strip_suffix("+00:00"))?;
+    let (date, time) = text.split_once('T')?;
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let date: Vec<&str> = date.split('-').collect();
+    let time: Vec<&str> = time.split(':').collect();
+    let ([year, month, day], [hour, minute, second]) = (&date[..], &time[..]) else {
+        return None;
+    };
+    let (year, month, day) = (number(year, 4)?, number(month, 2)?, number(day, 2)?);
+    let (hour, minute, second) = (number(hour, 2)?, number(minute, 2)?, number(second, 2)?);
+    if !(1..=12).contains(&month) || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let days = days_from_civil(year, month as u32, day as u32);
+    if day < 1 || civil_from_days(days) != (year, month as u32, day as u32) {
+        return None;
+    }
+
+    let digits = per_second.ilog10() as usize;
+    if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let mut ticks = 0;
+    for i in 0..digits {
+        ticks = ticks * 10 + fraction.as_bytes().get(i).map_or(0, |b| (b - b'0') as i64);
+    }
+    let seconds = days * 86_400 + hour * 3600 + minute * 60 + second;
+    seconds.checked_mul(per_second)?.checked_add(ticks)
+}
+
+/// Days from 1970-01-01 to a day of the proleptic Gregorian calendar, the
+/// inverse of [`civil_from_days`].
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * ((month as i64 + 9) % 12) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Year, month and day of `days` after 1970-01-01 in the proleptic Gregorian
+/// calendar (Howard Hinnant's `civil_from_days`).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::Dataset;
+    use crate::dtype::DType;
+    use crate::error::MmappetError;
+    use crate::format::ValueFormat;
+    use crate::schema::Schema;
+    use crate::writer::DatasetWriter;
+
+    #[test]
+    fn test_timestamp_column() {
+        let dir = std::env::temp_dir().join(format!("mmappet-timestamps-{}", std::process::id()));
+        let schema = Schema::new(&[
+            ("acquired", DType::TimestampMillis),
+            ("scanned", DType::TimestampMicros),
+        ])
+        .unwrap();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write_timestamps("acquired", &[1_714_566_600_250, -1]).unwrap();
+        let times = [UNIX_EPOCH + Duration::from_micros(1_500_001), UNIX_EPOCH - Duration::from_micros(1)];
+        writer.write_datetimes("scanned", &times).unwrap();
+        assert!(matches!(
+            writer.write::<i64>("acquired", &[0]),
+            Err(MmappetError::TypeMismatch { .. })
+        ));
+        assert_eq!(writer.finish().unwrap(), 2);
+
+        let ds = Dataset::open(&dir).unwrap();
+        assert_eq!(ds["acquired"].as_timestamps(), Some(&[1_714_566_600_250, -1][..]));
+        assert_eq!(ds["scanned"].as_datetimes().unwrap().collect::<Vec<_>>(), times);
+        assert!(ds["acquired"].as_str_slice(0).is_none());
+
+        let format = ValueFormat::default();
+        let acquired = ds["acquired"].as_typed_array();
+        assert_eq!(format.format_at(&acquired, 0), "2024-05-01T12:30:00.250Z");
+        assert_eq!(format.format_at(&acquired, 1), "1969-12-31T23:59:59.999Z");
+        assert_eq!(format.format_at(&ds["scanned"].as_typed_array(), 0), "1970-01-01T00:00:01.500001Z");
+        assert_eq!(format_iso8601(951_782_400, 1), "2000-02-29T00:00:00Z");
+        assert_eq!(parse_iso8601("2024-05-01T12:30:00.250Z", 1_000), Some(1_714_566_600_250));
+        assert_eq!(parse_iso8601("1969-12-31T23:59:59.999999Z", 1_000), Some(-1));
+        assert_eq!(parse_iso8601("2000-02-29T00:00:00+00:00", 1_000_000), Some(951_782_400_000_000));
+        assert_eq!(parse_iso8601("2001-02-29T00:00:00Z", 1_000), None);
+        assert_eq!(parse_iso8601("2024-05-01 12:30:00", 1_000), None);
+
+        assert_eq!(ds.argsort("acquired").unwrap(), vec![1, 0]);
+        let stats = ds["scanned"].stats().unwrap();
+        assert_eq!((stats.min, stats.max), (-1.0, 1_500_001.0));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,412 @@
+//! Deep consistency checks of datasets, beyond what opening checks.
+//!
+//! Two metadata keys declare expectations that are checked: `sorted` lists
+//! columns (comma-separated) whose values never decrease, and
+//! `checksum.{column}` holds the CRC-32 of a column file as `crc32:{hex}`,
+//! see [`Dataset::record_checksums`]. Columns marked sorted in schema.json
+//! are checked as well.
+
+use std::fmt;
+use std::path::Path;
+
+use crate::column::{swap_bytes, Column, TypedArrayView};
+use crate::dataset::{column_file, Dataset};
+use crate::error::{MmappetError, Result};
+use crate::meta::read_metadata;
+use crate::schema::Schema;
+
+/// Metadata key listing the columns expected to be sorted ascending.
+const SORTED_KEY: &str = "sorted";
+/// Prefix of the metadata keys holding column checksums.
+const CHECKSUM_PREFIX: &str = "checksum.";
+
+/// A problem found by [`Dataset::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ValidationIssue {
+    /// The column's file is missing or its reference can't be resolved.
+    MissingFile { column: String, message: String },
+    /// The file size isn't a multiple of the element size.
+    InvalidFileSize {
+        column: String,
+        actual: usize,
+        element_size: usize,
+    },
+    /// The column has a different row count than the first readable column.
+    LengthMismatch {
+        column: String,
+        expected: usize,
+        actual: usize,
+    },
+    /// The file couldn't be read.
+    Unreadable { column: String, message: String },
+    /// A column flagged as sorted decreases from `row - 1` to `row`.
+    NotSorted { column: String, row: usize },
+    /// The file doesn't match its recorded checksum.
+    ChecksumMismatch {
+        column: String,
+        expected: String,
+        actual: String,
+    },
+    /// `meta.txt` can't be read or declares something about an unknown column.
+    InvalidMetadata { message: String },
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationIssue::MissingFile { column, message } => {
+                write!(f, "column '{}': {}", column, message)
+            }
+            ValidationIssue::InvalidFileSize {
+                column,
+                actual,
+                element_size,
+            } => write!(
+                f,
+                "column '{}': file has {} bytes, not a multiple of {}",
+                column, actual, element_size
+            ),
+            ValidationIssue::LengthMismatch {
+                column,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "column '{}': {} rows, expected {}",
+                column, actual, expected
+            ),
+            ValidationIssue::Unreadable { column, message } => {
+                write!(f, "column '{}': {}", column, message)
+            }
+            ValidationIssue::NotSorted { column, row } => {
+                write!(
+                    f,
+                    "column '{}': flagged as sorted but decreases at row {}",
+                    column, row
+                )
+            }
+            ValidationIssue::ChecksumMismatch {
+                column,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "column '{}': checksum is {}, recorded {}",
+                column, actual, expected
+            ),
+            ValidationIssue::InvalidMetadata { message } => write!(f, "metadata: {}", message),
+        }
+    }
+}
+
+/// What [`Dataset::validate`] found out about one column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnReport {
+    pub name: String,
+    /// Number of rows, `None` if the file couldn't be read.
+    pub rows: Option<usize>,
+    /// NaN values, always 0 for non-float columns.
+    pub nan_count: usize,
+    /// Infinite values, always 0 for non-float columns.
+    pub inf_count: usize,
+    /// Whether the values never decrease, if the column is flagged as sorted.
+    pub sorted: Option<bool>,
+    /// Whether the file matches its recorded checksum, if there is one.
+    pub checksum_ok: Option<bool>,
+}
+
+/// Result of [`Dataset::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// One report per column, in schema order.
+    pub columns: Vec<ColumnReport>,
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    /// Check if no issues were found.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl Dataset {
+    /// Check the dataset in `path` in depth and report every problem found.
+    ///
+    /// Checks that column files exist, fit their dtype and have equal
+    /// lengths, counts NaN and infinite floats, and checks the columns
+    /// flagged as sorted and the recorded checksums (see the module docs of
+    /// the metadata keys). Unlike [`Dataset::open`] this reads all data and
+    /// doesn't stop at the first problem. Only fails if the schema can't be
+    /// read.
+    pub fn validate<P: AsRef<Path>>(path: P) -> Result<ValidationReport> {
+        let path = path.as_ref();
+        let schema = Schema::from_path(path)?;
+        let mut issues = Vec::new();
+
+        let metadata = read_metadata(path).unwrap_or_else(|e| {
+            issues.push(ValidationIssue::InvalidMetadata {
+                message: e.to_string(),
+            });
+            Default::default()
+        });
+        let sorted: Vec<&str> = metadata
+            .get(SORTED_KEY)
+            .map(|names| {
+                names
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|n| !n.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        for name in &sorted {
+            if schema.get(name).is_none() {
+                issues.push(ValidationIssue::InvalidMetadata {
+                    message: format!("'{}' lists unknown column '{}'", SORTED_KEY, name),
+                });
+            }
+        }
+        for key in metadata.keys() {
+            if let Some(name) = key.strip_prefix(CHECKSUM_PREFIX) {
+                if schema.get(name).is_none() {
+                    issues.push(ValidationIssue::InvalidMetadata {
+                        message: format!("checksum of unknown column '{}'", name),
+                    });
+                }
+            }
+        }
+
+        let mut columns = Vec::with_capacity(schema.len());
+        let mut expected_rows = None;
+        for col_def in schema.columns() {
+            let name = &col_def.name;
+            let mut report = ColumnReport {
+                name: name.clone(),
+                rows: None,
+                nan_count: 0,
+                inf_count: 0,
+                sorted: None,
+                checksum_ok: None,
+            };
+
+            let column = column_file(path, &schema, col_def)
+                .map_err(|e| ValidationIssue::MissingFile {
+                    column: name.clone(),
+                    message: e.to_string(),
+                })
+                .and_then(|(col_path, byte_order)| {
+                    Column::open_with_byte_order(&col_path, col_def.dtype, byte_order)
+                        .and_then(|column| column.with_shape(&col_path, &col_def.shape))
+                        .map_err(|e| match e {
+                            MmappetError::InvalidFileSize {
+                                actual,
+                                element_size,
+                                ..
+                            } => ValidationIssue::InvalidFileSize {
+                                column: name.clone(),
+                                actual,
+                                element_size,
+                            },
+                            e => ValidationIssue::Unreadable {
+                                column: name.clone(),
+                                message: e.to_string(),
+                            },
+                        })
+                });
+            let column = match column {
+                Ok(column) => column,
+                Err(issue) => {
+                    issues.push(issue);
+                    columns.push(report);
+                    continue;
+                }
+            };
+
+            report.rows = Some(column.len());
+            match expected_rows {
+                None => expected_rows = Some(column.len()),
+                Some(expected) if expected != column.len() => {
+                    issues.push(ValidationIssue::LengthMismatch {
+                        column: name.clone(),
+                        expected,
+                        actual: column.len(),
+                    });
+                }
+                Some(_) => {}
+            }
+
+            let array = column.as_typed_array();
+            (report.nan_count, report.inf_count) = non_finite_counts(&array);
+            let is_sorted = col_def.attributes.sorted || sorted.contains(&name.as_str());
+            if is_sorted && column.width() == 1 {
+                let decrease = first_decrease(&array);
+                report.sorted = Some(decrease.is_none());
+                if let Some(row) = decrease {
+                    issues.push(ValidationIssue::NotSorted {
+                        column: name.clone(),
+                        row,
+                    });
+                }
+            }
+            if let Some(expected) = metadata.get(&format!("{}{}", CHECKSUM_PREFIX, name)) {
+                let actual = checksum(&column);
+                report.checksum_ok = Some(*expected == actual);
+                if *expected != actual {
+                    issues.push(ValidationIssue::ChecksumMismatch {
+                        column: name.clone(),
+                        expected: expected.clone(),
+                        actual,
+                    });
+                }
+            }
+            columns.push(report);
+        }
+
+        Ok(ValidationReport { columns, issues })
+    }
+
+    /// Record the checksum of every column in the metadata, for
+    /// [`Dataset::validate`] to check later.
+    pub fn record_checksums(&self, tool: &str) -> Result<()> {
+        let mut checksums = Vec::with_capacity(self.num_columns());
+        for name in self.column_names() {
+            let column = self.mapped_column(name)?;
+            checksums.push((format!("{}{}", CHECKSUM_PREFIX, name), checksum(column)));
+        }
+        let changes: Vec<(&str, &str)> = checksums
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        self.set_metadata(&changes, tool)
+    }
+}
+
+/// Checksum of a column file as stored in the metadata, followed by the
+/// strings of a string column or the labels of a categorical one.
+fn checksum(column: &Column) -> String {
+    let mut hasher = crc32fast::Hasher::new();
+    if column.is_swapped() {
+        // Swap back in pieces, to hash the bytes as stored in the file
+        let size = column.dtype().size_bytes();
+        let mut buf = Vec::new();
+        for piece in column.as_bytes().chunks(size * 8192) {
+            buf.clear();
+            buf.extend_from_slice(piece);
+            swap_bytes(&mut buf, size);
+            hasher.update(&buf);
+        }
+    } else {
+        hasher.update(column.as_bytes());
+    }
+    if let Some(strings) = column.as_str_array() {
+        hasher.update(strings.bytes());
+    }
+    if let Some(labels) = column.as_categorical() {
+        for label in labels.categories() {
+            hasher.update(label.as_bytes());
+            hasher.update(b"\n");
+        }
+    }
+    format!("crc32:{:08x}", hasher.finalize())
+}
+
+/// NaN and infinite values of a float array.
+fn non_finite_counts(array: &TypedArrayView<'_>) -> (usize, usize) {
+    fn counts(values: impl Iterator<Item = f64>) -> (usize, usize) {
+        values.fold((0, 0), |(nan, inf), x| {
+            (nan + x.is_nan() as usize, inf + x.is_infinite() as usize)
+        })
+    }
+
+    match array {
+        TypedArrayView::Float16(a) => counts(a.iter().map(|x| x.to_f64())),
+        TypedArrayView::Float32(a) => counts(a.iter().map(|&x| x as f64)),
+        TypedArrayView::Float64(a) => counts(a.iter().copied()),
+        _ => (0, 0),
+    }
+}
+
+/// First row whose value is smaller than the previous one.
+fn first_decrease(array: &TypedArrayView<'_>) -> Option<usize> {
+    fn first<T: PartialOrd>(values: &[T]) -> Option<usize> {
+        values.windows(2).position(|w| w[1] < w[0]).map(|i| i + 1)
+    }
+
+    match array {
+        TypedArrayView::UInt8(a) | TypedArrayView::Bool(a) => first(a.as_slice().unwrap()),
+        TypedArrayView::Int8(a) => first(a.as_slice().unwrap()),
+        TypedArrayView::UInt16(a) => first(a.as_slice().unwrap()),
+        TypedArrayView::Int16(a) => first(a.as_slice().unwrap()),
+        TypedArrayView::UInt32(a) => first(a.as_slice().unwrap()),
+        TypedArrayView::Int32(a) => first(a.as_slice().unwrap()),
+        TypedArrayView::UInt64(a) => first(a.as_slice().unwrap()),
+        TypedArrayView::Int64(a) | TypedArrayView::TimestampMillis(a) | TypedArrayView::TimestampMicros(a) => {
+            first(a.as_slice().unwrap())
+        }
+        TypedArrayView::Float16(a) => first(a.as_slice().unwrap()),
+        TypedArrayView::Float32(a) => first(a.as_slice().unwrap()),
+        TypedArrayView::Float64(a) => first(a.as_slice().unwrap()),
+        TypedArrayView::Utf8(a) => (1..a.len()).find(|&i| a.get(i) < a.get(i - 1)),
+        TypedArrayView::Categorical(a) => (1..a.len()).find(|&i| a.value(i) < a.value(i - 1)),
+        // Custom values without a numeric value compare as NaN, i.e. never decrease
+        TypedArrayView::Custom(a) => (1..a.len()).find(|&i| {
+            let value = |i| array.get_f64(i).unwrap_or(f64::NAN);
+            value(i) < value(i - 1)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::write_dataset;
+
+    #[test]
+    fn test_validate() {
+        let dir = write_dataset(
+            "validate",
+            &[("frame", &[1, 2, 2, 3]), ("tof", &[5, 3, 4, 1])],
+        );
+        let ds = Dataset::open(&dir).unwrap();
+        ds.record_checksums("test").unwrap();
+        ds.set_metadata(&[("sorted", "frame,tof,gone")], "test")
+            .unwrap();
+        drop(ds);
+
+        let report = Dataset::validate(&dir).unwrap();
+        assert_eq!(report.columns[0].sorted, Some(true));
+        assert_eq!(report.columns[0].checksum_ok, Some(true));
+        assert_eq!(report.columns[1].rows, Some(4));
+        assert_eq!(
+            report.issues,
+            vec![
+                ValidationIssue::InvalidMetadata {
+                    message: "'sorted' lists unknown column 'gone'".to_string()
+                },
+                ValidationIssue::NotSorted {
+                    column: "tof".to_string(),
+                    row: 1
+                },
+            ]
+        );
+
+        // Every broken column is reported, not just the first
+        std::fs::write(dir.join("0.bin"), [0u8; 6]).unwrap();
+        std::fs::write(dir.join("1.bin"), bytemuck::cast_slice(&[5u32, 3, 4])).unwrap();
+        let report = Dataset::validate(&dir).unwrap();
+        assert!(!report.is_ok());
+        assert!(matches!(
+            report.issues[1],
+            ValidationIssue::InvalidFileSize { actual: 6, .. }
+        ));
+        assert!(matches!(
+            report.issues[3],
+            ValidationIssue::ChecksumMismatch { .. }
+        ));
+        assert_eq!(report.columns[0].rows, None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
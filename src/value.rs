@@ -0,0 +1,278 @@
+//! Dynamically typed values, for tools that can't name column types.
+
+use std::fmt;
+
+use half::f16;
+
+use crate::column::{Column, TypedArrayView};
+use crate::dataset::Dataset;
+use crate::error::Result;
+use crate::format::ValueFormat;
+use crate::registry;
+
+/// A value of any dtype, e.g. from [`Row::get`].
+///
+/// Text borrows from the dataset, so values are cheap to read.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value<'a> {
+    /// A null row of a column with a validity bitmap.
+    Null,
+    U8(u8),
+    I8(i8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    U64(u64),
+    I64(i64),
+    F16(f16),
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    /// Milliseconds since the Unix epoch.
+    TimestampMillis(i64),
+    /// Microseconds since the Unix epoch.
+    TimestampMicros(i64),
+    /// Text of a `utf8` column, or label of a `categorical` one.
+    Str(&'a str),
+    /// Bytes of a value of the registered dtype `dtype`.
+    Custom { dtype: &'static str, bytes: &'a [u8] },
+    /// The values of a row of an array column, nested by its shape.
+    Array(Vec<Value<'a>>),
+}
+
+impl<'a> Value<'a> {
+    /// Check if the value is [`Value::Null`].
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /// The value as a float: numbers, booleans as 0 or 1, timestamps in
+    /// ticks and custom values with stats support. `None` for others.
+    pub fn as_f64(&self) -> Option<f64> {
+        Some(match *self {
+            Value::U8(v) => v as f64,
+            Value::I8(v) => v as f64,
+            Value::U16(v) => v as f64,
+            Value::I16(v) => v as f64,
+            Value::U32(v) => v as f64,
+            Value::I32(v) => v as f64,
+            Value::U64(v) => v as f64,
+            Value::I64(v) | Value::TimestampMillis(v) | Value::TimestampMicros(v) => v as f64,
+            Value::F16(v) => v.to_f64(),
+            Value::F32(v) => v as f64,
+            Value::F64(v) => v,
+            Value::Bool(v) => v as u8 as f64,
+            Value::Custom { dtype, bytes } => registry::lookup(dtype)?.to_f64?(bytes),
+            Value::Null | Value::Str(_) | Value::Array(_) => return None,
+        })
+    }
+
+    /// The text of a [`Value::Str`].
+    pub fn as_str(&self) -> Option<&'a str> {
+        match *self {
+            Value::Str(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value<'_> {
+    /// Format as [`ValueFormat::default`] does.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&ValueFormat::default().format_value(self))
+    }
+}
+
+impl ValueFormat {
+    /// Format a dynamically-typed value; nulls are `null` and arrays are
+    /// nested in brackets.
+    pub fn format_value(&self, value: &Value<'_>) -> String {
+        match *value {
+            Value::Null => "null".to_string(),
+            Value::U8(v) => self.format_int(v),
+            Value::I8(v) => self.format_int(v),
+            Value::U16(v) => self.format_int(v),
+            Value::I16(v) => self.format_int(v),
+            Value::U32(v) => self.format_int(v),
+            Value::I32(v) => self.format_int(v),
+            Value::U64(v) => self.format_int(v),
+            Value::I64(v) => self.format_int(v),
+            Value::F16(v) => self.format_float(v),
+            Value::F32(v) => self.format_float(v),
+            Value::F64(v) => self.format_float(v),
+            Value::Bool(v) => self.format_bool(v),
+            Value::TimestampMillis(v) => self.format_timestamp(v, 1_000),
+            Value::TimestampMicros(v) => self.format_timestamp(v, 1_000_000),
+            Value::Str(text) => text.to_string(),
+            Value::Custom { dtype, bytes } => match registry::lookup(dtype) {
+                Some(def) => (def.format)(bytes, self),
+                None => format!("<{} bytes of {}>", bytes.len(), dtype),
+            },
+            Value::Array(ref values) => {
+                let values: Vec<String> = values.iter().map(|v| self.format_value(v)).collect();
+                format!("[{}]", values.join(", "))
+            }
+        }
+    }
+}
+
+impl<'a> TypedArrayView<'a> {
+    /// Element `index` as a [`Value`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn value(&self, index: usize) -> Value<'a> {
+        match self {
+            TypedArrayView::UInt8(arr) => Value::U8(arr[index]),
+            TypedArrayView::Int8(arr) => Value::I8(arr[index]),
+            TypedArrayView::UInt16(arr) => Value::U16(arr[index]),
+            TypedArrayView::Int16(arr) => Value::I16(arr[index]),
+            TypedArrayView::UInt32(arr) => Value::U32(arr[index]),
+            TypedArrayView::Int32(arr) => Value::I32(arr[index]),
+            TypedArrayView::UInt64(arr) => Value::U64(arr[index]),
+            TypedArrayView::Int64(arr) => Value::I64(arr[index]),
+            TypedArrayView::Float16(arr) => Value::F16(arr[index]),
+            TypedArrayView::Float32(arr) => Value::F32(arr[index]),
+            TypedArrayView::Float64(arr) => Value::F64(arr[index]),
+            TypedArrayView::Bool(arr) => Value::Bool(arr[index] != 0),
+            TypedArrayView::TimestampMillis(arr) => Value::TimestampMillis(arr[index]),
+            TypedArrayView::TimestampMicros(arr) => Value::TimestampMicros(arr[index]),
+            TypedArrayView::Utf8(arr) => Value::Str(arr.get(index)),
+            TypedArrayView::Categorical(arr) => Value::Str(arr.value(index)),
+            TypedArrayView::Custom(arr) => Value::Custom {
+                dtype: arr.def().name,
+                bytes: arr.get(index),
+            },
+        }
+    }
+}
+
+impl Column {
+    /// The value of `row`, a [`Value::Array`] nested by the shape for array
+    /// columns. Validity bitmaps aren't consulted, see [`Row::get`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `row` is out of bounds.
+    pub fn value(&self, row: usize) -> Value<'_> {
+        assert!(row < self.len(), "row {} out of bounds for length {}", row, self.len());
+        let array = self.as_typed_array();
+        let start = row * self.width();
+        let mut values = (start..start + self.width()).map(|i| array.value(i));
+        nest(&mut values, self.shape())
+    }
+}
+
+/// Group `values` into arrays of `shape`, or take one value for scalars.
+fn nest<'a>(values: &mut impl Iterator<Item = Value<'a>>, shape: &[usize]) -> Value<'a> {
+    match shape.split_first() {
+        None => values.next().unwrap(),
+        Some((&len, rest)) => Value::Array((0..len).map(|_| nest(values, rest)).collect()),
+    }
+}
+
+/// A row of a dataset, whose values are read by column name, see
+/// [`Dataset::row`].
+#[derive(Clone, Copy)]
+pub struct Row<'a> {
+    dataset: &'a Dataset,
+    index: usize,
+}
+
+impl Dataset {
+    /// Row `index`, for reading values without knowing their types.
+    ///
+    /// ```rust,no_run
+    /// let ds = mmappet::Dataset::open("data.mmappet")?;
+    /// let row = ds.row(0);
+    /// for name in ds.schema().column_names() {
+    ///     println!("{}: {}", name, row.get(name)?);
+    /// }
+    /// # Ok::<(), mmappet::MmappetError>(())
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn row(&self, index: usize) -> Row<'_> {
+        assert!(index < self.len(), "row {} out of bounds for length {}", index, self.len());
+        Row {
+            dataset: self,
+            index,
+        }
+    }
+}
+
+impl<'a> Row<'a> {
+    /// Position of the row in the dataset.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The value of column `name`, [`Value::Null`] where its validity bitmap
+    /// marks the row null.
+    ///
+    /// Each call checks for the bitmap; typed access is much faster for
+    /// scanning many rows. Returns an error if the column doesn't exist.
+    pub fn get(&self, name: &str) -> Result<Value<'a>> {
+        let column = self.dataset.nullable(name)?;
+        if !column.is_valid(self.index) {
+            return Ok(Value::Null);
+        }
+        Ok(column.column().value(self.index))
+    }
+
+    /// The values of all columns, in schema order.
+    pub fn values(&self) -> Result<Vec<Value<'a>>> {
+        let dataset = self.dataset;
+        dataset.schema().columns().map(|def| self.get(&def.name)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dtype::DType;
+    use crate::schema::Schema;
+    use crate::writer::DatasetWriter;
+
+    #[test]
+    fn test_row_values() {
+        let dir = std::env::temp_dir().join(format!("mmappet-value-{}", std::process::id()));
+        let mut schema = Schema::new(&[
+            ("mz", DType::Float64),
+            ("charge", DType::UInt8),
+            ("xy", DType::Float32),
+            ("peptide", DType::Utf8),
+            ("protein", DType::Categorical),
+        ])
+        .unwrap();
+        schema.set_shape("xy", &[2, 2]).unwrap();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write("mz", &[401.5f64, 502.25]).unwrap();
+        writer.write_opt("charge", &[Some(2u8), None]).unwrap();
+        writer.write("xy", &(0..8).map(|i| i as f32).collect::<Vec<_>>()).unwrap();
+        writer.write_str("peptide", &["PEP", "TIDE"]).unwrap();
+        writer.write_categorical("protein", &["P1", "P2"]).unwrap();
+        writer.finish().unwrap();
+
+        let ds = Dataset::open(&dir).unwrap();
+        let row = ds.row(1);
+        assert_eq!(row.get("mz").unwrap(), Value::F64(502.25));
+        assert_eq!(row.get("charge").unwrap(), Value::Null);
+        assert_eq!(ds.row(0).get("charge").unwrap().as_f64(), Some(2.0));
+        assert_eq!(row.get("peptide").unwrap().as_str(), Some("TIDE"));
+        assert_eq!(row.get("protein").unwrap(), Value::Str("P2"));
+        let pair = |a: f32, b: f32| Value::Array(vec![Value::F32(a), Value::F32(b)]);
+        let xy = row.get("xy").unwrap();
+        assert_eq!(xy, Value::Array(vec![pair(4.0, 5.0), pair(6.0, 7.0)]));
+        let format = ValueFormat { precision: Some(1), ..Default::default() };
+        assert_eq!(format.format_value(&xy), "[[4.0, 5.0], [6.0, 7.0]]");
+        assert_eq!(row.values().unwrap().len(), 5);
+        assert!(row.get("missing").is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,301 @@
+//! Zero-copy row-range views over a dataset.
+
+use std::ops::{Bound, Range, RangeBounds};
+
+use ndarray::ArrayView1;
+
+use crate::column::{checked_range, TypedArrayView};
+use crate::delta;
+use crate::readahead::Readahead;
+use crate::dataset::Dataset;
+use crate::dtype::MmappetType;
+use crate::error::{MmappetError, Result};
+use crate::schema::Schema;
+
+impl Dataset {
+    /// View a contiguous range of rows without copying.
+    ///
+    /// Views are cheap to create and `Copy`, so a large dataset can be split
+    /// into parts handed to worker threads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rows` is out of bounds.
+    pub fn slice(&self, rows: impl RangeBounds<usize>) -> DatasetView<'_> {
+        DatasetView::new(self, checked_range(rows, self.len()))
+    }
+
+    /// Iterate over the dataset in consecutive views of `chunk_rows` rows.
+    ///
+    /// Pages are only touched as chunks are read, so datasets larger than
+    /// memory can be processed in a single pass. The last chunk may be
+    /// shorter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_rows` is zero.
+    pub fn chunks(&self, chunk_rows: usize) -> DatasetChunks<'_> {
+        assert!(chunk_rows > 0, "chunk_rows must be non-zero");
+        DatasetChunks {
+            dataset: self,
+            chunk_rows,
+            offset: 0,
+            readahead: Vec::new(),
+        }
+    }
+
+    /// View the rows whose values in the sorted column `name` lie in `values`,
+    /// found by binary search.
+    ///
+    /// The column must be sorted in ascending order (e.g. `frame` or
+    /// `retention_time`); otherwise the result is unspecified. Returns an
+    /// error if the column doesn't exist, holds arrays or the type doesn't
+    /// match.
+    ///
+    /// A delta-encoded column (see
+    /// [`DatasetWriter::delta_encode`](crate::DatasetWriter::delta_encode))
+    /// that wasn't accessed yet is searched by its samples, without decoding
+    /// all of it.
+    pub fn range_by<T: MmappetType + PartialOrd>(
+        &self,
+        name: &str,
+        values: impl RangeBounds<T>,
+    ) -> Result<DatasetView<'_>> {
+        let encoded = match self.column_if_mapped(name) {
+            Some(_) => None,
+            None => delta::delta_file(self.column_path(name)?),
+        };
+        let data: &[T] = match &encoded {
+            Some(_) => {
+                let dtype = self.schema().get(name).unwrap().dtype;
+                if dtype != T::DTYPE {
+                    return Err(MmappetError::TypeMismatch {
+                        column: Some(name.to_string()),
+                        expected: dtype,
+                        actual: T::DTYPE,
+                    });
+                }
+                &[]
+            }
+            None => {
+                self.mapped_column(name)?.check_scalar(name)?;
+                self.get::<T>(name)?
+            }
+        };
+        let partition_point = |pred: &dyn Fn(&T) -> bool| match &encoded {
+            Some(path) => delta::partition_point(path, pred),
+            None => Ok(data.partition_point(pred)),
+        };
+
+        let start = match values.start_bound() {
+            Bound::Included(lo) => partition_point(&|x| x < lo)?,
+            Bound::Excluded(lo) => partition_point(&|x| x <= lo)?,
+            Bound::Unbounded => 0,
+        };
+        let end = match values.end_bound() {
+            Bound::Included(hi) => partition_point(&|x| x <= hi)?,
+            Bound::Excluded(hi) => partition_point(&|x| x < hi)?,
+            Bound::Unbounded => self.len(),
+        };
+        Ok(DatasetView::new(self, start..end.max(start)))
+    }
+}
+
+/// A contiguous range of rows of a [`Dataset`], borrowing its columns.
+#[derive(Clone, Copy)]
+pub struct DatasetView<'a> {
+    dataset: &'a Dataset,
+    start: usize,
+    end: usize,
+}
+
+impl<'a> DatasetView<'a> {
+    /// Create a view of `rows`, which must lie within the dataset.
+    pub(crate) fn new(dataset: &'a Dataset, rows: Range<usize>) -> Self {
+        debug_assert!(rows.start <= rows.end && rows.end <= dataset.len());
+        DatasetView {
+            dataset,
+            start: rows.start,
+            end: rows.end,
+        }
+    }
+
+    /// The dataset this view borrows from.
+    pub fn dataset(&self) -> &'a Dataset {
+        self.dataset
+    }
+
+    /// Row range of the underlying dataset covered by this view.
+    pub fn rows(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// Get the schema.
+    pub fn schema(&self) -> &'a Schema {
+        self.dataset.schema()
+    }
+
+    /// Get a typed slice of the view's rows by column name, holding all
+    /// values of array columns row after row.
+    ///
+    /// Returns an error if the column doesn't exist or the type doesn't match.
+    pub fn get<T: MmappetType>(&self, name: &str) -> Result<&'a [T]> {
+        let data = self.dataset.get::<T>(name)?;
+        let width = self.dataset.mapped_column(name)?.width();
+        Ok(&data[self.start * width..self.end * width])
+    }
+
+    /// Get the view's rows of a column as dynamically-typed array enum.
+    ///
+    /// Returns an error if the column doesn't exist.
+    pub fn typed_array(&self, name: &str) -> Result<TypedArrayView<'a>> {
+        let column = self
+            .dataset
+            .column(name)
+            .ok_or_else(|| self.dataset.column_not_found(name))?;
+        Ok(column.slice_typed_array(self.start..self.end))
+    }
+
+    /// Narrow the view to `rows`, counted from the start of the view.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rows` is out of bounds.
+    pub fn slice(&self, rows: impl RangeBounds<usize>) -> DatasetView<'a> {
+        let rows = checked_range(rows, self.len());
+        DatasetView::new(self.dataset, self.start + rows.start..self.start + rows.end)
+    }
+
+    /// Get a typed ArrayView1 of the view's rows by column name.
+    ///
+    /// Returns an error if the column doesn't exist or the type doesn't match.
+    pub fn get_array<T: MmappetType>(&self, name: &str) -> Result<ArrayView1<'a, T>> {
+        self.get::<T>(name).map(ArrayView1::from)
+    }
+
+    /// Number of rows in the view.
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Check if the view is empty.
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Number of columns.
+    pub fn num_columns(&self) -> usize {
+        self.dataset.num_columns()
+    }
+
+    /// Iterate over column names.
+    pub fn column_names(&self) -> impl Iterator<Item = &'a str> {
+        self.dataset.schema().column_names().into_iter()
+    }
+}
+
+/// Sequential iterator over fixed-size row chunks of a dataset.
+///
+/// Created by [`Dataset::chunks`].
+pub struct DatasetChunks<'a> {
+    dataset: &'a Dataset,
+    chunk_rows: usize,
+    offset: usize,
+    /// One prefetcher per column, in schema order.
+    readahead: Vec<Readahead>,
+}
+
+impl DatasetChunks<'_> {
+    /// Prefetch pages of every column on background threads, staying
+    /// `distance` rows ahead of the chunk currently being consumed.
+    ///
+    /// The prefetchers advise the kernel of the upcoming ranges (`madvise`
+    /// on Unix) and fault the pages in, so I/O overlaps with processing.
+    pub fn with_readahead(mut self, distance: usize) -> Self {
+        self.readahead = self
+            .dataset
+            .schema()
+            .columns()
+            .map(|col| {
+                let column = self.dataset.column(&col.name).unwrap();
+                let size = column.row_size();
+                let readahead = column.readahead(distance.saturating_mul(size));
+                readahead.advance(self.offset * size);
+                readahead
+            })
+            .collect();
+        self
+    }
+}
+
+impl<'a> Iterator for DatasetChunks<'a> {
+    type Item = DatasetView<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let len = self.dataset.len();
+        if self.offset >= len {
+            return None;
+        }
+
+        let end = (self.offset + self.chunk_rows).min(len);
+        let view = DatasetView::new(self.dataset, self.offset..end);
+        self.offset = end;
+
+        for (readahead, col) in self.readahead.iter().zip(self.dataset.schema().columns()) {
+            let column = self.dataset.column(&col.name).unwrap();
+            readahead.advance(self.offset * column.row_size());
+        }
+
+        Some(view)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.dataset.len() - self.offset).div_ceil(self.chunk_rows);
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for DatasetChunks<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use crate::dataset::Dataset;
+    use crate::test_util::write_dataset;
+
+    #[test]
+    fn test_slice() {
+        let dir = write_dataset(
+            "slice",
+            &[("frame", &[1, 2, 3, 4, 5]), ("tof", &[9, 8, 7, 6, 5])],
+        );
+        let ds = Dataset::open(&dir).unwrap();
+
+        let view = ds.slice(1..4);
+        assert_eq!(view.len(), 3);
+        assert_eq!(view.get::<u32>("frame").unwrap(), &[2, 3, 4]);
+        assert_eq!(view.typed_array("tof").unwrap().get_f64(0), Some(8.0));
+        assert!(view.typed_array("mz").is_err());
+
+        let inner = view.slice(1..);
+        assert_eq!(inner.rows(), 2..4);
+        assert_eq!(inner.get::<u32>("tof").unwrap(), &[7, 6]);
+        assert!(ds.slice(..).len() == 5 && ds.slice(5..).is_empty());
+
+        assert_eq!(ds.range_by("frame", 2u32..4).unwrap().rows(), 1..3);
+        assert_eq!(ds.range_by("frame", 2u32..=4).unwrap().rows(), 1..4);
+        assert_eq!(ds.range_by("frame", 9u32..).unwrap().rows(), 5..5);
+        let reversed = (Bound::Included(4u32), Bound::Excluded(2u32));
+        assert!(ds.range_by("frame", reversed).unwrap().is_empty());
+        assert!(ds.range_by("frame", 1.0f32..).is_err());
+
+        let chunks: Vec<_> = ds.chunks(2).with_readahead(4).map(|c| c.rows()).collect();
+        assert_eq!(chunks, vec![0..2, 2..4, 4..5]);
+        let tofs: Vec<&[u32]> = ds.chunks(3).map(|c| c.get("tof").unwrap()).collect();
+        assert_eq!(tofs, vec![&[9, 8, 7][..], &[6, 5]]);
+        assert_eq!(ds.chunks(10).len(), 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,225 @@
+//! Reloading datasets that change on disk (feature `watch`).
+//!
+//! Changes to the directory are reported by the OS through the `notify`
+//! crate. Where it can't watch the directory the watcher falls back to
+//! polling it; a poll only lists one directory, so it stays cheap. Network
+//! filesystems may accept a watch yet never report changes made on other
+//! machines.
+
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher as _};
+
+use crate::dataset::Dataset;
+use crate::journal::JOURNAL_FILE;
+
+/// Time between two looks at a dataset's directory when polling, or while
+/// a reopen keeps failing.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Why the watching thread wakes up.
+enum Wake {
+    Changed,
+    Stop,
+}
+
+/// Handle of a watched dataset, see [`Dataset::watch`]. Watching stops when
+/// it's dropped.
+pub struct Watcher {
+    /// Sends [`Wake::Stop`] to the watching thread.
+    wake: Sender<Wake>,
+    /// Reports changes to the directory, unless it's polled.
+    events: Option<RecommendedWatcher>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watcher {
+    /// Whether the directory is polled because the OS couldn't watch it.
+    pub fn is_polling(&self) -> bool {
+        self.events.is_none()
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        drop(self.events.take());
+        let _ = self.wake.send(Wake::Stop);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Dataset {
+    /// Watch the dataset's directory on a background thread, reopening the
+    /// dataset when its schema changes or rows are appended, and passing each
+    /// reopened dataset to `callback`.
+    ///
+    /// The directory is watched with OS notifications (inotify, FSEvents,
+    /// ReadDirectoryChangesW), or polled every 100 ms where they aren't
+    /// available, see [`Watcher::is_polling`]. Reopening re-validates the
+    /// dataset; if that fails, e.g. because an append without a `COMMIT`
+    /// file is in progress, it's retried every 100 ms until it succeeds.
+    ///
+    /// ```rust,no_run
+    /// let ds = mmappet::Dataset::open("acquisition.mmappet")?;
+    /// let _watcher = ds.watch(|ds| println!("{} rows", ds.len()));
+    /// std::thread::park();
+    /// # Ok::<(), mmappet::MmappetError>(())
+    /// ```
+    pub fn watch<F>(self, callback: F) -> Watcher
+    where
+        F: FnMut(&Dataset) + Send + 'static,
+    {
+        let (wake, woken) = mpsc::channel();
+        let events = watch_events(self.path(), wake.clone());
+        spawn_watcher(self, callback, wake, woken, events)
+    }
+}
+
+/// Start the thread reopening `dataset` when woken through `woken`, polling
+/// if there are no `events`.
+fn spawn_watcher<F>(
+    mut dataset: Dataset,
+    mut callback: F,
+    wake: Sender<Wake>,
+    woken: Receiver<Wake>,
+    events: Option<RecommendedWatcher>,
+) -> Watcher
+where
+    F: FnMut(&Dataset) + Send + 'static,
+{
+    let polling = events.is_none();
+    let thread = std::thread::spawn(move || {
+        let mut seen = fingerprint(dataset.path());
+        let mut retry = false;
+        while wait(&woken, polling || retry) {
+            let current = fingerprint(dataset.path());
+            if current == seen {
+                retry = false;
+                continue;
+            }
+            let Ok(reopened) = dataset.reopen() else {
+                retry = true;
+                continue;
+            };
+            retry = false;
+            seen = current;
+            let changed = reopened.schema().to_json() != dataset.schema().to_json()
+                || reopened.len() != dataset.len()
+                || reopened.generation() != dataset.generation();
+            dataset = reopened;
+            if changed {
+                callback(&dataset);
+            }
+        }
+    });
+    Watcher {
+        wake,
+        events,
+        thread: Some(thread),
+    }
+}
+
+/// Send [`Wake::Changed`] to `wake` whenever something in `dir` changes, or
+/// return `None` if the OS can't watch it.
+fn watch_events(dir: &Path, wake: Sender<Wake>) -> Option<RecommendedWatcher> {
+    let mut events = notify::recommended_watcher(move |_| {
+        let _ = wake.send(Wake::Changed);
+    })
+    .ok()?;
+    events.watch(dir, RecursiveMode::NonRecursive).ok()?;
+    Some(events)
+}
+
+/// Wait for a change, or at most [`POLL_INTERVAL`] if `poll`, and take
+/// changes queued meanwhile along. Returns `false` once the thread should
+/// stop.
+fn wait(woken: &Receiver<Wake>, poll: bool) -> bool {
+    let wake = if poll {
+        match woken.recv_timeout(POLL_INTERVAL) {
+            Err(RecvTimeoutError::Timeout) => Wake::Changed,
+            Err(RecvTimeoutError::Disconnected) => Wake::Stop,
+            Ok(wake) => wake,
+        }
+    } else {
+        woken.recv().unwrap_or(Wake::Stop)
+    };
+    std::iter::once(wake)
+        .chain(woken.try_iter())
+        .all(|wake| matches!(wake, Wake::Changed))
+}
+
+/// Name, size and modification time of the files in `dir`, besides the
+/// journal that only exists during appends.
+fn fingerprint(dir: &Path) -> Vec<(String, u64, Option<SystemTime>)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<_> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let metadata = entry.metadata().ok()?;
+            let modified = metadata.modified().ok();
+            (name != JOURNAL_FILE).then_some((name, metadata.len(), modified))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::ArrayView1;
+
+    use super::*;
+    use crate::column::TypedArrayView;
+    use crate::dtype::DType;
+    use crate::schema::Schema;
+    use crate::writer::DatasetWriter;
+
+    #[test]
+    fn test_watch_append() {
+        let dir = std::env::temp_dir().join(format!("mmappet-watch-{}", std::process::id()));
+        let schema = Schema::new(&[("tof", DType::UInt32)]).unwrap();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write("tof", &[1u32, 2]).unwrap();
+        writer.finish().unwrap();
+
+        let (sender, lens) = mpsc::channel();
+        let watcher = Dataset::open(&dir).unwrap().watch(move |ds| {
+            let _ = sender.send((ds.len(), ds.get::<u32>("tof").unwrap().to_vec()));
+        });
+        assert!(!watcher.is_polling());
+        let mut ds = Dataset::open(&dir).unwrap();
+        ds.append_batch(&[("tof", TypedArrayView::UInt32(ArrayView1::from(&[3u32])))]).unwrap();
+        let (len, values) = lens.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(len, 3);
+        assert_eq!(values, [1, 2, 3]);
+        drop(watcher);
+
+        // Polling, as where the OS can't watch the directory
+        let (sender, lens) = mpsc::channel();
+        let (wake, woken) = mpsc::channel();
+        let polled = spawn_watcher(
+            Dataset::open(&dir).unwrap(),
+            move |ds| {
+                let _ = sender.send(ds.len());
+            },
+            wake,
+            woken,
+            None,
+        );
+        assert!(polled.is_polling());
+        ds.append_batch(&[("tof", TypedArrayView::UInt32(ArrayView1::from(&[4u32])))]).unwrap();
+        assert_eq!(lens.recv_timeout(Duration::from_secs(10)).unwrap(), 4);
+        drop(polled);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,834 @@
+//! Writer for creating new datasets.
+
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use bytemuck::{cast_slice, Pod};
+use ndarray::{Array1, ArrayView1};
+
+use crate::categorical::{write_categories, CategoryEncoder};
+use crate::column::{TypedArray, TypedArrayView};
+use crate::commit::Commit;
+use crate::dataset::Dataset;
+use crate::dtype::{ByteOrder, DType, MmappetType};
+use crate::error::{MmappetError, Result};
+use crate::nullable::{validity_path, ValidityBuilder};
+use crate::schema::Schema;
+use crate::timestamp::to_ticks;
+
+/// Creates a dataset directory by appending typed values to its columns.
+///
+/// Values can be written per column, in any order and in as many pieces as
+/// convenient, or as row batches holding one array per column. `schema.txt`
+/// is only written by [`DatasetWriter::finish`], after all columns were
+/// checked to have equal length, so an interrupted write never leaves behind
+/// a directory that opens as a valid dataset.
+///
+/// ```rust,no_run
+/// use mmappet::{DType, DatasetWriter, Schema};
+///
+/// let schema = Schema::new(&[("tof", DType::UInt32), ("mz", DType::Float64)])?;
+/// let mut writer = DatasetWriter::create("out.mmappet", &schema)?;
+/// writer.write::<u32>("tof", &[10, 20])?;
+/// writer.write::<f64>("mz", &[400.1, 512.7])?;
+/// let rows = writer.finish()?;
+/// # Ok::<(), mmappet::MmappetError>(())
+/// ```
+pub struct DatasetWriter {
+    dir: PathBuf,
+    schema: Schema,
+    files: Vec<BufWriter<File>>,
+    lens: Vec<usize>,
+    /// Columns to build a Bloom filter for once finished.
+    bloom_filters: Vec<String>,
+    /// Index of each column to delta-encode once finished.
+    delta_encoded: Vec<usize>,
+    /// Index and zstd level of each column to compress once finished.
+    #[cfg(feature = "zstd")]
+    compressed: Vec<(usize, i32)>,
+    /// Metadata to set once finished.
+    metadata: Vec<(String, String)>,
+    /// Validity bitmap of each column given nulls, see
+    /// [`DatasetWriter::write_opt`].
+    validity: Vec<Option<ValidityBuilder>>,
+    /// String data file and its length of each [`DType::Utf8`] column.
+    strings: Vec<Option<(BufWriter<File>, u64)>>,
+    /// Dictionary of each [`DType::Categorical`] column.
+    categories: Vec<Option<CategoryEncoder>>,
+}
+
+impl DatasetWriter {
+    /// Create `dir` and an empty file for every column of `schema`.
+    ///
+    /// Reference columns can't be written and are rejected.
+    pub fn create<P: AsRef<Path>>(dir: P, schema: &Schema) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        if let Some(col) = schema.columns().find(|c| c.reference.is_some()) {
+            return Err(MmappetError::InvalidReference {
+                path: Some(dir),
+                column: col.name.clone(),
+                message: "reference columns can't be written".to_string(),
+            });
+        }
+        fs::create_dir_all(&dir).map_err(|e| MmappetError::io(&dir, e))?;
+        // Rows of a dataset written before don't apply
+        Commit::remove(&dir)?;
+
+        let mut files = Vec::with_capacity(schema.len());
+        let mut strings = Vec::with_capacity(schema.len());
+        for col in schema.columns() {
+            let path = dir.join(format!("{}.bin", col.index));
+            let file = File::create(&path).map_err(|e| MmappetError::io(&path, e))?;
+            files.push(BufWriter::new(file));
+            strings.push(if col.dtype == DType::Utf8 {
+                let path = crate::strings::data_path(&path);
+                let file = File::create(&path).map_err(|e| MmappetError::io(&path, e))?;
+                Some((BufWriter::new(file), 0))
+            } else {
+                None
+            });
+        }
+
+        // Values are written as they are in memory
+        let mut native_schema = schema.clone();
+        native_schema.set_byte_order(ByteOrder::native());
+
+        Ok(DatasetWriter {
+            dir,
+            schema: native_schema,
+            files,
+            lens: vec![0; schema.len()],
+            bloom_filters: Vec::new(),
+            delta_encoded: Vec::new(),
+            #[cfg(feature = "zstd")]
+            compressed: Vec::new(),
+            metadata: Vec::new(),
+            validity: (0..schema.len()).map(|_| None).collect(),
+            strings,
+            categories: schema
+                .columns()
+                .map(|col| (col.dtype == DType::Categorical).then(CategoryEncoder::default))
+                .collect(),
+        })
+    }
+
+    /// Get the schema being written.
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Number of values written so far to each column, in schema order.
+    pub fn lens(&self) -> &[usize] {
+        &self.lens
+    }
+
+    /// Also build a Bloom filter for an integer column when finishing, see
+    /// [`Dataset::build_bloom_filter`](crate::Dataset::build_bloom_filter).
+    ///
+    /// Returns an error if the column doesn't exist or isn't an integer column.
+    pub fn bloom_filter(&mut self, column: &str) -> Result<()> {
+        let col = self
+            .schema
+            .get(column)
+            .ok_or_else(|| MmappetError::ColumnNotFound {
+                path: Some(self.dir.clone()),
+                column: column.to_string(),
+            })?;
+        crate::bloom::check_dtype(column, col.dtype)?;
+        if !self.bloom_filters.iter().any(|name| name == column) {
+            self.bloom_filters.push(column.to_string());
+        }
+        Ok(())
+    }
+
+    /// Store a column zstd-compressed at `level` when finishing, in blocks of
+    /// 1 MiB that are decompressed when the column is first accessed.
+    ///
+    /// Compressed columns are read-only and can't be appended to. This
+    /// replaces a [`DatasetWriter::delta_encode`] of the column. Returns an
+    /// error if the column doesn't exist.
+    #[cfg(feature = "zstd")]
+    pub fn compress(&mut self, column: &str, level: i32) -> Result<()> {
+        let index = self
+            .schema
+            .get(column)
+            .ok_or_else(|| MmappetError::ColumnNotFound {
+                path: Some(self.dir.clone()),
+                column: column.to_string(),
+            })?
+            .index;
+        self.delta_encoded.retain(|&i| i != index);
+        match self.compressed.iter_mut().find(|(i, _)| *i == index) {
+            Some((_, l)) => *l = level,
+            None => self.compressed.push((index, level)),
+        }
+        Ok(())
+    }
+
+    /// Store a sorted integer column as the differences between consecutive
+    /// values when finishing, decoded when the column is first accessed.
+    /// [`Dataset::range_by`](crate::Dataset::range_by) searches it without
+    /// decoding all of it.
+    ///
+    /// Delta-encoded columns are read-only and can't be appended to. Returns
+    /// an error if the column doesn't exist, doesn't hold integers or holds
+    /// arrays; [`DatasetWriter::finish`] returns one if its values decrease.
+    pub fn delta_encode(&mut self, column: &str) -> Result<()> {
+        let col = self
+            .schema
+            .get(column)
+            .ok_or_else(|| MmappetError::ColumnNotFound {
+                path: Some(self.dir.clone()),
+                column: column.to_string(),
+            })?;
+        crate::delta::check_dtype(column, col.dtype)?;
+        if col.width() != 1 {
+            return Err(MmappetError::NotScalar {
+                column: column.to_string(),
+                width: col.width(),
+            });
+        }
+        let index = col.index;
+        #[cfg(feature = "zstd")]
+        self.compressed.retain(|&(i, _)| i != index);
+        if !self.delta_encoded.contains(&index) {
+            self.delta_encoded.push(index);
+        }
+        Ok(())
+    }
+
+    /// Set a metadata key when finishing, see
+    /// [`Dataset::set_metadata`](crate::Dataset::set_metadata).
+    ///
+    /// Returns an error if the key is invalid. Setting a key again replaces
+    /// its value.
+    pub fn set_metadata(&mut self, key: &str, value: &str) -> Result<()> {
+        crate::meta::check_key(&self.dir, key)?;
+        match self.metadata.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.metadata.push((key.to_string(), value.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Append values to a column by name; `width` values per row for array
+    /// columns, row after row.
+    ///
+    /// Returns an error if the column doesn't exist, the type doesn't match
+    /// or the values don't fill whole rows.
+    pub fn write<T: MmappetType>(&mut self, column: &str, values: &[T]) -> Result<()> {
+        let index = self
+            .schema
+            .get(column)
+            .ok_or_else(|| MmappetError::ColumnNotFound {
+                path: Some(self.dir.clone()),
+                column: column.to_string(),
+            })?
+            .index;
+        self.write_at(index, values)
+    }
+
+    /// Append an owned array to a column by name, e.g. the result of a
+    /// [`compute`](crate::compute) kernel; `width` values per row for array
+    /// columns.
+    ///
+    /// Returns an error if the column doesn't exist, the dtype doesn't match
+    /// or the values don't fill whole rows.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a categorical code doesn't index its labels.
+    pub fn write_array(&mut self, column: &str, values: &TypedArray) -> Result<()> {
+        fn bytes<T: Pod>(values: &Array1<T>) -> Cow<'_, [u8]> {
+            match values.as_slice() {
+                Some(values) => Cow::Borrowed(cast_slice(values)),
+                None => Cow::Owned(cast_slice(&values.to_vec()).to_vec()),
+            }
+        }
+
+        let index = self
+            .schema
+            .get(column)
+            .ok_or_else(|| MmappetError::ColumnNotFound {
+                path: Some(self.dir.clone()),
+                column: column.to_string(),
+            })?
+            .index;
+        self.check_dtype(index, values.dtype())?;
+        let bytes = match values {
+            TypedArray::UInt8(a) | TypedArray::Bool(a) => bytes(a),
+            TypedArray::Int8(a) => bytes(a),
+            TypedArray::UInt16(a) => bytes(a),
+            TypedArray::Int16(a) => bytes(a),
+            TypedArray::UInt32(a) => bytes(a),
+            TypedArray::Int32(a) => bytes(a),
+            TypedArray::UInt64(a) => bytes(a),
+            TypedArray::Int64(a) | TypedArray::TimestampMillis(a) | TypedArray::TimestampMicros(a) => bytes(a),
+            TypedArray::Float16(a) => bytes(a),
+            TypedArray::Float32(a) => bytes(a),
+            TypedArray::Float64(a) => bytes(a),
+            TypedArray::Utf8(strings) => return self.write_strs(index, strings.iter().map(String::as_str)),
+            TypedArray::Categorical { codes, categories } => {
+                let labels = codes.iter().map(|&code| categories[code as usize].as_str());
+                return self.write_labels(index, labels);
+            }
+            TypedArray::Custom { bytes, .. } => Cow::Borrowed(bytes.as_slice()),
+        };
+        let rows = self.check_rows(index, values.len())?;
+        self.write_bytes(index, &bytes, rows)
+    }
+
+    /// Append values to a column by name, `None` for nulls.
+    ///
+    /// The column gets a validity bitmap, see [`Dataset::nullable`]; nulls
+    /// are stored as zero. Returns an error if the column doesn't exist, holds
+    /// arrays or the type doesn't match.
+    pub fn write_opt<T: MmappetType>(&mut self, column: &str, values: &[Option<T>]) -> Result<()> {
+        let col = self
+            .schema
+            .get(column)
+            .ok_or_else(|| MmappetError::ColumnNotFound {
+                path: Some(self.dir.clone()),
+                column: column.to_string(),
+            })?;
+        if col.width() > 1 {
+            return Err(MmappetError::NotScalar {
+                column: col.name.clone(),
+                width: col.width(),
+            });
+        }
+        let index = col.index;
+        self.check_dtype(index, T::DTYPE)?;
+
+        let start = self.lens[index];
+        self.track_validity(index);
+        let data: Vec<T> = values.iter().map(|v| v.unwrap_or_else(T::zeroed)).collect();
+        self.write_bytes(index, cast_slice(&data), data.len())?;
+
+        for (i, value) in values.iter().enumerate() {
+            if value.is_none() {
+                self.set_null(index, start + i);
+            }
+        }
+        Ok(())
+    }
+
+    /// Append strings to a [`DType::Utf8`] column by name.
+    ///
+    /// Returns an error if the column doesn't exist or doesn't hold strings.
+    pub fn write_str(&mut self, column: &str, values: &[&str]) -> Result<()> {
+        let index = self
+            .schema
+            .get(column)
+            .ok_or_else(|| MmappetError::ColumnNotFound {
+                path: Some(self.dir.clone()),
+                column: column.to_string(),
+            })?
+            .index;
+        self.check_dtype(index, DType::Utf8)?;
+        self.write_strs(index, values.iter().copied())
+    }
+
+    /// Append labels to a [`DType::Categorical`] column by name.
+    ///
+    /// Labels are assigned codes in order of first appearance. Returns an
+    /// error if the column doesn't exist or isn't categorical.
+    pub fn write_categorical(&mut self, column: &str, values: &[&str]) -> Result<()> {
+        let index = self
+            .schema
+            .get(column)
+            .ok_or_else(|| MmappetError::ColumnNotFound {
+                path: Some(self.dir.clone()),
+                column: column.to_string(),
+            })?
+            .index;
+        self.check_dtype(index, DType::Categorical)?;
+        self.write_labels(index, values.iter().copied())
+    }
+
+    /// Append ticks to a timestamp column by name, see
+    /// [`DType::ticks_per_second`].
+    ///
+    /// Returns an error if the column doesn't exist or doesn't hold
+    /// timestamps.
+    pub fn write_timestamps(&mut self, column: &str, ticks: &[i64]) -> Result<()> {
+        let (index, _) = self.timestamp_column(column)?;
+        let rows = self.check_rows(index, ticks.len())?;
+        self.write_bytes(index, cast_slice(ticks), rows)
+    }
+
+    /// Append times to a timestamp column by name, rounded down to its ticks.
+    ///
+    /// Returns an error if the column doesn't exist or doesn't hold
+    /// timestamps.
+    pub fn write_datetimes(&mut self, column: &str, times: &[SystemTime]) -> Result<()> {
+        let (_, per_second) = self.timestamp_column(column)?;
+        let ticks: Vec<i64> = times.iter().map(|&t| to_ticks(t, per_second)).collect();
+        self.write_timestamps(column, &ticks)
+    }
+
+    /// Append values to the column at schema position `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn write_at<T: MmappetType>(&mut self, index: usize, values: &[T]) -> Result<()> {
+        self.check_dtype(index, T::DTYPE)?;
+        let rows = self.check_rows(index, values.len())?;
+        self.write_bytes(index, cast_slice(values), rows)
+    }
+
+    /// Append a row batch: one array per column, in schema order, all with
+    /// the same number of rows. Arrays of array columns hold `width` values
+    /// per row.
+    ///
+    /// Nothing is written if the batch doesn't match the schema.
+    pub fn write_batch(&mut self, batch: &[TypedArrayView<'_>]) -> Result<()> {
+        let rows = self.check_batch(batch)?;
+        for (index, array) in batch.iter().enumerate() {
+            match array {
+                TypedArrayView::Utf8(strings) => self.write_strs(index, strings.iter())?,
+                TypedArrayView::Categorical(labels) => self.write_labels(index, labels.iter())?,
+                _ => self.write_bytes(index, &view_bytes(array), rows)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Append the rows `rows` of a row batch, checked as by
+    /// [`DatasetWriter::write_batch`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if a row is out of bounds.
+    pub(crate) fn write_rows(
+        &mut self,
+        batch: &[TypedArrayView<'_>],
+        rows: &[usize],
+    ) -> Result<()> {
+        let batch_rows = self.check_batch(batch)?;
+        for (index, array) in batch.iter().enumerate() {
+            match array {
+                TypedArrayView::Utf8(strings) => {
+                    self.write_strs(index, rows.iter().map(|&row| strings.get(row)))?
+                }
+                TypedArrayView::Categorical(labels) => {
+                    self.write_labels(index, rows.iter().map(|&row| labels.value(row)))?
+                }
+                _ => {
+                    let bytes = view_bytes(array);
+                    let row_size = bytes.len() / batch_rows.max(1);
+                    let mut selected = Vec::with_capacity(rows.len() * row_size);
+                    for &row in rows {
+                        selected.extend_from_slice(&bytes[row * row_size..(row + 1) * row_size]);
+                    }
+                    self.write_bytes(index, &selected, rows.len())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of rows of a row batch, or an error if it doesn't match the
+    /// schema.
+    fn check_batch(&self, batch: &[TypedArrayView<'_>]) -> Result<usize> {
+        if batch.len() != self.schema.len() {
+            return Err(MmappetError::InvalidBatch {
+                path: Some(self.dir.clone()),
+                message: format!(
+                    "batch has {} columns, schema has {}",
+                    batch.len(),
+                    self.schema.len()
+                ),
+            });
+        }
+        let rows = match batch.first() {
+            Some(array) => self.check_rows(0, array.len())?,
+            None => 0,
+        };
+        for (index, array) in batch.iter().enumerate() {
+            self.check_dtype(index, array.dtype())?;
+            let width = self.schema.get_by_index(index).unwrap().width();
+            if array.len() != rows * width {
+                return Err(MmappetError::LengthMismatch {
+                    path: Some(self.dir.clone()),
+                    column: self.column_name(index).to_string(),
+                    expected: rows * width,
+                    actual: array.len(),
+                });
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Flush all columns, check they have equal length, write the validity
+    /// bitmaps and dictionaries, encode the requested columns, write the
+    /// metadata and `schema.txt` (and `schema.json` if any column has
+    /// attributes), then build the requested Bloom filters.
+    ///
+    /// Returns the number of rows written.
+    pub fn finish(self) -> Result<usize> {
+        let rows = self.lens.first().copied().unwrap_or(0);
+        for (index, &len) in self.lens.iter().enumerate() {
+            if len != rows {
+                return Err(MmappetError::LengthMismatch {
+                    path: Some(self.dir.clone()),
+                    column: self.column_name(index).to_string(),
+                    expected: rows,
+                    actual: len,
+                });
+            }
+        }
+
+        let string_files = self.strings.into_iter().flatten().map(|(file, _)| file);
+        for file in self.files.into_iter().chain(string_files) {
+            file.into_inner()
+                .map_err(|e| e.into_error())
+                .and_then(|f| f.sync_all())
+                .map_err(|e| MmappetError::io(&self.dir, e))?;
+        }
+
+        for (index, validity) in self.validity.iter().enumerate() {
+            if let Some(validity) = validity {
+                let path = validity_path(&self.dir, index);
+                fs::write(&path, validity.as_bytes()).map_err(|e| MmappetError::io(&path, e))?;
+            }
+        }
+
+        for (index, encoder) in self.categories.iter().enumerate() {
+            if let Some(encoder) = encoder {
+                let path = self.dir.join(format!("{}.bin", index));
+                write_categories(&path, encoder.categories())?;
+            }
+        }
+
+        for &index in &self.delta_encoded {
+            let path = self.dir.join(format!("{}.bin", index));
+            let col = self.schema.get_by_index(index).unwrap();
+            crate::delta::encode_file(&path, &col.name, col.dtype)
+                .map_err(|e| e.with_path(&self.dir))?;
+        }
+        #[cfg(feature = "zstd")]
+        for &(index, level) in &self.compressed {
+            let path = self.dir.join(format!("{}.bin", index));
+            crate::compress::compress_file(&path, level, crate::compress::BLOCK_SIZE)?;
+        }
+
+        // Before the schema, so a dataset is never visible without its metadata
+        if !self.metadata.is_empty() {
+            let changes: Vec<(&str, &str)> = self
+                .metadata
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect();
+            let tool = concat!("mmappet ", env!("CARGO_PKG_VERSION"));
+            crate::meta::set_metadata(&self.dir, &changes, tool)?;
+        }
+
+        let schema_path = self.dir.join("schema.txt");
+        fs::write(&schema_path, self.schema.to_string())
+            .map_err(|e| MmappetError::io(&schema_path, e))?;
+        // Readers prefer schema.json; schema.txt keeps older ones working
+        if self.schema.has_attributes() {
+            let json_path = self.dir.join("schema.json");
+            fs::write(&json_path, self.schema.to_json())
+                .map_err(|e| MmappetError::io(&json_path, e))?;
+        }
+
+        if !self.bloom_filters.is_empty() {
+            let dataset = Dataset::open(&self.dir)?;
+            for name in &self.bloom_filters {
+                dataset.build_bloom_filter(name)?;
+            }
+        }
+
+        Ok(rows)
+    }
+
+    fn column_name(&self, index: usize) -> &str {
+        &self.schema.get_by_index(index).unwrap().name
+    }
+
+    fn check_dtype(&self, index: usize, actual: DType) -> Result<()> {
+        let col = self.schema.get_by_index(index).unwrap();
+        if col.dtype != actual {
+            return Err(MmappetError::TypeMismatch {
+                column: Some(col.name.clone()),
+                expected: col.dtype,
+                actual,
+            });
+        }
+        Ok(())
+    }
+
+    /// Index and ticks per second of the timestamp column `column`.
+    fn timestamp_column(&self, column: &str) -> Result<(usize, i64)> {
+        let col = self
+            .schema
+            .get(column)
+            .ok_or_else(|| MmappetError::ColumnNotFound {
+                path: Some(self.dir.clone()),
+                column: column.to_string(),
+            })?;
+        match col.dtype.ticks_per_second() {
+            Some(per_second) => Ok((col.index, per_second)),
+            None => Err(MmappetError::TypeMismatch {
+                column: Some(col.name.clone()),
+                expected: DType::TimestampMicros,
+                actual: col.dtype,
+            }),
+        }
+    }
+
+    /// Number of rows that `values` values of the column at `index` fill.
+    pub(crate) fn check_rows(&self, index: usize, values: usize) -> Result<usize> {
+        let col = self.schema.get_by_index(index).unwrap();
+        if !values.is_multiple_of(col.width()) {
+            return Err(MmappetError::InvalidBatch {
+                path: Some(self.dir.clone()),
+                message: format!(
+                    "{} values don't fill rows of {} in column '{}'",
+                    values, col.width(), col.name
+                ),
+            });
+        }
+        Ok(values / col.width())
+    }
+
+    /// Append `count` rows given as raw bytes to the column at `index`.
+    pub(crate) fn write_bytes(&mut self, index: usize, bytes: &[u8], count: usize) -> Result<()> {
+        let path = &self.dir;
+        self.files[index]
+            .write_all(bytes)
+            .map_err(|e| MmappetError::io(path.join(format!("{}.bin", index)), e))?;
+        self.lens[index] += count;
+        if let Some(validity) = &mut self.validity[index] {
+            validity.push_valid(count);
+        }
+        Ok(())
+    }
+
+    /// Give the column at `index` a validity bitmap, with the rows written so
+    /// far valid, unless it has one.
+    pub(crate) fn track_validity(&mut self, index: usize) {
+        if self.validity[index].is_none() {
+            let mut validity = ValidityBuilder::default();
+            validity.push_valid(self.lens[index]);
+            self.validity[index] = Some(validity);
+        }
+    }
+
+    /// Mark the written `row` of the column at `index` as null.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the column has no validity bitmap or `row` wasn't written.
+    pub(crate) fn set_null(&mut self, index: usize, row: usize) {
+        let validity = self.validity[index].as_mut().expect("column has no validity bitmap");
+        validity.set_null(row);
+    }
+
+    /// Append strings to the [`DType::Utf8`] column at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the column doesn't hold strings.
+    pub(crate) fn write_strs<'s>(
+        &mut self,
+        index: usize,
+        values: impl IntoIterator<Item = &'s str>,
+    ) -> Result<()> {
+        let (file, len) = self.strings[index].as_mut().expect("not a string column");
+        let mut ends = Vec::new();
+        for value in values {
+            file.write_all(value.as_bytes()).map_err(|e| {
+                MmappetError::io(self.dir.join(format!("{}.str", index)), e)
+            })?;
+            *len += value.len() as u64;
+            ends.push(*len);
+        }
+        self.write_bytes(index, cast_slice(&ends), ends.len())
+    }
+
+    /// Append labels to the [`DType::Categorical`] column at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the column isn't categorical.
+    pub(crate) fn write_labels<'s>(
+        &mut self,
+        index: usize,
+        values: impl IntoIterator<Item = &'s str>,
+    ) -> Result<()> {
+        let encoder = self.categories[index].as_mut().expect("not a categorical column");
+        let codes: Vec<u32> = values.into_iter().map(|label| encoder.encode(label)).collect();
+        self.write_bytes(index, cast_slice(&codes), codes.len())
+    }
+
+    /// Append `rows` rows given as the values of every column, with one
+    /// validity flag per row for columns that have nulls.
+    pub(crate) fn write_columns(
+        &mut self,
+        columns: Vec<(Values<'_>, Option<Vec<bool>>)>,
+        rows: usize,
+    ) -> Result<()> {
+        for (index, (values, validity)) in columns.into_iter().enumerate() {
+            let first_row = self.lens[index];
+            if validity.is_some() {
+                self.track_validity(index);
+            }
+            match values {
+                Values::Bytes(bytes) => self.write_bytes(index, &bytes, rows)?,
+                Values::Strs(strings) => self.write_strs(index, strings)?,
+                Values::Labels(labels) => self.write_labels(index, labels)?,
+            }
+            for (row, valid) in validity.iter().flatten().enumerate() {
+                if !valid {
+                    self.set_null(index, first_row + row);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Values of a column of a batch, ready to be written.
+pub(crate) enum Values<'b> {
+    Bytes(Vec<u8>),
+    Strs(Vec<&'b str>),
+    Labels(Vec<&'b str>),
+}
+
+/// Create the dataset `dir` by calling `write` with a temporary sibling
+/// directory to write it to, which is renamed to `dir` once `write` succeeds,
+/// replacing anything there, and removed if it fails, so an import that
+/// stops midway leaves `dir` as it was.
+pub(crate) fn create_atomically<T>(dir: &Path, write: impl FnOnce(&Path) -> Result<T>) -> Result<T> {
+    let name = dir.file_name().ok_or_else(|| {
+        let message = "not a directory name";
+        MmappetError::io(dir, std::io::Error::new(std::io::ErrorKind::InvalidInput, message))
+    })?;
+    let tmp_dir = dir.with_file_name(format!(
+        ".{}.{}.tmp",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+    let result = write(&tmp_dir).and_then(|value| {
+        if dir.exists() {
+            fs::remove_dir_all(dir).map_err(|e| MmappetError::io(dir, e))?;
+        }
+        fs::rename(&tmp_dir, dir).map_err(|e| MmappetError::io(dir, e))?;
+        Ok(value)
+    });
+    if result.is_err() {
+        let _ = fs::remove_dir_all(&tmp_dir);
+    }
+    result
+}
+
+/// Raw bytes of an array, copied only if it isn't contiguous.
+///
+/// # Panics
+///
+/// Panics for strings and categories, which aren't held in fixed-size
+/// elements on their own.
+pub(crate) fn view_bytes<'a>(array: &'a TypedArrayView<'_>) -> Cow<'a, [u8]> {
+    fn bytes<'a, T: Pod>(array: &'a ArrayView1<'_, T>) -> Cow<'a, [u8]> {
+        match array.as_slice() {
+            Some(values) => Cow::Borrowed(cast_slice(values)),
+            None => Cow::Owned(cast_slice(&array.to_vec()).to_vec()),
+        }
+    }
+
+    match array {
+        TypedArrayView::UInt8(a) => bytes(a),
+        TypedArrayView::Int8(a) => bytes(a),
+        TypedArrayView::UInt16(a) => bytes(a),
+        TypedArrayView::Int16(a) => bytes(a),
+        TypedArrayView::UInt32(a) => bytes(a),
+        TypedArrayView::Int32(a) => bytes(a),
+        TypedArrayView::UInt64(a) => bytes(a),
+        TypedArrayView::Int64(a) | TypedArrayView::TimestampMillis(a) | TypedArrayView::TimestampMicros(a) => bytes(a),
+        TypedArrayView::Float16(a) => bytes(a),
+        TypedArrayView::Float32(a) => bytes(a),
+        TypedArrayView::Float64(a) => bytes(a),
+        TypedArrayView::Bool(a) => bytes(a),
+        TypedArrayView::Utf8(_) => panic!("strings have no fixed-size bytes"),
+        TypedArrayView::Categorical(_) => panic!("codes need their dictionary"),
+        TypedArrayView::Custom(a) => Cow::Borrowed(a.as_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::Dataset;
+    use crate::schema::ColumnAttributes;
+
+    #[test]
+    fn test_writer_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("mmappet-writer-{}", std::process::id()));
+        let mut schema = Schema::new(&[("tof", DType::UInt32), ("mz", DType::Float64)]).unwrap();
+        let sorted = ColumnAttributes {
+            sorted: true,
+            ..Default::default()
+        };
+        schema.set_attributes("tof", sorted.clone()).unwrap();
+
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write::<f64>("mz", &[1.5]).unwrap();
+        writer.write::<u32>("tof", &[10]).unwrap();
+        let tofs = [20u32, 30];
+        let mzs = [2.5f64, 3.5];
+        writer
+            .write_batch(&[
+                TypedArrayView::UInt32(ArrayView1::from(&tofs)),
+                TypedArrayView::Float64(ArrayView1::from(&mzs)),
+            ])
+            .unwrap();
+
+        assert!(matches!(
+            writer.write::<f32>("mz", &[1.0]),
+            Err(MmappetError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            writer.write_batch(&[TypedArrayView::UInt32(ArrayView1::from(&tofs))]),
+            Err(MmappetError::InvalidBatch { .. })
+        ));
+        writer.bloom_filter("tof").unwrap();
+        writer.set_metadata("instrument", "timsTOF").unwrap();
+        assert!(matches!(
+            writer.set_metadata("bad key", ""),
+            Err(MmappetError::InvalidMetadata { .. })
+        ));
+        assert!(matches!(
+            writer.bloom_filter("mz"),
+            Err(MmappetError::InvalidDType { .. })
+        ));
+        assert_eq!(writer.finish().unwrap(), 3);
+        assert!(dir.join("tof.bloom").exists());
+        assert!(dir.join("schema.json").exists());
+
+        let ds = Dataset::open(&dir).unwrap();
+        assert!(!ds.may_contain("tof", 11u32).unwrap());
+        assert_eq!(ds.get::<u32>("tof").unwrap(), &[10, 20, 30]);
+        assert_eq!(ds.get::<f64>("mz").unwrap(), &[1.5, 2.5, 3.5]);
+        assert_eq!(ds.schema().get("tof").unwrap().attributes, sorted);
+        assert_eq!(ds.metadata().unwrap()["instrument"], "timsTOF");
+
+        // Unequal lengths are rejected and leave no schema.txt behind
+        std::fs::remove_dir_all(&dir).unwrap();
+        let mut writer = DatasetWriter::create(&dir, &schema).unwrap();
+        writer.write::<u32>("tof", &[1, 2]).unwrap();
+        assert!(matches!(
+            writer.finish(),
+            Err(MmappetError::LengthMismatch { .. })
+        ));
+        assert!(!dir.join("schema.txt").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
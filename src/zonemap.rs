@@ -0,0 +1,183 @@
+//! Per-chunk min/max statistics ("zone maps") for skipping rows in scans.
+//!
+//! A `<column>.zones` file holds the smallest and largest value of each run
+//! of [`ZONE_ROWS`] rows, as `f64`. Expression evaluation uses them to skip
+//! chunks where a comparison can't hold. Layout, all words native-endian:
+//! magic, row count, rows per zone, zone count (`u64`), then a min and max
+//! (`f64`) per zone.
+
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::column::TypedArrayView;
+use crate::dataset::Dataset;
+use crate::error::{MmappetError, Result};
+use crate::stats::range_stats;
+
+/// Magic bytes at the start of a `<column>.zones` file.
+const ZONE_MAP_MAGIC: &[u8; 8] = b"MMPZONE1";
+
+/// Rows summarized by one zone.
+pub(crate) const ZONE_ROWS: usize = 1 << 16;
+
+impl Dataset {
+    /// Compute the zone map of a column and persist it as `<name>.zones`.
+    ///
+    /// Later filters on the column, also from other processes, skip chunks
+    /// whose value range rules out a match. NaNs are left out of the ranges.
+    /// String, categorical and custom dtypes without a numeric value can't be
+    /// mapped.
+    pub fn build_zone_map(&self, name: &str) -> Result<()> {
+        let column = self
+            .column(name)
+            .ok_or_else(|| self.column_not_found(name))?;
+        column.check_scalar(name)?;
+        let array = column.as_typed_array();
+        let numeric = match &array {
+            TypedArrayView::Custom(custom) => custom.def().to_f64.is_some(),
+            TypedArrayView::Utf8(_) | TypedArrayView::Categorical(_) => false,
+            _ => true,
+        };
+        if !numeric {
+            return Err(MmappetError::InvalidDType {
+                dtype: column.dtype().to_string(),
+                message: format!("column '{}' has no numeric value to map", name),
+            });
+        }
+
+        let count = self.len().div_ceil(ZONE_ROWS);
+        let mut buf = Vec::with_capacity(32 + count * 16);
+        buf.extend_from_slice(ZONE_MAP_MAGIC);
+        for word in [self.len(), ZONE_ROWS, count] {
+            buf.extend_from_slice(&(word as u64).to_ne_bytes());
+        }
+        for start in (0..self.len()).step_by(ZONE_ROWS) {
+            let stats = range_stats(&array, start..(start + ZONE_ROWS).min(self.len()));
+            buf.extend_from_slice(&stats.min.to_ne_bytes());
+            buf.extend_from_slice(&stats.max.to_ne_bytes());
+        }
+
+        let path = self.path().join(format!("{}.zones", name));
+        fs::write(&path, buf).map_err(|e| MmappetError::io(&path, e))?;
+        self.cache_zone_map(name, ZoneMap::read(&path)?.map(Arc::new));
+        Ok(())
+    }
+
+    /// The zone map of a column, if one is persisted and up to date.
+    pub(crate) fn zone_map(&self, name: &str) -> Result<Option<Arc<ZoneMap>>> {
+        if let Some(zones) = self.cached_zone_map(name) {
+            return Ok(zones);
+        }
+        let path = self.path().join(format!("{}.zones", name));
+        let zones = ZoneMap::read(&path)?
+            .filter(|zones| zones.rows == self.len())
+            .map(Arc::new);
+        self.cache_zone_map(name, zones.clone());
+        Ok(zones)
+    }
+}
+
+/// Value range of each zone of a column, read from `<column>.zones`.
+pub(crate) struct ZoneMap {
+    /// Row count of the dataset the map was built for.
+    rows: usize,
+    zone_rows: usize,
+    zones: Vec<(f64, f64)>,
+}
+
+impl ZoneMap {
+    /// Read a map written by [`Dataset::build_zone_map`]; `Ok(None)` if absent.
+    fn read(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let bytes = fs::read(path).map_err(|e| MmappetError::io(path, e))?;
+        let invalid = || MmappetError::InvalidIndex {
+            path: path.to_path_buf(),
+            offset: None,
+        };
+
+        let word =
+            |i: usize| -> Option<[u8; 8]> { bytes.get(8 + i * 8..16 + i * 8)?.try_into().ok() };
+        if bytes.get(..8) != Some(&ZONE_MAP_MAGIC[..]) {
+            return Err(invalid());
+        }
+        let header = |i: usize| {
+            word(i)
+                .map(|w| u64::from_ne_bytes(w) as usize)
+                .ok_or_else(invalid)
+        };
+        let (rows, zone_rows, count) = (header(0)?, header(1)?, header(2)?);
+        if zone_rows == 0
+            || count != rows.div_ceil(zone_rows)
+            || Some(bytes.len()) != count.checked_mul(16).map(|len| 32 + len)
+        {
+            return Err(invalid());
+        }
+
+        let zones = (0..count)
+            .map(|i| {
+                let min = f64::from_ne_bytes(word(3 + 2 * i).unwrap());
+                let max = f64::from_ne_bytes(word(4 + 2 * i).unwrap());
+                (min, max)
+            })
+            .collect();
+        Ok(Some(ZoneMap {
+            rows,
+            zone_rows,
+            zones,
+        }))
+    }
+
+    /// Smallest and largest non-NaN value within `rows`, widened to whole
+    /// zones. An empty range (`min > max`) means all values are NaN.
+    pub(crate) fn bounds(&self, rows: Range<usize>) -> (f64, f64) {
+        let zones = rows.start / self.zone_rows..rows.end.div_ceil(self.zone_rows);
+        self.zones[zones].iter().fold(
+            (f64::INFINITY, f64::NEG_INFINITY),
+            |(min, max), &(lo, hi)| (min.min(lo), max.max(hi)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::{col, lit};
+    use crate::test_util::write_dataset;
+
+    #[test]
+    fn test_zone_map() {
+        let frames: Vec<u32> = (0..3 * ZONE_ROWS as u32).map(|i| i / 100).collect();
+        let dir = write_dataset("zonemap", &[("frame", &frames)]);
+        let ds = Dataset::open(&dir).unwrap();
+        assert!(ds.zone_map("frame").unwrap().is_none());
+
+        ds.build_zone_map("frame").unwrap();
+        let ds = Dataset::open(&dir).unwrap();
+        let zones = ds.zone_map("frame").unwrap().unwrap();
+        assert_eq!(zones.bounds(0..10), (0.0, 655.0));
+        assert_eq!(zones.bounds(ZONE_ROWS..ZONE_ROWS + 1), (655.0, 1310.0));
+
+        // Pruned and unpruned evaluation agree
+        let expr = col("frame")
+            .between(700, 701)
+            .or(col("frame").eq(lit(1500)));
+        let rows: Vec<usize> = ds.matching_rows(&expr).unwrap().collect();
+        assert_eq!(rows.len(), 300);
+        assert_eq!(rows[0], 70000);
+        assert_eq!(rows[200], 150000);
+        let mask = ds.mask(&!col("frame").lt(1000)).unwrap();
+        assert_eq!(mask.iter().filter(|&&m| m).count(), frames.len() - 100_000);
+
+        fs::write(dir.join("frame.zones"), b"garbage").unwrap();
+        assert!(matches!(
+            Dataset::open(&dir).unwrap().mask(&expr),
+            Err(MmappetError::InvalidIndex { .. })
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}